@@ -37,6 +37,12 @@
             xorg.libXi
             xorg.libX11
 
+            # alert tones (the `audio` feature)
+            alsa-lib
+
+            # gamepads (the `gamepad` feature)
+            udev
+
           ];
 
           LD_LIBRARY_PATH = "${lib.makeLibraryPath buildInputs}";
@@ -0,0 +1,24 @@
+//! Feeds arbitrary bytes through the server's decode boundary, and anything
+//! that decodes through the server itself. Neither may panic.
+//!
+//! Run with `cargo +nightly fuzz run decode_client_message` from the repo root.
+
+#![no_main]
+
+use gamik::game::GameState;
+use gamik::net::{ServerState, decode};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = decode::decode_server_message(data);
+
+    let Ok(msg) = decode::decode_client_message(data) else {
+        return;
+    };
+    let endpoint = iroh::SecretKey::from_bytes(&[1; 32]).public();
+    let mut server = ServerState::new(GameState::create_test_world("fuzz".into()));
+    server.connect(endpoint);
+    server.handle_client_message(endpoint, msg);
+    server.step();
+    let _ = server.drain_updates(endpoint);
+});
@@ -0,0 +1,304 @@
+//! Alerts when something hostile comes into the player's awareness.
+//!
+//! Each frame, the entities that [entered](crate::client::Sight::noticed)
+//! the player's awareness are checked for any that
+//! [threaten](crate::client::threatens) them. Each one can play a short
+//! tone, flash the edges of the screen and be noted in the combat log, as
+//! the [`AlertStyle`] of its entity type says. An entity stepping in and
+//! out of view alerts at most once every [`REALERT_SECONDS`].
+
+use crate::client;
+use crate::game::{EntityID, EntityType, GameState};
+
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Seconds the edges of the screen flash after an alert.
+pub const FLASH_SECONDS: f64 = 0.8;
+
+/// Seconds before the same entity can alert again.
+pub const REALERT_SECONDS: f64 = 10.0;
+
+/// Entity types that can be hostile, offered in the settings. Players
+/// are not, as nothing makes one [threaten](client::threatens) another.
+pub const ALERTING: [EntityType; 1] = [EntityType::Npc];
+
+/// How an alert calls for attention.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlertStyle {
+    /// Play a tone.
+    pub sound: bool,
+    /// Flash the edges of the screen.
+    pub flash: bool,
+    /// Note it in the combat log.
+    pub log: bool,
+}
+
+impl Default for AlertStyle {
+    fn default() -> Self {
+        Self {
+            sound: true,
+            flash: true,
+            log: true,
+        }
+    }
+}
+
+impl AlertStyle {
+    pub fn any(self) -> bool {
+        self.sound || self.flash || self.log
+    }
+}
+
+/// Alert settings by entity type.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlertConfig {
+    /// Styles the player changed, by [entity type name](EntityType::name);
+    /// the other types get [`AlertStyle::default`].
+    pub styles: BTreeMap<String, AlertStyle>,
+}
+
+impl AlertConfig {
+    /// How hostile entities of `entity_type` alert.
+    pub fn style(&self, entity_type: &EntityType) -> AlertStyle {
+        self.styles
+            .get(entity_type.name())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Make hostile entities of `entity_type` alert with `style`.
+    pub fn set_style(&mut self, entity_type: &EntityType, style: AlertStyle) {
+        let name = entity_type.name().to_owned();
+        if style == AlertStyle::default() {
+            self.styles.remove(&name);
+        } else {
+            self.styles.insert(name, style);
+        }
+    }
+}
+
+/// A hostile entity that came into the player's awareness.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Alert {
+    pub entity: EntityID,
+    pub style: AlertStyle,
+}
+
+/// The alerts raised lately, the flash they left and the speaker that
+/// plays their tone.
+#[derive(Default)]
+pub struct Alerts {
+    /// When each entity last alerted, in seconds.
+    raised: FxHashMap<EntityID, f64>,
+    /// When the edges of the screen stop flashing.
+    flash_until: Option<f64>,
+    speaker: Speaker,
+}
+
+impl Alerts {
+    /// Raise alerts at time `now` (in seconds) for the entities in
+    /// `entered` that threaten `player` in `state`, styled by `config`.
+    /// Starts the flash if any asks for it; the caller plays the tone and
+    /// writes the log.
+    pub fn raise(
+        &mut self,
+        state: &GameState,
+        player: EntityID,
+        entered: &[EntityID],
+        config: &AlertConfig,
+        now: f64,
+    ) -> Vec<Alert> {
+        self.raised
+            .retain(|_, raised| now - *raised < REALERT_SECONDS);
+        let Some(player_entity) = state.entities().get(&player) else {
+            return Vec::new();
+        };
+        let mut alerts = Vec::new();
+        for eid in entered.iter().filter(|eid| **eid != player) {
+            let Some(other) = state.entities().get(eid) else {
+                continue;
+            };
+            let style = config.style(&other.entity_type);
+            if !style.any()
+                || !client::threatens(player_entity, other)
+                || self.raised.contains_key(eid)
+            {
+                continue;
+            }
+            self.raised.insert(*eid, now);
+            if style.flash {
+                self.flash_until = Some(now + FLASH_SECONDS);
+            }
+            alerts.push(Alert {
+                entity: *eid,
+                style,
+            });
+        }
+        alerts
+    }
+
+    /// How strongly the edges of the screen flash at time `now`, from 1
+    /// just after an alert down to 0, or `None` once the flash is over.
+    pub fn flash(&mut self, now: f64) -> Option<f32> {
+        let left = self.flash_until? - now;
+        if left <= 0.0 {
+            self.flash_until = None;
+            return None;
+        }
+        Some((left / FLASH_SECONDS) as f32)
+    }
+
+    /// Returns `true` until a [`flash`](Self::flash) was found over.
+    pub fn is_flashing(&self) -> bool {
+        self.flash_until.is_some()
+    }
+
+    /// Forget the alerts raised, for another character or world.
+    pub fn clear(&mut self) {
+        self.raised.clear();
+        self.flash_until = None;
+    }
+
+    /// Play the alert tone, if there is a sound device.
+    pub fn play_tone(&mut self) {
+        self.speaker.play();
+    }
+}
+
+/// The default sound device, opened on the first tone.
+#[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+#[derive(Default)]
+enum Speaker {
+    #[default]
+    Unopened,
+    Open {
+        /// Plays as long as it is kept.
+        _stream: rodio::OutputStream,
+        handle: rodio::OutputStreamHandle,
+    },
+    /// There is no device, or it would not open.
+    Missing,
+}
+
+#[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+impl Speaker {
+    /// Pitch, length and volume of the alert tone.
+    const TONE_HZ: f32 = 880.0;
+    const TONE_SECONDS: f32 = 0.15;
+    const TONE_VOLUME: f32 = 0.2;
+
+    fn play(&mut self) {
+        use rodio::Source as _;
+        use std::time::Duration;
+
+        if matches!(self, Self::Unopened) {
+            *self = match rodio::OutputStream::try_default() {
+                Ok((_stream, handle)) => Self::Open { _stream, handle },
+                Err(e) => {
+                    log::warn!("No sound for alerts: {e}");
+                    Self::Missing
+                }
+            };
+        }
+        if let Self::Open { handle, .. } = self {
+            let tone = rodio::source::SineWave::new(Self::TONE_HZ)
+                .take_duration(Duration::from_secs_f32(Self::TONE_SECONDS))
+                .amplify(Self::TONE_VOLUME);
+            if let Err(e) = handle.play_raw(tone) {
+                log::warn!("Could not play the alert tone: {e}");
+            }
+        }
+    }
+}
+
+/// Stands in for the sound device in the web build, and in builds without
+/// the `audio` feature.
+#[cfg(not(all(feature = "audio", not(target_arch = "wasm32"))))]
+#[derive(Default)]
+struct Speaker {
+    /// Whether the player was told there is no sound.
+    told: bool,
+}
+
+#[cfg(not(all(feature = "audio", not(target_arch = "wasm32"))))]
+impl Speaker {
+    fn play(&mut self) {
+        if !std::mem::replace(&mut self.told, true) {
+            log::warn!("No sound for alerts in this build");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{AiBehavior, Entity, Point, spawn_player};
+
+    /// No alert at all.
+    const OFF: AlertStyle = AlertStyle {
+        sound: false,
+        flash: false,
+        log: false,
+    };
+
+    #[test]
+    fn hostiles_entering_awareness_alert_once_as_their_type_says() {
+        let mut state = GameState::create_test_world("w".into());
+        let player = spawn_player(&mut state, "Alice".into());
+        let mut npc = |id, ai, faction: Option<&str>| {
+            let eid = EntityID(id);
+            let mut npc = Entity::new(EntityType::Npc, Point { x: 0, y: 0 }, None);
+            npc.ai = Some(ai);
+            npc.faction = faction.map(str::to_owned);
+            state.insert_entity(eid, npc);
+            eid
+        };
+        let hunter = npc(1001, AiBehavior::ChaseNearestPlayer, None);
+        let guard = npc(1002, AiBehavior::Wander, Some("villagers"));
+        let deer = npc(1003, AiBehavior::Flee, None);
+        let entered = [player, hunter, guard, deer];
+
+        let mut config = AlertConfig::default();
+        let mut alerts = Alerts::default();
+        let raised = alerts.raise(&state, player, &entered, &config, 0.0);
+        assert_eq!(
+            raised.iter().map(|alert| alert.entity).collect::<Vec<_>>(),
+            [hunter],
+            "only what threatens the player alerts"
+        );
+        assert_eq!(alerts.flash(0.0), Some(1.0));
+        assert_eq!(alerts.flash(FLASH_SECONDS), None);
+        assert!(!alerts.is_flashing());
+
+        if let Some(alice) = state.entity_mut(player) {
+            alice.reputation.adjust("villagers", -50);
+        }
+        let raised = alerts.raise(&state, player, &entered, &config, 1.0);
+        assert_eq!(
+            raised.iter().map(|alert| alert.entity).collect::<Vec<_>>(),
+            [guard],
+            "the hunter alerted a moment ago"
+        );
+
+        let quiet = AlertStyle {
+            sound: false,
+            ..AlertStyle::default()
+        };
+        config.set_style(&EntityType::Npc, quiet);
+        assert_eq!(config.style(&EntityType::Npc), quiet);
+        assert_eq!(config.style(&EntityType::Player), AlertStyle::default());
+        let raised = alerts.raise(&state, player, &[hunter], &config, REALERT_SECONDS);
+        assert_eq!(raised.first().map(|alert| alert.style), Some(quiet));
+
+        config.set_style(&EntityType::Npc, OFF);
+        let raised = alerts.raise(&state, player, &[guard], &config, 2.0 * REALERT_SECONDS);
+        assert!(raised.is_empty(), "alerts for the type are off");
+        assert_eq!(alerts.flash(2.0 * REALERT_SECONDS), None);
+        config.set_style(&EntityType::Npc, AlertStyle::default());
+        assert!(config.styles.is_empty(), "only changed styles are kept");
+    }
+}
@@ -1,20 +1,125 @@
 //! Application shell — wires game, UI, and networking together.
 
-use crate::game::{self, Direction, EntityID, GameAction, GameState, Point};
-use crate::net::{Message, ServerMessage, run_client_internal, run_server_internal};
-use crate::ui;
+use crate::alert::{self, Alerts};
+use crate::client::{
+    self, Applied, ExploreStop, Explorer, Joined, Sight, Start, Trail, Transfer, Transfers,
+    WorldSync, item_name,
+};
+use crate::config::{
+    ClientConfig, DEFAULT_ZOOM, Friend, LowPower, MAX_FRIENDS, MAX_SERVERS, MAX_ZOOM, MIN_ZOOM,
+    MacroPlayer, MacroRecorder, SavedServer, UiSessionState,
+};
+use crate::crash;
+use crate::game::chunks::CHUNK_SIZE;
+use crate::game::content::{self, ContentWatcher};
+use crate::game::dialogue::{self, DialogueView};
+use crate::game::faction::Tier;
+use crate::game::fov::{self, ExploredMap};
+use crate::game::intent::Intent;
+use crate::game::item::{self, Encumbrance};
+use crate::game::math;
+use crate::game::region::RegionId;
+use crate::game::roster::{CharacterStats, Roster};
+use crate::game::shop;
+use crate::game::slots::{self, SaveSlot};
+use crate::game::world_events::{self, Announcement};
+use crate::game::worldgen::{self, MAX_WORLD_SIDE, WorldGenConfig, namegen};
+use crate::game::{
+    self, ContentRegistry, Direction, Entity, EntityID, EntityType, GameAction, GameState, Ping,
+    PingKind, PlayerColor, Point, SPAWN_POINT, TileMap, Wait, WaitEnd, WorldId,
+};
+use crate::gamepad::{self, Gamepads, PadButton};
+use crate::input_replay::{self, InputRecording, InputReplay};
+use crate::keymap::{self, Command, Context};
+use crate::net::access::MAX_SECRET_LEN;
+use crate::net::cache::MAX_MARKER_NAME_LEN;
+use crate::net::chat::Category;
+use crate::net::diagnostics::{Outcome, Report};
+use crate::net::recording::{self, Playback, Recording};
+use crate::net::view::{self, MAX_VIEW_RADIUS, MIN_VIEW_RADIUS};
+use crate::net::whisper::{self, ChatCommand};
+use crate::net::{
+    ClientMessage, DebugData, LineKind, MarkerColor, Markers, Message, MessageLog, Presence,
+    PresenceBoard, ServerMessage, ServerStatus, Silhouette, SnapshotAssembler, TICK_INTERVAL,
+    WhenEmpty, Whisper, diagnostics, presence, status,
+};
+use crate::power::PowerWatch;
+use crate::profile::{MemoryReport, Profiler, System};
+use crate::server::Server;
+use crate::{export, ui};
 
-use egui::{FontId, RichText};
+use egui::RichText;
 use iroh::EndpointAddr;
 use iroh::EndpointId;
+use iroh::SecretKey;
 use iroh::protocol::Router;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
-use tokio::sync::mpsc;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{mpsc, oneshot};
 
 // Toggle this constant to enable/disable test mode
 const TEST_MODE: bool = true;
 
+/// Lines of console output kept on screen.
+const MAX_CONSOLE_LINES: usize = 200;
+
+/// Lines of whispers kept on screen, and of chat asked for on joining a
+/// world.
+const MAX_CHAT_LINES: usize = 50;
+
+/// Where session logs are exported.
+const LOG_EXPORT_DIR: &str = "exports";
+
+/// Tiles shown in each direction from the player on the map.
+const MINIMAP_RADIUS: i32 = 32;
+
+/// Side of the map in the map window, in points.
+const MINIMAP_SIZE: f32 = 260.0;
+
+/// Most cells a side of the world preview, however large the world.
+const WORLD_PREVIEW_CELLS: u32 = 128;
+/// Longer side of the world preview on screen, in points.
+const WORLD_PREVIEW_SIZE: f32 = 256.0;
+
+/// Most pixels a side of a save slot's map thumbnail.
+const SLOT_THUMBNAIL_PIXELS: usize = 128;
+/// Longer side of a save slot's thumbnail on screen, in points.
+const SLOT_THUMBNAIL_SIZE: f32 = 64.0;
+
+/// Width of the compass strip, in points.
+const COMPASS_WIDTH: f32 = 320.0;
+
+/// Distance of the options of the ping menu from its center, in points.
+const PING_MENU_RADIUS: f32 = 48.0;
+
+/// How long a tile flashes after an entity is knocked onto it or a blast
+/// reaches it.
+const FLASH_SECONDS: f64 = 0.3;
+
+/// Turns passed by [`Command::WaitLong`].
+const WAIT_LONG_TURNS: u32 = 10;
+
+/// How long after pressing [`Command::Rest`] pressing it again confirms it.
+const REST_CONFIRM_SECONDS: f64 = 3.0;
+
+/// Time between frames on the map while nothing moves but the idle
+/// animations of the terrain.
+const ANIMATION_FRAME: Duration = Duration::from_millis(100);
+
+/// Time between frames in the menus while nothing moves, so clocks and
+/// timers still tick.
+const IDLE_FRAME: Duration = Duration::from_secs(1);
+
+/// Time between frames while a gamepad is plugged in, which brings none of
+/// its own, so its sticks and buttons are read.
+const GAMEPAD_FRAME: Duration = Duration::from_millis(16);
+
+/// Radius of the gamepad's quick menu, in points.
+const QUICK_MENU_RADIUS: f32 = 96.0;
+
 /// Which screen the application is currently showing.
 #[derive(Debug, Clone, PartialEq)]
 enum AppScreen {
@@ -24,6 +129,378 @@ enum AppScreen {
     CharacterSelection,
     WorldSelection,
     Playing,
+    /// The address book of servers, with their status.
+    ServerBrowser,
+    /// Waiting in line for a slot on a full server.
+    JoinQueue,
+    /// Picking a saved recording to watch.
+    ReplaySelection,
+    /// Watching a recording.
+    Replay,
+    /// Connectivity checks, for when joining fails.
+    Diagnostics,
+}
+
+/// A recording being watched, with the viewer's controls.
+struct ReplayView {
+    playback: Playback,
+    playing: bool,
+    /// Playback speed, in multiples of the server's tick rate.
+    speed: f64,
+    /// Ticks due but not yet shown, carried between frames.
+    pending_ticks: f64,
+    /// Tile at the center of the screen, unless following an entity.
+    camera: Point,
+    /// Entity the camera stays on.
+    follow: Option<EntityID>,
+}
+
+impl ReplayView {
+    fn new(playback: Playback) -> Self {
+        let camera = playback
+            .entities()
+            .values()
+            .find(|e| e.entity_type == EntityType::Player)
+            .map_or(SPAWN_POINT, |e| e.position);
+        Self {
+            playback,
+            playing: false,
+            speed: 1.0,
+            pending_ticks: 0.0,
+            camera,
+            follow: None,
+        }
+    }
+
+    /// Move playback on by the ticks due after `seconds` of play.
+    fn advance(&mut self, seconds: f64) {
+        if !self.playing {
+            return;
+        }
+        self.pending_ticks += seconds * self.speed * 1000.0 / TICK_INTERVAL.as_millis() as f64;
+        let whole = self.pending_ticks.floor();
+        self.pending_ticks -= whole;
+        self.playback.seek(self.playback.tick() + whole as u64);
+        if self.playback.is_at_end() {
+            self.playing = false;
+        }
+    }
+
+    /// Where the camera points this frame.
+    fn center(&self) -> Point {
+        self.follow
+            .and_then(|eid| self.playback.entities().get(&eid))
+            .map_or(self.camera, |e| e.position)
+    }
+}
+
+/// What the server browser knows of a saved server's status.
+#[derive(Debug, Clone)]
+enum Probe {
+    Pending,
+    /// Answered, with its round trip time.
+    Online(ServerStatus, Duration),
+    Offline(String),
+}
+
+/// Status pings and the add/edit form of the server browser.
+#[derive(Default)]
+struct ServerBrowser {
+    /// Latest status of each saved server, by endpoint ID.
+    probes: FxHashMap<String, Probe>,
+    results: Option<mpsc::UnboundedReceiver<(String, Probe)>>,
+    form: Option<ServerForm>,
+    /// Why the last join failed.
+    error: Option<String>,
+}
+
+impl ServerBrowser {
+    /// Ping every server in `servers` again.
+    fn refresh(&mut self, servers: &[SavedServer]) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.results = Some(rx);
+        for server in servers {
+            let Ok(addr) = server.id.parse::<EndpointId>() else {
+                let reason = "not a valid server ID".to_owned();
+                self.probes
+                    .insert(server.id.clone(), Probe::Offline(reason));
+                continue;
+            };
+            self.probes.insert(server.id.clone(), Probe::Pending);
+            let (tx, id) = (tx.clone(), server.id.clone());
+            tokio::spawn(async move {
+                let probe = match status::probe(addr).await {
+                    Ok((status, rtt)) => Probe::Online(status, rtt),
+                    Err(e) => Probe::Offline(e),
+                };
+                tx.send((id, probe)).ok();
+            });
+        }
+    }
+
+    /// Take in the answers that arrived. Returns `true` while some are
+    /// still due.
+    fn poll(&mut self) -> bool {
+        while let Some((id, probe)) = self.results.as_mut().and_then(|rx| rx.try_recv().ok()) {
+            self.probes.insert(id, probe);
+        }
+        self.probes.values().any(|p| matches!(p, Probe::Pending))
+    }
+}
+
+/// A server being added to the address book, or edited.
+#[derive(Debug, Default)]
+struct ServerForm {
+    /// Index of the server being edited; `None` when adding one.
+    index: Option<usize>,
+    name: String,
+    id: String,
+    error: Option<String>,
+}
+
+impl ServerForm {
+    fn edit(index: usize, server: &SavedServer) -> Self {
+        Self {
+            index: Some(index),
+            name: server.name.clone(),
+            id: server.id.clone(),
+            error: None,
+        }
+    }
+
+    /// The server as entered, checked against the others in `servers`.
+    fn finish(&self, servers: &[SavedServer]) -> Result<SavedServer, String> {
+        let id = self
+            .id
+            .trim()
+            .parse::<EndpointId>()
+            .map_err(|e| format!("Not a valid server ID: {e}"))?
+            .to_string();
+        let duplicate = servers
+            .iter()
+            .enumerate()
+            .find(|(i, s)| Some(*i) != self.index && s.id == id);
+        if let Some((_, other)) = duplicate {
+            return Err(format!("Already saved as {}", other.name));
+        }
+        if self.index.is_none() && servers.len() >= MAX_SERVERS {
+            return Err(format!("At most {MAX_SERVERS} servers can be saved"));
+        }
+        let name = self.name.trim();
+        Ok(SavedServer {
+            name: if name.is_empty() {
+                short_id(&id)
+            } else {
+                name.to_owned()
+            },
+            last_played: self
+                .index
+                .and_then(|i| servers.get(i))
+                .and_then(|s| s.last_played),
+            id,
+        })
+    }
+}
+
+/// The start of an endpoint ID, enough to tell servers apart.
+fn short_id(id: &str) -> String {
+    let start: String = id.chars().take(10).collect();
+    if start.len() < id.len() {
+        format!("{start}…")
+    } else {
+        start
+    }
+}
+
+/// Players on a server as the browser shows them, e.g. "8/8 playing · 3
+/// waiting".
+fn players_line(status: &ServerStatus) -> String {
+    let playing = match status.max_players {
+        Some(max) => format!("{}/{max} playing", status.players),
+        None => format!("{} playing", status.players),
+    };
+    if status.queued == 0 {
+        playing
+    } else {
+        format!("{playing} · {} waiting", status.queued)
+    }
+}
+
+/// The network diagnostics screen: the server to check against, and the
+/// checks running or last run.
+#[derive(Default)]
+struct DiagnosticsView {
+    server: String,
+    running: Option<oneshot::Receiver<Report>>,
+    report: Option<Report>,
+}
+
+impl DiagnosticsView {
+    /// Run the checks again, against the server typed in if any.
+    fn start(&mut self) {
+        let server = self.server.trim().parse::<EndpointId>().ok();
+        let (tx, rx) = oneshot::channel();
+        self.running = Some(rx);
+        self.report = None;
+        tokio::spawn(async move {
+            tx.send(diagnostics::run(server).await).ok();
+        });
+    }
+
+    /// Take in the report once done. Returns `true` while the checks run.
+    fn poll(&mut self) -> bool {
+        if let Some(rx) = &mut self.running
+            && let Ok(report) = rx.try_recv()
+        {
+            self.running = None;
+            self.report = Some(report);
+        }
+        self.running.is_some()
+    }
+}
+
+/// Seconds between probes of friends' presence while the main menu shows.
+const FRIENDS_REFRESH_SECONDS: f64 = 15.0;
+
+/// Friends' presence, and the endpoint ours is told from.
+#[derive(Default)]
+struct FriendsList {
+    /// What friends are told; shared with the presence endpoint.
+    board: PresenceBoard,
+    /// The presence endpoint, once bound.
+    router: Option<Router>,
+    starting: Option<oneshot::Receiver<Result<Router, String>>>,
+    /// Latest presence of each friend, by endpoint ID; `None` while they
+    /// are being asked, and why they count as offline otherwise.
+    presence: FxHashMap<String, Option<Result<Presence, String>>>,
+    results: Option<mpsc::UnboundedReceiver<(String, Result<Presence, String>)>>,
+    /// When friends were last asked, in seconds of UI time.
+    refreshed_at: Option<f64>,
+    /// Name and endpoint ID of the friend being added.
+    name: String,
+    id: String,
+    error: Option<String>,
+}
+
+impl FriendsList {
+    /// Bind the presence endpoint with identity `key`, if not yet done,
+    /// answering `friends`.
+    fn start(&mut self, key: [u8; 32], friends: &[Friend]) {
+        if self.router.is_some() || self.starting.is_some() {
+            return;
+        }
+        self.set_friends(friends);
+        let (tx, rx) = oneshot::channel();
+        self.starting = Some(rx);
+        let board = self.board.clone();
+        tokio::spawn(async move {
+            let router = presence::serve(&key, board).await;
+            tx.send(router.map_err(|e| e.to_string())).ok();
+        });
+    }
+
+    /// Answer only `friends`.
+    fn set_friends(&self, friends: &[Friend]) {
+        let ids = friends
+            .iter()
+            .filter_map(|f| f.id.parse::<EndpointId>().ok());
+        self.board.set_friends(ids);
+    }
+
+    /// Our endpoint ID, for friends to add.
+    fn own_id(&self) -> Option<String> {
+        Some(self.router.as_ref()?.endpoint().id().to_string())
+    }
+
+    /// Ask every friend in `friends` again.
+    fn refresh(&mut self, friends: &[Friend], now: f64) {
+        let Some(router) = &self.router else {
+            return;
+        };
+        self.refreshed_at = Some(now);
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.results = Some(rx);
+        for friend in friends {
+            let Ok(addr) = friend.id.parse::<EndpointId>() else {
+                let reason = "not a valid ID".to_owned();
+                self.presence.insert(friend.id.clone(), Some(Err(reason)));
+                continue;
+            };
+            // Keep showing what we knew until the answer comes.
+            self.presence.entry(friend.id.clone()).or_insert(None);
+            let (tx, id, endpoint) = (tx.clone(), friend.id.clone(), router.endpoint().clone());
+            tokio::spawn(async move {
+                tx.send((id, presence::probe(&endpoint, addr).await)).ok();
+            });
+        }
+    }
+
+    /// Take in the endpoint once bound and the answers that arrived, and
+    /// ask friends again every [`FRIENDS_REFRESH_SECONDS`].
+    fn poll(&mut self, friends: &[Friend], now: f64) {
+        if let Some(rx) = &mut self.starting
+            && let Ok(started) = rx.try_recv()
+        {
+            self.starting = None;
+            match started {
+                Ok(router) => self.router = Some(router),
+                Err(e) => self.error = Some(format!("Friends are unavailable: {e}")),
+            }
+        }
+        while let Some((id, presence)) = self.results.as_mut().and_then(|rx| rx.try_recv().ok()) {
+            self.presence.insert(id, Some(presence));
+        }
+        if self
+            .refreshed_at
+            .is_none_or(|at| now - at >= FRIENDS_REFRESH_SECONDS)
+        {
+            self.refresh(friends, now);
+        }
+    }
+
+    /// The friend as entered, checked against the others in `friends`.
+    fn finish(&self, friends: &[Friend]) -> Result<Friend, String> {
+        let id = self
+            .id
+            .trim()
+            .parse::<EndpointId>()
+            .map_err(|e| format!("Not a valid friend ID: {e}"))?
+            .to_string();
+        if self.own_id().as_ref() == Some(&id) {
+            return Err("That is your own ID".to_owned());
+        }
+        if let Some(other) = friends.iter().find(|f| f.id == id) {
+            return Err(format!("Already added as {}", other.name));
+        }
+        if friends.len() >= MAX_FRIENDS {
+            return Err(format!("At most {MAX_FRIENDS} friends can be added"));
+        }
+        let name = self.name.trim();
+        Ok(Friend {
+            name: if name.is_empty() {
+                short_id(&id)
+            } else {
+                name.to_owned()
+            },
+            id,
+        })
+    }
+}
+
+/// Seconds since the Unix epoch.
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Progress of a screenshot taken from the pause menu.
+#[derive(Debug, Clone, PartialEq)]
+enum Screenshot {
+    /// Wait one frame so the closed pause menu is not in the picture.
+    Queued(PathBuf),
+    /// Requested from the viewport; saved once the image arrives.
+    Requested(PathBuf),
 }
 
 pub struct GamikApp {
@@ -32,13 +509,179 @@ pub struct GamikApp {
     menu_input_string: String,
 
     game: GameState,
+    /// Map cell size, zoomed with `Ctrl` and the mouse wheel.
     font_size: f32,
-    router: Option<Router>,
+    /// The server hosted in single player.
+    server: Option<Server>,
     // Networking state
     server_to_client_rx: Option<mpsc::UnboundedReceiver<Message>>,
-    client_to_server_tx: Option<mpsc::UnboundedSender<GameAction>>,
+    client_to_server_tx: Option<mpsc::UnboundedSender<ClientMessage>>,
+    /// Keeps `game` in step with the snapshots and deltas received.
+    world_sync: WorldSync,
     screen: AppScreen,
     single_player: bool,
+    debug_overlays: ui::DebugOverlays,
+    /// What the server planned, while the paths overlay asks for it.
+    debug_data: DebugData,
+    /// Map tiles drawn last frame, with their text laid out.
+    frame_buffer: ui::FrameBuffer,
+    /// Time spent per frame in the client's systems.
+    profiler: Profiler,
+    /// Local player's field of view and what it is aware of, recomputed
+    /// when they may have changed.
+    sight: Sight,
+    config: ClientConfig,
+    recorder: MacroRecorder,
+    macro_player: Option<MacroPlayer>,
+    /// Auto-explore, while it runs.
+    explorer: Option<Explorer>,
+    /// Whether the player asked to wait several turns, until the server
+    /// says the wait ended or another key stops it.
+    waiting: bool,
+    /// Until when pressing [`Command::Rest`] again starts the rest.
+    rest_armed: Option<f64>,
+    /// Breadcrumbs behind the local player.
+    trail: Trail,
+    /// Alerts raised for hostiles coming into view.
+    alerts: Alerts,
+    /// Tiles the local player has seen, for map exports.
+    explored: ExploredMap,
+    /// What the player's other characters in this world have seen, kept
+    /// for when they are played again.
+    explored_by_character: FxHashMap<EntityID, ExploredMap>,
+    /// The player's characters in this world, as sent by the server.
+    roster: Roster,
+    paused: bool,
+    /// Destination of map exports and screenshots, as typed in the pause menu.
+    export_path: String,
+    /// Export the map at glyph size instead of one pixel per tile.
+    export_glyph_scale: bool,
+    /// Result of the last export, shown in the pause menu.
+    export_status: Option<String>,
+    screenshot: Option<Screenshot>,
+    console_open: bool,
+    console_input: String,
+    character_sheet_open: bool,
+    /// Node of the local player's conversation, as sent by the server.
+    dialogue: Option<DialogueView>,
+    /// Vendor whose shop window is open.
+    vendor: Option<EntityID>,
+    /// Why the last trade was refused, shown in the shop window.
+    trade_status: Option<String>,
+    /// The last item that broke or could not be repaired, shown in the
+    /// character sheet.
+    item_status: Option<String>,
+    /// Progress of picking a character, or why the server refused it.
+    spawn_status: Option<String>,
+    /// Password or invite code sent when joining the current server.
+    join_secret: Option<String>,
+    /// The password field on character selection, once the server asked
+    /// for one.
+    password_input: Option<String>,
+    /// Place in line on a full server, 1 being next.
+    queue_position: u32,
+    /// Recording being watched.
+    replay: Option<ReplayView>,
+    /// Recordings saved on the server, as last listed.
+    server_recordings: Vec<String>,
+    /// Exports, save slots, imports and the recording being downloaded.
+    transfers: Transfers,
+    /// Progress or outcome of the last recording download.
+    download_status: Option<String>,
+    /// Name of the save slot to save to, as typed in the pause menu.
+    slot_name: String,
+    /// Outcome of the last save, load or deletion of a save slot.
+    slot_status: Option<String>,
+    /// Thumbnails of save slots, by slot, with when the slot was saved.
+    slot_thumbnails: FxHashMap<PathBuf, (u64, Option<egui::TextureHandle>)>,
+    /// Commands sent and their output, oldest first.
+    console_log: Vec<String>,
+    /// Chat, notable events in the world and what happened to us, oldest
+    /// first.
+    chat: MessageLog,
+    /// Regions the character has been in since joining the world.
+    visited_regions: FxHashSet<RegionId>,
+    /// Whether the player is typing a chat message, opened with `Enter`.
+    chat_open: bool,
+    chat_input: String,
+    /// Whispers sent and received, oldest first; see [`whisper`].
+    whispers: Vec<Whisper>,
+    /// Whether the chat shows whispers rather than everything else.
+    whispers_tab: bool,
+    /// Whispers received since the tab was last looked at.
+    unread_whispers: usize,
+    /// Outcome of the last command typed into the chat: a whisper that
+    /// failed, a `/block`, or a server command's output.
+    chat_status: Option<String>,
+    /// Server commands sent from the chat and not yet answered.
+    chat_commands: usize,
+    /// A crash report the player is being shown; see [`crash`].
+    crash_report: Option<PathBuf>,
+    /// When the world was last snapshotted for crash reports.
+    crash_snapshot_at: f64,
+    /// Raw input being recorded or played back, in debug builds; see
+    /// [`input_replay`].
+    input_replay: InputReplay,
+    /// World events running on the server, as last announced.
+    world_events: Vec<Announcement>,
+    /// View radius the server granted, if we asked for one.
+    view_granted: Option<u32>,
+    /// Entities near us but out of sight, in outline, while our view is
+    /// limited.
+    silhouettes: BTreeMap<EntityID, Silhouette>,
+    /// Tiles hit by knockbacks and blasts, flashing in a color until the
+    /// given time.
+    flashes: Vec<(Point, egui::Color32, f64)>,
+    /// Share of their channelled intent entities have done, as last
+    /// reported by the server.
+    action_progress: FxHashMap<EntityID, f32>,
+    /// Pings from the player's team, until they expire.
+    pings: Vec<Ping>,
+    /// Tile being pinged and where its radial menu is open, after a middle
+    /// click.
+    ping_menu: Option<(Point, egui::Pos2)>,
+    /// Command whose next key pressed is added to its keys, from the key
+    /// bindings settings.
+    rebinding: Option<(Context, Command)>,
+    gamepads: Gamepads,
+    /// Content files read again as they change, in debug builds. Content
+    /// is shared by the whole process, so this also reloads it for the
+    /// server hosted in single player.
+    content_watch: Option<ContentWatcher>,
+    /// The player's markers on this world's map, kept on this machine.
+    markers: Markers,
+    /// Whether the map window is shown, toggled with `M`.
+    map_open: bool,
+    /// The way the player last moved, and where they were then.
+    facing: Direction,
+    last_position: Option<Point>,
+    /// Seed and generator parameters entered on the world creation screen.
+    new_world_seed: u64,
+    new_world_config: WorldGenConfig,
+    /// Preview of the world they give, and the seed and parameters it was
+    /// drawn for.
+    world_preview: Option<(u64, WorldGenConfig, egui::TextureHandle)>,
+    /// World last hosted and server last joined, for resuming next time.
+    last_world: Option<String>,
+    last_server: Option<String>,
+    /// Session as last saved; the app saves again soon after it changes.
+    saved_session: UiSessionState,
+    /// Whether the main menu offers to go back to the last game.
+    resume_prompt: bool,
+    browser: ServerBrowser,
+    friends: FriendsList,
+    diagnostics: DiagnosticsView,
+    /// Whether the client is still waiting for the server to answer.
+    connecting: bool,
+    /// Why the client task stopped, if it failed.
+    client_failed: Option<oneshot::Receiver<String>>,
+    /// Context to wake for a repaint when a message arrives; `None` in
+    /// tests, where nothing is drawn.
+    repaint: Option<egui::Context>,
+    /// Whether the machine runs on battery, looked at now and then.
+    power: PowerWatch,
+    /// Whether the client is saving power; see [`LowPower`].
+    low_power: bool,
 
     // Test mode field
     test_mode_initialized: bool,
@@ -48,7 +691,7 @@ impl Default for GamikApp {
     fn default() -> Self {
         Self {
             menu_input_string: String::new(),
-            router: None,
+            server: None,
             screen: if TEST_MODE {
                 AppScreen::Playing
             } else {
@@ -57,10 +700,93 @@ impl Default for GamikApp {
             player_id: EntityID(0),
             button_size: None,
             game: GameState::create_test_world("default".into()),
-            font_size: 14.0,
+            font_size: DEFAULT_ZOOM,
             server_to_client_rx: None,
             client_to_server_tx: None,
+            world_sync: WorldSync::default(),
             single_player: true,
+            debug_overlays: ui::DebugOverlays::default(),
+            debug_data: DebugData::default(),
+            frame_buffer: ui::FrameBuffer::default(),
+            profiler: Profiler::default(),
+            sight: Sight::default(),
+            config: ClientConfig::default(),
+            recorder: MacroRecorder::default(),
+            macro_player: None,
+            explorer: None,
+            waiting: false,
+            rest_armed: None,
+            trail: Trail::default(),
+            alerts: Alerts::default(),
+            explored: ExploredMap::default(),
+            explored_by_character: FxHashMap::default(),
+            roster: Roster::default(),
+            paused: false,
+            export_path: "exports/map.png".to_owned(),
+            export_glyph_scale: false,
+            export_status: None,
+            screenshot: None,
+            console_open: false,
+            console_input: String::new(),
+            character_sheet_open: false,
+            dialogue: None,
+            vendor: None,
+            trade_status: None,
+            item_status: None,
+            spawn_status: None,
+            join_secret: None,
+            password_input: None,
+            queue_position: 0,
+            replay: None,
+            server_recordings: Vec::new(),
+            transfers: Transfers::default(),
+            download_status: None,
+            slot_name: String::new(),
+            slot_status: None,
+            slot_thumbnails: FxHashMap::default(),
+            console_log: Vec::new(),
+            chat: MessageLog::default(),
+            visited_regions: FxHashSet::default(),
+            chat_open: false,
+            chat_input: String::new(),
+            whispers: Vec::new(),
+            whispers_tab: false,
+            unread_whispers: 0,
+            chat_status: None,
+            chat_commands: 0,
+            crash_report: crash::unseen(Path::new(crash::CRASH_DIR)),
+            crash_snapshot_at: 0.0,
+            input_replay: InputReplay::default(),
+            world_events: Vec::new(),
+            view_granted: None,
+            silhouettes: BTreeMap::new(),
+            flashes: Vec::new(),
+            pings: Vec::new(),
+            ping_menu: None,
+            rebinding: None,
+            gamepads: Gamepads::default(),
+            content_watch: cfg!(debug_assertions)
+                .then(|| ContentWatcher::new(content::CONTENT_DIR)),
+            markers: Markers::new(WorldId(0)),
+            map_open: false,
+            facing: Direction::Up,
+            last_position: None,
+            new_world_seed: 0,
+            new_world_config: WorldGenConfig::default(),
+            world_preview: None,
+            last_world: None,
+            last_server: None,
+            saved_session: UiSessionState::default(),
+            resume_prompt: false,
+            browser: ServerBrowser::default(),
+            friends: FriendsList::default(),
+            diagnostics: DiagnosticsView::default(),
+            connecting: false,
+            client_failed: None,
+            repaint: None,
+            power: PowerWatch::default(),
+            low_power: false,
+            action_progress: FxHashMap::default(),
             test_mode_initialized: false,
         }
     }
@@ -115,43 +841,117 @@ impl GamikApp {
         // Apply the fonts to the context
         cc.egui_ctx.set_fonts(fonts);
 
-        Self::default()
+        let config = ClientConfig::load(cc.storage);
+        game::persist::set_backups(config.save_backups());
+        #[cfg(feature = "backup-peer")]
+        install_backup_peer(&config);
+
+        let session = UiSessionState::load(cc.storage);
+        Self {
+            config,
+            font_size: session.zoom,
+            character_sheet_open: session.character_sheet_open,
+            map_open: session.map_open,
+            console_open: session.console_open,
+            last_world: session.world.clone(),
+            last_server: session.server.clone(),
+            resume_prompt: session.can_resume(),
+            saved_session: session,
+            repaint: Some(cc.egui_ctx.clone()),
+            ..Self::default()
+        }
     }
 
-    fn start_client<A>(&mut self, addr: A)
-    where
-        A: Into<EndpointAddr> + Clone,
-    {
-        let (msg_tx, msg_rx) = mpsc::unbounded_channel();
-        let (event_tx, event_rx) = mpsc::unbounded_channel();
+    /// What to restore on the next start.
+    fn session(&self) -> UiSessionState {
+        UiSessionState {
+            world: self.last_world.clone(),
+            server: self.last_server.clone(),
+            character_sheet_open: self.character_sheet_open,
+            map_open: self.map_open,
+            console_open: self.console_open,
+            zoom: self.font_size,
+            ..UiSessionState::default()
+        }
+    }
 
-        let s_addr = addr.clone().into();
+    /// Host the world saved at `path` and join it.
+    fn host_world(&mut self, path: &Path) {
+        let Ok(world) = game::load_from_file(path) else {
+            return;
+        };
+        self.last_world = path.file_stem().and_then(|s| s.to_str()).map(str::to_owned);
+        self.single_player = true;
+        let key = SecretKey::from_bytes(&presence::generate_key());
+        self.start_server(world, key.public());
+        if let Some(server) = &self.server {
+            let addr = server.addr();
+            self.start_client(addr, Some(key));
+            self.screen = AppScreen::CharacterSelection;
+        }
+    }
 
-        self.server_to_client_rx = Some(msg_rx);
-        self.client_to_server_tx = Some(event_tx);
+    /// Join the server with endpoint ID `id`, as typed in. Returns `false`
+    /// if `id` is not one.
+    fn join_server(&mut self, id: &str) -> bool {
+        let Ok(addr) = id.trim().parse::<EndpointId>() else {
+            return false;
+        };
+        let id = addr.to_string();
+        self.config.played_server(&id, unix_now());
+        self.spawn_status = Some(format!("Connecting to {}…", short_id(&id)));
+        self.join_secret = None;
+        self.password_input = None;
+        self.last_server = Some(id);
+        self.browser.error = None;
+        self.connecting = true;
+        self.single_player = false;
+        self.start_client(addr, None);
+        self.screen = AppScreen::CharacterSelection;
+        true
+    }
 
-        tokio::spawn(async move {
-            let _ = run_client_internal(s_addr, msg_tx, event_rx).await;
-        });
+    /// Connect to `addr`, from an endpoint bound with `key` if given, as
+    /// when playing on a server hosted here.
+    fn start_client(&mut self, addr: impl Into<EndpointAddr>, key: Option<SecretKey>) {
+        let player = self.config.player_key();
+        let connection = match self.repaint.clone() {
+            Some(ctx) => client::connect_waking(addr, player, key, move || ctx.request_repaint()),
+            None => client::connect(addr, player, key),
+        };
+        self.server_to_client_rx = Some(connection.rx);
+        self.client_to_server_tx = Some(connection.tx);
+        self.client_failed = Some(connection.failed);
     }
 
-    fn start_server(&mut self, game: GameState) {
-        let (router_tx, mut router_rx) = mpsc::unbounded_channel();
+    /// Host `game`, with the player on endpoint `host` as its host.
+    fn start_server(&mut self, game: GameState, host: EndpointId) {
+        let (server_tx, mut server_rx) = mpsc::unbounded_channel();
+        let mut builder = Server::builder()
+            .world(game)
+            .when_empty(self.config.when_empty)
+            .host(host);
+        if let Some(password) = &self.config.host_password {
+            builder = builder.password(password.as_str());
+        }
 
         // Spawn an async task to start the server
         tokio::spawn(async move {
-            match run_server_internal(game).await {
-                Ok(router) => {
-                    // Send the router back to the main thread
-                    let _ = router_tx.send(router);
+            let spawned = builder.spawn().await;
+            match spawned {
+                Ok(server) => {
+                    // Give the relays a moment to learn about us.
+                    tokio::time::sleep(Duration::from_millis(2000)).await;
+                    // Send the server back to the main thread
+                    server_tx.send(server).ok();
                 }
                 Err(e) => eprintln!("Server error: {e}"),
             }
         });
 
-        while self.router.is_none() {
-            if let Ok(router) = router_rx.try_recv() {
-                self.router = Some(router);
+        while self.server.is_none() {
+            if let Ok(server) = server_rx.try_recv() {
+                self.server = Some(server);
             }
         }
     }
@@ -172,17 +972,21 @@ impl GamikApp {
             });
 
         // Start server (blocking)
-        self.start_server(test_world);
+        let key = SecretKey::from_bytes(&presence::generate_key());
+        self.start_server(test_world, key.public());
 
         // Connect as client
-        if let Some(router) = &self.router {
-            let eid = router.endpoint().addr();
-            self.start_client(eid);
+        if let Some(server) = &self.server {
+            let eid = server.addr();
+            self.start_client(eid, Some(key));
         }
 
         // Spawn test player
         if let Some(tx) = &self.client_to_server_tx {
-            let _ = tx.send(GameAction::SpawnPlayer("TestPlayer".to_string()));
+            tx.send(ClientMessage::Action(GameAction::SpawnPlayer(
+                "TestPlayer".to_string(),
+            )))
+            .ok();
         }
 
         self.test_mode_initialized = true;
@@ -190,6 +994,35 @@ impl GamikApp {
 }
 
 impl eframe::App for GamikApp {
+    /// Record raw input, or play a recording back in its place; debug
+    /// builds only.
+    fn raw_input_hook(&mut self, ctx: &egui::Context, raw_input: &mut egui::RawInput) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+        let mut record = false;
+        let mut play = false;
+        raw_input.events.retain(|event| match event {
+            egui::Event::Key {
+                key, pressed: true, ..
+            } if *key == input_replay::RECORD_KEY || *key == input_replay::PLAY_KEY => {
+                record |= *key == input_replay::RECORD_KEY;
+                play |= *key == input_replay::PLAY_KEY;
+                false
+            }
+            _ => true,
+        });
+        if record {
+            self.toggle_input_recording(ctx.content_rect().size());
+        }
+        if play {
+            self.toggle_input_playback();
+        }
+        if self.input_replay.hook(raw_input) {
+            log_console(&mut self.console_log, "Input playback finished".to_owned());
+        }
+    }
+
     /// Called each time the UI needs repainting, which may be many times per second.
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         // Initialize test mode once
@@ -198,10 +1031,47 @@ impl eframe::App for GamikApp {
         }
 
         // Poll network → update local game state copy
-        self.poll_network();
+        self.poll_network(ctx.input(|i| i.time));
+        self.crash_context(ctx.input(|i| i.time));
+        if let Some(report) = crash::take_new() {
+            self.crash_report = Some(report);
+        }
+        if self.crash_report.is_some() {
+            self.crash_report_window(ctx);
+        }
 
-        // Request continuous repainting to keep UI responsive
-        ctx.request_repaint();
+        self.update_low_power(ctx.input(|i| i.time));
+        self.reload_content();
+
+        // Input and, through the connection, messages bring frames on their
+        // own; otherwise repaint only as often as something on screen moves.
+        if self.animating() && self.low_power {
+            ctx.request_repaint_after(ANIMATION_FRAME);
+        } else if self.animating() {
+            ctx.request_repaint();
+        } else if self.screen == AppScreen::Playing && !self.low_power {
+            // Trees and water shimmer; a few frames a second are enough.
+            ctx.request_repaint_after(ANIMATION_FRAME);
+        } else {
+            ctx.request_repaint_after(IDLE_FRAME);
+        }
+        if self.gamepads.connected() {
+            let frame = if self.low_power {
+                ANIMATION_FRAME
+            } else {
+                GAMEPAD_FRAME
+            };
+            ctx.request_repaint_after(frame);
+        }
+        // Read on every screen, so presses in the menus are not kept for
+        // the world.
+        let context = match self.screen {
+            AppScreen::Playing => self.input_context(),
+            _ => Context::Menus,
+        };
+        let pad = self
+            .gamepads
+            .poll(ctx.input(|i| i.time), &self.config.gamepad, context);
 
         match self.screen {
             AppScreen::MainMenu => {
@@ -219,13 +1089,40 @@ impl eframe::App for GamikApp {
             AppScreen::WorldSelection => {
                 self.show_world_selection_menu(ctx);
             }
-            AppScreen::Playing => {
-                // Collect input → game actions
-                self.input(ctx);
+            AppScreen::ServerBrowser => self.show_server_browser(ctx),
+            AppScreen::JoinQueue => self.show_join_queue(ctx),
+            AppScreen::ReplaySelection => self.show_replay_selection_menu(ctx),
+            AppScreen::Replay => self.replay_screen(ctx),
+            AppScreen::Diagnostics => self.show_diagnostics(ctx),
+            AppScreen::Playing => self.playing_screen(ctx, context, pad),
+        }
+    }
 
-                // Render
-                self.rogue_screen(ctx);
-            }
+    /// Save soon after the session changes, rather than only every half
+    /// minute.
+    fn auto_save_interval(&self) -> std::time::Duration {
+        if self.session() == self.saved_session {
+            std::time::Duration::from_secs(30)
+        } else {
+            std::time::Duration::from_secs(1)
+        }
+    }
+
+    /// Persist the client config and UI session, and keep the received world on disk so
+    /// rejoining only needs the changes.
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        self.config.save(storage);
+        let session = self.session();
+        session.save(storage);
+        self.saved_session = session;
+
+        // With a limited view, we only hold the part of the world near us.
+        let partial = self.view_granted.is_some();
+        let Some(cache) = self.world_sync.cache(&self.game, partial) else {
+            return;
+        };
+        if let Err(e) = cache.save() {
+            log::warn!("Failed to save world cache: {e}");
         }
     }
 }
@@ -235,407 +1132,3706 @@ impl eframe::App for GamikApp {
 // ---------------------------------------------------------------------------
 
 impl GamikApp {
-    /// Drain all pending network messages into local game state.
-    fn poll_network(&mut self) {
-        let Some(rx) = &mut self.server_to_client_rx else {
-            return;
+    /// The world with its panels and windows, driven by the commands of
+    /// this frame.
+    fn playing_screen(&mut self, ctx: &egui::Context, context: Context, pad: Vec<Command>) {
+        // Keys are only bound from the pause menu.
+        if !self.paused {
+            self.rebinding = None;
+        }
+        // Keys pressed while binding one go to the binding.
+        let commands = if self.rebinding.is_some() {
+            Vec::new()
+        } else {
+            let mut keys = ctx.input(|i| {
+                self.config
+                    .keymap
+                    .pressed(context, |key| i.key_pressed(key))
+            });
+            keys.extend(pad);
+            keys
         };
-        while let Ok(msg) = rx.try_recv() {
-            if let Message::Server(smsg) = msg {
-                match smsg {
-                    ServerMessage::EntityMap(emap) => {
-                        self.game.entities = emap;
-                    }
-                    ServerMessage::PlayerID(pid) => self.player_id = pid,
-                }
+        for command in &commands {
+            match command {
+                Command::Pause => self.paused = true,
+                Command::Close => self.close(context),
+                Command::Console => self.console_open = !self.console_open,
+                Command::Chat => self.chat_open = true,
+                _ => {}
             }
         }
-    }
+        // Collect input → game actions
+        if matches!(context, Context::Gameplay | Context::Targeting) {
+            self.input(ctx, &commands);
+        }
 
-    fn show_main_menu(&mut self, ctx: &egui::Context) {
-        egui::CentralPanel::default().show(ctx, |ui| {
-            ui.vertical_centered(|ui| {
-                ui.add_space(50.0);
+        if let Some(assembler) = self.world_sync.loading() {
+            egui::TopBottomPanel::bottom("loading").show(ctx, |ui| {
+                ui.add(
+                    egui::ProgressBar::new(assembler.progress())
+                        .text("Loading world…")
+                        .show_percentage(),
+                );
+            });
+        }
+        if !self.world_events.is_empty() {
+            egui::TopBottomPanel::top("world_events").show(ctx, |ui| {
+                for event in &self.world_events {
+                    ui.colored_label(egui::Color32::GOLD, &event.text);
+                }
+            });
+        }
+        if let Some(status) = self.macro_status() {
+            egui::TopBottomPanel::bottom("macro").show(ctx, |ui| {
+                ui.label(status);
+            });
+        }
+        let encumbrance = self
+            .game
+            .entities()
+            .get(&self.player_id)
+            .map(|player| Encumbrance::of(player, &ContentRegistry::builtin()));
+        if let Some(encumbrance) = encumbrance.filter(|e| *e != Encumbrance::Unburdened) {
+            egui::TopBottomPanel::bottom("encumbrance").show(ctx, |ui| {
+                ui.colored_label(
+                    egui::Color32::ORANGE,
+                    format!("You are {encumbrance} and move slowly"),
+                );
+            });
+        }
 
-                ui.heading(RichText::new("Roguelike Game").size(32.0));
+        // Render
+        self.rogue_screen(ctx);
+        if self.character_sheet_open {
+            self.character_sheet(ctx);
+        }
+        if self.map_open {
+            self.map_window(ctx);
+        }
+        if self.config.show_compass {
+            self.compass_hud(ctx);
+        }
+        if self.dialogue.is_some() {
+            self.dialogue_window(ctx);
+        }
+        if self.vendor.is_some() {
+            self.shop_window(ctx);
+        }
+        if self.console_open {
+            self.console_window(ctx);
+        }
+        self.chat_window(ctx);
+        if self.paused {
+            self.pause_menu(ctx);
+        }
+        self.poll_screenshot(ctx);
+    }
 
-                ui.add_space(50.0);
+    /// Drain all pending network messages into local game state. `now` is
+    /// the UI time in seconds, used to time effects.
+    fn poll_network(&mut self, now: f64) {
+        if let Some(reason) = self
+            .client_failed
+            .as_mut()
+            .and_then(|rx| rx.try_recv().ok())
+        {
+            self.client_failed = None;
+            if self.connecting {
+                self.connecting = false;
+                self.spawn_status = None;
+                self.browser.error = Some(format!("Could not connect: {reason}"));
+                self.screen = AppScreen::ServerBrowser;
+            } else {
+                log::info!("Disconnected: {reason}");
+            }
+            crash::note(format!("Connection failed: {reason}"));
+        }
+        while let Some(msg) = self
+            .server_to_client_rx
+            .as_mut()
+            .and_then(|rx| rx.try_recv().ok())
+        {
+            let Message::Server(smsg) = msg else {
+                continue;
+            };
+            let smsg = match self.world_sync.receive(smsg, &mut self.game) {
+                Ok(applied) => {
+                    self.world_updated(applied);
+                    continue;
+                }
+                Err(smsg) => smsg,
+            };
+            let smsg = match client::join_reply(smsg) {
+                Ok(joined) => {
+                    self.joined(joined);
+                    continue;
+                }
+                Err(smsg) => smsg,
+            };
+            let smsg = match self.transfers.receive(smsg) {
+                Ok(transfer) => {
+                    self.transferred(transfer);
+                    continue;
+                }
+                Err(smsg) => smsg,
+            };
+            match smsg {
+                ServerMessage::Silhouettes(delta) => delta.apply_to(&mut self.silhouettes),
+                ServerMessage::ViewDistance(granted) => {
+                    self.view_granted = granted;
+                    self.update_view_radius();
+                }
+                ServerMessage::Roster(roster) => self.roster = roster,
+                ServerMessage::WorldInfo { id, name } => self.join_world(id, name),
+                msg @ (ServerMessage::ConsoleOutput(_)
+                | ServerMessage::Chat(_)
+                | ServerMessage::ChatHistory(_)
+                | ServerMessage::Whisper(_)
+                | ServerMessage::WhisperFailed(_)) => self.chat_message(msg),
+                ServerMessage::Dialogue(view) => self.dialogue = view,
+                msg @ (ServerMessage::TradeRejected(_)
+                | ServerMessage::RepairRejected(_)
+                | ServerMessage::StackRejected(_)
+                | ServerMessage::ItemBroke(_)
+                | ServerMessage::Pushed { .. }
+                | ServerMessage::Explosion { .. }
+                | ServerMessage::ActionProgress { .. }
+                | ServerMessage::PathBlocked { .. }
+                | ServerMessage::WaitEnded { .. }) => self.game_feedback(msg, now),
+                ServerMessage::WorldEvent(announcement) => self.world_event(announcement),
+                ServerMessage::Ping(ping) => self.pings.push(ping),
+                ServerMessage::Debug(data) => self.debug_data = data,
+                // Read above.
+                _ => {}
+            }
+        }
+    }
 
-                if ui.button(RichText::new("Start Game").size(20.0)).clicked() {
-                    self.single_player = true;
-                    self.screen = AppScreen::WorldSelection;
+    /// Add chat, whispers and console output to their windows.
+    fn chat_message(&mut self, msg: ServerMessage) {
+        match msg {
+            ServerMessage::ConsoleOutput(output) => {
+                if self.chat_commands > 0 {
+                    self.chat_commands -= 1;
+                    self.chat_status = Some(output.clone());
+                }
+                log_console(&mut self.console_log, output);
+            }
+            ServerMessage::Chat(line) => self.chat.push(line),
+            ServerMessage::ChatHistory(lines) => self.chat.history(lines),
+            ServerMessage::Whisper(whisper) => {
+                if !self.whispers_tab {
+                    self.unread_whispers += 1;
                 }
+                self.whispers.push(whisper);
+                let excess = self.whispers.len().saturating_sub(MAX_CHAT_LINES);
+                self.whispers.drain(..excess);
+            }
+            ServerMessage::WhisperFailed(reason) => {
+                self.chat_status = Some(reason);
+                self.whispers_tab = true;
+            }
+            _ => {}
+        }
+    }
 
-                ui.add_space(20.0);
+    /// Follow the server through joining: the character we play, rejections,
+    /// the join queue and resumed sessions.
+    fn joined(&mut self, joined: Joined) {
+        match joined {
+            Joined::Connected => {
+                if self.connecting {
+                    self.connecting = false;
+                    self.spawn_status = None;
+                }
+            }
+            Joined::Playing(pid) => {
+                self.player_id = pid;
+                self.last_position = None;
+                self.trail.clear();
+                self.send_color();
+                // The server confirmed the character we picked or created.
+                if matches!(
+                    self.screen,
+                    AppScreen::CharacterSelection | AppScreen::CharacterCreation
+                ) {
+                    self.screen = AppScreen::Playing;
+                }
+                self.spawn_status = None;
+            }
+            Joined::Resumed => {}
+            Joined::Refused { reason, secret } => {
+                self.spawn_status = Some(format!("Could not join: {reason}"));
+                if secret {
+                    self.password_input.get_or_insert_with(String::new);
+                }
+            }
+            Joined::Queued(position) => {
+                self.queue_position = position;
+                self.spawn_status = None;
+                self.password_input = None;
+                self.screen = AppScreen::JoinQueue;
+            }
+            Joined::Admitted => {
+                self.queue_position = 0;
+                self.screen = AppScreen::CharacterSelection;
+                if let Some(tx) = &self.client_to_server_tx {
+                    tx.send(ClientMessage::ChatHistory(MAX_CHAT_LINES as u32))
+                        .ok();
+                }
+            }
+            Joined::Forgotten => {
+                if let Some(tx) = &self.client_to_server_tx {
+                    tx.send(ClientMessage::Identify(self.config.player_key()))
+                        .ok();
+                }
+                if self.screen == AppScreen::Playing {
+                    self.screen = AppScreen::CharacterSelection;
+                }
+            }
+        }
+    }
 
-                if ui
-                    .button(RichText::new("Join Online Game").size(20.0))
-                    .clicked()
-                {
-                    self.single_player = false;
-                    // Parse the endpoint address
-                    match self.menu_input_string.parse::<EndpointId>() {
-                        Ok(addr) => {
-                            self.start_client(addr);
-                            self.menu_input_string.clear();
-                            self.screen = AppScreen::CharacterSelection;
-                        }
-                        Err(_) => {
-                            self.screen = AppScreen::MainMenu;
-                        }
-                    }
+    /// Show what came of actions in the world: rejections, broken items,
+    /// knockback, blasts, progress and blocked walks.
+    fn game_feedback(&mut self, msg: ServerMessage, now: f64) {
+        match msg {
+            ServerMessage::TradeRejected(reason) => self.trade_status = Some(reason),
+            ServerMessage::RepairRejected(reason) => {
+                self.item_status = Some(format!("Could not repair: {reason}"));
+            }
+            ServerMessage::StackRejected(reason) => {
+                self.item_status = Some(format!("Could not move items: {reason}"));
+            }
+            ServerMessage::ItemBroke(item) => {
+                let registry = ContentRegistry::builtin();
+                let text = format!("Your {} broke", item_name(&registry, &item));
+                self.chat
+                    .note(self.game.tick, Category::Combat, text.clone());
+                self.item_status = Some(text);
+            }
+            ServerMessage::Pushed { entity_id, to, .. } => {
+                let until = now + FLASH_SECONDS;
+                self.flashes.push((to, egui::Color32::DARK_RED, until));
+                if entity_id == self.player_id {
+                    let text = "You were knocked back".to_owned();
+                    self.chat.note(self.game.tick, Category::Combat, text);
+                }
+            }
+            ServerMessage::Explosion { origin, tiles } => {
+                let text = format!("A blast went off at ({}, {})", origin.x, origin.y);
+                self.chat.note(self.game.tick, Category::Combat, text);
+                let until = now + FLASH_SECONDS;
+                let color = egui::Color32::from_rgb(160, 80, 0);
+                self.flashes
+                    .extend(tiles.into_iter().map(|tile| (tile, color, until)));
+            }
+            ServerMessage::ActionProgress {
+                entity_id,
+                fraction,
+            } => {
+                self.action_progress.insert(entity_id, fraction);
+            }
+            ServerMessage::PathBlocked {
+                entity_id,
+                destination,
+            } => {
+                let who = if entity_id == self.player_id {
+                    "You".to_owned()
+                } else {
+                    self.game
+                        .entities()
+                        .get(&entity_id)
+                        .and_then(|e| e.name.clone())
+                        .unwrap_or_else(|| format!("#{}", entity_id.0))
+                };
+                let text = format!(
+                    "{who} could not get through to ({}, {})",
+                    destination.x, destination.y
+                );
+                self.chat.note(self.game.tick, Category::Exploration, text);
+                let until = now + FLASH_SECONDS;
+                self.flashes
+                    .push((destination, egui::Color32::DARK_GRAY, until));
+            }
+            ServerMessage::WaitEnded { entity_id, end } => {
+                if entity_id == self.player_id && std::mem::take(&mut self.waiting) {
+                    self.wait_ended(end);
                 }
+            }
+            _ => {}
+        }
+    }
 
-                ui.add_space(20.0);
+    /// Keep crash reports up to date with the tick and, if the player opted
+    /// in, a snapshot of the world every [`crash::SNAPSHOT_SECONDS`].
+    fn crash_context(&mut self, now: f64) {
+        if self.screen != AppScreen::Playing {
+            return;
+        }
+        crash::set_tick(self.game.tick);
+        if self.config.crash_snapshots && now - self.crash_snapshot_at >= crash::SNAPSHOT_SECONDS {
+            self.crash_snapshot_at = now;
+            crash::keep_snapshot(&self.game);
+        }
+    }
 
-                ui.label("Enter Server ID:");
-                ui.text_edit_singleline(&mut self.menu_input_string);
+    /// Where the last crash report was written, and that it stays here.
+    fn crash_report_window(&mut self, ctx: &egui::Context) {
+        let Some(path) = self.crash_report.clone() else {
+            return;
+        };
+        let mut close = false;
+        egui::Window::new("Crash Report")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+            .show(ctx, |ui| {
+                ui.label("The game ran into an error. A report was saved to:");
+                ui.monospace(path.display().to_string());
+                ui.label(
+                    "Attach that folder to a bug report to help fix it. \
+                     Nothing was sent anywhere.",
+                );
+                ui.horizontal(|ui| {
+                    if ui.button("Copy Path").clicked() {
+                        ctx.copy_text(path.display().to_string());
+                    }
+                    if ui.button("Close").clicked() {
+                        close = true;
+                    }
+                });
             });
-        });
+        if close {
+            if let Err(e) = crash::mark_seen(&path) {
+                log::warn!("Failed to mark the crash report as seen: {e}");
+            }
+            self.crash_report = None;
+        }
     }
 
-    fn show_world_selection_menu(&mut self, ctx: &egui::Context) {
-        egui::CentralPanel::default().show(ctx, |ui| {
-            ui.vertical_centered(|ui| {
-                ui.add_space(50.0);
+    /// Start recording raw input in a window of `screen` points, or stop
+    /// and save the recording.
+    fn toggle_input_recording(&mut self, screen: egui::Vec2) {
+        if !self.input_replay.is_recording() {
+            self.input_replay.record(screen);
+            log_console(&mut self.console_log, "Recording input".to_owned());
+            return;
+        }
+        let Some(recording) = self.input_replay.stop() else {
+            return;
+        };
+        let frames = recording.frames.len();
+        let line = match recording.save(&unix_now().to_string()) {
+            Ok(path) => format!("Saved {frames} frames of input to {}", path.display()),
+            Err(e) => format!("Failed to save the input recording: {e}"),
+        };
+        log_console(&mut self.console_log, line);
+    }
 
-                ui.heading("World Selection");
-                ui.add_space(20.0);
+    /// Play the newest input recording back, or stop playing.
+    fn toggle_input_playback(&mut self) {
+        if self.input_replay.is_playing() {
+            self.input_replay.stop();
+            log_console(&mut self.console_log, "Input playback stopped".to_owned());
+            return;
+        }
+        let Some(path) = InputRecording::newest() else {
+            log_console(&mut self.console_log, "No input recordings".to_owned());
+            return;
+        };
+        let line = match InputRecording::load(&path) {
+            Ok(recording) => {
+                let line = format!("Playing {}", path.display());
+                self.input_replay.play(recording);
+                line
+            }
+            Err(e) => format!("Failed to load {}: {e}", path.display()),
+        };
+        log_console(&mut self.console_log, line);
+    }
 
-                // Create New World button
-                if ui
-                    .button(RichText::new("Create New World").size(20.0))
-                    .clicked()
-                {
-                    self.screen = AppScreen::WorldCreation;
-                }
+    /// Track a world event that started or ended.
+    fn world_event(&mut self, announcement: Announcement) {
+        log_console(&mut self.console_log, announcement.text.clone());
+        let text = announcement.text.clone();
+        self.chat.note(self.game.tick, Category::System, text);
+        self.world_events.retain(|e| e.id != announcement.id);
+        if announcement.active {
+            self.world_events.push(announcement);
+        }
+        self.update_view_radius();
+    }
 
-                ui.add_space(30.0);
+    /// See as far as the server granted, or the usual radius, widened by
+    /// running events.
+    fn update_view_radius(&mut self) {
+        let active = self.world_events.iter().map(|e| e.id.as_str());
+        self.sight.fov.radius = match self.view_granted {
+            Some(radius) => view::sight(radius, active),
+            None => world_events::view_radius(active),
+        };
+    }
 
-                // List existing worlds
-                let world_files = get_world_files();
+    /// Ask the server for the view distance set in the config, or less
+    /// while saving power.
+    fn send_view_distance(&self) {
+        if let Some(tx) = &self.client_to_server_tx {
+            let view = self.config.requested_view(self.low_power);
+            tx.send(ClientMessage::ViewDistance(view)).ok();
+        }
+    }
 
-                if world_files.is_empty() {
-                    ui.label("No existing worlds found");
-                } else {
-                    ui.label(RichText::new("Load Existing World:").size(16.0));
-                    ui.add_space(10.0);
+    /// Ask the server for its debug data while the paths overlay is on,
+    /// and tell it to stop once it is off.
+    fn send_debug_request(&mut self) {
+        if !self.debug_overlays.paths {
+            self.debug_data = DebugData::default();
+        }
+        if let Some(tx) = &self.client_to_server_tx {
+            tx.send(ClientMessage::Debug(self.debug_overlays.paths))
+                .ok();
+        }
+    }
 
-                    egui::ScrollArea::vertical()
-                        .max_height(300.0)
-                        .show(ui, |ui| {
-                            for world_path in world_files {
-                                if let Some(filename) = world_path.file_stem() {
-                                    if let Some(name) = filename.to_str() {
-                                        if ui.button(RichText::new(name).size(18.0)).clicked() {
-                                            // Load the world here
-                                            if let Ok(world) = game::load_from_file(&world_path) {
-                                                self.start_server(world);
-
-                                                let eid =
-                                                    self.router.as_ref().unwrap().endpoint().addr();
-                                                self.start_client(eid);
-                                                self.screen = AppScreen::CharacterSelection;
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        });
-                }
+    /// Tell the server the color to draw our character in.
+    fn send_color(&mut self) {
+        let color = self.config.player_color();
+        self.send_action(GameAction::SetColor(color));
+    }
 
-                ui.add_space(20.0);
+    /// Use the content files if they changed and still make sense together.
+    fn reload_content(&mut self) {
+        let Some(reloaded) = self
+            .content_watch
+            .as_mut()
+            .and_then(|watch| watch.poll(Instant::now()))
+        else {
+            return;
+        };
+        match reloaded {
+            Ok(content) => {
+                content.install();
+                log_console(&mut self.console_log, "Reloaded the content files".into());
+            }
+            Err(e) => log_console(
+                &mut self.console_log,
+                format!("Kept the content in use, the files are broken: {e}"),
+            ),
+        }
+    }
 
-                // Back button
-                if ui.button(RichText::new("Back").size(16.0)).clicked() {
-                    self.screen = AppScreen::MainMenu;
+    /// Start or stop saving power as the setting and the battery say.
+    fn update_low_power(&mut self, now: f64) {
+        let low_power = match self.config.low_power {
+            LowPower::Auto => self.config.low_power.active(self.power.poll(now)),
+            setting => setting.active(None),
+        };
+        if low_power == self.low_power {
+            return;
+        }
+        self.low_power = low_power;
+        if low_power {
+            self.debug_overlays = ui::DebugOverlays::default();
+            self.send_debug_request();
+        }
+        self.send_view_distance();
+    }
+
+    /// Show what came of moving a file to or from the server.
+    fn transferred(&mut self, transfer: Transfer) {
+        match transfer {
+            Transfer::Exported(status) => self.export_status = Some(status),
+            Transfer::SlotSaved(name) => self.slot_saved(&name),
+            Transfer::SlotRejected(status) => self.slot_status = Some(status),
+            Transfer::LeftBehind(line) => log_console(&mut self.console_log, line),
+            Transfer::Recordings(names) => self.server_recordings = names,
+            Transfer::Download { status, next } => {
+                self.download_status = Some(status);
+                if let (Some(request), Some(tx)) = (next, &self.client_to_server_tx) {
+                    tx.send(request).ok();
                 }
-            });
-        });
+            }
+            Transfer::Nothing => {}
+        }
     }
 
-    fn show_character_selection_menu(&mut self, ctx: &egui::Context) {
+    /// Follow up on what a world message changed in `game`.
+    fn world_updated(&mut self, applied: Applied) {
+        match applied {
+            Applied::Replaced => {
+                self.silhouettes.clear();
+                self.sight.invalidate();
+            }
+            Applied::Changed { tick, touched } => {
+                self.sight.note(&touched);
+                let entities = &self.game.entities();
+                self.action_progress
+                    .retain(|eid, _| entities.get(eid).is_some_and(|e| e.queue.is_channelling()));
+                self.pings.retain(|ping| !ping.expired(tick));
+                self.note_region();
+            }
+            Applied::Missing { tick, missing } => {
+                if let Some(tx) = &self.client_to_server_tx {
+                    tx.send(ClientMessage::RequestSnapshotParts { tick, missing })
+                        .ok();
+                }
+            }
+            Applied::Pending => {}
+            Applied::Terrain => self.sight.terrain_changed(),
+        }
+    }
+
+    /// Log the first time since joining that the character enters each
+    /// region.
+    fn note_region(&mut self) {
+        let Some(pos) = self
+            .game
+            .entities()
+            .get(&self.player_id)
+            .map(|e| e.position)
+        else {
+            return;
+        };
+        let region = RegionId::of(pos);
+        if self.visited_regions.insert(region) {
+            let text = format!("Entered region ({}, {})", region.x, region.y);
+            self.chat.note(self.game.tick, Category::Exploration, text);
+        }
+    }
+
+    /// Switch to the world the server announced, loading our cache of it,
+    /// and tell the server which tick we already have.
+    fn join_world(&mut self, id: WorldId, name: String) {
+        if self.game.world_id != id {
+            self.visited_regions.clear();
+            self.explored = ExploredMap::default();
+            self.explored_by_character.clear();
+            self.roster = Roster::default();
+        }
+        if self.markers.world_id != id {
+            self.markers = Markers::load(id);
+        }
+        // The server re-announces running events after this.
+        self.world_events.clear();
+        self.publish_presence(&name);
+        let server = (!self.single_player)
+            .then(|| self.last_server.clone())
+            .flatten();
+        crash::set_world(id, &name, server.as_deref());
+        crash::note(format!("Joined {name} ({id})"));
+        // A limited view left us only part of the world; start afresh.
+        let partial = self.view_granted.is_some();
+        let start = self.world_sync.join(&mut self.game, id, name, partial);
+        if matches!(start, Start::Cached(_)) {
+            self.world_updated(Applied::Replaced);
+        }
+        if self.config.requested_view(self.low_power).is_some() {
+            self.send_view_distance();
+        }
+        if let Some(tx) = &self.client_to_server_tx {
+            tx.send(start.sync(self.join_secret.clone())).ok();
+            tx.send(ClientMessage::ChatHistory(MAX_CHAT_LINES as u32))
+                .ok();
+            if !self.config.blocked.is_empty() {
+                tx.send(ClientMessage::Block(self.config.blocked.clone()))
+                    .ok();
+            }
+        }
+    }
+
+    /// Tell friends we are in the world `name`, hosting it or on a server.
+    fn publish_presence(&self, name: &str) {
+        let world: String = name.chars().take(presence::MAX_PRESENCE_NAME_LEN).collect();
+        let presence = if self.single_player {
+            self.server.as_ref().map(|server| Presence::Hosting {
+                world,
+                server: server.id().to_string(),
+            })
+        } else {
+            self.last_server
+                .clone()
+                .map(|server| Presence::Playing { world, server })
+        };
+        self.friends.board.set(presence.unwrap_or_default());
+    }
+
+    fn show_main_menu(&mut self, ctx: &egui::Context) {
+        let now = ctx.input(|i| i.time);
+        let key = self.config.presence_key();
+        self.friends.start(key, &self.config.friends);
+        self.friends.poll(&self.config.friends, now);
+        self.friends.board.set(Presence::InMenus);
+        egui::SidePanel::right("friends").show(ctx, |ui| self.friends_panel(ui));
+
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.vertical_centered(|ui| {
                 ui.add_space(50.0);
 
-                ui.heading("Character Selection");
+                ui.heading(RichText::new("Roguelike Game").size(32.0));
+
+                ui.add_space(50.0);
+
+                if ui.button(RichText::new("Start Game").size(20.0)).clicked() {
+                    self.single_player = true;
+                    self.screen = AppScreen::WorldSelection;
+                }
+
                 ui.add_space(20.0);
 
-                // Create New World button
                 if ui
-                    .button(RichText::new("Create New Character").size(20.0))
+                    .button(RichText::new("Join Online Game").size(20.0))
                     .clicked()
                 {
-                    self.screen = AppScreen::CharacterCreation;
+                    let id = self.menu_input_string.clone();
+                    if self.join_server(&id) {
+                        self.menu_input_string.clear();
+                    }
                 }
 
-                ui.add_space(30.0);
-
-                let playables = self.game.get_playable_entities();
-
-                if playables.is_empty() {
-                    ui.label("No existing characters found");
-                } else {
-                    ui.label(RichText::new("Load Existing Character:").size(16.0));
-                    ui.add_space(10.0);
+                ui.add_space(20.0);
 
-                    egui::ScrollArea::vertical()
-                        .max_height(300.0)
-                        .show(ui, |ui| {
-                            for playable in playables {
-                                if ui
-                                    .button(RichText::new(format!("{playable:#?}")).size(18.0))
-                                    .clicked()
-                                {
-                                    if let Some(tx) = &self.client_to_server_tx {
-                                        if let Err(e) = tx.send(GameAction::SpawnAs(playable)) {
-                                            eprintln!("Failed to send game event: {e}");
-                                        } else {
-                                            self.screen = AppScreen::Playing;
-                                        }
-                                    }
-                                }
-                            }
-                        });
-                }
+                ui.label("Enter Server ID:");
+                ui.text_edit_singleline(&mut self.menu_input_string);
 
                 ui.add_space(20.0);
 
-                // Back button
-                if ui.button(RichText::new("Back").size(16.0)).clicked() {
-                    if self.single_player {
-                        self.screen = AppScreen::WorldSelection;
-                    } else {
-                        self.screen = AppScreen::MainMenu;
-                    }
+                if ui
+                    .button(RichText::new("Server Browser").size(20.0))
+                    .clicked()
+                {
+                    self.browser.refresh(&self.config.servers);
+                    self.screen = AppScreen::ServerBrowser;
                 }
-            });
-        });
-    }
-    fn show_world_creation_menu(&mut self, ctx: &egui::Context) {
-        egui::CentralPanel::default().show(ctx, |ui| {
-            ui.vertical_centered(|ui| {
-                ui.add_space(50.0);
 
-                ui.heading("World Creation");
                 ui.add_space(20.0);
 
-                // Text input for world name
-                ui.label("Enter world name:");
-                ui.add_space(5.0);
-
-                ui.text_edit_singleline(&mut self.menu_input_string);
+                if ui
+                    .button(RichText::new("Watch Replay").size(20.0))
+                    .clicked()
+                {
+                    self.screen = AppScreen::ReplaySelection;
+                }
 
                 ui.add_space(20.0);
 
-                // Create Test World button
                 if ui
-                    .button(RichText::new("Create Test World").size(20.0))
+                    .button(RichText::new("Network Diagnostics").size(20.0))
                     .clicked()
                 {
-                    let world_name = if self.menu_input_string.trim().is_empty() {
-                        "world_lol".to_string()
-                    } else {
-                        self.menu_input_string.trim().to_string()
-                    };
-                    self.menu_input_string.clear();
-                    let new_world = GameState::create_test_world(world_name);
-                    match game::save_to_file(&new_world) {
-                        Ok(()) => {
-                            self.screen = AppScreen::WorldSelection;
-                        }
-                        Err(e) => {
-                            eprintln!("Failed to create world: {e}");
+                    self.open_diagnostics(self.last_server.clone());
+                }
+            });
+        });
+        if self.resume_prompt {
+            self.resume_window(ctx);
+        }
+    }
+
+    /// Main menu panel with friends' presence, to join them or add more.
+    fn friends_panel(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Friends");
+        match self.friends.own_id() {
+            Some(id) => {
+                ui.horizontal(|ui| {
+                    ui.label(format!("Your ID: {}", short_id(&id)))
+                        .on_hover_text(&id);
+                    if ui.small_button("Copy").clicked() {
+                        ui.ctx().copy_text(id.clone());
+                    }
+                });
+            }
+            None => {
+                ui.weak("Going online…");
+            }
+        }
+        ui.separator();
+
+        if self.config.friends.is_empty() {
+            ui.weak("Add friends by their ID. You see each other once both added the other.");
+        }
+        let mut join = None;
+        let mut remove = None;
+        for (i, friend) in self.config.friends.iter().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label(&friend.name).on_hover_text(&friend.id);
+                match self.friends.presence.get(&friend.id) {
+                    Some(Some(Ok(presence))) => {
+                        ui.colored_label(egui::Color32::LIGHT_GREEN, presence.describe());
+                        if let Some(server) = presence.server()
+                            && ui.small_button("Join").clicked()
+                        {
+                            join = Some(server.to_owned());
                         }
                     }
+                    Some(Some(Err(reason))) => {
+                        ui.weak("Offline").on_hover_text(reason);
+                    }
+                    Some(None) | None => {
+                        ui.weak("…");
+                    }
                 }
+                if ui.small_button("Remove").clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
 
-                ui.add_space(20.0);
+        ui.separator();
+        ui.add(egui::TextEdit::singleline(&mut self.friends.name).hint_text("Name"));
+        ui.add(egui::TextEdit::singleline(&mut self.friends.id).hint_text("Friend ID"));
+        let mut changed = false;
+        if ui.button("Add Friend").clicked() {
+            match self.friends.finish(&self.config.friends) {
+                Ok(friend) => {
+                    self.config.friends.push(friend);
+                    self.friends.name.clear();
+                    self.friends.id.clear();
+                    self.friends.error = None;
+                    changed = true;
+                }
+                Err(e) => self.friends.error = Some(e),
+            }
+        }
+        if let Some(error) = &self.friends.error {
+            ui.colored_label(egui::Color32::LIGHT_RED, error);
+        }
 
-                // Back button
-                if ui.button(RichText::new("Back").size(16.0)).clicked() {
-                    self.screen = AppScreen::WorldSelection;
+        if let Some(i) = remove.filter(|i| *i < self.config.friends.len()) {
+            let friend = self.config.friends.remove(i);
+            self.friends.presence.remove(&friend.id);
+            changed = true;
+        }
+        if changed {
+            self.friends.set_friends(&self.config.friends);
+            self.friends.refreshed_at = None;
+        }
+        if let Some(server) = join
+            && !self.join_server(&server)
+        {
+            self.friends.error = Some(format!("`{server}` is not a valid server ID"));
+        }
+    }
+
+    /// Offer to go back to the world or server of the last session.
+    fn resume_window(&mut self, ctx: &egui::Context) {
+        egui::Window::new("Welcome back")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0.0, -40.0))
+            .show(ctx, |ui| {
+                if let Some(server) = self.last_server.clone() {
+                    ui.label(format!("Last joined: {server}"));
+                    if ui.button("Rejoin server").clicked() {
+                        self.resume_prompt = false;
+                        self.join_server(&server);
+                    }
+                }
+                if let Some(world) = self.last_world.clone() {
+                    ui.label(format!("Last hosted: {world}"));
+                    if ui.button("Continue world").clicked() {
+                        self.resume_prompt = false;
+                        self.host_world(&Path::new("worlds").join(format!("{world}.world")));
+                    }
+                }
+                if ui.button("Not now").clicked() {
+                    self.resume_prompt = false;
                 }
             });
-        });
     }
 
-    fn show_character_creation_menu(&mut self, ctx: &egui::Context) {
+    /// Waiting for a slot on a full server, with a way out.
+    fn show_join_queue(&mut self, ctx: &egui::Context) {
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.vertical_centered(|ui| {
                 ui.add_space(50.0);
-
-                ui.heading("Character Creation");
+                ui.heading("Server Full");
                 ui.add_space(20.0);
+                ui.label(match self.queue_position {
+                    1 => "You are next in line.".to_owned(),
+                    n => format!("You are number {n} in line."),
+                });
+                ui.label("You will be let in as soon as someone leaves.");
+                ui.add_space(10.0);
+                ui.spinner();
+                ui.add_space(20.0);
+                if ui.button(RichText::new("Leave").size(16.0)).clicked() {
+                    // Dropping our end closes the connection, giving up
+                    // our place.
+                    self.client_to_server_tx = None;
+                    self.server_to_client_rx = None;
+                    self.queue_position = 0;
+                    self.screen = AppScreen::ServerBrowser;
+                }
+            });
+        });
+    }
 
-                // Text input for character name
-                ui.label("Enter Character name:");
-                ui.add_space(5.0);
-
-                ui.text_edit_singleline(&mut self.menu_input_string);
-
+    fn show_server_browser(&mut self, ctx: &egui::Context) {
+        if self.browser.poll() {
+            ctx.request_repaint_after(Duration::from_millis(200));
+        }
+        let now = unix_now();
+        let mut join = None;
+        let mut remove = None;
+        let mut diagnose = false;
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.add_space(50.0);
+                ui.heading("Servers");
                 ui.add_space(20.0);
 
-                // Create Character button
-                if ui
-                    .button(RichText::new("Create New Character").size(20.0))
-                    .clicked()
-                {
-                    let char_name = if self.menu_input_string.trim().is_empty() {
-                        "John".to_string()
-                    } else {
-                        self.menu_input_string.trim().to_string()
-                    };
-                    self.menu_input_string.clear();
-                    if let Some(tx) = &self.client_to_server_tx {
-                        if let Err(e) = tx.send(GameAction::SpawnPlayer(char_name)) {
-                            eprintln!("Failed to send game event: {e}");
-                        } else {
-                            self.screen = AppScreen::CharacterSelection;
+                if self.config.servers.is_empty() {
+                    ui.label("No saved servers yet.");
+                } else {
+                    egui::Grid::new("servers").striped(true).show(ui, |ui| {
+                        for header in ["Name", "Server", "Last played", "Status"] {
+                            ui.strong(header);
+                        }
+                        ui.end_row();
+                        for (i, server) in self.config.servers.iter().enumerate() {
+                            ui.label(&server.name);
+                            ui.label(short_id(&server.id)).on_hover_text(&server.id);
+                            ui.label(server.last_played.map_or("never".to_owned(), |t| {
+                                ui::time_ago(now.saturating_sub(t))
+                            }));
+                            match self.browser.probes.get(&server.id) {
+                                Some(Probe::Online(status, rtt)) => ui.colored_label(
+                                    egui::Color32::LIGHT_GREEN,
+                                    format!(
+                                        "{} · {} · {} ms",
+                                        status.world_name,
+                                        players_line(status),
+                                        rtt.as_millis()
+                                    ),
+                                ),
+                                Some(Probe::Offline(reason)) => ui
+                                    .colored_label(egui::Color32::LIGHT_RED, "Offline")
+                                    .on_hover_text(reason),
+                                Some(Probe::Pending) | None => ui.weak("Pinging…"),
+                            };
+                            if ui.button("Join").clicked() {
+                                join = Some(server.id.clone());
+                            }
+                            if ui.button("Edit").clicked() {
+                                self.browser.form = Some(ServerForm::edit(i, server));
+                            }
+                            if ui.button("Remove").clicked() {
+                                remove = Some(i);
+                            }
+                            ui.end_row();
                         }
+                    });
+                }
+
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Add Server").clicked() {
+                        self.browser.form = Some(ServerForm::default());
+                    }
+                    if ui.button("Refresh").clicked() {
+                        self.browser.refresh(&self.config.servers);
+                    }
+                });
+                if let Some(error) = &self.browser.error {
+                    ui.colored_label(egui::Color32::LIGHT_RED, error);
+                    if ui.button("Diagnose").clicked() {
+                        diagnose = true;
                     }
                 }
 
                 ui.add_space(20.0);
-
-                // Back button
                 if ui.button(RichText::new("Back").size(16.0)).clicked() {
-                    self.screen = AppScreen::CharacterSelection;
+                    self.screen = AppScreen::MainMenu;
                 }
             });
         });
-    }
+
+        if let Some(i) = remove.filter(|i| *i < self.config.servers.len()) {
+            self.config.servers.remove(i);
+        }
+        if diagnose {
+            self.open_diagnostics(self.last_server.clone());
+            return;
+        }
+        if let Some(id) = join
+            && !self.join_server(&id)
+        {
+            self.browser.error = Some(format!("`{id}` is not a valid server ID"));
+        }
+        self.server_form_window(ctx);
+    }
+
+    /// Show the diagnostics screen and run the checks, against `server` if
+    /// given.
+    fn open_diagnostics(&mut self, server: Option<String>) {
+        self.diagnostics.server = server.unwrap_or_default();
+        self.diagnostics.start();
+        self.screen = AppScreen::Diagnostics;
+    }
+
+    /// Connectivity checks and what to do about the ones that failed.
+    fn show_diagnostics(&mut self, ctx: &egui::Context) {
+        if self.diagnostics.poll() {
+            ctx.request_repaint_after(Duration::from_millis(200));
+        }
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.add_space(50.0);
+                ui.heading("Network Diagnostics");
+                ui.add_space(20.0);
+
+                ui.horizontal(|ui| {
+                    ui.label("Server ID (optional):");
+                    ui.text_edit_singleline(&mut self.diagnostics.server);
+                    let running = self.diagnostics.running.is_some();
+                    if ui
+                        .add_enabled(!running, egui::Button::new("Run Checks"))
+                        .clicked()
+                    {
+                        self.diagnostics.start();
+                    }
+                });
+                ui.add_space(10.0);
+
+                if self.diagnostics.running.is_some() {
+                    ui.spinner();
+                    ui.label("Checking… this can take up to half a minute.");
+                }
+                if let Some(report) = &self.diagnostics.report {
+                    egui::Grid::new("diagnostics").striped(true).show(ui, |ui| {
+                        for check in &report.checks {
+                            ui.strong(check.name);
+                            match &check.outcome {
+                                Outcome::Passed(detail) => {
+                                    ui.colored_label(egui::Color32::LIGHT_GREEN, "✔");
+                                    ui.label(detail);
+                                }
+                                Outcome::Failed { detail, advice } => {
+                                    ui.colored_label(egui::Color32::LIGHT_RED, "✖");
+                                    ui.label(detail).on_hover_text(*advice);
+                                }
+                                Outcome::Skipped(why) => {
+                                    ui.weak("–");
+                                    ui.weak(why);
+                                }
+                            }
+                            ui.end_row();
+                        }
+                    });
+                    ui.add_space(10.0);
+                    let color = if report.all_passed() {
+                        egui::Color32::LIGHT_GREEN
+                    } else {
+                        egui::Color32::LIGHT_RED
+                    };
+                    ui.colored_label(color, report.verdict());
+                }
+
+                ui.add_space(20.0);
+                if ui.button(RichText::new("Back").size(16.0)).clicked() {
+                    self.screen = AppScreen::MainMenu;
+                }
+            });
+        });
+    }
+
+    /// The window adding a server to the address book, or editing one.
+    fn server_form_window(&mut self, ctx: &egui::Context) {
+        let Some(form) = &mut self.browser.form else {
+            return;
+        };
+        let (mut save, mut cancel) = (false, false);
+        let title = if form.index.is_some() {
+            "Edit Server"
+        } else {
+            "Add Server"
+        };
+        egui::Window::new(title)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("server_form").show(ui, |ui| {
+                    ui.label("Name");
+                    ui.text_edit_singleline(&mut form.name);
+                    ui.end_row();
+                    ui.label("Server ID");
+                    ui.text_edit_singleline(&mut form.id);
+                    ui.end_row();
+                });
+                if let Some(error) = &form.error {
+                    ui.colored_label(egui::Color32::LIGHT_RED, error);
+                }
+                ui.horizontal(|ui| {
+                    save = ui.button("Save").clicked();
+                    cancel = ui.button("Cancel").clicked();
+                });
+            });
+
+        if cancel {
+            self.browser.form = None;
+        } else if save {
+            match form.finish(&self.config.servers) {
+                Ok(server) => {
+                    match form.index.and_then(|i| self.config.servers.get_mut(i)) {
+                        Some(saved) => *saved = server,
+                        None => self.config.servers.push(server),
+                    }
+                    self.browser.form = None;
+                    self.browser.refresh(&self.config.servers);
+                }
+                Err(e) => form.error = Some(e),
+            }
+        }
+    }
+
+    fn show_replay_selection_menu(&mut self, ctx: &egui::Context) {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.add_space(50.0);
+
+                ui.heading("Replays");
+                ui.add_space(20.0);
+
+                let names = recording::list();
+                if names.is_empty() {
+                    ui.label("No recordings found. Download one from a server's pause menu.");
+                } else {
+                    egui::ScrollArea::vertical()
+                        .max_height(300.0)
+                        .show(ui, |ui| {
+                            for name in names {
+                                if !ui.button(RichText::new(&name).size(18.0)).clicked() {
+                                    continue;
+                                }
+                                match Recording::load(&name) {
+                                    Ok(recording) => {
+                                        self.replay =
+                                            Some(ReplayView::new(Playback::new(recording)));
+                                        self.screen = AppScreen::Replay;
+                                    }
+                                    Err(e) => {
+                                        self.download_status =
+                                            Some(format!("Could not load {name}: {e}"));
+                                    }
+                                }
+                            }
+                        });
+                }
+                if let Some(status) = &self.download_status {
+                    ui.label(status);
+                }
+
+                ui.add_space(20.0);
+
+                // Back button
+                if ui.button(RichText::new("Back").size(16.0)).clicked() {
+                    self.screen = AppScreen::MainMenu;
+                }
+            });
+        });
+    }
+
+    fn show_world_selection_menu(&mut self, ctx: &egui::Context) {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.add_space(50.0);
+
+                ui.heading("World Selection");
+                ui.add_space(20.0);
+
+                // Create New World button
+                if ui
+                    .button(RichText::new("Create New World").size(20.0))
+                    .clicked()
+                {
+                    self.screen = AppScreen::WorldCreation;
+                }
+
+                ui.add_space(30.0);
+
+                // List existing worlds
+                let world_files = get_world_files();
+
+                if world_files.is_empty() {
+                    ui.label("No existing worlds found");
+                } else {
+                    ui.label(RichText::new("Load Existing World:").size(16.0));
+                    ui.add_space(10.0);
+
+                    egui::ScrollArea::vertical()
+                        .max_height(300.0)
+                        .show(ui, |ui| {
+                            for world_path in world_files {
+                                let Some(name) = world_path.file_stem().and_then(|s| s.to_str())
+                                else {
+                                    continue;
+                                };
+                                let mut text = RichText::new(name).size(18.0);
+                                if self.last_world.as_deref() == Some(name) {
+                                    text = text.strong();
+                                }
+                                if ui.button(text).clicked() {
+                                    self.resume_prompt = false;
+                                    self.host_world(&world_path);
+                                }
+                            }
+                        });
+                }
+
+                self.save_slots_list(ui);
+
+                ui.add_space(20.0);
+
+                ui.horizontal(|ui| {
+                    ui.label("When no one is playing:");
+                    egui::ComboBox::from_id_salt("when empty")
+                        .selected_text(self.config.when_empty.label())
+                        .show_ui(ui, |ui| {
+                            for option in WhenEmpty::ALL {
+                                ui.selectable_value(
+                                    &mut self.config.when_empty,
+                                    option,
+                                    option.label(),
+                                );
+                            }
+                        });
+                })
+                .response
+                .on_hover_text(
+                    "Pausing saves CPU on an idle server; catching up simulates \
+                     the time missed, up to five minutes, when someone joins",
+                );
+
+                ui.horizontal(|ui| {
+                    ui.label("Password to join:");
+                    let mut password = self.config.host_password.clone().unwrap_or_default();
+                    let edit = egui::TextEdit::singleline(&mut password)
+                        .password(true)
+                        .char_limit(MAX_SECRET_LEN)
+                        .hint_text("none");
+                    if ui.add(edit).changed() && password.len() <= MAX_SECRET_LEN {
+                        self.config.host_password = (!password.is_empty()).then_some(password);
+                    }
+                })
+                .response
+                .on_hover_text(
+                    "Leave empty to keep the password set with `access password`, if any",
+                );
+
+                ui.add_space(20.0);
+
+                // Back button
+                if ui.button(RichText::new("Back").size(16.0)).clicked() {
+                    self.screen = AppScreen::MainMenu;
+                }
+            });
+        });
+    }
+
+    fn show_character_selection_menu(&mut self, ctx: &egui::Context) {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.add_space(50.0);
+
+                ui.heading("Character Selection");
+                ui.add_space(20.0);
+
+                // Create New Character button, unless the roster is full
+                let create = ui
+                    .add_enabled(
+                        !self.roster.is_full(),
+                        egui::Button::new(RichText::new("Create New Character").size(20.0)),
+                    )
+                    .on_disabled_hover_text("Your roster is full");
+                if create.clicked() {
+                    self.screen = AppScreen::CharacterCreation;
+                }
+
+                ui.add_space(30.0);
+
+                if let Some(status) = &self.spawn_status {
+                    ui.label(status);
+                    ui.add_space(10.0);
+                }
+
+                self.password_prompt(ui);
+
+                // Our own characters first, with what they have done, then
+                // everyone else's that may still be free.
+                let label_of = |eid: EntityID| {
+                    let name = self
+                        .game
+                        .entities()
+                        .get(&eid)
+                        .and_then(|e| e.name.as_deref())
+                        .unwrap_or("Unnamed");
+                    format!("{name} (#{})", eid.0)
+                };
+                let playables = self.game.get_playable_entities();
+                let own: Vec<(EntityID, String)> = self
+                    .roster
+                    .characters
+                    .iter()
+                    .filter(|(eid, _)| playables.contains(eid))
+                    .map(|(eid, stats)| {
+                        (*eid, format!("{} — {}", label_of(*eid), stats_line(stats)))
+                    })
+                    .collect();
+                let others: Vec<(EntityID, String)> = playables
+                    .iter()
+                    .filter(|eid| !self.roster.contains(**eid))
+                    .map(|eid| (*eid, label_of(*eid)))
+                    .collect();
+
+                let mut picked = None;
+                if !self.world_sync.is_loaded() {
+                    ui.label("Loading world...");
+                } else if playables.is_empty() {
+                    ui.label("No existing characters found");
+                } else {
+                    egui::ScrollArea::vertical()
+                        .max_height(300.0)
+                        .show(ui, |ui| {
+                            for (heading, characters) in
+                                [("Your Characters:", &own), ("Other Characters:", &others)]
+                            {
+                                if characters.is_empty() {
+                                    continue;
+                                }
+                                ui.label(RichText::new(heading).size(16.0));
+                                ui.add_space(10.0);
+                                for (eid, label) in characters {
+                                    if ui.button(RichText::new(label).size(18.0)).clicked() {
+                                        picked = Some(*eid);
+                                    }
+                                }
+                                ui.add_space(10.0);
+                            }
+                        });
+                }
+                if let Some(eid) = picked
+                    && let Some(tx) = &self.client_to_server_tx
+                {
+                    if let Err(e) = tx.send(ClientMessage::Action(GameAction::SpawnAs(eid))) {
+                        log::warn!("Failed to send game event: {e}");
+                    } else {
+                        // Playing starts once the server confirms.
+                        self.spawn_status = Some("Joining...".to_owned());
+                    }
+                }
+
+                if self.world_sync.is_loaded() && !self.roster.is_full() {
+                    ui.add_space(20.0);
+                    self.import_buttons(ui);
+                }
+
+                ui.add_space(20.0);
+
+                // Back button
+                if ui.button(RichText::new("Back").size(16.0)).clicked() {
+                    if self.single_player {
+                        self.screen = AppScreen::WorldSelection;
+                    } else {
+                        self.screen = AppScreen::MainMenu;
+                    }
+                }
+            });
+        });
+    }
+
+    /// Ask for the password or invite code the server wants, and join with
+    /// it once entered.
+    fn password_prompt(&mut self, ui: &mut egui::Ui) {
+        let mut secret = None;
+        if let Some(input) = &mut self.password_input {
+            ui.horizontal(|ui| {
+                let field = ui.add(
+                    egui::TextEdit::singleline(input)
+                        .password(true)
+                        .hint_text("Password or invite code"),
+                );
+                let entered = field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if (entered || ui.button("Join").clicked()) && !input.trim().is_empty() {
+                    secret = Some(input.trim().to_owned());
+                }
+            });
+            ui.add_space(10.0);
+        }
+        if let Some(secret) = secret {
+            self.password_input = None;
+            self.join_secret = Some(secret);
+            self.spawn_status = Some("Joining...".to_owned());
+            self.join_world(self.game.world_id, self.game.world_name.clone());
+        }
+    }
+
+    /// Buttons to bring in characters exported from other worlds.
+    fn import_buttons(&mut self, ui: &mut egui::Ui) {
+        let files = get_character_files();
+        if files.is_empty() {
+            return;
+        }
+        ui.label(RichText::new("Import From Another World:").size(16.0));
+        ui.add_space(10.0);
+        for path in files {
+            let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            if !ui.button(RichText::new(name).size(18.0)).clicked() {
+                continue;
+            }
+            match fs::read_to_string(&path) {
+                Ok(text) => {
+                    if let Some(tx) = &self.client_to_server_tx
+                        && tx.send(ClientMessage::ImportCharacter(text)).is_ok()
+                    {
+                        // Playing starts once the server confirms.
+                        self.spawn_status = Some("Importing...".to_owned());
+                    }
+                }
+                Err(e) => {
+                    self.spawn_status = Some(format!("Could not read {}: {e}", path.display()));
+                }
+            }
+        }
+    }
+
+    fn show_world_creation_menu(&mut self, ctx: &egui::Context) {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.add_space(50.0);
+
+                ui.heading("World Creation");
+                ui.add_space(20.0);
+
+                // Text input for world name
+                ui.label("Enter world name:");
+                ui.add_space(5.0);
+
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.menu_input_string);
+                    if ui.button("Suggest").clicked() {
+                        self.menu_input_string = namegen::world_name(self.new_world_seed);
+                    }
+                });
+
+                ui.add_space(20.0);
+                self.world_generator_settings(ui);
+                ui.add_space(10.0);
+
+                if ui
+                    .button(RichText::new("Generate World").size(20.0))
+                    .clicked()
+                {
+                    let world_name = if self.menu_input_string.trim().is_empty() {
+                        namegen::world_name(self.new_world_seed)
+                    } else {
+                        self.take_world_name()
+                    };
+                    let new_world =
+                        worldgen::generate(world_name, self.new_world_seed, &self.new_world_config);
+                    match game::save_to_file(&new_world) {
+                        Ok(()) => {
+                            self.world_preview = None;
+                            self.screen = AppScreen::WorldSelection;
+                        }
+                        Err(e) => {
+                            log::warn!("Failed to create world: {e}");
+                        }
+                    }
+                }
+
+                ui.add_space(10.0);
+
+                // Create Test World button
+                if ui
+                    .button(RichText::new("Create Test World").size(20.0))
+                    .clicked()
+                {
+                    let world_name = self.take_world_name();
+                    let new_world = GameState::create_test_world(world_name);
+                    match game::save_to_file(&new_world) {
+                        Ok(()) => {
+                            self.screen = AppScreen::WorldSelection;
+                        }
+                        Err(e) => {
+                            eprintln!("Failed to create world: {e}");
+                        }
+                    }
+                }
+
+                ui.add_space(20.0);
+
+                // Back button
+                if ui.button(RichText::new("Back").size(16.0)).clicked() {
+                    self.screen = AppScreen::WorldSelection;
+                }
+            });
+        });
+    }
+
+    /// The world name entered, clearing the input.
+    fn take_world_name(&mut self) -> String {
+        let name = std::mem::take(&mut self.menu_input_string);
+        if name.trim().is_empty() {
+            "world_lol".to_string()
+        } else {
+            name.trim().to_string()
+        }
+    }
+
+    /// Seed, size and density inputs for a generated world, beside a preview
+    /// of its terrain that follows them as they change.
+    fn world_generator_settings(&mut self, ui: &mut egui::Ui) {
+        let config = &mut self.new_world_config;
+        ui.horizontal(|ui| {
+            egui::Grid::new("world_generator").show(ui, |ui| {
+                ui.label("Seed");
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut self.new_world_seed));
+                    if ui.button("Random").clicked() {
+                        self.new_world_seed = uuid::Uuid::new_v4().as_u64_pair().0;
+                    }
+                });
+                ui.end_row();
+                ui.label("Width");
+                ui.add(egui::Slider::new(&mut config.width, 16..=MAX_WORLD_SIDE).logarithmic(true));
+                ui.end_row();
+                ui.label("Height");
+                ui.add(
+                    egui::Slider::new(&mut config.height, 16..=MAX_WORLD_SIDE).logarithmic(true),
+                );
+                ui.end_row();
+                ui.label("Trees (‰)");
+                ui.add(egui::Slider::new(&mut config.tree_density, 0..=1000));
+                ui.end_row();
+                ui.label("Groves (‰)");
+                ui.add(egui::Slider::new(&mut config.grove_chance, 0..=1000));
+                ui.end_row();
+                ui.label("Grove trees (‰)");
+                ui.add(egui::Slider::new(&mut config.grove_density, 0..=1000));
+                ui.end_row();
+                ui.label("Rivers (‰)");
+                ui.add(egui::Slider::new(&mut config.river_chance, 0..=1000));
+                ui.end_row();
+                ui.label("Lakes (‰)");
+                ui.add(egui::Slider::new(&mut config.lake_chance, 0..=1000));
+                ui.end_row();
+                ui.label("Infinite");
+                ui.checkbox(&mut config.infinite, "")
+                    .on_hover_text("Grow the world as players explore it");
+                ui.end_row();
+            });
+
+            let seed = self.new_world_seed;
+            let stale = self
+                .world_preview
+                .as_ref()
+                .is_none_or(|(s, c, _)| *s != seed || *c != *config);
+            if stale {
+                let preview = worldgen::preview(seed, config, WORLD_PREVIEW_CELLS);
+                let texture = ui.ctx().load_texture(
+                    "world_preview",
+                    ui::preview_image(&preview),
+                    egui::TextureOptions::NEAREST,
+                );
+                self.world_preview = Some((seed, config.clone(), texture));
+            }
+            if let Some((_, _, texture)) = &self.world_preview {
+                let size = texture.size_vec2();
+                let scale = WORLD_PREVIEW_SIZE / size.x.max(size.y);
+                ui.image((texture.id(), size * scale));
+            }
+        });
+    }
+
+    fn show_character_creation_menu(&mut self, ctx: &egui::Context) {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.add_space(50.0);
+
+                ui.heading("Character Creation");
+                ui.add_space(20.0);
+
+                // Text input for character name
+                ui.label("Enter Character name:");
+                ui.add_space(5.0);
+
+                ui.text_edit_singleline(&mut self.menu_input_string);
+
+                ui.add_space(20.0);
+
+                // Create Character button
+                if ui
+                    .button(RichText::new("Create New Character").size(20.0))
+                    .clicked()
+                {
+                    let char_name = if self.menu_input_string.trim().is_empty() {
+                        "John".to_string()
+                    } else {
+                        self.menu_input_string.trim().to_string()
+                    };
+                    self.menu_input_string.clear();
+                    if let Some(tx) = &self.client_to_server_tx {
+                        if let Err(e) =
+                            tx.send(ClientMessage::Action(GameAction::SpawnPlayer(char_name)))
+                        {
+                            eprintln!("Failed to send game event: {e}");
+                        } else {
+                            self.screen = AppScreen::CharacterSelection;
+                        }
+                    }
+                }
+
+                ui.add_space(20.0);
+
+                // Back button
+                if ui.button(RichText::new("Back").size(16.0)).clicked() {
+                    self.screen = AppScreen::CharacterSelection;
+                }
+            });
+        });
+    }
 
     // -----------------------------------------------------------------------
     // Input
     // -----------------------------------------------------------------------
 
-    pub fn input(&mut self, ctx: &egui::Context) {
-        let mut messages_to_send = Vec::new();
+    /// What the player is doing, for the keys to follow.
+    fn input_context(&self) -> Context {
+        if self.chat_open {
+            Context::Editor
+        } else if self.console_open {
+            Context::Console
+        } else if self.paused {
+            Context::Menus
+        } else if self.ping_menu.is_some() {
+            Context::Targeting
+        } else {
+            Context::Gameplay
+        }
+    }
+
+    /// Close what is open in `context`.
+    fn close(&mut self, context: Context) {
+        match context {
+            Context::Targeting => self.ping_menu = None,
+            Context::Console => self.console_open = false,
+            Context::Editor => {
+                self.chat_open = false;
+                self.chat_input.clear();
+            }
+            Context::Menus => self.paused = false,
+            Context::Gameplay => {}
+        }
+    }
+
+    /// Act on the `commands` the keys of the gameplay context gave, and on
+    /// zooming.
+    pub fn input(&mut self, ctx: &egui::Context, commands: &[Command]) {
+        let mut messages_to_send = Vec::new();
+        let mut toggle_recording = false;
+        let mut play_macro = None;
+        let mut toggle_explore = false;
+        let mut wait = None;
+        let (now, zoom, shift) = ctx.input(|i| (i.time, i.zoom_delta(), i.modifiers.shift));
+        self.font_size = (self.font_size * zoom).clamp(MIN_ZOOM, MAX_ZOOM);
+
+        let entities = &self.game.entities();
+        messages_to_send.extend(
+            commands
+                .iter()
+                .filter_map(|command| command.control())
+                .filter_map(|control| control.action(entities, self.player_id)),
+        );
+        for command in commands {
+            match command {
+                Command::CharacterSheet => self.character_sheet_open = !self.character_sheet_open,
+                Command::Map => self.map_open = !self.map_open,
+                Command::Trade => {
+                    self.trade_status = None;
+                    self.vendor = match self.vendor {
+                        Some(_) => None,
+                        None => self.nearest_in_range(|e| e.shop.is_some()),
+                    };
+                }
+                Command::SpatialOverlay if cfg!(debug_assertions) => {
+                    self.debug_overlays.spatial_index = !self.debug_overlays.spatial_index;
+                }
+                Command::FovOverlay if cfg!(debug_assertions) => {
+                    self.debug_overlays.fov = !self.debug_overlays.fov;
+                }
+                Command::PathOverlay if cfg!(debug_assertions) => {
+                    self.debug_overlays.paths = !self.debug_overlays.paths;
+                    self.send_debug_request();
+                }
+                Command::ChunkOverlay if cfg!(debug_assertions) => {
+                    self.debug_overlays.chunks = !self.debug_overlays.chunks;
+                }
+                // Timings matter most in release builds, so this one is always on.
+                Command::ProfilerOverlay => {
+                    self.debug_overlays.profiler = !self.debug_overlays.profiler;
+                }
+                Command::RecordMacro => toggle_recording = true,
+                Command::PlayMacro => play_macro = Some(shift),
+                Command::Explore => toggle_explore = true,
+                Command::WaitLong => wait = Some(Wait::Turns(WAIT_LONG_TURNS)),
+                Command::Rest => wait = self.confirm_rest(now).then_some(Wait::UntilInterrupted),
+                _ => {}
+            }
+        }
+
+        if self.rest_armed.is_some_and(|until| until <= now) {
+            self.rest_armed = None;
+        }
+        // Any key stops a wait in progress.
+        if self.waiting && !commands.is_empty() {
+            self.waiting = false;
+            messages_to_send.push(GameAction::Cancel);
+        }
+        if let Some(wait) = wait {
+            self.waiting = true;
+            messages_to_send.push(GameAction::Wait(wait));
+        }
+
+        // Manual input takes over from a replaying macro or auto-explore.
+        if !messages_to_send.is_empty() {
+            self.macro_player = None;
+            self.explorer = None;
+        }
+        for action in &messages_to_send {
+            self.recorder.record(action);
+        }
+        self.drive_macros(toggle_recording, play_macro, now, &mut messages_to_send);
+        if toggle_explore {
+            self.explorer = match self.explorer.take() {
+                Some(_) => None,
+                None => Some(Explorer::start(&self.game, &self.sight)),
+            };
+        }
+        if let Some(explorer) = &mut self.explorer {
+            match explorer.poll(&self.game, &self.sight, &self.explored, now) {
+                Ok(step) => messages_to_send.extend(step),
+                Err(stop) => {
+                    self.explorer = None;
+                    self.explore_stopped(stop);
+                }
+            }
+        }
+
+        // Send all the collected messages
+        if let Some(tx) = &self.client_to_server_tx {
+            for event in messages_to_send {
+                crash::action(self.game.tick, &event);
+                if let Err(e) = tx.send(ClientMessage::Action(event)) {
+                    eprintln!("Failed to send game event: {e}");
+                }
+            }
+        }
+    }
+
+    /// Start or stop recording a macro and start replaying the last one, and
+    /// add what a replaying macro does now to `messages`.
+    fn drive_macros(
+        &mut self,
+        toggle_recording: bool,
+        play_macro: Option<bool>,
+        now: f64,
+        messages: &mut Vec<GameAction>,
+    ) {
+        if toggle_recording {
+            if self.recorder.is_recording() {
+                let name = format!("Macro {}", self.config.macros.len() + 1);
+                if let Some(recorded) = self.recorder.finish(name) {
+                    self.config.add_macro(recorded);
+                }
+            } else {
+                self.macro_player = None;
+                self.recorder.start();
+            }
+        }
+        if let Some(looping) = play_macro
+            && !self.recorder.is_recording()
+        {
+            self.macro_player = self
+                .config
+                .macros
+                .last()
+                .map(|recorded| MacroPlayer::new(recorded, looping, now));
+        }
+        if let Some(player) = &mut self.macro_player {
+            messages.extend(player.poll(now));
+            if player.is_finished() {
+                self.macro_player = None;
+            }
+        }
+    }
+
+    /// Returns `true` while something on screen moves without input or
+    /// messages, so frames must keep coming.
+    fn animating(&self) -> bool {
+        self.connecting
+            || self.screen == AppScreen::Replay
+            || !self.flashes.is_empty()
+            || self.alerts.is_flashing()
+            || self.macro_player.is_some()
+            || self.explorer.is_some()
+            || self.rest_armed.is_some()
+            || self.input_replay.is_playing()
+            || self.world_sync.is_loading()
+    }
+
+    /// Name of `eid` in chat notes: its own, or what it is.
+    fn entity_name(&self, eid: EntityID) -> String {
+        self.game.entities().get(&eid).map_or_else(
+            || format!("#{}", eid.0),
+            |e| {
+                e.name
+                    .clone()
+                    .unwrap_or_else(|| format!("a {}", e.entity_type.name()))
+            },
+        )
+    }
+
+    /// Returns `true` if [`Command::Rest`] was pressed a second time soon
+    /// enough to confirm it; the first press only asks.
+    fn confirm_rest(&mut self, now: f64) -> bool {
+        if self.rest_armed.take().is_some_and(|until| until > now) {
+            return true;
+        }
+        self.rest_armed = Some(now + REST_CONFIRM_SECONDS);
+        false
+    }
+
+    /// Tell the player why their wait ended.
+    fn wait_ended(&mut self, end: WaitEnd) {
+        let text = match end {
+            WaitEnd::Done => "You finish waiting".to_owned(),
+            WaitEnd::Noticed(eid) => {
+                format!("You stop waiting: {} came into view", self.entity_name(eid))
+            }
+            WaitEnd::Hurt => "You stop waiting: you are hurt".to_owned(),
+            WaitEnd::NoTurns => "The world runs in real time, with no turns to wait".to_owned(),
+        };
+        self.chat.note(self.game.tick, Category::Exploration, text);
+    }
+
+    /// Tell the player why auto-explore stopped.
+    fn explore_stopped(&mut self, stop: ExploreStop) {
+        let text = match stop {
+            ExploreStop::Done => "Nothing left to explore within reach".to_owned(),
+            ExploreStop::Noticed(eid) => {
+                format!(
+                    "You stop exploring: {} came into view",
+                    self.entity_name(eid)
+                )
+            }
+            ExploreStop::Danger(eid) => {
+                format!(
+                    "You stop exploring: {} is hostile and nearby",
+                    self.entity_name(eid)
+                )
+            }
+            ExploreStop::Hurt => "You stop exploring: you are hurt".to_owned(),
+            ExploreStop::Stuck => "You stop exploring: the way is blocked".to_owned(),
+        };
+        self.chat.note(self.game.tick, Category::Exploration, text);
+    }
+
+    /// Alert the player, as set for each type, to the hostiles that came
+    /// into view by time `now`.
+    fn raise_alerts(&mut self, now: f64) {
+        let alerts = self.alerts.raise(
+            &self.game,
+            self.player_id,
+            &self.sight.noticed.entered,
+            &self.config.alerts,
+            now,
+        );
+        if alerts.iter().any(|alert| alert.style.sound) {
+            self.alerts.play_tone();
+        }
+        for alert in alerts.into_iter().filter(|alert| alert.style.log) {
+            let text = format!("Danger: {} comes into view", self.entity_name(alert.entity));
+            self.chat.note(self.game.tick, Category::Combat, text);
+        }
+    }
+
+    /// Status line shown while a macro is being recorded or replayed, while
+    /// auto-exploring or waiting, or while a rest waits to be confirmed.
+    fn macro_status(&self) -> Option<String> {
+        if self.rest_armed.is_some() {
+            Some(format!(
+                "Rest until something comes into view or you are hurt? {} again to confirm",
+                self.config
+                    .keymap
+                    .describe(Context::Gameplay, Command::Rest)
+            ))
+        } else if self.waiting {
+            Some("… Waiting — any key to stop".to_owned())
+        } else if self.recorder.is_recording() {
+            Some(format!(
+                "● Recording macro ({}/{}) — {} to stop",
+                self.recorder.len(),
+                crate::config::MAX_MACRO_LEN,
+                self.config
+                    .keymap
+                    .describe(Context::Gameplay, Command::RecordMacro)
+            ))
+        } else if self.explorer.is_some() {
+            Some(format!(
+                "» Exploring — {} or move to stop",
+                self.config
+                    .keymap
+                    .describe(Context::Gameplay, Command::Explore)
+            ))
+        } else {
+            self.macro_player
+                .as_ref()
+                .map(|_| "▶ Playing macro — move to stop".to_owned())
+        }
+    }
+
+    fn rogue_screen(&mut self, ctx: &egui::Context) {
+        egui::TopBottomPanel::top("lol").show(ctx, |ui| {
+            // Calculate button size on first frame if not already calculated
+            if self.button_size.is_none() {
+                let chinese_char = "中";
+                let font_id = egui::FontId::new(self.font_size, egui::FontFamily::Proportional);
+                let char_galley = ui.fonts_mut(|f| {
+                    f.layout_no_wrap(
+                        chinese_char.to_string(),
+                        font_id.clone(),
+                        egui::Color32::WHITE,
+                    )
+                });
+
+                let size = char_galley.size();
+                self.button_size = Some(size.x.max(size.y));
+            }
+
+            let button_size = self.button_size.unwrap();
+
+            // Calculate available space
+            let content = ui.ctx().content_rect();
+            let cols = ((content.width() / button_size) as usize).max(1);
+            let rows = ((content.height() / button_size) as usize).max(1);
+
+            // Camera centering
+            let center = self
+                .game
+                .entities()
+                .get(&self.player_id)
+                .map_or(Point { x: 0, y: 0 }, |e| e.position);
+
+            let cam_x = center.x - (cols as i32 / 2);
+            let cam_y = center.y - (rows as i32 / 2);
+
+            let awareness = self.update_view();
+            if let Some(entity) = self.game.entities().get(&self.player_id) {
+                self.trail.follow(entity.position);
+            }
+            self.raise_alerts(ui.input(|i| i.time));
+
+            let time = ui.input(|i| i.time);
+            self.flashes.retain(|(_, _, until)| *until > time);
+            self.fill_frame(&awareness, cols, rows, cam_x, cam_y, time);
+
+            let size = egui::vec2(cols as f32, rows as f32) * button_size;
+            let mut tile_action = None;
+            ui.centered_and_justified(|ui| {
+                let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click());
+                self.frame_buffer.paint(ui, rect, button_size);
+                let origin = rect.min - egui::vec2(cam_x as f32, cam_y as f32) * button_size;
+                let painter = ui.painter_at(rect);
+                ui::trail(&painter, origin, button_size, self.trail.tiles());
+                self.draw_progress(&painter, origin, button_size, &awareness);
+                self.draw_name_plates(&painter, origin, button_size, &awareness);
+                self.draw_pings(&painter, origin, button_size, time);
+                self.draw_markers(&painter, rect, origin, button_size);
+                self.draw_debug(&painter, origin, button_size, cols, rows, (cam_x, cam_y));
+                if ui.input(|i| i.modifiers.command)
+                    && let Some(pos) = response.hover_pos()
+                    && let Some((col, row)) = self.frame_buffer.tile_at(rect, button_size, pos)
+                {
+                    let target = Point {
+                        x: col as i32 + cam_x,
+                        y: row as i32 + cam_y,
+                    };
+                    self.draw_route(&painter, origin, button_size, target);
+                }
+                let clicked = response.interact_pointer_pos().and_then(|pos| {
+                    let (col, row) = self.frame_buffer.tile_at(rect, button_size, pos)?;
+                    let x = col as i32 + cam_x;
+                    Some((
+                        pos,
+                        Point {
+                            x,
+                            y: row as i32 + cam_y,
+                        },
+                    ))
+                });
+                if let Some((pos, point)) = clicked {
+                    if response.secondary_clicked() {
+                        tile_action = Some((point, ui.input(|i| i.modifiers)));
+                    } else if response.middle_clicked() {
+                        self.ping_menu = Some((point, pos));
+                    }
+                }
+            });
+            if let Some((target, modifiers)) = tile_action {
+                self.tile_clicked(target, modifiers);
+            }
+        });
+
+        self.show_ping_menu(ctx);
+        self.show_quick_menu(ctx);
+        if let Some(strength) = self.alerts.flash(ctx.input(|i| i.time)) {
+            let layer = egui::LayerId::new(egui::Order::Foreground, egui::Id::new("alert_flash"));
+            ui::edge_flash(&ctx.layer_painter(layer), ctx.content_rect(), strength);
+        }
+
+        self.profiler.finish_tick(ctx.cumulative_frame_nr());
+        if self.debug_overlays.any() {
+            self.debug_window(ctx);
+        }
+    }
+
+    /// Lay out the `cols` × `rows` tiles seen from `(cam_x, cam_y)` in the
+    /// frame buffer, with what is aware of them in `awareness`.
+    fn fill_frame(
+        &mut self,
+        awareness: &[EntityID],
+        cols: usize,
+        rows: usize,
+        cam_x: i32,
+        cam_y: i32,
+        time: f64,
+    ) {
+        // Build spatial index once per frame for O(1) lookups
+        let index = ui::build_visible_index(self.game.entities(), awareness);
+        let outlines: FxHashMap<Point, &EntityType> = self
+            .silhouettes
+            .values()
+            .map(|s| (s.position, &s.entity_type))
+            .collect();
+        let occupancy = self
+            .debug_overlays
+            .spatial_index
+            .then(|| ui::bucket_occupancy(self.game.entities()));
+        let fov_origin = self
+            .debug_overlays
+            .fov
+            .then(|| {
+                self.game
+                    .entities()
+                    .get(&self.player_id)
+                    .map(|e| e.position)
+            })
+            .flatten();
+
+        self.frame_buffer.begin(cols, rows, self.font_size);
+        for row in 0..rows {
+            for col in 0..cols {
+                let point = Point {
+                    x: col as i32 + cam_x,
+                    y: row as i32 + cam_y,
+                };
+
+                let mut glyph = ui::glyph_at(&index, &self.game.terrain, &point);
+                let outline = outlines.get(&point).filter(|_| !index.contains_key(&point));
+                if let Some(entity) = index.get(&point).filter(|_| !self.low_power) {
+                    glyph = ui::animate(glyph, &entity.entity_type, &point, time);
+                } else if let Some(entity_type) = outline {
+                    glyph = ui::silhouette(entity_type);
+                }
+                if let Some((_, color, _)) = self.flashes.iter().rev().find(|(p, ..)| *p == point) {
+                    glyph.bg_color = *color;
+                }
+                // Silhouettes are out of sight, and faded already.
+                if !self.sight.fov.mask.contains(point) && outline.is_none() {
+                    ui::dim(&mut glyph);
+                }
+                if let Some(occupancy) = &occupancy {
+                    let count = occupancy.get(&point).copied().unwrap_or(0);
+                    glyph.bg_color = ui::occupancy_color(count);
+                }
+                if let Some(origin) = fov_origin {
+                    let in_sight = self.sight.fov.mask.contains(point);
+                    let in_margin = math::within_square(origin, point, fov::AWARENESS_MARGIN);
+                    if let Some(color) = ui::fov_color(in_sight, in_margin) {
+                        glyph.bg_color = color;
+                    }
+                }
+                self.frame_buffer.set(col, row, glyph.into());
+            }
+        }
+    }
+
+    /// Progress bars over the entities in `awareness` channelling an
+    /// intent, as last reported; `origin` is where tile `(0, 0)` would be
+    /// drawn.
+    fn draw_progress(
+        &self,
+        painter: &egui::Painter,
+        origin: egui::Pos2,
+        cell: f32,
+        awareness: &[EntityID],
+    ) {
+        for eid in awareness {
+            let (Some(entity), Some(fraction)) =
+                (self.game.entities().get(eid), self.action_progress.get(eid))
+            else {
+                continue;
+            };
+            let offset = egui::vec2(entity.position.x as f32, entity.position.y as f32) * cell;
+            ui::progress_bar(painter, origin + offset, cell, *fraction);
+        }
+    }
+
+    /// The routes and chunk boundaries of the debug overlays that are on,
+    /// over the `cols` × `rows` tiles seen from `cam`.
+    fn draw_debug(
+        &self,
+        painter: &egui::Painter,
+        origin: egui::Pos2,
+        cell: f32,
+        cols: usize,
+        rows: usize,
+        (cam_x, cam_y): (i32, i32),
+    ) {
+        if self.debug_overlays.paths {
+            let paths = self.debug_data.paths.iter().filter_map(|(eid, path)| {
+                let from = self.game.entities().get(eid)?.position;
+                Some((from, path.as_slice()))
+            });
+            ui::debug_paths(painter, origin, cell, paths);
+        }
+        if self.debug_overlays.chunks {
+            let min = Point { x: cam_x, y: cam_y };
+            let max = Point {
+                x: cam_x + cols as i32 - 1,
+                y: cam_y + rows as i32 - 1,
+            };
+            ui::chunk_boundaries(painter, origin, cell, min, max);
+        }
+    }
+
+    /// The way a walk queued with `Ctrl` + right click would take to
+    /// `target`, or a cross if there is none.
+    fn draw_route(&self, painter: &egui::Painter, origin: egui::Pos2, cell: f32, target: Point) {
+        let Some(from) = self
+            .game
+            .entities()
+            .get(&self.player_id)
+            .map(|e| e.position)
+        else {
+            return;
+        };
+        let route = game::pathfind(&self.game, from, target);
+        ui::route(painter, origin, cell, route.as_deref(), target);
+    }
+
+    /// Names of the other players in `awareness` and in sight, above their
+    /// characters in their colors.
+    fn draw_name_plates(
+        &self,
+        painter: &egui::Painter,
+        origin: egui::Pos2,
+        cell: f32,
+        awareness: &[EntityID],
+    ) {
+        for eid in awareness.iter().filter(|eid| **eid != self.player_id) {
+            let Some(entity) = self.game.entities().get(eid) else {
+                continue;
+            };
+            let Some(name) = &entity.name else {
+                continue;
+            };
+            if entity.entity_type != EntityType::Player
+                || !self.sight.fov.mask.contains(entity.position)
+            {
+                continue;
+            }
+            let offset = egui::vec2(entity.position.x as f32, entity.position.y as f32) * cell;
+            ui::name_plate(
+                painter,
+                origin + offset,
+                cell,
+                name,
+                ui::entity_color(entity),
+            );
+        }
+    }
+
+    /// Act on a right click on `target`: throw a bomb with `Shift`, queue
+    /// a walk with `Ctrl`, put a marker there with `Alt`, or send the group
+    /// there.
+    fn tile_clicked(&mut self, target: Point, modifiers: egui::Modifiers) {
+        if modifiers.alt {
+            self.place_marker(target);
+            return;
+        }
+        // Every other order takes a turn, which ends a wait.
+        self.waiting = false;
+        if modifiers.shift {
+            self.send_action(GameAction::Throw {
+                item: game::explosion::BOMB.to_owned(),
+                target,
+            });
+        } else if modifiers.command {
+            self.send_action(GameAction::Queue(Intent::WalkTo(target)));
+        } else {
+            self.move_group(target);
+        }
+    }
+
+    /// Put a marker on `pos` if it was explored, and open the map to name
+    /// it.
+    fn place_marker(&mut self, pos: Point) {
+        if self.explored.get(pos).is_none() || self.markers.add(pos).is_none() {
+            return;
+        }
+        self.map_open = true;
+        self.save_markers();
+    }
+
+    fn save_markers(&mut self) {
+        if let Err(e) = self.markers.save() {
+            log::warn!("Failed to save map markers: {e}");
+        }
+    }
+
+    /// The player's markers: on their tiles when in view, otherwise as
+    /// arrows on the edge of `rect` pointing their way.
+    fn draw_markers(
+        &self,
+        painter: &egui::Painter,
+        rect: egui::Rect,
+        origin: egui::Pos2,
+        cell: f32,
+    ) {
+        for marker in self.markers.iter() {
+            let min = origin + egui::vec2(marker.pos.x as f32, marker.pos.y as f32) * cell;
+            let tile = egui::Rect::from_min_size(min, egui::vec2(cell, cell));
+            if rect.contains_rect(tile) {
+                ui::marker_flag(painter, min, cell, marker);
+            } else {
+                let color = ui::marker_color(marker.color);
+                ui::edge_arrow(painter, rect, tile.center(), color, &marker.name);
+            }
+        }
+    }
+
+    /// The explored map around the player with their markers, and the
+    /// markers to rename, recolor or remove; toggled with `M`.
+    fn map_window(&mut self, ctx: &egui::Context) {
+        let center = self
+            .game
+            .entities()
+            .get(&self.player_id)
+            .map_or(SPAWN_POINT, |e| e.position);
+        let mut open = self.map_open;
+        let mut changed = false;
+        let mut removed = None;
+        egui::Window::new("Map").open(&mut open).show(ctx, |ui| {
+            let side = egui::vec2(MINIMAP_SIZE, MINIMAP_SIZE);
+            let (rect, _) = ui.allocate_exact_size(side, egui::Sense::hover());
+            let markers = self.markers.iter();
+            let players = self
+                .game
+                .entities()
+                .iter()
+                .filter(|(eid, e)| {
+                    **eid != self.player_id
+                        && e.entity_type == EntityType::Player
+                        && self.sight.fov.mask.contains(e.position)
+                })
+                .map(|(_, e)| (e.position, ui::entity_color(e)));
+            ui::minimap(
+                &ui.painter_at(rect),
+                &self.explored,
+                &self.game.terrain,
+                center,
+                MINIMAP_RADIUS,
+                markers,
+                players,
+            );
+            ui.separator();
+            if self.markers.is_empty() {
+                ui.label("Alt + right click an explored tile to mark it.");
+            }
+            for index in 0..self.markers.len() {
+                let Some(marker) = self.markers.get_mut(index) else {
+                    continue;
+                };
+                ui.horizontal(|ui| {
+                    let color = ui::marker_color(marker.color);
+                    let selected = RichText::new(marker.color.name()).color(color);
+                    egui::ComboBox::from_id_salt(("marker color", index))
+                        .selected_text(selected)
+                        .show_ui(ui, |ui| {
+                            for option in MarkerColor::ALL {
+                                let text =
+                                    RichText::new(option.name()).color(ui::marker_color(option));
+                                changed |= ui
+                                    .selectable_value(&mut marker.color, option, text)
+                                    .changed();
+                            }
+                        });
+                    changed |= ui
+                        .add(
+                            egui::TextEdit::singleline(&mut marker.name)
+                                .char_limit(MAX_MARKER_NAME_LEN)
+                                .desired_width(140.0),
+                        )
+                        .lost_focus();
+                    ui.label(format!("{}, {}", marker.pos.x, marker.pos.y));
+                    if ui.small_button("🗑").on_hover_text("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+            }
+        });
+        self.map_open = open;
+        if let Some(index) = removed {
+            self.markers.remove(index);
+            changed = true;
+        }
+        if changed {
+            self.save_markers();
+        }
+    }
+
+    /// Coordinates, facing and world time over a compass strip showing
+    /// which way the player's markers and team pings lie; enabled from the
+    /// pause menu.
+    fn compass_hud(&mut self, ctx: &egui::Context) {
+        let Some(pos) = self
+            .game
+            .entities()
+            .get(&self.player_id)
+            .map(|e| e.position)
+        else {
+            return;
+        };
+        self.update_facing(pos);
+        let facing = ui::heading(self.facing);
+        let marks: Vec<(f32, egui::Color32)> = self
+            .markers
+            .iter()
+            .map(|m| (ui::bearing(pos, m.pos), ui::marker_color(m.color)))
+            .chain(
+                self.pings
+                    .iter()
+                    .map(|p| (ui::bearing(pos, p.pos), ui::ping_color(p.kind))),
+            )
+            .collect();
+        let status = format!(
+            "{}, {} · facing {} · {}",
+            pos.x,
+            pos.y,
+            ui::compass_point(facing),
+            ui::world_time(self.game.tick)
+        );
+        egui::Area::new(egui::Id::new("compass"))
+            .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 40.0))
+            .interactable(false)
+            .show(ctx, |ui| {
+                ui.vertical_centered(|ui| {
+                    ui.label(status);
+                    let size = egui::vec2(COMPASS_WIDTH, 28.0);
+                    let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+                    ui::compass_strip(&ui.painter_at(rect), rect, facing, &marks);
+                });
+            });
+    }
+
+    /// Turn the player the way they moved since the last call.
+    fn update_facing(&mut self, pos: Point) {
+        let Some(last) = self.last_position.replace(pos) else {
+            return;
+        };
+        let (dx, dy) = (pos.x - last.x, pos.y - last.y);
+        if dx.abs() > dy.abs() {
+            self.facing = if dx > 0 {
+                Direction::Right
+            } else {
+                Direction::Left
+            };
+        } else if dy != 0 {
+            self.facing = if dy > 0 {
+                Direction::Down
+            } else {
+                Direction::Up
+            };
+        }
+    }
+
+    /// Pings from the player's team, pulsing on their tiles with how many
+    /// tiles away they are.
+    fn draw_pings(&self, painter: &egui::Painter, origin: egui::Pos2, cell: f32, time: f64) {
+        let player = self
+            .game
+            .entities()
+            .get(&self.player_id)
+            .map(|e| e.position);
+        for ping in &self.pings {
+            let offset = egui::vec2(ping.pos.x as f32, ping.pos.y as f32) * cell;
+            let distance = player.map(|p| math::chebyshev(p, ping.pos));
+            ui::ping_marker(painter, origin + offset, cell, ping.kind, distance, time);
+        }
+    }
+
+    /// The radial menu of ping kinds opened by middle-clicking a tile:
+    /// click a kind to ping the tile with it, anywhere else or `Esc` to
+    /// close it.
+    fn show_ping_menu(&mut self, ctx: &egui::Context) {
+        let Some((pos, center)) = self.ping_menu else {
+            return;
+        };
+        let layer = egui::LayerId::new(egui::Order::Foreground, egui::Id::new("ping_menu"));
+        let (pointer, clicked) =
+            ctx.input(|i| (i.pointer.hover_pos(), i.pointer.primary_clicked()));
+        let hovered = pointer.and_then(|pointer| {
+            ui::radial_choice(center, pointer, PingKind::ALL.len(), PING_MENU_RADIUS / 2.0)
+        });
+        let options: Vec<(&str, egui::Color32)> = PingKind::ALL
+            .iter()
+            .map(|kind| (kind.name(), ui::ping_color(*kind)))
+            .collect();
+        ui::radial_menu(
+            &ctx.layer_painter(layer),
+            center,
+            PING_MENU_RADIUS,
+            &options,
+            hovered,
+        );
+        if clicked {
+            if let Some(kind) = hovered.and_then(|index| PingKind::ALL.get(index)) {
+                self.send_action(GameAction::Ping { pos, kind: *kind });
+            }
+            self.ping_menu = None;
+        }
+    }
+
+    /// The gamepad's quick menu, in the middle of the screen, while its
+    /// button is held.
+    fn show_quick_menu(&self, ctx: &egui::Context) {
+        let Some(aim) = self.gamepads.quick_aim() else {
+            return;
+        };
+        let layer = egui::LayerId::new(egui::Order::Foreground, egui::Id::new("quick_menu"));
+        let color = ctx.style().visuals.strong_text_color();
+        let options: Vec<(&str, egui::Color32)> = gamepad::QUICK_COMMANDS
+            .iter()
+            .map(|command| (command.name(), color))
+            .collect();
+        ui::radial_menu(
+            &ctx.layer_painter(layer),
+            ctx.content_rect().center(),
+            QUICK_MENU_RADIUS,
+            &options,
+            Gamepads::quick_choice(aim),
+        );
+    }
+
+    /// Recompute the local player's field of view and explored map, and
+    /// return the entities it is aware of.
+    fn update_view(&mut self) -> Vec<EntityID> {
+        if let Some(previous) = self.sight.follow(self.player_id) {
+            // Each character remembers what it has seen.
+            let explored = self
+                .explored_by_character
+                .remove(&self.player_id)
+                .unwrap_or_default();
+            let left = std::mem::replace(&mut self.explored, explored);
+            self.explored_by_character.insert(previous, left);
+            self.trail.clear();
+            self.alerts.clear();
+        }
+        let (entities, terrain, sight) =
+            (&self.game.entities(), &self.game.terrain, &mut self.sight);
+        let view_changed = self
+            .profiler
+            .time(System::Fov, || sight.look(entities, terrain));
+        self.explored.update(&sight.fov.mask, entities);
+        self.profiler
+            .time(System::Awareness, || sight.notice(view_changed))
+    }
+
+    /// Send the local player and the entities it owns to `target`, as
+    /// ordered by right-clicking a tile.
+    fn move_group(&self, target: Point) {
+        let owned = self
+            .game
+            .entities()
+            .iter()
+            .filter(|(_, entity)| entity.owner == Some(self.player_id))
+            .map(|(eid, _)| *eid);
+        let members = std::iter::once(self.player_id)
+            .chain(owned)
+            .take(game::formation::MAX_GROUP_SIZE)
+            .collect();
+        self.send_action(GameAction::MoveGroup { members, target });
+    }
+
+    /// Send an action to the server, if connected.
+    fn send_action(&self, action: GameAction) {
+        crash::action(self.game.tick, &action);
+        if let Some(tx) = &self.client_to_server_tx {
+            tx.send(ClientMessage::Action(action)).ok();
+        }
+    }
+
+    /// The local player's name, position, health, faction and reputations,
+    /// toggled with C.
+    fn character_sheet(&self, ctx: &egui::Context) {
+        egui::Window::new("Character").show(ctx, |ui| {
+            let Some(player) = self.game.entities().get(&self.player_id) else {
+                ui.label("No character");
+                return;
+            };
+            let registry = ContentRegistry::builtin();
+            ui.heading(player.name.as_deref().unwrap_or("Unnamed"));
+            ui.label(format!(
+                "Position: ({}, {})",
+                player.position.x, player.position.y
+            ));
+            if let Some(health) = player.health {
+                ui.label(format!("Health: {}/{}", health.current, health.max));
+            }
+            ui.label(format!(
+                "Faction: {}",
+                player
+                    .faction
+                    .as_deref()
+                    .map_or("none", |id| registry.faction(id).map_or(id, |f| &f.name))
+            ));
+            ui.separator();
+            ui.label("Reputation");
+            egui::Grid::new("reputation").striped(true).show(ui, |ui| {
+                for faction in &registry.factions {
+                    let value = player.reputation.get(&faction.id);
+                    ui.label(&faction.name);
+                    ui.label(value.to_string());
+                    ui.label(Tier::of(value).to_string());
+                    ui.end_row();
+                }
+            });
+            ui.separator();
+            ui.label("Inventory");
+            ui.label(format!(
+                "Load: {}/{} ({})",
+                player.inventory.weight(&registry),
+                registry.carry.max_weight,
+                Encumbrance::of(player, &registry)
+            ));
+            ui.label(format!(
+                "Slots: {}/{}",
+                player.inventory.slots(&registry),
+                registry.carry.max_slots
+            ));
+            self.inventory_list(ui, player);
+            if let Some(status) = &self.item_status {
+                ui.separator();
+                ui.label(status);
+            }
+        });
+    }
+
+    /// The stacks `player` carries, with buttons to repair, split, merge
+    /// and stash them, and what the container in range holds.
+    fn inventory_list(&self, ui: &mut egui::Ui, player: &Entity) {
+        let registry = ContentRegistry::builtin();
+        let container = self.game.entities().iter().find(|(eid, entity)| {
+            item::is_container(&self.game, self.player_id, **eid)
+                && dialogue::in_range(player.position, entity.position)
+        });
+        let mut previous = None;
+        for (item, count) in player.inventory.stacks(&registry) {
+            let name = item_name(&registry, item);
+            // Only the first of an item is in use and worn.
+            let condition = (previous != Some(item))
+                .then(|| player.inventory.condition(&registry, item))
+                .flatten();
+            previous = Some(item);
+            ui.horizontal(|ui| {
+                if let Some((uses, durability)) = condition {
+                    ui.label(format!("{count} × {name} ({uses}/{durability})"));
+                    let repairable = registry.item(item).is_some_and(|d| !d.repair.is_empty());
+                    if repairable && uses < durability && ui.button("Repair").clicked() {
+                        self.send_action(GameAction::Repair(item.to_owned()));
+                    }
+                } else {
+                    ui.label(format!("{count} × {name}"));
+                }
+                if count > 1 && ui.small_button("Split").clicked() {
+                    let count = count / 2;
+                    let item = item.to_owned();
+                    self.send_action(GameAction::SplitStack { item, count });
+                }
+                if let Some((container, _)) = container
+                    && ui.small_button("Stash").clicked()
+                {
+                    self.send_action(GameAction::Stash {
+                        container: *container,
+                        item: item.to_owned(),
+                        count,
+                    });
+                }
+            });
+        }
+        for (item, _) in player.inventory.split_stacks() {
+            if ui
+                .button(format!("Merge {}", item_name(&registry, item)))
+                .clicked()
+            {
+                self.send_action(GameAction::MergeStacks(item.to_owned()));
+            }
+        }
+        let Some((container, entity)) = container else {
+            return;
+        };
+        ui.separator();
+        ui.label(format!(
+            "In {}",
+            entity.name.as_deref().unwrap_or(entity.entity_type.name())
+        ));
+        for (item, count) in entity.inventory.stacks(&registry) {
+            ui.horizontal(|ui| {
+                ui.label(format!("{count} × {}", item_name(&registry, item)));
+                if ui.small_button("Take").clicked() {
+                    self.send_action(GameAction::Retrieve {
+                        container: *container,
+                        item: item.to_owned(),
+                        count,
+                    });
+                }
+            });
+        }
+    }
+
+    /// Stock of the open vendor with buy buttons, and what it buys from the
+    /// player's inventory. Closes once the vendor is out of range.
+    fn shop_window(&mut self, ctx: &egui::Context) {
+        let registry = ContentRegistry::builtin();
+        let (Some(vendor_id), Some(player)) =
+            (self.vendor, self.game.entities().get(&self.player_id))
+        else {
+            return;
+        };
+        let Some((vendor, def)) = self
+            .game
+            .entities()
+            .get(&vendor_id)
+            .filter(|vendor| dialogue::in_range(player.position, vendor.position))
+            .and_then(|vendor| Some((vendor, registry.shop(&vendor.shop.as_ref()?.id)?)))
+        else {
+            self.vendor = None;
+            return;
+        };
+        let shop = vendor.shop.as_ref();
+
+        let mut action = None;
+        egui::Window::new(def.name.as_str())
+            .id(egui::Id::new("shop"))
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "You have {} {}",
+                    player.inventory.count(&registry.currency),
+                    item_name(&registry, &registry.currency)
+                ));
+                ui.separator();
+                egui::Grid::new("stock").striped(true).show(ui, |ui| {
+                    for entry in &def.stock {
+                        let stock = shop.map_or(0, |s| s.stock(&entry.item));
+                        let carried = player.inventory.count(&entry.item);
+                        if entry.count == 0 && carried == 0 {
+                            continue;
+                        }
+                        ui.label(item_name(&registry, &entry.item));
+                        if entry.count > 0 {
+                            ui.label(format!("{stock} left"));
+                            let buy = ui.add_enabled(
+                                stock > 0 && player.inventory.can_take(&registry, &entry.item, 1),
+                                egui::Button::new(format!("Buy ({})", entry.price)),
+                            );
+                            if buy.clicked() {
+                                action = Some(GameAction::Buy {
+                                    vendor: vendor_id,
+                                    item: entry.item.clone(),
+                                    count: 1,
+                                });
+                            }
+                        } else {
+                            ui.label("");
+                            ui.label("");
+                        }
+                        if entry.sell_price > 0 && carried > 0 {
+                            let price = shop::sale_price(&registry, &player.inventory, entry, 1)
+                                .unwrap_or(entry.sell_price);
+                            if ui.button(format!("Sell ({price})")).clicked() {
+                                action = Some(GameAction::Sell {
+                                    vendor: vendor_id,
+                                    item: entry.item.clone(),
+                                    count: 1,
+                                });
+                            }
+                        } else {
+                            ui.label("");
+                        }
+                        ui.end_row();
+                    }
+                });
+                if let Some(status) = &self.trade_status {
+                    ui.colored_label(egui::Color32::LIGHT_RED, status);
+                }
+            });
+        if let Some(action) = action {
+            self.trade_status = None;
+            if let Some(tx) = &self.client_to_server_tx {
+                tx.send(ClientMessage::Action(action)).ok();
+            }
+        }
+    }
+
+    /// The nearest other entity in talking range that passes `filter`.
+    fn nearest_in_range(&self, filter: impl Fn(&game::Entity) -> bool) -> Option<EntityID> {
+        client::nearest_in_range(self.game.entities(), self.player_id, filter)
+    }
 
-        ctx.input(|i| {
-            if i.key_pressed(egui::Key::W) || i.key_pressed(egui::Key::ArrowUp) {
-                messages_to_send.push(GameAction::Move(Direction::Up));
-            }
+    /// The current dialogue node, with a button per option.
+    fn dialogue_window(&self, ctx: &egui::Context) {
+        let Some(view) = &self.dialogue else {
+            return;
+        };
+        let mut chosen = None;
+        egui::Window::new(view.speaker.as_str())
+            .id(egui::Id::new("dialogue"))
+            .collapsible(false)
+            .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0.0, -40.0))
+            .show(ctx, |ui| {
+                ui.label(&view.text);
+                ui.separator();
+                for (index, option) in (0u32..).zip(&view.options) {
+                    if ui.button(format!("{}. {option}", index + 1)).clicked() {
+                        chosen = Some(index);
+                    }
+                }
+            });
+        if let Some(index) = chosen
+            && let Some(tx) = &self.client_to_server_tx
+        {
+            tx.send(ClientMessage::Action(GameAction::Choose(index)))
+                .ok();
+        }
+    }
+
+    /// Server console, toggled with the backtick key.
+    fn console_window(&mut self, ctx: &egui::Context) {
+        egui::Window::new("Console")
+            .default_width(480.0)
+            .show(ctx, |ui| {
+                egui::ScrollArea::vertical()
+                    .max_height(240.0)
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        for line in &self.console_log {
+                            ui.monospace(line);
+                        }
+                    });
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut self.console_input)
+                        .hint_text("help")
+                        .desired_width(f32::INFINITY),
+                );
+                // The key that toggles the console is not part of a command.
+                self.console_input.retain(|c| c != '`');
+                response.request_focus();
+
+                if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                    let line = std::mem::take(&mut self.console_input);
+                    if !line.trim().is_empty() {
+                        log_console(&mut self.console_log, format!("> {line}"));
+                        if let Some(tx) = &self.client_to_server_tx {
+                            tx.send(ClientMessage::Command(line)).ok();
+                        }
+                    }
+                }
+            });
+    }
+
+    /// Recent chat and notable events in the bottom left corner, with a
+    /// line to type in while chatting.
+    fn chat_window(&mut self, ctx: &egui::Context) {
+        if self.chat.is_empty() && self.whispers.is_empty() && !self.chat_open {
+            return;
+        }
+        // Lines of chat start with who said them, in their player's color.
+        let speakers: FxHashMap<String, egui::Color32> = self
+            .game
+            .entities()
+            .values()
+            .filter(|e| e.color.is_some())
+            .filter_map(|e| Some((e.name.clone()?, ui::entity_color(e))))
+            .collect();
+        egui::Window::new("Chat")
+            .anchor(egui::Align2::LEFT_BOTTOM, egui::vec2(8.0, -8.0))
+            .default_width(360.0)
+            .title_bar(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.selectable_label(!self.whispers_tab, "All").clicked() {
+                        self.whispers_tab = false;
+                    }
+                    let whispers = match self.unread_whispers {
+                        0 => "Whispers".to_owned(),
+                        unread => format!("Whispers ({unread})"),
+                    };
+                    if ui.selectable_label(self.whispers_tab, whispers).clicked() {
+                        self.whispers_tab = true;
+                    }
+                });
+                if self.whispers_tab {
+                    self.unread_whispers = 0;
+                }
+                let mut searching = false;
+                if self.chat_open && !self.whispers_tab {
+                    ui.horizontal(|ui| {
+                        for category in Category::ALL {
+                            let shown = self.chat.shows(category);
+                            if ui.selectable_label(shown, category.name()).clicked() {
+                                self.chat.show(category, !shown);
+                            }
+                        }
+                        let search = egui::TextEdit::singleline(&mut self.chat.search)
+                            .hint_text("Search")
+                            .desired_width(f32::INFINITY);
+                        searching = ui.add(search).has_focus();
+                    });
+                }
+                egui::ScrollArea::vertical()
+                    .max_height(160.0)
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        if self.whispers_tab {
+                            self.whispers_list(ui);
+                            return;
+                        }
+                        for line in self.chat.visible() {
+                            let color = match line.category {
+                                Category::Chat => ui.visuals().text_color(),
+                                Category::System => egui::Color32::LIGHT_BLUE,
+                                Category::Combat => egui::Color32::LIGHT_RED,
+                                Category::Exploration => egui::Color32::LIGHT_GREEN,
+                            };
+                            let said = (line.kind == Some(LineKind::Chat))
+                                .then(|| line.text.split_once(": "))
+                                .flatten()
+                                .and_then(|(name, text)| Some((name, speakers.get(name)?, text)));
+                            ui.horizontal_wrapped(|ui| {
+                                ui.weak(ui::clock(line.tick))
+                                    .on_hover_text(ui::world_time(line.tick));
+                                if let Some((name, speaker, text)) = said {
+                                    ui.colored_label(*speaker, format!("{name}:"));
+                                    ui.colored_label(color, text);
+                                } else {
+                                    ui.colored_label(color, &line.text);
+                                }
+                            });
+                        }
+                    });
+                if let Some(status) = &self.chat_status {
+                    ui.colored_label(egui::Color32::LIGHT_YELLOW, status);
+                }
+                if !self.chat_open {
+                    return;
+                }
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut self.chat_input)
+                        .hint_text("Say something, or /msg <player> <text>")
+                        .char_limit(crate::net::chat::MAX_CHAT_LEN)
+                        .desired_width(f32::INFINITY),
+                );
+                if !searching {
+                    response.request_focus();
+                }
+                if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                    self.chat_open = false;
+                    let text = std::mem::take(&mut self.chat_input);
+                    if !text.trim().is_empty() {
+                        self.send_chat(&text);
+                    }
+                }
+            });
+    }
+
+    /// Whispers sent and received, with the outcome of the last command.
+    fn whispers_list(&self, ui: &mut egui::Ui) {
+        if self.whispers.is_empty() {
+            ui.weak("Whisper to a player with /msg <player> <text>");
+        }
+        let me = self
+            .game
+            .entities()
+            .get(&self.player_id)
+            .and_then(|e| e.name.as_deref());
+        for whisper in &self.whispers {
+            let text =
+                if me.is_some_and(|me| whisper::name_key(me) == whisper::name_key(&whisper.from)) {
+                    format!("To {}: {}", whisper.to, whisper.text)
+                } else {
+                    format!("From {}: {}", whisper.from, whisper.text)
+                };
+            ui.colored_label(egui::Color32::from_rgb(220, 160, 255), text);
+        }
+    }
 
-            if i.key_pressed(egui::Key::S) || i.key_pressed(egui::Key::ArrowDown) {
-                messages_to_send.push(GameAction::Move(Direction::Down));
+    /// Send a line typed into the chat, or carry out its command.
+    fn send_chat(&mut self, line: &str) {
+        let Some(tx) = self.client_to_server_tx.clone() else {
+            return;
+        };
+        let command = match whisper::parse_chat(line) {
+            Ok(command) => command,
+            Err(e) => {
+                self.chat_status = Some(e);
+                self.whispers_tab = true;
+                return;
+            }
+        };
+        match command {
+            ChatCommand::Say(text) => {
+                tx.send(ClientMessage::Chat(text)).ok();
             }
-            if i.key_pressed(egui::Key::A) || i.key_pressed(egui::Key::ArrowLeft) {
-                messages_to_send.push(GameAction::Move(Direction::Left));
+            ChatCommand::Server(line) => {
+                log_console(&mut self.console_log, format!("> {line}"));
+                self.chat_status = None;
+                self.chat_commands += 1;
+                tx.send(ClientMessage::Command(line)).ok();
             }
-            if i.key_pressed(egui::Key::D) || i.key_pressed(egui::Key::ArrowRight) {
-                messages_to_send.push(GameAction::Move(Direction::Right));
+            ChatCommand::Whisper { to, text } => {
+                self.chat_status = None;
+                self.whispers_tab = true;
+                tx.send(ClientMessage::Whisper { to, text }).ok();
             }
-            if i.key_pressed(egui::Key::R) {
-                messages_to_send.push(GameAction::SaveWorld);
+            ChatCommand::Block(name) => {
+                self.chat_status = Some(match self.config.block(&name) {
+                    Ok(()) => format!("Blocked {name}; their whispers will not reach you"),
+                    Err(e) => format!("Could not block {name}: {e}"),
+                });
+                self.whispers_tab = true;
+                tx.send(ClientMessage::Block(self.config.blocked.clone()))
+                    .ok();
+            }
+            ChatCommand::Unblock(name) => {
+                self.chat_status = Some(if self.config.unblock(&name) {
+                    format!("Unblocked {name}")
+                } else {
+                    format!("{name} was not blocked")
+                });
+                self.whispers_tab = true;
+                tx.send(ClientMessage::Block(self.config.blocked.clone()))
+                    .ok();
             }
+        }
+    }
+
+    /// Watch a recording: the whole world, with a timeline to scrub
+    /// through and a camera moved with the movement keys.
+    fn replay_screen(&mut self, ctx: &egui::Context) {
+        let Some(view) = &mut self.replay else {
+            self.screen = AppScreen::ReplaySelection;
+            return;
+        };
+        view.advance(f64::from(ctx.input(|i| i.stable_dt)));
+        let keymap = &self.config.keymap;
+        let (dx, dy, close) = ctx.input(|i| {
+            let held = |direction| {
+                let command = Command::Move(direction);
+                i32::from(keymap.held(Context::Gameplay, command, |key| i.key_down(key)))
+            };
+            let x = held(Direction::Right) - held(Direction::Left);
+            let y = held(Direction::Down) - held(Direction::Up);
+            let pressed = keymap.pressed(Context::Menus, |key| i.key_pressed(key));
+            (x, y, pressed.contains(&Command::Close))
         });
-        // Send all the collected messages
-        if let Some(tx) = &self.client_to_server_tx {
-            for event in messages_to_send {
-                if let Err(e) = tx.send(event) {
-                    eprintln!("Failed to send game event: {e}");
+        if dx != 0 || dy != 0 {
+            // Panning lets go of the followed entity.
+            view.camera = view.center();
+            view.follow = None;
+            view.camera.x += dx;
+            view.camera.y += dy;
+        }
+
+        let mut back = false;
+        egui::TopBottomPanel::bottom("timeline").show(ctx, |ui| {
+            back = replay_controls(ui, view);
+        });
+        egui::TopBottomPanel::top("replay").show(ctx, |ui| {
+            let recording = view.playback.recording();
+            ui.horizontal(|ui| {
+                ui.label(format!("Replay of {}", recording.world_name));
+                let actions = recording
+                    .tick(view.playback.tick())
+                    .map_or(0, |t| t.actions.len());
+                ui.label(format!("{actions} action(s) this tick"));
+                ui.label("Follow:");
+                let players: Vec<(EntityID, String)> = view
+                    .playback
+                    .entities()
+                    .iter()
+                    .filter(|(_, e)| e.entity_type == EntityType::Player)
+                    .map(|(eid, e)| (*eid, e.name.clone().unwrap_or_default()))
+                    .collect();
+                for (eid, name) in players {
+                    let label = format!("{name} (#{})", eid.0);
+                    if ui
+                        .selectable_label(view.follow == Some(eid), label)
+                        .clicked()
+                    {
+                        view.follow = Some(eid);
+                    }
+                }
+            });
+        });
+
+        let center = view.center();
+        let index = ui::build_spatial_index(view.playback.entities());
+        // Recordings hold the entities alone.
+        let terrain = TileMap::default();
+        let font_size = self.font_size;
+        let button_size = self.button_size.unwrap_or(font_size);
+        let frame_buffer = &mut self.frame_buffer;
+        egui::CentralPanel::default().show(ctx, |ui| {
+            let rect = ui.available_rect_before_wrap();
+            let cols = ((rect.width() / button_size) as usize).max(1);
+            let rows = ((rect.height() / button_size) as usize).max(1);
+            let (cam_x, cam_y) = (center.x - cols as i32 / 2, center.y - rows as i32 / 2);
+            frame_buffer.begin(cols, rows, font_size);
+            for row in 0..rows {
+                for col in 0..cols {
+                    let point = Point {
+                        x: col as i32 + cam_x,
+                        y: row as i32 + cam_y,
+                    };
+                    frame_buffer.set(col, row, ui::glyph_at(&index, &terrain, &point).into());
                 }
             }
+            let size = egui::vec2(cols as f32, rows as f32) * button_size;
+            let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+            frame_buffer.paint(ui, rect, button_size);
+        });
+
+        if back || close {
+            self.replay = None;
+            self.screen = AppScreen::ReplaySelection;
         }
     }
 
-    fn rogue_screen(&mut self, ctx: &egui::Context) {
-        egui::TopBottomPanel::top("lol").show(ctx, |ui| {
-            // Customize button styling for tighter spacing
-            let style = ui.style_mut();
-            style.spacing.button_padding = egui::vec2(0.0, 0.0);
-            style.visuals.widgets.inactive.bg_stroke.width = 0.0;
-            style.visuals.widgets.hovered.bg_stroke.width = 0.0;
-            style.visuals.widgets.active.bg_stroke.width = 0.0;
+    /// The keys of every command in every context, with a button per key to
+    /// unbind it and one to add a key, and the conflicts between them.
+    fn key_bindings(&mut self, ui: &mut egui::Ui) {
+        if let Some((context, command)) = self.rebinding {
+            let pressed = ui.input(|i| {
+                i.events.iter().find_map(|event| match event {
+                    egui::Event::Key {
+                        key, pressed: true, ..
+                    } => Some(*key),
+                    _ => None,
+                })
+            });
+            if let Some(key) = pressed {
+                self.config.keymap.add_key(context, command, key);
+                self.rebinding = None;
+            }
+        }
+        let conflicts = self.config.keymap.conflicts();
+        let title = match conflicts.len() {
+            0 => "Key bindings".to_owned(),
+            n => format!("Key bindings ({n} conflicting)"),
+        };
+        egui::CollapsingHeader::new(title)
+            .id_salt("key_bindings")
+            .show(ui, |ui| {
+                egui::ScrollArea::vertical()
+                    .max_height(240.0)
+                    .show(ui, |ui| {
+                        for context in Context::ALL {
+                            ui.strong(context.name());
+                            egui::Grid::new(("key_bindings", context.name())).show(ui, |ui| {
+                                for command in context.commands() {
+                                    self.key_binding_row(ui, context, *command, &conflicts);
+                                    ui.end_row();
+                                }
+                            });
+                        }
+                    });
+                ui.horizontal(|ui| {
+                    if self.rebinding.is_some() {
+                        ui.label("Press a key…");
+                        if ui.button("Cancel").clicked() {
+                            self.rebinding = None;
+                        }
+                    } else if ui
+                        .add_enabled(!self.config.keymap.is_default(), egui::Button::new("Reset"))
+                        .clicked()
+                    {
+                        self.config.keymap.reset();
+                    }
+                });
+            });
+    }
 
-            // Calculate button size on first frame if not already calculated
-            if self.button_size.is_none() {
-                let chinese_char = "中";
-                let font_id = egui::FontId::new(self.font_size, egui::FontFamily::Proportional);
-                let char_galley = ui.fonts_mut(|f| {
-                    f.layout_no_wrap(
-                        chinese_char.to_string(),
-                        font_id.clone(),
-                        egui::Color32::WHITE,
+    /// Stick tuning and what each gamepad button does while walking about.
+    fn gamepad_settings(&mut self, ui: &mut egui::Ui) {
+        let connected = self.gamepads.connected();
+        let config = &mut self.config.gamepad;
+        egui::CollapsingHeader::new("Gamepad")
+            .id_salt("gamepad")
+            .show(ui, |ui| {
+                if !connected {
+                    ui.weak("No gamepad plugged in");
+                }
+                egui::Grid::new("gamepad_tuning").show(ui, |ui| {
+                    ui.label("Stick dead zone (%)");
+                    ui.add(egui::Slider::new(&mut config.dead_zone_percent, 10..=90));
+                    ui.end_row();
+                    ui.label("Repeat after (ms)");
+                    ui.add(egui::Slider::new(&mut config.repeat_delay_ms, 50..=1000));
+                    ui.end_row();
+                    ui.label("Repeat every (ms)");
+                    ui.add(egui::Slider::new(&mut config.repeat_interval_ms, 30..=500));
+                    ui.end_row();
+                });
+                ui.separator();
+                egui::Grid::new("gamepad_buttons").show(ui, |ui| {
+                    for button in PadButton::ALL {
+                        ui.label(button.name());
+                        if button == config.quick_menu {
+                            ui.weak("Quick menu (hold)");
+                            ui.end_row();
+                            continue;
+                        }
+                        let current = config.command(button, Context::Gameplay);
+                        let mut picked = current;
+                        egui::ComboBox::from_id_salt(("gamepad_button", button.name()))
+                            .selected_text(picked.map_or("None", Command::name))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut picked, None, "None");
+                                for command in Context::Gameplay.commands() {
+                                    ui.selectable_value(
+                                        &mut picked,
+                                        Some(*command),
+                                        command.name(),
+                                    );
+                                }
+                            });
+                        if picked != current {
+                            config.bind_gameplay(button, picked);
+                        }
+                        ui.end_row();
+                    }
+                });
+                if *config != gamepad::GamepadConfig::default() && ui.button("Reset").clicked() {
+                    *config = gamepad::GamepadConfig::default();
+                }
+            });
+    }
+
+    /// One command of [`key_bindings`](Self::key_bindings): its name, its
+    /// keys, red where they conflict, and a button to add one.
+    fn key_binding_row(
+        &mut self,
+        ui: &mut egui::Ui,
+        context: Context,
+        command: Command,
+        conflicts: &[keymap::Conflict],
+    ) {
+        ui.label(command.name());
+        ui.horizontal(|ui| {
+            let keymap = &self.config.keymap;
+            let keys: Vec<(egui::Key, RichText, String)> = keymap
+                .keys(context, command)
+                .into_iter()
+                .map(|key| {
+                    let clash = conflicts
+                        .iter()
+                        .filter(|c| c.context == context && c.key == key)
+                        .find_map(|c| c.commands.into_iter().find(|other| *other != command));
+                    let mut text = RichText::new(key.symbol_or_name());
+                    let mut hint = "Click to unbind".to_owned();
+                    if let Some(other) = clash {
+                        text = text.color(egui::Color32::LIGHT_RED);
+                        hint = format!("Also bound to {}. {hint}", other.name());
+                    } else if let Some((below, other)) = keymap.overrides(context, key) {
+                        hint = format!("Instead of {} ({}). {hint}", other.name(), below.name());
+                    }
+                    (key, text, hint)
+                })
+                .collect();
+            for (key, text, hint) in keys {
+                if ui.button(text).on_hover_text(hint).clicked() {
+                    self.config.keymap.remove_key(context, command, key);
+                }
+            }
+            let adding = self.rebinding == Some((context, command));
+            if ui
+                .selectable_label(adding, "+")
+                .on_hover_text("Add a key")
+                .clicked()
+            {
+                self.rebinding = (!adding).then_some((context, command));
+            }
+        });
+    }
+
+    /// How hostiles of each type coming into view are announced.
+    fn alert_settings(&mut self, ui: &mut egui::Ui) {
+        let config = &mut self.config.alerts;
+        egui::CollapsingHeader::new("Danger alerts")
+            .id_salt("alerts")
+            .show(ui, |ui| {
+                egui::Grid::new("alert_styles").show(ui, |ui| {
+                    ui.label("");
+                    ui.label("Sound");
+                    ui.label("Flash");
+                    ui.label("Log");
+                    ui.end_row();
+                    for entity_type in alert::ALERTING {
+                        let mut style = config.style(&entity_type);
+                        ui.label(entity_type.name());
+                        let changed = ui.checkbox(&mut style.sound, "").changed()
+                            | ui.checkbox(&mut style.flash, "").changed()
+                            | ui.checkbox(&mut style.log, "").changed();
+                        if changed {
+                            config.set_style(&entity_type, style);
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+    }
+
+    /// Limit how far around us the server sends the world, to save
+    /// bandwidth or to see further.
+    fn view_distance_setting(&mut self, ui: &mut egui::Ui) {
+        let mut limited = self.config.view_distance.is_some();
+        let mut radius = self
+            .config
+            .view_distance
+            .unwrap_or(fov::VIEW_RADIUS.unsigned_abs());
+        let mut changed = ui.checkbox(&mut limited, "Limit view distance").changed();
+        if limited {
+            changed |= ui
+                .add(
+                    egui::Slider::new(&mut radius, MIN_VIEW_RADIUS..=MAX_VIEW_RADIUS).text("tiles"),
+                )
+                .changed();
+        }
+        if changed {
+            self.config.view_distance = limited.then_some(radius);
+            self.send_view_distance();
+        }
+        if let Some(granted) = self.view_granted
+            && granted != radius
+        {
+            ui.label(format!("The server limits it to {granted} tiles"));
+        }
+    }
+
+    /// Pick the color other players see our characters in.
+    fn color_setting(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let mut rgb = self.config.player_color().0;
+            let mut changed = egui::color_picker::color_edit_button_srgb(ui, &mut rgb).changed();
+            ui.label("Your color");
+            if self.config.player_color.is_some() && ui.button("Reset").clicked() {
+                self.config.player_color = None;
+                changed = true;
+            } else if changed {
+                self.config.player_color = Some(PlayerColor(rgb));
+            }
+            if changed {
+                self.send_color();
+            }
+        });
+    }
+
+    /// Menu opened with Escape: resume, export the explored map or the
+    /// character, or take a screenshot of the glyph view.
+    fn pause_menu(&mut self, ctx: &egui::Context) {
+        egui::Window::new("Paused")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+            .show(ctx, |ui| {
+                if ui.button("Resume").clicked() {
+                    self.paused = false;
+                }
+                ui.checkbox(
+                    &mut self.config.show_compass,
+                    "Show compass and coordinates",
+                );
+                if ui
+                    .checkbox(
+                        &mut self.config.crash_snapshots,
+                        "Include the world in crash reports",
+                    )
+                    .on_hover_text("Reports stay on this machine until you share them")
+                    .changed()
+                    && !self.config.crash_snapshots
+                {
+                    crash::clear_snapshot();
+                }
+                let mut backups = self.config.save_backups();
+                if ui
+                    .add(
+                        egui::Slider::new(&mut backups, 0..=game::persist::MAX_SAVE_BACKUPS)
+                            .text("backups kept of each save"),
                     )
+                    .changed()
+                {
+                    self.config.save_backups = Some(backups);
+                    game::persist::set_backups(backups);
+                }
+                self.view_distance_setting(ui);
+                self.color_setting(ui);
+                egui::ComboBox::from_label("Low-power mode")
+                    .selected_text(self.config.low_power.label())
+                    .show_ui(ui, |ui| {
+                        for mode in LowPower::ALL {
+                            ui.selectable_value(&mut self.config.low_power, mode, mode.label());
+                        }
+                    })
+                    .response
+                    .on_hover_text("Fewer frames, no idle animations and a shorter view");
+                self.key_bindings(ui);
+                self.gamepad_settings(ui);
+                self.alert_settings(ui);
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    ui.label("Save to:");
+                    ui.text_edit_singleline(&mut self.export_path);
+                });
+                ui.checkbox(&mut self.export_glyph_scale, "Glyph scale");
+                ui.horizontal(|ui| {
+                    if ui.button("Export map").clicked() {
+                        self.export_map();
+                    }
+                    if ui.button("Screenshot").clicked() {
+                        self.screenshot =
+                            Some(Screenshot::Queued(PathBuf::from(&self.export_path)));
+                        self.paused = false;
+                    }
                 });
+                if ui.button("Export character").clicked()
+                    && let Some(tx) = &self.client_to_server_tx
+                {
+                    tx.send(ClientMessage::ExportCharacter).ok();
+                }
+                if ui.button("Export log").clicked() {
+                    self.export_status = Some(self.export_log());
+                }
+                if let Some(status) = &self.export_status {
+                    ui.label(status);
+                }
+                if self.single_player {
+                    ui.separator();
+                    self.save_slot_section(ui);
+                }
+                ui.separator();
+                self.recordings_section(ui);
+            });
+    }
 
-                let size = char_galley.size();
-                self.button_size = Some(size.x.max(size.y));
+    /// Pause menu section saving the hosted world to a named save slot.
+    fn save_slot_section(&mut self, ui: &mut egui::Ui) {
+        let Some(tx) = &self.client_to_server_tx else {
+            return;
+        };
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.slot_name)
+                    .hint_text("Snapshot name")
+                    .char_limit(slots::MAX_SLOT_NAME_LEN),
+            );
+            if ui.button("Save As…").clicked() {
+                self.slot_status = Some(match slots::slot_name(&self.slot_name) {
+                    Ok(name) => {
+                        tx.send(ClientMessage::SaveSlot(name)).ok();
+                        "Saving…".to_owned()
+                    }
+                    Err(reason) => format!("Could not save: {reason}"),
+                });
             }
+        });
+        if let Some(status) = &self.slot_status {
+            ui.label(status);
+        }
+    }
 
-            let button_size = self.button_size.unwrap();
+    /// The world was saved to slot `name`: draw the explored map beside it.
+    fn slot_saved(&mut self, name: &str) {
+        let path = slots::thumbnail_path(self.game.world_id, name);
+        if let Some(image) = ui::map_thumbnail(&self.explored, SLOT_THUMBNAIL_PIXELS)
+            && let Err(e) = export::save_png(&path, &image)
+        {
+            log::warn!("Failed to save {}: {e}", path.display());
+        }
+        self.slot_status = Some(format!("Saved \"{name}\""));
+    }
 
-            // Calculate available space
-            let content = ui.ctx().content_rect();
-            let cols = ((content.width() / button_size) as usize).max(1);
-            let rows = ((content.height() / button_size) as usize).max(1);
+    /// World selection list of save slots, newest first, to load or delete.
+    fn save_slots_list(&mut self, ui: &mut egui::Ui) {
+        let saved = slots::list();
+        if saved.is_empty() {
+            return;
+        }
+        ui.add_space(20.0);
+        ui.label(RichText::new("Saved Snapshots:").size(16.0));
+        ui.add_space(10.0);
 
-            // Camera centering
-            let center = self
-                .game
-                .entities
-                .get(&self.player_id)
-                .map_or(Point { x: 0, y: 0 }, |e| e.position);
+        let now = unix_now();
+        let mut load = None;
+        let mut delete = None;
+        egui::ScrollArea::vertical()
+            .id_salt("save slots")
+            .max_height(300.0)
+            .show(ui, |ui| {
+                for slot in &saved {
+                    ui.horizontal(|ui| {
+                        if let Some(texture) = self.slot_thumbnail(ui.ctx(), slot) {
+                            let size = texture.size_vec2();
+                            let scale = SLOT_THUMBNAIL_SIZE / size.x.max(size.y);
+                            ui.image((texture.id(), size * scale));
+                        }
+                        ui.label(format!(
+                            "{}: {} ({})",
+                            slot.world_name,
+                            slot.name,
+                            ui::time_ago(now.saturating_sub(slot.saved_at))
+                        ));
+                        if ui.button("Load").clicked() {
+                            load = Some(slot.clone());
+                        }
+                        if ui.button("Delete").clicked() {
+                            delete = Some(slot.clone());
+                        }
+                    });
+                }
+            });
+        if let Some(status) = &self.slot_status {
+            ui.label(status);
+        }
 
-            let cam_x = center.x - (cols as i32 / 2);
-            let cam_y = center.y - (rows as i32 / 2);
+        if let Some(slot) = delete {
+            self.slot_thumbnails.remove(&slot.path);
+            self.slot_status = Some(match slots::delete(&slot) {
+                Ok(()) => format!("Deleted \"{}\"", slot.name),
+                Err(e) => format!("Could not delete \"{}\": {e}", slot.name),
+            });
+        }
+        if let Some(slot) = load {
+            self.resume_prompt = false;
+            self.host_world(&slot.path);
+            if self.screen == AppScreen::CharacterSelection {
+                // Saving goes to the world's own file, leaving the slot be.
+                self.last_world = Some(slot.world_name);
+                self.slot_status = None;
+            } else {
+                self.slot_status = Some(format!("Could not load \"{}\"", slot.name));
+            }
+        }
+    }
+
+    /// The thumbnail of `slot`, read again whenever it was saved again.
+    fn slot_thumbnail(
+        &mut self,
+        ctx: &egui::Context,
+        slot: &SaveSlot,
+    ) -> Option<egui::TextureHandle> {
+        let stale = self
+            .slot_thumbnails
+            .get(&slot.path)
+            .is_none_or(|(saved_at, _)| *saved_at != slot.saved_at);
+        if stale {
+            let texture = export::load_png(&slot.thumbnail_path()).ok().map(|image| {
+                ctx.load_texture(
+                    format!("slot {}", slot.path.display()),
+                    image,
+                    egui::TextureOptions::NEAREST,
+                )
+            });
+            self.slot_thumbnails
+                .insert(slot.path.clone(), (slot.saved_at, texture));
+        }
+        self.slot_thumbnails.get(&slot.path)?.1.clone()
+    }
 
-            ui.spacing_mut().item_spacing = egui::vec2(0.0, 0.0);
+    /// Pause menu section listing the server's recordings for download.
+    fn recordings_section(&mut self, ui: &mut egui::Ui) {
+        let Some(tx) = &self.client_to_server_tx else {
+            return;
+        };
+        if ui.button("List server recordings").clicked() {
+            tx.send(ClientMessage::ListRecordings).ok();
+        }
+        for name in &self.server_recordings {
+            ui.horizontal(|ui| {
+                ui.label(name);
+                if ui.button("Download").clicked()
+                    && let Some(request) = self.transfers.fetch(name)
+                {
+                    tx.send(request).ok();
+                    self.download_status = Some(format!("Downloading {name}…"));
+                }
+            });
+        }
+        if let Some(status) = &self.download_status {
+            ui.label(status);
+        }
+    }
 
-            // Build spatial index once per frame for O(1) lookups
-            let index = ui::build_spatial_index(&self.game.entities);
+    /// Write the explored map to the export path as a PNG.
+    fn export_map(&mut self) {
+        let scale = if self.export_glyph_scale {
+            self.button_size.map_or(1, |size| size.round() as usize)
+        } else {
+            1
+        };
+        let Some(image) = ui::map_image(&self.explored, scale) else {
+            self.export_status = Some("Nothing to export (or the map is too large)".to_owned());
+            return;
+        };
+        self.export_status = Some(save_image(Path::new(&self.export_path), &image));
+    }
 
-            ui.centered_and_justified(|ui| {
-                ui.vertical_centered(|ui| {
-                    for row in 0..rows {
-                        ui.horizontal(|ui| {
-                            for col in 0..cols {
-                                let point = Point {
-                                    x: col as i32 + cam_x,
-                                    y: row as i32 + cam_y,
-                                };
+    /// Write everything logged this session to a text file in
+    /// [`LOG_EXPORT_DIR`], and say where.
+    fn export_log(&self) -> String {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let path = Path::new(LOG_EXPORT_DIR).join(format!("log-{now}.txt"));
+        let text = self.chat.to_text(ui::world_time);
+        match fs::create_dir_all(LOG_EXPORT_DIR).and_then(|()| fs::write(&path, text)) {
+            Ok(()) => format!("Saved {}", path.display()),
+            Err(e) => format!("Failed to save {}: {e}", path.display()),
+        }
+    }
 
-                                let glyph = ui::glyph_at(&index, &point);
-
-                                let button = egui::Button::new(
-                                    RichText::new(glyph.character)
-                                        .color(glyph.fg_color)
-                                        .font(FontId::proportional(
-                                            self.font_size / glyph.size_mod,
-                                        )),
-                                )
-                                .min_size(egui::vec2(button_size, button_size))
-                                .corner_radius(0.0)
-                                .fill(glyph.bg_color);
-                                ui.add(button);
-                            }
-                        });
-                    }
+    /// Request a queued screenshot, and save it once the image arrives.
+    fn poll_screenshot(&mut self, ctx: &egui::Context) {
+        match self.screenshot.take() {
+            Some(Screenshot::Queued(path)) => {
+                ctx.send_viewport_cmd(egui::ViewportCommand::Screenshot(egui::UserData::default()));
+                self.screenshot = Some(Screenshot::Requested(path));
+            }
+            Some(Screenshot::Requested(path)) => {
+                let image = ctx.input(|i| {
+                    i.raw.events.iter().find_map(|event| match event {
+                        egui::Event::Screenshot { image, .. } => Some(image.clone()),
+                        _ => None,
+                    })
                 });
-            });
+                match image {
+                    Some(image) => self.export_status = Some(save_image(&path, &image)),
+                    None => self.screenshot = Some(Screenshot::Requested(path)),
+                }
+            }
+            None => {}
+        }
+    }
+
+    /// Estimated memory held by the client's copy of the world and its caches.
+    fn memory_report(&self) -> MemoryReport {
+        MemoryReport::default()
+            .nest("game", self.game.memory_report())
+            .with("fov", self.sight.fov.mask.memory_bytes())
+            .with("tile index", self.sight.tiles.memory_bytes())
+            .with("explored map", self.explored.memory_bytes())
+            .with(
+                "loading snapshot",
+                self.world_sync
+                    .loading()
+                    .map_or(0, SnapshotAssembler::memory_bytes),
+            )
+    }
+
+    /// Legend and statistics for the enabled debug overlays.
+    fn debug_window(&self, ctx: &egui::Context) {
+        egui::Window::new("Debug").show(ctx, |ui| {
+            if self.debug_overlays.spatial_index {
+                let occupancy = ui::bucket_occupancy(self.game.entities());
+                let stacked = occupancy.values().filter(|count| **count > 1).count();
+                ui.label(format!(
+                    "Spatial index: {} buckets, {} entities, {stacked} stacked",
+                    occupancy.len(),
+                    self.game.entities().len(),
+                ));
+                ui.colored_label(ui::occupancy_color(1), "■ one entity");
+                ui.colored_label(ui::occupancy_color(2), "■ several entities");
+            }
+            if self.debug_overlays.fov {
+                ui.label(format!(
+                    "Field of view: {} tiles in sight, {} entities noticed",
+                    self.sight.fov.mask.len(),
+                    self.sight.awareness.len(),
+                ));
+                let sight = ui::fov_color(true, true).unwrap_or_default();
+                let margin = ui::fov_color(false, true).unwrap_or_default();
+                ui.colored_label(sight, "■ in sight");
+                ui.colored_label(margin, "■ awareness margin only");
+            }
+            if self.debug_overlays.paths {
+                ui.label(format!(
+                    "Planned paths: {} (servers built for release send none)",
+                    self.debug_data.paths.len()
+                ));
+            }
+            if self.debug_overlays.chunks {
+                ui.label(format!("Chunks: {CHUNK_SIZE} × {CHUNK_SIZE} tiles"));
+            }
+            if self.debug_overlays.profiler {
+                ui.label("Client frame timings (server: `profile` in the console)");
+                ui::profile_chart(ui, &self.profiler);
+                ui.label(format!(
+                    "Map: {} tiles, {} laid out this frame",
+                    self.frame_buffer.len(),
+                    self.frame_buffer.laid_out()
+                ));
+                ui.separator();
+                ui.label("Client memory (server: `memory` in the console)");
+                ui.monospace(self.memory_report().to_string());
+            }
         });
     }
 }
 
+/// Play, speed and timeline controls of the replay screen. Returns `true`
+/// if the viewer asked to leave.
+fn replay_controls(ui: &mut egui::Ui, view: &mut ReplayView) -> bool {
+    let (start, end) = (
+        view.playback.recording().start_tick(),
+        view.playback.recording().end_tick(),
+    );
+    let mut tick = view.playback.tick();
+    let mut back = false;
+    ui.horizontal(|ui| {
+        back = ui.button("Back").clicked();
+        let play = if view.playing { "Pause" } else { "Play" };
+        if ui.button(play).clicked() {
+            if view.playback.is_at_end() {
+                tick = start;
+            }
+            view.playing = !view.playing;
+        }
+        let keyframes: Vec<u64> = view.playback.recording().keyframe_ticks().collect();
+        if ui.button("⏮").on_hover_text("Previous keyframe").clicked() {
+            tick = keyframes
+                .iter()
+                .rev()
+                .copied()
+                .find(|k| *k < tick)
+                .unwrap_or(start);
+        }
+        if ui.button("⏭").on_hover_text("Next keyframe").clicked() {
+            tick = keyframes.iter().copied().find(|k| *k > tick).unwrap_or(end);
+        }
+        for speed in [0.5, 1.0, 2.0, 4.0] {
+            if ui
+                .selectable_label(view.speed == speed, format!("{speed}×"))
+                .clicked()
+            {
+                view.speed = speed;
+            }
+        }
+        ui.label(format!("Tick {tick} / {end}"));
+    });
+    ui.spacing_mut().slider_width = ui.available_width();
+    ui.add(egui::Slider::new(&mut tick, start..=end).show_value(false));
+    if tick != view.playback.tick() {
+        view.playback.seek(tick);
+    }
+    back
+}
+
+/// Append to the console log, dropping the oldest lines beyond
+/// [`MAX_CONSOLE_LINES`].
+fn log_console(log: &mut Vec<String>, line: String) {
+    crash::note(line.clone());
+    log.push(line);
+    let excess = log.len().saturating_sub(MAX_CONSOLE_LINES);
+    log.drain(..excess);
+}
+
+/// Summary of a roster character's statistics for the character list.
+fn stats_line(stats: &CharacterStats) -> String {
+    format!(
+        "{} ticks, {} steps, {} attacks",
+        stats.ticks_played, stats.steps, stats.attacks
+    )
+}
+
+/// Sync saves with the backup peer set in `config`, if any.
+#[cfg(feature = "backup-peer")]
+fn install_backup_peer(config: &ClientConfig) {
+    let Some(id) = &config.backup_peer else {
+        return;
+    };
+    match id.trim().parse::<EndpointId>() {
+        Ok(peer) => {
+            let installed = crate::net::backup::PeerSync::new(peer)
+                .is_some_and(|sync| game::persist::install_sync(Box::new(sync)).is_ok());
+            if !installed {
+                log::warn!("Could not sync saves with {id}");
+            }
+        }
+        Err(e) => log::warn!("Invalid backup peer {id}: {e}"),
+    }
+}
+
+/// Save `image` as a PNG and describe the outcome for the pause menu.
+fn save_image(path: &Path, image: &egui::ColorImage) -> String {
+    match export::save_png(path, image) {
+        Ok(()) => format!("Saved {}", path.display()),
+        Err(e) => {
+            log::warn!("Failed to save {}: {e}", path.display());
+            format!("Failed to save {}: {e}", path.display())
+        }
+    }
+}
+
 /// Lists all available world files.
 pub fn get_world_files() -> Vec<PathBuf> {
-    let worlds_dir = PathBuf::from("worlds");
+    list_files("worlds", "world")
+}
+
+/// Lists the characters exported from other worlds.
+pub fn get_character_files() -> Vec<PathBuf> {
+    list_files(client::CHARACTERS_DIR, "character")
+}
+
+/// Files in `dir` with the given extension.
+fn list_files(dir: &str, extension: &str) -> Vec<PathBuf> {
+    let dir = PathBuf::from(dir);
 
-    if !worlds_dir.exists() {
+    if !dir.exists() {
         return Vec::new();
     }
 
-    let Ok(entries) = fs::read_dir(worlds_dir) else {
+    let Ok(entries) = fs::read_dir(dir) else {
         return Vec::new();
     };
 
     entries
         .filter_map(|entry| entry.ok())
         .map(|entry| entry.path())
-        .filter(|path| path.extension().and_then(|s| s.to_str()) == Some("world"))
+        .filter(|path| path.extension().and_then(|s| s.to_str()) == Some(extension))
         .collect()
 }
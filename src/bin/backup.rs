@@ -0,0 +1,43 @@
+//! Keep the saves that gamik clients sync to this node.
+//!
+//! ```text
+//! cargo run --release --features backup-peer --bin backup -- [dir]
+//! ```
+//!
+//! Saves are kept in `dir` (`backups` by default), in a directory per
+//! client. Set the endpoint ID printed at start as `backup_peer` in a
+//! client built with the `backup-peer` feature; see `gamik::net::backup`.
+
+use gamik::net::backup::{BACKUP_ALPN, BackupServer};
+
+use iroh::Endpoint;
+use iroh::protocol::Router;
+use std::io::{self, Write as _};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let root = std::env::args()
+        .nth(1)
+        .map_or_else(|| PathBuf::from("backups"), PathBuf::from);
+    let endpoint = match Endpoint::bind().await {
+        Ok(endpoint) => endpoint,
+        Err(e) => {
+            writeln!(io::stderr(), "backup: {e}").ok();
+            return ExitCode::FAILURE;
+        }
+    };
+    writeln!(
+        io::stdout(),
+        "Keeping saves in {} as {}",
+        root.display(),
+        endpoint.id()
+    )
+    .ok();
+    let _router = Router::builder(endpoint)
+        .accept(BACKUP_ALPN, BackupServer::new(root))
+        .spawn();
+    std::future::pending::<()>().await;
+    ExitCode::SUCCESS
+}
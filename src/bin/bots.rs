@@ -0,0 +1,102 @@
+//! Load-test a server with simulated players.
+//!
+//! ```text
+//! cargo run --release --bin bots -- <server-id> --count 300
+//! ```
+//!
+//! Every bot spawns a character and random-walks with its own seed. Once a
+//! second the totals are printed; stop with Ctrl+C or `--seconds`.
+
+use gamik::net::bot::{Behavior, BotClient, BotStats};
+
+use iroh::{EndpointAddr, EndpointId};
+use std::io::{self, Write as _};
+use std::process::ExitCode;
+use std::time::Duration;
+
+const USAGE: &str = "\
+Usage: bots <server-id> [--count <n>] [--idle] [--seconds <n>]
+
+  --count <n>    Number of bots to connect (default 100)
+  --idle         Only connect and spawn; do not walk
+  --seconds <n>  Stop after this many seconds (default: run until killed)";
+
+struct Options {
+    server: EndpointId,
+    count: usize,
+    idle: bool,
+    seconds: Option<u64>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut args = args.iter();
+    let server = args
+        .next()
+        .ok_or(USAGE)?
+        .parse()
+        .map_err(|e| format!("invalid server id: {e}"))?;
+    let mut options = Options {
+        server,
+        count: 100,
+        idle: false,
+        seconds: None,
+    };
+    while let Some(flag) = args.next() {
+        let mut value = || args.next().ok_or(format!("{flag} needs a value"));
+        match flag.as_str() {
+            "--count" => {
+                options.count = value()?
+                    .parse()
+                    .map_err(|e| format!("invalid --count: {e}"))?;
+            }
+            "--seconds" => {
+                options.seconds = Some(
+                    value()?
+                        .parse()
+                        .map_err(|e| format!("invalid --seconds: {e}"))?,
+                );
+            }
+            "--idle" => options.idle = true,
+            _ => return Err(format!("unknown argument `{flag}`\n{USAGE}")),
+        }
+    }
+    Ok(options)
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            writeln!(io::stderr(), "bots: {e}").ok();
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let addr = EndpointAddr::from(options.server);
+    let bots = BotClient::swarm(&addr, options.count, |n| {
+        if options.idle {
+            Behavior::Idle
+        } else {
+            Behavior::RandomWalk { seed: n as u64 }
+        }
+    });
+
+    let mut elapsed = 0;
+    while options.seconds.is_none_or(|limit| elapsed < limit) {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        elapsed += 1;
+        let stats: Vec<BotStats> = bots.iter().map(BotClient::stats).collect();
+        writeln!(
+            io::stdout(),
+            "{elapsed:>4}s  {}/{} spawned  {} sent  {} received",
+            stats.iter().filter(|s| s.spawned).count(),
+            bots.len(),
+            stats.iter().map(|s| s.sent).sum::<u64>(),
+            stats.iter().map(|s| s.received).sum::<u64>(),
+        )
+        .ok();
+    }
+    ExitCode::SUCCESS
+}
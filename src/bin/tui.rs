@@ -0,0 +1,436 @@
+//! Play in a terminal: on a server, over SSH, or for the love of it.
+//!
+//! ```text
+//! cargo run --release --features tui --bin tui -- <server-id> --name Alice
+//! ```
+//!
+//! The map is drawn from the same [client core](gamik::client) as in the
+//! window: the same glyphs, for what the character sees and is aware of,
+//! with terrain out of sight dimmed. Keys are those of the window: move
+//! with the arrow keys or WASD, talk with T, attack with X, chop with G and
+//! cancel with Q. Quit with Esc.
+
+use gamik::client::{self, Applied, Connection, Control, Joined, Sight, WorldSync};
+use gamik::game::{Direction, EntityID, GameAction, GameState, PlayerKey, Point};
+use gamik::net::{ClientMessage, Message, ServerMessage};
+use gamik::ui;
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use iroh::EndpointId;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::VecDeque;
+use std::io::{self, Write as _};
+use std::process::ExitCode;
+use std::time::Duration;
+
+const USAGE: &str = "\
+Usage: tui <server-id> [--name <name>]
+
+  --name <name>  Name of the character to play (default Wanderer)";
+
+/// How long to wait for a key before drawing the next frame.
+const FRAME: Duration = Duration::from_millis(50);
+
+/// Chat and status lines shown below the map.
+const KEPT_LINES: usize = 4;
+
+struct Options {
+    server: EndpointId,
+    name: String,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut args = args.iter();
+    let server = args
+        .next()
+        .ok_or(USAGE)?
+        .parse()
+        .map_err(|e| format!("invalid server id: {e}"))?;
+    let mut options = Options {
+        server,
+        name: "Wanderer".to_owned(),
+    };
+    while let Some(flag) = args.next() {
+        match flag.as_str() {
+            "--name" => {
+                options.name = args.next().ok_or("--name needs a value")?.clone();
+            }
+            _ => return Err(format!("unknown argument `{flag}`\n{USAGE}")),
+        }
+    }
+    Ok(options)
+}
+
+/// The terminal client's state: a connection, the world as received, and
+/// what the character sees of it.
+struct Tui {
+    connection: Connection,
+    world_sync: WorldSync,
+    sight: Sight,
+    game: GameState,
+    player: Option<EntityID>,
+    /// Chat and status lines, oldest first.
+    lines: VecDeque<String>,
+}
+
+impl Tui {
+    fn new(options: &Options) -> Self {
+        let connection = client::connect(options.server, PlayerKey::generate(), None);
+        let spawn = GameAction::SpawnPlayer(options.name.clone());
+        connection.tx.send(ClientMessage::Action(spawn)).ok();
+        let mut tui = Self {
+            connection,
+            world_sync: WorldSync::default(),
+            sight: Sight::default(),
+            game: GameState::create_test_world("default".into()),
+            player: None,
+            lines: VecDeque::new(),
+        };
+        tui.log(format!("Connecting to {}…", options.server));
+        tui
+    }
+
+    fn log(&mut self, line: String) {
+        if self.lines.len() >= KEPT_LINES {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+
+    /// Take in what the server sent.
+    ///
+    /// # Errors
+    /// With the reason, once the connection failed.
+    fn poll_network(&mut self) -> Result<(), String> {
+        if let Ok(reason) = self.connection.failed.try_recv() {
+            return Err(reason);
+        }
+        while let Ok(msg) = self.connection.rx.try_recv() {
+            let Message::Server(msg) = msg else {
+                continue;
+            };
+            let msg = match self.world_sync.receive(msg, &mut self.game) {
+                Ok(applied) => {
+                    self.world_updated(applied);
+                    continue;
+                }
+                Err(msg) => msg,
+            };
+            match client::join_reply(msg) {
+                Ok(joined) => self.joined(joined),
+                Err(msg) => self.observe(msg),
+            }
+        }
+        Ok(())
+    }
+
+    fn world_updated(&mut self, applied: Applied) {
+        match applied {
+            Applied::Replaced => self.sight.invalidate(),
+            Applied::Changed { touched, .. } => self.sight.note(&touched),
+            Applied::Missing { tick, missing } => {
+                self.connection
+                    .tx
+                    .send(ClientMessage::RequestSnapshotParts { tick, missing })
+                    .ok();
+            }
+            Applied::Pending => {}
+            Applied::Terrain => self.sight.terrain_changed(),
+        }
+    }
+
+    fn joined(&mut self, joined: Joined) {
+        match joined {
+            Joined::Playing(eid) => self.player = Some(eid),
+            Joined::Refused { reason, .. } => self.log(format!("Could not join: {reason}")),
+            Joined::Queued(position) => self.log(format!("Waiting in line: {position}")),
+            _ => {}
+        }
+    }
+
+    fn observe(&mut self, msg: ServerMessage) {
+        match msg {
+            ServerMessage::WorldInfo { id, name } => {
+                self.log(format!("Joined {name}"));
+                let start = self.world_sync.join(&mut self.game, id, name, false);
+                self.connection.tx.send(start.sync(None)).ok();
+            }
+            ServerMessage::Chat(line) => self.log(line.text),
+            _ => {}
+        }
+    }
+
+    /// Handle a key press. Returns `false` to quit.
+    fn key(&self, code: KeyCode) -> bool {
+        let control = match code {
+            KeyCode::Esc => return false,
+            KeyCode::Up | KeyCode::Char('w') => Control::Move(Direction::Up),
+            KeyCode::Down | KeyCode::Char('s') => Control::Move(Direction::Down),
+            KeyCode::Left | KeyCode::Char('a') => Control::Move(Direction::Left),
+            KeyCode::Right | KeyCode::Char('d') => Control::Move(Direction::Right),
+            KeyCode::Char('t') => Control::Talk,
+            KeyCode::Char('x') => Control::Attack,
+            KeyCode::Char('g') => Control::Chop,
+            KeyCode::Char('q') => Control::Cancel,
+            KeyCode::Char('.') => Control::Wait,
+            _ => return true,
+        };
+        if let Some(player) = self.player
+            && let Some(action) = control.action(self.game.entities(), player)
+        {
+            self.connection.tx.send(ClientMessage::Action(action)).ok();
+        }
+        true
+    }
+
+    fn draw(&mut self, frame: &mut Frame<'_>) {
+        let [map, log] = Layout::vertical([
+            Constraint::Min(1),
+            Constraint::Length(KEPT_LINES as u16 + 2),
+        ])
+        .areas(frame.area());
+        let title = match self.world_sync.loading() {
+            Some(assembler) => format!(" Loading world… {:.0}% ", assembler.progress() * 100.0),
+            None => " gamik ".to_owned(),
+        };
+        let map_block = Block::bordered().title(title);
+        let lines = self.map_lines(map_block.inner(map));
+        frame.render_widget(Paragraph::new(lines).block(map_block), map);
+        let log_lines: Vec<Line<'_>> = self.lines.iter().map(|l| Line::raw(l.as_str())).collect();
+        frame.render_widget(Paragraph::new(log_lines).block(Block::bordered()), log);
+    }
+
+    /// The map around the character, two terminal columns to a tile so
+    /// that tiles come out roughly square.
+    fn map_lines(&mut self, area: Rect) -> Vec<Line<'static>> {
+        let Some(player) = self.player else {
+            return Vec::new();
+        };
+        self.sight.follow(player);
+        let view_changed = self.sight.look(self.game.entities(), &self.game.terrain);
+        let awareness = self.sight.notice(view_changed);
+        let index = ui::build_visible_index(self.game.entities(), &awareness);
+        let center = self
+            .game
+            .entities()
+            .get(&player)
+            .map_or(Point { x: 0, y: 0 }, |e| e.position);
+
+        let (cols, rows) = (i32::from(area.width / 2), i32::from(area.height));
+        let (cam_x, cam_y) = (center.x - cols / 2, center.y - rows / 2);
+        (0..rows)
+            .map(|row| {
+                let spans: Vec<Span<'static>> = (0..cols)
+                    .map(|col| {
+                        let point = Point {
+                            x: col + cam_x,
+                            y: row + cam_y,
+                        };
+                        let mut glyph = ui::glyph_at(&index, &self.game.terrain, &point);
+                        if !self.sight.fov.mask.contains(point) {
+                            ui::dim(&mut glyph);
+                        }
+                        tile(&glyph)
+                    })
+                    .collect();
+                Line::from(spans)
+            })
+            .collect()
+    }
+}
+
+/// A glyph as two terminal columns: wide characters fill both, narrow ones
+/// are padded.
+fn tile(glyph: &ui::Glyph) -> Span<'static> {
+    let rgb = |c: egui::Color32| Color::Rgb(c.r(), c.g(), c.b());
+    let mut style = Style::new().fg(rgb(glyph.fg_color)).bg(rgb(glyph.bg_color));
+    if glyph.bold {
+        style = style.add_modifier(Modifier::BOLD);
+    }
+    let text = if glyph.character.is_ascii() {
+        format!("{} ", glyph.character)
+    } else {
+        glyph.character.to_owned()
+    };
+    Span::styled(text, style)
+}
+
+fn run(terminal: &mut DefaultTerminal, tui: &mut Tui) -> io::Result<Result<(), String>> {
+    loop {
+        if let Err(reason) = tui.poll_network() {
+            return Ok(Err(reason));
+        }
+        terminal.draw(|frame| tui.draw(frame))?;
+        if !event::poll(FRAME)? {
+            continue;
+        }
+        if let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+            && !tui.key(key.code)
+        {
+            return Ok(Ok(()));
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            writeln!(io::stderr(), "tui: {e}").ok();
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut tui = Tui::new(&options);
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, &mut tui);
+    ratatui::restore();
+    match result {
+        Ok(Ok(())) => ExitCode::SUCCESS,
+        Ok(Err(reason)) => {
+            writeln!(io::stderr(), "tui: disconnected: {reason}").ok();
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            writeln!(io::stderr(), "tui: terminal error: {e}").ok();
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gamik::game;
+    use gamik::net::Denied;
+
+    use ratatui::Terminal;
+    use ratatui::backend::TestBackend;
+    use tokio::sync::{mpsc, oneshot};
+
+    /// The server's ends of a [`Tui`]'s connection.
+    struct Server {
+        /// What the client sent.
+        sent: mpsc::UnboundedReceiver<ClientMessage>,
+        send: mpsc::UnboundedSender<Message>,
+        fail: oneshot::Sender<String>,
+    }
+
+    /// A client playing `game`, connected to no server but the returned
+    /// ends.
+    fn offline(game: GameState) -> (Tui, Server) {
+        let (tx, sent) = mpsc::unbounded_channel();
+        let (send, rx) = mpsc::unbounded_channel();
+        let (fail, failed) = oneshot::channel();
+        let tui = Tui {
+            connection: Connection { tx, rx, failed },
+            world_sync: WorldSync::default(),
+            sight: Sight::default(),
+            game,
+            player: None,
+            lines: VecDeque::new(),
+        };
+        (tui, Server { sent, send, fail })
+    }
+
+    /// The terminal `tui` draws, `width` by `height` cells, as text.
+    fn render(tui: &mut Tui, width: u16, height: u16) -> Vec<String> {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).expect("a terminal");
+        terminal.draw(|frame| tui.draw(frame)).expect("drawn");
+        let buffer = terminal.backend().buffer();
+        (0..height)
+            .map(|y| {
+                (0..width)
+                    .filter_map(|x| buffer.cell((x, y)).map(|cell| cell.symbol().to_owned()))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn the_map_is_drawn_around_the_character_with_the_log_below() {
+        let mut game = GameState::create_test_world("Terminal".into());
+        let alice = game::spawn_player(&mut game, "Alice".into());
+        let (mut tui, _server) = offline(game);
+        tui.log("Joined Terminal".into());
+
+        // Until the server says who the character is, there is no map.
+        let screen = render(&mut tui, 40, 14);
+        assert!(screen.iter().all(|line| !line.contains('@')));
+
+        tui.player = Some(alice);
+        let screen = render(&mut tui, 40, 14);
+        assert!(screen.first().is_some_and(|l| l.contains(" gamik ")));
+        // Eight rows of map, six inside the border, with the character
+        // on the middle one, in the middle of nineteen two-column tiles.
+        let row = screen.get(1 + 3).expect("the middle row");
+        assert_eq!(row.chars().position(|c| c == '@'), Some(1 + 9 * 2), "{row}");
+        assert_eq!(screen.iter().filter(|l| l.contains('@')).count(), 1);
+        assert!(screen.get(9).is_some_and(|l| l.contains("Joined Terminal")));
+    }
+
+    #[test]
+    fn messages_and_keys_reach_the_client_until_it_disconnects() {
+        let mut game = GameState::create_test_world("Terminal".into());
+        let alice = game::spawn_player(&mut game, "Alice".into());
+        let (mut tui, mut server) = offline(game);
+
+        assert!(tui.key(KeyCode::Char('d')));
+        assert!(server.sent.try_recv().is_err(), "no character to move");
+
+        for msg in [
+            ServerMessage::PlayerID(alice),
+            ServerMessage::AccessDenied(Denied::WrongPassword),
+            ServerMessage::SpawnRejected("the roster is full".into()),
+        ] {
+            server.send.send(Message::Server(msg)).expect("sent");
+        }
+        assert_eq!(tui.poll_network(), Ok(()));
+        assert_eq!(tui.player, Some(alice));
+        let refusals: Vec<&str> = tui.lines.iter().rev().take(2).map(String::as_str).collect();
+        assert_eq!(
+            refusals,
+            [
+                "Could not join: the roster is full",
+                "Could not join: wrong password or invite code"
+            ]
+        );
+
+        assert!(tui.key(KeyCode::Char('d')));
+        assert!(matches!(
+            server.sent.try_recv(),
+            Ok(ClientMessage::Action(GameAction::Move(Direction::Right)))
+        ));
+        assert!(tui.key(KeyCode::Char('z')), "unbound keys do nothing");
+        assert!(server.sent.try_recv().is_err());
+        assert!(!tui.key(KeyCode::Esc), "Esc quits");
+
+        server
+            .fail
+            .send("the server went away".into())
+            .expect("sent");
+        assert_eq!(tui.poll_network(), Err("the server went away".into()));
+    }
+
+    #[test]
+    fn arguments_need_a_server_and_known_flags() {
+        let args = |args: &[&str]| {
+            parse_args(&args.iter().map(|&a| a.to_owned()).collect::<Vec<_>>())
+                .map(|options| options.name)
+        };
+        let id = iroh::SecretKey::from_bytes(&[1; 32]).public().to_string();
+        assert_eq!(args(&[&id]), Ok("Wanderer".into()));
+        assert_eq!(args(&[&id, "--name", "Alice"]), Ok("Alice".into()));
+        assert_eq!(args(&[]), Err(USAGE.into()));
+        assert!(args(&["nobody"]).is_err_and(|e| e.starts_with("invalid server id")));
+        assert_eq!(args(&[&id, "--name"]), Err("--name needs a value".into()));
+        assert!(args(&[&id, "--fast"]).is_err_and(|e| e.starts_with("unknown argument")));
+    }
+}
@@ -0,0 +1,166 @@
+//! Headless world generator.
+//!
+//! Generates a world from a seed and an optional biome file, and writes the
+//! `.world` file plus a PNG preview next to it, without starting the game:
+//!
+//! ```text
+//! cargo run --bin worldgen -- --name big --width 1024 --height 1024 --seed 42
+//! cargo run --bin worldgen -- --name woods --biome assets/biomes/forest.biome
+//! ```
+
+use gamik::game::fov::ExploredMap;
+use gamik::game::worldgen::{self, WorldGenConfig};
+use gamik::game::{self, Point};
+use gamik::{export, ui};
+
+use std::io::{self, Write as _};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: worldgen --name <name> [options]
+
+Options:
+  --seed <n>           World seed (default: random)
+  --width <n>          World width in tiles (overrides the biome file)
+  --height <n>         World height in tiles (overrides the biome file)
+  --biome <file>       Biome file with generator settings
+  --out <file>         Output path (default: worlds/<name>.world)
+  --preview-scale <n>  Pixels per tile in the preview (default: 1)
+  --no-preview         Do not write a PNG preview";
+
+/// Parsed command line.
+struct Args {
+    name: String,
+    seed: Option<u64>,
+    width: Option<u32>,
+    height: Option<u32>,
+    biome: Option<PathBuf>,
+    out: Option<PathBuf>,
+    preview_scale: usize,
+    preview: bool,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Self {
+            name: String::new(),
+            seed: None,
+            width: None,
+            height: None,
+            biome: None,
+            out: None,
+            preview_scale: 1,
+            preview: true,
+        };
+
+        while let Some(flag) = args.next() {
+            if flag == "--no-preview" {
+                parsed.preview = false;
+                continue;
+            }
+            let value = args
+                .next()
+                .ok_or_else(|| format!("missing value for {flag}"))?;
+            let number = |what: &str| -> Result<u64, String> {
+                value
+                    .parse()
+                    .map_err(|e| format!("invalid {what} `{value}` ({e})"))
+            };
+            match flag.as_str() {
+                "--name" => parsed.name = value.clone(),
+                "--seed" => parsed.seed = Some(number("seed")?),
+                "--width" => parsed.width = Some(side(number("width")?)?),
+                "--height" => parsed.height = Some(side(number("height")?)?),
+                "--biome" => parsed.biome = Some(PathBuf::from(&value)),
+                "--out" => parsed.out = Some(PathBuf::from(&value)),
+                "--preview-scale" => {
+                    parsed.preview_scale =
+                        usize::try_from(number("preview scale")?).map_err(|e| e.to_string())?;
+                }
+                _ => return Err(format!("unknown option {flag}")),
+            }
+        }
+
+        if parsed.name.is_empty() {
+            return Err("--name is required".into());
+        }
+        Ok(parsed)
+    }
+}
+
+fn side(value: u64) -> Result<u32, String> {
+    u32::try_from(value).map_err(|e| format!("invalid world size ({e})"))
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let mut config = match &args.biome {
+        Some(path) => {
+            let source = std::fs::read_to_string(path)
+                .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+            WorldGenConfig::parse(&source).map_err(|e| format!("{}: {e}", path.display()))?
+        }
+        None => WorldGenConfig::default(),
+    };
+    config.width = args.width.unwrap_or(config.width);
+    config.height = args.height.unwrap_or(config.height);
+    config.validate()?;
+
+    let seed = args
+        .seed
+        .unwrap_or_else(|| game::WorldId::generate().0 as u64);
+    let state = worldgen::generate(args.name.clone(), seed, &config);
+
+    let out = args
+        .out
+        .unwrap_or_else(|| PathBuf::from("worlds").join(format!("{}.world", args.name)));
+    if let Some(parent) = out.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("failed to create {}: {e}", parent.display()))?;
+    }
+    game::save_to_path(&state, &out)
+        .map_err(|e| format!("failed to write {}: {e}", out.display()))?;
+    writeln!(
+        io::stdout(),
+        "Generated {} ({}×{}, seed {seed}, {} entities) → {}",
+        state.world_id,
+        config.width,
+        config.height,
+        state.entities().len(),
+        out.display()
+    )
+    .ok();
+
+    if args.preview {
+        let mut explored = ExploredMap::default();
+        let max = Point {
+            x: config.width as i32 - 1,
+            y: config.height as i32 - 1,
+        };
+        explored.reveal(Point { x: 0, y: 0 }, max, state.entities());
+        let image = ui::map_image(&explored, args.preview_scale)
+            .ok_or("preview too large; lower --preview-scale or use --no-preview")?;
+        let preview = out.with_extension("png");
+        export::save_png(&preview, &image)
+            .map_err(|e| format!("failed to write {}: {e}", preview.display()))?;
+        writeln!(io::stdout(), "Preview → {}", preview.display()).ok();
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            writeln!(io::stderr(), "{e}\n\n{USAGE}").ok();
+            return ExitCode::FAILURE;
+        }
+    };
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            writeln!(io::stderr(), "worldgen: {e}").ok();
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! Offline checks for saved worlds.
+//!
+//! ```text
+//! cargo run --bin worldtool -- validate worlds/big.world
+//! cargo run --bin worldtool -- repair worlds/big.world
+//! cargo run --bin worldtool -- manifest worlds/big.world > big.biome
+//! cargo run --bin worldtool -- upgrade worlds/*.world
+//! ```
+//!
+//! `repair` keeps the original file as `<file>.bak` unless `--out` is given;
+//! `upgrade` always does.
+
+use gamik::game::{self, persist};
+
+use std::io::{self, Write as _};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage:
+  worldtool validate <file>...            Report problems in saved worlds
+  worldtool repair <file> [--out <file>]  Fix what can be fixed and save
+  worldtool manifest <file>               Print the seed and biome a world was generated from
+  worldtool upgrade <file>...             Repair and rewrite worlds in the newest format";
+
+/// Print the problems in each world. Fails if any world is broken or
+/// cannot be read.
+fn validate(files: &[String]) -> Result<(), String> {
+    let mut broken = 0;
+    for file in files {
+        let state = game::load_from_file(Path::new(file))
+            .map_err(|e| format!("failed to load {file}: {e}"))?;
+        let issues = persist::validate(&state);
+        if issues.is_empty() {
+            writeln!(io::stdout(), "{file}: ok").ok();
+        } else {
+            broken += 1;
+            writeln!(io::stdout(), "{file}: {} issue(s)", issues.len()).ok();
+            for issue in &issues {
+                writeln!(io::stdout(), "  {issue}").ok();
+            }
+        }
+    }
+    match broken {
+        0 => Ok(()),
+        n => Err(format!("{n} world(s) need repair")),
+    }
+}
+
+/// Repair `file` and save it to `out`, or in place with a backup.
+fn repair(file: &str, out: Option<PathBuf>) -> Result<(), String> {
+    let path = Path::new(file);
+    let mut state =
+        game::load_from_file(path).map_err(|e| format!("failed to load {file}: {e}"))?;
+    let fixed = persist::repair(&mut state);
+    for issue in &fixed {
+        writeln!(io::stdout(), "fixed: {issue}").ok();
+    }
+
+    let out = match out {
+        Some(out) => out,
+        None if fixed.is_empty() => {
+            writeln!(io::stdout(), "{file}: nothing to repair").ok();
+            return Ok(());
+        }
+        None => {
+            let backup = path.with_extension("world.bak");
+            std::fs::copy(path, &backup)
+                .map_err(|e| format!("failed to back up to {}: {e}", backup.display()))?;
+            writeln!(io::stdout(), "original kept as {}", backup.display()).ok();
+            path.to_path_buf()
+        }
+    };
+    game::save_to_path(&state, &out)
+        .map_err(|e| format!("failed to write {}: {e}", out.display()))?;
+    writeln!(
+        io::stdout(),
+        "{} issue(s) repaired → {}",
+        fixed.len(),
+        out.display()
+    )
+    .ok();
+    Ok(())
+}
+
+/// Print what `file` was generated from, as a biome file.
+fn manifest(file: &str) -> Result<(), String> {
+    let state =
+        game::load_from_file(Path::new(file)).map_err(|e| format!("failed to load {file}: {e}"))?;
+    let manifest = state
+        .gen_manifest()
+        .ok_or_else(|| format!("{file} was not generated from a seed"))?;
+    write!(io::stdout(), "{manifest}").ok();
+    Ok(())
+}
+
+/// Upgrade each world in place, keeping the original as a backup, and print
+/// what changed. Fails if any world could not be upgraded.
+fn upgrade(files: &[String]) -> Result<(), String> {
+    let mut failed = 0;
+    for file in files {
+        let path = Path::new(file);
+        let backup = path.with_extension("world.bak");
+        let upgraded = std::fs::copy(path, &backup)
+            .map_err(|e| format!("failed to back up to {}: {e}", backup.display()))
+            .and_then(|_| persist::upgrade(path, path).map_err(|e| e.to_string()));
+        match upgraded {
+            Ok(upgrade) => {
+                writeln!(io::stdout(), "{file}: {upgrade}").ok();
+            }
+            Err(e) => {
+                failed += 1;
+                writeln!(io::stdout(), "{file}: {e}").ok();
+            }
+        }
+    }
+    match failed {
+        0 => Ok(()),
+        n => Err(format!("{n} world(s) could not be upgraded")),
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    match args {
+        [command, files @ ..] if command == "validate" && !files.is_empty() => validate(files),
+        [command, file] if command == "repair" => repair(file, None),
+        [command, file] if command == "manifest" => manifest(file),
+        [command, files @ ..] if command == "upgrade" && !files.is_empty() => upgrade(files),
+        [command, file, flag, out] if command == "repair" && flag == "--out" => {
+            repair(file, Some(PathBuf::from(out)))
+        }
+        _ => Err(USAGE.to_owned()),
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            writeln!(io::stderr(), "worldtool: {e}").ok();
+            ExitCode::FAILURE
+        }
+    }
+}
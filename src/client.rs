@@ -0,0 +1,983 @@
+//! The client core shared by the frontends, free of any UI toolkit.
+//!
+//! The window client, the terminal one (the `tui` binary) and the
+//! [bots](crate::net::bot) differ only in how they draw and read input.
+//! They [`connect`] the same way, keep their copy of the world in step with
+//! [`WorldSync`], which also picks up the local cache of a world on
+//! joining, turn keys into actions with [`Control`], and work out what the
+//! player sees with [`Sight`], so a glyph drawn in one is drawn in the
+//! other. An [`Explorer`] walks the player out to the edge of what they
+//! have seen, and a [`Trail`] remembers where they walked. The server's
+//! answers while joining are read with [`join_reply`], and files moving
+//! to and from it with [`Transfers`].
+
+use crate::game::fov::{self, Awareness, AwarenessDiff, ExploredMap, OpaqueSet, PlayerFov};
+use crate::game::{
+    self, AiBehavior, ContentRegistry, Direction, Entity, EntityID, EntityMap, GameAction,
+    GameState, PlayerKey, Point, PortableCharacter, SpatialIndex, TileMap, Wait, WorldId, dialogue,
+    intent, math, pack, path,
+};
+use crate::net::recording::{self, Recording};
+use crate::net::{
+    ClientMessage, Denied, Message, ServerMessage, SnapshotAssembler, WorldCache, WorldDelta,
+    run_client_internal,
+};
+
+use iroh::{EndpointAddr, SecretKey};
+use rustc_hash::FxHashSet;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot};
+
+/// Seconds [auto-explore](Explorer) waits for a step to be taken before it
+/// gives up.
+pub const EXPLORE_PATIENCE_SECONDS: f64 = 2.0;
+
+/// Tiles a [`Trail`] remembers.
+pub const TRAIL_LENGTH: usize = 48;
+
+/// Where exported characters are saved and imports are looked for.
+pub const CHARACTERS_DIR: &str = "characters";
+
+/// A connection to a server, as channels; the networking runs in a task.
+#[derive(Debug)]
+pub struct Connection {
+    pub tx: mpsc::UnboundedSender<ClientMessage>,
+    pub rx: mpsc::UnboundedReceiver<Message>,
+    /// Answers with the reason once the connection failed or closed.
+    pub failed: oneshot::Receiver<String>,
+}
+
+/// Connect to `addr` as `player`, from within a Tokio runtime, from an
+/// endpoint bound with `key` if given; see [`ServerBuilder::host`].
+///
+/// [`ServerBuilder::host`]: crate::server::ServerBuilder::host
+pub fn connect(
+    addr: impl Into<EndpointAddr>,
+    player: PlayerKey,
+    key: Option<SecretKey>,
+) -> Connection {
+    let (msg_tx, msg_rx) = mpsc::unbounded_channel();
+    let (event_tx, event_rx) = mpsc::unbounded_channel();
+
+    // Identify first, so spawning already adds to our roster.
+    event_tx.send(ClientMessage::Identify(player)).ok();
+
+    let addr = addr.into();
+    let (failed_tx, failed_rx) = oneshot::channel();
+    tokio::spawn(async move {
+        if let Err(e) = run_client_internal(addr, key, msg_tx, event_rx).await {
+            failed_tx.send(e.to_string()).ok();
+        }
+    });
+    Connection {
+        tx: event_tx,
+        rx: msg_rx,
+        failed: failed_rx,
+    }
+}
+
+/// Like [`connect`], calling `wake` whenever something arrives that may
+/// change what is shown, and once the connection fails.
+///
+/// A frontend can sleep until then instead of polling every frame. Empty
+/// deltas do not wake; they are picked up with the next message or frame.
+pub fn connect_waking(
+    addr: impl Into<EndpointAddr>,
+    player: PlayerKey,
+    key: Option<SecretKey>,
+    wake: impl Fn() + Send + Sync + 'static,
+) -> Connection {
+    let mut connection = connect(addr, player, key);
+    let wake = Arc::new(wake);
+    let (msg_tx, msg_rx) = mpsc::unbounded_channel();
+    let mut inbound = std::mem::replace(&mut connection.rx, msg_rx);
+    let wake_on_message = wake.clone();
+    tokio::spawn(async move {
+        while let Some(msg) = inbound.recv().await {
+            let quiet = matches!(&msg, Message::Server(ServerMessage::Delta(d)) if d.is_empty());
+            if msg_tx.send(msg).is_err() {
+                return;
+            }
+            if !quiet {
+                wake_on_message();
+            }
+        }
+    });
+    let (failed_tx, failed_rx) = oneshot::channel();
+    let failed = std::mem::replace(&mut connection.failed, failed_rx);
+    tokio::spawn(async move {
+        if let Ok(reason) = failed.await {
+            failed_tx.send(reason).ok();
+            wake();
+        }
+    });
+    connection
+}
+
+/// What a world message changed in the client's copy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Applied {
+    /// The whole world was replaced.
+    Replaced,
+    /// A delta for `tick` was applied, adding, changing or removing
+    /// `touched`.
+    Changed { tick: u64, touched: Vec<EntityID> },
+    /// Snapshot parts went missing; ask for them again.
+    Missing { tick: u64, missing: Vec<u32> },
+    /// Nothing yet: a snapshot is still arriving.
+    Pending,
+    /// The terrain of a chunk arrived.
+    Terrain,
+}
+
+/// What a client joining a world already has of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Start {
+    /// The whole world, as of this tick, from before a reconnect.
+    Held(u64),
+    /// The local cache of the world, as of this tick.
+    Cached(u64),
+    /// Nothing; the server sends a snapshot.
+    Fresh,
+}
+
+impl Start {
+    pub fn since_tick(self) -> Option<u64> {
+        match self {
+            Self::Held(tick) | Self::Cached(tick) => Some(tick),
+            Self::Fresh => None,
+        }
+    }
+
+    /// The reply to [`ServerMessage::WorldInfo`], asking for what is
+    /// missing, with the server's join `secret` if it has one.
+    pub fn sync(self, secret: Option<String>) -> ClientMessage {
+        ClientMessage::Sync {
+            since_tick: self.since_tick(),
+            packs: pack::active(),
+            secret,
+        }
+    }
+}
+
+/// Keeps the client's copy of the world in step with the snapshots and
+/// deltas the server sends, assembling chunked snapshots on the way.
+#[derive(Debug, Default)]
+pub struct WorldSync {
+    loading: Option<SnapshotAssembler>,
+    /// Whether the world held is complete, as of its tick.
+    loaded: bool,
+}
+
+impl WorldSync {
+    /// The chunked snapshot being received, if any.
+    pub fn loading(&self) -> Option<&SnapshotAssembler> {
+        self.loading.as_ref()
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.loading.is_some()
+    }
+
+    /// Whether `game` holds a complete copy of the server's world.
+    pub fn is_loaded(&self) -> bool {
+        self.loaded
+    }
+
+    /// Switch `game` to the world `id`, named `name`, as the server
+    /// announced it. Unless `game` holds all of it already, it is replaced
+    /// with the local cache of the world, if any. A `partial` world, from a
+    /// limited view, is never kept.
+    pub fn join(
+        &mut self,
+        game: &mut GameState,
+        id: WorldId,
+        name: String,
+        partial: bool,
+    ) -> Start {
+        let start = if self.loaded && game.world_id == id && !partial {
+            Start::Held(game.tick)
+        } else if let Some(cache) = WorldCache::load(id) {
+            game.set_entities(cache.entities);
+            game.tick = cache.tick;
+            Start::Cached(cache.tick)
+        } else {
+            Start::Fresh
+        };
+        if game.world_id != id {
+            // The server sends the terrain after announcing the world.
+            game.terrain = TileMap::default();
+        }
+        game.world_id = id;
+        game.world_name = name;
+        self.loaded = start != Start::Fresh;
+        start
+    }
+
+    /// The world to keep on disk, so rejoining only needs the changes;
+    /// `None` while it is incomplete, or `partial`.
+    pub fn cache(&self, game: &GameState, partial: bool) -> Option<WorldCache> {
+        (self.loaded && !self.is_loading() && !partial).then(|| WorldCache {
+            world_id: game.world_id,
+            tick: game.tick,
+            entities: game.entities().clone(),
+        })
+    }
+
+    /// Apply `msg` to `game` if it is about the world.
+    ///
+    /// # Errors
+    /// Hands back any other message.
+    pub fn receive(
+        &mut self,
+        msg: ServerMessage,
+        game: &mut GameState,
+    ) -> Result<Applied, ServerMessage> {
+        let (tick, entities) = match msg {
+            ServerMessage::Snapshot { tick, entities } => {
+                self.loading = None;
+                (tick, entities)
+            }
+            ServerMessage::SnapshotManifest { tick, parts, .. } => {
+                self.loading = Some(SnapshotAssembler::new(tick, parts));
+                return Ok(Applied::Pending);
+            }
+            ServerMessage::SnapshotPart { tick, index, bytes } => {
+                let Some(assembler) = &mut self.loading else {
+                    return Ok(Applied::Pending);
+                };
+                assembler.insert(tick, index, bytes);
+                let Some(finished) = assembler.finish() else {
+                    return Ok(Applied::Pending);
+                };
+                self.loading = None;
+                finished
+            }
+            ServerMessage::Delta(delta) => return Ok(self.delta(delta, game)),
+            ServerMessage::Terrain { chunk, tiles } => {
+                game.terrain.insert_chunk(chunk, tiles);
+                return Ok(Applied::Terrain);
+            }
+            other => return Err(other),
+        };
+        game.set_entities(entities);
+        game.tick = tick;
+        self.loaded = true;
+        Ok(Applied::Replaced)
+    }
+
+    fn delta(&mut self, delta: WorldDelta, game: &mut GameState) -> Applied {
+        if let Some(assembler) = &mut self.loading {
+            let tick = assembler.tick();
+            return match assembler.defer(delta) {
+                Some(missing) => Applied::Missing { tick, missing },
+                None => Applied::Pending,
+            };
+        }
+        let touched: Vec<EntityID> = delta.touched().collect();
+        game.edit_entities(touched.iter().copied(), |entities| delta.apply_to(entities));
+        game.tick = delta.tick;
+        Applied::Changed {
+            tick: delta.tick,
+            touched,
+        }
+    }
+}
+
+/// Where joining a server stands, from its answer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Joined {
+    /// A new connection opened; the server's first message on it.
+    Connected,
+    /// The server gave us this character to play, new or resumed.
+    Playing(EntityID),
+    /// The session was resumed with no character picked yet.
+    Resumed,
+    /// The server would not let us in, or not spawn us, for `reason`. With
+    /// `secret`, a password or invite code may get us in.
+    Refused { reason: String, secret: bool },
+    /// The server is full; we wait in line at this place, 1 being next.
+    Queued(u32),
+    /// Our turn in line came; what was asked for while waiting went
+    /// unanswered.
+    Admitted,
+    /// The server forgot us while we were away; say who we are and pick a
+    /// character again.
+    Forgotten,
+}
+
+/// Read `msg` if it is about joining the server.
+///
+/// # Errors
+/// Hands back any other message.
+pub fn join_reply(msg: ServerMessage) -> Result<Joined, ServerMessage> {
+    let refused = |reason: String, secret| Joined::Refused { reason, secret };
+    Ok(match msg {
+        ServerMessage::Session(_) => Joined::Connected,
+        ServerMessage::PlayerID(eid) | ServerMessage::Resumed(Some(eid)) => Joined::Playing(eid),
+        ServerMessage::Resumed(None) => Joined::Resumed,
+        ServerMessage::SpawnRejected(reason) => refused(reason, false),
+        ServerMessage::PacksRejected(missing) => refused(pack::describe_missing(&missing), false),
+        ServerMessage::AccessDenied(denied) => refused(
+            denied.to_string(),
+            matches!(denied, Denied::PasswordRequired | Denied::WrongPassword),
+        ),
+        ServerMessage::Queued(position) => Joined::Queued(position),
+        ServerMessage::Admitted => Joined::Admitted,
+        ServerMessage::ResumeRejected => Joined::Forgotten,
+        other => return Err(other),
+    })
+}
+
+/// What a player asks for with a key, whatever the frontend reads keys
+/// from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    Move(Direction),
+    /// Save the world, when hosting.
+    Save,
+    /// Talk to the nearest character with something to say.
+    Talk,
+    /// Attack the nearest thing with health.
+    Attack,
+    /// Chop the nearest tree.
+    Chop,
+    /// Cancel the queued action.
+    Cancel,
+    /// Pass a turn.
+    Wait,
+}
+
+impl Control {
+    /// The action asking for this, for `player`; `None` if there is
+    /// nothing in range to do it to.
+    pub fn action(self, entities: &EntityMap, player: EntityID) -> Option<GameAction> {
+        let nearest = |filter: fn(&Entity) -> bool| nearest_in_range(entities, player, filter);
+        Some(match self {
+            Self::Move(direction) => GameAction::Move(direction),
+            Self::Save => GameAction::SaveWorld,
+            Self::Talk => GameAction::Talk(nearest(|e| e.dialogue.is_some())?),
+            Self::Attack => GameAction::Attack(nearest(|e| e.health.is_some())?),
+            Self::Chop => GameAction::Queue(intent::Intent::Chop(nearest(intent::choppable)?)),
+            Self::Cancel => GameAction::Cancel,
+            Self::Wait => GameAction::Wait(Wait::Turns(1)),
+        })
+    }
+}
+
+/// The entity nearest `player` that `filter` accepts, within reach for
+/// talking or trading.
+pub fn nearest_in_range(
+    entities: &EntityMap,
+    player: EntityID,
+    filter: impl Fn(&Entity) -> bool,
+) -> Option<EntityID> {
+    let position = entities.get(&player)?.position;
+    entities
+        .iter()
+        .filter(|(eid, entity)| {
+            **eid != player && filter(entity) && dialogue::in_range(position, entity.position)
+        })
+        .min_by_key(|(_, entity)| math::manhattan(entity.position, position))
+        .map(|(eid, _)| *eid)
+}
+
+/// What the local player sees and is aware of, kept up to date
+/// incrementally as the world changes.
+#[derive(Debug, Clone)]
+pub struct Sight {
+    /// Field of view of the character followed.
+    pub fov: PlayerFov,
+    /// Sight blockers in the world.
+    pub opaque: OpaqueSet,
+    /// Entities by tile, following the deltas applied.
+    pub tiles: SpatialIndex,
+    /// Entities that changed tile in the latest look, or `None` if the
+    /// index was rebuilt.
+    moved: Option<Vec<EntityID>>,
+    pub awareness: Awareness,
+    /// Entities that came into and left the awareness in the latest
+    /// notice.
+    pub noticed: AwarenessDiff,
+}
+
+impl Default for Sight {
+    fn default() -> Self {
+        Self {
+            fov: PlayerFov::new(EntityID(0)),
+            opaque: OpaqueSet::default(),
+            tiles: SpatialIndex::default(),
+            moved: None,
+            awareness: Awareness::default(),
+            noticed: AwarenessDiff::default(),
+        }
+    }
+}
+
+impl Sight {
+    /// Follow `player` from now on, keeping the view radius. Returns the
+    /// character followed before, if it was another.
+    pub fn follow(&mut self, player: EntityID) -> Option<EntityID> {
+        let previous = self.fov.entity_id;
+        if previous == player {
+            return None;
+        }
+        let radius = self.fov.radius;
+        self.fov = PlayerFov::new(player);
+        self.fov.radius = radius;
+        Some(previous)
+    }
+
+    /// Note entities a delta added, changed or removed.
+    pub fn note(&mut self, touched: &[EntityID]) {
+        self.tiles.note(touched.iter().copied());
+    }
+
+    /// Start over after the whole world was replaced.
+    pub fn invalidate(&mut self) {
+        self.tiles.invalidate();
+    }
+
+    /// Cast the view again on the next look, after terrain arrived.
+    pub fn terrain_changed(&mut self) {
+        self.fov.invalidate();
+    }
+
+    /// Bring the field of view up to date with `entities`, over `terrain`,
+    /// looking only at the tiles noted entities were on or moved to.
+    /// Returns `true` if it had to be recomputed.
+    pub fn look(&mut self, entities: &EntityMap, terrain: &TileMap) -> bool {
+        self.moved = self.tiles.sync(entities);
+        self.opaque.update(entities, &self.tiles);
+        self.fov.update(entities, &self.opaque, terrain)
+    }
+
+    /// Bring the awareness up to date with the latest look, whose view
+    /// changed if `view_changed`, and return it. What changed is kept in
+    /// [`noticed`](Self::noticed).
+    pub fn notice(&mut self, view_changed: bool) -> Vec<EntityID> {
+        self.noticed = self.awareness.update(
+            &self.tiles,
+            self.moved.as_deref(),
+            &self.fov.mask,
+            view_changed,
+            fov::AWARENESS_MARGIN,
+        );
+        self.awareness.iter().collect()
+    }
+}
+
+/// Returns `true` if `other` is a danger to `player`: it hunts players, or
+/// its faction is hostile to them.
+pub fn threatens(player: &Entity, other: &Entity) -> bool {
+    other.ai == Some(AiBehavior::ChaseNearestPlayer)
+        || other
+            .faction
+            .as_deref()
+            .is_some_and(|faction| player.reputation.is_hostile(faction))
+}
+
+/// Why [auto-explore](Explorer) stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExploreStop {
+    /// Every tile within reach was seen.
+    Done,
+    /// Someone or something new came into view.
+    Noticed(EntityID),
+    /// Something that [`threatens`] the player is in view.
+    Danger(EntityID),
+    /// The player lost health.
+    Hurt,
+    /// No step could be taken for [`EXPLORE_PATIENCE_SECONDS`].
+    Stuck,
+}
+
+/// Auto-explore: walks the player a step at a time towards the nearest
+/// [frontier](ExploredMap::nearest_frontier), over and over, until there is
+/// none left or something needs their attention.
+#[derive(Debug, Clone)]
+pub struct Explorer {
+    /// Entities the player was already aware of.
+    known: FxHashSet<EntityID>,
+    /// The player's health when last polled.
+    health: Option<u32>,
+    /// Where the player was when the last step was sent, and when.
+    stepped: Option<(Point, f64)>,
+}
+
+impl Explorer {
+    /// Start exploring with what the player followed by `sight` is aware of
+    /// now, so only what comes into view later stops it.
+    pub fn start(state: &GameState, sight: &Sight) -> Self {
+        Self {
+            known: sight.awareness.iter().collect(),
+            health: state
+                .entities()
+                .get(&sight.fov.entity_id)
+                .and_then(|e| e.health)
+                .map(|h| h.current),
+            stepped: None,
+        }
+    }
+
+    /// The next step at time `now` (in seconds), if the last one was taken,
+    /// or why exploring stops. `explored` is what the player has seen.
+    ///
+    /// # Errors
+    /// With why exploring stops: the player was hurt, saw someone, or has
+    /// nothing left to explore.
+    pub fn poll(
+        &mut self,
+        state: &GameState,
+        sight: &Sight,
+        explored: &ExploredMap,
+        now: f64,
+    ) -> Result<Option<GameAction>, ExploreStop> {
+        let player = sight.fov.entity_id;
+        let entity = state.entities().get(&player).ok_or(ExploreStop::Done)?;
+        let health = entity.health.map(|h| h.current);
+        if health < self.health {
+            return Err(ExploreStop::Hurt);
+        }
+        self.health = health;
+        for eid in sight.awareness.iter().filter(|eid| *eid != player) {
+            let Some(other) = state.entities().get(&eid) else {
+                continue;
+            };
+            if threatens(entity, other) {
+                return Err(ExploreStop::Danger(eid));
+            }
+            if self.known.insert(eid) && !other.entity_type.is_terrain() {
+                return Err(ExploreStop::Noticed(eid));
+            }
+        }
+
+        let position = entity.position;
+        if let Some((from, since)) = self.stepped
+            && from == position
+        {
+            return if now - since < EXPLORE_PATIENCE_SECONDS {
+                Ok(None)
+            } else {
+                Err(ExploreStop::Stuck)
+            };
+        }
+        let goal = explored
+            .nearest_frontier(&state.terrain, position)
+            .ok_or(ExploreStop::Done)?;
+        let next = game::pathfind(state, position, goal)
+            .and_then(|route| route.first().copied())
+            .ok_or(ExploreStop::Stuck)?;
+        let direction = path::direction_towards(position, next).ok_or(ExploreStop::Stuck)?;
+        self.stepped = Some((position, now));
+        Ok(Some(GameAction::Move(direction)))
+    }
+}
+
+/// The last [`TRAIL_LENGTH`] tiles the player walked over, drawn as
+/// breadcrumbs behind them.
+#[derive(Debug, Clone, Default)]
+pub struct Trail {
+    tiles: VecDeque<Point>,
+}
+
+impl Trail {
+    /// Note the player standing on `position`.
+    pub fn follow(&mut self, position: Point) {
+        if self.tiles.back() == Some(&position) {
+            return;
+        }
+        self.tiles.push_back(position);
+        if self.tiles.len() > TRAIL_LENGTH {
+            self.tiles.pop_front();
+        }
+    }
+
+    /// Forget the trail, for another character or world.
+    pub fn clear(&mut self) {
+        self.tiles.clear();
+    }
+
+    /// The tiles walked over, oldest first, ending with the one stood on.
+    pub fn tiles(&self) -> impl ExactSizeIterator<Item = Point> + '_ {
+        self.tiles.iter().copied()
+    }
+}
+
+/// What came of moving a file to or from the server, to show the player.
+#[derive(Debug, Clone)]
+pub enum Transfer {
+    /// Outcome of exporting the character to [`CHARACTERS_DIR`].
+    Exported(String),
+    /// The server saved the world to the slot with this name.
+    SlotSaved(String),
+    /// Why the world could not be saved to a slot.
+    SlotRejected(String),
+    /// The items an imported character had to leave behind, as a line
+    /// for the log.
+    LeftBehind(String),
+    /// Names of the recordings saved on the server.
+    Recordings(Vec<String>),
+    /// Progress or outcome of a recording download, and the request for
+    /// its next part, if any.
+    Download {
+        status: String,
+        next: Option<ClientMessage>,
+    },
+    /// Nothing to show: a part of no download of ours, or an import that
+    /// kept everything.
+    Nothing,
+}
+
+/// Character exports, save slots, imports and recording downloads, as the
+/// server answers them.
+#[derive(Debug, Default)]
+pub struct Transfers {
+    /// Recording being downloaded: its name, the next part expected and
+    /// the bytes so far.
+    download: Option<(String, u32, Vec<u8>)>,
+}
+
+impl Transfers {
+    pub fn is_downloading(&self) -> bool {
+        self.download.is_some()
+    }
+
+    /// Start downloading the recording `name`, returning the request for
+    /// its first part; `None` while another download runs.
+    pub fn fetch(&mut self, name: &str) -> Option<ClientMessage> {
+        if self.is_downloading() {
+            return None;
+        }
+        self.download = Some((name.to_owned(), 0, Vec::new()));
+        Some(ClientMessage::FetchRecording {
+            name: name.to_owned(),
+            part: 0,
+        })
+    }
+
+    /// Handle `msg` if it is about a file transfer, saving exported
+    /// characters and finished downloads on the way.
+    ///
+    /// # Errors
+    /// Hands back any other message.
+    pub fn receive(&mut self, msg: ServerMessage) -> Result<Transfer, ServerMessage> {
+        Ok(match msg {
+            ServerMessage::CharacterExport(text) => Transfer::Exported(save_character(&text)),
+            ServerMessage::ExportRejected(reason) => {
+                Transfer::Exported(format!("Could not export: {reason}"))
+            }
+            ServerMessage::SlotSaved(name) => Transfer::SlotSaved(name),
+            ServerMessage::SlotRejected(reason) => {
+                Transfer::SlotRejected(format!("Could not save: {reason}"))
+            }
+            ServerMessage::Imported { stripped } => left_behind(&stripped),
+            ServerMessage::Recordings(names) => Transfer::Recordings(names),
+            ServerMessage::RecordingPart {
+                name,
+                index,
+                parts,
+                bytes,
+            } => self.recording_part(&name, index, parts, &bytes),
+            ServerMessage::RecordingUnavailable(reason) => {
+                self.download = None;
+                Transfer::Download {
+                    status: format!("Download failed: {reason}"),
+                    next: None,
+                }
+            }
+            other => return Err(other),
+        })
+    }
+
+    /// Store a downloaded part of a recording and ask for the next one, or
+    /// save the recording once it is complete.
+    fn recording_part(&mut self, name: &str, index: u32, parts: u32, bytes: &[u8]) -> Transfer {
+        let Some((downloading, next, buffer)) = &mut self.download else {
+            return Transfer::Nothing;
+        };
+        if downloading != name || *next != index {
+            return Transfer::Nothing;
+        }
+        buffer.extend_from_slice(bytes);
+        *next += 1;
+        if *next < parts {
+            return Transfer::Download {
+                status: format!("Downloading {name}: {next}/{parts}"),
+                next: Some(ClientMessage::FetchRecording {
+                    name: name.to_owned(),
+                    part: *next,
+                }),
+            };
+        }
+        let Some((_, _, buffer)) = self.download.take() else {
+            return Transfer::Nothing;
+        };
+        let status = if Recording::decode(&buffer).is_err() {
+            format!("Download of {name} is corrupt")
+        } else {
+            match recording::save_bytes(name, &buffer) {
+                Ok(path) => format!("Saved {}; watch it from the main menu", path.display()),
+                Err(e) => format!("Failed to save {name}: {e}"),
+            }
+        };
+        Transfer::Download { status, next: None }
+    }
+}
+
+/// Display name of an item, or its ID if the registry does not know it.
+pub fn item_name<'a>(registry: &'a ContentRegistry, id: &'a str) -> &'a str {
+    registry.item(id).map_or(id, |item| item.name.as_str())
+}
+
+/// The log line naming the items an imported character left behind.
+fn left_behind(stripped: &[(String, u32)]) -> Transfer {
+    if stripped.is_empty() {
+        return Transfer::Nothing;
+    }
+    let registry = ContentRegistry::builtin();
+    let items: Vec<String> = stripped
+        .iter()
+        .map(|(item, count)| format!("{count} {}", item_name(&registry, item)))
+        .collect();
+    Transfer::LeftBehind(format!(
+        "Left behind by this world's rules: {}",
+        items.join(", ")
+    ))
+}
+
+/// Write an exported character to [`CHARACTERS_DIR`] and describe the
+/// outcome.
+fn save_character(text: &str) -> String {
+    let name = PortableCharacter::from_ron(text).map_or_else(|_| String::new(), |c| c.name);
+    let mut stem: String = name
+        .chars()
+        .filter(|c| c.is_alphanumeric() || matches!(c, '-' | '_'))
+        .collect();
+    if stem.is_empty() {
+        stem = "character".to_owned();
+    }
+    let path = Path::new(CHARACTERS_DIR).join(format!("{stem}.character"));
+    match fs::create_dir_all(CHARACTERS_DIR).and_then(|()| fs::write(&path, text)) {
+        Ok(()) => format!("Saved {}", path.display()),
+        Err(e) => {
+            log::warn!("Failed to save {}: {e}", path.display());
+            format!("Failed to save {}: {e}", path.display())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::pack::PackManifest;
+    use crate::net::recording::RECORDINGS_DIR;
+
+    /// Part `index` of `parts` of the recording `name`.
+    fn part(name: &str, index: u32, parts: u32, bytes: &[u8]) -> ServerMessage {
+        ServerMessage::RecordingPart {
+            name: name.to_owned(),
+            index,
+            parts,
+            bytes: bytes.to_vec(),
+        }
+    }
+
+    /// The status of a download step, and whether it asks for the part
+    /// `next`.
+    fn download(transfer: Result<Transfer, ServerMessage>) -> (String, Option<u32>) {
+        match transfer {
+            Ok(Transfer::Download { status, next }) => {
+                let next = next.map(|request| match request {
+                    ClientMessage::FetchRecording { part, .. } => part,
+                    other => panic!("asked for {other:?}"),
+                });
+                (status, next)
+            }
+            other => panic!("not a download step: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn join_replies_say_where_joining_stands() {
+        let alice = EntityID(7);
+        assert_eq!(
+            join_reply(ServerMessage::PlayerID(alice)).ok(),
+            Some(Joined::Playing(alice))
+        );
+        assert_eq!(
+            join_reply(ServerMessage::Resumed(Some(alice))).ok(),
+            Some(Joined::Playing(alice))
+        );
+        assert_eq!(
+            join_reply(ServerMessage::Resumed(None)).ok(),
+            Some(Joined::Resumed)
+        );
+        assert_eq!(
+            join_reply(ServerMessage::Queued(3)).ok(),
+            Some(Joined::Queued(3))
+        );
+        assert_eq!(
+            join_reply(ServerMessage::Admitted).ok(),
+            Some(Joined::Admitted)
+        );
+        assert_eq!(
+            join_reply(ServerMessage::ResumeRejected).ok(),
+            Some(Joined::Forgotten)
+        );
+        assert!(matches!(
+            join_reply(ServerMessage::Dialogue(None)),
+            Err(ServerMessage::Dialogue(None))
+        ));
+    }
+
+    #[test]
+    fn refusals_say_why_and_whether_a_secret_helps() {
+        let refused = |msg| match join_reply(msg) {
+            Ok(Joined::Refused { reason, secret }) => (reason, secret),
+            other => panic!("not refused: {other:?}"),
+        };
+        assert_eq!(
+            refused(ServerMessage::AccessDenied(Denied::PasswordRequired)),
+            (Denied::PasswordRequired.to_string(), true)
+        );
+        assert!(refused(ServerMessage::AccessDenied(Denied::WrongPassword)).1);
+        for denied in [Denied::TooManyAttempts, Denied::ServerFull] {
+            let reason = denied.to_string();
+            assert_eq!(
+                refused(ServerMessage::AccessDenied(denied)),
+                (reason, false)
+            );
+        }
+        assert_eq!(
+            refused(ServerMessage::SpawnRejected("the roster is full".into())),
+            ("the roster is full".to_owned(), false)
+        );
+        let missing = vec![PackManifest {
+            id: "castles".into(),
+            hash: 1,
+        }];
+        let (reason, secret) = refused(ServerMessage::PacksRejected(missing));
+        assert!(
+            reason.starts_with("missing content packs: castles"),
+            "{reason}"
+        );
+        assert!(!secret);
+    }
+
+    #[test]
+    fn a_download_asks_for_each_part_and_saves_the_recording() {
+        let name = format!("client-test-{}", WorldId::generate());
+        let bytes = bitcode::encode(&Recording::start(&GameState::create_test_world(
+            "Downloaded".into(),
+        )));
+        let (first, rest) = bytes.split_at(bytes.len() / 2);
+        let mut transfers = Transfers::default();
+
+        assert!(matches!(
+            transfers.fetch(&name),
+            Some(ClientMessage::FetchRecording { part: 0, .. })
+        ));
+        assert!(
+            transfers.fetch("another").is_none(),
+            "one download at a time"
+        );
+
+        let (status, next) = download(transfers.receive(part(&name, 0, 2, first)));
+        assert_eq!(
+            (status.as_str(), next),
+            (&*format!("Downloading {name}: 1/2"), Some(1))
+        );
+        let (status, next) = download(transfers.receive(part(&name, 1, 2, rest)));
+        assert!(status.starts_with("Saved"), "{status}");
+        assert_eq!(next, None);
+        assert!(!transfers.is_downloading());
+
+        let saved = Recording::load(&name).expect("the download was saved");
+        assert_eq!(saved.world_name, "Downloaded");
+        fs::remove_file(Path::new(RECORDINGS_DIR).join(format!("{name}.recording"))).ok();
+        fs::remove_dir(RECORDINGS_DIR).ok();
+    }
+
+    #[test]
+    fn downloads_ignore_stray_parts_and_fail_cleanly() {
+        let mut transfers = Transfers::default();
+        assert!(
+            matches!(
+                transfers.receive(part("replay", 0, 1, b"x")),
+                Ok(Transfer::Nothing)
+            ),
+            "no download running"
+        );
+
+        transfers.fetch("replay");
+        for stray in [part("other", 0, 2, b"x"), part("replay", 1, 2, b"x")] {
+            assert!(matches!(transfers.receive(stray), Ok(Transfer::Nothing)));
+        }
+        let (status, next) = download(transfers.receive(part("replay", 0, 1, b"junk")));
+        assert_eq!(
+            (status.as_str(), next),
+            ("Download of replay is corrupt", None)
+        );
+        assert!(!transfers.is_downloading());
+
+        transfers.fetch("replay");
+        let (status, next) =
+            download(transfers.receive(ServerMessage::RecordingUnavailable("no such file".into())));
+        assert_eq!(
+            (status.as_str(), next),
+            ("Download failed: no such file", None)
+        );
+        assert!(transfers.fetch("replay").is_some(), "free to start again");
+
+        let mut transfers = Transfers::default();
+        let bytes = bitcode::encode(&Recording::start(&GameState::create_test_world(
+            "Misnamed".into(),
+        )));
+        transfers.fetch("not a name");
+        let (status, _) = download(transfers.receive(part("not a name", 0, 1, &bytes)));
+        assert!(status.starts_with("Failed to save not a name"), "{status}");
+    }
+
+    #[test]
+    fn transfers_describe_exports_slots_and_imports() {
+        let mut transfers = Transfers::default();
+        let mut receive = |msg| transfers.receive(msg);
+        assert!(matches!(
+            receive(ServerMessage::ExportRejected("no character".into())),
+            Ok(Transfer::Exported(status)) if status == "Could not export: no character"
+        ));
+        assert!(matches!(
+            receive(ServerMessage::SlotSaved("Before the war".into())),
+            Ok(Transfer::SlotSaved(name)) if name == "Before the war"
+        ));
+        assert!(matches!(
+            receive(ServerMessage::SlotRejected("disk full".into())),
+            Ok(Transfer::SlotRejected(status)) if status == "Could not save: disk full"
+        ));
+        assert!(matches!(
+            receive(ServerMessage::Imported {
+                stripped: Vec::new()
+            }),
+            Ok(Transfer::Nothing)
+        ));
+        assert!(matches!(
+            receive(ServerMessage::Imported {
+                stripped: vec![("pelt".into(), 2), ("relic".into(), 1)]
+            }),
+            Ok(Transfer::LeftBehind(line))
+                if line == "Left behind by this world's rules: 2 Wolf Pelt, 1 relic"
+        ));
+        assert!(matches!(
+            receive(ServerMessage::Recordings(vec!["siege".into()])),
+            Ok(Transfer::Recordings(names)) if names == ["siege"]
+        ));
+        assert!(matches!(
+            receive(ServerMessage::Admitted),
+            Err(ServerMessage::Admitted)
+        ));
+    }
+}
@@ -0,0 +1,621 @@
+//! Client configuration, persisted with eframe's storage.
+//!
+//! Holds the player's recorded action macros: short sequences of actions
+//! captured client-side and replayed one at a time. Replays are paced and
+//! capped here, and still go through the server's per-tick action limit.
+//!
+//! The [`UiSessionState`] is kept beside it: where the player was and what
+//! they had open, so the next start can pick up from there.
+
+use crate::alert::AlertConfig;
+use crate::game::fov::VIEW_RADIUS;
+use crate::game::{GameAction, PlayerColor, PlayerKey, persist};
+use crate::gamepad::GamepadConfig;
+use crate::keymap::Keymap;
+use crate::net::{WhenEmpty, presence, whisper};
+
+use serde::{Deserialize, Serialize};
+
+/// Storage key of the [`ClientConfig`].
+const CONFIG_KEY: &str = "gamik_client_config";
+
+/// Storage key of the [`UiSessionState`].
+const SESSION_KEY: &str = "gamik_ui_session";
+
+/// Layout version of the [`UiSessionState`]; a stored session of another
+/// version is dropped rather than misread.
+pub const SESSION_VERSION: u32 = 1;
+
+/// Map cell size, in points, before the player zooms.
+pub const DEFAULT_ZOOM: f32 = 14.0;
+
+/// Smallest and largest map cell size, in points.
+pub const MIN_ZOOM: f32 = 8.0;
+pub const MAX_ZOOM: f32 = 32.0;
+
+/// Most actions a single macro can hold.
+pub const MAX_MACRO_LEN: usize = 64;
+
+/// Most macros kept; recording another drops the oldest.
+pub const MAX_MACROS: usize = 9;
+
+/// Seconds between two replayed actions.
+pub const MACRO_STEP_SECONDS: f64 = 0.15;
+
+/// Most times a looping macro repeats before stopping on its own.
+pub const MAX_MACRO_LOOPS: u32 = 100;
+
+/// Most servers kept in the address book.
+pub const MAX_SERVERS: usize = 64;
+
+/// Most friends kept on the friends list.
+pub const MAX_FRIENDS: usize = 64;
+
+/// View radius asked of servers in low-power mode, at most.
+pub const LOW_POWER_VIEW_RADIUS: u32 = 8;
+
+/// When the client saves power: with fewer frames, no idle animations or
+/// debug overlays, and a view of at most [`LOW_POWER_VIEW_RADIUS`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LowPower {
+    /// While the machine runs on battery, if it can tell.
+    #[default]
+    Auto,
+    On,
+    Off,
+}
+
+impl LowPower {
+    pub const ALL: [Self; 3] = [Self::Auto, Self::On, Self::Off];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Auto => "While on battery",
+            Self::On => "Always",
+            Self::Off => "Never",
+        }
+    }
+
+    /// Whether to save power now; `on_battery` is whether the machine runs
+    /// on battery, if known.
+    pub fn active(self, on_battery: Option<bool>) -> bool {
+        match self {
+            Self::Auto => on_battery == Some(true),
+            Self::On => true,
+            Self::Off => false,
+        }
+    }
+}
+
+/// Settings that survive restarts of the client.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClientConfig {
+    pub macros: Vec<ActionMacro>,
+    /// Identity sent to servers, so they keep this player's characters
+    /// together. Picked on first use.
+    pub player_key: Option<PlayerKey>,
+    /// Color this player's characters are drawn in, for everyone; `None`
+    /// for the one their key gives.
+    pub player_color: Option<PlayerColor>,
+    /// Whether the compass, coordinates and world time are shown while
+    /// playing.
+    pub show_compass: bool,
+    /// The server browser's address book.
+    pub servers: Vec<SavedServer>,
+    /// What worlds this client hosts do while no one is connected.
+    pub when_empty: WhenEmpty,
+    /// Password joining worlds this client hosts needs; `None` for the one
+    /// saved with each world, if any. See [`access`](crate::net::access).
+    pub host_password: Option<String>,
+    /// View radius asked of servers, so they send only the world that near;
+    /// `None` for the whole world and the usual radius.
+    pub view_distance: Option<u32>,
+    /// Endpoint ID of the node saves are synced to, in builds with the
+    /// `backup-peer` feature; see `net::backup`.
+    pub backup_peer: Option<String>,
+    /// Identity friends add, as the bytes of its secret key; see
+    /// [`presence`]. Picked on first use.
+    pub presence_key: Option<[u8; 32]>,
+    /// Players whose presence is shown, and who may see ours.
+    pub friends: Vec<Friend>,
+    /// Players whose whispers servers drop, by name; see [`whisper`].
+    pub blocked: Vec<String>,
+    /// Whether crash reports include a recent snapshot of the world; see
+    /// [`crash`](crate::crash).
+    pub crash_snapshots: bool,
+    /// Backups kept of each world saved; `None` for
+    /// [`DEFAULT_SAVE_BACKUPS`](persist::DEFAULT_SAVE_BACKUPS).
+    pub save_backups: Option<u32>,
+    /// When to save power; see [`LowPower`].
+    pub low_power: LowPower,
+    /// Keys the player bound, by context; see [`keymap`](crate::keymap).
+    pub keymap: Keymap,
+    /// Stick tuning and button bindings; see [`gamepad`](crate::gamepad).
+    pub gamepad: GamepadConfig,
+    /// How hostile entities coming into view are announced, by type; see
+    /// [`alert`](crate::alert).
+    pub alerts: AlertConfig,
+}
+
+impl ClientConfig {
+    /// Load the config from eframe's storage, or the default if there is none.
+    pub fn load(storage: Option<&dyn eframe::Storage>) -> Self {
+        storage
+            .and_then(|storage| eframe::get_value(storage, CONFIG_KEY))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, CONFIG_KEY, self);
+    }
+
+    /// View radius to ask of servers, `None` for the whole world, cut down
+    /// to [`LOW_POWER_VIEW_RADIUS`] while saving power.
+    pub fn requested_view(&self, low_power: bool) -> Option<u32> {
+        if !low_power {
+            return self.view_distance;
+        }
+        let radius = self.view_distance.unwrap_or(VIEW_RADIUS.unsigned_abs());
+        Some(radius.min(LOW_POWER_VIEW_RADIUS))
+    }
+
+    /// This player's identity, picking one if there is none yet.
+    pub fn player_key(&mut self) -> PlayerKey {
+        *self.player_key.get_or_insert_with(PlayerKey::generate)
+    }
+
+    /// Backups kept of each world saved, at most
+    /// [`MAX_SAVE_BACKUPS`](persist::MAX_SAVE_BACKUPS).
+    pub fn save_backups(&self) -> u32 {
+        self.save_backups
+            .unwrap_or(persist::DEFAULT_SAVE_BACKUPS)
+            .min(persist::MAX_SAVE_BACKUPS)
+    }
+
+    /// This player's color, as picked or as their key gives it.
+    pub fn player_color(&mut self) -> PlayerColor {
+        match self.player_color {
+            Some(color) => color,
+            None => PlayerColor::of(self.player_key()),
+        }
+    }
+
+    /// This player's identity among friends, picking one if there is none
+    /// yet.
+    pub fn presence_key(&mut self) -> [u8; 32] {
+        *self.presence_key.get_or_insert_with(presence::generate_key)
+    }
+
+    /// Note that the server with endpoint ID `id` was joined at `now`, in
+    /// seconds since the Unix epoch.
+    pub fn played_server(&mut self, id: &str, now: u64) {
+        for server in self.servers.iter_mut().filter(|s| s.id == id) {
+            server.last_played = Some(now);
+        }
+    }
+
+    /// Block the player `name`, up to [`whisper::MAX_BLOCKED`] players.
+    pub fn block(&mut self, name: &str) -> Result<(), String> {
+        let key = whisper::name_key(name);
+        if self.blocked.iter().any(|b| whisper::name_key(b) == key) {
+            return Ok(());
+        }
+        if self.blocked.len() >= whisper::MAX_BLOCKED {
+            return Err(format!(
+                "you can block at most {} players",
+                whisper::MAX_BLOCKED
+            ));
+        }
+        self.blocked.push(name.trim().to_owned());
+        Ok(())
+    }
+
+    /// Unblock the player `name`. Returns `false` if they were not blocked.
+    pub fn unblock(&mut self, name: &str) -> bool {
+        let key = whisper::name_key(name);
+        let before = self.blocked.len();
+        self.blocked.retain(|b| whisper::name_key(b) != key);
+        self.blocked.len() < before
+    }
+
+    /// Keep a newly recorded macro, dropping the oldest beyond [`MAX_MACROS`].
+    pub fn add_macro(&mut self, action_macro: ActionMacro) {
+        self.macros.push(action_macro);
+        if self.macros.len() > MAX_MACROS {
+            self.macros.remove(0);
+        }
+    }
+}
+
+/// A player on the friends list.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Friend {
+    pub name: String,
+    /// Endpoint ID of their presence; see [`presence`].
+    pub id: String,
+}
+
+/// A server in the address book.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedServer {
+    pub name: String,
+    /// Endpoint ID of the server.
+    pub id: String,
+    /// When it was last joined, in seconds since the Unix epoch.
+    pub last_played: Option<u64>,
+}
+
+/// Where the player left off, saved whenever it changes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiSessionState {
+    /// [`SESSION_VERSION`] when saved.
+    pub version: u32,
+    /// Name of the world last hosted.
+    pub world: Option<String>,
+    /// Endpoint ID of the server last joined.
+    pub server: Option<String>,
+    pub character_sheet_open: bool,
+    pub map_open: bool,
+    pub console_open: bool,
+    /// Map cell size, in points.
+    pub zoom: f32,
+}
+
+impl Default for UiSessionState {
+    fn default() -> Self {
+        Self {
+            version: SESSION_VERSION,
+            world: None,
+            server: None,
+            character_sheet_open: false,
+            map_open: false,
+            console_open: false,
+            zoom: DEFAULT_ZOOM,
+        }
+    }
+}
+
+impl UiSessionState {
+    /// Load the session from eframe's storage, or a fresh one if there is
+    /// none.
+    pub fn load(storage: Option<&dyn eframe::Storage>) -> Self {
+        Self::restore(storage.and_then(|storage| eframe::get_value(storage, SESSION_KEY)))
+    }
+
+    pub fn save(&self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, SESSION_KEY, self);
+    }
+
+    /// The session to resume from `stored`, dropping it if it was saved
+    /// by another version.
+    fn restore(stored: Option<Self>) -> Self {
+        match stored {
+            Some(session) if session.version == SESSION_VERSION => Self {
+                zoom: session.zoom.clamp(MIN_ZOOM, MAX_ZOOM),
+                ..session
+            },
+            _ => Self::default(),
+        }
+    }
+
+    /// Returns `true` if there is a game to go back to.
+    pub fn can_resume(&self) -> bool {
+        self.world.is_some() || self.server.is_some()
+    }
+}
+
+/// A named sequence of recorded actions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActionMacro {
+    pub name: String,
+    pub actions: Vec<GameAction>,
+}
+
+/// Captures the actions the player sends while recording.
+#[derive(Debug, Default)]
+pub struct MacroRecorder {
+    actions: Option<Vec<GameAction>>,
+}
+
+impl MacroRecorder {
+    pub fn is_recording(&self) -> bool {
+        self.actions.is_some()
+    }
+
+    /// Number of actions recorded so far.
+    pub fn len(&self) -> usize {
+        self.actions.as_ref().map_or(0, Vec::len)
+    }
+
+    pub fn start(&mut self) {
+        self.actions = Some(Vec::new());
+    }
+
+    /// Record `action` if recording. Only in-game actions are kept, and
+    /// nothing past [`MAX_MACRO_LEN`].
+    pub fn record(&mut self, action: &GameAction) {
+        if let Some(actions) = &mut self.actions
+            && matches!(action, GameAction::Move(_))
+            && actions.len() < MAX_MACRO_LEN
+        {
+            actions.push(action.clone());
+        }
+    }
+
+    /// Stop recording. Returns the macro, unless nothing was recorded.
+    pub fn finish(&mut self, name: String) -> Option<ActionMacro> {
+        let actions = self.actions.take()?;
+        (!actions.is_empty()).then_some(ActionMacro { name, actions })
+    }
+}
+
+/// Replays a macro, one action every [`MACRO_STEP_SECONDS`].
+#[derive(Debug)]
+pub struct MacroPlayer {
+    actions: Vec<GameAction>,
+    next: usize,
+    looping: bool,
+    loops: u32,
+    next_at: f64,
+}
+
+impl MacroPlayer {
+    /// Start replaying `action_macro` at time `now` (in seconds), once or
+    /// in a loop.
+    pub fn new(action_macro: &ActionMacro, looping: bool, now: f64) -> Self {
+        Self {
+            actions: action_macro.actions.clone(),
+            next: 0,
+            looping,
+            loops: 0,
+            next_at: now,
+        }
+    }
+
+    /// The next action, if one is due at time `now`.
+    pub fn poll(&mut self, now: f64) -> Option<GameAction> {
+        if self.is_finished() || now < self.next_at {
+            return None;
+        }
+        let action = self.actions.get(self.next)?.clone();
+        self.next += 1;
+        self.next_at = now + MACRO_STEP_SECONDS;
+        if self.next == self.actions.len() && self.looping {
+            self.loops += 1;
+            if self.loops < MAX_MACRO_LOOPS {
+                self.next = 0;
+            }
+        }
+        Some(action)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.actions.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Direction;
+    use crate::net::view::MIN_VIEW_RADIUS;
+
+    fn walk(n: usize) -> ActionMacro {
+        ActionMacro {
+            name: "walk".into(),
+            actions: vec![GameAction::Move(Direction::Up); n],
+        }
+    }
+
+    #[test]
+    fn ui_sessions_fill_in_defaults_and_drop_other_versions() {
+        let stored: UiSessionState =
+            ron::from_str(r#"(version: 1, server: Some("abc"), zoom: 100.0)"#).expect("valid");
+        let session = UiSessionState::restore(Some(stored));
+        assert_eq!(session.server.as_deref(), Some("abc"));
+        assert!(session.zoom <= MAX_ZOOM);
+        assert!(!session.map_open);
+        assert!(session.can_resume());
+
+        let old: UiSessionState =
+            ron::from_str(r#"(version: 0, world: Some("woods"))"#).expect("valid");
+        assert_eq!(
+            UiSessionState::restore(Some(old)),
+            UiSessionState::default()
+        );
+        assert!(!UiSessionState::restore(None).can_resume());
+    }
+
+    #[test]
+    fn blocked_players_are_kept_once_whatever_their_case() {
+        let mut config = ClientConfig::default();
+        assert_eq!(config.block("Old Tom"), Ok(()));
+        assert_eq!(config.block(" old tom "), Ok(()));
+        assert_eq!(config.blocked, vec!["Old Tom".to_owned()]);
+
+        for n in 1..whisper::MAX_BLOCKED {
+            assert_eq!(config.block(&format!("pest {n}")), Ok(()));
+        }
+        assert!(config.block("one too many").is_err());
+
+        assert!(config.unblock("OLD TOM"));
+        assert!(!config.unblock("Old Tom"));
+        assert_eq!(config.block("one too many"), Ok(()));
+    }
+
+    #[test]
+    fn low_power_follows_the_battery_and_narrows_the_view() {
+        assert!(LowPower::Auto.active(Some(true)));
+        assert!(!LowPower::Auto.active(None));
+        assert!(LowPower::On.active(Some(false)));
+        assert!(!LowPower::Off.active(Some(true)));
+
+        let mut config = ClientConfig::default();
+        assert_eq!(config.requested_view(false), None);
+        assert_eq!(config.requested_view(true), Some(LOW_POWER_VIEW_RADIUS));
+        config.view_distance = Some(MIN_VIEW_RADIUS);
+        assert_eq!(config.requested_view(true), Some(MIN_VIEW_RADIUS));
+    }
+
+    #[test]
+    fn recorder_keeps_only_moves_up_to_the_limit() {
+        let mut recorder = MacroRecorder::default();
+        recorder.record(&GameAction::Move(Direction::Up));
+        assert!(!recorder.is_recording());
+
+        recorder.start();
+        recorder.record(&GameAction::SaveWorld);
+        for _ in 0..MAX_MACRO_LEN + 5 {
+            recorder.record(&GameAction::Move(Direction::Left));
+        }
+        let recorded = recorder.finish("left".into()).expect("recorded moves");
+        assert_eq!(recorded.actions.len(), MAX_MACRO_LEN);
+
+        recorder.start();
+        assert!(recorder.finish("empty".into()).is_none());
+    }
+
+    #[test]
+    fn player_paces_actions() {
+        let mut player = MacroPlayer::new(&walk(2), false, 10.0);
+        assert!(player.poll(10.0).is_some());
+        assert!(player.poll(10.05).is_none());
+        assert!(player.poll(10.0 + MACRO_STEP_SECONDS).is_some());
+        assert!(player.is_finished());
+        assert!(player.poll(100.0).is_none());
+    }
+
+    #[test]
+    fn looping_player_stops_after_max_loops() {
+        let mut player = MacroPlayer::new(&walk(1), true, 0.0);
+        let mut now = 0.0;
+        let mut sent = 0;
+        while player.poll(now).is_some() {
+            sent += 1;
+            now += MACRO_STEP_SECONDS;
+        }
+        assert_eq!(sent, MAX_MACRO_LOOPS);
+    }
+
+    #[test]
+    fn config_drops_oldest_macro() {
+        let mut config = ClientConfig::default();
+        for n in 1..=MAX_MACROS + 1 {
+            config.add_macro(walk(n));
+        }
+        assert_eq!(config.macros.len(), MAX_MACROS);
+        assert_eq!(config.macros.first().map(|m| m.actions.len()), Some(2));
+    }
+
+    #[test]
+    fn keymap_falls_through_and_reports_conflicts() {
+        use crate::keymap::{Command, Conflict, Context};
+        use egui::Key;
+
+        let mut config = ClientConfig::default();
+        let keymap = &mut config.keymap;
+        assert_eq!(keymap.conflicts(), []);
+        fn press(keymap: &Keymap, context: Context, pressed: &[Key]) -> Vec<Command> {
+            keymap.pressed(context, |key| pressed.contains(&key))
+        }
+        let up = Command::Move(Direction::Up);
+        assert_eq!(
+            press(keymap, Context::Gameplay, &[Key::Escape]),
+            [Command::Pause]
+        );
+        assert_eq!(
+            press(keymap, Context::Targeting, &[Key::Escape, Key::W]),
+            [Command::Close, up],
+            "the ping menu takes `Esc` and lets the movement keys through"
+        );
+        assert_eq!(press(keymap, Context::Editor, &[Key::W]), []);
+
+        keymap.add_key(Context::Gameplay, Command::Attack, Key::T);
+        assert_eq!(
+            keymap.conflicts(),
+            [Conflict {
+                context: Context::Gameplay,
+                key: Key::T,
+                commands: [Command::Talk, Command::Attack],
+            }]
+        );
+        assert_eq!(press(keymap, Context::Gameplay, &[Key::T]), [Command::Talk]);
+        assert_eq!(
+            keymap.overrides(Context::Targeting, Key::Escape),
+            Some((Context::Gameplay, Command::Pause))
+        );
+
+        keymap.remove_key(Context::Gameplay, up, Key::W);
+        let stored: ClientConfig =
+            ron::from_str(&ron::to_string(&config).expect("encodes")).expect("decodes");
+        assert_eq!(stored.keymap.keys(Context::Gameplay, up), [Key::ArrowUp]);
+        config
+            .keymap
+            .remove_key(Context::Gameplay, Command::Attack, Key::T);
+        config.keymap.add_key(Context::Gameplay, up, Key::W);
+        assert!(
+            config.keymap.is_default(),
+            "bindings back to the defaults are forgotten"
+        );
+    }
+
+    #[cfg(feature = "gamepad")]
+    #[test]
+    fn gamepad_buttons_follow_the_context_and_sticks_repeat() {
+        use crate::gamepad::{PadButton, Repeat};
+        use crate::keymap::{Command, Context};
+
+        let mut config = GamepadConfig::default();
+        assert_eq!(config.stick_direction(0.3, -0.2), None);
+        assert_eq!(config.stick_direction(0.3, -0.8), Some(Direction::Down));
+        assert_eq!(config.stick_direction(-0.9, 0.5), Some(Direction::Left));
+
+        assert_eq!(
+            config.command(PadButton::East, Context::Gameplay),
+            Some(Command::Cancel)
+        );
+        assert_eq!(
+            config.command(PadButton::East, Context::Menus),
+            Some(Command::Close),
+            "`B` backs out of menus"
+        );
+        assert_eq!(config.command(PadButton::South, Context::Console), None);
+
+        config.bind_gameplay(PadButton::East, Some(Command::Trade));
+        assert_eq!(
+            config.command(PadButton::East, Context::Gameplay),
+            Some(Command::Trade)
+        );
+        assert_eq!(
+            config.command(PadButton::East, Context::Menus),
+            Some(Command::Close),
+            "rebinding for gameplay keeps the menu binding"
+        );
+        config.bind_gameplay(PadButton::North, None);
+        assert_eq!(config.command(PadButton::North, Context::Gameplay), None);
+
+        let mut repeat = Repeat::default();
+        let right = Direction::Right;
+        assert_eq!(repeat.step(right, 0.0, &config), Some(right));
+        assert_eq!(repeat.step(right, 0.2, &config), None, "within the delay");
+        assert_eq!(repeat.step(right, 0.3, &config), Some(right));
+        assert_eq!(
+            repeat.step(right, 0.4, &config),
+            None,
+            "within the interval"
+        );
+        assert_eq!(repeat.step(right, 0.45, &config), Some(right));
+        assert_eq!(
+            repeat.step(Direction::Up, 0.5, &config),
+            Some(Direction::Up),
+            "a new direction steps at once"
+        );
+        repeat.release();
+        assert_eq!(
+            repeat.step(Direction::Up, 0.55, &config),
+            Some(Direction::Up)
+        );
+    }
+}
@@ -0,0 +1,308 @@
+//! Local crash reports: what the game was doing when it went down.
+//!
+//! [`install`] sets a panic hook that writes a bundle to
+//! `<CRASH_DIR>/<time>/`: `report.txt`, with the panic, the build and what
+//! the client was on (world, tick, the last actions it sent), `log.txt`,
+//! with the last lines [noted](note), and, if the player opted in, the world
+//! as last [snapshotted](keep_snapshot), as `world.world`, which loads like
+//! any save. [`fatal`] writes one for errors that end the game without a
+//! panic.
+//!
+//! Nothing is sent anywhere: the player attaches the bundle to a bug
+//! report. The client shows where it is as soon as it can, or on the next
+//! start if the game went down with it.
+
+use crate::game::{GameAction, GameState, WorldId, persist};
+
+use std::backtrace::Backtrace;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError, TryLockError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Where crash bundles are written.
+pub const CRASH_DIR: &str = "crashes";
+
+/// Lines of the log kept for a bundle.
+pub const KEPT_LINES: usize = 200;
+
+/// Actions kept for a bundle.
+pub const KEPT_ACTIONS: usize = 50;
+
+/// Seconds between snapshots of the world, while the player opted in.
+pub const SNAPSHOT_SECONDS: f64 = 30.0;
+
+/// File marking a bundle the player was shown.
+const SEEN_MARKER: &str = "seen";
+
+/// What goes into the next bundle.
+struct Recorder {
+    lines: VecDeque<String>,
+    /// The last actions sent, with the tick they were sent in.
+    actions: VecDeque<(u64, String)>,
+    /// The world played in, by ID and name, if any.
+    world: Option<(WorldId, String)>,
+    tick: u64,
+    /// Endpoint ID of the server, or `None` when hosting or in the menus.
+    server: Option<String>,
+    /// The world as a `.world` file, if the player opted in.
+    snapshot: Option<Vec<u8>>,
+}
+
+static RECORDER: Mutex<Recorder> = Mutex::new(Recorder {
+    lines: VecDeque::new(),
+    actions: VecDeque::new(),
+    world: None,
+    tick: 0,
+    server: None,
+    snapshot: None,
+});
+
+/// A bundle written this run that the player was not shown yet.
+static NEW_BUNDLE: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+fn recorder() -> MutexGuard<'static, Recorder> {
+    RECORDER.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Keep `line` for the log of the next bundle.
+pub fn note(line: impl Into<String>) {
+    let mut recorder = recorder();
+    if recorder.lines.len() >= KEPT_LINES {
+        recorder.lines.pop_front();
+    }
+    recorder.lines.push_back(line.into());
+}
+
+/// Keep an action sent in `tick`.
+pub fn action(tick: u64, action: &GameAction) {
+    let mut recorder = recorder();
+    if recorder.actions.len() >= KEPT_ACTIONS {
+        recorder.actions.pop_front();
+    }
+    recorder.actions.push_back((tick, format!("{action:?}")));
+}
+
+/// Note the world joined, `id` named `name`, on the server `server` or
+/// hosted here; a snapshot of another world is dropped.
+pub fn set_world(id: WorldId, name: &str, server: Option<&str>) {
+    let mut recorder = recorder();
+    if recorder
+        .world
+        .as_ref()
+        .is_some_and(|(world, _)| *world != id)
+    {
+        recorder.snapshot = None;
+    }
+    recorder.world = Some((id, name.to_owned()));
+    recorder.server = server.map(str::to_owned);
+}
+
+pub fn set_tick(tick: u64) {
+    recorder().tick = tick;
+}
+
+/// Keep `state` to go in the next bundle.
+pub fn keep_snapshot(state: &GameState) {
+    let encoded = persist::encode(state).ok();
+    recorder().snapshot = encoded;
+}
+
+pub fn clear_snapshot() {
+    recorder().snapshot = None;
+}
+
+/// Write a bundle whenever a thread panics, after the usual message.
+pub fn install() {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        previous(info);
+        let mut what = String::from("panicked");
+        if let Some(location) = info.location() {
+            write!(what, " at {location}").ok();
+        }
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("(no message)");
+        let thread = std::thread::current();
+        write!(
+            what,
+            " on thread {}: {message}\n\nBacktrace:\n{}",
+            thread.name().unwrap_or("unnamed"),
+            Backtrace::force_capture()
+        )
+        .ok();
+        match write_bundle(Path::new(CRASH_DIR), &what) {
+            Ok(path) => log::info!("Crash report written to {}", path.display()),
+            Err(e) => log::error!("Could not write a crash report: {e}"),
+        }
+    }));
+}
+
+/// Write a bundle for the fatal error `what`.
+///
+/// # Errors
+/// If the bundle cannot be written.
+pub fn fatal(what: &str) -> io::Result<PathBuf> {
+    write_bundle(Path::new(CRASH_DIR), what)
+}
+
+/// Write a bundle for `what` to a fresh directory under `root`.
+///
+/// If the recorder is held by the thread that went down, the bundle says
+/// only what happened.
+///
+/// # Errors
+/// If the directory or one of its files cannot be written.
+pub fn write_bundle(root: &Path, what: &str) -> io::Result<PathBuf> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let dir = (0..)
+        .map(|n| match n {
+            0 => root.join(now.to_string()),
+            n => root.join(format!("{now}-{n}")),
+        })
+        .find(|dir| !dir.exists())
+        .unwrap_or_else(|| root.join(now.to_string()));
+    fs::create_dir_all(&dir)?;
+
+    let mut report = format!(
+        "gamik crash report\n\
+         Time: {now} s since the Unix epoch\n\
+         Build: {} ({}/{}, {}), save format {}\n\
+         What: {what}\n",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        if cfg!(debug_assertions) {
+            "debug"
+        } else {
+            "release"
+        },
+        persist::SAVE_FORMAT,
+    );
+    let recorder = match RECORDER.try_lock() {
+        Ok(recorder) => Some(recorder),
+        Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    };
+    if let Some(recorder) = recorder {
+        match &recorder.world {
+            Some((id, name)) => {
+                writeln!(report, "World: {name} ({id}), tick {}", recorder.tick).ok();
+            }
+            None => report.push_str("World: none\n"),
+        }
+        writeln!(
+            report,
+            "Server: {}",
+            recorder.server.as_deref().unwrap_or("hosted here, or none")
+        )
+        .ok();
+        report.push_str("Last actions, oldest first:\n");
+        for (tick, action) in &recorder.actions {
+            writeln!(report, "  tick {tick}: {action}").ok();
+        }
+        let log: Vec<&str> = recorder.lines.iter().map(String::as_str).collect();
+        fs::write(dir.join("log.txt"), log.join("\n"))?;
+        if let Some(snapshot) = &recorder.snapshot {
+            fs::write(dir.join("world.world"), snapshot)?;
+        }
+    }
+    fs::write(dir.join("report.txt"), report)?;
+
+    *NEW_BUNDLE.lock().unwrap_or_else(PoisonError::into_inner) = Some(dir.clone());
+    Ok(dir)
+}
+
+/// The bundle written since last asked, if any.
+pub fn take_new() -> Option<PathBuf> {
+    NEW_BUNDLE
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .take()
+}
+
+/// The newest bundle under `root` the player was not shown, e.g. from a
+/// crash that took the game down.
+pub fn unseen(root: &Path) -> Option<PathBuf> {
+    fs::read_dir(root)
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|dir| dir.join("report.txt").exists() && !dir.join(SEEN_MARKER).exists())
+        .max_by_key(|dir| fs::metadata(dir).and_then(|m| m.modified()).ok())
+}
+
+/// Note that the player was shown the bundle at `dir`.
+///
+/// # Errors
+/// If the marker cannot be written.
+pub fn mark_seen(dir: &Path) -> io::Result<()> {
+    fs::write(dir.join(SEEN_MARKER), "")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Direction;
+
+    #[test]
+    fn bundles_are_written_and_read_back() {
+        let root = std::env::temp_dir().join(format!("gamik-crash-{}", WorldId::generate()));
+        let world = GameState::create_test_world("Crashy".into());
+        set_world(world.world_id, "Crashy", Some("far-away"));
+        set_tick(42);
+        note("first line");
+        note("last line");
+        action(41, &GameAction::Move(Direction::Up));
+        keep_snapshot(&world);
+
+        let dir = write_bundle(&root, "panicked at the test").expect("bundle written");
+        let read = |name: &str| fs::read_to_string(dir.join(name)).expect(name);
+        let report = read("report.txt");
+        for line in [
+            "What: panicked at the test".to_owned(),
+            format!("World: Crashy ({}), tick 42", world.world_id),
+            "Server: far-away".to_owned(),
+            "  tick 41: Move(Up)".to_owned(),
+        ] {
+            assert!(report.lines().any(|l| l == line), "{line} in {report}");
+        }
+        assert!(read("log.txt").contains("first line\nlast line"));
+        let saved = persist::load_from_file(&dir.join("world.world")).expect("the snapshot loads");
+        assert_eq!(saved.checksum(), world.checksum());
+
+        assert_eq!(take_new(), Some(dir.clone()));
+        assert_eq!(take_new(), None, "taken");
+        assert_eq!(unseen(&root), Some(dir.clone()));
+        mark_seen(&dir).expect("marked");
+        assert_eq!(unseen(&root), None);
+
+        let again = write_bundle(&root, "again").expect("second bundle written");
+        assert_ne!(
+            again, dir,
+            "a bundle of the same second gets its own directory"
+        );
+        assert_eq!(unseen(&root), Some(again));
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn bundles_that_cannot_be_written_are_errors() {
+        let root = std::env::temp_dir().join(format!("gamik-crash-{}", WorldId::generate()));
+        fs::write(&root, "a file, not a directory").expect("written");
+        assert!(write_bundle(&root, "nowhere to go").is_err());
+        assert_eq!(unseen(&root), None);
+        assert!(mark_seen(&root.join("missing")).is_err());
+        fs::remove_file(&root).ok();
+    }
+}
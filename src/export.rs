@@ -0,0 +1,55 @@
+//! Writing images to disk, for map exports, screenshots and world previews,
+//! and reading back the thumbnails of save slots.
+
+use egui::ColorImage;
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+/// Encode `image` as an 8-bit RGBA PNG at `path`, creating parent directories.
+///
+/// # Errors
+/// If the file cannot be created or written.
+pub fn save_png(path: &Path, image: &ColorImage) -> io::Result<()> {
+    if let Some(parent) = path.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        std::fs::create_dir_all(parent)?;
+    }
+    let [width, height] = image.size;
+    let width = u32::try_from(width).map_err(io::Error::other)?;
+    let height = u32::try_from(height).map_err(io::Error::other)?;
+
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    let bytes: Vec<u8> = image.pixels.iter().flat_map(|c| c.to_array()).collect();
+    writer.write_image_data(&bytes).map_err(io::Error::other)?;
+    writer.finish().map_err(io::Error::other)
+}
+
+/// Decode the 8-bit RGBA PNG at `path`, as written by [`save_png`].
+///
+/// # Errors
+/// If the file cannot be read or is not an 8-bit RGBA PNG.
+pub fn load_png(path: &Path) -> io::Result<ColorImage> {
+    let decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    let mut reader = decoder.read_info().map_err(io::Error::other)?;
+    let size = reader
+        .output_buffer_size()
+        .ok_or_else(|| io::Error::other("image too large"))?;
+    let mut bytes = vec![0; size];
+    let info = reader.next_frame(&mut bytes).map_err(io::Error::other)?;
+    if info.color_type != png::ColorType::Rgba || info.bit_depth != png::BitDepth::Eight {
+        return Err(io::Error::other("not an 8-bit RGBA image"));
+    }
+    let bytes = bytes
+        .get(..info.buffer_size())
+        .ok_or_else(|| io::Error::other("truncated image"))?;
+    Ok(ColorImage::from_rgba_unmultiplied(
+        [info.width as usize, info.height as usize],
+        bytes,
+    ))
+}
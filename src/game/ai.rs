@@ -0,0 +1,139 @@
+//! Creatures that act on their own.
+//!
+//! An entity with an [`AiBehavior`] picks one [`GameAction`] each tick it
+//! spends in an [active region](super::region), and carries it out through
+//! [`apply`], like a player's action. Choices left to chance roll through
+//! [`rng`](super::rng), mixed with the seed given to [`run_ai_tick`], so
+//! simulating the same ticks again makes the same choices.
+//!
+//! Creatures notice players within [`SIGHT_RADIUS`] tiles. They take no
+//! turns of their own: while the world runs in turns, they act once per
+//! tick the turns use.
+
+use super::collision;
+use super::combat;
+use super::math;
+use super::path::{self, DIRECTIONS};
+use super::region::ActiveRegions;
+use super::rng::{self, Roll};
+use super::{EntityID, EntityType, GameAction, GameEvent, GameState, Point, apply};
+
+use bitcode::{Decode, Encode};
+
+/// Distance within which creatures notice players.
+pub const SIGHT_RADIUS: u32 = 8;
+
+/// Chance, in percent, that a wandering creature takes a step in a tick.
+pub const WANDER_CHANCE: u32 = 25;
+
+/// How a creature acts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub enum AiBehavior {
+    /// Step in a random direction now and then.
+    Wander,
+    /// Step away from the nearest player in sight.
+    Flee,
+    /// Walk up to the nearest player in sight and hit them.
+    ChaseNearestPlayer,
+}
+
+impl AiBehavior {
+    pub const ALL: [Self; 3] = [Self::Wander, Self::Flee, Self::ChaseNearestPlayer];
+
+    /// Name of the behavior in console commands.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Wander => "wander",
+            Self::Flee => "flee",
+            Self::ChaseNearestPlayer => "chase",
+        }
+    }
+
+    /// Stable numeric tag, for checksums.
+    pub fn tag(self) -> u8 {
+        match self {
+            Self::Wander => 0,
+            Self::Flee => 1,
+            Self::ChaseNearestPlayer => 2,
+        }
+    }
+}
+
+/// Let every creature in an active region act once, lowest ID first, and
+/// return what happened. `seed` sets this world's rolls apart from
+/// another's.
+pub fn run_ai_tick(state: &mut GameState, seed: u64) -> Vec<GameEvent> {
+    let active = ActiveRegions::of(state);
+    let creatures: Vec<(EntityID, AiBehavior)> = state
+        .entities
+        .iter()
+        .filter(|(_, e)| e.entity_type != EntityType::Player && active.contains(e.position))
+        .filter_map(|(eid, e)| Some((*eid, e.ai?)))
+        .collect();
+    let mut events = Vec::new();
+    for (eid, behavior) in creatures {
+        let Some(action) = decide(state, eid, behavior, seed, &mut events) else {
+            continue;
+        };
+        events.extend(apply(state, eid, &action));
+    }
+    events
+}
+
+/// What `eid` does this tick, following `behavior`; `None` to stay put.
+/// Rolls it makes are added to `events`.
+fn decide(
+    state: &GameState,
+    eid: EntityID,
+    behavior: AiBehavior,
+    seed: u64,
+    events: &mut Vec<GameEvent>,
+) -> Option<GameAction> {
+    let from = state.entities.get(&eid)?.position;
+    match behavior {
+        AiBehavior::Wander => {
+            let rolled = rng::rolled(state, eid, "ai/wander", seed);
+            let roll = rolled.roll;
+            events.push(GameEvent::Rolled(rolled));
+            if !roll.chance(WANDER_CHANCE) {
+                return None;
+            }
+            let way = Roll::from_seed(roll.value);
+            let direction = *DIRECTIONS.get(way.below(DIRECTIONS.len() as u64) as usize)?;
+            collision::cost_at(state, path::step(from, direction))
+                .map(|_| GameAction::Move(direction))
+        }
+        AiBehavior::Flee => {
+            let (_, threat) = nearest_player(state, from)?;
+            let distance = math::manhattan(from, threat);
+            DIRECTIONS
+                .into_iter()
+                .map(|direction| (direction, path::step(from, direction)))
+                .filter(|(_, to)| collision::cost_at(state, *to).is_some())
+                .filter(|(_, to)| math::manhattan(*to, threat) > distance)
+                .max_by_key(|(_, to)| math::chebyshev(*to, threat))
+                .map(|(direction, _)| GameAction::Move(direction))
+        }
+        AiBehavior::ChaseNearestPlayer => {
+            let (target, at) = nearest_player(state, from)?;
+            if combat::in_reach(from, at) {
+                return Some(GameAction::Attack(target));
+            }
+            path::first_step(from, at, |p| collision::cost_at(state, p)).map(GameAction::Move)
+        }
+    }
+}
+
+/// The player nearest `from` within [`SIGHT_RADIUS`], the lowest ID on a
+/// tie, and where they are.
+fn nearest_player(state: &GameState, from: Point) -> Option<(EntityID, Point)> {
+    state
+        .entities
+        .iter()
+        .filter(|(_, e)| e.entity_type == EntityType::Player)
+        .filter(|(_, e)| e.health.as_ref().is_none_or(|h| !h.is_dead()))
+        .map(|(eid, e)| (math::manhattan(from, e.position), *eid, e.position))
+        .filter(|(distance, ..)| *distance <= SIGHT_RADIUS)
+        .min_by_key(|(distance, eid, _)| (*distance, *eid))
+        .map(|(_, eid, at)| (eid, at))
+}
@@ -0,0 +1,35 @@
+//! Bridges over water.
+//!
+//! A bridge is built on top of a water tile rather than replacing it: the
+//! water stays, and the bridge makes the tile walkable. Walkers still prefer
+//! solid ground, so paths only cross a bridge when going around would take
+//! more than [`BRIDGE_COST`] steps per bridge tile. A bridge leaves no
+//! debris, so destroying one returns the tile to open water.
+//!
+//! Generated worlds have their water in the [terrain](super::terrain), which
+//! is bridged the same way; [`flood`] puts water entities down by hand.
+
+use super::collision;
+use super::{Entity, EntityID, EntityType, GameState, Point};
+
+/// Path cost of stepping onto a bridge, in tiles of solid ground.
+pub const BRIDGE_COST: u32 = 3;
+
+/// Put water at `at` and return it. Whatever stands there stays, so this is
+/// meant for empty tiles.
+pub fn flood(state: &mut GameState, at: Point) -> EntityID {
+    let id = state.entity_gen.next();
+    state.insert_entity(id, Entity::new(EntityType::Water, at, None));
+    id
+}
+
+/// Build a bridge at `at`, which must be open water. Returns the new
+/// bridge, or `None` if there is no water to bridge.
+pub fn build(state: &mut GameState, at: Point) -> Option<EntityID> {
+    if !collision::is_open_water(state, at) {
+        return None;
+    }
+    let id = state.entity_gen.next();
+    state.insert_entity(id, Entity::new(EntityType::Bridge, at, None));
+    Some(id)
+}
@@ -0,0 +1,299 @@
+//! Chunks: endless worlds, planted as players explore them.
+//!
+//! A world generated with
+//! [`infinite`](super::worldgen::WorldGenConfig::infinite) set has no edge.
+//! It is cut into square chunks of [`CHUNK_SIZE`] tiles, and only the chunks
+//! within [`LOAD_RADIUS`] of an [anchor](super::region::is_anchor), or of
+//! [`SPAWN_POINT`], hold entities. [`update`] runs before every tick: a chunk
+//! coming into range for the first time is planted from the world's seed,
+//! as [`worldgen`](super::worldgen) would have planted it up front. A chunk
+//! left further than [`UNLOAD_RADIUS`] behind has its entities, and its
+//! terrain from the world's [`TileMap`](super::TileMap), taken out of the
+//! world and kept in [`Chunks`], which is saved with it, until the chunk
+//! loads again just as it was. Each load and unload is reported as a
+//! [`GameEvent`], for the layers above to hook into.
+//!
+//! Chunks load further out than [regions](super::region) are simulated, so
+//! nothing that moves on its own walks into a chunk that is not loaded. The
+//! world still ends at [`MAX_COORDINATE`]; chunks beyond it are never
+//! loaded.
+
+use super::persist::MAX_COORDINATE;
+use super::region;
+use super::worldgen;
+use super::{Entity, EntityID, EntityMap, GameEvent, GameState, Point, SPAWN_POINT, Terrain};
+use crate::profile;
+
+use bitcode::{Decode, Encode};
+use rustc_hash::FxHashSet;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Side of a chunk, in tiles.
+pub const CHUNK_SIZE: i32 = 32;
+
+/// Chunks around an anchor, in each direction, that are loaded.
+pub const LOAD_RADIUS: i32 = 2;
+
+/// Chunks around an anchor, in each direction, that stay loaded once they
+/// are.
+///
+/// A little more than [`LOAD_RADIUS`], so walking back and forth over a
+/// chunk border does not load and unload the same chunks every step.
+pub const UNLOAD_RADIUS: i32 = LOAD_RADIUS + 1;
+
+/// The chunk a tile is in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Encode, Decode)]
+pub struct ChunkId {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl ChunkId {
+    pub const fn of(point: Point) -> Self {
+        Self {
+            x: point.x.div_euclid(CHUNK_SIZE),
+            y: point.y.div_euclid(CHUNK_SIZE),
+        }
+    }
+
+    /// The chunk's top-left tile.
+    pub const fn origin(self) -> Point {
+        Point {
+            x: self.x.saturating_mul(CHUNK_SIZE),
+            y: self.y.saturating_mul(CHUNK_SIZE),
+        }
+    }
+
+    /// Returns `true` if the whole chunk lies within [`MAX_COORDINATE`].
+    fn in_bounds(self) -> bool {
+        let min = self.origin();
+        let bound = -MAX_COORDINATE..=MAX_COORDINATE - (CHUNK_SIZE - 1);
+        bound.contains(&min.x) && bound.contains(&min.y)
+    }
+}
+
+/// Which chunks of an endless world are loaded, and the entities and
+/// terrain of those that are not.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Encode, Decode)]
+pub struct Chunks {
+    pub(super) loaded: BTreeSet<ChunkId>,
+    /// Entities of unloaded chunks that were planted before, by chunk.
+    pub(super) stored: BTreeMap<ChunkId, Vec<(EntityID, Entity)>>,
+    /// Tiles of unloaded chunks with anything but grass, by chunk.
+    pub(super) terrain: BTreeMap<ChunkId, Vec<Terrain>>,
+}
+
+impl Chunks {
+    /// Loaded chunks, by row then column.
+    pub fn loaded(&self) -> impl Iterator<Item = ChunkId> + '_ {
+        self.loaded.iter().copied()
+    }
+
+    /// Number of chunks planted but not loaded.
+    pub fn stored(&self) -> usize {
+        self.stored.len()
+    }
+
+    /// Entities kept for unloaded chunks.
+    pub fn stored_entities(&self) -> impl Iterator<Item = (EntityID, &Entity)> {
+        self.stored
+            .values()
+            .flat_map(|entities| entities.iter().map(|(eid, entity)| (*eid, entity)))
+    }
+
+    /// The tiles kept for `chunk` while it is unloaded, if any.
+    pub fn stored_terrain(&self, chunk: ChunkId) -> Option<&[Terrain]> {
+        self.terrain.get(&chunk).map(Vec::as_slice)
+    }
+
+    /// Bytes held on the heap for unloaded chunks.
+    pub fn heap_bytes(&self) -> usize {
+        let entities: usize = self.stored.values().map(profile::vec_bytes).sum();
+        let tiles: usize = self.terrain.values().map(profile::vec_bytes).sum();
+        profile::btree_map_bytes(&self.stored)
+            + entities
+            + profile::btree_map_bytes(&self.terrain)
+            + tiles
+    }
+}
+
+/// Chunks within `radius` of an anchor or of [`SPAWN_POINT`].
+fn around(state: &GameState, radius: i32) -> BTreeSet<ChunkId> {
+    let anchors = state
+        .entities
+        .values()
+        .filter(|e| region::is_anchor(e))
+        .map(|e| e.position);
+    let mut chunks = BTreeSet::new();
+    for point in anchors.chain([SPAWN_POINT]) {
+        let center = ChunkId::of(point);
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let chunk = ChunkId {
+                    x: center.x.saturating_add(dx),
+                    y: center.y.saturating_add(dy),
+                };
+                if chunk.in_bounds() {
+                    chunks.insert(chunk);
+                }
+            }
+        }
+    }
+    chunks
+}
+
+/// Load the chunks anchors came near and unload those they left, and
+/// return a [`GameEvent::ChunkLoaded`] or [`GameEvent::ChunkUnloaded`] for
+/// each. Does nothing in worlds that are not endless.
+///
+/// Fresh chunks are only planted while the world's manifest
+/// [checks](super::GenManifest::check) out; in a world made by another
+/// version of the generator they load empty, as planting them would not
+/// match their neighbors.
+pub fn update(state: &mut GameState) -> Vec<GameEvent> {
+    let Some(mut chunks) = state.chunks.take() else {
+        return Vec::new();
+    };
+    let mut events = Vec::new();
+
+    let kept = around(state, UNLOAD_RADIUS);
+    let leaving: BTreeSet<ChunkId> = chunks
+        .loaded
+        .iter()
+        .filter(|chunk| !kept.contains(chunk))
+        .copied()
+        .collect();
+    if !leaving.is_empty() {
+        let gone: Vec<EntityID> = state
+            .entities
+            .iter()
+            .filter(|(_, e)| !region::is_anchor(e) && leaving.contains(&ChunkId::of(e.position)))
+            .map(|(eid, _)| *eid)
+            .collect();
+        for chunk in &leaving {
+            chunks.loaded.remove(chunk);
+            chunks.stored.entry(*chunk).or_default();
+            if let Some(tiles) = state.terrain.remove_chunk(*chunk) {
+                chunks.terrain.insert(*chunk, tiles);
+            }
+            events.push(GameEvent::ChunkUnloaded(*chunk));
+        }
+        for eid in gone {
+            let Some(entity) = state.remove_entity(eid) else {
+                continue;
+            };
+            let chunk = ChunkId::of(entity.position);
+            chunks.stored.entry(chunk).or_default().push((eid, entity));
+        }
+    }
+
+    let planting = state
+        .generation
+        .as_ref()
+        .filter(|manifest| manifest.check().is_ok())
+        .map(|manifest| (manifest.seed, manifest.config.clone()));
+    let entering: Vec<ChunkId> = around(state, LOAD_RADIUS)
+        .into_iter()
+        .filter(|chunk| chunks.loaded.insert(*chunk))
+        .collect();
+    let occupied: FxHashSet<Point> = if entering.is_empty() {
+        FxHashSet::default()
+    } else {
+        state.entities.values().map(|e| e.position).collect()
+    };
+    for chunk in entering {
+        if let Some(entities) = chunks.stored.remove(&chunk) {
+            if let Some(tiles) = chunks.terrain.remove(&chunk) {
+                state.terrain.insert_chunk(chunk, tiles);
+            }
+            for (eid, entity) in entities {
+                state.insert_entity(eid, entity);
+            }
+        } else if let Some((seed, config)) = &planting {
+            let mut planted = EntityMap::default();
+            worldgen::plant_chunk(
+                &mut state.entity_gen,
+                &mut planted,
+                &mut state.terrain,
+                *seed,
+                config,
+                chunk.origin(),
+                CHUNK_SIZE,
+            );
+            planted.retain(|_, entity| !occupied.contains(&entity.position));
+            for point in occupied.iter().filter(|p| ChunkId::of(**p) == chunk) {
+                state.terrain.set(*point, Terrain::Grass);
+            }
+            for (eid, entity) in planted {
+                state.insert_entity(eid, entity);
+            }
+        }
+        events.push(GameEvent::ChunkLoaded(chunk));
+    }
+
+    state.chunks = Some(chunks);
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::worldgen::WorldGenConfig;
+    use crate::game::{ContentRegistry, TileMap, advance, spawn_player};
+
+    #[test]
+    fn unloaded_chunks_take_their_terrain_along_and_bring_it_back() {
+        let config = WorldGenConfig {
+            infinite: true,
+            ..WorldGenConfig::default()
+        };
+        let mut state = worldgen::generate("w".into(), 4, &config);
+        let registry = ContentRegistry::builtin();
+        let player = spawn_player(&mut state, "Alice".into());
+        let walk = |state: &mut GameState, x, y| {
+            state.place_entity(player, Point { x, y });
+            advance(state, &registry)
+        };
+
+        walk(&mut state, -1000, -1000);
+        let rock = Point { x: -1000, y: -1003 };
+        let chunk = ChunkId::of(rock);
+        state.terrain.set(rock, Terrain::Rock);
+        let tiles = state.terrain.chunk(chunk).expect("not all grass").to_vec();
+
+        let events = walk(&mut state, 1000, 1000);
+        assert!(events.contains(&GameEvent::ChunkUnloaded(chunk)));
+        assert_eq!(state.terrain.chunk(chunk), None);
+        assert_eq!(state.terrain.get(rock), Terrain::Grass);
+        let chunks = state.chunks.as_ref().expect("infinite");
+        assert_eq!(chunks.stored_terrain(chunk), Some(tiles.as_slice()));
+        assert!(
+            state
+                .terrain
+                .chunks()
+                .all(|(loaded, _)| chunks.loaded().any(|c| c == loaded)),
+            "only loaded chunks keep terrain in the world"
+        );
+
+        let events = walk(&mut state, -1000, -1000);
+        assert!(events.contains(&GameEvent::ChunkLoaded(chunk)));
+        assert_eq!(state.terrain.get(rock), Terrain::Rock);
+        assert_eq!(state.terrain.chunk(chunk), Some(tiles.as_slice()));
+        let chunks = state.chunks.as_ref().expect("infinite");
+        assert_eq!(chunks.stored_terrain(chunk), None, "not kept twice");
+    }
+
+    #[test]
+    fn chunks_of_grass_keep_no_terrain_and_bounded_worlds_none_at_all() {
+        let mut tiles = TileMap::default();
+        assert_eq!(tiles.remove_chunk(ChunkId { x: 3, y: 3 }), None);
+        tiles.set(Point { x: 100, y: 100 }, Terrain::Sand);
+        assert!(tiles.remove_chunk(ChunkId { x: 3, y: 3 }).is_some());
+        assert_eq!(tiles.get(Point { x: 100, y: 100 }), Terrain::Grass);
+
+        let mut bounded = GameState::create_test_world("w".into());
+        bounded.terrain.set(Point { x: 3, y: 3 }, Terrain::Rock);
+        assert!(update(&mut bounded).is_empty());
+        assert_eq!(bounded.terrain.get(Point { x: 3, y: 3 }), Terrain::Rock);
+    }
+}
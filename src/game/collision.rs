@@ -0,0 +1,168 @@
+//! Which tiles entities can move onto.
+//!
+//! Every entity fills its tile, except debris such as rubble that can be
+//! walked over, and bridges, which also make the water under them
+//! walkable, be it an entity or [terrain](super::terrain). Rock terrain
+//! fills its tile too. Plain [`GameAction::Move`](super::GameAction::Move)
+//! steps only refuse open water and rock, so players can still walk
+//! through trees;
+//! movement the server schedules itself, such as group moves, checks
+//! [`is_blocked`] first so that no two entities end up on one tile.
+//!
+//! Entities are looked up in the state's
+//! [`index`](super::GameState::index), which must be synced, as it is
+//! while [`apply`](super::apply) and [`advance`](super::advance) run.
+//!
+//! [`push`] moves an entity against its will, for knockback: it slides
+//! until it has gone the full distance or hits something, and hitting
+//! something hurts.
+
+use super::bridge::BRIDGE_COST;
+use super::combat;
+use super::formation;
+use super::path;
+use super::{Direction, Entity, EntityID, EntityType, GameEvent, GameState, Point, Terrain};
+
+/// Damage taken by a pushed entity, and by what it hits, when a push is
+/// cut short.
+pub const COLLISION_DAMAGE: u32 = 5;
+
+/// Returns `true` if `terrain` keeps entities off its tile, given whether
+/// the tile is `bridged`.
+fn terrain_blocks(terrain: Terrain, bridged: bool) -> bool {
+    terrain.blocks_movement() && !(bridged && terrain == Terrain::Water)
+}
+
+/// The entities on `point`.
+fn on_tile(state: &GameState, point: Point) -> impl Iterator<Item = (EntityID, &Entity)> {
+    state
+        .index
+        .entities_at(point)
+        .iter()
+        .filter_map(|eid| Some((*eid, state.entities.get(eid)?)))
+}
+
+/// Whether a bridge is on `point`, and how many entities on it keep
+/// others off.
+fn occupancy(state: &GameState, point: Point) -> (bool, u32) {
+    let bridged = on_tile(state, point).any(|(_, e)| e.entity_type == EntityType::Bridge);
+    let blockers = on_tile(state, point)
+        .filter(|(_, e)| {
+            e.entity_type.blocks_movement() && !(bridged && e.entity_type == EntityType::Water)
+        })
+        .count();
+    (bridged, u32::try_from(blockers).unwrap_or(u32::MAX))
+}
+
+/// Returns `true` if an entity stands on `point`, or its terrain keeps
+/// them off.
+pub fn is_blocked(state: &GameState, point: Point) -> bool {
+    cost_at(state, point).is_none()
+}
+
+/// Cost of stepping onto `point` for [`path::first_step`], or `None` if
+/// it is blocked.
+pub fn cost_at(state: &GameState, point: Point) -> Option<u32> {
+    cost_past(state, point, 0)
+}
+
+/// [`cost_at`] of `point` once `passing` of the entities on it have moved
+/// on.
+pub fn cost_past(state: &GameState, point: Point, passing: u32) -> Option<u32> {
+    let (bridged, blockers) = occupancy(state, point);
+    if blockers > passing || terrain_blocks(state.terrain.get(point), bridged) {
+        None
+    } else if bridged {
+        Some(BRIDGE_COST)
+    } else {
+        Some(1)
+    }
+}
+
+/// The unoccupied tile closest to `center`, searching ring by ring.
+/// Gives `center` itself if everything nearby is taken.
+pub fn free_tile_near(state: &GameState, center: Point) -> Point {
+    (1..=32i32)
+        .flat_map(|r| (-r..=r).flat_map(move |dy| (-r..=r).map(move |dx| (dx, dy, r))))
+        .filter(|(dx, dy, r)| dx.abs() == *r || dy.abs() == *r)
+        .map(|(dx, dy, _)| Point {
+            x: center.x + dx,
+            y: center.y + dy,
+        })
+        .find(|p| !is_blocked(state, *p))
+        .unwrap_or(center)
+}
+
+/// Returns `true` if `point` is water, as an entity or as terrain, with no
+/// bridge over it.
+pub fn is_open_water(state: &GameState, point: Point) -> bool {
+    (state.terrain.get(point) == Terrain::Water
+        || on_tile(state, point).any(|(_, e)| e.entity_type == EntityType::Water))
+        && !on_tile(state, point).any(|(_, e)| e.entity_type == EntityType::Bridge)
+}
+
+/// Returns `true` if a plain step onto `point` is refused: it is open
+/// water, or terrain that blocks movement whatever stands on it.
+pub fn is_impassable(state: &GameState, point: Point) -> bool {
+    let terrain = state.terrain.get(point);
+    (terrain.blocks_movement() && terrain != Terrain::Water) || is_open_water(state, point)
+}
+
+/// Push `eid` up to `distance` tiles in `direction`, stopping in front of
+/// the first occupied tile. Anchored entities such as trees do not move.
+///
+/// Returns a [`GameEvent::Pushed`], or `None` if nothing moved or collided.
+pub fn push(
+    state: &mut GameState,
+    eid: EntityID,
+    direction: Direction,
+    distance: u32,
+) -> Option<GameEvent> {
+    let entity = state.entities.get(&eid)?;
+    if entity.entity_type.is_anchored() {
+        return None;
+    }
+    let from = entity.position;
+
+    let mut to = from;
+    let mut collided = false;
+    for _ in 0..distance {
+        let next = path::step(to, direction);
+        if is_blocked(state, next) {
+            collided = true;
+            break;
+        }
+        to = next;
+    }
+    let obstacle = if collided {
+        let next = path::step(to, direction);
+        let mut blockers: Vec<EntityID> = on_tile(state, next)
+            .filter(|(_, e)| e.entity_type.blocks_movement())
+            .map(|(id, _)| id)
+            .collect();
+        // The lowest ID, as when going through the entities in order.
+        blockers.sort_unstable();
+        blockers.first().copied()
+    } else {
+        None
+    };
+    if to == from && obstacle.is_none() {
+        return None;
+    }
+
+    state.place_entity(eid, to);
+    if let Some(entity) = state.entity_mut(eid) {
+        // Being thrown about cancels any walk in progress.
+        formation::stop(entity);
+    }
+    if let Some(obstacle) = obstacle {
+        combat::damage(state, eid, COLLISION_DAMAGE);
+        combat::damage(state, obstacle, COLLISION_DAMAGE);
+    }
+    Some(GameEvent::Pushed {
+        entity_id: eid,
+        from,
+        to,
+        obstacle,
+    })
+}
@@ -0,0 +1,219 @@
+//! Health, damage and melee attacks.
+//!
+//! Only entities with [`Health`] can be hurt; trees and scenery have none
+//! unless the host gives them some. Creatures at zero health leave a
+//! [corpse](EntityType::Corpse) holding what they carried, terrain wrecks
+//! into its [debris](EntityType::debris), and players stay where they
+//! fell, at zero health.
+//!
+//! A hit deals [`ATTACK_DAMAGE`] plus the attacker's [`CombatStats`] attack
+//! and weapon, less the target's defense, but always at least one point.
+//! Nothing about it is random, so replays hit the same.
+
+use super::collision;
+use super::debris;
+use super::intent::{self, Interruption};
+use super::math;
+use super::path;
+use super::{
+    ContentRegistry, Direction, Entity, EntityID, EntityType, GameEvent, GameState, Point,
+};
+
+use bitcode::{Decode, Encode};
+
+/// Health of a new player.
+pub const PLAYER_HEALTH: u32 = 100;
+
+/// Damage dealt by a melee attack without a weapon.
+pub const ATTACK_DAMAGE: u32 = 10;
+
+/// Tiles a melee attack knocks its target back.
+pub const ATTACK_KNOCKBACK: u32 = 2;
+
+/// Current and maximum hit points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub struct Health {
+    pub current: u32,
+    pub max: u32,
+}
+
+impl Health {
+    /// Full health of `max` hit points.
+    pub fn new(max: u32) -> Self {
+        Self { current: max, max }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current == 0
+    }
+}
+
+/// Attack and defense, on top of what every entity has.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Encode, Decode)]
+pub struct CombatStats {
+    /// Damage added to the entity's hits.
+    pub attack: u32,
+    /// Damage taken off hits on the entity.
+    pub defense: u32,
+}
+
+/// Damage of a hit by `attacker`, with `bonus` from its weapon, on
+/// `defender`.
+pub fn hit_damage(attacker: CombatStats, bonus: u32, defender: CombatStats) -> u32 {
+    ATTACK_DAMAGE
+        .saturating_add(attacker.attack)
+        .saturating_add(bonus)
+        .saturating_sub(defender.defense)
+        .max(1)
+}
+
+/// Returns `true` if an entity at `a` can hit one at `b`.
+pub fn in_reach(a: Point, b: Point) -> bool {
+    a != b && math::within_square(a, b, 1)
+}
+
+/// Take `amount` hit points from `eid`, if it has health. Returns the
+/// damage actually dealt; any damage interrupts what the entity was
+/// channelling.
+pub fn damage(state: &mut GameState, eid: EntityID, amount: u32) -> u32 {
+    let Some(entity) = state.entity_mut(eid) else {
+        return 0;
+    };
+    let Some(health) = entity.health.as_mut() else {
+        return 0;
+    };
+    let dealt = amount.min(health.current);
+    health.current -= dealt;
+    if dealt > 0 {
+        intent::interrupt(entity, Interruption::Damage);
+    }
+    dealt
+}
+
+/// Settle everything but players that ran out of health.
+///
+/// Terrain wrecks into its debris, which cannot be hurt, and creatures leave
+/// a corpse, under the same ID, holding what they carried. Returns the IDs
+/// and names of the creatures killed.
+pub fn resolve_deaths(state: &mut GameState) -> Vec<(EntityID, Option<String>)> {
+    let dead: Vec<(EntityID, bool)> = state
+        .entities
+        .iter()
+        .filter(|(_, e)| {
+            e.entity_type != EntityType::Player && e.health.is_some_and(|h| h.is_dead())
+        })
+        .map(|(eid, e)| (*eid, e.entity_type.is_terrain()))
+        .collect();
+    let mut killed = Vec::new();
+    for (eid, terrain) in dead {
+        if terrain {
+            debris::destroy(state, eid);
+            if let Some(entity) = state.entity_mut(eid) {
+                entity.health = None;
+            }
+            continue;
+        }
+        let Some(entity) = state.remove_entity(eid) else {
+            continue;
+        };
+        let mut corpse = Entity::new(EntityType::Corpse, entity.position, entity.name.clone());
+        corpse.inventory = entity.inventory;
+        state.insert_entity(eid, corpse);
+        killed.push((eid, entity.name));
+    }
+    killed
+}
+
+/// `attacker` hits `target` in melee: damage, then knockback away from the
+/// attacker. Attacking a faction member costs reputation with its friends.
+///
+/// The attacker's best [weapon](super::Inventory::weapon) adds its damage
+/// and wears by one use.
+pub fn attack(
+    state: &mut GameState,
+    registry: &ContentRegistry,
+    attacker: EntityID,
+    target: EntityID,
+) -> Vec<GameEvent> {
+    let (Some(from), Some(victim)) = (
+        state.entities.get(&attacker).map(|e| e.position),
+        state.entities.get(&target),
+    ) else {
+        return Vec::new();
+    };
+    if attacker == target || victim.health.is_none() || !in_reach(from, victim.position) {
+        return Vec::new();
+    }
+    let to = victim.position;
+    let faction = victim.faction.clone();
+    let defense = victim.combat;
+    let (stats, weapon) = state
+        .entities
+        .get(&attacker)
+        .map(|e| {
+            let weapon = e
+                .inventory
+                .weapon(registry)
+                .map(|(item, damage)| (item.to_owned(), damage));
+            (e.combat, weapon)
+        })
+        .unwrap_or_default();
+
+    let bonus = weapon.as_ref().map_or(0, |(_, damage)| *damage);
+    let dealt = damage(state, target, hit_damage(stats, bonus, defense));
+    let mut events = vec![GameEvent::Attacked {
+        entity_id: attacker,
+        target,
+    }];
+    if dealt > 0 {
+        events.push(GameEvent::Damaged {
+            entity_id: target,
+            amount: dealt,
+        });
+    }
+    if let Some(entity) = state.entity_mut(attacker) {
+        if let Some(faction) = faction {
+            entity.reputation.record_attack(registry, &faction);
+        }
+        if let Some((item, _)) = weapon
+            && entity.inventory.wear_out(registry, &item)
+        {
+            events.push(GameEvent::ItemBroke {
+                entity_id: attacker,
+                item,
+            });
+        }
+    }
+    if let Some(direction) = path::direction_towards(from, to) {
+        events.extend(collision::push(state, target, direction, ATTACK_KNOCKBACK));
+    }
+    events.extend(
+        resolve_deaths(state)
+            .into_iter()
+            .map(|(entity_id, name)| GameEvent::Died { entity_id, name }),
+    );
+    events
+}
+
+/// `attacker` hits whatever can be hurt on the tile next to it in
+/// `direction`, the lowest ID first if several can; see [`attack`].
+pub fn attack_toward(
+    state: &mut GameState,
+    registry: &ContentRegistry,
+    attacker: EntityID,
+    direction: Direction,
+) -> Vec<GameEvent> {
+    let Some(from) = state.entities.get(&attacker).map(|e| e.position) else {
+        return Vec::new();
+    };
+    let at = path::step(from, direction);
+    let target = state
+        .entities
+        .iter()
+        .find(|(eid, e)| **eid != attacker && e.position == at && e.health.is_some())
+        .map(|(eid, _)| *eid);
+    match target {
+        Some(target) => attack(state, registry, attacker, target),
+        None => Vec::new(),
+    }
+}
@@ -0,0 +1,584 @@
+//! Data-driven game content.
+//!
+//! Content is written in [RON](https://github.com/ron-rs/ron) files under
+//! `assets/content` and embedded in the binary, so the server and every
+//! client agree on it without shipping files around. While working on it,
+//! a [`ContentWatcher`] reads the files again as they change, so edits
+//! show up without restarting.
+
+use super::EntityType;
+use super::dialogue::{DialogueTree, Effect};
+use super::faction::FactionDef;
+use super::item::{CarryLimits, ItemDef};
+use super::render::{AppearanceDef, RenderLayer};
+use super::shop::ShopDef;
+use super::tags::Tags;
+use super::world_events::{self, WorldEventDef};
+use super::worldgen::namegen::{self, NameStyle};
+
+use crate::watch::FileWatcher;
+
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock, PoisonError, RwLock};
+use std::time::Instant;
+
+/// Where the content files live, relative to the project.
+pub const CONTENT_DIR: &str = "assets/content";
+
+/// RON source of each content file.
+#[derive(Debug, Clone, Copy)]
+pub struct Sources<'a> {
+    pub factions: &'a str,
+    pub dialogue: &'a str,
+    pub items: &'a str,
+    pub shops: &'a str,
+    pub events: &'a str,
+    pub appearance: &'a str,
+    pub names: &'a str,
+}
+
+impl Sources<'static> {
+    /// The files under `assets/content`, as embedded in the binary.
+    pub const BUILTIN: Self = Self {
+        factions: include_str!("../../assets/content/factions.ron"),
+        dialogue: include_str!("../../assets/content/dialogue.ron"),
+        items: include_str!("../../assets/content/items.ron"),
+        shops: include_str!("../../assets/content/shops.ron"),
+        events: include_str!("../../assets/content/events.ron"),
+        appearance: include_str!("../../assets/content/appearance.ron"),
+        names: include_str!("../../assets/content/names.ron"),
+    };
+}
+
+impl<'a> Sources<'a> {
+    /// Each file's name and source, in a fixed order.
+    pub const fn files(&self) -> [(&'static str, &'a str); 7] {
+        [
+            ("factions.ron", self.factions),
+            ("dialogue.ron", self.dialogue),
+            ("items.ron", self.items),
+            ("shops.ron", self.shops),
+            ("events.ron", self.events),
+            ("appearance.ron", self.appearance),
+            ("names.ron", self.names),
+        ]
+    }
+}
+
+fn current() -> &'static RwLock<Arc<ContentRegistry>> {
+    static CURRENT: OnceLock<RwLock<Arc<ContentRegistry>>> = OnceLock::new();
+    CURRENT.get_or_init(|| {
+        let builtin = ContentRegistry::from_sources(Sources::BUILTIN)
+            .unwrap_or_else(|e| panic!("embedded content: {e}"));
+        RwLock::new(Arc::new(builtin))
+    })
+}
+
+/// Reads the content files from a directory again whenever they change,
+/// for working on content without restarting; see [`FileWatcher`].
+#[derive(Debug)]
+pub struct ContentWatcher {
+    files: FileWatcher,
+}
+
+impl ContentWatcher {
+    /// Watch the files under `dir`. The first [`poll`](Self::poll) reads
+    /// them, and reports them only if they differ from the content in use.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            files: FileWatcher::new(dir, Sources::BUILTIN.files()),
+        }
+    }
+
+    /// The content, parsed and cross-checked, if the files changed since
+    /// they were last read. Reads them at most once per
+    /// [`WATCH_INTERVAL`](crate::watch::WATCH_INTERVAL).
+    ///
+    /// # Errors
+    /// If the changed files cannot be read, do not parse or do not agree;
+    /// the content in use should then be kept.
+    pub fn poll(&mut self, now: Instant) -> Option<Result<ContentRegistry, String>> {
+        let files = match self.files.poll(now)? {
+            Ok(files) => files,
+            Err(e) => return Some(Err(e)),
+        };
+        let [factions, dialogue, items, shops, events, appearance, names] = files[..] else {
+            return Some(Err("a content file is missing".into()));
+        };
+        Some(ContentRegistry::from_sources(Sources {
+            factions,
+            dialogue,
+            items,
+            shops,
+            events,
+            appearance,
+            names,
+        }))
+    }
+}
+
+/// Every piece of content the game knows about.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContentRegistry {
+    pub factions: Vec<FactionDef>,
+    pub dialogues: Vec<DialogueTree>,
+    pub items: Vec<ItemDef>,
+    /// How much an entity may carry.
+    pub carry: CarryLimits,
+    /// ID of the item shops trade in.
+    pub currency: String,
+    /// Currency new players start with.
+    pub starting_purse: u32,
+    pub shops: Vec<ShopDef>,
+    pub world_events: Vec<WorldEventDef>,
+    /// How each entity type is drawn, one per [`EntityType`].
+    pub appearances: Vec<AppearanceDef>,
+    /// How generated names are made; see [`namegen`].
+    pub name_styles: Vec<NameStyle>,
+}
+
+#[derive(Deserialize)]
+struct FactionFile {
+    factions: Vec<FactionDef>,
+}
+
+#[derive(Deserialize)]
+struct DialogueFile {
+    dialogues: Vec<DialogueTree>,
+}
+
+#[derive(Deserialize)]
+struct ItemFile {
+    currency: String,
+    starting_purse: u32,
+    #[serde(default)]
+    carry: CarryLimits,
+    items: Vec<ItemDef>,
+}
+
+#[derive(Deserialize)]
+struct ShopFile {
+    shops: Vec<ShopDef>,
+}
+
+#[derive(Deserialize)]
+struct EventFile {
+    events: Vec<WorldEventDef>,
+}
+
+#[derive(Deserialize)]
+struct AppearanceFile {
+    appearances: Vec<AppearanceDef>,
+}
+
+#[derive(Deserialize)]
+struct NameFile {
+    styles: Vec<NameStyle>,
+}
+
+impl ContentRegistry {
+    /// The content in use: the content embedded in the binary, parsed on
+    /// first use, unless other content was [installed](Self::install)
+    /// since.
+    pub fn builtin() -> Arc<Self> {
+        current()
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Use this content from now on, in place of what
+    /// [`builtin`](Self::builtin) returned so far. Earlier content lives on
+    /// while callers still hold it, and is freed once they let it go.
+    pub fn install(self) -> Arc<Self> {
+        let content = Arc::new(self);
+        *current().write().unwrap_or_else(PoisonError::into_inner) = content.clone();
+        content
+    }
+
+    /// Parse and cross-check content from RON sources.
+    ///
+    /// # Errors
+    /// With the first source that does not parse, or the first reference
+    /// to something no source defines.
+    pub fn from_sources(sources: Sources<'_>) -> Result<Self, String> {
+        let factions: FactionFile =
+            ron::from_str(sources.factions).map_err(|e| format!("factions.ron: {e}"))?;
+        let dialogue: DialogueFile =
+            ron::from_str(sources.dialogue).map_err(|e| format!("dialogue.ron: {e}"))?;
+        let items: ItemFile =
+            ron::from_str(sources.items).map_err(|e| format!("items.ron: {e}"))?;
+        let shops: ShopFile =
+            ron::from_str(sources.shops).map_err(|e| format!("shops.ron: {e}"))?;
+        let events: EventFile =
+            ron::from_str(sources.events).map_err(|e| format!("events.ron: {e}"))?;
+        let appearance: AppearanceFile =
+            ron::from_str(sources.appearance).map_err(|e| format!("appearance.ron: {e}"))?;
+        let names: NameFile =
+            ron::from_str(sources.names).map_err(|e| format!("names.ron: {e}"))?;
+        let registry = Self {
+            factions: factions.factions,
+            dialogues: dialogue.dialogues,
+            items: items.items,
+            carry: items.carry,
+            currency: items.currency,
+            starting_purse: items.starting_purse,
+            shops: shops.shops,
+            world_events: events.events,
+            appearances: appearance.appearances,
+            name_styles: names.styles,
+        };
+        registry.validate()?;
+        Ok(registry)
+    }
+
+    /// Check that IDs are unique and every reference resolves.
+    fn validate(&self) -> Result<(), String> {
+        let mut ids = BTreeSet::new();
+        for faction in &self.factions {
+            if !ids.insert(faction.id.as_str()) {
+                return Err(format!("duplicate faction `{}`", faction.id));
+            }
+        }
+        for faction in &self.factions {
+            for (other, standing) in &faction.standings {
+                if !ids.contains(other.as_str()) {
+                    return Err(format!(
+                        "faction `{}` refers to unknown `{other}`",
+                        faction.id
+                    ));
+                }
+                if !(-100..=100).contains(standing) {
+                    return Err(format!(
+                        "standing of `{}` towards `{other}` is outside -100..=100",
+                        faction.id
+                    ));
+                }
+            }
+        }
+        self.validate_dialogue()?;
+        self.validate_shops()?;
+        self.validate_events()?;
+        self.validate_appearances()?;
+        self.validate_names()
+    }
+
+    fn validate_dialogue(&self) -> Result<(), String> {
+        let mut ids = BTreeSet::new();
+        for tree in &self.dialogues {
+            if !ids.insert(tree.id.as_str()) {
+                return Err(format!("duplicate dialogue `{}`", tree.id));
+            }
+            let bad = |what: String| Err(format!("dialogue `{}`: {what}", tree.id));
+            if tree.node(&tree.start).is_none() {
+                return bad(format!("unknown start node `{}`", tree.start));
+            }
+            let mut nodes = BTreeSet::new();
+            for node in &tree.nodes {
+                if !nodes.insert(node.id.as_str()) {
+                    return bad(format!("duplicate node `{}`", node.id));
+                }
+                for option in &node.options {
+                    if let Some(next) = &option.next
+                        && tree.node(next).is_none()
+                    {
+                        return bad(format!("node `{}` leads to unknown `{next}`", node.id));
+                    }
+                    for effect in &option.effects {
+                        match effect {
+                            Effect::Reputation(faction, _) if self.faction(faction).is_none() => {
+                                return bad(format!("unknown faction `{faction}`"));
+                            }
+                            Effect::Tag(tag) | Effect::Untag(tag) => {
+                                if let Err(e) = Tags::default().insert(tag) {
+                                    return bad(format!("tag `{tag}`: {e}"));
+                                }
+                            }
+                            Effect::Reputation(..) => {}
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_shops(&self) -> Result<(), String> {
+        let mut ids = BTreeSet::new();
+        for item in &self.items {
+            if !ids.insert(item.id.as_str()) {
+                return Err(format!("duplicate item `{}`", item.id));
+            }
+        }
+        if self.item(&self.currency).is_none() {
+            return Err(format!("unknown currency `{}`", self.currency));
+        }
+        for item in &self.items {
+            let bad = |what: &str| Err(format!("item `{}` {what}", item.id));
+            if item.stack_size == 0 {
+                return bad("has a stack size of 0");
+            }
+            if item.durability == Some(0) {
+                return bad("breaks before its first use");
+            }
+            if item.durability.is_some() && item.stack_size != 1 {
+                return bad("wears out but stacks");
+            }
+            if !item.repair.is_empty() && item.durability.is_none() {
+                return bad("is repaired but never wears out");
+            }
+            if let Some(material) = item.repair.keys().find(|m| self.item(m).is_none()) {
+                return bad(&format!("is repaired with unknown `{material}`"));
+            }
+            if let Some(material) = item.craft.keys().find(|m| self.item(m).is_none()) {
+                return bad(&format!("is crafted from unknown `{material}`"));
+            }
+        }
+        if self.carry.burdened_weight > self.carry.max_weight {
+            return Err("`burdened_weight` is above `max_weight`".to_owned());
+        }
+        let mut ids = BTreeSet::new();
+        for shop in &self.shops {
+            if !ids.insert(shop.id.as_str()) {
+                return Err(format!("duplicate shop `{}`", shop.id));
+            }
+            if shop.restock_ticks == 0 {
+                return Err(format!("shop `{}` never restocks", shop.id));
+            }
+            let mut stocked = BTreeSet::new();
+            for entry in &shop.stock {
+                if self.item(&entry.item).is_none() || !stocked.insert(entry.item.as_str()) {
+                    return Err(format!(
+                        "shop `{}`: unknown or repeated item `{}`",
+                        shop.id, entry.item
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_events(&self) -> Result<(), String> {
+        let mut ids = BTreeSet::new();
+        for event in &self.world_events {
+            let bad = |what: &str| Err(format!("event `{}` {what}", event.id));
+            if !ids.insert(event.id.as_str()) {
+                return bad("is listed twice");
+            }
+            if world_events::hook(&event.id).is_none() {
+                return bad("has no hook");
+            }
+            if event.every == 0 || event.offset >= event.every {
+                return bad("needs `offset` below a non-zero `every`");
+            }
+            if event.duration == 0 || event.duration >= event.every {
+                return bad("must end before it starts again");
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_appearances(&self) -> Result<(), String> {
+        let mut types = BTreeSet::new();
+        for appearance in &self.appearances {
+            let name = appearance.entity_type.as_str();
+            if !EntityType::ALL.iter().any(|t| t.name() == name) {
+                return Err(format!("appearance of unknown entity type `{name}`"));
+            }
+            if !types.insert(name) {
+                return Err(format!("entity type `{name}` has two appearances"));
+            }
+            if appearance.glyph.is_empty() {
+                return Err(format!("entity type `{name}` has an empty glyph"));
+            }
+            if let Some(animation) = &appearance.animation
+                && (animation.frames.is_empty() || animation.frame_ms == 0)
+            {
+                return Err(format!(
+                    "animation of `{name}` needs frames and a frame time"
+                ));
+            }
+        }
+        match EntityType::ALL.iter().find(|t| !types.contains(t.name())) {
+            Some(missing) => Err(format!(
+                "entity type `{}` has no appearance",
+                missing.name()
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn validate_names(&self) -> Result<(), String> {
+        let mut ids = BTreeSet::new();
+        for style in &self.name_styles {
+            let bad = |what: &str| Err(format!("name style `{}` {what}", style.id));
+            if !ids.insert(style.id.as_str()) {
+                return bad("is listed twice");
+            }
+            let (min, max) = style.syllables;
+            if min == 0 || min > max {
+                return bad("needs at least one syllable, and `min` up to `max`");
+            }
+            if style.vowels.is_empty() {
+                return bad("has no vowels");
+            }
+        }
+        match namegen::STYLES.iter().find(|id| !ids.contains(*id)) {
+            Some(missing) => Err(format!("name style `{missing}` is missing")),
+            None => Ok(()),
+        }
+    }
+
+    pub fn dialogue(&self, id: &str) -> Option<&DialogueTree> {
+        self.dialogues.iter().find(|d| d.id == id)
+    }
+
+    pub fn faction(&self, id: &str) -> Option<&FactionDef> {
+        self.factions.iter().find(|f| f.id == id)
+    }
+
+    pub fn item(&self, id: &str) -> Option<&ItemDef> {
+        self.items.iter().find(|i| i.id == id)
+    }
+
+    pub fn shop(&self, id: &str) -> Option<&ShopDef> {
+        self.shops.iter().find(|s| s.id == id)
+    }
+
+    pub fn name_style(&self, id: &str) -> Option<&NameStyle> {
+        self.name_styles.iter().find(|s| s.id == id)
+    }
+
+    pub fn appearance(&self, entity_type: &EntityType) -> Option<&AppearanceDef> {
+        let name = entity_type.name();
+        self.appearances.iter().find(|a| a.entity_type == name)
+    }
+
+    /// Render layer of `entity_type`. Validation makes sure every type has
+    /// one; creatures are assumed otherwise.
+    pub fn layer(&self, entity_type: &EntityType) -> RenderLayer {
+        self.appearance(entity_type)
+            .map_or(RenderLayer::Creature, |a| a.layer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::WorldId;
+
+    #[test]
+    fn watched_content_reloads_when_its_files_change_and_still_agree() {
+        let dir = std::env::temp_dir().join(format!("gamik-content-{}", WorldId::generate()));
+        std::fs::create_dir_all(&dir).expect("temp dir is writable");
+        for (name, source) in Sources::BUILTIN.files() {
+            std::fs::write(dir.join(name), source).expect("temp dir is writable");
+        }
+        let mut watch = ContentWatcher::new(&dir);
+        let mut now = std::time::Instant::now();
+        assert!(watch.poll(now).is_none(), "the files are the embedded ones");
+
+        let items = Sources::BUILTIN.items.replacen(
+            "starting_purse:",
+            "starting_purse: \"seven\", _was:",
+            1,
+        );
+        std::fs::write(dir.join("items.ron"), &items).expect("temp dir is writable");
+        assert!(watch.poll(now).is_none(), "read at most once a second");
+        now += crate::watch::WATCH_INTERVAL;
+        let err = watch
+            .poll(now)
+            .expect("changed")
+            .expect_err("a purse of words");
+        assert!(err.starts_with("items.ron"), "{err}");
+
+        let items = Sources::BUILTIN
+            .items
+            .replacen("starting_purse:", "starting_purse: 7, //", 1);
+        std::fs::write(dir.join("items.ron"), items).expect("temp dir is writable");
+        now += crate::watch::WATCH_INTERVAL;
+        let reloaded = watch.poll(now).expect("changed").expect("still agrees");
+        assert_eq!(reloaded.starting_purse, 7);
+        now += crate::watch::WATCH_INTERVAL;
+        assert!(watch.poll(now).is_none(), "reported once");
+
+        std::fs::remove_file(dir.join("shops.ron")).expect("was written");
+        now += crate::watch::WATCH_INTERVAL;
+        let err = watch
+            .poll(now)
+            .expect("changed")
+            .expect_err("a missing file");
+        assert!(err.starts_with("shops.ron"), "{err}");
+        std::fs::remove_dir_all(&dir).ok();
+
+        // Other tests run alongside, so install only what is in use already.
+        let before = ContentRegistry::builtin();
+        let installed = (*before).clone().install();
+        assert!(Arc::ptr_eq(&installed, &ContentRegistry::builtin()));
+        drop(installed);
+        assert_eq!(
+            Arc::strong_count(&before),
+            1,
+            "content replaced is freed once let go"
+        );
+    }
+
+    /// The builtin content with `from` replaced by `to` in `appearance.ron`.
+    fn with_appearance(from: &str, to: &str) -> Result<ContentRegistry, String> {
+        let appearance = Sources::BUILTIN.appearance.replacen(from, to, 1);
+        assert_ne!(appearance, Sources::BUILTIN.appearance, "`{from}` is there");
+        ContentRegistry::from_sources(Sources {
+            appearance: &appearance,
+            ..Sources::BUILTIN
+        })
+    }
+
+    #[test]
+    fn animations_need_frames_and_a_frame_time() {
+        let water = "(frames: [\"~\", \"~\", \"≈\"], frame_ms: 1200, shimmer: 25)";
+        assert!(with_appearance(water, "(frames: [\"~\"], frame_ms: 1, shimmer: 0)").is_ok());
+        for broken in [
+            "(frames: [], frame_ms: 1200, shimmer: 25)",
+            "(frames: [\"~\"], frame_ms: 0, shimmer: 25)",
+        ] {
+            let err = with_appearance(water, broken).expect_err(broken);
+            assert!(err.contains("animation of `water`"), "{err}");
+        }
+    }
+
+    #[test]
+    fn every_entity_type_has_one_appearance_with_a_glyph() {
+        let registry = ContentRegistry::builtin();
+        for entity_type in EntityType::ALL {
+            let appearance = registry.appearance(&entity_type).expect("validated");
+            assert_eq!(appearance.entity_type, entity_type.name());
+        }
+        let water = registry.appearance(&EntityType::Water).expect("validated");
+        assert_eq!(registry.layer(&EntityType::Water), RenderLayer::Terrain);
+        assert_eq!((water.fg, water.bg), ((40, 90, 200), (0, 0, 0)));
+        assert_eq!(water.style.remembered, 35, "the default");
+
+        for (from, to, expected) in [
+            ("\"corpse\"", "\"dragon\"", "unknown entity type `dragon`"),
+            ("\"npc\"", "\"player\"", "`player` has two appearances"),
+            ("glyph: \"丄\"", "glyph: \"\"", "`stump` has an empty glyph"),
+            (
+                "(entity_type: \"bridge\"",
+                "(kind: \"bridge\"",
+                "appearance.ron",
+            ),
+            (
+                "(entity_type: \"bridge\", layer: FloorDecal, glyph: \"=\", fg: (110, 75, 40)),",
+                "",
+                "`bridge` has no appearance",
+            ),
+        ] {
+            match with_appearance(from, to) {
+                Ok(_) => panic!("`{to}` in place of `{from}` is accepted"),
+                Err(err) => assert!(err.contains(expected), "{err}"),
+            }
+        }
+    }
+}
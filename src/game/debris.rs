@@ -0,0 +1,23 @@
+//! Destructible terrain.
+//!
+//! Destroyed terrain is not simply removed: a tree leaves a stump, which no
+//! longer blocks sight but still blocks the way, and a stump leaves rubble
+//! that can be walked over. See [`EntityType::debris`].
+//!
+//! Clients notice the change through their [`OpaqueSet`](super::fov::OpaqueSet),
+//! which recomputes the view of every player that could see it.
+
+use super::{EntityID, EntityType, GameState};
+
+/// Destroy `eid`, turning it into its debris or removing it if nothing is
+/// left. Returns what it became, or `None` if it is gone.
+pub fn destroy(state: &mut GameState, eid: EntityID) -> Option<EntityType> {
+    let entity = state.entity_mut(eid)?;
+    if let Some(debris) = entity.entity_type.debris() {
+        entity.entity_type = debris.clone();
+        Some(debris)
+    } else {
+        state.remove_entity(eid);
+        None
+    }
+}
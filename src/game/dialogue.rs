@@ -0,0 +1,182 @@
+//! Conversations with NPCs.
+//!
+//! An entity whose [`Entity::dialogue`](super::Entity::dialogue) names a
+//! [`DialogueTree`] can be talked to with [`GameAction::Talk`]. The player's
+//! place in the tree is kept on the player entity as a [`Conversation`], so
+//! it is part of the simulated state like everything else; the server sends
+//! each player the [`DialogueView`] of the node they are at. Picking an
+//! option applies its [`Effect`]s and moves on, or ends the conversation.
+//!
+//! [`GameAction::Talk`]: super::GameAction::Talk
+
+use super::content::ContentRegistry;
+use super::math;
+use super::{EntityID, GameState, Point};
+
+use bitcode::{Decode, Encode};
+use serde::Deserialize;
+
+/// Furthest an NPC can be, in tiles along either axis, to talk to it.
+pub const TALK_RANGE: i32 = 1;
+
+/// A dialogue tree, as written in `assets/content/dialogue.ron`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct DialogueTree {
+    pub id: String,
+    /// Shown as the speaker when the NPC has no name of its own.
+    pub speaker: String,
+    /// ID of the node conversations start at.
+    pub start: String,
+    pub nodes: Vec<DialogueNode>,
+}
+
+impl DialogueTree {
+    pub fn node(&self, id: &str) -> Option<&DialogueNode> {
+        self.nodes.iter().find(|n| n.id == id)
+    }
+}
+
+/// One thing the NPC says, with the player's possible answers.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct DialogueNode {
+    pub id: String,
+    pub text: String,
+    pub options: Vec<DialogueOption>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct DialogueOption {
+    pub text: String,
+    /// Node to continue at, or `None` to end the conversation.
+    pub next: Option<String>,
+    #[serde(default)]
+    pub effects: Vec<Effect>,
+}
+
+/// What picking an option does to the player.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub enum Effect {
+    /// Change the player's reputation with a faction.
+    Reputation(String, i32),
+    /// Tag the player, e.g. to mark a quest as started.
+    Tag(String),
+    /// Remove a tag from the player.
+    Untag(String),
+}
+
+/// Where a player is in a conversation.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Encode, Decode)]
+pub struct Conversation {
+    pub npc: EntityID,
+    pub tree: String,
+    pub node: String,
+}
+
+/// What the client shows of the current node.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct DialogueView {
+    pub speaker: String,
+    pub text: String,
+    pub options: Vec<String>,
+}
+
+/// Whether two points are close enough to talk.
+pub fn in_range(a: Point, b: Point) -> bool {
+    math::within_square(a, b, TALK_RANGE)
+}
+
+/// Start a conversation between `player` and `npc`. Returns `false` if
+/// `npc` has no dialogue or is out of range; an ongoing conversation is
+/// replaced.
+pub fn talk(
+    state: &mut GameState,
+    registry: &ContentRegistry,
+    player: EntityID,
+    npc: EntityID,
+) -> bool {
+    let Some(target) = state.entities.get(&npc) else {
+        return false;
+    };
+    let Some(tree) = target
+        .dialogue
+        .as_deref()
+        .and_then(|id| registry.dialogue(id))
+    else {
+        return false;
+    };
+    let npc_position = target.position;
+    let conversation = Conversation {
+        npc,
+        tree: tree.id.clone(),
+        node: tree.start.clone(),
+    };
+    match state.entity_mut(player) {
+        Some(entity) if player != npc && in_range(entity.position, npc_position) => {
+            entity.conversation = Some(conversation);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Pick option `index` of the node `player` is at. Returns `false` if the
+/// player is not talking or there is no such option.
+pub fn choose(
+    state: &mut GameState,
+    registry: &ContentRegistry,
+    player: EntityID,
+    index: u32,
+) -> bool {
+    let Some(entity) = state.entity_mut(player) else {
+        return false;
+    };
+    let Some(option) = entity
+        .conversation
+        .as_ref()
+        .and_then(|c| registry.dialogue(&c.tree)?.node(&c.node))
+        .and_then(|node| node.options.get(index as usize))
+    else {
+        return false;
+    };
+
+    for effect in &option.effects {
+        match effect {
+            Effect::Reputation(faction, delta) => {
+                entity.reputation.adjust(faction, *delta);
+            }
+            // A full tag set only loses the flag, not the conversation.
+            Effect::Tag(tag) => {
+                entity.tags.insert(tag).ok();
+            }
+            Effect::Untag(tag) => {
+                entity.tags.remove(tag);
+            }
+        }
+    }
+    match (&option.next, &mut entity.conversation) {
+        (Some(next), Some(conversation)) => conversation.node.clone_from(next),
+        _ => entity.conversation = None,
+    }
+    true
+}
+
+/// The node `player` is at, as shown to them.
+pub fn view(
+    state: &GameState,
+    registry: &ContentRegistry,
+    player: EntityID,
+) -> Option<DialogueView> {
+    let conversation = state.entities.get(&player)?.conversation.as_ref()?;
+    let tree = registry.dialogue(&conversation.tree)?;
+    let node = tree.node(&conversation.node)?;
+    let speaker = state
+        .entities
+        .get(&conversation.npc)
+        .and_then(|npc| npc.name.clone())
+        .unwrap_or_else(|| tree.speaker.clone());
+    Some(DialogueView {
+        speaker,
+        text: node.text.clone(),
+        options: node.options.iter().map(|o| o.text.clone()).collect(),
+    })
+}
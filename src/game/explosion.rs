@@ -0,0 +1,153 @@
+//! Explosions and other area-of-effect blasts.
+//!
+//! A blast reaches every tile within its radius that has a line of sight to
+//! the origin, using the same shadowcasting as [`fov`](super::fov): sight
+//! blockers such as trees are hit themselves but shelter what lies behind
+//! them. Within that area, terrain is wrecked into
+//! [`debris`](super::debris), entities with
+//! [`Health`](super::Health) take damage that falls off with distance, and
+//! everything that can move is knocked away from the origin.
+//!
+//! The whole blast is reported as one [`GameEvent::Exploded`].
+//!
+//! Players set blasts off by throwing a [`BOMB`] item at a tile they can see
+//! within [`THROW_RANGE`].
+
+use super::collision;
+use super::combat;
+use super::debris;
+use super::fov;
+use super::math;
+use super::path;
+use super::{EntityID, EntityType, GameEvent, GameState, Point};
+
+/// Largest radius a blast may have.
+pub const MAX_BLAST_RADIUS: i32 = 16;
+
+/// Item that explodes when thrown.
+pub const BOMB: &str = "bomb";
+
+/// Furthest a bomb can be thrown, in tiles.
+pub const THROW_RANGE: i32 = 6;
+
+/// How strong a blast is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Blast {
+    pub origin: Point,
+    pub radius: i32,
+    /// Damage at the origin; it falls off linearly to the edge.
+    pub damage: u32,
+    /// Tiles entities are pushed away from the origin.
+    pub knockback: u32,
+}
+
+impl Blast {
+    /// The blast of a thrown [`BOMB`].
+    pub fn bomb(origin: Point) -> Self {
+        Self {
+            origin,
+            radius: 3,
+            damage: 40,
+            knockback: 2,
+        }
+    }
+}
+
+/// What a blast did, carried by [`GameEvent::Exploded`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explosion {
+    pub origin: Point,
+    /// Tiles the blast reached, sorted by row then column.
+    pub tiles: Vec<Point>,
+    /// Entities that took damage.
+    pub damaged: Vec<EntityID>,
+    /// Entities that were knocked back, with where they started and
+    /// landed.
+    pub pushed: Vec<(EntityID, Point, Point)>,
+    /// Entities destroyed: terrain wrecked into debris or removed, and
+    /// creatures killed.
+    pub destroyed: Vec<EntityID>,
+    /// The creatures killed, with their names.
+    pub killed: Vec<(EntityID, Option<String>)>,
+}
+
+/// The tiles `blast` reaches, sorted by row then column.
+pub fn affected_tiles(state: &GameState, blast: &Blast) -> Vec<Point> {
+    let radius = blast.radius.clamp(0, MAX_BLAST_RADIUS);
+    let is_opaque = |p| fov::blocks_sight(state, p);
+    let mut tiles: Vec<Point> = fov::compute_fov(blast.origin, radius, is_opaque)
+        .into_iter()
+        .collect();
+    tiles.sort_by_key(|p| (p.y, p.x));
+    tiles
+}
+
+/// Set off `blast` and report what it did.
+pub fn explode(state: &mut GameState, blast: &Blast) -> GameEvent {
+    let tiles = affected_tiles(state, blast);
+    let mut explosion = Explosion {
+        origin: blast.origin,
+        tiles: Vec::new(),
+        damaged: Vec::new(),
+        pushed: Vec::new(),
+        destroyed: Vec::new(),
+        killed: Vec::new(),
+    };
+
+    // Entities are handled in ID order, from the state before the blast.
+    let reached: Vec<(EntityID, Point, EntityType)> = state
+        .entities
+        .iter()
+        .filter(|(_, e)| {
+            tiles
+                .binary_search_by_key(&(e.position.y, e.position.x), |p| (p.y, p.x))
+                .is_ok()
+        })
+        .map(|(eid, e)| (*eid, e.position, e.entity_type.clone()))
+        .collect();
+
+    for (eid, position, entity_type) in reached {
+        if entity_type.is_destructible() {
+            debris::destroy(state, eid);
+            explosion.destroyed.push(eid);
+            continue;
+        }
+        let falloff = math::chebyshev(position, blast.origin);
+        let reach = blast.radius.unsigned_abs() + 1;
+        let damage = math::scale(blast.damage, reach.saturating_sub(falloff), reach);
+        if combat::damage(state, eid, damage) > 0 {
+            explosion.damaged.push(eid);
+        }
+        if let Some(direction) = path::direction_towards(blast.origin, position)
+            && let Some(GameEvent::Pushed { to, .. }) =
+                collision::push(state, eid, direction, blast.knockback)
+        {
+            explosion.pushed.push((eid, position, to));
+        }
+    }
+
+    explosion.killed = combat::resolve_deaths(state);
+    explosion
+        .destroyed
+        .extend(explosion.killed.iter().map(|(eid, _)| *eid));
+    explosion.tiles = tiles;
+    GameEvent::Exploded(explosion)
+}
+
+/// `thrower` throws a [`BOMB`] from its inventory at `target`, which must be
+/// in sight and within [`THROW_RANGE`].
+///
+/// Returns `None` if the thrower has no bomb or cannot reach the target.
+pub fn throw_bomb(state: &mut GameState, thrower: EntityID, target: Point) -> Option<GameEvent> {
+    let entity = state.entities.get(&thrower)?;
+    let from = entity.position;
+    if entity.inventory.count(BOMB) == 0 || !math::within_square(from, target, THROW_RANGE) {
+        return None;
+    }
+    let is_opaque = |p| fov::blocks_sight(state, p);
+    if !fov::compute_fov(from, THROW_RANGE, is_opaque).contains(&target) {
+        return None;
+    }
+    state.entity_mut(thrower)?.inventory.remove(BOMB, 1);
+    Some(explode(state, &Blast::bomb(target)))
+}
@@ -0,0 +1,156 @@
+//! Factions and per-player reputation.
+//!
+//! Factions are defined as data in the [`ContentRegistry`], with a standing
+//! between every pair from -100 (sworn enemies) to 100 (allies). Each player
+//! carries a [`Reputation`] with every faction, which the player's actions
+//! move up or down; an NPC treats a player as hostile once that reputation
+//! drops low enough.
+
+use super::content::ContentRegistry;
+use crate::profile;
+
+use bitcode::{Decode, Encode};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Lowest reputation or standing.
+pub const MIN_REPUTATION: i32 = -100;
+
+/// Highest reputation or standing.
+pub const MAX_REPUTATION: i32 = 100;
+
+/// Reputation at or below which members of a faction attack on sight.
+pub const HOSTILE_BELOW: i32 = -25;
+
+/// Reputation lost with a faction for attacking one of its members.
+pub const ATTACK_PENALTY: i32 = 10;
+
+/// A faction, as written in `assets/content/factions.ron`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct FactionDef {
+    pub id: String,
+    pub name: String,
+    /// Standing towards other factions, by ID. Missing pairs are neutral.
+    #[serde(default)]
+    pub standings: BTreeMap<String, i32>,
+}
+
+impl ContentRegistry {
+    /// Standing between factions `a` and `b`, whichever side declared it.
+    /// A faction always stands at [`MAX_REPUTATION`] with itself.
+    pub fn standing(&self, a: &str, b: &str) -> i32 {
+        if a == b {
+            return MAX_REPUTATION;
+        }
+        let declared = |from: &str, to: &str| {
+            self.faction(from)
+                .and_then(|f| f.standings.get(to))
+                .copied()
+        };
+        declared(a, b).or_else(|| declared(b, a)).unwrap_or(0)
+    }
+}
+
+/// Coarse reading of a reputation value, for display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tier {
+    Hostile,
+    Unfriendly,
+    Neutral,
+    Friendly,
+    Allied,
+}
+
+impl Tier {
+    pub fn of(value: i32) -> Self {
+        match value {
+            ..=HOSTILE_BELOW => Self::Hostile,
+            -24..=-10 => Self::Unfriendly,
+            -9..=24 => Self::Neutral,
+            25..=74 => Self::Friendly,
+            _ => Self::Allied,
+        }
+    }
+}
+
+impl fmt::Display for Tier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Hostile => "hostile",
+            Self::Unfriendly => "unfriendly",
+            Self::Neutral => "neutral",
+            Self::Friendly => "friendly",
+            Self::Allied => "allied",
+        })
+    }
+}
+
+/// A player's reputation with each faction, by faction ID. Factions not
+/// listed are neutral (0).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Encode, Decode)]
+pub struct Reputation(BTreeMap<String, i32>);
+
+impl Reputation {
+    pub fn get(&self, faction: &str) -> i32 {
+        self.0.get(faction).copied().unwrap_or(0)
+    }
+
+    /// Set the reputation with `faction`, clamped to the valid range.
+    /// Neutral entries are dropped so the map only holds what matters.
+    pub fn set(&mut self, faction: &str, value: i32) {
+        let value = value.clamp(MIN_REPUTATION, MAX_REPUTATION);
+        if value == 0 {
+            self.0.remove(faction);
+        } else {
+            self.0.insert(faction.to_owned(), value);
+        }
+    }
+
+    /// Change the reputation with `faction` by `delta`. Returns the new value.
+    pub fn adjust(&mut self, faction: &str, delta: i32) -> i32 {
+        self.set(faction, self.get(faction).saturating_add(delta));
+        self.get(faction)
+    }
+
+    /// Non-neutral reputations, sorted by faction ID.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, i32)> {
+        self.0
+            .iter()
+            .map(|(faction, value)| (faction.as_str(), *value))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Bytes the reputation owns on the heap.
+    pub fn heap_bytes(&self) -> usize {
+        profile::btree_map_bytes(&self.0) + self.0.keys().map(String::capacity).sum::<usize>()
+    }
+
+    /// Record an attack on a member of `victim`.
+    ///
+    /// The victim's faction loses [`ATTACK_PENALTY`]; every other faction
+    /// reacts in proportion to its standing with the victim, so allies of
+    /// the victim take offense and its enemies approve.
+    pub fn record_attack(&mut self, registry: &ContentRegistry, victim: &str) {
+        for faction in &registry.factions {
+            let standing = registry.standing(&faction.id, victim);
+            let delta = -ATTACK_PENALTY * standing / MAX_REPUTATION;
+            if delta != 0 {
+                self.adjust(&faction.id, delta);
+            }
+        }
+    }
+
+    /// Whether members of `faction` are hostile to whoever holds this
+    /// reputation.
+    pub fn is_hostile(&self, faction: &str) -> bool {
+        self.get(faction) <= HOSTILE_BELOW
+    }
+}
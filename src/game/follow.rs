@@ -0,0 +1,103 @@
+//! Followers: owned entities that stay close to their owner, like pets.
+//!
+//! A follower with a [`Follow`] leash idles while its owner is within the
+//! leash radius. Once the owner gets further away, the follower walks back
+//! one tile per tick, or slower when burdened, until it is next to its owner
+//! again. Starting and
+//! stopping at different distances keeps a follower on the edge of its leash
+//! from stepping back and forth every tick. A follower left more than
+//! [`TELEPORT_DISTANCE`] tiles behind is moved next to its owner at once.
+//!
+//! Group orders take precedence: a follower with a
+//! [`destination`](super::Entity::destination) ignores its leash until it
+//! arrives.
+
+use super::collision;
+use super::item;
+use super::math;
+use super::path;
+use super::region::ActiveRegions;
+use super::{ContentRegistry, EntityID, GameState};
+
+use bitcode::{Decode, Encode};
+
+/// Leash radius of a new follower.
+pub const DEFAULT_LEASH: u32 = 3;
+
+/// Distance at which a follower catches up by teleporting.
+pub const TELEPORT_DISTANCE: u32 = 24;
+
+/// Keeps an entity near its [`owner`](super::Entity::owner).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub struct Follow {
+    /// Distance from the owner the follower tolerates before walking back.
+    pub leash: u32,
+    /// Whether the follower is walking back to its owner.
+    pub catching_up: bool,
+}
+
+impl Follow {
+    pub fn new(leash: u32) -> Self {
+        Self {
+            leash,
+            catching_up: false,
+        }
+    }
+}
+
+/// Move every follower in an `active` region that strayed too far from its
+/// owner, and return the ones that moved.
+pub fn advance(
+    state: &mut GameState,
+    registry: &ContentRegistry,
+    active: &ActiveRegions,
+) -> Vec<EntityID> {
+    let tick = state.tick;
+    let followers: Vec<(EntityID, EntityID)> = state
+        .entities
+        .iter()
+        .filter(|(_, e)| e.follow.is_some() && e.destination.is_none())
+        .filter(|(_, e)| active.contains(e.position))
+        .filter_map(|(eid, e)| Some((*eid, e.owner?)))
+        .collect();
+
+    let mut moved = Vec::new();
+    for (eid, owner) in followers {
+        let Some(target) = state.entities.get(&owner).map(|o| o.position) else {
+            continue;
+        };
+        let Some(entity) = state.entity_mut(eid) else {
+            continue;
+        };
+        let Some(follow) = &mut entity.follow else {
+            continue;
+        };
+        let from = entity.position;
+        let distance = math::manhattan(from, target);
+        if distance <= 1 {
+            follow.catching_up = false;
+            continue;
+        }
+        if distance > follow.leash {
+            follow.catching_up = true;
+        }
+        if !follow.catching_up {
+            continue;
+        }
+
+        let to = if distance > TELEPORT_DISTANCE {
+            collision::free_tile_near(state, target)
+        } else if !item::can_step(entity, registry, tick) {
+            continue;
+        } else {
+            let Some(direction) = path::first_step(from, target, |p| collision::cost_at(state, p))
+            else {
+                continue;
+            };
+            path::step(from, direction)
+        };
+        state.place_entity(eid, to);
+        moved.push(eid);
+    }
+    moved
+}
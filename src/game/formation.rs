@@ -0,0 +1,321 @@
+//! Group movement.
+//!
+//! A player can send itself and the entities it owns to a tile with one
+//! [`GameAction::MoveGroup`](super::GameAction::MoveGroup). Each member gets
+//! its own destination, keeping its place around the first member (the
+//! leader) so the group arrives in roughly the shape it left in; members
+//! that were scattered close up to within [`FORMATION_SPREAD`] tiles.
+//!
+//! Members then walk one tile per tick in [`advance`], or slower when
+//! burdened. No two entities ever end up on the same tile.
+//!
+//! Walkers of the same [team](super::ping::same_team) give way to each
+//! other rather than scatter. Each plans its step as if the friendly
+//! walkers in its way were to move on, at [`CROWD_COST`] more a tile, so
+//! it follows them through a doorway instead of taking the long way
+//! around. Steps are then taken in order of priority, whoever has waited
+//! longest first and then the lowest ID, each onto a tile that is free or
+//! comes free as the walker on it moves on; a walker that finds its tile
+//! still taken waits its turn. Two walkers stepping onto each other's tile,
+//! e.g. from either end of a narrow bridge, pass each other.
+//!
+//! Each step is planned afresh against the world as it is, so a walker goes
+//! around a tree felled or a player stepping into its way since it set out.
+//! When no step brings it closer, it waits for the way to clear; after
+//! [`PATH_PATIENCE`] ticks of that it gives up with a
+//! [`GameEvent::PathBlocked`] rather than bumping into the obstacle forever.
+
+use super::collision;
+use super::item;
+use super::math;
+use super::path;
+use super::ping;
+use super::region::ActiveRegions;
+use super::{ContentRegistry, Entity, EntityID, GameEvent, GameState, Point};
+
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// Most entities one group order may move.
+pub const MAX_GROUP_SIZE: usize = 32;
+
+/// Furthest a member's destination may be from the leader's, per axis.
+pub const FORMATION_SPREAD: i32 = 3;
+
+/// Ticks a walker waits for a blocked way to clear before giving up.
+pub const PATH_PATIENCE: u64 = 20;
+
+/// Extra cost of planning through a tile a friendly walker is on: how much
+/// further around a walker would rather go than wait behind it.
+pub const CROWD_COST: u32 = 4;
+
+/// Send `entity` walking to `target`.
+pub fn walk_to(entity: &mut Entity, target: Point) {
+    entity.destination = Some(target);
+    entity.blocked_since = None;
+}
+
+/// Stop `entity` walking, wherever it got to.
+pub fn stop(entity: &mut Entity) {
+    entity.destination = None;
+    entity.blocked_since = None;
+}
+
+/// Returns `true` if `actor` may give orders to `member`: itself, or an
+/// entity it owns.
+pub fn commands(state: &GameState, actor: EntityID, member: EntityID) -> bool {
+    member == actor
+        || state
+            .entities
+            .get(&member)
+            .is_some_and(|e| e.owner == Some(actor))
+}
+
+/// Send the `members` that `actor` commands to `target`, in formation around
+/// the first of them. Returns the members that were given a destination.
+pub fn order(
+    state: &mut GameState,
+    actor: EntityID,
+    members: &[EntityID],
+    target: Point,
+) -> Vec<EntityID> {
+    let mut ordered: Vec<EntityID> = Vec::new();
+    for member in members.iter().take(MAX_GROUP_SIZE) {
+        if !ordered.contains(member)
+            && state.entities.contains_key(member)
+            && commands(state, actor, *member)
+        {
+            ordered.push(*member);
+        }
+    }
+    let Some(leader) = ordered
+        .first()
+        .and_then(|eid| state.entities.get(eid))
+        .map(|e| e.position)
+    else {
+        return ordered;
+    };
+
+    for eid in &ordered {
+        if let Some(entity) = state.entity_mut(*eid) {
+            let dx = (entity.position.x - leader.x).clamp(-FORMATION_SPREAD, FORMATION_SPREAD);
+            let dy = (entity.position.y - leader.y).clamp(-FORMATION_SPREAD, FORMATION_SPREAD);
+            let target = Point {
+                x: target.x.saturating_add(dx),
+                y: target.y.saturating_add(dy),
+            };
+            walk_to(entity, target);
+        }
+    }
+    ordered
+}
+
+/// Move every entity with a destination in an `active` region one tile
+/// along its path, and report the ones that gave up.
+///
+/// A member whose own tile is taken stops once it is within
+/// [`FORMATION_SPREAD`] tiles of it; further away it waits for the way to
+/// clear, for up to [`PATH_PATIENCE`] ticks.
+pub fn advance(
+    state: &mut GameState,
+    registry: &ContentRegistry,
+    active: &ActiveRegions,
+) -> Vec<GameEvent> {
+    let tick = state.tick;
+    let mut walkers: Vec<(u64, EntityID)> = Vec::new();
+    let mut arrived = Vec::new();
+    for (eid, entity) in &state.entities {
+        let Some(destination) = entity.destination else {
+            continue;
+        };
+        if !active.contains(entity.position) {
+            continue;
+        }
+        if entity.position == destination {
+            arrived.push(*eid);
+        } else {
+            walkers.push((entity.blocked_since.unwrap_or(tick), *eid));
+        }
+    }
+    for eid in arrived {
+        if let Some(entity) = state.entity_mut(eid) {
+            stop(entity);
+        }
+    }
+    // Whoever has waited longest goes first, then the lowest ID.
+    walkers.sort_unstable();
+    let walkers: Vec<EntityID> = walkers.into_iter().map(|(_, eid)| eid).collect();
+
+    let mut crowd = Crowd {
+        walking: walkers
+            .iter()
+            .filter_map(|eid| Some((state.entities.get(eid)?.position, *eid)))
+            .collect(),
+    };
+    let mut events = Vec::new();
+    let mut steps = Vec::new();
+    for eid in walkers {
+        match crowd.plan(state, registry, eid) {
+            Plan::Step(to) => steps.push((eid, to)),
+            Plan::Wait => {}
+            Plan::Stuck => {
+                if let Some(event) = stuck(state, eid) {
+                    events.push(event);
+                }
+            }
+        }
+    }
+
+    let moved = crowd.resolve(state, &steps);
+    for (eid, _) in steps {
+        let Some(entity) = state.entity_mut(eid) else {
+            continue;
+        };
+        if moved.contains(&eid) {
+            entity.blocked_since = None;
+            if Some(entity.position) == entity.destination {
+                stop(entity);
+            }
+        } else if let Some(event) = wait(entity, eid, tick) {
+            events.push(event);
+        }
+    }
+    events
+}
+
+/// What a walker means to do this tick.
+enum Plan {
+    /// Step onto this tile, if it is free by then.
+    Step(Point),
+    /// Stay put; it cannot step this tick.
+    Wait,
+    /// Stay put; no step brings it closer.
+    Stuck,
+}
+
+/// The tiles walkers are on as they move.
+struct Crowd {
+    /// The walker on each tile, if any.
+    walking: FxHashMap<Point, EntityID>,
+}
+
+impl Crowd {
+    /// Plan the next step of `eid`, as if the friendly walkers in its way
+    /// were to move on, for [`CROWD_COST`] more a tile.
+    fn plan(&self, state: &GameState, registry: &ContentRegistry, eid: EntityID) -> Plan {
+        let Some(entity) = state.entities.get(&eid) else {
+            return Plan::Wait;
+        };
+        let Some(destination) = entity.destination else {
+            return Plan::Wait;
+        };
+        if !item::can_step(entity, registry, state.tick) {
+            return Plan::Wait;
+        }
+        let friendly = |point: Point| {
+            self.walking
+                .get(&point)
+                .and_then(|other| state.entities.get(other))
+                .is_some_and(|other| ping::same_team(entity, other))
+        };
+        let cost = |point: Point| {
+            if friendly(point) {
+                collision::cost_past(state, point, 1).map(|cost| cost + CROWD_COST)
+            } else {
+                collision::cost_at(state, point)
+            }
+        };
+        match path::first_step(entity.position, destination, cost) {
+            Some(direction) => Plan::Step(path::step(entity.position, direction)),
+            None => Plan::Stuck,
+        }
+    }
+
+    /// Carry out the planned `steps`, in order, onto tiles that are free or
+    /// come free as others step off them, until no one else can move. Two
+    /// walkers stepping onto each other's tile pass each other. Returns the
+    /// walkers that moved.
+    fn resolve(
+        &mut self,
+        state: &mut GameState,
+        steps: &[(EntityID, Point)],
+    ) -> FxHashSet<EntityID> {
+        let wants: FxHashMap<EntityID, Point> = steps.iter().copied().collect();
+        let mut moved = FxHashSet::default();
+        let mut progress = true;
+        while progress {
+            progress = false;
+            for &(eid, to) in steps {
+                if moved.contains(&eid) {
+                    continue;
+                }
+                let Some(from) = state.entities.get(&eid).map(|e| e.position) else {
+                    continue;
+                };
+                if !collision::is_blocked(state, to) {
+                    self.shift(state, eid, from, to);
+                    moved.insert(eid);
+                    progress = true;
+                    continue;
+                }
+                let Some(&other) = self.walking.get(&to) else {
+                    continue;
+                };
+                if !moved.contains(&other)
+                    && wants.get(&other) == Some(&from)
+                    && collision::cost_past(state, to, 1).is_some()
+                    && collision::cost_past(state, from, 1).is_some()
+                {
+                    self.shift(state, eid, from, to);
+                    self.shift(state, other, to, from);
+                    moved.extend([eid, other]);
+                    progress = true;
+                }
+            }
+        }
+        moved
+    }
+
+    /// Move `eid` from `from` to `to`.
+    fn shift(&mut self, state: &mut GameState, eid: EntityID, from: Point, to: Point) {
+        state.place_entity(eid, to);
+        if self.walking.get(&from) == Some(&eid) {
+            self.walking.remove(&from);
+        }
+        self.walking.insert(to, eid);
+    }
+}
+
+/// `eid` found no step that brings it closer: it stops if it is within
+/// [`FORMATION_SPREAD`] tiles of its destination, and waits otherwise.
+fn stuck(state: &mut GameState, eid: EntityID) -> Option<GameEvent> {
+    let tick = state.tick;
+    let entity = state.entity_mut(eid)?;
+    let destination = entity.destination?;
+    if math::manhattan(entity.position, destination) <= FORMATION_SPREAD.unsigned_abs() {
+        stop(entity);
+        None
+    } else {
+        wait(entity, eid, tick)
+    }
+}
+
+/// `eid` waits for its way to clear this tick. Once it has waited for
+/// [`PATH_PATIENCE`] ticks it gives up, reported as a
+/// [`GameEvent::PathBlocked`].
+fn wait(entity: &mut Entity, eid: EntityID, tick: u64) -> Option<GameEvent> {
+    match entity.blocked_since {
+        Some(since) if tick.saturating_sub(since) >= PATH_PATIENCE => {
+            let destination = entity.destination?;
+            stop(entity);
+            Some(GameEvent::PathBlocked {
+                entity_id: eid,
+                destination,
+            })
+        }
+        Some(_) => None,
+        None => {
+            entity.blocked_since = Some(tick);
+            None
+        }
+    }
+}
@@ -0,0 +1,714 @@
+//! Field of view via symmetric shadowcasting.
+//!
+//! The world is scanned in four quadrants, row by row outwards from the
+//! origin. Slopes are exact fractions of integers rather than floats, so the
+//! result is bit-identical on every platform and there are no rounding
+//! artifacts where quadrants meet on the diagonals. The algorithm is
+//! symmetric: if `b` is visible from `a`, then `a` is visible from `b`.
+//!
+//! Results are stored in a [`FovMask`], a bitset over the square window
+//! around the origin, so recomputing a player's view every tick reuses one
+//! small allocation instead of building a hash set.
+//!
+//! A [`PlayerFov`] is only recomputed when the player moves, its radius
+//! changes, or the [`OpaqueSet`] changes inside its window; a tree felled on
+//! the other side of the world leaves everyone else's view alone. Opaque
+//! [terrain](super::terrain) is looked up as the view is cast, and does not
+//! change unless the view is [invalidated](PlayerFov::invalidate).
+//!
+//! Awareness is kept up to date the same way: a [`SpatialIndex`] follows
+//! the entities that changed, and an [`Awareness`] only rescans its window when
+//! the view was recomputed, otherwise checking just the entities that
+//! moved. Each update reports who entered and left the set.
+
+use super::math;
+use super::path::{self, DIRECTIONS};
+use super::{EntityID, EntityMap, EntityType, GameState, Point, SpatialIndex, TileMap};
+use crate::profile;
+
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::collections::{BTreeSet, VecDeque};
+
+/// How far players can see.
+pub const VIEW_RADIUS: i32 = 12;
+
+/// Distance within which a player notices entities even without line of
+/// sight.
+pub const AWARENESS_MARGIN: i32 = 2;
+
+/// Most tiles [`ExploredMap::nearest_frontier`] looks through.
+pub const MAX_FRONTIER_SEARCH: usize = 16384;
+
+/// Returns `true` if something on `point` blocks line of sight, an entity
+/// or its terrain, as looked up in the state's synced
+/// [`index`](GameState::index).
+pub fn blocks_sight(state: &GameState, point: Point) -> bool {
+    state.terrain.get(point).blocks_sight()
+        || state.index.entities_at(point).iter().any(|eid| {
+            state
+                .entities
+                .get(eid)
+                .is_some_and(|e| e.entity_type.blocks_sight())
+        })
+}
+
+/// The positions that block line of sight, with a version that increases
+/// every time they change.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OpaqueSet {
+    positions: FxHashSet<Point>,
+    version: u64,
+    /// Tiles that became or stopped being opaque in the latest version,
+    /// sorted by row then column.
+    changed: Vec<Point>,
+}
+
+impl OpaqueSet {
+    /// The opaque positions among `entities`.
+    pub fn of(entities: &EntityMap) -> Self {
+        let mut opaque = Self::default();
+        opaque.sync(entities);
+        opaque
+    }
+
+    /// Bring the set up to date with `entities`, starting a new version if
+    /// anything changed. Returns `true` if it did.
+    pub fn sync(&mut self, entities: &EntityMap) -> bool {
+        let positions: FxHashSet<Point> = entities
+            .values()
+            .filter(|e| e.entity_type.blocks_sight())
+            .map(|e| e.position)
+            .collect();
+        if positions == self.positions {
+            return false;
+        }
+        let mut changed: Vec<Point> = positions
+            .symmetric_difference(&self.positions)
+            .copied()
+            .collect();
+        changed.sort_by_key(|p| (p.y, p.x));
+        self.positions = positions;
+        self.changed = changed;
+        self.version += 1;
+        true
+    }
+
+    pub fn contains(&self, point: Point) -> bool {
+        self.positions.contains(&point)
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Bring the set up to date with `entities` as indexed by `tiles`,
+    /// which was just synced: only the tiles that sync changed are looked
+    /// at, unless it rebuilt the index. Returns `true` if the set changed.
+    ///
+    /// The set must have followed the same index since it was built.
+    pub fn update(&mut self, entities: &EntityMap, tiles: &SpatialIndex) -> bool {
+        let Some(points) = tiles.changed_tiles() else {
+            return self.sync(entities);
+        };
+        let changed: Vec<Point> = points
+            .iter()
+            .copied()
+            .filter(|point| {
+                let opaque = tiles.entities_at(*point).iter().any(|eid| {
+                    entities
+                        .get(eid)
+                        .is_some_and(|e| e.entity_type.blocks_sight())
+                });
+                if opaque {
+                    self.positions.insert(*point)
+                } else {
+                    self.positions.remove(point)
+                }
+            })
+            .collect();
+        if changed.is_empty() {
+            return false;
+        }
+        self.changed = changed;
+        self.version += 1;
+        true
+    }
+
+    /// Tiles changed by the latest version.
+    pub fn changed(&self) -> &[Point] {
+        &self.changed
+    }
+}
+
+/// Every position visible from `origin` within Euclidean distance `radius`.
+///
+/// Opaque tiles are visible themselves but hide what lies behind them.
+pub fn compute_fov(
+    origin: Point,
+    radius: i32,
+    is_opaque: impl Fn(Point) -> bool,
+) -> FxHashSet<Point> {
+    let mut visible = FxHashSet::default();
+    shadowcast(origin, radius, &is_opaque, &mut |p| {
+        visible.insert(p);
+    });
+    visible
+}
+
+/// Visible positions as a bitset over the window around the origin.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FovMask {
+    origin: Point,
+    radius: i32,
+    bits: Vec<u64>,
+}
+
+impl FovMask {
+    /// An empty mask covering the `(2 * radius + 1)²` window around `origin`.
+    pub fn new(origin: Point, radius: i32) -> Self {
+        let mut mask = Self {
+            origin,
+            radius: 0,
+            bits: Vec::new(),
+        };
+        mask.reset(origin, radius);
+        mask
+    }
+
+    /// Compute the field of view from `origin` into a new mask.
+    pub fn compute(origin: Point, radius: i32, is_opaque: impl Fn(Point) -> bool) -> Self {
+        let mut mask = Self::new(origin, radius);
+        mask.recompute(origin, radius, is_opaque);
+        mask
+    }
+
+    /// Recompute in place, keeping the allocation.
+    pub fn recompute(&mut self, origin: Point, radius: i32, is_opaque: impl Fn(Point) -> bool) {
+        self.reset(origin, radius);
+        shadowcast(origin, radius, &is_opaque, &mut |p| self.set(p));
+    }
+
+    /// Clear the mask and move its window.
+    fn reset(&mut self, origin: Point, radius: i32) {
+        let radius = radius.max(0);
+        let side = 2 * radius as usize + 1;
+        self.origin = origin;
+        self.radius = radius;
+        self.bits.clear();
+        self.bits.resize((side * side).div_ceil(64), 0);
+    }
+
+    pub fn origin(&self) -> Point {
+        self.origin
+    }
+
+    pub fn radius(&self) -> i32 {
+        self.radius
+    }
+
+    fn side(&self) -> i64 {
+        2 * i64::from(self.radius) + 1
+    }
+
+    /// Bit index of `point`, if it lies inside the window.
+    fn index(&self, point: Point) -> Option<usize> {
+        let radius = i64::from(self.radius);
+        let dx = i64::from(point.x) - i64::from(self.origin.x) + radius;
+        let dy = i64::from(point.y) - i64::from(self.origin.y) + radius;
+        let side = self.side();
+        ((0..side).contains(&dx) && (0..side).contains(&dy))
+            .then(|| usize::try_from(dy * side + dx).ok())
+            .flatten()
+    }
+
+    /// Mark `point` visible. Points outside the window are ignored.
+    pub fn set(&mut self, point: Point) {
+        if let Some(index) = self.index(point)
+            && let Some(word) = self.bits.get_mut(index / 64)
+        {
+            *word |= 1 << (index % 64);
+        }
+    }
+
+    /// Whether `point` lies inside the window, visible or not.
+    pub fn covers(&self, point: Point) -> bool {
+        self.index(point).is_some()
+    }
+
+    /// Whether `point` is visible.
+    pub fn contains(&self, point: Point) -> bool {
+        self.index(point).and_then(|index| {
+            self.bits
+                .get(index / 64)
+                .map(|word| (word >> (index % 64)) & 1)
+        }) == Some(1)
+    }
+
+    /// Number of visible positions.
+    pub fn len(&self) -> usize {
+        self.bits
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits.iter().all(|word| *word == 0)
+    }
+
+    /// Bytes held by the bitset.
+    pub fn memory_bytes(&self) -> usize {
+        profile::vec_bytes(&self.bits)
+    }
+
+    /// Visible positions, row by row.
+    pub fn iter(&self) -> impl Iterator<Item = Point> + '_ {
+        let side = self.side();
+        let corner = (
+            i64::from(self.origin.x) - i64::from(self.radius),
+            i64::from(self.origin.y) - i64::from(self.radius),
+        );
+        (0i64..)
+            .zip(&self.bits)
+            .flat_map(|(word_index, word)| {
+                let mut word = *word;
+                std::iter::from_fn(move || {
+                    (word != 0).then(|| {
+                        let bit = i64::from(word.trailing_zeros());
+                        word &= word - 1;
+                        word_index * 64 + bit
+                    })
+                })
+            })
+            .filter_map(move |index| {
+                Some(Point {
+                    x: i32::try_from(corner.0 + index % side).ok()?,
+                    y: i32::try_from(corner.1 + index / side).ok()?,
+                })
+            })
+    }
+}
+
+/// A player's current field of view, kept between ticks so recomputing it
+/// does not allocate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlayerFov {
+    pub entity_id: EntityID,
+    pub mask: FovMask,
+    /// How far the player sees; [`VIEW_RADIUS`] unless an event changes it.
+    pub radius: i32,
+    /// Origin, radius and [`OpaqueSet`] version the mask was computed for.
+    computed: Option<(Point, i32, u64)>,
+}
+
+impl PlayerFov {
+    pub fn new(entity_id: EntityID) -> Self {
+        Self {
+            entity_id,
+            mask: FovMask::new(Point { x: 0, y: 0 }, 0),
+            radius: VIEW_RADIUS,
+            computed: None,
+        }
+    }
+
+    /// Bring the view up to date with the player's position and `opaque`,
+    /// with `terrain` under it. The view is left empty if the player does
+    /// not exist.
+    ///
+    /// Returns `true` if the view had to be recomputed.
+    pub fn update(&mut self, entities: &EntityMap, opaque: &OpaqueSet, terrain: &TileMap) -> bool {
+        let Some(player) = entities.get(&self.entity_id) else {
+            self.mask.reset(Point { x: 0, y: 0 }, 0);
+            self.computed = None;
+            return false;
+        };
+        let key = (player.position, self.radius);
+        if let Some((origin, radius, version)) = self.computed
+            && (origin, radius) == key
+        {
+            // Only the latest version's changes are known; a view further
+            // behind than that is recomputed.
+            let out_of_sight = version + 1 == opaque.version()
+                && !opaque.changed().iter().any(|p| self.mask.covers(*p));
+            if version == opaque.version() || out_of_sight {
+                self.computed = Some((origin, radius, opaque.version()));
+                return false;
+            }
+        }
+        self.mask.recompute(key.0, key.1, |p| {
+            opaque.contains(p) || terrain.get(p).blocks_sight()
+        });
+        self.computed = Some((key.0, key.1, opaque.version()));
+        true
+    }
+
+    /// Recompute the view on the next update, after the terrain changed.
+    pub fn invalidate(&mut self) {
+        self.computed = None;
+    }
+}
+
+/// Every tile a player has seen, with the entity last seen on it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExploredMap {
+    tiles: FxHashMap<Point, Option<EntityType>>,
+}
+
+impl ExploredMap {
+    /// Remember what is currently in view.
+    pub fn update(&mut self, fov: &FovMask, entities: &EntityMap) {
+        for point in fov.iter() {
+            self.tiles.insert(point, None);
+        }
+        for entity in entities.values().filter(|e| fov.contains(e.position)) {
+            // A bridge is remembered rather than the water under it.
+            let tile = self.tiles.entry(entity.position).or_default();
+            if !(entity.entity_type == EntityType::Water && *tile == Some(EntityType::Bridge)) {
+                *tile = Some(entity.entity_type.clone());
+            }
+        }
+    }
+
+    /// Mark every tile from `min` to `max` as seen, with the entities on
+    /// them, e.g. to preview a whole world.
+    pub fn reveal(&mut self, min: Point, max: Point, entities: &EntityMap) {
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                self.tiles.insert(Point { x, y }, None);
+            }
+        }
+        for entity in entities.values() {
+            if let Some(tile) = self.tiles.get_mut(&entity.position) {
+                *tile = Some(entity.entity_type.clone());
+            }
+        }
+    }
+
+    /// `None` if `point` was never seen, otherwise the entity last seen there.
+    pub fn get(&self, point: Point) -> Option<Option<&EntityType>> {
+        self.tiles.get(&point).map(Option::as_ref)
+    }
+
+    /// Returns `true` if `point` was seen, and looked like something could
+    /// stand there: open ground, or a bridge over water.
+    pub fn is_open(&self, terrain: &TileMap, point: Point) -> bool {
+        match self.get(point) {
+            None => false,
+            Some(Some(EntityType::Bridge)) => true,
+            Some(Some(entity_type)) if entity_type.blocks_movement() => false,
+            Some(_) => !terrain.get(point).blocks_movement(),
+        }
+    }
+
+    /// The closest open tile to `from` next to a tile never seen, walking
+    /// only over open tiles: where to go to see more. `None` once everything
+    /// within reach was seen, or after [`MAX_FRONTIER_SEARCH`] tiles.
+    pub fn nearest_frontier(&self, terrain: &TileMap, from: Point) -> Option<Point> {
+        let mut visited = FxHashSet::from_iter([from]);
+        let mut queue = VecDeque::from([from]);
+        while let Some(point) = queue.pop_front() {
+            if point != from
+                && DIRECTIONS
+                    .iter()
+                    .any(|d| self.get(path::step(point, *d)).is_none())
+            {
+                return Some(point);
+            }
+            if visited.len() >= MAX_FRONTIER_SEARCH {
+                break;
+            }
+            for direction in DIRECTIONS {
+                let next = path::step(point, direction);
+                if self.is_open(terrain, next) && visited.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+
+    /// Number of tiles seen.
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    /// Estimated bytes held by the map.
+    pub fn memory_bytes(&self) -> usize {
+        profile::hash_map_bytes(&self.tiles)
+    }
+
+    /// Smallest and largest corner of the explored area.
+    pub fn bounds(&self) -> Option<(Point, Point)> {
+        self.tiles.keys().fold(None, |bounds, p| {
+            let (min, max) = bounds.unwrap_or((*p, *p));
+            Some((
+                Point {
+                    x: min.x.min(p.x),
+                    y: min.y.min(p.y),
+                },
+                Point {
+                    x: max.x.max(p.x),
+                    y: max.y.max(p.y),
+                },
+            ))
+        })
+    }
+}
+
+/// Entities a player with view `fov` is aware of: everything visible, plus
+/// anything within `margin` tiles of the player, sorted by ID.
+pub fn build_awareness(entities: &EntityMap, fov: &FovMask, margin: i32) -> Vec<EntityID> {
+    let origin = fov.origin();
+    entities
+        .iter()
+        .filter(|(_, e)| {
+            fov.contains(e.position) || math::within_square(origin, e.position, margin)
+        })
+        .map(|(eid, _)| *eid)
+        .collect()
+}
+
+/// Entities that entered and left an [`Awareness`] in one update, sorted by
+/// ID.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AwarenessDiff {
+    pub entered: Vec<EntityID>,
+    pub left: Vec<EntityID>,
+}
+
+/// The entities a player is aware of, as [`build_awareness`] gives them,
+/// kept up to date incrementally.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Awareness {
+    aware: BTreeSet<EntityID>,
+    /// Origin and radius of the view the set was computed for.
+    view: Option<(Point, i32)>,
+}
+
+impl Awareness {
+    /// Bring the set up to date with view `fov`. `moved` is what
+    /// [`SpatialIndex::sync`] returned, and `view_changed` whether the view
+    /// was recomputed since the last update.
+    ///
+    /// A changed view rescans the tiles in sight and within `margin`; an
+    /// unchanged one only checks the entities that moved.
+    pub fn update(
+        &mut self,
+        tiles: &SpatialIndex,
+        moved: Option<&[EntityID]>,
+        fov: &FovMask,
+        view_changed: bool,
+        margin: i32,
+    ) -> AwarenessDiff {
+        let origin = fov.origin();
+        let view = Some((origin, fov.radius()));
+        let mut diff = AwarenessDiff::default();
+        match moved {
+            Some(moved) if !view_changed && self.view == view => {
+                for eid in moved {
+                    let aware = tiles
+                        .position(*eid)
+                        .is_some_and(|p| fov.contains(p) || math::within_square(origin, p, margin));
+                    if aware && self.aware.insert(*eid) {
+                        diff.entered.push(*eid);
+                    } else if !aware && self.aware.remove(eid) {
+                        diff.left.push(*eid);
+                    }
+                }
+            }
+            _ => {
+                let nearby = (-margin..=margin).flat_map(|dy| {
+                    (-margin..=margin).map(move |dx| Point {
+                        x: origin.x.saturating_add(dx),
+                        y: origin.y.saturating_add(dy),
+                    })
+                });
+                let aware: BTreeSet<EntityID> = fov
+                    .iter()
+                    .chain(nearby)
+                    .flat_map(|point| tiles.entities_at(point).iter().copied())
+                    .collect();
+                diff.entered = aware.difference(&self.aware).copied().collect();
+                diff.left = self.aware.difference(&aware).copied().collect();
+                self.aware = aware;
+                self.view = view;
+            }
+        }
+        diff
+    }
+
+    /// The entities in the set, sorted by ID.
+    pub fn iter(&self) -> impl Iterator<Item = EntityID> + '_ {
+        self.aware.iter().copied()
+    }
+
+    pub fn contains(&self, eid: EntityID) -> bool {
+        self.aware.contains(&eid)
+    }
+
+    pub fn len(&self) -> usize {
+        self.aware.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.aware.is_empty()
+    }
+}
+
+/// Run the shadowcaster, calling `reveal` for every visible position.
+fn shadowcast(
+    origin: Point,
+    radius: i32,
+    is_opaque: &impl Fn(Point) -> bool,
+    reveal: &mut impl FnMut(Point),
+) {
+    reveal(origin);
+    if radius <= 0 {
+        return;
+    }
+
+    for quadrant in [
+        Quadrant::North,
+        Quadrant::East,
+        Quadrant::South,
+        Quadrant::West,
+    ] {
+        let mut scan = Scan {
+            origin,
+            quadrant,
+            radius,
+            is_opaque,
+            reveal: &mut *reveal,
+        };
+        scan.cast_light(Row {
+            depth: 1,
+            start: Slope::new(-1, 1),
+            end: Slope::new(1, 1),
+        });
+    }
+}
+
+/// Exact slope `num / den`, with `den > 0`.
+#[derive(Debug, Clone, Copy)]
+struct Slope {
+    num: i64,
+    den: i64,
+}
+
+impl Slope {
+    const fn new(num: i64, den: i64) -> Self {
+        Self { num, den }
+    }
+
+    /// Slope of the edge between column `col` and the previous one.
+    const fn of_tile(depth: i64, col: i64) -> Self {
+        Self::new(2 * col - 1, 2 * depth)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Quadrant {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Quadrant {
+    /// World position of the tile at `depth` rows out and column `col`.
+    fn transform(self, origin: Point, depth: i64, col: i64) -> Point {
+        // Depth and column never exceed the radius, which is an `i32`.
+        let (depth, col) = (depth as i32, col as i32);
+        let (dx, dy) = match self {
+            Self::North => (col, -depth),
+            Self::South => (col, depth),
+            Self::East => (depth, col),
+            Self::West => (-depth, col),
+        };
+        Point {
+            x: origin.x.saturating_add(dx),
+            y: origin.y.saturating_add(dy),
+        }
+    }
+}
+
+/// One row of a quadrant, between two slopes.
+#[derive(Debug, Clone, Copy)]
+struct Row {
+    depth: i64,
+    start: Slope,
+    end: Slope,
+}
+
+impl Row {
+    /// First column: `depth * start` rounded half up.
+    fn min_col(&self) -> i64 {
+        (2 * self.depth * self.start.num + self.start.den).div_euclid(2 * self.start.den)
+    }
+
+    /// Last column: `depth * end` rounded half down.
+    fn max_col(&self) -> i64 {
+        -(self.end.den - 2 * self.depth * self.end.num).div_euclid(2 * self.end.den)
+    }
+
+    /// Whether a floor tile in column `col` lies inside the row's slopes, so
+    /// that the origin would also be visible from it.
+    fn is_symmetric(&self, col: i64) -> bool {
+        col * self.start.den >= self.depth * self.start.num
+            && col * self.end.den <= self.depth * self.end.num
+    }
+
+    fn next(&self) -> Self {
+        Self {
+            depth: self.depth + 1,
+            ..*self
+        }
+    }
+}
+
+struct Scan<'a, F, R> {
+    origin: Point,
+    quadrant: Quadrant,
+    radius: i32,
+    is_opaque: &'a F,
+    reveal: &'a mut R,
+}
+
+impl<F: Fn(Point) -> bool, R: FnMut(Point)> Scan<'_, F, R> {
+    fn in_radius(&self, point: Point) -> bool {
+        math::within_circle(self.origin, point, self.radius)
+    }
+
+    /// Scan `row` and, recursively, the rows behind it that are still lit.
+    fn cast_light(&mut self, mut row: Row) {
+        if row.depth > i64::from(self.radius) {
+            return;
+        }
+
+        let mut prev_opaque = None;
+        for col in row.min_col()..=row.max_col() {
+            let point = self.quadrant.transform(self.origin, row.depth, col);
+            let opaque = (self.is_opaque)(point);
+
+            if (opaque || row.is_symmetric(col)) && self.in_radius(point) {
+                (self.reveal)(point);
+            }
+            if prev_opaque == Some(true) && !opaque {
+                row.start = Slope::of_tile(row.depth, col);
+            }
+            if prev_opaque == Some(false) && opaque {
+                let mut next = row.next();
+                next.end = Slope::of_tile(row.depth, col);
+                self.cast_light(next);
+            }
+            prev_opaque = Some(opaque);
+        }
+        if prev_opaque == Some(false) {
+            self.cast_light(row.next());
+        }
+    }
+}
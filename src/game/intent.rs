@@ -0,0 +1,377 @@
+//! Queued intents: what an entity means to do over the next ticks.
+//!
+//! An entity works through its [`ActionQueue`] one [`Intent`] at a time, in
+//! [`advance`]. Walking hands the target to the entity's
+//! [`destination`](super::Entity::destination) and finishes when group
+//! movement lets go of it. Chopping, crafting and building are channelled:
+//! they take a fixed number of ticks (see [`Intent::duration`]) and only
+//! change the world once done. The tick the current intent started is
+//! stored rather than a counter, so an entity busy chopping does not change
+//! every tick, and the tick it completes in follows from it alone (see
+//! [`ActionQueue::completes_at`]).
+//!
+//! Channelled intents report their progress as
+//! [`GameEvent::ActionProgress`], at most [`PROGRESS_STEPS`] times each, so
+//! that servers do not send an update for every tick of work.
+//!
+//! [`GameAction::Cancel`](super::GameAction::Cancel) and a step by hand
+//! drop the whole queue; taking damage drops it only while channelling,
+//! see [`Intent::interrupted_by`].
+
+use super::bridge;
+use super::collision;
+use super::combat;
+use super::debris;
+use super::formation;
+use super::math;
+use super::region::ActiveRegions;
+use super::{ContentRegistry, Entity, EntityID, EntityType, GameEvent, GameState, Point};
+use crate::profile;
+
+use bitcode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+/// Most intents one entity may have queued.
+pub const MAX_QUEUED_INTENTS: usize = 8;
+
+/// Ticks it takes to chop down a tree or clear a stump.
+pub const CHOP_TICKS: u32 = 20;
+
+/// Ticks it takes to craft one item.
+pub const CRAFT_TICKS: u32 = 40;
+
+/// Ticks it takes to build a bridge.
+pub const BUILD_TICKS: u32 = 60;
+
+/// Most [`GameEvent::ActionProgress`] reports per channelled intent, not
+/// counting the one as it starts.
+pub const PROGRESS_STEPS: u32 = 4;
+
+/// Something an entity means to do.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub enum Intent {
+    /// Walk to a tile, as a group order would.
+    WalkTo(Point),
+    /// Chop down the tree or clear the stump `target`, standing next to it.
+    Chop(EntityID),
+    /// Craft one of the item with this ID from its materials.
+    Craft(String),
+    /// Build a bridge over the open water at a tile next to the entity.
+    Build(Point),
+}
+
+impl Intent {
+    /// Ticks the intent takes, if it is channelled.
+    pub const fn duration(&self) -> Option<u32> {
+        match self {
+            Self::WalkTo(_) => None,
+            Self::Chop(_) => Some(CHOP_TICKS),
+            Self::Craft(_) => Some(CRAFT_TICKS),
+            Self::Build(_) => Some(BUILD_TICKS),
+        }
+    }
+
+    /// Returns `true` if `cause` makes the entity drop the intent, and
+    /// everything queued after it.
+    pub const fn interrupted_by(&self, cause: Interruption) -> bool {
+        match cause {
+            Interruption::Step | Interruption::Cancel => true,
+            // Walkers keep going under fire; work needs a steady hand.
+            Interruption::Damage => self.duration().is_some(),
+        }
+    }
+
+    /// Stable numeric tag, used by [`GameState::checksum`].
+    pub const fn tag(&self) -> u8 {
+        match self {
+            Self::WalkTo(_) => 0,
+            Self::Chop(_) => 1,
+            Self::Craft(_) => 2,
+            Self::Build(_) => 3,
+        }
+    }
+}
+
+/// What can make an entity drop its queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interruption {
+    /// A [`GameAction::Cancel`](super::GameAction::Cancel).
+    Cancel,
+    /// A step taken by hand.
+    Step,
+    /// Any damage taken.
+    Damage,
+}
+
+/// Returns `true` if `entity` can be chopped.
+pub fn choppable(entity: &Entity) -> bool {
+    matches!(entity.entity_type, EntityType::Tree | EntityType::Stump)
+}
+
+/// What an entity means to do, in order.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Encode, Decode)]
+pub struct ActionQueue {
+    intents: Vec<Intent>,
+    /// Tick the first intent started, once it has.
+    started_at: Option<u64>,
+}
+
+impl ActionQueue {
+    /// Queue `intent` after the others. Returns `false`, queueing nothing,
+    /// if the queue is full.
+    pub fn push(&mut self, intent: Intent) -> bool {
+        if self.intents.len() >= MAX_QUEUED_INTENTS {
+            return false;
+        }
+        self.intents.push(intent);
+        true
+    }
+
+    /// The intent being worked on.
+    pub fn current(&self) -> Option<&Intent> {
+        self.intents.first()
+    }
+
+    pub fn started_at(&self) -> Option<u64> {
+        self.started_at
+    }
+
+    /// The tick the current intent completes in, if it is channelled and
+    /// has started: the last of its [`Intent::duration`] ticks of work.
+    pub fn completes_at(&self) -> Option<u64> {
+        let duration = self.current()?.duration()?;
+        Some(self.started_at? + u64::from(duration) - 1)
+    }
+
+    /// Returns `true` while a channelled intent is under way.
+    pub fn is_channelling(&self) -> bool {
+        self.completes_at().is_some()
+    }
+
+    /// Ticks of the current intent done by the start of `tick`, and how
+    /// many it takes, if it is channelled and has started.
+    pub fn progress(&self, tick: u64) -> Option<(u32, u32)> {
+        let total = self.current()?.duration()?;
+        let done = tick.saturating_sub(self.started_at?);
+        Some((u32::try_from(done).unwrap_or(total).min(total), total))
+    }
+
+    /// Queued intents, the current one first.
+    pub fn iter(&self) -> impl Iterator<Item = &Intent> {
+        self.intents.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.intents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intents.is_empty()
+    }
+
+    /// Move on to the next intent.
+    fn finish(&mut self) {
+        if !self.intents.is_empty() {
+            self.intents.remove(0);
+        }
+        self.started_at = None;
+    }
+
+    /// Bytes the queue owns on the heap.
+    pub fn heap_bytes(&self) -> usize {
+        profile::vec_bytes(&self.intents)
+            + self
+                .intents
+                .iter()
+                .map(|intent| match intent {
+                    Intent::Craft(item) => item.capacity(),
+                    Intent::WalkTo(_) | Intent::Chop(_) | Intent::Build(_) => 0,
+                })
+                .sum::<usize>()
+    }
+}
+
+/// Drop everything `entity` meant to do if `cause` interrupts its current
+/// intent, and stop a walk it had started. Returns `true` if anything was
+/// dropped.
+pub fn interrupt(entity: &mut Entity, cause: Interruption) -> bool {
+    if !entity
+        .queue
+        .current()
+        .is_some_and(|intent| intent.interrupted_by(cause))
+    {
+        return false;
+    }
+    let queue = std::mem::take(&mut entity.queue);
+    if let (Some(Intent::WalkTo(_)), Some(_)) = (queue.current(), queue.started_at) {
+        formation::stop(entity);
+    }
+    true
+}
+
+/// `eid` drops everything it had queued, reported as the current intent
+/// ending undone.
+pub fn cancel(state: &mut GameState, eid: EntityID) -> Option<GameEvent> {
+    let entity = state.entity_mut(eid)?;
+    interrupt(entity, Interruption::Cancel).then_some(GameEvent::IntentEnded {
+        entity_id: eid,
+        completed: false,
+    })
+}
+
+/// Returns `true` if `eid` can go on with `intent`: what it works on is
+/// still there and in reach, and it has what it needs.
+fn can_work(state: &GameState, registry: &ContentRegistry, eid: EntityID, intent: &Intent) -> bool {
+    let Some(entity) = state.entities.get(&eid) else {
+        return false;
+    };
+    match intent {
+        Intent::WalkTo(_) => true,
+        Intent::Chop(target) => state
+            .entities
+            .get(target)
+            .is_some_and(|t| choppable(t) && combat::in_reach(entity.position, t.position)),
+        Intent::Craft(item) => registry
+            .item(item)
+            .filter(|def| !def.craft.is_empty())
+            .is_some_and(|def| {
+                def.craft
+                    .iter()
+                    .all(|(material, count)| entity.inventory.count(material) >= *count)
+            }),
+        Intent::Build(at) => {
+            combat::in_reach(entity.position, *at) && collision::is_open_water(state, *at)
+        }
+    }
+}
+
+/// Carry out a channelled intent whose work is done. Returns `false` if it
+/// could not be, e.g. because the crafted item does not fit.
+fn complete(
+    state: &mut GameState,
+    registry: &ContentRegistry,
+    eid: EntityID,
+    intent: &Intent,
+) -> bool {
+    match intent {
+        Intent::WalkTo(_) => true,
+        Intent::Chop(target) => {
+            debris::destroy(state, *target);
+            true
+        }
+        Intent::Craft(item) => {
+            let (Some(entity), Some(def)) = (state.entity_mut(eid), registry.item(item)) else {
+                return false;
+            };
+            let mut after = entity.inventory.clone();
+            for (material, count) in &def.craft {
+                if !after.remove(material, *count) {
+                    return false;
+                }
+            }
+            if !after.can_take(registry, item, 1) {
+                return false;
+            }
+            after.add(item, 1);
+            entity.inventory = after;
+            true
+        }
+        Intent::Build(at) => bridge::build(state, *at).is_some(),
+    }
+}
+
+/// Work one tick on the current intent of every entity in an `active`
+/// region, in ID order, and return what happened.
+pub fn advance(
+    state: &mut GameState,
+    registry: &ContentRegistry,
+    active: &ActiveRegions,
+) -> Vec<GameEvent> {
+    let tick = state.tick;
+    let busy: Vec<EntityID> = state
+        .entities
+        .iter()
+        .filter(|(_, e)| !e.queue.is_empty() && active.contains(e.position))
+        .map(|(eid, _)| *eid)
+        .collect();
+
+    let mut events = Vec::new();
+    for eid in busy {
+        let Some(entity) = state.entities.get(&eid) else {
+            continue;
+        };
+        let Some(intent) = entity.queue.current().cloned() else {
+            continue;
+        };
+        let started = entity.queue.started_at.is_some();
+        let completes_at = entity.queue.completes_at();
+        let before = entity.queue.progress(tick);
+        let after = entity.queue.progress(tick + 1);
+
+        let done = match &intent {
+            Intent::WalkTo(target) if !started => {
+                if let Some(entity) = state.entity_mut(eid) {
+                    formation::walk_to(entity, *target);
+                }
+                None
+            }
+            // Group movement lets go once it arrives or gives up; a walk
+            // that ended short of the formation's spread did not make it.
+            Intent::WalkTo(target) => state
+                .entities
+                .get(&eid)
+                .filter(|e| e.destination.is_none())
+                .map(|e| {
+                    math::manhattan(e.position, *target)
+                        <= formation::FORMATION_SPREAD.unsigned_abs()
+                }),
+            _ if !can_work(state, registry, eid, &intent) => Some(false),
+            // Overdue only after its region slept through the last tick.
+            _ if completes_at.is_some_and(|t| t <= tick) => {
+                Some(complete(state, registry, eid, &intent))
+            }
+            _ => None,
+        };
+
+        let Some(entity) = state.entity_mut(eid) else {
+            continue;
+        };
+        match done {
+            Some(completed) => {
+                entity.queue.finish();
+                events.push(GameEvent::IntentEnded {
+                    entity_id: eid,
+                    completed,
+                });
+            }
+            None if !started => {
+                entity.queue.started_at = Some(tick);
+                if let Some(total) = intent.duration() {
+                    events.push(GameEvent::ActionProgress {
+                        entity_id: eid,
+                        done: 0,
+                        total,
+                    });
+                }
+            }
+            None => {
+                if let (Some((was, total)), Some((done, _))) = (before, after)
+                    && progress_step(done, total) > progress_step(was, total)
+                {
+                    events.push(GameEvent::ActionProgress {
+                        entity_id: eid,
+                        done,
+                        total,
+                    });
+                }
+            }
+        }
+    }
+    events
+}
+
+/// Which of the [`PROGRESS_STEPS`] reports `done` ticks out of `total` have
+/// reached.
+fn progress_step(done: u32, total: u32) -> u32 {
+    done * PROGRESS_STEPS / total.max(1)
+}
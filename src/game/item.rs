@@ -0,0 +1,825 @@
+//! Items and what entities carry.
+//!
+//! Items are defined in the [`ContentRegistry`] and referred to by ID. One
+//! of them is the currency that shops trade in.
+//!
+//! Items weigh something, and what an entity may carry is capped by the
+//! registry's [`CarryLimits`]: a total weight and a number of slots.
+//! Gaining items past the caps is refused; carrying more than the burdened
+//! weight slows the entity down (see [`Encumbrance`]).
+//!
+//! Items stack up to their [`ItemDef::stack_size`], one stack per slot.
+//! Taking items tops up the last stack of the item before starting a new
+//! one. Players may [split](Inventory::split) a stack off by hand, which is
+//! then kept apart until [merged](Inventory::merge) back, and move items in
+//! and out of [containers](is_container) with [`stash`] and [`retrieve`].
+//!
+//! Tools and weapons wear out. An item with a [`ItemDef::durability`] lasts
+//! that many uses; the inventory tracks the wear of the one in use, and it
+//! breaks when worn through. Worn items sell for less, and [`repair`] mends
+//! them with materials at a [`REPAIR_STATION_TAG`]ged entity in range.
+
+use super::dialogue;
+use super::math;
+use super::{ContentRegistry, Entity, EntityID, EntityType, GameState};
+use crate::profile;
+
+use bitcode::{Decode, Encode};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// An item, as written in `assets/content/items.ron`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ItemDef {
+    pub id: String,
+    pub name: String,
+    /// Weight of one item.
+    #[serde(default)]
+    pub weight: u32,
+    /// Most of the item one slot holds; 1 if it does not stack.
+    #[serde(default = "unstacked")]
+    pub stack_size: u32,
+    /// Damage the item adds to a melee attack by whoever carries it.
+    #[serde(default)]
+    pub damage: u32,
+    /// Uses before the item breaks; `None` if it never wears out.
+    #[serde(default)]
+    pub durability: Option<u32>,
+    /// Materials used up to repair the item; it cannot be repaired without.
+    #[serde(default)]
+    pub repair: BTreeMap<String, u32>,
+    /// Materials used up to craft one of the item; it cannot be crafted
+    /// without.
+    #[serde(default)]
+    pub craft: BTreeMap<String, u32>,
+}
+
+const fn unstacked() -> u32 {
+    1
+}
+
+impl ItemDef {
+    /// Slots `count` of the item take up.
+    pub fn stacks(&self, count: u32) -> u32 {
+        count.div_ceil(self.stack_size.max(1))
+    }
+
+    /// What `price` becomes for one item with `uses` left, in proportion to
+    /// its durability.
+    pub fn worn_price(&self, price: u32, uses: u32) -> u32 {
+        self.durability
+            .map_or(price, |durability| math::scale(price, uses, durability))
+    }
+}
+
+/// Tag of the entities players repair items at.
+pub const REPAIR_STATION_TAG: &str = "station.repair";
+
+/// How much an entity may carry, as written in `assets/content/items.ron`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct CarryLimits {
+    /// Most weight an entity may take on.
+    pub max_weight: u32,
+    /// Weight above which an entity is [`Encumbrance::Burdened`].
+    pub burdened_weight: u32,
+    /// Most stacks an entity may carry.
+    pub max_slots: u32,
+}
+
+impl Default for CarryLimits {
+    fn default() -> Self {
+        Self {
+            max_weight: u32::MAX,
+            burdened_weight: u32::MAX,
+            max_slots: u32::MAX,
+        }
+    }
+}
+
+/// How weighed down an entity is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Encumbrance {
+    Unburdened,
+    /// Above the burdened weight: steps every other tick.
+    Burdened,
+    /// Above the most it may take on, e.g. after an import or a console
+    /// `give`: steps every fourth tick.
+    Overloaded,
+}
+
+impl Encumbrance {
+    /// How heavily `entity` is loaded under `registry`'s limits.
+    pub fn of(entity: &Entity, registry: &ContentRegistry) -> Self {
+        let weight = entity.inventory.weight(registry);
+        if weight > registry.carry.max_weight {
+            Self::Overloaded
+        } else if weight > registry.carry.burdened_weight {
+            Self::Burdened
+        } else {
+            Self::Unburdened
+        }
+    }
+
+    /// Ticks between two steps.
+    pub const fn step_ticks(self) -> u64 {
+        match self {
+            Self::Unburdened => 1,
+            Self::Burdened => 2,
+            Self::Overloaded => 4,
+        }
+    }
+}
+
+impl fmt::Display for Encumbrance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Unburdened => "unburdened",
+            Self::Burdened => "burdened",
+            Self::Overloaded => "overloaded",
+        })
+    }
+}
+
+/// Returns `true` if `entity` may take a step in `tick`. Burdened entities
+/// only step on some ticks, the same ones on every server.
+pub fn can_step(entity: &Entity, registry: &ContentRegistry, tick: u64) -> bool {
+    tick.is_multiple_of(Encumbrance::of(entity, registry).step_ticks())
+}
+
+/// How many of each item an entity carries, by item ID.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Encode, Decode)]
+pub struct Inventory {
+    items: BTreeMap<String, u32>,
+    /// Uses taken off the item in use, for items that have been used.
+    wear: BTreeMap<String, u32>,
+    /// Stacks split off by hand, by item ID, which taking more of the item
+    /// does not top up. Their items are counted in `items` too.
+    split: BTreeMap<String, Vec<u32>>,
+}
+
+impl Inventory {
+    /// `items` with `wear`, none split off; for saves from before stacks
+    /// could be split.
+    pub(super) const fn unsplit(items: BTreeMap<String, u32>, wear: BTreeMap<String, u32>) -> Self {
+        Self {
+            items,
+            wear,
+            split: BTreeMap::new(),
+        }
+    }
+
+    pub fn count(&self, item: &str) -> u32 {
+        self.items.get(item).copied().unwrap_or(0)
+    }
+
+    /// Add `count` of `item`, saturating at `u32::MAX`.
+    pub fn add(&mut self, item: &str, count: u32) {
+        if count > 0 {
+            let held = self.items.entry(item.to_owned()).or_default();
+            *held = held.saturating_add(count);
+        }
+    }
+
+    /// Take `count` of `item`. Returns `false`, taking nothing, if there are
+    /// fewer than that. Unworn items go first; the worn one only goes with
+    /// the last.
+    pub fn remove(&mut self, item: &str, count: u32) -> bool {
+        let held = self.count(item);
+        if held < count {
+            return false;
+        }
+        if held == count {
+            self.items.remove(item);
+            self.wear.remove(item);
+            self.split.remove(item);
+            return true;
+        }
+        self.items.insert(item.to_owned(), held - count);
+        // Stacks filled on their own go first, then the last split off.
+        let mut excess = self.split_count(item).saturating_sub(held - count);
+        if let Some(stacks) = self.split.get_mut(item) {
+            while excess > 0
+                && let Some(last) = stacks.last_mut()
+            {
+                let taken = excess.min(*last);
+                *last -= taken;
+                excess -= taken;
+                if *last == 0 {
+                    stacks.pop();
+                }
+            }
+            if stacks.is_empty() {
+                self.split.remove(item);
+            }
+        }
+        true
+    }
+
+    /// Items in stacks split off by hand.
+    fn split_count(&self, item: &str) -> u32 {
+        self.split.get(item).map_or(0, |stacks| stacks.iter().sum())
+    }
+
+    /// Items in the stacks that fill up on their own.
+    fn loose(&self, item: &str) -> u32 {
+        self.count(item).saturating_sub(self.split_count(item))
+    }
+
+    /// Stacks split off by hand, by item ID.
+    pub fn split_stacks(&self) -> impl Iterator<Item = (&str, &[u32])> {
+        self.split
+            .iter()
+            .map(|(item, stacks)| (item.as_str(), stacks.as_slice()))
+    }
+
+    /// Split `count` of `item` off the stacks that fill up on their own,
+    /// into a stack of its own.
+    ///
+    /// # Errors
+    /// If `count` is 0 or more than a stack holds, there are fewer in those
+    /// stacks, or the new stack does not fit in the carry slots.
+    pub fn split(
+        &mut self,
+        registry: &ContentRegistry,
+        item: &str,
+        count: u32,
+    ) -> Result<(), StackError> {
+        let size = registry.item(item).map_or(1, |def| def.stack_size.max(1));
+        if count == 0 || count > size {
+            return Err(StackError::InvalidCount);
+        }
+        let loose = self.loose(item);
+        if loose < count {
+            return Err(StackError::NotCarried);
+        }
+        let slots = self
+            .slots(registry)
+            .saturating_sub(slots_of(registry, item, loose))
+            .saturating_add(slots_of(registry, item, loose - count))
+            .saturating_add(1);
+        if slots > registry.carry.max_slots {
+            return Err(StackError::NoRoom);
+        }
+        self.split.entry(item.to_owned()).or_default().push(count);
+        Ok(())
+    }
+
+    /// Merge the stacks of `item` split off by hand back into its others.
+    ///
+    /// # Errors
+    /// If none of `item` was split off.
+    pub fn merge(&mut self, item: &str) -> Result<(), StackError> {
+        self.split
+            .remove(item)
+            .map(drop)
+            .ok_or(StackError::NotSplit)
+    }
+
+    /// Items carried, sorted by ID.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u32)> {
+        self.items
+            .iter()
+            .map(|(item, count)| (item.as_str(), *count))
+    }
+
+    /// Uses taken off the `item` in use.
+    pub fn wear(&self, item: &str) -> u32 {
+        self.wear.get(item).copied().unwrap_or(0)
+    }
+
+    /// Items with wear, sorted by ID.
+    pub fn worn(&self) -> impl Iterator<Item = (&str, u32)> {
+        self.wear.iter().map(|(item, wear)| (item.as_str(), *wear))
+    }
+
+    /// Uses left in the `item` in use and its durability, if it wears out
+    /// and is carried.
+    pub fn condition(&self, registry: &ContentRegistry, item: &str) -> Option<(u32, u32)> {
+        let durability = registry.item(item)?.durability?;
+        (self.count(item) > 0).then(|| (durability.saturating_sub(self.wear(item)), durability))
+    }
+
+    /// Use `item` once. Returns `true` if that wore it through and it
+    /// broke; the next one carried is unworn.
+    pub fn wear_out(&mut self, registry: &ContentRegistry, item: &str) -> bool {
+        let Some((uses, _)) = self.condition(registry, item) else {
+            return false;
+        };
+        if uses > 1 {
+            *self.wear.entry(item.to_owned()).or_default() += 1;
+            return false;
+        }
+        self.wear.remove(item);
+        self.remove(item, 1);
+        true
+    }
+
+    /// The carried item adding the most damage, the first by ID on ties.
+    pub fn weapon<'a>(&'a self, registry: &ContentRegistry) -> Option<(&'a str, u32)> {
+        self.iter()
+            .filter_map(|(item, _)| Some((item, registry.item(item)?.damage)))
+            .filter(|(_, damage)| *damage > 0)
+            .fold(None, |best, (item, damage)| match best {
+                Some((_, most)) if most >= damage => best,
+                _ => Some((item, damage)),
+            })
+    }
+
+    /// Total weight carried, saturating at `u32::MAX`. Items unknown to
+    /// the registry weigh nothing.
+    pub fn weight(&self, registry: &ContentRegistry) -> u32 {
+        self.iter().fold(0, |total: u32, (item, count)| {
+            let weight = registry.item(item).map_or(0, |def| def.weight);
+            total.saturating_add(weight.saturating_mul(count))
+        })
+    }
+
+    /// Items carried as full stacks, then what is left over, then the
+    /// stacks split off by hand, sorted by ID. Items unknown to the
+    /// registry make one stack.
+    pub fn stacks<'a>(
+        &'a self,
+        registry: &'a ContentRegistry,
+    ) -> impl Iterator<Item = (&'a str, u32)> + 'a {
+        self.iter().flat_map(|(item, _)| {
+            let loose = self.loose(item);
+            let size = registry
+                .item(item)
+                .map_or(loose, |def| def.stack_size)
+                .max(1);
+            let full = (0..loose / size).map(move |_| (item, size));
+            let split = self.split.get(item).into_iter().flatten();
+            full.chain(Some((item, loose % size)).filter(|(_, rest)| *rest > 0))
+                .chain(split.map(move |count| (item, *count)))
+        })
+    }
+
+    /// Slots taken up, saturating at `u32::MAX`.
+    pub fn slots(&self, registry: &ContentRegistry) -> u32 {
+        self.iter().fold(0, |total: u32, (item, _)| {
+            let split = self.split.get(item).map_or(0, Vec::len);
+            total
+                .saturating_add(slots_of(registry, item, self.loose(item)))
+                .saturating_add(u32::try_from(split).unwrap_or(u32::MAX))
+        })
+    }
+
+    /// Returns `true` if `count` more of `item` stay within `registry`'s
+    /// [`CarryLimits`]. Gaining nothing always fits.
+    pub fn can_take(&self, registry: &ContentRegistry, item: &str, count: u32) -> bool {
+        if count == 0 {
+            return true;
+        }
+        let limits = registry.carry;
+        let added = registry
+            .item(item)
+            .map_or(0, |def| def.weight.saturating_mul(count));
+        let loose = self.loose(item);
+        let slots = self
+            .slots(registry)
+            .saturating_sub(slots_of(registry, item, loose))
+            .saturating_add(slots_of(registry, item, loose.saturating_add(count)));
+        (added == 0 || self.weight(registry).saturating_add(added) <= limits.max_weight)
+            && slots <= limits.max_slots
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Bytes the inventory owns on the heap.
+    pub fn heap_bytes(&self) -> usize {
+        profile::btree_map_bytes(&self.items)
+            + self.items.keys().map(String::capacity).sum::<usize>()
+            + profile::btree_map_bytes(&self.wear)
+            + self.wear.keys().map(String::capacity).sum::<usize>()
+            + profile::btree_map_bytes(&self.split)
+            + self
+                .split
+                .iter()
+                .map(|(item, stacks)| item.capacity() + profile::vec_bytes(stacks))
+                .sum::<usize>()
+    }
+}
+
+/// Slots `count` of `item` take up; an unknown item takes one.
+fn slots_of(registry: &ContentRegistry, item: &str, count: u32) -> u32 {
+    registry
+        .item(item)
+        .map_or(u32::from(count > 0), |def| def.stacks(count))
+}
+
+/// Why a repair was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepairError {
+    /// No [`REPAIR_STATION_TAG`]ged entity is in range.
+    NoStation,
+    /// The item is not carried, does not wear out or cannot be repaired.
+    NotRepairable,
+    /// The item is not worn.
+    NotWorn,
+    MissingMaterials,
+}
+
+impl fmt::Display for RepairError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::NoStation => "there is nowhere to repair it nearby",
+            Self::NotRepairable => "that cannot be repaired",
+            Self::NotWorn => "that is not worn",
+            Self::MissingMaterials => "you lack the materials",
+        })
+    }
+}
+
+impl std::error::Error for RepairError {}
+
+/// `eid` mends the `item` it is using at a repair station in range, using
+/// up the item's repair materials.
+///
+/// # Errors
+/// If the item cannot be repaired, no station is in range, or the
+/// materials are missing.
+pub fn repair(
+    state: &mut GameState,
+    registry: &ContentRegistry,
+    eid: EntityID,
+    item: &str,
+) -> Result<(), RepairError> {
+    let entity = state.entities.get(&eid).ok_or(RepairError::NotRepairable)?;
+    let at_station = state.entities.iter().any(|(other, station)| {
+        *other != eid
+            && station.tags.contains(REPAIR_STATION_TAG)
+            && dialogue::in_range(entity.position, station.position)
+    });
+    if !at_station {
+        return Err(RepairError::NoStation);
+    }
+    let def = registry
+        .item(item)
+        .filter(|def| def.durability.is_some() && !def.repair.is_empty())
+        .filter(|_| entity.inventory.count(item) > 0)
+        .ok_or(RepairError::NotRepairable)?;
+    if entity.inventory.wear(item) == 0 {
+        return Err(RepairError::NotWorn);
+    }
+
+    let mut after = entity.inventory.clone();
+    for (material, count) in &def.repair {
+        if !after.remove(material, *count) {
+            return Err(RepairError::MissingMaterials);
+        }
+    }
+    after.wear.remove(item);
+    if let Some(entity) = state.entity_mut(eid) {
+        entity.inventory = after;
+    }
+    Ok(())
+}
+
+/// Why items could not be split, merged or moved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackError {
+    InvalidCount,
+    /// Fewer of the item are carried, or in the stacks split from.
+    NotCarried,
+    /// None of the item was split off by hand.
+    NotSplit,
+    /// Whoever gets the items cannot carry them; see [`CarryLimits`].
+    NoRoom,
+    /// The other entity is neither a corpse nor owned by the player.
+    NotAContainer,
+    /// The container is further than [`dialogue::TALK_RANGE`].
+    OutOfRange,
+}
+
+impl fmt::Display for StackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::InvalidCount => "a stack cannot hold that many",
+            Self::NotCarried => "there are not that many",
+            Self::NotSplit => "no stack of that was split off",
+            Self::NoRoom => "there is no room for that",
+            Self::NotAContainer => "that cannot hold your things",
+            Self::OutOfRange => "that is too far away",
+        })
+    }
+}
+
+impl std::error::Error for StackError {}
+
+/// Returns `true` if `container` is something `eid` may put items in and
+/// take them out of: a corpse, or an entity `eid` owns.
+pub fn is_container(state: &GameState, eid: EntityID, container: EntityID) -> bool {
+    state.entities.get(&container).is_some_and(|entity| {
+        container != eid && (entity.entity_type == EntityType::Corpse || entity.owner == Some(eid))
+    })
+}
+
+/// `eid` puts `count` of `item` in `container`, which must be in range.
+///
+/// # Errors
+/// If `container` is not one or is out of range, `eid` carries too few, or
+/// the container cannot carry them.
+pub fn stash(
+    state: &mut GameState,
+    registry: &ContentRegistry,
+    eid: EntityID,
+    container: EntityID,
+    item: &str,
+    count: u32,
+) -> Result<(), StackError> {
+    check_container(state, eid, container)?;
+    move_items(state, registry, eid, container, item, count)
+}
+
+/// `eid` takes `count` of `item` out of `container`, which must be in
+/// range.
+///
+/// # Errors
+/// If `container` is not one or is out of range, holds too few, or `eid`
+/// cannot carry them.
+pub fn retrieve(
+    state: &mut GameState,
+    registry: &ContentRegistry,
+    eid: EntityID,
+    container: EntityID,
+    item: &str,
+    count: u32,
+) -> Result<(), StackError> {
+    check_container(state, eid, container)?;
+    move_items(state, registry, container, eid, item, count)
+}
+
+fn check_container(
+    state: &GameState,
+    eid: EntityID,
+    container: EntityID,
+) -> Result<(), StackError> {
+    if !is_container(state, eid, container) {
+        return Err(StackError::NotAContainer);
+    }
+    let position = |eid| state.entities.get(&eid).map(|e| e.position);
+    match (position(eid), position(container)) {
+        (Some(a), Some(b)) if dialogue::in_range(a, b) => Ok(()),
+        _ => Err(StackError::OutOfRange),
+    }
+}
+
+/// Move `count` of `item` from one inventory to another. The last of an
+/// item takes its wear along, unless the receiver has its own in use.
+fn move_items(
+    state: &mut GameState,
+    registry: &ContentRegistry,
+    from: EntityID,
+    to: EntityID,
+    item: &str,
+    count: u32,
+) -> Result<(), StackError> {
+    if count == 0 {
+        return Err(StackError::InvalidCount);
+    }
+    let inventory = |eid| state.entities.get(&eid).map(|e| &e.inventory);
+    let (Some(giver), Some(taker)) = (inventory(from), inventory(to)) else {
+        return Err(StackError::NotCarried);
+    };
+    let held = giver.count(item);
+    if held < count {
+        return Err(StackError::NotCarried);
+    }
+    if !taker.can_take(registry, item, count) {
+        return Err(StackError::NoRoom);
+    }
+    let wear = (held == count && taker.count(item) == 0).then(|| giver.wear(item));
+    if let Some(giver) = state.entity_mut(from) {
+        giver.inventory.remove(item, count);
+    }
+    if let Some(taker) = state.entity_mut(to) {
+        taker.inventory.add(item, count);
+        if let Some(wear) = wear.filter(|wear| *wear > 0) {
+            taker.inventory.wear.insert(item.to_owned(), wear);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{GameAction, GameEvent, Point, SPAWN_POINT, apply, spawn_player};
+
+    #[test]
+    fn split_stacks_stay_apart_until_merged() {
+        let registry = ContentRegistry::builtin();
+        let mut inventory = Inventory::default();
+        inventory.add("bread", 23);
+        assert_eq!(inventory.split(&registry, "bread", 4), Ok(()));
+        fn stacks<'a>(
+            inventory: &'a Inventory,
+            registry: &'a ContentRegistry,
+        ) -> Vec<(&'a str, u32)> {
+            inventory.stacks(registry).collect()
+        }
+        assert_eq!(
+            stacks(&inventory, &registry),
+            [("bread", 10), ("bread", 9), ("bread", 4)]
+        );
+
+        // New bread tops up the stacks that fill on their own.
+        inventory.add("bread", 5);
+        assert_eq!(
+            stacks(&inventory, &registry),
+            [("bread", 10), ("bread", 10), ("bread", 4), ("bread", 4)]
+        );
+        assert_eq!(inventory.slots(&registry), 4);
+
+        // Those go first, then the last stack split off.
+        assert!(inventory.remove("bread", 25));
+        assert_eq!(stacks(&inventory, &registry), [("bread", 3)]);
+        assert_eq!(inventory.merge("bread"), Ok(()));
+        assert_eq!(inventory.merge("bread"), Err(StackError::NotSplit));
+        assert_eq!(inventory.count("bread"), 3);
+        assert!(inventory.remove("bread", 3));
+        assert!(inventory.is_empty() && inventory.split_stacks().count() == 0);
+    }
+
+    #[test]
+    fn splits_need_a_count_a_stack_holds_and_a_free_slot() {
+        let registry = ContentRegistry::builtin();
+        let mut inventory = Inventory::default();
+        inventory.add("bread", 12);
+        assert_eq!(
+            inventory.split(&registry, "bread", 0),
+            Err(StackError::InvalidCount)
+        );
+        assert_eq!(
+            inventory.split(&registry, "bread", 11),
+            Err(StackError::InvalidCount)
+        );
+        assert_eq!(inventory.split(&registry, "bread", 10), Ok(()));
+        assert_eq!(
+            inventory.split(&registry, "bread", 3),
+            Err(StackError::NotCarried),
+            "only 2 are left to split from"
+        );
+        assert_eq!(
+            inventory.split(&registry, "apple", 1),
+            Err(StackError::NotCarried)
+        );
+
+        let mut full = Inventory::default();
+        full.add("rope", registry.carry.max_slots - 1);
+        full.add("bread", 2);
+        assert_eq!(full.split(&registry, "bread", 1), Err(StackError::NoRoom));
+        assert_eq!(full.count("bread"), 2);
+    }
+
+    /// A world with a player, and a corpse holding 4 bombs `dx` tiles east
+    /// of it.
+    fn corpse_world(dx: i32) -> (GameState, EntityID, EntityID) {
+        let mut state = GameState::create_test_world("crypt".into());
+        let player = spawn_player(&mut state, "Alice".into());
+        let corpse = state.entity_gen.next();
+        let at = Point {
+            x: SPAWN_POINT.x + dx,
+            y: SPAWN_POINT.y,
+        };
+        let mut body = Entity::new(EntityType::Corpse, at, Some("Bob".into()));
+        body.inventory.add("bomb", 4);
+        state.insert_entity(corpse, body);
+        (state, player, corpse)
+    }
+
+    fn count(state: &GameState, eid: EntityID, item: &str) -> u32 {
+        state
+            .entities
+            .get(&eid)
+            .map_or(0, |entity| entity.inventory.count(item))
+    }
+
+    #[test]
+    fn items_move_in_and_out_of_containers_in_range() {
+        let (mut state, player, corpse) = corpse_world(1);
+        let take = |count| GameAction::Retrieve {
+            container: corpse,
+            item: "bomb".into(),
+            count,
+        };
+        assert_eq!(
+            apply(&mut state, player, &take(3)),
+            [GameEvent::StacksChanged { entity_id: player }]
+        );
+        assert_eq!(
+            (count(&state, player, "bomb"), count(&state, corpse, "bomb")),
+            (3, 1)
+        );
+
+        let stash = GameAction::Stash {
+            container: corpse,
+            item: "bomb".into(),
+            count: 2,
+        };
+        apply(&mut state, player, &stash);
+        assert_eq!(
+            (count(&state, player, "bomb"), count(&state, corpse, "bomb")),
+            (1, 3)
+        );
+
+        let rejected = |state: &mut GameState, action| match apply(state, player, &action)[..] {
+            [GameEvent::StackRejected { reason, .. }] => Some(reason),
+            _ => None,
+        };
+        assert_eq!(rejected(&mut state, take(4)), Some(StackError::NotCarried));
+        assert_eq!(
+            rejected(&mut state, take(0)),
+            Some(StackError::InvalidCount)
+        );
+
+        // The player's own followers hold things; other people's do not.
+        let mule = state.entity_gen.next();
+        let mut follower = Entity::new(EntityType::Npc, SPAWN_POINT, None);
+        follower.owner = Some(player);
+        state.insert_entity(mule, follower);
+        assert!(is_container(&state, player, mule));
+        assert!(!is_container(&state, mule, player));
+        assert!(!is_container(&state, player, player));
+        let give = GameAction::Stash {
+            container: mule,
+            item: "bomb".into(),
+            count: 1,
+        };
+        assert_eq!(rejected(&mut state, give.clone()), None);
+        assert_eq!(count(&state, mule, "bomb"), 1);
+        let stranger = spawn_player(&mut state, "Carol".into());
+        let steal = GameAction::Retrieve {
+            container: mule,
+            item: "bomb".into(),
+            count: 1,
+        };
+        assert!(matches!(
+            apply(&mut state, stranger, &steal)[..],
+            [GameEvent::StackRejected {
+                reason: StackError::NotAContainer,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn containers_out_of_reach_or_full_refuse_items() {
+        let (mut state, player, corpse) = corpse_world(dialogue::TALK_RANGE + 1);
+        let take = GameAction::Retrieve {
+            container: corpse,
+            item: "bomb".into(),
+            count: 4,
+        };
+        assert!(matches!(
+            apply(&mut state, player, &take)[..],
+            [GameEvent::StackRejected {
+                reason: StackError::OutOfRange,
+                ..
+            }]
+        ));
+
+        let (mut state, player, corpse) = corpse_world(1);
+        let registry = ContentRegistry::builtin();
+        if let Some(entity) = state.entity_mut(player) {
+            entity.inventory.add("pelt", registry.carry.max_weight / 8);
+        }
+        assert!(matches!(
+            apply(&mut state, player, &take)[..],
+            [GameEvent::StackRejected {
+                reason: StackError::NoRoom,
+                ..
+            }]
+        ));
+        assert_eq!(count(&state, corpse, "bomb"), 4);
+    }
+
+    #[test]
+    fn the_last_of_an_item_takes_its_wear_along() {
+        let (mut state, player, corpse) = corpse_world(1);
+        let registry = ContentRegistry::builtin();
+        if let Some(entity) = state.entity_mut(player) {
+            entity.inventory.add("hatchet", 1);
+            entity.inventory.wear_out(&registry, "hatchet");
+        }
+        let stash = GameAction::Stash {
+            container: corpse,
+            item: "hatchet".into(),
+            count: 1,
+        };
+        apply(&mut state, player, &stash);
+        let held = |eid| {
+            let entity = state.entities.get(&eid)?;
+            entity.inventory.condition(&registry, "hatchet")
+        };
+        assert_eq!(held(player), None);
+        assert_eq!(held(corpse), Some((29, 30)));
+    }
+}
@@ -0,0 +1,86 @@
+//! Integer geometry on the game grid.
+//!
+//! Distances, ranges and shapes used by the simulation all come from here.
+//! Everything is integer-only, with no floats or square roots, so results
+//! are bit-identical on every platform; checksums, replays and
+//! re-simulation depend on that. Intermediate values are widened to 64
+//! bits, so any two points in `i32` range can be compared without
+//! overflow.
+
+use super::Point;
+
+use std::iter;
+
+/// Manhattan distance: steps needed moving only along the axes.
+pub fn manhattan(a: Point, b: Point) -> u32 {
+    a.x.abs_diff(b.x).saturating_add(a.y.abs_diff(b.y))
+}
+
+/// Chebyshev distance: steps needed when diagonal steps are allowed.
+pub fn chebyshev(a: Point, b: Point) -> u32 {
+    a.x.abs_diff(b.x).max(a.y.abs_diff(b.y))
+}
+
+/// Square of the Euclidean distance, saturating at `u64::MAX` for points
+/// at opposite corners of the grid.
+pub fn distance_squared(a: Point, b: Point) -> u64 {
+    let dx = u64::from(a.x.abs_diff(b.x));
+    let dy = u64::from(a.y.abs_diff(b.y));
+    (dx * dx).saturating_add(dy * dy)
+}
+
+/// Returns `true` if `point` lies in the square of tiles within `range` of
+/// `center` on both axes. Nothing is in range of a negative range.
+pub fn within_square(center: Point, point: Point, range: i32) -> bool {
+    u32::try_from(range).is_ok_and(|range| chebyshev(center, point) <= range)
+}
+
+/// Returns `true` if `point` lies within Euclidean distance `radius` of
+/// `center`. Nothing is in a circle with a negative radius.
+pub fn within_circle(center: Point, point: Point, radius: i32) -> bool {
+    u64::try_from(radius).is_ok_and(|radius| distance_squared(center, point) <= radius * radius)
+}
+
+/// `value * num / den`, rounded down; `0` if `den` is `0`.
+pub fn scale(value: u32, num: u32, den: u32) -> u32 {
+    u64::from(value)
+        .saturating_mul(u64::from(num))
+        .checked_div(u64::from(den))
+        .map_or(0, |scaled| u32::try_from(scaled).unwrap_or(u32::MAX))
+}
+
+/// The tiles on the straight line from `from` to `to`, both included, by
+/// Bresenham's algorithm. The line from `to` to `from` may pass through
+/// different tiles where it is ambiguous.
+pub fn line(from: Point, to: Point) -> impl Iterator<Item = Point> {
+    let (x1, y1) = (i64::from(to.x), i64::from(to.y));
+    let (mut x, mut y) = (i64::from(from.x), i64::from(from.y));
+    let (dx, dy) = ((x1 - x).abs(), -(y1 - y).abs());
+    let (sx, sy) = ((x1 - x).signum(), (y1 - y).signum());
+    let mut error = dx + dy;
+    let mut done = false;
+    iter::from_fn(move || {
+        if done {
+            return None;
+        }
+        // Every point lies between the two ends, so it fits in an `i32`.
+        let point = Point {
+            x: i32::try_from(x).ok()?,
+            y: i32::try_from(y).ok()?,
+        };
+        if (x, y) == (x1, y1) {
+            done = true;
+        } else {
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                x += sx;
+            }
+            if doubled <= dx {
+                error += dx;
+                y += sy;
+            }
+        }
+        Some(point)
+    })
+}
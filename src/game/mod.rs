@@ -3,27 +3,114 @@
 //! This module contains all game state types, the [`GameAction`] enum for
 //! state mutations, and the pure [`apply`] function that advances the game.
 
+pub mod ai;
+pub mod bridge;
+pub mod chunks;
+pub mod collision;
+pub mod combat;
+pub mod content;
+pub mod debris;
+pub mod dialogue;
+pub mod explosion;
+pub mod faction;
+pub mod follow;
+pub mod formation;
+pub mod fov;
+pub mod intent;
+pub mod item;
+pub mod math;
+pub mod pack;
+pub mod path;
+pub mod persist;
+pub mod ping;
+pub mod region;
+pub mod render;
+pub mod replay;
+pub mod rng;
+pub mod roster;
+pub mod scheduler;
+pub mod shop;
+pub mod slots;
+pub mod spatial;
+pub mod tags;
+pub mod terrain;
+pub mod transfer;
+pub mod world_events;
+pub mod worldgen;
+
+pub use ai::AiBehavior;
+pub use chunks::{ChunkId, Chunks};
+pub use combat::{CombatStats, Health};
+pub use content::ContentRegistry;
+pub use dialogue::{Conversation, DialogueView};
+pub use explosion::{Blast, Explosion};
+pub use faction::Reputation;
+pub use follow::Follow;
+pub use intent::{ActionQueue, Intent, Interruption};
+pub use item::{Inventory, RepairError, StackError};
+pub use pack::PackManifest;
+pub use persist::{
+    SaveError, SaveHeader, SaveSync, load_from_file, read_save_header, save_to_file, save_to_path,
+};
+pub use ping::{Ping, PingKind};
+pub use roster::{PlayerColor, PlayerKey, Roster, Rosters};
+pub use scheduler::{Scheduler, Wait, WaitEnd};
+pub use shop::{Shop, TradeError};
+pub use spatial::SpatialIndex;
+pub use tags::{Metadata, Tags};
+pub use terrain::{Terrain, TileMap};
+pub use transfer::{PortableCharacter, TransferRules};
+pub use world_events::ActiveEvent;
+pub use worldgen::GenManifest;
+
+use crate::profile::{self, MemoryReport, Profiler, System};
+
 use bitcode::{Decode, Encode};
-use rustc_hash::FxHashMap;
-use std::fs;
-use std::io;
-use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Instant;
 
 // ---------------------------------------------------------------------------
 // Type aliases
 // ---------------------------------------------------------------------------
 
 /// Map from entity IDs to their data.
-pub type EntityMap = FxHashMap<EntityID, Entity>;
+///
+/// Ordered by ID so that iteration (awareness, snapshot encoding, checksums)
+/// is the same on every run and platform.
+pub type EntityMap = BTreeMap<EntityID, Entity>;
 
 // ---------------------------------------------------------------------------
 // Core value types
 // ---------------------------------------------------------------------------
 
 /// Unique identifier for an entity in the game world.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Encode, Decode)]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Encode, Decode, Serialize, Deserialize,
+)]
 pub struct EntityID(pub u32);
 
+/// Stable identity of a world: a UUID generated once at creation.
+///
+/// Unlike the world name, it never changes, so caches and per-world data
+/// are keyed by it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Encode, Decode)]
+pub struct WorldId(pub u128);
+
+impl WorldId {
+    /// Generate a fresh random (v4) id.
+    pub fn generate() -> Self {
+        Self(uuid::Uuid::new_v4().as_u128())
+    }
+}
+
+impl fmt::Display for WorldId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        uuid::Uuid::from_u128(self.0).hyphenated().fmt(f)
+    }
+}
+
 /// Monotonically increasing generator for [`EntityID`] values.
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Encode, Decode)]
 pub struct EntityGenerator(u32);
@@ -36,14 +123,17 @@ impl EntityGenerator {
 }
 
 /// A 2-D point on the game grid.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Encode, Decode)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Encode, Decode, Serialize, Deserialize)]
 pub struct Point {
     pub x: i32,
     pub y: i32,
 }
 
+/// Where new players appear.
+pub const SPAWN_POINT: Point = Point { x: 10, y: 10 };
+
 /// Cardinal direction for movement.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
 pub enum Direction {
     Up,
     Down,
@@ -68,12 +158,99 @@ impl Direction {
 pub enum EntityType {
     Player,
     Tree,
+    /// A non-player character, such as a vendor.
+    Npc,
+    /// What is left of a destroyed tree.
+    Stump,
+    /// What is left of destroyed debris.
+    Rubble,
+    /// Impassable unless bridged.
+    Water,
+    /// Makes the water on its tile walkable.
+    Bridge,
+    /// What is left of a creature that was killed, with what it carried.
+    Corpse,
 }
 
 impl EntityType {
+    /// Every entity type, in tag order.
+    pub const ALL: [Self; 8] = [
+        Self::Player,
+        Self::Tree,
+        Self::Npc,
+        Self::Stump,
+        Self::Rubble,
+        Self::Water,
+        Self::Bridge,
+        Self::Corpse,
+    ];
+
+    /// Name of the type in content files.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Player => "player",
+            Self::Tree => "tree",
+            Self::Npc => "npc",
+            Self::Stump => "stump",
+            Self::Rubble => "rubble",
+            Self::Water => "water",
+            Self::Bridge => "bridge",
+            Self::Corpse => "corpse",
+        }
+    }
+
     pub fn blocks_sight(&self) -> bool {
         matches!(self, Self::Tree)
     }
+
+    /// Returns `true` if other entities cannot share the tile.
+    pub fn blocks_movement(&self) -> bool {
+        !matches!(self, Self::Rubble | Self::Bridge | Self::Corpse)
+    }
+
+    /// Returns `true` for scenery: trees and the debris they leave, water
+    /// and bridges.
+    pub fn is_terrain(&self) -> bool {
+        matches!(
+            self,
+            Self::Tree | Self::Stump | Self::Rubble | Self::Water | Self::Bridge
+        )
+    }
+
+    /// Returns `true` for terrain that blasts can destroy.
+    pub fn is_destructible(&self) -> bool {
+        self.is_terrain() && *self != Self::Water
+    }
+
+    /// Returns `true` if the entity cannot be pushed around.
+    pub fn is_anchored(&self) -> bool {
+        self.is_terrain()
+    }
+
+    /// What the entity turns into when destroyed, if anything is left.
+    pub fn debris(&self) -> Option<Self> {
+        match self {
+            Self::Tree => Some(Self::Stump),
+            Self::Stump => Some(Self::Rubble),
+            Self::Player | Self::Npc | Self::Rubble | Self::Water | Self::Bridge | Self::Corpse => {
+                None
+            }
+        }
+    }
+
+    /// Stable numeric tag, used by [`GameState::checksum`].
+    const fn tag(&self) -> u8 {
+        match self {
+            Self::Player => 0,
+            Self::Tree => 1,
+            Self::Npc => 2,
+            Self::Stump => 3,
+            Self::Rubble => 4,
+            Self::Water => 5,
+            Self::Bridge => 6,
+            Self::Corpse => 7,
+        }
+    }
 }
 
 /// An entity in the game world.
@@ -82,6 +259,89 @@ pub struct Entity {
     pub position: Point,
     pub name: Option<String>,
     pub entity_type: EntityType,
+    pub tags: Tags,
+    pub metadata: Metadata,
+    /// ID of the faction the entity belongs to, if any.
+    pub faction: Option<String>,
+    /// Standing of a player with every faction.
+    pub reputation: Reputation,
+    /// ID of the dialogue tree used when a player talks to the entity.
+    pub dialogue: Option<String>,
+    /// Where a player is in their current conversation, if any.
+    pub conversation: Option<Conversation>,
+    pub inventory: Inventory,
+    /// Present if the entity is a vendor.
+    pub shop: Option<Shop>,
+    /// Player who may give the entity orders.
+    pub owner: Option<EntityID>,
+    /// Where the entity is walking to, one tile per tick; see [`formation`].
+    pub destination: Option<Point>,
+    /// Tick the way to the destination was found blocked, while the entity
+    /// waits for it to clear.
+    pub blocked_since: Option<u64>,
+    /// Present if the entity stays close to its owner; see [`follow`].
+    pub follow: Option<Follow>,
+    /// Present if the entity can be hurt.
+    pub health: Option<Health>,
+    /// Attack and defense in melee; see [`combat`].
+    pub combat: CombatStats,
+    /// What the entity means to do next; see [`intent`].
+    pub queue: ActionQueue,
+    /// Color a player's character is drawn in instead of its appearance's.
+    pub color: Option<PlayerColor>,
+    /// Present if the entity acts on its own; see [`ai`].
+    pub ai: Option<AiBehavior>,
+}
+
+impl Entity {
+    /// An untagged entity without metadata, faction, reputation, dialogue,
+    /// items, shop, owner, health or mind of its own.
+    pub fn new(entity_type: EntityType, position: Point, name: Option<String>) -> Self {
+        Self {
+            position,
+            name,
+            entity_type,
+            tags: Tags::default(),
+            metadata: Metadata::default(),
+            faction: None,
+            reputation: Reputation::default(),
+            dialogue: None,
+            conversation: None,
+            inventory: Inventory::default(),
+            shop: None,
+            owner: None,
+            destination: None,
+            blocked_since: None,
+            follow: None,
+            health: None,
+            combat: CombatStats::default(),
+            queue: ActionQueue::default(),
+            color: None,
+            ai: None,
+        }
+    }
+
+    /// Bytes the entity owns on the heap.
+    pub fn heap_bytes(&self) -> usize {
+        self.name.as_ref().map_or(0, String::capacity)
+            + self.tags.heap_bytes()
+            + self.metadata.heap_bytes()
+            + self.faction.as_ref().map_or(0, String::capacity)
+            + self.reputation.heap_bytes()
+            + self.dialogue.as_ref().map_or(0, String::capacity)
+            + self
+                .conversation
+                .as_ref()
+                .map_or(0, |c| c.tree.capacity() + c.node.capacity())
+            + self.inventory.heap_bytes()
+            + self.shop.as_ref().map_or(0, Shop::heap_bytes)
+            + self.queue.heap_bytes()
+    }
+}
+
+/// Estimated bytes held by an entity map, names included.
+pub fn entity_map_bytes(entities: &EntityMap) -> usize {
+    profile::btree_map_bytes(entities) + entities.values().map(Entity::heap_bytes).sum::<usize>()
 }
 
 // ---------------------------------------------------------------------------
@@ -89,13 +349,103 @@ pub struct Entity {
 // ---------------------------------------------------------------------------
 
 /// Every possible state-mutating action that can be applied to the game.
-#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
 pub enum GameAction {
     Move(Direction),
     SpawnPlayer(String),
     /// Networking-level: request to control an existing entity.
     SpawnAs(EntityID),
     SaveWorld,
+    /// Start a conversation with an NPC in range.
+    Talk(EntityID),
+    /// Pick an option of the current dialogue node, by index.
+    Choose(u32),
+    /// Buy items from a vendor in range.
+    Buy {
+        vendor: EntityID,
+        item: String,
+        count: u32,
+    },
+    /// Sell items to a vendor in range.
+    Sell {
+        vendor: EntityID,
+        item: String,
+        count: u32,
+    },
+    /// Send the acting player and entities it owns to `target`, in
+    /// formation; see [`formation`].
+    MoveGroup {
+        members: Vec<EntityID>,
+        target: Point,
+    },
+    /// Hit an adjacent entity, knocking it back.
+    Attack(EntityID),
+    /// Hit whatever can be hurt on the adjacent tile in a direction.
+    AttackToward(Direction),
+    /// Throw an item at a tile; only bombs can be thrown so far.
+    Throw {
+        item: String,
+        target: Point,
+    },
+    /// Mend a worn item at a repair station in range.
+    Repair(String),
+    /// Add an intent to the acting entity's queue; see [`intent`].
+    Queue(Intent),
+    /// Drop everything the acting entity has queued.
+    Cancel,
+    /// Mark a tile in sight for the acting player's team; see [`ping`].
+    Ping {
+        pos: Point,
+        kind: PingKind,
+    },
+    /// Pass the rest of the acting entity's turn; see [`scheduler`].
+    EndTurn,
+    /// Pass the acting entity's turn, and as many after it as asked; see
+    /// [`scheduler`].
+    Wait(Wait),
+    /// Draw the acting player's character in this color, lightened to
+    /// stay legible; see [`PlayerColor`].
+    SetColor(PlayerColor),
+    /// Split this many of an item off the acting entity's stacks into one
+    /// of its own; see [`Inventory::split`].
+    SplitStack {
+        item: String,
+        count: u32,
+    },
+    /// Merge the acting entity's stacks of an item split off by hand back
+    /// into its others.
+    MergeStacks(String),
+    /// Put items in a container in range; see [`item::is_container`].
+    Stash {
+        container: EntityID,
+        item: String,
+        count: u32,
+    },
+    /// Take items out of a container in range.
+    Retrieve {
+        container: EntityID,
+        item: String,
+        count: u32,
+    },
+}
+
+impl GameAction {
+    /// Returns `true` if the action uses up the acting entity's turn while
+    /// the world runs in turns. Talking, trading and pinging are free.
+    pub fn takes_turn(&self) -> bool {
+        matches!(
+            self,
+            Self::Move(_)
+                | Self::MoveGroup { .. }
+                | Self::Attack(_)
+                | Self::AttackToward(_)
+                | Self::Throw { .. }
+                | Self::Repair(_)
+                | Self::Queue(_)
+                | Self::EndTurn
+                | Self::Wait(_)
+        )
+    }
 }
 
 /// Events emitted by [`apply`] so upper layers know what happened.
@@ -113,6 +463,100 @@ pub enum GameEvent {
     },
     /// Upper layer should trigger a world save.
     SaveRequested,
+    /// The entity's conversation started, moved on or ended.
+    DialogueChanged {
+        entity_id: EntityID,
+    },
+    /// A buy or sell by the entity went through.
+    Traded {
+        entity_id: EntityID,
+    },
+    TradeRejected {
+        entity_id: EntityID,
+        reason: TradeError,
+    },
+    /// The entity hit `target` in melee.
+    Attacked {
+        entity_id: EntityID,
+        target: EntityID,
+    },
+    /// The entity was knocked from `from` to `to`, as opposed to walking
+    /// there, and slammed into `obstacle` if one stopped it.
+    Pushed {
+        entity_id: EntityID,
+        from: Point,
+        to: Point,
+        obstacle: Option<EntityID>,
+    },
+    /// A blast went off; see [`explosion`].
+    Exploded(Explosion),
+    /// The entity lost `amount` hit points to a hit.
+    Damaged {
+        entity_id: EntityID,
+        amount: u32,
+    },
+    /// A creature ran out of health and left a corpse.
+    Died {
+        entity_id: EntityID,
+        name: Option<String>,
+    },
+    /// The entity wore its `item` through and it broke.
+    ItemBroke {
+        entity_id: EntityID,
+        item: String,
+    },
+    /// The entity repaired its `item`.
+    Repaired {
+        entity_id: EntityID,
+        item: String,
+    },
+    RepairRejected {
+        entity_id: EntityID,
+        reason: RepairError,
+    },
+    /// The entity split, merged or moved items.
+    StacksChanged {
+        entity_id: EntityID,
+    },
+    StackRejected {
+        entity_id: EntityID,
+        reason: StackError,
+    },
+    /// The entity's current intent ended, done or given up.
+    IntentEnded {
+        entity_id: EntityID,
+        completed: bool,
+    },
+    /// The entity has done `done` of the `total` ticks its channelled
+    /// intent takes; reported as it starts and a few times on the way.
+    ActionProgress {
+        entity_id: EntityID,
+        done: u32,
+        total: u32,
+    },
+    /// A world event started or ended.
+    Announced(world_events::Announcement),
+    /// A player marked a tile for their team.
+    Pinged(Ping),
+    /// The entity gave up walking to `destination`, the way there blocked
+    /// for [`formation::PATH_PATIENCE`] ticks.
+    PathBlocked {
+        entity_id: EntityID,
+        destination: Point,
+    },
+    /// The entity stopped waiting, as `end` says; see [`scheduler`].
+    WaitEnded {
+        entity_id: EntityID,
+        end: WaitEnd,
+    },
+    /// A chunk of an endless world came into range and its entities were
+    /// planted or put back; see [`chunks`].
+    ChunkLoaded(ChunkId),
+    /// A chunk of an endless world fell out of range and its entities were
+    /// put aside.
+    ChunkUnloaded(ChunkId),
+    /// A roll decided something; see [`rng`].
+    Rolled(rng::Rolled),
 }
 
 // ---------------------------------------------------------------------------
@@ -120,11 +564,71 @@ pub enum GameEvent {
 // ---------------------------------------------------------------------------
 
 /// Pure, deterministic game state — no networking handles, no UI state.
-#[derive(Debug, Clone, PartialEq, Encode, Decode)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub struct GameState {
     pub entity_gen: EntityGenerator,
-    pub entities: EntityMap,
+    /// Changed through [`insert_entity`](Self::insert_entity),
+    /// [`place_entity`](Self::place_entity),
+    /// [`remove_entity`](Self::remove_entity) and
+    /// [`entity_mut`](Self::entity_mut), which keep the index in step.
+    entities: EntityMap,
+    pub world_id: WorldId,
     pub world_name: String,
+    /// Number of server ticks this world has been simulated for.
+    pub tick: u64,
+    /// Scheduled events currently running; see [`world_events`].
+    pub world_events: Vec<ActiveEvent>,
+    /// The characters each player owns; see [`roster`].
+    pub rosters: Rosters,
+    /// What the host lets characters carry in and out; see [`transfer`].
+    pub transfer: TransferRules,
+    /// What the world was generated from, if it was; see [`worldgen`].
+    pub generation: Option<GenManifest>,
+    /// Whose turn it is, while the world runs in turns rather than in real
+    /// time; see [`scheduler`].
+    pub turns: Option<Scheduler>,
+    /// The chunks of an infinite world, loaded or put aside; see
+    /// [`chunks`].
+    pub chunks: Option<Chunks>,
+    /// The ground under the entities; see [`terrain`].
+    pub terrain: TileMap,
+    /// The entities by tile, rebuilt rather than saved; see [`spatial`].
+    #[bitcode(skip)]
+    index: SpatialIndex,
+}
+
+/// States are equal when what is saved of them is; the index only mirrors
+/// the entities.
+impl PartialEq for GameState {
+    fn eq(&self, other: &Self) -> bool {
+        let Self {
+            entity_gen,
+            entities,
+            world_id,
+            world_name,
+            tick,
+            world_events,
+            rosters,
+            transfer,
+            generation,
+            turns,
+            chunks,
+            terrain,
+            index: _,
+        } = self;
+        *entity_gen == other.entity_gen
+            && *entities == other.entities
+            && *world_id == other.world_id
+            && *world_name == other.world_name
+            && *tick == other.tick
+            && *world_events == other.world_events
+            && *rosters == other.rosters
+            && *transfer == other.transfer
+            && *generation == other.generation
+            && *turns == other.turns
+            && *chunks == other.chunks
+            && *terrain == other.terrain
+    }
 }
 
 impl GameState {
@@ -144,23 +648,174 @@ impl GameState {
 
         for pos in tree_positions {
             let id = entity_gen.next();
-            entities.insert(
-                id,
-                Entity {
-                    name: None,
-                    position: pos,
-                    entity_type: EntityType::Tree,
-                },
-            );
+            entities.insert(id, Entity::new(EntityType::Tree, pos, None));
         }
 
         Self {
             entity_gen,
+            index: SpatialIndex::of(&entities),
             entities,
+            world_id: WorldId::generate(),
             world_name: name,
+            tick: 0,
+            world_events: Vec::new(),
+            rosters: Rosters::new(),
+            transfer: TransferRules::default(),
+            generation: None,
+            turns: None,
+            chunks: None,
+            terrain: TileMap::default(),
+        }
+    }
+
+    /// Generate a dungeon of rooms and corridors cut out of rock,
+    /// `width` × `height` tiles, from `seed`; see [`worldgen::dungeon`].
+    pub fn create_dungeon_world(name: String, width: u32, height: u32, seed: u64) -> Self {
+        worldgen::dungeon::generate(name, width, height, seed)
+    }
+
+    /// The seed, config and generator version the world was generated
+    /// from, for generating it again; `None` for worlds made another way.
+    pub fn gen_manifest(&self) -> Option<&GenManifest> {
+        self.generation.as_ref()
+    }
+
+    /// Platform-independent checksum of the simulated state, for determinism
+    /// tests. The world's identity (id and name) is not included.
+    pub fn checksum(&self) -> u64 {
+        let mut hash = fnv1a(FNV_OFFSET, &self.tick.to_le_bytes());
+        hash = fnv1a(hash, &self.entity_gen.0.to_le_bytes());
+        for (eid, entity) in &self.entities {
+            hash = entity_checksum(fnv1a(hash, &eid.0.to_le_bytes()), entity);
+        }
+        hash = fnv1a(hash, &(self.world_events.len() as u64).to_le_bytes());
+        for event in &self.world_events {
+            hash = fnv1a(fnv1a_str(hash, &event.id), &event.ends_at.to_le_bytes());
+        }
+        if let Some(chunks) = &self.chunks {
+            for chunk in chunks.loaded() {
+                hash = fnv1a(fnv1a(hash, &chunk.x.to_le_bytes()), &chunk.y.to_le_bytes());
+            }
+            for (eid, entity) in chunks.stored_entities() {
+                hash = fnv1a(hash, &eid.0.to_le_bytes());
+                hash = fnv1a(hash, &entity.position.x.to_le_bytes());
+                hash = fnv1a(hash, &entity.position.y.to_le_bytes());
+            }
+        }
+        if let Some(turns) = &self.turns {
+            hash = turns.checksum(hash);
+        }
+        for (chunk, tiles) in self.terrain.chunks() {
+            hash = fnv1a(fnv1a(hash, &chunk.x.to_le_bytes()), &chunk.y.to_le_bytes());
+            let tags: Vec<u8> = tiles.iter().map(|tile| tile.tag()).collect();
+            hash = fnv1a(hash, &tags);
+        }
+        hash
+    }
+
+    /// The entities, by ID.
+    pub fn entities(&self) -> &EntityMap {
+        &self.entities
+    }
+
+    /// The entities by tile, as of the last [`sync_index`](Self::sync_index)
+    /// and the changes made since through [`insert_entity`](Self::insert_entity),
+    /// [`place_entity`](Self::place_entity) and
+    /// [`remove_entity`](Self::remove_entity).
+    pub fn index(&self) -> &SpatialIndex {
+        &self.index
+    }
+
+    /// Bring the [`index`](Self::index) up to date with the entities; see
+    /// [`SpatialIndex::sync`].
+    pub fn sync_index(&mut self) {
+        self.index.sync(&self.entities);
+    }
+
+    /// Rebuild the index from scratch, after the world jumped to another
+    /// tick.
+    pub fn rebuild_index(&mut self) {
+        self.index.invalidate();
+        self.sync_index();
+    }
+
+    /// Replace every entity, as with a snapshot, and rebuild the index.
+    pub fn set_entities(&mut self, entities: EntityMap) {
+        self.entities = entities;
+        self.rebuild_index();
+    }
+
+    /// Change the entities with `f`, as a delta does, then bring the index
+    /// up to date with the `changed` ones.
+    pub fn edit_entities<T>(
+        &mut self,
+        changed: impl IntoIterator<Item = EntityID>,
+        f: impl FnOnce(&mut EntityMap) -> T,
+    ) -> T {
+        let out = f(&mut self.entities);
+        self.index.note(changed);
+        self.sync_index();
+        out
+    }
+
+    /// `eid`, to change. It is noted in the index, so a new position is
+    /// picked up by the next [`sync_index`](Self::sync_index);
+    /// [`place_entity`](Self::place_entity) moves it right away.
+    pub fn entity_mut(&mut self, eid: EntityID) -> Option<&mut Entity> {
+        let entity = self.entities.get_mut(&eid)?;
+        self.index.note([eid]);
+        Some(entity)
+    }
+
+    /// Every entity, in ID order, to change; each is noted in the index as
+    /// by [`entity_mut`](Self::entity_mut).
+    pub fn entities_mut(&mut self) -> impl Iterator<Item = (EntityID, &mut Entity)> {
+        self.index.note(self.entities.keys().copied());
+        self.entities.iter_mut().map(|(eid, entity)| (*eid, entity))
+    }
+
+    /// Add `entity` as `eid`, or replace the entity there was, keeping the
+    /// index in step. Returns the entity replaced.
+    pub fn insert_entity(&mut self, eid: EntityID, entity: Entity) -> Option<Entity> {
+        self.index.set(eid, Some(entity.position));
+        self.entities.insert(eid, entity)
+    }
+
+    /// Remove `eid`, keeping the index in step.
+    pub fn remove_entity(&mut self, eid: EntityID) -> Option<Entity> {
+        self.index.set(eid, None);
+        self.entities.remove(&eid)
+    }
+
+    /// Move `eid` to `to`, keeping the index in step.
+    pub fn place_entity(&mut self, eid: EntityID, to: Point) {
+        if let Some(entity) = self.entities.get_mut(&eid) {
+            entity.position = to;
+            self.index.set(eid, Some(to));
         }
     }
 
+    /// Estimated memory held by the state.
+    pub fn memory_report(&self) -> MemoryReport {
+        MemoryReport::default()
+            .with("entities", entity_map_bytes(&self.entities))
+            .with("spatial index", self.index.memory_bytes())
+            .with("terrain", self.terrain.heap_bytes())
+            .with(
+                "unloaded chunks",
+                self.chunks.as_ref().map_or(0, Chunks::heap_bytes),
+            )
+            .with("world name", self.world_name.capacity())
+    }
+
+    /// IDs of the entities tagged `tag`, in ID order.
+    pub fn tagged<'a>(&'a self, tag: &'a str) -> impl Iterator<Item = EntityID> + 'a {
+        self.entities
+            .iter()
+            .filter(move |(_, e)| e.tags.contains(tag))
+            .map(|(eid, _)| *eid)
+    }
+
     /// Return IDs of all player-type entities.
     pub fn get_playable_entities(&self) -> Vec<EntityID> {
         self.entities
@@ -171,21 +826,155 @@ impl GameState {
     }
 }
 
+/// Fold what `entity` is and carries into `hash`, for
+/// [`GameState::checksum`].
+fn entity_checksum(mut hash: u64, entity: &Entity) -> u64 {
+    hash = fnv1a(hash, &entity.position.x.to_le_bytes());
+    hash = fnv1a(hash, &entity.position.y.to_le_bytes());
+    hash = fnv1a(hash, &[entity.entity_type.tag()]);
+    let name = entity.name.as_deref().unwrap_or_default();
+    hash = fnv1a_str(hash, name);
+    hash = fnv1a(hash, &(entity.tags.len() as u64).to_le_bytes());
+    for tag in entity.tags.iter() {
+        hash = fnv1a_str(hash, tag);
+    }
+    hash = fnv1a(hash, &(entity.metadata.len() as u64).to_le_bytes());
+    for (key, value) in entity.metadata.iter() {
+        hash = fnv1a_str(fnv1a_str(hash, key), value);
+    }
+    hash = fnv1a_str(hash, entity.faction.as_deref().unwrap_or_default());
+    hash = fnv1a(hash, &(entity.reputation.len() as u64).to_le_bytes());
+    for (faction, value) in entity.reputation.iter() {
+        hash = fnv1a(fnv1a_str(hash, faction), &value.to_le_bytes());
+    }
+    hash = fnv1a_str(hash, entity.dialogue.as_deref().unwrap_or_default());
+    if let Some(conversation) = &entity.conversation {
+        hash = fnv1a(hash, &conversation.npc.0.to_le_bytes());
+        hash = fnv1a_str(fnv1a_str(hash, &conversation.tree), &conversation.node);
+    }
+    hash = fnv1a(hash, &(entity.inventory.len() as u64).to_le_bytes());
+    for (item, count) in entity.inventory.iter() {
+        hash = fnv1a(fnv1a_str(hash, item), &count.to_le_bytes());
+    }
+    for (item, wear) in entity.inventory.worn() {
+        hash = fnv1a(fnv1a_str(hash, item), &wear.to_le_bytes());
+    }
+    for (item, stacks) in entity.inventory.split_stacks() {
+        hash = fnv1a_str(hash, item);
+        for count in stacks {
+            hash = fnv1a(hash, &count.to_le_bytes());
+        }
+    }
+    if let Some(shop) = &entity.shop {
+        hash = fnv1a_str(hash, &shop.id);
+        for (item, count) in shop.iter() {
+            hash = fnv1a(fnv1a_str(hash, item), &count.to_le_bytes());
+        }
+    }
+    hash = fnv1a(hash, &entity.owner.map_or(0, |o| o.0).to_le_bytes());
+    if let Some(destination) = entity.destination {
+        hash = fnv1a(hash, &destination.x.to_le_bytes());
+        hash = fnv1a(hash, &destination.y.to_le_bytes());
+    }
+    if let Some(since) = entity.blocked_since {
+        hash = fnv1a(hash, &since.to_le_bytes());
+    }
+    if let Some(follow) = entity.follow {
+        hash = fnv1a(hash, &follow.leash.to_le_bytes());
+        hash = fnv1a(hash, &[u8::from(follow.catching_up)]);
+    }
+    if let Some(health) = entity.health {
+        hash = fnv1a(hash, &health.current.to_le_bytes());
+        hash = fnv1a(hash, &health.max.to_le_bytes());
+    }
+    hash = fnv1a(hash, &entity.combat.attack.to_le_bytes());
+    hash = fnv1a(hash, &entity.combat.defense.to_le_bytes());
+    if let Some(PlayerColor(rgb)) = entity.color {
+        hash = fnv1a(hash, &rgb);
+    }
+    if !entity.queue.is_empty() {
+        hash = fnv1a(hash, &(entity.queue.len() as u64).to_le_bytes());
+        for queued in entity.queue.iter() {
+            hash = fnv1a(hash, &[queued.tag()]);
+            hash = match queued {
+                Intent::WalkTo(at) | Intent::Build(at) => {
+                    fnv1a(fnv1a(hash, &at.x.to_le_bytes()), &at.y.to_le_bytes())
+                }
+                Intent::Chop(target) => fnv1a(hash, &target.0.to_le_bytes()),
+                Intent::Craft(item) => fnv1a_str(hash, item),
+            };
+        }
+        let started_at = entity.queue.started_at().unwrap_or(u64::MAX);
+        hash = fnv1a(hash, &started_at.to_le_bytes());
+    }
+    if let Some(ai) = entity.ai {
+        hash = fnv1a(hash, &[ai.tag()]);
+    }
+    hash
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// 64-bit FNV-1a, chosen over `FxHasher` because its output does not depend
+/// on the platform's pointer width.
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |h, b| {
+        (h ^ u64::from(*b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Hash a length-prefixed string, so that adjacent strings cannot run into
+/// each other.
+fn fnv1a_str(hash: u64, s: &str) -> u64 {
+    fnv1a(fnv1a(hash, &(s.len() as u64).to_le_bytes()), s.as_bytes())
+}
+
 // ---------------------------------------------------------------------------
 // Pure apply function
 // ---------------------------------------------------------------------------
 
+/// Bring the index up to date and, while the world runs in turns, spend
+/// `entity_id`'s turn on `action`; see [`apply`]. Returns `false` if the
+/// action must be ignored, since it is not the actor's turn.
+fn take_turn(state: &mut GameState, entity_id: EntityID, action: &GameAction) -> bool {
+    state.sync_index();
+    if (action.takes_turn() || *action == GameAction::Cancel)
+        && let Some(turns) = &mut state.turns
+    {
+        turns.stop_waiting(entity_id);
+    }
+    if action.takes_turn()
+        && state
+            .entities
+            .get(&entity_id)
+            .is_some_and(scheduler::is_actor)
+        && let Some(turns) = &mut state.turns
+    {
+        if turns.next_actor(&state.entities) != Some(entity_id) {
+            return false;
+        }
+        turns.spend(entity_id);
+        turns.next_actor(&state.entities);
+    }
+    true
+}
+
 /// Apply a single [`GameAction`] to the game state and return resulting events.
 ///
 /// This is the **only** way game state should be mutated. The function is pure:
 /// given identical `(state, entity_id, action)` inputs it always produces the
 /// same output, which makes it straightforward to test and to replay.
+///
+/// While the world runs in turns, an action by an
+/// [actor](scheduler::is_actor) that [takes a turn](GameAction::takes_turn)
+/// is ignored unless it is the actor's turn, and hands the turn on to the
+/// next one. It ends the actor's wait all the same, as does a cancel.
 pub fn apply(state: &mut GameState, entity_id: EntityID, action: &GameAction) -> Vec<GameEvent> {
+    if !take_turn(state, entity_id, action) {
+        return Vec::new();
+    }
     match action {
-        GameAction::Move(direction) => {
-            move_entity(state, entity_id, *direction);
-            vec![GameEvent::EntityMoved { entity_id }]
-        }
+        GameAction::Move(direction) => walk(state, entity_id, *direction),
         GameAction::SpawnPlayer(name) => {
             let new_id = spawn_player(state, name.clone());
             vec![GameEvent::PlayerSpawned { entity_id: new_id }]
@@ -196,54 +985,305 @@ pub fn apply(state: &mut GameState, entity_id: EntityID, action: &GameAction) ->
         GameAction::SaveWorld => {
             vec![GameEvent::SaveRequested]
         }
+        GameAction::Talk(npc) => {
+            let registry = ContentRegistry::builtin();
+            if dialogue::talk(state, &registry, entity_id, *npc) {
+                vec![GameEvent::DialogueChanged { entity_id }]
+            } else {
+                Vec::new()
+            }
+        }
+        GameAction::Choose(index) => {
+            let registry = ContentRegistry::builtin();
+            if dialogue::choose(state, &registry, entity_id, *index) {
+                vec![GameEvent::DialogueChanged { entity_id }]
+            } else {
+                Vec::new()
+            }
+        }
+        GameAction::Buy { .. }
+        | GameAction::Sell { .. }
+        | GameAction::Repair(_)
+        | GameAction::SplitStack { .. }
+        | GameAction::MergeStacks(_)
+        | GameAction::Stash { .. }
+        | GameAction::Retrieve { .. } => item_action(state, entity_id, action),
+        GameAction::Attack(target) => {
+            let events = combat::attack(state, &ContentRegistry::builtin(), entity_id, *target);
+            if !events.is_empty() {
+                roster::record(state, entity_id, |stats| stats.attacks += 1);
+            }
+            events
+        }
+        GameAction::AttackToward(direction) => {
+            let registry = ContentRegistry::builtin();
+            let events = combat::attack_toward(state, &registry, entity_id, *direction);
+            if !events.is_empty() {
+                roster::record(state, entity_id, |stats| stats.attacks += 1);
+            }
+            events
+        }
+        GameAction::Throw { item, target } if item == explosion::BOMB => {
+            explosion::throw_bomb(state, entity_id, *target)
+                .into_iter()
+                .collect()
+        }
+        GameAction::Throw { .. } | GameAction::EndTurn => Vec::new(),
+        GameAction::Queue(queued) => {
+            if let Some(entity) = state.entity_mut(entity_id) {
+                entity.queue.push(queued.clone());
+            }
+            Vec::new()
+        }
+        GameAction::Cancel => intent::cancel(state, entity_id).into_iter().collect(),
+        GameAction::Ping { pos, kind } => ping::ping(state, entity_id, *pos, *kind)
+            .into_iter()
+            .collect(),
+        GameAction::MoveGroup { members, target } => {
+            // Members walk over the next ticks, in `formation::advance`.
+            formation::order(state, entity_id, members, *target);
+            Vec::new()
+        }
+        GameAction::Wait(wait) => scheduler::wait(state, entity_id, *wait)
+            .into_iter()
+            .collect(),
+        GameAction::SetColor(color) => {
+            if let Some(entity) = state.entity_mut(entity_id)
+                && entity.entity_type == EntityType::Player
+            {
+                entity.color = Some(color.legible());
+            }
+            Vec::new()
+        }
     }
 }
 
-/// Spawn a new player entity and return its ID.
-pub fn spawn_player(state: &mut GameState, name: String) -> EntityID {
-    let id = state.entity_gen.next();
-    state.entities.insert(
-        id,
-        Entity {
-            name: Some(name),
-            position: Point { x: 10, y: 10 },
-            entity_type: EntityType::Player,
+/// Buy, sell, repair, split, merge or move an item.
+fn item_action(state: &mut GameState, entity_id: EntityID, action: &GameAction) -> Vec<GameEvent> {
+    match action {
+        GameAction::Buy {
+            vendor,
+            item,
+            count,
+        } => {
+            let registry = ContentRegistry::builtin();
+            vec![trade_event(
+                entity_id,
+                shop::buy(state, &registry, entity_id, *vendor, item, *count),
+            )]
+        }
+        GameAction::Sell {
+            vendor,
+            item,
+            count,
+        } => {
+            let registry = ContentRegistry::builtin();
+            vec![trade_event(
+                entity_id,
+                shop::sell(state, &registry, entity_id, *vendor, item, *count),
+            )]
+        }
+        GameAction::Repair(item) => {
+            let outcome = item::repair(state, &ContentRegistry::builtin(), entity_id, item);
+            vec![repair_event(entity_id, item, outcome)]
+        }
+        GameAction::SplitStack { item, count } => {
+            let registry = ContentRegistry::builtin();
+            let outcome = state
+                .entities
+                .get_mut(&entity_id)
+                .map_or(Err(StackError::NotCarried), |entity| {
+                    entity.inventory.split(&registry, item, *count)
+                });
+            vec![stack_event(entity_id, outcome)]
+        }
+        GameAction::MergeStacks(item) => {
+            let outcome = state
+                .entities
+                .get_mut(&entity_id)
+                .map_or(Err(StackError::NotSplit), |entity| {
+                    entity.inventory.merge(item)
+                });
+            vec![stack_event(entity_id, outcome)]
+        }
+        GameAction::Stash {
+            container,
+            item,
+            count,
+        } => {
+            let registry = ContentRegistry::builtin();
+            let outcome = item::stash(state, &registry, entity_id, *container, item, *count);
+            vec![stack_event(entity_id, outcome)]
+        }
+        GameAction::Retrieve {
+            container,
+            item,
+            count,
+        } => {
+            let registry = ContentRegistry::builtin();
+            let outcome = item::retrieve(state, &registry, entity_id, *container, item, *count);
+            vec![stack_event(entity_id, outcome)]
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Step `entity_id` in `direction`, unless the way is blocked or it
+/// cannot move.
+fn walk(state: &mut GameState, entity_id: EntityID, direction: Direction) -> Vec<GameEvent> {
+    let registry = ContentRegistry::builtin();
+    let blocked = state.entities.get(&entity_id).is_some_and(|e| {
+        !item::can_step(e, &registry, state.tick)
+            || collision::is_impassable(state, path::step(e.position, direction))
+    });
+    if blocked {
+        return Vec::new();
+    }
+    move_entity(state, entity_id, direction);
+    roster::record(state, entity_id, |stats| stats.steps += 1);
+    let mut events = vec![GameEvent::EntityMoved { entity_id }];
+    if let Some(entity) = state.entity_mut(entity_id) {
+        // Taking a step by hand cancels a group order and whatever
+        // was queued.
+        formation::stop(entity);
+        intent::interrupt(entity, Interruption::Step);
+        // Walking away ends a conversation.
+        if entity.conversation.take().is_some() {
+            events.push(GameEvent::DialogueChanged { entity_id });
+        }
+    }
+    events
+}
+
+fn repair_event(entity_id: EntityID, item: &str, outcome: Result<(), RepairError>) -> GameEvent {
+    match outcome {
+        Ok(()) => GameEvent::Repaired {
+            entity_id,
+            item: item.to_owned(),
         },
-    );
-    id
+        Err(reason) => GameEvent::RepairRejected { entity_id, reason },
+    }
 }
 
-/// Move an entity one tile in the given direction.
-pub fn move_entity(state: &mut GameState, entity_id: EntityID, direction: Direction) {
-    if let Some(entity) = state.entities.get_mut(&entity_id) {
-        let (dx, dy) = direction.delta();
-        entity.position.x = entity.position.x.saturating_add(dx);
-        entity.position.y = entity.position.y.saturating_add(dy);
+fn stack_event(entity_id: EntityID, outcome: Result<(), StackError>) -> GameEvent {
+    match outcome {
+        Ok(()) => GameEvent::StacksChanged { entity_id },
+        Err(reason) => GameEvent::StackRejected { entity_id, reason },
     }
 }
 
-// ---------------------------------------------------------------------------
-// Persistence (serialization + file I/O)
-// ---------------------------------------------------------------------------
+fn trade_event(entity_id: EntityID, outcome: Result<(), TradeError>) -> GameEvent {
+    match outcome {
+        Ok(()) => GameEvent::Traded { entity_id },
+        Err(reason) => GameEvent::TradeRejected { entity_id, reason },
+    }
+}
+
+/// Finish a tick once its actions are applied.
+///
+/// Works on queued intents, walks ordered groups and followers in the
+/// [`region`]s around players, then restocks shops and runs world events for
+/// the new tick, returning what happened.
+///
+/// Together with [`apply`] this is everything a tick does to the world, so
+/// re-running the same actions from the same state gives the same state.
+///
+/// While the world runs in turns, this first passes the turn of the actor
+/// up next if it [waits](scheduler::pass_waiting), then simulates as many
+/// ticks as the turns taken since used up, none while waiting on an actor.
+pub fn advance(state: &mut GameState, registry: &ContentRegistry) -> Vec<GameEvent> {
+    advance_timed(state, registry, None)
+}
+
+/// [`advance`], counting the time spent in creature AI towards
+/// [`System::Ai`] of `profiler` and the rest towards [`System::Apply`].
+pub fn advance_profiled(
+    state: &mut GameState,
+    registry: &ContentRegistry,
+    profiler: &mut Profiler,
+) -> Vec<GameEvent> {
+    advance_timed(state, registry, Some(profiler))
+}
+
+fn advance_timed(
+    state: &mut GameState,
+    registry: &ContentRegistry,
+    mut profiler: Option<&mut Profiler>,
+) -> Vec<GameEvent> {
+    state.sync_index();
+    if state.turns.is_none() {
+        return advance_tick(state, registry, profiler);
+    }
+    let mut events = scheduler::pass_waiting(state);
+    let due = state.turns.as_mut().map_or(0, Scheduler::take_elapsed);
+    for _ in 0..due {
+        events.extend(advance_tick(state, registry, profiler.as_deref_mut()));
+    }
+    events
+}
 
-/// Saves the [`GameState`] to a `.world` file in the `worlds` directory.
-pub fn save_to_file(state: &GameState) -> io::Result<()> {
-    let worlds_dir = PathBuf::from("worlds");
-    fs::create_dir_all(&worlds_dir)?;
+fn advance_tick(
+    state: &mut GameState,
+    registry: &ContentRegistry,
+    profiler: Option<&mut Profiler>,
+) -> Vec<GameEvent> {
+    let start = Instant::now();
+    let mut events = chunks::update(state);
+    let active = region::ActiveRegions::of(state);
+    events.extend(intent::advance(state, registry, &active));
+    events.extend(formation::advance(state, registry, &active));
+    follow::advance(state, registry, &active);
+    let seed = state.gen_manifest().map_or(0, |manifest| manifest.seed);
+    let ai_start = Instant::now();
+    events.extend(ai::run_ai_tick(state, seed));
+    let ai = ai_start.elapsed();
+    state.tick += 1;
+    shop::restock(state, registry);
+    events.extend(
+        world_events::advance(state, registry)
+            .into_iter()
+            .map(GameEvent::Announced),
+    );
+    if let Some(profiler) = profiler {
+        profiler.record(System::Ai, ai);
+        profiler.record(System::Apply, start.elapsed().saturating_sub(ai));
+    }
+    events
+}
 
-    let file_path = worlds_dir.join(format!("{}.world", state.world_name));
-    let encoded = bitcode::encode(state);
-    fs::write(&file_path, encoded)?;
+/// Spawn a new player entity with full health and the starting purse, and
+/// return its ID.
+pub fn spawn_player(state: &mut GameState, name: String) -> EntityID {
+    let id = state.entity_gen.next();
+    let registry = ContentRegistry::builtin();
+    let mut player = Entity::new(EntityType::Player, SPAWN_POINT, Some(name));
+    player.health = Some(Health::new(combat::PLAYER_HEALTH));
+    player
+        .inventory
+        .add(&registry.currency, registry.starting_purse);
+    state.insert_entity(id, player);
+    id
+}
 
-    Ok(())
+/// A cheapest path from `from` to `to` around entities and terrain that
+/// block movement, as the tiles stepped onto in order; `None` if there is none.
+///
+/// Blocking entities are looked up in the state's synced
+/// [`index`](GameState::index). See [`path::find`].
+pub fn pathfind(state: &GameState, from: Point, to: Point) -> Option<Vec<Point>> {
+    path::find(from, to, |p| collision::cost_at(state, p))
 }
 
-/// Loads a [`GameState`] from a `.world` file.
-pub fn load_from_file(file_path: &Path) -> io::Result<GameState> {
-    let bytes = fs::read(file_path)?;
-    let state: GameState =
-        bitcode::decode(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-    Ok(state)
+/// Move an entity one tile in the given direction.
+pub fn move_entity(state: &mut GameState, entity_id: EntityID, direction: Direction) {
+    if let Some(entity) = state.entities.get(&entity_id) {
+        let (dx, dy) = direction.delta();
+        let to = Point {
+            x: entity.position.x.saturating_add(dx),
+            y: entity.position.y.saturating_add(dy),
+        };
+        state.place_entity(entity_id, to);
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -253,12 +1293,24 @@ pub fn load_from_file(file_path: &Path) -> io::Result<GameState> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use item::Encumbrance;
+    use rustc_hash::{FxHashMap, FxHashSet};
 
     fn empty_state() -> GameState {
         GameState {
             entity_gen: EntityGenerator::default(),
             entities: EntityMap::default(),
+            world_id: WorldId(0),
             world_name: "test".into(),
+            tick: 0,
+            world_events: Vec::new(),
+            rosters: Rosters::new(),
+            transfer: TransferRules::default(),
+            generation: None,
+            turns: None,
+            chunks: None,
+            terrain: TileMap::default(),
+            index: SpatialIndex::of(&EntityMap::default()),
         }
     }
 
@@ -363,13 +1415,13 @@ mod tests {
         let mut state = empty_state();
         let id = spawn_player(&mut state, "P".into());
         // Place entity at origin
-        state.entities.get_mut(&id).expect("just spawned").position = Point { x: 0, y: 0 };
+        state.place_entity(id, Point { x: 0, y: 0 });
 
         // i32::saturating_sub(1) allows going below zero (saturates at i32::MIN)
         move_entity(&mut state, id, Direction::Up);
         assert_eq!(state.entities[&id].position, Point { x: 0, y: -1 });
 
-        state.entities.get_mut(&id).expect("exists").position = Point { x: 0, y: 0 };
+        state.place_entity(id, Point { x: 0, y: 0 });
         move_entity(&mut state, id, Direction::Left);
         assert_eq!(state.entities[&id].position, Point { x: -1, y: 0 });
     }
@@ -385,28 +1437,204 @@ mod tests {
     }
 
     #[test]
-    fn apply_spawn_player_returns_player_spawned_event() {
+    fn turns_go_by_energy_and_time_follows_them() {
         let mut state = empty_state();
-        let events = apply(
-            &mut state,
-            EntityID(0),
-            &GameAction::SpawnPlayer("Bob".into()),
-        );
-        assert_eq!(events.len(), 1);
-        match &events[0] {
-            GameEvent::PlayerSpawned { entity_id } => {
-                assert!(state.entities.contains_key(entity_id));
-            }
-            other => panic!("expected PlayerSpawned, got {other:?}"),
-        }
+        let alice = spawn_player(&mut state, "Alice".into());
+        let bob = spawn_player(&mut state, "Bob".into());
+        state.turns = Some(Scheduler::default());
+        let step = GameAction::Move(Direction::Right);
+
+        // Both fill up together, and the lower ID goes first.
+        assert!(apply(&mut state, bob, &step).is_empty());
+        assert!(!apply(&mut state, alice, &step).is_empty());
+        assert!(apply(&mut state, alice, &step).is_empty());
+        let turns = state.turns.as_mut().expect("turns");
+        assert_eq!(turns.next_actor(&state.entities), Some(bob));
+        // Free actions do not wait for a turn.
+        assert!(!apply(&mut state, alice, &GameAction::SpawnPlayer("Carol".into())).is_empty());
+        assert!(apply(&mut state, bob, &GameAction::EndTurn).is_empty());
+
+        let ticks = 2 * scheduler::TURN_COST / scheduler::NORMAL_SPEED;
+        advance(&mut state, &ContentRegistry::builtin());
+        assert_eq!(state.tick, u64::from(ticks));
+        advance(&mut state, &ContentRegistry::builtin());
+        assert_eq!(state.tick, u64::from(ticks));
     }
 
     #[test]
-    fn apply_save_world_returns_save_requested() {
+    fn waits_pass_turns_until_done_or_interrupted() {
         let mut state = empty_state();
-        let events = apply(&mut state, EntityID(0), &GameAction::SaveWorld);
-        assert_eq!(events, vec![GameEvent::SaveRequested]);
-    }
+        let registry = ContentRegistry::builtin();
+        let alice = spawn_player(&mut state, "Alice".into());
+        let ended = |events: Vec<GameEvent>| {
+            events.into_iter().find_map(|e| match e {
+                GameEvent::WaitEnded { entity_id, end } if entity_id == alice => Some(end),
+                _ => None,
+            })
+        };
+        let wait = |turns| GameAction::Wait(Wait::Turns(turns));
+        assert_eq!(
+            ended(apply(&mut state, alice, &wait(3))),
+            Some(WaitEnd::NoTurns)
+        );
+
+        // The first turn passes at once, the others one per tick.
+        state.turns = Some(Scheduler::default());
+        assert!(apply(&mut state, alice, &wait(3)).is_empty());
+        assert_eq!(ended(advance(&mut state, &registry)), None);
+        assert_eq!(ended(advance(&mut state, &registry)), Some(WaitEnd::Done));
+        let per_turn = u64::from(scheduler::TURN_COST / scheduler::NORMAL_SPEED);
+        assert_eq!(state.tick, 4 * per_turn);
+        advance(&mut state, &registry);
+        assert_eq!(state.tick, 4 * per_turn);
+
+        // Resting goes on until someone comes into view...
+        let rest = GameAction::Wait(Wait::UntilInterrupted);
+        assert!(apply(&mut state, alice, &rest).is_empty());
+        for _ in 0..5 {
+            assert_eq!(ended(advance(&mut state, &registry)), None);
+        }
+        assert!(state.tick > 4 * per_turn);
+        let bob = state.entity_gen.next();
+        let near = Point {
+            x: SPAWN_POINT.x + 3,
+            y: SPAWN_POINT.y,
+        };
+        state.insert_entity(bob, Entity::new(EntityType::Npc, near, None));
+        assert_eq!(
+            ended(advance(&mut state, &registry)),
+            Some(WaitEnd::Noticed(bob))
+        );
+
+        // ...or the player is hurt, or does something else.
+        assert!(apply(&mut state, alice, &rest).is_empty());
+        combat::damage(&mut state, alice, 1);
+        assert_eq!(ended(advance(&mut state, &registry)), Some(WaitEnd::Hurt));
+        assert!(apply(&mut state, alice, &rest).is_empty());
+        let turns = state.turns.as_ref().expect("turns");
+        assert!(turns.is_waiting(alice));
+        apply(&mut state, alice, &GameAction::Move(Direction::Up));
+        let turns = state.turns.as_ref().expect("turns");
+        assert!(!turns.is_waiting(alice));
+    }
+
+    #[test]
+    fn creatures_chase_flee_and_wander_the_same_way_every_time() {
+        let mut state = empty_state();
+        let pid = spawn_player(&mut state, "Alice".into());
+        let at = |x| Point {
+            x,
+            y: SPAWN_POINT.y,
+        };
+        let creature = |state: &mut GameState, x, ai| {
+            let eid = state.entity_gen.next();
+            let mut npc = Entity::new(EntityType::Npc, at(x), None);
+            npc.ai = Some(ai);
+            state.insert_entity(eid, npc);
+            eid
+        };
+        let hunter = creature(
+            &mut state,
+            SPAWN_POINT.x + 4,
+            AiBehavior::ChaseNearestPlayer,
+        );
+        let prey = creature(&mut state, SPAWN_POINT.x - 2, AiBehavior::Flee);
+        creature(&mut state, SPAWN_POINT.x + 40, AiBehavior::Wander);
+
+        let mut replayed = state.clone();
+        for _ in 0..3 {
+            ai::run_ai_tick(&mut state, 7);
+        }
+        assert_eq!(
+            state.entities.get(&hunter).expect("hunter").position,
+            at(SPAWN_POINT.x + 1)
+        );
+        assert_eq!(
+            state.entities.get(&prey).expect("prey").position,
+            at(SPAWN_POINT.x - 5)
+        );
+        let events = ai::run_ai_tick(&mut state, 7);
+        assert!(events.contains(&GameEvent::Attacked {
+            entity_id: hunter,
+            target: pid,
+        }));
+
+        for _ in 0..4 {
+            ai::run_ai_tick(&mut replayed, 7);
+        }
+        assert_eq!(replayed.checksum(), state.checksum());
+    }
+
+    #[test]
+    fn profiled_ticks_time_ai_apart_and_change_nothing_else() {
+        let registry = ContentRegistry::builtin();
+        let mut state = empty_state();
+        spawn_player(&mut state, "Alice".into());
+        let eid = state.entity_gen.next();
+        let mut npc = Entity::new(EntityType::Npc, Point { x: 5, y: 5 }, None);
+        npc.ai = Some(AiBehavior::ChaseNearestPlayer);
+        state.insert_entity(eid, npc);
+
+        let mut plain = state.clone();
+        let mut profiler = Profiler::default();
+        for tick in 0..3 {
+            advance(&mut plain, &registry);
+            advance_profiled(&mut state, &registry, &mut profiler);
+            profiler.finish_tick(tick);
+        }
+        assert_eq!(plain.checksum(), state.checksum());
+        for timings in profiler.history() {
+            let (apply, ai) = (timings.get(System::Apply), timings.get(System::Ai));
+            assert!(!apply.is_zero() && !ai.is_zero());
+            assert_eq!(timings.total(), apply + ai);
+        }
+    }
+
+    #[test]
+    fn players_get_apart_colors_and_may_pick_legible_ones() {
+        let colors: FxHashSet<PlayerColor> =
+            (0..8).map(|k| PlayerColor::of(PlayerKey(k))).collect();
+        assert_eq!(colors.len(), 8);
+        assert_eq!(PlayerColor::of(PlayerKey(7)), PlayerColor::of(PlayerKey(7)));
+
+        let mut state = empty_state();
+        let pid = spawn_player(&mut state, "Alice".into());
+        let tree = state.entity_gen.next();
+        let at = Point { x: 3, y: 3 };
+        state.insert_entity(tree, Entity::new(EntityType::Tree, at, None));
+        let dark = PlayerColor([10, 0, 40]);
+        apply(&mut state, pid, &GameAction::SetColor(dark));
+        apply(&mut state, tree, &GameAction::SetColor(dark));
+        assert_eq!(
+            state.entities.get(&pid).expect("pid").color,
+            Some(PlayerColor([32, 0, 128]))
+        );
+        assert_eq!(state.entities.get(&tree).expect("tree").color, None);
+    }
+
+    #[test]
+    fn apply_spawn_player_returns_player_spawned_event() {
+        let mut state = empty_state();
+        let events = apply(
+            &mut state,
+            EntityID(0),
+            &GameAction::SpawnPlayer("Bob".into()),
+        );
+        assert_eq!(events.len(), 1);
+        match &events[0] {
+            GameEvent::PlayerSpawned { entity_id } => {
+                assert!(state.entities.contains_key(entity_id));
+            }
+            other => panic!("expected PlayerSpawned, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn apply_save_world_returns_save_requested() {
+        let mut state = empty_state();
+        let events = apply(&mut state, EntityID(0), &GameAction::SaveWorld);
+        assert_eq!(events, vec![GameEvent::SaveRequested]);
+    }
 
     #[test]
     fn apply_spawn_as_returns_spawn_as_requested() {
@@ -456,7 +1684,2671 @@ mod tests {
         assert_eq!(tree_count, 6);
     }
 
-    // -- get_playable_entities -----------------------------------------------
+    #[test]
+    fn created_worlds_get_distinct_ids() {
+        let a = GameState::create_test_world("w".into());
+        let b = GameState::create_test_world("w".into());
+        assert_ne!(a.world_id, b.world_id);
+        assert_eq!(a.world_id.to_string().len(), 36);
+    }
+
+    // -- worldgen -------------------------------------------------------------
+
+    #[test]
+    fn worldgen_is_deterministic_per_seed() {
+        let config = worldgen::WorldGenConfig::default();
+        let a = worldgen::generate("a".into(), 7, &config);
+        let b = worldgen::generate("b".into(), 7, &config);
+        let c = worldgen::generate("c".into(), 8, &config);
+        assert_eq!(a.checksum(), b.checksum());
+        assert_ne!(a.checksum(), c.checksum());
+        assert_ne!(a.world_id, b.world_id);
+        assert!(!a.entities.is_empty());
+    }
+
+    #[test]
+    fn worldgen_stays_in_bounds_and_clears_spawn() {
+        let config = worldgen::WorldGenConfig {
+            width: 40,
+            height: 30,
+            tree_density: 1000,
+            grove_chance: 0,
+            ..Default::default()
+        };
+        let state = worldgen::generate("w".into(), 1, &config);
+        let radius = config.clearing_radius as i32;
+        for entity in state.entities.values() {
+            let Point { x, y } = entity.position;
+            assert!((0..40).contains(&x) && (0..30).contains(&y));
+            assert!((x - SPAWN_POINT.x).abs() > radius || (y - SPAWN_POINT.y).abs() > radius);
+        }
+        let clearing = (2 * radius + 1) * (2 * radius + 1);
+        let bare = (0..30)
+            .flat_map(|y| (0..40).map(move |x| Point { x, y }))
+            .filter(|p| state.terrain.get(*p) != Terrain::Grass)
+            .count();
+        assert_eq!(state.entities.len(), (40 * 30 - clearing) as usize - bare);
+    }
+
+    #[test]
+    fn worldgen_preview_fits_and_agrees_with_generation() {
+        let config = worldgen::WorldGenConfig {
+            width: 300,
+            height: 100,
+            ..Default::default()
+        };
+        let coarse = worldgen::preview(3, &config, 64);
+        assert_eq!((coarse.width, coarse.height, coarse.scale), (60, 20, 5));
+        assert_eq!(coarse.tree_chance.len(), 60 * 20);
+        assert_eq!(coarse, worldgen::preview(3, &config, 64));
+
+        let fine = worldgen::preview(3, &config, 300);
+        assert_eq!(fine.scale, 1);
+        let state = worldgen::generate("w".into(), 3, &config);
+        for entity in state.entities.values() {
+            let Point { x, y } = entity.position;
+            let chance = fine.tree_chance.get(y as usize * fine.width + x as usize);
+            assert!(chance.is_some_and(|c| *c > 0));
+        }
+        let spawn = SPAWN_POINT.y as usize * fine.width + SPAWN_POINT.x as usize;
+        assert_eq!(fine.tree_chance.get(spawn), Some(&0));
+    }
+
+    #[test]
+    fn rivers_and_lakes_follow_the_seed_across_chunks() {
+        let dry = worldgen::WorldGenConfig {
+            width: 192,
+            height: 192,
+            river_chance: 0,
+            lake_chance: 0,
+            ..Default::default()
+        };
+        let rivers = worldgen::WorldGenConfig {
+            river_chance: 1000,
+            ..dry.clone()
+        };
+        let lakes = worldgen::WorldGenConfig {
+            lake_chance: 1000,
+            ..dry.clone()
+        };
+        let tiles = || (0..192).flat_map(|y| (0..192).map(move |x| Point { x, y }));
+        let water = |state: &GameState| {
+            tiles()
+                .filter(|p| state.terrain.get(*p) == Terrain::Water)
+                .count()
+        };
+        let dry_world = worldgen::generate("w".into(), 6, &dry);
+        let river_world = worldgen::generate("w".into(), 6, &rivers);
+        let lake_world = worldgen::generate("w".into(), 6, &lakes);
+        assert!(water(&river_world) > water(&dry_world) + 1000);
+        assert!(water(&lake_world) > water(&dry_world) + 500);
+
+        // Every strip has a river down its whole length, bed or ford.
+        let radius = dry.clearing_radius as i32;
+        for y in (0..192).filter(|y| (y - SPAWN_POINT.y).abs() > radius) {
+            for strip in 0..192 / worldgen::RIVER_SPACING {
+                let columns =
+                    strip * worldgen::RIVER_SPACING..(strip + 1) * worldgen::RIVER_SPACING;
+                assert!(
+                    columns
+                        .map(|x| river_world.terrain.get(Point { x, y }))
+                        .any(|t| matches!(t, Terrain::Water | Terrain::Sand)),
+                    "no river in strip {strip} at row {y}"
+                );
+            }
+        }
+
+        let preview = worldgen::preview(6, &rivers, 192);
+        assert!(
+            tiles().all(|p| preview.terrain.get(p.y as usize * 192 + p.x as usize)
+                == Some(&river_world.terrain.get(p)))
+        );
+        let endless = worldgen::generate(
+            "w".into(),
+            6,
+            &worldgen::WorldGenConfig {
+                infinite: true,
+                ..rivers
+            },
+        );
+        assert!(
+            tiles()
+                .filter(|p| p.x < 96 && p.y < 96)
+                .all(|p| endless.terrain.get(p) == river_world.terrain.get(p))
+        );
+    }
+
+    #[test]
+    fn dungeons_are_walled_rooms_all_reachable_from_spawn() {
+        let dungeon = GameState::create_dungeon_world("d".into(), 80, 60, 11);
+        let tiles: Vec<Point> = (0..60)
+            .flat_map(|y| (0..80).map(move |x| Point { x, y }))
+            .collect();
+        let floor: FxHashSet<Point> = tiles
+            .iter()
+            .copied()
+            .filter(|p| dungeon.terrain.get(*p) == Terrain::Grass)
+            .collect();
+        assert!(floor.contains(&SPAWN_POINT));
+        assert!(floor.len() > 80 * 60 / 8, "{} tiles of floor", floor.len());
+        assert!(
+            tiles
+                .iter()
+                .filter(|p| p.x == 0 || p.y == 0 || p.x == 79 || p.y == 59)
+                .all(|p| dungeon.terrain.get(*p) == Terrain::Rock)
+        );
+
+        let mut reached = FxHashSet::from_iter([SPAWN_POINT]);
+        let mut frontier = vec![SPAWN_POINT];
+        while let Some(point) = frontier.pop() {
+            for direction in path::DIRECTIONS {
+                let next = path::step(point, direction);
+                if floor.contains(&next) && reached.insert(next) {
+                    frontier.push(next);
+                }
+            }
+        }
+        assert_eq!(reached, floor);
+
+        let again = GameState::create_dungeon_world("d".into(), 80, 60, 11);
+        assert_eq!(again.terrain, dungeon.terrain);
+        let other = GameState::create_dungeon_world("d".into(), 80, 60, 12);
+        assert_ne!(other.terrain, dungeon.terrain);
+        let tiny = GameState::create_dungeon_world("d".into(), 1, 1, 11);
+        assert_eq!(tiny.terrain.get(SPAWN_POINT), Terrain::Grass);
+    }
+
+    #[test]
+    fn generated_worlds_keep_their_manifest_and_extend_identically() {
+        let config = worldgen::WorldGenConfig {
+            width: 30,
+            height: 20,
+            ..Default::default()
+        };
+        let mut small = worldgen::generate("w".into(), 9, &config);
+        let manifest = small.gen_manifest().expect("generated").clone();
+        assert_eq!(manifest.seed, 9);
+        assert_eq!(
+            worldgen::WorldGenConfig::parse(&manifest.to_string()),
+            Ok(config)
+        );
+        assert!(
+            GameState::create_test_world("t".into())
+                .gen_manifest()
+                .is_none()
+        );
+
+        worldgen::extend(&mut small, 50, 40).expect("same generator");
+        let big = worldgen::generate(
+            "w".into(),
+            9,
+            &worldgen::WorldGenConfig {
+                width: 50,
+                height: 40,
+                ..Default::default()
+            },
+        );
+        let trees = |state: &GameState| {
+            let mut trees: Vec<Point> = state.entities.values().map(|e| e.position).collect();
+            trees.sort_by_key(|p| (p.x, p.y));
+            trees
+        };
+        assert_eq!(trees(&small), trees(&big));
+        assert_eq!(small.terrain, big.terrain);
+        assert_eq!(small.gen_manifest(), big.gen_manifest());
+
+        small.generation = Some(worldgen::GenManifest {
+            version: worldgen::GENERATOR_VERSION + 1,
+            ..manifest
+        });
+        let err = worldgen::extend(&mut small, 60, 60).expect_err("another generator version");
+        assert!(err.contains("generator version"), "{err}");
+    }
+
+    #[test]
+    fn names_follow_the_seed_and_name_hermits_and_regions() {
+        use worldgen::namegen;
+
+        let world = namegen::world_name(5);
+        assert_eq!(world, namegen::world_name(5));
+        assert!(world.starts_with(|c: char| c.is_uppercase()), "{world}");
+        let names: FxHashSet<String> = (0..20).map(namegen::world_name).collect();
+        assert!(names.len() > 10, "{names:?}");
+
+        let region = |x, y| namegen::region_name(5, Point { x, y });
+        assert_eq!(region(0, 0), region(63, 63));
+        assert_ne!(region(0, 0), namegen::region_name(6, Point { x: 0, y: 0 }));
+
+        let config = worldgen::WorldGenConfig {
+            width: 200,
+            height: 200,
+            grove_chance: 1000,
+            ..Default::default()
+        };
+        let state = worldgen::generate("w".into(), 5, &config);
+        let hermits: Vec<&Entity> = state
+            .entities
+            .values()
+            .filter(|e| e.entity_type == EntityType::Npc)
+            .collect();
+        assert!(!hermits.is_empty());
+        for hermit in hermits {
+            let name = hermit.name.as_deref().unwrap_or_default();
+            assert_eq!(name, namegen::npc_name(5, hermit.position));
+        }
+
+        let err = ContentRegistry::from_sources(content::Sources {
+            names: r#"(styles: [(id: "world", syllables: (1, 2), onsets: [], vowels: ["a"], codas: [])])"#,
+            ..content::Sources::BUILTIN
+        })
+        .expect_err("a missing name style");
+        assert!(err.contains("`region` is missing"), "{err}");
+    }
+
+    #[test]
+    fn infinite_worlds_plant_chunks_near_players_and_keep_them_aside() {
+        let config = worldgen::WorldGenConfig {
+            width: 200,
+            height: 200,
+            ..Default::default()
+        };
+        let endless = worldgen::WorldGenConfig {
+            infinite: true,
+            ..config.clone()
+        };
+        let mut state = worldgen::generate("w".into(), 4, &endless);
+        assert!(state.entities.values().all(|e| {
+            let Point { x, y } = e.position;
+            (-64..96).contains(&x) && (-64..96).contains(&y)
+        }));
+        let trees = |state: &GameState, max: i32| {
+            let mut trees: Vec<Point> = state
+                .entities
+                .values()
+                .map(|e| e.position)
+                .filter(|p| (0..max).contains(&p.x) && (0..max).contains(&p.y))
+                .collect();
+            trees.sort_by_key(|p| (p.x, p.y));
+            trees
+        };
+        let bounded = worldgen::generate("w".into(), 4, &config);
+        assert_eq!(trees(&state, 96), trees(&bounded, 96));
+        assert!(worldgen::extend(&mut state, 300, 300).is_err());
+
+        let registry = ContentRegistry::builtin();
+        let player = spawn_player(&mut state, "Alice".into());
+        let walk = |state: &mut GameState, x, y| {
+            state.place_entity(player, Point { x, y });
+            advance(state, &registry)
+        };
+        let events = walk(&mut state, -1000, -1000);
+        assert!(events.contains(&GameEvent::ChunkLoaded(ChunkId { x: -32, y: -32 })));
+        let far: Vec<EntityID> = state
+            .entities
+            .iter()
+            .filter(|(_, e)| e.entity_type != EntityType::Player && e.position.x < -900)
+            .map(|(eid, _)| *eid)
+            .collect();
+        let marked = *far.first().expect("land west of the origin is planted too");
+        state
+            .entities
+            .get_mut(&marked)
+            .expect("planted")
+            .tags
+            .insert("marked")
+            .expect("tag");
+
+        let events = walk(&mut state, 1000, 1000);
+        assert!(events.contains(&GameEvent::ChunkUnloaded(ChunkId { x: -32, y: -32 })));
+        assert!(!state.entities.contains_key(&marked));
+        let chunks = state.chunks.as_ref().expect("infinite");
+        assert!(chunks.stored() > 0);
+        assert!(
+            chunks
+                .loaded()
+                .any(|chunk| chunk == ChunkId::of(SPAWN_POINT))
+        );
+
+        let before = state.entity_gen.0;
+        walk(&mut state, -1000, -1000);
+        assert_eq!(state.entity_gen.0, before, "nothing is planted twice");
+        assert!(
+            state
+                .entities
+                .get(&marked)
+                .expect("marked")
+                .tags
+                .contains("marked")
+        );
+    }
+
+    #[test]
+    fn worldgen_config_parses_and_validates() {
+        let config = worldgen::WorldGenConfig::parse("# big\nwidth 300\ngrove_chance 0\n")
+            .expect("valid config");
+        assert_eq!(config.width, 300);
+        assert_eq!(config.grove_chance, 0);
+        assert_eq!(config.height, worldgen::WorldGenConfig::default().height);
+
+        let err =
+            worldgen::WorldGenConfig::parse("width 10\ncolour green").expect_err("an unknown key");
+        assert!(err.starts_with("line 2"), "{err}");
+        assert!(worldgen::WorldGenConfig::parse("tree_density 1001").is_err());
+        assert!(worldgen::WorldGenConfig::parse("width 0").is_err());
+        let endless = worldgen::WorldGenConfig::parse("infinite 1").expect("valid config");
+        assert!(endless.infinite);
+        assert_eq!(
+            worldgen::WorldGenConfig::parse(&endless.to_string()),
+            Ok(endless)
+        );
+        assert!(worldgen::WorldGenConfig::parse("infinite 2").is_err());
+    }
+
+    // -- save header ---------------------------------------------------------
+
+    #[test]
+    fn save_header_is_split_from_body() {
+        let state = GameState::create_test_world("w".into());
+        let mut bytes = persist::encode(&state).expect("encodes");
+        assert!(bytes.starts_with(&persist::SAVE_MAGIC));
+
+        let (decoded, body) = persist::split_header(&bytes).expect("valid header");
+        assert_eq!(
+            decoded,
+            SaveHeader {
+                world_id: state.world_id,
+                world_name: "w".into(),
+                format: persist::SAVE_FORMAT,
+            }
+        );
+        assert_eq!(body, bitcode::encode(&state));
+        assert_eq!(
+            persist::migrate(decoded.format, &body).expect("decodes"),
+            state
+        );
+        bytes.truncate(10);
+        assert!(matches!(
+            persist::split_header(&bytes),
+            Err(SaveError::TruncatedHeader)
+        ));
+    }
+
+    #[test]
+    fn old_saves_upgrade_to_the_newest_format() {
+        let dir = std::env::temp_dir().join(format!("gamik-upgrade-{}", WorldId::generate()));
+        std::fs::create_dir_all(&dir).expect("temp dir is writable");
+        let path = dir.join("old.world");
+
+        std::fs::write(&path, include_bytes!("fixtures/format0.world"))
+            .expect("temp dir is writable");
+        let old = load_from_file(&path).expect("format 0 save");
+        let upgrade = persist::upgrade(&path, &path).expect("upgrades");
+        assert_eq!(upgrade.from_format, 0);
+        assert_eq!(upgrade.repaired, []);
+        assert_eq!(
+            read_save_header(&path).expect("new header").format,
+            persist::SAVE_FORMAT
+        );
+        assert_eq!(load_from_file(&path).expect("upgraded"), old);
+
+        // A save that decodes but is broken is repaired on the way.
+        let mut state = GameState::create_test_world("old".into());
+        let highest = state.entity_gen.0;
+        state.entity_gen.0 = 0;
+        save_to_path(&state, &path).expect("saves");
+        let upgrade = persist::upgrade(&path, &path).expect("upgrades");
+        assert_eq!(upgrade.repaired.len(), 1);
+        assert_eq!(
+            load_from_file(&path).expect("upgraded").entity_gen.0,
+            highest
+        );
+
+        // Saves from a newer build are refused rather than misread.
+        let newer = bitcode::encode(&SaveHeader {
+            world_id: state.world_id,
+            world_name: state.world_name.clone(),
+            format: persist::SAVE_FORMAT + 1,
+        });
+        let mut bytes = (newer.len() as u32).to_le_bytes().to_vec();
+        bytes.extend_from_slice(&newer);
+        bytes.extend_from_slice(&bitcode::encode(&state));
+        std::fs::write(&path, bytes).expect("temp dir is writable");
+        assert!(matches!(
+            load_from_file(&path),
+            Err(SaveError::TooNew(format)) if format == persist::SAVE_FORMAT + 1
+        ));
+        let mut newer = persist::encode(&state).expect("encodes");
+        if let Some(format) = newer.get_mut(4..8) {
+            format.copy_from_slice(&(persist::SAVE_FORMAT + 1).to_le_bytes());
+        }
+        std::fs::write(&path, newer).expect("temp dir is writable");
+        assert!(matches!(read_save_header(&path), Err(SaveError::TooNew(_))));
+        assert!(matches!(
+            persist::migrate(persist::SAVE_FORMAT + 1, &[]),
+            Err(SaveError::TooNew(_))
+        ));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn saves_replace_the_file_whole_and_keep_rolling_backups() {
+        let dir = std::env::temp_dir().join(format!("gamik-backups-{}", WorldId::generate()));
+        std::fs::create_dir_all(&dir).expect("temp dir is writable");
+        let path = dir.join("w.world");
+        let mut state = GameState::create_test_world("w".into());
+        let saved_tick = |path: &std::path::Path| load_from_file(path).expect("loads").tick;
+
+        persist::set_backups(2);
+        for tick in 0..4 {
+            state.tick = tick;
+            save_to_path(&state, &path).expect("saves");
+        }
+        assert_eq!(saved_tick(&path), 3);
+        assert_eq!(saved_tick(&persist::backup_path(&path, 1)), 2);
+        assert_eq!(saved_tick(&persist::backup_path(&path, 2)), 1);
+        assert!(!persist::backup_path(&path, 3).exists());
+
+        // Keeping fewer drops the older backups on the next save.
+        persist::set_backups(1);
+        state.tick = 4;
+        save_to_path(&state, &path).expect("saves");
+        assert_eq!(saved_tick(&persist::backup_path(&path, 1)), 3);
+        assert!(!persist::backup_path(&path, 2).exists());
+        persist::set_backups(persist::DEFAULT_SAVE_BACKUPS);
+
+        let mut names: Vec<String> = std::fs::read_dir(&dir)
+            .expect("temp dir is readable")
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["w.world", "w.world.bak1"]);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn saves_and_loads_go_through_the_installed_sync() {
+        use std::path::{Path, PathBuf};
+        use std::sync::{Arc, Mutex};
+
+        struct Recorder(Arc<Mutex<Vec<(&'static str, PathBuf)>>>);
+
+        impl SaveSync for Recorder {
+            fn after_save(&self, path: &Path, _header: &SaveHeader) -> std::io::Result<()> {
+                self.0
+                    .lock()
+                    .expect("not poisoned")
+                    .push(("saved", path.to_owned()));
+                Ok(())
+            }
+
+            fn before_load(&self, path: &Path) -> std::io::Result<()> {
+                self.0
+                    .lock()
+                    .expect("not poisoned")
+                    .push(("loading", path.to_owned()));
+                Ok(())
+            }
+        }
+
+        let calls = Arc::default();
+        assert!(persist::install_sync(Box::new(Recorder(Arc::clone(&calls)))).is_ok());
+        assert!(persist::install_sync(Box::new(persist::LocalOnly)).is_err());
+
+        let dir = std::env::temp_dir().join(format!("gamik-sync-{}", WorldId::generate()));
+        std::fs::create_dir_all(&dir).expect("temp dir is writable");
+        let path = dir.join("synced.world");
+        let state = GameState::create_test_world("synced".into());
+        save_to_path(&state, &path).expect("saves");
+        assert_eq!(load_from_file(&path).expect("loads"), state);
+
+        // Other tests save and load too; only this file's calls count.
+        let ours: Vec<&str> = calls
+            .lock()
+            .expect("not poisoned")
+            .iter()
+            .filter(|(_, p)| *p == path)
+            .map(|(what, _)| *what)
+            .collect();
+        assert_eq!(ours, vec!["saved", "loading"]);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn save_slots_are_kept_apart_from_the_world_and_each_other() {
+        let root = std::env::temp_dir().join(format!("gamik-slots-{}", WorldId::generate()));
+        let mut state = GameState::create_test_world("slotted".into());
+
+        assert!(slots::save_in(&root, &state, "  ").is_err());
+        assert!(slots::save_in(&root, &state, "../escape").is_err());
+        let before = slots::save_in(&root, &state, " before the bridge ").expect("saves");
+        assert_eq!(before.name, "before the bridge");
+        assert_eq!(before.world_name, "slotted");
+
+        state.tick += 100;
+        slots::save_in(&root, &state, "after").expect("saves");
+        let listed = slots::list_in(&root);
+        assert_eq!(listed.len(), 2);
+        assert_eq!(load_from_file(&before.path).expect("loads").tick, 0);
+
+        // Saving to a name again replaces that slot only.
+        slots::save_in(&root, &state, "before the bridge").expect("saves");
+        assert_eq!(slots::list_in(&root).len(), 2);
+        assert_eq!(load_from_file(&before.path).expect("loads").tick, 100);
+
+        slots::delete(&before).expect("deletes");
+        let left: Vec<String> = slots::list_in(&root).into_iter().map(|s| s.name).collect();
+        assert_eq!(left, vec!["after".to_owned()]);
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    // -- validation & repair -------------------------------------------------
+
+    #[test]
+    fn fresh_worlds_validate_clean() {
+        let mut state = GameState::create_test_world("w".into());
+        spawn_player(&mut state, "Alice".into());
+        assert!(persist::validate(&state).is_empty());
+        let generated = worldgen::generate("w".into(), 3, &worldgen::WorldGenConfig::default());
+        assert!(persist::validate(&generated).is_empty());
+    }
+
+    #[test]
+    fn repair_fixes_corrupt_worlds() {
+        let mut state = GameState::create_test_world("w".into());
+        let alice = spawn_player(&mut state, "Alice".into());
+        let tree = |x, y| Entity::new(EntityType::Tree, Point { x, y }, None);
+        state.insert_entity(EntityID(40), tree(5, 5));
+        state.insert_entity(EntityID(41), tree(i32::MAX, 0));
+        if let Some(player) = state.entity_mut(alice) {
+            player.name = None;
+        }
+
+        let issues = persist::validate(&state);
+        assert_eq!(
+            issues,
+            vec![
+                persist::Issue::StackedBlockers {
+                    position: Point { x: 5, y: 5 },
+                    entities: vec![EntityID(1), EntityID(40)],
+                },
+                persist::Issue::UnnamedPlayer(alice),
+                persist::Issue::OutOfBounds {
+                    entity: EntityID(41),
+                    position: Point { x: i32::MAX, y: 0 },
+                },
+                persist::Issue::StaleEntityGenerator {
+                    next: alice.0,
+                    highest: 41,
+                },
+            ]
+        );
+
+        assert_eq!(persist::repair(&mut state), issues);
+        assert!(persist::validate(&state).is_empty());
+        assert!(!state.entities.contains_key(&EntityID(40)));
+        assert_eq!(
+            state.entities.get(&EntityID(41)).expect("entity").position,
+            SPAWN_POINT
+        );
+        assert_eq!(spawn_player(&mut state, "Bob".into()), EntityID(42));
+    }
+
+    // -- math ----------------------------------------------------------------
+
+    #[test]
+    fn grid_math_is_exact_at_the_edges() {
+        let (a, b) = (Point { x: -3, y: 2 }, Point { x: 4, y: -1 });
+        assert_eq!(math::manhattan(a, b), 10);
+        assert_eq!(math::chebyshev(a, b), 7);
+        assert_eq!(math::distance_squared(a, b), 58);
+        assert!(math::within_square(a, b, 7) && !math::within_square(a, b, 6));
+        assert!(!math::within_square(a, a, -1));
+
+        let (min, max) = (
+            Point {
+                x: i32::MIN,
+                y: i32::MIN,
+            },
+            Point {
+                x: i32::MAX,
+                y: i32::MAX,
+            },
+        );
+        assert_eq!(math::manhattan(min, max), u32::MAX);
+        assert!(!math::within_circle(min, max, i32::MAX));
+        assert!(math::within_circle(
+            min,
+            Point { x: i32::MIN, y: -1 },
+            i32::MAX
+        ));
+
+        assert_eq!(math::scale(40, 3, 4), 30);
+        assert_eq!(math::scale(7, 1, 0), 0);
+        assert_eq!(math::scale(u32::MAX, u32::MAX, 1), u32::MAX);
+    }
+
+    #[test]
+    fn lines_step_one_tile_at_a_time_between_their_ends() {
+        let from = Point { x: 2, y: -1 };
+        for (x, y) in [(9, 2), (-4, 7), (2, -6), (0, 0), (2, -1), (-5, -5)] {
+            let to = Point { x, y };
+            let line: Vec<Point> = math::line(from, to).collect();
+            assert_eq!(line.first(), Some(&from));
+            assert_eq!(line.last(), Some(&to));
+            assert_eq!(
+                line.len() as u32,
+                math::chebyshev(from, to) + 1,
+                "to {to:?}"
+            );
+            assert!(
+                line.windows(2).all(|pair| pair
+                    .first()
+                    .zip(pair.last())
+                    .is_some_and(|(a, b)| { math::chebyshev(*a, *b) == 1 })),
+                "to {to:?}"
+            );
+        }
+        let diagonal: Vec<Point> = math::line(Point { x: 0, y: 0 }, Point { x: 5, y: 2 }).collect();
+        assert_eq!(
+            diagonal,
+            [(0, 0), (1, 0), (2, 1), (3, 1), (4, 2), (5, 2)].map(|(x, y)| Point { x, y })
+        );
+    }
+
+    #[test]
+    fn rolls_are_reproducible_from_the_state_and_their_seed() {
+        let mut state = GameState::create_test_world("w".into());
+        let alice = spawn_player(&mut state, "Alice".into());
+        let first = rng::roll(&state, alice, "loot/0");
+        assert_eq!(rng::roll(&state, alice, "loot/0"), first);
+        assert_eq!(rng::Roll::from_seed(first.seed), first);
+        assert_ne!(rng::roll(&state, alice, "loot/1"), first);
+        assert_ne!(rng::roll(&state, EntityID(alice.0 + 1), "loot/0"), first);
+        let mut rolled = rng::rolled(&state, alice, "loot/0", 0);
+        assert_eq!(rolled.roll, first);
+        assert!(rolled.is_fair());
+        assert_ne!(rng::rolled(&state, alice, "loot/0", 7).roll, first);
+        rolled.salt = 7;
+        assert!(!rolled.is_fair(), "the salt is part of the seed");
+        state.tick += 1;
+        assert_ne!(rng::roll(&state, alice, "loot/0"), first);
+
+        let rolls: Vec<u64> = (0..1000)
+            .map(|i| rng::roll(&state, alice, &format!("test/{i}")).below(10))
+            .collect();
+        assert!(rolls.iter().all(|r| *r < 10));
+        assert!((0..10).all(|n| rolls.iter().filter(|r| **r == n).count() > 50));
+        assert_eq!(first.below(0), 0);
+        assert!(!first.chance(0) && first.chance(100));
+    }
+
+    // -- fov -----------------------------------------------------------------
+
+    fn fov_with_walls(origin: Point, radius: i32, walls: &[(i32, i32)]) -> FxHashSet<Point> {
+        let walls: FxHashSet<Point> = walls.iter().map(|&(x, y)| Point { x, y }).collect();
+        fov::compute_fov(origin, radius, |p| walls.contains(&p))
+    }
+
+    #[test]
+    fn fov_open_field_sees_exactly_the_radius() {
+        let origin = Point { x: 3, y: -2 };
+        let visible = fov_with_walls(origin, 5, &[]);
+        for dx in -6..=6 {
+            for dy in -6..=6 {
+                let p = Point {
+                    x: origin.x + dx,
+                    y: origin.y + dy,
+                };
+                assert_eq!(visible.contains(&p), dx * dx + dy * dy <= 25, "{dx},{dy}");
+            }
+        }
+    }
+
+    #[test]
+    fn fov_pillar_casts_shadow() {
+        let origin = Point { x: 0, y: 0 };
+        let visible = fov_with_walls(origin, 8, &[(2, 0)]);
+        assert!(visible.contains(&Point { x: 2, y: 0 }));
+        assert!(!visible.contains(&Point { x: 3, y: 0 }));
+        assert!(!visible.contains(&Point { x: 6, y: 0 }));
+        assert!(visible.contains(&Point { x: 4, y: 3 }));
+    }
+
+    #[test]
+    fn fov_diagonal_seams_mirror_exactly() {
+        // Walls mirrored across y = x must give a mirrored view; float slopes
+        // used to disagree where the quadrants meet on the diagonal.
+        let origin = Point { x: 0, y: 0 };
+        let visible = fov_with_walls(origin, 9, &[(3, 1), (1, 3), (5, 4), (4, 5)]);
+        assert!(
+            visible
+                .iter()
+                .all(|p| visible.contains(&Point { x: p.y, y: p.x })),
+            "view is not mirrored across the diagonal"
+        );
+    }
+
+    #[test]
+    fn fov_is_symmetric_around_corners() {
+        let size = 16;
+        let is_wall = |p: Point| {
+            !(0..size).contains(&p.x)
+                || !(0..size).contains(&p.y)
+                || fnv1a(FNV_OFFSET, &[p.x as u8, p.y as u8]).is_multiple_of(4)
+        };
+        let floors: Vec<Point> = (0..size)
+            .flat_map(|x| (0..size).map(move |y| Point { x, y }))
+            .filter(|p| !is_wall(*p))
+            .collect();
+        let views: FxHashMap<Point, FxHashSet<Point>> = floors
+            .iter()
+            .map(|p| (*p, fov::compute_fov(*p, 10, is_wall)))
+            .collect();
+
+        for a in &floors {
+            for b in &floors {
+                assert_eq!(
+                    views.get(a).is_some_and(|view| view.contains(b)),
+                    views.get(b).is_some_and(|view| view.contains(a)),
+                    "{a:?} and {b:?} disagree"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn fov_mask_matches_hash_set_result() {
+        let walls: FxHashSet<Point> = [(2, 0), (-1, 3), (4, 4), (0, -2)]
+            .into_iter()
+            .map(|(x, y)| Point { x, y })
+            .collect();
+        let origin = Point { x: 0, y: 0 };
+        let set = fov::compute_fov(origin, 7, |p| walls.contains(&p));
+        let mask = fov::FovMask::compute(origin, 7, |p| walls.contains(&p));
+
+        assert_eq!(mask.len(), set.len());
+        assert_eq!(mask.iter().collect::<FxHashSet<Point>>(), set);
+        assert!(!mask.contains(Point { x: 100, y: 0 }));
+    }
+
+    #[test]
+    fn fov_mask_ignores_points_outside_its_window() {
+        let mut mask = fov::FovMask::new(Point { x: 10, y: 10 }, 1);
+        mask.set(Point { x: 11, y: 9 });
+        mask.set(Point { x: 13, y: 10 });
+        assert_eq!(mask.iter().collect::<Vec<_>>(), vec![Point { x: 11, y: 9 }]);
+    }
+
+    #[test]
+    fn awareness_includes_visible_and_nearby_entities() {
+        let mut state = GameState::create_test_world("w".into());
+        let alice = spawn_player(&mut state, "Alice".into());
+        let bob = spawn_player(&mut state, "Bob".into());
+        // Hide Bob behind the tree at (15, 5), out of reach of the margin.
+        state.place_entity(bob, Point { x: 16, y: 4 });
+
+        let mut view = fov::PlayerFov::new(alice);
+        state.place_entity(alice, Point { x: 13, y: 7 });
+        view.update(
+            &state.entities,
+            &fov::OpaqueSet::of(&state.entities),
+            &state.terrain,
+        );
+
+        let aware = fov::build_awareness(&state.entities, &view.mask, fov::AWARENESS_MARGIN);
+        assert!(aware.contains(&alice));
+        assert!(!aware.contains(&bob));
+
+        state.place_entity(bob, Point { x: 14, y: 6 });
+        let aware = fov::build_awareness(&state.entities, &view.mask, fov::AWARENESS_MARGIN);
+        assert!(aware.contains(&bob));
+    }
+
+    #[test]
+    fn incremental_awareness_matches_a_full_rebuild() {
+        let mut state = GameState::create_test_world("w".into());
+        let alice = spawn_player(&mut state, "Alice".into());
+        let bob = spawn_player(&mut state, "Bob".into());
+        let opaque = fov::OpaqueSet::of(&state.entities);
+        let mut view = fov::PlayerFov::new(alice);
+        let mut tiles = SpatialIndex::default();
+        let mut awareness = fov::Awareness::default();
+        let mut before: Vec<EntityID> = Vec::new();
+        let mut saw_bob = false;
+
+        let place = |state: &mut GameState, tiles: &mut SpatialIndex, eid, x, y| {
+            state.place_entity(eid, Point { x, y });
+            tiles.note([eid]);
+        };
+        for step in 0..50 {
+            // Bob walks past the trees across Alice's view; Alice moves
+            // once halfway, and Bob leaves the world at the end.
+            place(&mut state, &mut tiles, bob, step - 15, 6);
+            if step == 25 {
+                place(&mut state, &mut tiles, alice, 10, 12);
+            }
+            if step == 49 {
+                state.remove_entity(bob);
+            }
+            let view_changed = view.update(&state.entities, &opaque, &state.terrain);
+            let moved = tiles.sync(&state.entities);
+            let diff = awareness.update(
+                &tiles,
+                moved.as_deref(),
+                &view.mask,
+                view_changed,
+                fov::AWARENESS_MARGIN,
+            );
+
+            let expected = fov::build_awareness(&state.entities, &view.mask, fov::AWARENESS_MARGIN);
+            assert_eq!(
+                awareness.iter().collect::<Vec<_>>(),
+                expected,
+                "step {step}"
+            );
+            let entered: Vec<EntityID> = expected
+                .iter()
+                .filter(|e| !before.contains(e))
+                .copied()
+                .collect();
+            let left: Vec<EntityID> = before
+                .iter()
+                .filter(|e| !expected.contains(e))
+                .copied()
+                .collect();
+            assert_eq!((diff.entered, diff.left), (entered, left), "step {step}");
+            saw_bob |= awareness.contains(bob);
+            before = expected;
+        }
+        assert!(saw_bob);
+        assert!(awareness.contains(alice) && !awareness.contains(bob));
+    }
+
+    #[test]
+    fn explored_map_remembers_what_was_seen() {
+        let mut state = GameState::create_test_world("w".into());
+        let alice = spawn_player(&mut state, "Alice".into());
+        let opaque = fov::OpaqueSet::of(&state.entities);
+        let mut view = fov::PlayerFov::new(alice);
+        let mut explored = fov::ExploredMap::default();
+        view.update(&state.entities, &opaque, &state.terrain);
+        explored.update(&view.mask, &state.entities);
+
+        let tree = Point { x: 5, y: 5 };
+        assert_eq!(explored.get(tree), Some(Some(&EntityType::Tree)));
+        assert_eq!(explored.get(Point { x: 10, y: 9 }), Some(None));
+        assert_eq!(explored.get(Point { x: 40, y: 40 }), None);
+
+        // Walking away keeps the memory.
+        state.place_entity(alice, Point { x: 60, y: 60 });
+        view.update(&state.entities, &opaque, &state.terrain);
+        explored.update(&view.mask, &state.entities);
+        assert_eq!(explored.get(tree), Some(Some(&EntityType::Tree)));
+        let (min, max) = explored.bounds().expect("explored");
+        assert!(min.x <= 5 && max.x >= 60);
+    }
+
+    #[test]
+    fn frontier_is_the_nearest_open_edge_of_the_explored_within_reach() {
+        let mut terrain = TileMap::default();
+        let mut explored = fov::ExploredMap::default();
+        explored.reveal(
+            Point { x: 0, y: 0 },
+            Point { x: 10, y: 10 },
+            &EntityMap::default(),
+        );
+        // A pocket at x = 1, walled in by rock.
+        for i in 0..=10 {
+            for wall in [
+                Point { x: 0, y: i },
+                Point { x: 2, y: i },
+                Point { x: i, y: 0 },
+                Point { x: i, y: 10 },
+            ] {
+                terrain.set(wall, Terrain::Rock);
+            }
+        }
+        let from = Point { x: 1, y: 5 };
+        assert!(explored.is_open(&terrain, from));
+        assert!(!explored.is_open(&terrain, Point { x: 2, y: 5 }));
+        assert!(!explored.is_open(&terrain, Point { x: 11, y: 5 }));
+        assert_eq!(explored.nearest_frontier(&terrain, from), None);
+
+        // A gap in the wall leads out to the unexplored past x = 10.
+        terrain.set(Point { x: 2, y: 5 }, Terrain::Grass);
+        assert_eq!(
+            explored.nearest_frontier(&terrain, from),
+            Some(Point { x: 10, y: 5 })
+        );
+    }
+
+    #[test]
+    fn trees_and_rock_block_sight() {
+        let mut state = GameState::create_test_world("w".into());
+        spawn_player(&mut state, "Alice".into());
+        let opaque = fov::OpaqueSet::of(&state.entities);
+        assert!(opaque.contains(Point { x: 5, y: 5 }));
+        assert!(fov::blocks_sight(&state, Point { x: 5, y: 5 }));
+        assert!(!fov::blocks_sight(&state, SPAWN_POINT));
+        assert!(!fov::blocks_sight(&state, Point { x: 10, y: 10 }));
+
+        state.terrain.set(Point { x: 10, y: 10 }, Terrain::Rock);
+        assert!(fov::blocks_sight(&state, Point { x: 10, y: 10 }));
+        assert!(!opaque.contains(Point { x: 10, y: 10 }));
+    }
+
+    #[test]
+    fn spatial_index_follows_noted_moves() {
+        let mut state = GameState::create_test_world("w".into());
+        let mut tiles = SpatialIndex::of(&state.entities);
+        let mut opaque = fov::OpaqueSet::of(&state.entities);
+        let (from, to) = (Point { x: 5, y: 5 }, Point { x: 7, y: 6 });
+        let tree = *tiles.entities_at(from).first().expect("a tree");
+
+        state.place_entity(tree, to);
+        tiles.note([tree]);
+        assert_eq!(tiles.sync(&state.entities), Some(vec![tree]));
+        assert!(tiles.entities_at(from).is_empty());
+        assert_eq!(tiles.entities_at(to), [tree]);
+        assert_eq!(tiles.changed_tiles(), Some(&[from, to][..]));
+        let in_rect: Vec<EntityID> = tiles
+            .entities_in_rect(Point { x: 6, y: 4 }, Point { x: 8, y: 8 })
+            .collect();
+        assert_eq!(in_rect, [tree]);
+
+        // Sight blockers follow just the tiles that changed.
+        assert!(opaque.update(&state.entities, &tiles));
+        assert_eq!(opaque.changed(), [from, to]);
+        assert!(opaque.contains(to) && !opaque.contains(from));
+        assert!(
+            state
+                .entities
+                .values()
+                .filter(|e| e.entity_type.blocks_sight())
+                .all(|e| opaque.contains(e.position))
+        );
+    }
+
+    /// Asserts that the state's index holds every entity where it is.
+    fn assert_indexed(state: &GameState) {
+        for (eid, entity) in &state.entities {
+            assert_eq!(state.index.position(*eid), Some(entity.position), "{eid:?}");
+            assert!(state.index.entities_at(entity.position).contains(eid));
+        }
+    }
+
+    #[test]
+    fn the_state_index_keeps_up_with_the_simulation() {
+        let mut state = GameState::create_test_world("w".into());
+        let alice = spawn_player(&mut state, "Alice".into());
+        assert_indexed(&state);
+
+        apply(&mut state, alice, &GameAction::Move(Direction::Right));
+        let at = state.entities.get(&alice).expect("alice").position;
+        assert_ne!(at, SPAWN_POINT);
+        assert!(state.index.entities_at(SPAWN_POINT).is_empty());
+        assert_indexed(&state);
+
+        collision::push(&mut state, alice, Direction::Down, 2);
+        assert_indexed(&state);
+
+        let tree = *state
+            .index
+            .entities_at(Point { x: 10, y: 5 })
+            .first()
+            .expect("a tree");
+        while debris::destroy(&mut state, tree).is_some() {}
+        assert_eq!(state.index.position(tree), None);
+        assert!(!collision::is_blocked(&state, Point { x: 10, y: 5 }));
+
+        let pet = state.entity_gen.next();
+        let mut entity = Entity::new(
+            EntityType::Npc,
+            Point {
+                x: at.x + 6,
+                y: at.y,
+            },
+            None,
+        );
+        entity.owner = Some(alice);
+        entity.follow = Some(Follow::new(3));
+        state.insert_entity(pet, entity);
+        let registry = ContentRegistry::builtin();
+        for _ in 0..4 {
+            advance(&mut state, &registry);
+        }
+        assert_ne!(
+            state.entities.get(&pet).expect("pet").position,
+            Point {
+                x: at.x + 6,
+                y: at.y
+            }
+        );
+        assert_indexed(&state);
+    }
+
+    #[test]
+    fn loaded_worlds_rebuild_their_index_and_edits_reach_it_by_the_next_sync() {
+        let mut state = GameState::create_test_world("w".into());
+        spawn_player(&mut state, "Alice".into());
+        let tree_at = Point { x: 5, y: 5 };
+
+        // Saves leave the index out: until synced, a loaded world's index
+        // is empty, and the world looks clear.
+        let mut loaded: GameState = bitcode::decode(&bitcode::encode(&state)).expect("decodes");
+        assert_eq!(loaded, state);
+        assert!(!loaded.index.is_built());
+        assert!(!collision::is_blocked(&loaded, tree_at));
+        loaded.sync_index();
+        assert!(collision::is_blocked(&loaded, tree_at));
+        assert_indexed(&loaded);
+
+        // A move through entity_mut is noted, and goes unseen until the
+        // next sync.
+        let tree = *loaded.index().entities_at(tree_at).first().expect("a tree");
+        let to = Point { x: 7, y: 6 };
+        loaded.entity_mut(tree).expect("tree").position = to;
+        assert!(collision::is_blocked(&loaded, tree_at));
+        assert!(!collision::is_blocked(&loaded, to));
+        loaded.sync_index();
+        assert!(!collision::is_blocked(&loaded, tree_at));
+        assert!(collision::is_blocked(&loaded, to));
+        assert!(fov::blocks_sight(&loaded, to));
+        assert_indexed(&loaded);
+    }
+
+    // -- entity map ----------------------------------------------------------
+
+    #[test]
+    fn entity_map_order_does_not_depend_on_insertion_order() {
+        let state = GameState::create_test_world("w".into());
+        let mut reversed = EntityMap::default();
+        for (eid, entity) in state.entities.iter().rev() {
+            reversed.insert(*eid, entity.clone());
+        }
+
+        let ids: Vec<EntityID> = reversed.keys().copied().collect();
+        assert!(ids.windows(2).all(|w| matches!(w, [a, b] if a < b)));
+        assert_eq!(bitcode::encode(&reversed), bitcode::encode(&state.entities));
+    }
+
+    // -- replay fixtures -----------------------------------------------------
+
+    #[test]
+    fn checksum_ignores_world_identity_but_not_entities() {
+        let a = GameState::create_test_world("a".into());
+        let mut b = GameState::create_test_world("b".into());
+        assert_eq!(a.checksum(), b.checksum());
+
+        let alice = spawn_player(&mut b, "Alice".into());
+        assert_ne!(a.checksum(), b.checksum());
+
+        let changes: [fn(&mut GameState); 3] = [
+            |state| state.terrain.set(Point { x: 3, y: 3 }, Terrain::Rock),
+            |state| state.turns = Some(Scheduler::default()),
+            |state| {
+                for (_, entity) in state.entities_mut() {
+                    entity.combat.defense += 1;
+                }
+            },
+        ];
+        for change in changes {
+            let mut changed = b.clone();
+            change(&mut changed);
+            assert_ne!(changed.checksum(), b.checksum());
+        }
+        let mut turns = b.clone();
+        turns.turns = Some(Scheduler::default());
+        let before = turns.checksum();
+        apply(&mut turns, alice, &GameAction::Move(Direction::Left));
+        assert_ne!(turns.checksum(), before, "the mover's energy was spent");
+    }
+
+    // -- tags & metadata -----------------------------------------------------
+
+    #[test]
+    fn tags_are_sorted_deduplicated_and_capped() {
+        let mut tags = Tags::default();
+        assert_eq!(tags.insert("boss"), Ok(true));
+        assert_eq!(tags.insert("boss"), Ok(false));
+        assert_eq!(tags.insert("act-1.quest_target"), Ok(true));
+        assert_eq!(
+            tags.iter().collect::<Vec<_>>(),
+            ["act-1.quest_target", "boss"]
+        );
+        assert!(matches!(
+            tags.insert("no spaces"),
+            Err(tags::TagError::InvalidName(_))
+        ));
+        assert_eq!(
+            tags.insert(&"x".repeat(tags::MAX_TAG_LEN + 1)),
+            Err(tags::TagError::TooLong)
+        );
+        for n in tags.len()..tags::MAX_TAGS {
+            assert_eq!(tags.insert(&format!("t{n}")), Ok(true));
+        }
+        assert_eq!(tags.insert("one_more"), Err(tags::TagError::Full));
+        assert!(tags.remove("boss"));
+        assert!(!tags.contains("boss"));
+    }
+
+    #[test]
+    fn metadata_is_capped_and_part_of_the_checksum() {
+        let mut state = GameState::create_test_world("w".into());
+        let before = state.checksum();
+        let entity = state.entity_mut(EntityID(1)).expect("tree");
+        assert_eq!(entity.metadata.set("loot", "gold"), Ok(None));
+        assert_eq!(
+            entity.metadata.set("loot", "silver"),
+            Ok(Some("gold".into()))
+        );
+        assert_eq!(
+            entity
+                .metadata
+                .set("loot", &"x".repeat(tags::MAX_VALUE_LEN + 1)),
+            Err(tags::TagError::TooLong)
+        );
+        entity.tags.insert("boss").expect("valid tag");
+        assert_ne!(state.checksum(), before);
+        assert_eq!(state.tagged("boss").collect::<Vec<_>>(), [EntityID(1)]);
+
+        let entity = state.entity_mut(EntityID(1)).expect("tree");
+        assert_eq!(entity.metadata.remove("loot").as_deref(), Some("silver"));
+        entity.tags.remove("boss");
+        assert_eq!(state.checksum(), before);
+    }
+
+    // -- factions & reputation -----------------------------------------------
+
+    #[test]
+    fn builtin_content_parses_and_rejects_bad_references() {
+        let registry = ContentRegistry::builtin();
+        assert!(registry.faction("bandits").is_some());
+        assert_eq!(registry.standing("bandits", "villagers"), -80);
+        assert_eq!(registry.standing("villagers", "villagers"), 100);
+        assert_eq!(registry.standing("villagers", "nobody"), 0);
+
+        let err = ContentRegistry::from_sources(content::Sources {
+            factions: r#"(factions: [(id: "a", name: "A", standings: {"b": 10})])"#,
+            ..content::Sources::BUILTIN
+        })
+        .expect_err("an unknown faction");
+        assert!(err.contains("unknown `b`"), "{err}");
+        let err = ContentRegistry::from_sources(content::Sources {
+            shops: r#"(shops: [(id: "s", name: "S", restock_ticks: 0, stock: [])])"#,
+            ..content::Sources::BUILTIN
+        })
+        .expect_err("a shop that never restocks");
+        assert!(err.contains("never restocks"), "{err}");
+    }
+
+    #[test]
+    fn every_entity_type_has_an_appearance() {
+        use render::RenderLayer;
+
+        let registry = ContentRegistry::builtin();
+        for entity_type in EntityType::ALL {
+            assert!(
+                registry.appearance(&entity_type).is_some(),
+                "{entity_type:?}"
+            );
+        }
+        assert_eq!(registry.layer(&EntityType::Water), RenderLayer::Terrain);
+        let rubble = registry.appearance(&EntityType::Rubble).expect("rubble");
+        assert!(rubble.style.small);
+        assert_eq!(rubble.style.remembered, 35);
+        let tree = registry.appearance(&EntityType::Tree).expect("tree");
+        assert!(tree.animation.as_ref().is_some_and(|a| a.frames.len() == 4));
+        // Bridges over water, creatures over bridges; ties go to the newer.
+        let key = |id, t| render::stack_key(&registry, EntityID(id), &t);
+        assert!(key(1, EntityType::Bridge) > key(2, EntityType::Water));
+        assert!(key(1, EntityType::Player) > key(2, EntityType::Bridge));
+        assert!(key(2, EntityType::Player) > key(1, EntityType::Npc));
+
+        let err = ContentRegistry::from_sources(content::Sources {
+            appearance: r#"(appearances: [(entity_type: "tree", layer: Terrain, glyph: "T", fg: (0, 0, 0))])"#,
+            ..content::Sources::BUILTIN
+        })
+        .expect_err("a missing appearance");
+        assert!(err.contains("`player` has no appearance"), "{err}");
+        let err = ContentRegistry::from_sources(content::Sources {
+            appearance: r#"(appearances: [(entity_type: "tree", layer: Terrain, glyph: "", fg: (0, 0, 0))])"#,
+            ..content::Sources::BUILTIN
+        })
+        .expect_err("an empty glyph");
+        assert!(err.contains("empty glyph"), "{err}");
+    }
+
+    #[test]
+    fn attacks_shift_reputation_through_standings() {
+        let registry = ContentRegistry::builtin();
+        let mut reputation = Reputation::default();
+        for _ in 0..3 {
+            reputation.record_attack(&registry, "villagers");
+        }
+        assert_eq!(reputation.get("villagers"), -30);
+        assert_eq!(reputation.get("rangers"), -12);
+        assert_eq!(reputation.get("bandits"), 24);
+        assert!(reputation.is_hostile("villagers"));
+        assert!(!reputation.is_hostile("rangers"));
+        assert_eq!(
+            faction::Tier::of(reputation.get("bandits")),
+            faction::Tier::Neutral
+        );
+
+        reputation.set("bandits", 500);
+        assert_eq!(reputation.get("bandits"), faction::MAX_REPUTATION);
+        reputation.set("bandits", 0);
+        assert_eq!(reputation.len(), 2);
+    }
+
+    // -- dialogue ------------------------------------------------------------
+
+    /// A world with a talking tree next to the spawn point and a player.
+    fn oak_and_player() -> (GameState, EntityID, EntityID) {
+        let mut state = empty_state();
+        let oak = state.entity_gen.next();
+        let mut entity = Entity::new(EntityType::Tree, Point { x: 11, y: 10 }, None);
+        entity.dialogue = Some("old_oak".into());
+        state.insert_entity(oak, entity);
+        let pid = spawn_player(&mut state, "Alice".into());
+        (state, oak, pid)
+    }
+
+    #[test]
+    fn dialogue_walks_the_tree_and_applies_effects() {
+        let (mut state, oak, pid) = oak_and_player();
+        let registry = ContentRegistry::builtin();
+        assert!(apply(&mut state, pid, &GameAction::Choose(0)).is_empty());
+
+        apply(&mut state, pid, &GameAction::Talk(oak));
+        let view = dialogue::view(&state, &registry, pid).expect("talking");
+        assert_eq!(view.speaker, "Old Oak");
+        assert_eq!(view.options.len(), 2);
+        assert!(apply(&mut state, pid, &GameAction::Choose(7)).is_empty());
+
+        apply(&mut state, pid, &GameAction::Choose(0));
+        apply(&mut state, pid, &GameAction::Choose(0));
+        let player = state.entities.get(&pid).expect("pid");
+        assert!(player.conversation.is_none());
+        assert_eq!(player.reputation.get("rangers"), 5);
+        assert!(player.tags.contains("quest.oak_promise"));
+    }
+
+    #[test]
+    fn dialogue_needs_range_and_ends_on_move() {
+        let (mut state, oak, pid) = oak_and_player();
+        assert!(apply(&mut state, pid, &GameAction::Talk(pid)).is_empty());
+        assert_eq!(
+            apply(&mut state, pid, &GameAction::Talk(oak)),
+            [GameEvent::DialogueChanged { entity_id: pid }]
+        );
+        apply(&mut state, pid, &GameAction::Move(Direction::Left));
+        assert!(
+            state
+                .entities
+                .get(&pid)
+                .expect("pid")
+                .conversation
+                .is_none()
+        );
+        apply(&mut state, pid, &GameAction::Move(Direction::Left));
+        assert!(apply(&mut state, pid, &GameAction::Talk(oak)).is_empty());
+    }
+
+    // -- shops ---------------------------------------------------------------
+
+    #[test]
+    fn trades_are_checked_against_purse_and_stock() {
+        let (mut state, oak, pid) = oak_and_player();
+        let registry = ContentRegistry::builtin();
+        let store = registry.shop("general_store").expect("builtin shop");
+        let buy = |item: &str, count| GameAction::Buy {
+            vendor: oak,
+            item: item.into(),
+            count,
+        };
+        assert!(matches!(
+            apply(&mut state, pid, &buy("bread", 1))[..],
+            [GameEvent::TradeRejected {
+                reason: TradeError::NotAVendor,
+                ..
+            }]
+        ));
+
+        state.entity_mut(oak).expect("oak").shop = Some(Shop::new(store));
+        apply(&mut state, pid, &buy("rope", 3));
+        let player = state.entities.get(&pid).expect("pid");
+        assert_eq!(player.inventory.count("rope"), 3);
+        assert_eq!(player.inventory.count("coin"), registry.starting_purse - 36);
+        let reject = |events: Vec<GameEvent>| match events[..] {
+            [GameEvent::TradeRejected { reason, .. }] => Some(reason),
+            _ => None,
+        };
+        assert_eq!(
+            reject(apply(&mut state, pid, &buy("rope", 1))),
+            Some(TradeError::OutOfStock)
+        );
+        assert_eq!(
+            reject(apply(&mut state, pid, &buy("bread", 10))),
+            Some(TradeError::CannotAfford)
+        );
+        assert_eq!(
+            reject(apply(&mut state, pid, &buy("pelt", 1))),
+            Some(TradeError::NotSold)
+        );
+
+        let sell = GameAction::Sell {
+            vendor: oak,
+            item: "rope".into(),
+            count: 2,
+        };
+        assert_eq!(
+            apply(&mut state, pid, &sell),
+            [GameEvent::Traded { entity_id: pid }]
+        );
+        assert_eq!(
+            reject(apply(&mut state, pid, &sell)),
+            Some(TradeError::NotCarried)
+        );
+        assert_eq!(
+            state
+                .entities
+                .get(&oak)
+                .expect("oak")
+                .shop
+                .as_ref()
+                .map(|s| s.stock("rope")),
+            Some(2)
+        );
+
+        state.tick = store.restock_ticks;
+        shop::restock(&mut state, &registry);
+        assert_eq!(
+            state
+                .entities
+                .get(&oak)
+                .expect("oak")
+                .shop
+                .as_ref()
+                .map(|s| s.stock("rope")),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn heavy_loads_are_refused_and_slow_their_carrier() {
+        let (mut state, oak, pid) = oak_and_player();
+        let registry = ContentRegistry::builtin();
+        let store = registry.shop("general_store").expect("builtin shop");
+        state.entity_mut(oak).expect("oak").shop = Some(Shop::new(store));
+        let player = state.entity_mut(pid).expect("player");
+        player.inventory.add("pelt", 11);
+        assert_eq!(player.inventory.weight(&registry), 88);
+        assert_eq!(Encumbrance::of(player, &registry), Encumbrance::Burdened);
+
+        let buy = |count| GameAction::Buy {
+            vendor: oak,
+            item: "rope".into(),
+            count,
+        };
+        assert!(matches!(
+            apply(&mut state, pid, &buy(3))[..],
+            [GameEvent::TradeRejected {
+                reason: TradeError::TooHeavy,
+                ..
+            }]
+        ));
+        assert_eq!(
+            apply(&mut state, pid, &buy(2)),
+            [GameEvent::Traded { entity_id: pid }]
+        );
+        assert!(
+            !state
+                .entities
+                .get(&pid)
+                .expect("pid")
+                .inventory
+                .can_take(&registry, "rope", 1)
+        );
+        assert!(
+            state
+                .entities
+                .get(&pid)
+                .expect("pid")
+                .inventory
+                .can_take(&registry, "coin", 100)
+        );
+
+        state.tick = 1;
+        let step = GameAction::Move(Direction::Right);
+        assert!(apply(&mut state, pid, &step).is_empty());
+        state.tick = 2;
+        assert_eq!(
+            apply(&mut state, pid, &step),
+            [GameEvent::EntityMoved { entity_id: pid }]
+        );
+
+        let player = state.entity_mut(pid).expect("player");
+        player.inventory.add("pelt", 1);
+        assert_eq!(Encumbrance::of(player, &registry), Encumbrance::Overloaded);
+        assert!(apply(&mut state, pid, &step).is_empty());
+        state.tick = 4;
+        assert_eq!(apply(&mut state, pid, &step).len(), 1);
+    }
+
+    #[test]
+    fn stacks_fill_up_before_taking_a_new_slot() {
+        let registry = ContentRegistry::builtin();
+        let mut inventory = Inventory::default();
+        inventory.add("bread", 23);
+        inventory.add("coin", 50);
+        assert_eq!(
+            inventory.stacks(&registry).collect::<Vec<_>>(),
+            [("bread", 10), ("bread", 10), ("bread", 3), ("coin", 50)]
+        );
+        assert_eq!(inventory.slots(&registry), 4);
+
+        inventory.remove("bread", 23);
+        inventory.add("rope", 15);
+        assert_eq!(inventory.slots(&registry), registry.carry.max_slots);
+        assert!(!inventory.can_take(&registry, "rope", 1));
+        assert!(!inventory.can_take(&registry, "bread", 1));
+        assert!(inventory.can_take(&registry, "coin", 950));
+        assert!(!inventory.can_take(&registry, "coin", 951));
+    }
+
+    #[test]
+    fn weapons_wear_out_sell_for_less_and_are_repaired_at_stations() {
+        let (mut state, oak, pid) = oak_and_player();
+        let registry = ContentRegistry::builtin();
+        let bob = spawn_player(&mut state, "Bob".into());
+        state.place_entity(bob, Point { x: 10, y: 11 });
+        state
+            .entities
+            .get_mut(&pid)
+            .expect("player")
+            .inventory
+            .add("hatchet", 2);
+
+        apply(&mut state, pid, &GameAction::Attack(bob));
+        let health = state
+            .entities
+            .get(&bob)
+            .expect("bob")
+            .health
+            .map(|h| h.current);
+        assert_eq!(
+            health,
+            Some(combat::PLAYER_HEALTH - combat::ATTACK_DAMAGE - 5)
+        );
+        let inventory = &mut state.entity_mut(pid).expect("player").inventory;
+        assert_eq!(inventory.condition(&registry, "hatchet"), Some((29, 30)));
+        for _ in 0..28 {
+            assert!(!inventory.wear_out(&registry, "hatchet"));
+        }
+        let store = registry.shop("general_store").expect("builtin shop");
+        let entry = store.entry("hatchet").expect("hatchet is sold");
+        assert_eq!(shop::sale_price(&registry, inventory, entry, 1), Some(10));
+        assert_eq!(shop::sale_price(&registry, inventory, entry, 2), Some(10));
+
+        let repair = GameAction::Repair("hatchet".into());
+        let rejected = |events: Vec<GameEvent>| match events[..] {
+            [GameEvent::RepairRejected { reason, .. }] => Some(reason),
+            _ => None,
+        };
+        assert_eq!(
+            rejected(apply(&mut state, pid, &repair)),
+            Some(RepairError::NoStation)
+        );
+        let station = state.entity_mut(oak).expect("oak");
+        station
+            .tags
+            .insert(item::REPAIR_STATION_TAG)
+            .expect("valid tag");
+        assert_eq!(
+            rejected(apply(&mut state, pid, &repair)),
+            Some(RepairError::MissingMaterials)
+        );
+        let inventory = &mut state.entity_mut(pid).expect("player").inventory;
+        inventory.add("whetstone", 1);
+        assert_eq!(apply(&mut state, pid, &repair).len(), 1);
+        let inventory = &mut state.entity_mut(pid).expect("player").inventory;
+        assert_eq!(inventory.condition(&registry, "hatchet"), Some((30, 30)));
+        assert_eq!(inventory.count("whetstone"), 0);
+
+        let broke = (0..30)
+            .filter(|_| inventory.wear_out(&registry, "hatchet"))
+            .count();
+        assert_eq!((broke, inventory.count("hatchet")), (1, 1));
+        assert_eq!(inventory.wear("hatchet"), 0);
+    }
+
+    // -- intents -------------------------------------------------------------
+
+    #[test]
+    fn work_far_from_players_waits_and_catches_up_when_they_return() {
+        let registry = ContentRegistry::builtin();
+        let mut state = empty_state();
+        let pid = spawn_player(&mut state, "Alice".into());
+        let (npc, tree) = (state.entity_gen.next(), state.entity_gen.next());
+        let mut hermit = Entity::new(EntityType::Npc, Point { x: 200, y: 10 }, None);
+        assert!(hermit.queue.push(Intent::Chop(tree)));
+        state.insert_entity(npc, hermit);
+        let at = Point { x: 201, y: 10 };
+        state.insert_entity(tree, Entity::new(EntityType::Tree, at, None));
+        let go = |state: &mut GameState, x: i32| {
+            state.place_entity(pid, Point { x, y: 12 });
+        };
+        let chopped = |state: &GameState| {
+            state.entities.get(&tree).expect("tree").entity_type == EntityType::Stump
+        };
+
+        // Nobody is near, so the hermit does not start.
+        for _ in 0..5 {
+            advance(&mut state, &registry);
+        }
+        assert_eq!(
+            state.entities.get(&npc).expect("npc").queue.started_at(),
+            None
+        );
+
+        // It starts with Alice around, then waits once she leaves...
+        go(&mut state, 190);
+        advance(&mut state, &registry);
+        assert!(
+            state
+                .entities
+                .get(&npc)
+                .expect("npc")
+                .queue
+                .started_at()
+                .is_some()
+        );
+        go(&mut state, 10);
+        for _ in 0..intent::CHOP_TICKS * 2 {
+            advance(&mut state, &registry);
+        }
+        assert!(!chopped(&state));
+
+        // ...and the work done meanwhile completes as she returns.
+        go(&mut state, 190);
+        let events = advance(&mut state, &registry);
+        assert!(chopped(&state));
+        assert!(events.contains(&GameEvent::IntentEnded {
+            entity_id: npc,
+            completed: true,
+        }));
+    }
+
+    #[test]
+    fn queued_intents_run_in_order_and_stop_when_interrupted() {
+        let registry = ContentRegistry::builtin();
+        let mut state = empty_state();
+        let pid = spawn_player(&mut state, "Alice".into());
+        let tree = state.entity_gen.next();
+        let at = Point { x: 13, y: 10 };
+        state.insert_entity(tree, Entity::new(EntityType::Tree, at, None));
+
+        apply(
+            &mut state,
+            pid,
+            &GameAction::Queue(Intent::WalkTo(Point { x: 12, y: 10 })),
+        );
+        apply(&mut state, pid, &GameAction::Queue(Intent::Chop(tree)));
+        let mut ended = Vec::new();
+        let mut halfway = None;
+        while !state.entities.get(&pid).expect("pid").queue.is_empty() && state.tick < 100 {
+            ended.extend(
+                advance(&mut state, &registry)
+                    .into_iter()
+                    .filter(|e| matches!(e, GameEvent::IntentEnded { .. })),
+            );
+            let queue = &state.entities.get(&pid).expect("pid").queue;
+            if queue.progress(state.tick) == Some((intent::CHOP_TICKS / 2, intent::CHOP_TICKS)) {
+                halfway = Some(state.tick);
+            }
+        }
+        assert_eq!(
+            state.entities.get(&tree).expect("tree").entity_type,
+            EntityType::Stump
+        );
+        let completed = GameEvent::IntentEnded {
+            entity_id: pid,
+            completed: true,
+        };
+        assert_eq!(ended, [completed.clone(), completed]);
+        let halfway = halfway.expect("progress was reported");
+        assert_eq!(state.tick - halfway, u64::from(intent::CHOP_TICKS / 2));
+
+        let chop = GameAction::Queue(Intent::Chop(tree));
+        apply(&mut state, pid, &chop);
+        advance(&mut state, &registry);
+        combat::damage(&mut state, pid, 1);
+        assert!(state.entities.get(&pid).expect("pid").queue.is_empty());
+        apply(&mut state, pid, &chop);
+        assert_eq!(
+            apply(&mut state, pid, &GameAction::Cancel),
+            [GameEvent::IntentEnded {
+                entity_id: pid,
+                completed: false,
+            }]
+        );
+        assert!(apply(&mut state, pid, &GameAction::Cancel).is_empty());
+        assert_eq!(
+            state.entities.get(&tree).expect("tree").entity_type,
+            EntityType::Stump
+        );
+    }
+
+    #[test]
+    fn channelled_craft_completes_on_schedule_and_reports_sparingly() {
+        let registry = ContentRegistry::builtin();
+        let mut state = empty_state();
+        let pid = spawn_player(&mut state, "Alice".into());
+        let inventory = &mut state.entity_mut(pid).expect("spawned").inventory;
+        inventory.add("rope", 1);
+        inventory.add("pelt", 1);
+
+        apply(
+            &mut state,
+            pid,
+            &GameAction::Queue(Intent::Craft("bomb".into())),
+        );
+        let mut progress = Vec::new();
+        let mut ended = Vec::new();
+        while !state.entities.get(&pid).expect("pid").queue.is_empty() && state.tick < 100 {
+            let completes_at = state.entities.get(&pid).expect("pid").queue.completes_at();
+            for event in advance(&mut state, &registry) {
+                match event {
+                    GameEvent::ActionProgress { done, total, .. } => progress.push((done, total)),
+                    GameEvent::IntentEnded { completed, .. } => {
+                        ended.push((state.tick - 1, completes_at, completed));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let total = intent::CRAFT_TICKS;
+        let last = u64::from(total) - 1;
+        assert_eq!(ended, [(last, Some(last), true)]);
+        assert_eq!(
+            progress,
+            [
+                (0, total),
+                (total / 4, total),
+                (total / 2, total),
+                (total * 3 / 4, total)
+            ]
+        );
+        let inventory = &state.entities.get(&pid).expect("pid").inventory;
+        assert_eq!(inventory.count("bomb"), 1);
+        assert_eq!(inventory.count("rope") + inventory.count("pelt"), 0);
+
+        // Nothing left to craft from.
+        apply(
+            &mut state,
+            pid,
+            &GameAction::Queue(Intent::Craft("bomb".into())),
+        );
+        assert_eq!(
+            advance(&mut state, &registry),
+            [GameEvent::IntentEnded {
+                entity_id: pid,
+                completed: false,
+            }]
+        );
+
+        // Damage stops work, but not a walk.
+        let walk = GameAction::Queue(Intent::WalkTo(Point { x: 30, y: 10 }));
+        apply(&mut state, pid, &walk);
+        advance(&mut state, &registry);
+        combat::damage(&mut state, pid, 1);
+        assert!(!state.entities.get(&pid).expect("pid").queue.is_empty());
+    }
+
+    // -- world events --------------------------------------------------------
+
+    /// Run `state` up to `until`, collecting `(tick, id, active)` per
+    /// announcement.
+    fn run_events(state: &mut GameState, until: u64) -> Vec<(u64, String, bool)> {
+        let registry = ContentRegistry::builtin();
+        let mut log = Vec::new();
+        while state.tick < until {
+            state.tick += 1;
+            for announcement in world_events::advance(state, &registry) {
+                log.push((state.tick, announcement.id, announcement.active));
+            }
+        }
+        log
+    }
+
+    #[test]
+    fn world_events_follow_the_schedule() {
+        let mut a = GameState::create_test_world("a".into());
+        let mut b = GameState::create_test_world("b".into());
+        let log = run_events(&mut a, 11_999);
+        assert_eq!(log, run_events(&mut b, 11_999));
+        assert_eq!(a.checksum(), b.checksum());
+        assert_eq!(
+            log,
+            [
+                (3000, "wandering_trader".to_owned(), true),
+                (4500, "aurora".to_owned(), true),
+                (4800, "wandering_trader".to_owned(), false),
+                (5700, "aurora".to_owned(), false),
+                (10_500, "aurora".to_owned(), true),
+                (11_700, "aurora".to_owned(), false),
+            ]
+        );
+
+        let err = ContentRegistry::from_sources(content::Sources {
+            events: r#"(events: [(id: "meteors", every: 10, duration: 1)])"#,
+            ..content::Sources::BUILTIN
+        })
+        .expect_err("an event without a hook");
+        assert!(err.contains("no hook"), "{err}");
+    }
+
+    #[test]
+    fn wandering_trader_comes_and_goes() {
+        use world_events::WanderingTrader;
+
+        let mut state = GameState::create_test_world("w".into());
+        spawn_player(&mut state, "Alice".into());
+        run_events(&mut state, 3000);
+        let traders: Vec<EntityID> = state.tagged(WanderingTrader::TAG).collect();
+        let [trader] = traders[..] else {
+            panic!("expected one trader, got {traders:?}");
+        };
+        let trader = state.entities.get(&trader).expect("trader");
+        assert_eq!(trader.entity_type, EntityType::Npc);
+        assert_ne!(trader.position, SPAWN_POINT);
+        assert!(dialogue::in_range(trader.position, SPAWN_POINT));
+        assert_eq!(
+            trader.shop.as_ref().map(|s| s.id.as_str()),
+            Some(WanderingTrader::SHOP)
+        );
+
+        run_events(&mut state, 4800);
+        assert_eq!(state.tagged(WanderingTrader::TAG).count(), 0);
+        assert_eq!(state.get_playable_entities().len(), 1);
+    }
+
+    #[test]
+    fn aurora_widens_the_view() {
+        assert_eq!(world_events::view_radius([]), fov::VIEW_RADIUS);
+        assert_eq!(
+            world_events::view_radius(["aurora", "wandering_trader"]),
+            fov::VIEW_RADIUS + 6
+        );
+    }
+
+    // -- group movement ------------------------------------------------------
+
+    #[test]
+    fn paths_lead_around_blockers() {
+        let wall: FxHashSet<Point> = (-2..=2).map(|y| Point { x: 1, y }).collect();
+        let from = Point { x: 0, y: 0 };
+        let to = Point { x: 2, y: 0 };
+        let mut point = from;
+        let mut steps = 0;
+        while let Some(direction) =
+            path::first_step(point, to, |p| (!wall.contains(&p)).then_some(1))
+        {
+            point = path::step(point, direction);
+            assert!(!wall.contains(&point));
+            steps += 1;
+        }
+        assert_eq!((point, steps), (to, 8));
+
+        // An unreachable goal leads as close as possible.
+        let boxed: FxHashSet<Point> = [(4, 0), (6, 0), (5, -1), (5, 1)]
+            .into_iter()
+            .map(|(x, y)| Point { x, y })
+            .collect();
+        let open = |p| (!boxed.contains(&p)).then_some(1);
+        let step = path::first_step(from, Point { x: 5, y: 0 }, open);
+        assert_eq!(step, Some(Direction::Right));
+        assert_eq!(
+            path::first_step(Point { x: 3, y: 0 }, Point { x: 5, y: 0 }, open),
+            None
+        );
+    }
+
+    #[test]
+    fn groups_move_in_formation_without_sharing_tiles() {
+        let mut state = empty_state();
+        let leader = spawn_player(&mut state, "Alice".into());
+        let stranger = spawn_player(&mut state, "Bob".into());
+        let mut pets = Vec::new();
+        for (x, y) in [(9, 10), (11, 10), (10, 30)] {
+            let id = state.entity_gen.next();
+            let mut pet = Entity::new(EntityType::Npc, Point { x, y }, None);
+            pet.owner = Some(leader);
+            state.insert_entity(id, pet);
+            pets.push(id);
+        }
+        state.place_entity(stranger, Point { x: 0, y: 0 });
+
+        let mut members = vec![leader, stranger];
+        members.extend(&pets);
+        apply(
+            &mut state,
+            leader,
+            &GameAction::MoveGroup {
+                members,
+                target: Point { x: 30, y: 10 },
+            },
+        );
+        assert_eq!(
+            state.entities.get(&stranger).expect("stranger").destination,
+            None
+        );
+
+        for _ in 0..100 {
+            formation::advance(
+                &mut state,
+                &ContentRegistry::builtin(),
+                &region::ActiveRegions::everywhere(),
+            );
+            let occupied: FxHashSet<Point> = state.entities.values().map(|e| e.position).collect();
+            assert_eq!(occupied.len(), state.entities.len());
+        }
+        let at = |eid: EntityID| state.entities.get(&eid).expect("eid").position;
+        let &[left, right, straggler] = pets.as_slice() else {
+            panic!("three pets, got {pets:?}");
+        };
+        assert_eq!(at(leader), Point { x: 30, y: 10 });
+        assert_eq!(at(left), Point { x: 29, y: 10 });
+        assert_eq!(at(right), Point { x: 31, y: 10 });
+        // The straggler closes up to the edge of the formation.
+        assert_eq!(
+            at(straggler),
+            Point {
+                x: 30,
+                y: 10 + formation::FORMATION_SPREAD
+            }
+        );
+        assert_eq!(at(stranger), Point { x: 0, y: 0 });
+        assert!(state.entities.values().all(|e| e.destination.is_none()));
+    }
+
+    #[test]
+    fn walkers_go_around_new_obstacles_and_give_up_when_walled_off() {
+        let registry = ContentRegistry::builtin();
+        let mut state = empty_state();
+        let alice = spawn_player(&mut state, "Alice".into());
+        let start = state.entities.get(&alice).expect("alice").position;
+        let plant = |state: &mut GameState, x, y| {
+            let id = state.entity_gen.next();
+            let tree = Entity::new(EntityType::Tree, Point { x, y }, None);
+            state.insert_entity(id, tree);
+        };
+
+        // A tree grows across the way after the walk set out.
+        let target = Point {
+            x: start.x + 10,
+            y: start.y,
+        };
+        apply(
+            &mut state,
+            alice,
+            &GameAction::Queue(Intent::WalkTo(target)),
+        );
+        advance(&mut state, &registry);
+        plant(&mut state, start.x + 5, start.y);
+        for _ in 0..20 {
+            advance(&mut state, &registry);
+        }
+        assert_eq!(state.entities.get(&alice).expect("alice").position, target);
+
+        // A goal walled in further than the formation's spread is given up
+        // on once the walker has waited long enough at the wall.
+        let walled = Point {
+            x: target.x + 12,
+            y: target.y,
+        };
+        for dy in -4..=4 {
+            for dx in -4..=4 {
+                if dx == -4 || dx == 4 || dy == -4 || dy == 4 {
+                    plant(&mut state, walled.x + dx, walled.y + dy);
+                }
+            }
+        }
+        apply(
+            &mut state,
+            alice,
+            &GameAction::Queue(Intent::WalkTo(walled)),
+        );
+        let mut events = Vec::new();
+        for _ in 0..formation::PATH_PATIENCE + 20 {
+            events.extend(advance(&mut state, &registry));
+        }
+        assert!(events.contains(&GameEvent::PathBlocked {
+            entity_id: alice,
+            destination: walled,
+        }));
+        assert!(events.contains(&GameEvent::IntentEnded {
+            entity_id: alice,
+            completed: false,
+        }));
+        let alice = state.entities.get(&alice).expect("alice");
+        assert_eq!(math::manhattan(alice.position, walled), 5);
+        assert_eq!((alice.destination, alice.blocked_since), (None, None));
+        assert!(alice.queue.is_empty());
+    }
+
+    #[test]
+    fn friendly_walkers_pass_on_a_narrow_bridge_and_queue_through_gaps() {
+        let registry = ContentRegistry::builtin();
+        let mut state = empty_state();
+        let walk = |state: &mut GameState, name: &str, from: Point, to: Point| {
+            let eid = spawn_player(state, name.into());
+            state.place_entity(eid, from);
+            apply(state, eid, &GameAction::Queue(Intent::WalkTo(to)));
+            eid
+        };
+        let run = |state: &mut GameState, ticks| {
+            let mut events = Vec::new();
+            for _ in 0..ticks {
+                events.extend(advance(state, &registry));
+                let players: Vec<Point> = state
+                    .entities
+                    .values()
+                    .filter(|e| e.entity_type == EntityType::Player)
+                    .map(|e| e.position)
+                    .collect();
+                let occupied: FxHashSet<&Point> = players.iter().collect();
+                assert_eq!(occupied.len(), players.len(), "two walkers on one tile");
+            }
+            events
+        };
+
+        // A river with a one-tile bridge, crossed from both ends at once.
+        for y in -20..=20 {
+            bridge::flood(&mut state, Point { x: 10, y });
+        }
+        bridge::build(&mut state, Point { x: 10, y: 0 }).expect("open water");
+        let (west, east) = (Point { x: 7, y: 0 }, Point { x: 13, y: 0 });
+        let alice = walk(&mut state, "Alice", west, east);
+        let bob = walk(&mut state, "Bob", east, west);
+        let events = run(&mut state, 12);
+        assert_eq!(state.entities.get(&alice).expect("alice").position, east);
+        assert_eq!(state.entities.get(&bob).expect("bob").position, west);
+        assert!(
+            !events
+                .iter()
+                .any(|e| matches!(e, GameEvent::PathBlocked { .. }))
+        );
+
+        // Three abreast before a one-tile gap file through it in turn,
+        // rather than scatter around the wall.
+        let mut state = empty_state();
+        for y in -10..=10 {
+            if y != 0 {
+                let id = state.entity_gen.next();
+                let tree = Entity::new(EntityType::Tree, Point { x: 5, y }, None);
+                state.insert_entity(id, tree);
+            }
+        }
+        let walkers: Vec<EntityID> = [-1, 0, 1]
+            .into_iter()
+            .map(|y| walk(&mut state, "Walker", Point { x: 3, y }, Point { x: 8, y }))
+            .collect();
+        let events = run(&mut state, 20);
+        for (eid, y) in walkers.iter().zip([-1, 0, 1]) {
+            assert_eq!(
+                state.entities.get(eid).expect("walker").position,
+                Point { x: 8, y }
+            );
+        }
+        assert!(
+            !events
+                .iter()
+                .any(|e| matches!(e, GameEvent::PathBlocked { .. }))
+        );
+    }
+
+    #[test]
+    fn followers_keep_up_without_oscillating() {
+        let mut state = empty_state();
+        let owner = spawn_player(&mut state, "Alice".into());
+        let pet = state.entity_gen.next();
+        let mut entity = Entity::new(EntityType::Npc, Point { x: 12, y: 10 }, None);
+        entity.owner = Some(owner);
+        entity.follow = Some(Follow::new(3));
+        state.insert_entity(pet, entity);
+        let at = |state: &GameState| state.entities.get(&pet).expect("pet").position;
+
+        // Within the leash the pet stays put, even as the owner paces.
+        for direction in [Direction::Left, Direction::Right, Direction::Left] {
+            apply(&mut state, owner, &GameAction::Move(direction));
+            assert!(
+                follow::advance(
+                    &mut state,
+                    &ContentRegistry::builtin(),
+                    &region::ActiveRegions::everywhere()
+                )
+                .is_empty()
+            );
+        }
+        assert_eq!(at(&state), Point { x: 12, y: 10 });
+
+        // Past it, the pet walks back until it is next to its owner...
+        apply(&mut state, owner, &GameAction::Move(Direction::Left));
+        let mut steps = 0;
+        while !follow::advance(
+            &mut state,
+            &ContentRegistry::builtin(),
+            &region::ActiveRegions::everywhere(),
+        )
+        .is_empty()
+        {
+            steps += 1;
+        }
+        assert_eq!((at(&state), steps), (Point { x: 9, y: 10 }, 3));
+        // ...and does not start again one tile short of the leash.
+        for _ in 0..2 {
+            apply(&mut state, owner, &GameAction::Move(Direction::Left));
+            assert!(
+                follow::advance(
+                    &mut state,
+                    &ContentRegistry::builtin(),
+                    &region::ActiveRegions::everywhere()
+                )
+                .is_empty()
+            );
+        }
+
+        state.place_entity(owner, Point { x: 100, y: 10 });
+        assert_eq!(
+            follow::advance(
+                &mut state,
+                &ContentRegistry::builtin(),
+                &region::ActiveRegions::everywhere()
+            ),
+            [pet]
+        );
+        assert!(math::manhattan(at(&state), Point { x: 100, y: 10 }) <= 2);
+    }
+
+    // -- combat --------------------------------------------------------------
+
+    #[test]
+    fn attacks_knock_targets_back_until_they_hit_something() {
+        use combat::{ATTACK_DAMAGE, PLAYER_HEALTH};
+
+        let mut state = GameState::create_test_world("w".into());
+        let alice = spawn_player(&mut state, "Alice".into());
+        let bob = spawn_player(&mut state, "Bob".into());
+        let place = |state: &mut GameState, eid, x, y| {
+            state.place_entity(eid, Point { x, y });
+        };
+        let health = |state: &GameState, eid| {
+            state
+                .entities
+                .get(&eid)
+                .expect("eid")
+                .health
+                .map(|h| h.current)
+        };
+        let attack = |state: &mut GameState, attacker, target| match &apply(
+            state,
+            attacker,
+            &GameAction::Attack(target),
+        )[..]
+        {
+            [
+                GameEvent::Attacked { .. },
+                GameEvent::Damaged { .. },
+                GameEvent::Pushed { to, obstacle, .. },
+            ] => Some((*to, *obstacle)),
+            _ => None,
+        };
+
+        place(&mut state, bob, 11, 10);
+        assert_eq!(
+            attack(&mut state, alice, bob),
+            Some((Point { x: 13, y: 10 }, None))
+        );
+        assert_eq!(health(&state, bob), Some(PLAYER_HEALTH - ATTACK_DAMAGE));
+        // Out of reach now.
+        assert!(apply(&mut state, alice, &GameAction::Attack(bob)).is_empty());
+
+        // Knocked against the tree at (10, 5): cut short, and it hurts.
+        let tree = state
+            .entities
+            .iter()
+            .find(|(_, e)| e.position == Point { x: 10, y: 5 })
+            .map(|(eid, _)| *eid)
+            .expect("tree");
+        place(&mut state, alice, 10, 8);
+        place(&mut state, bob, 10, 7);
+        assert_eq!(
+            attack(&mut state, alice, bob),
+            Some((Point { x: 10, y: 6 }, Some(tree)))
+        );
+        assert_eq!(
+            health(&state, bob),
+            Some(PLAYER_HEALTH - 2 * ATTACK_DAMAGE - collision::COLLISION_DAMAGE)
+        );
+
+        // Trees neither move nor take damage.
+        assert_eq!(collision::push(&mut state, tree, Direction::Up, 3), None);
+        assert!(apply(&mut state, bob, &GameAction::Attack(tree)).is_empty());
+    }
+
+    #[test]
+    fn the_dead_leave_corpses_and_hurt_trees_fall() {
+        let mut state = GameState::create_test_world("w".into());
+        let alice = spawn_player(&mut state, "Alice".into());
+        state.place_entity(alice, Point { x: 10, y: 6 });
+        let mut rat = Entity::new(EntityType::Npc, Point { x: 11, y: 6 }, Some("Rat".into()));
+        rat.health = Some(Health::new(combat::ATTACK_DAMAGE));
+        rat.combat.defense = 4;
+        rat.inventory.add("bomb", 1);
+        let rat_id = state.entity_gen.next();
+        state.insert_entity(rat_id, rat);
+        let strike = |state: &mut GameState, direction| {
+            apply(state, alice, &GameAction::AttackToward(direction))
+        };
+
+        assert!(strike(&mut state, Direction::Down).is_empty());
+        let events = strike(&mut state, Direction::Right);
+        assert!(events.contains(&GameEvent::Damaged {
+            entity_id: rat_id,
+            amount: combat::ATTACK_DAMAGE - 4,
+        }));
+        state.entity_mut(alice).expect("alice").combat.attack = 4;
+        let rat_at = state.entities.get(&rat_id).expect("rat_id").position;
+        state.place_entity(
+            alice,
+            Point {
+                x: rat_at.x - 1,
+                y: rat_at.y,
+            },
+        );
+        let events = strike(&mut state, Direction::Right);
+        assert!(events.contains(&GameEvent::Died {
+            entity_id: rat_id,
+            name: Some("Rat".into()),
+        }));
+        let corpse = state.entities.get(&rat_id).expect("rat_id");
+        assert_eq!(corpse.entity_type, EntityType::Corpse);
+        assert_eq!((corpse.health, corpse.inventory.count("bomb")), (None, 1));
+
+        // A tree given health is felled into a stump, which stays.
+        let tree = state
+            .entities
+            .iter()
+            .find(|(_, e)| e.position == Point { x: 10, y: 5 })
+            .map(|(eid, _)| *eid)
+            .expect("tree");
+        state.entity_mut(tree).expect("tree").health = Some(Health::new(1));
+        state.place_entity(alice, Point { x: 10, y: 6 });
+        assert!(!strike(&mut state, Direction::Up).is_empty());
+        assert_eq!(
+            state.entities.get(&tree).expect("tree").entity_type,
+            EntityType::Stump
+        );
+        assert_eq!(state.entities.get(&tree).expect("tree").health, None);
+    }
+
+    // -- explosions ----------------------------------------------------------
+
+    #[test]
+    fn blasts_are_stopped_by_what_they_destroy() {
+        let mut state = GameState::create_test_world("w".into());
+        let alice = spawn_player(&mut state, "Alice".into());
+        let bob = spawn_player(&mut state, "Bob".into());
+        state.place_entity(alice, Point { x: 12, y: 3 });
+        state.place_entity(bob, Point { x: 10, y: 6 });
+        let mut rat = Entity::new(EntityType::Npc, Point { x: 8, y: 3 }, None);
+        rat.health = Some(Health::new(10));
+        let rat_id = state.entity_gen.next();
+        state.insert_entity(rat_id, rat);
+        let tree = state
+            .entities
+            .iter()
+            .find(|(_, e)| e.position == Point { x: 10, y: 5 })
+            .map(|(eid, _)| *eid)
+            .expect("tree");
+
+        let GameEvent::Exploded(explosion) =
+            explosion::explode(&mut state, &Blast::bomb(Point { x: 10, y: 3 }))
+        else {
+            panic!("expected an explosion");
+        };
+        // The tree is blown away, but Bob was in its shadow.
+        assert!(explosion.tiles.contains(&Point { x: 10, y: 5 }));
+        assert!(!explosion.tiles.contains(&Point { x: 10, y: 6 }));
+        assert_eq!(explosion.destroyed, [tree, rat_id]);
+        assert_eq!(
+            state.entities.get(&tree).expect("tree").entity_type,
+            EntityType::Stump
+        );
+        assert_eq!(
+            state.entities.get(&bob).expect("bob").health,
+            Some(Health::new(combat::PLAYER_HEALTH))
+        );
+
+        // Two tiles out: half damage, pushed away from the origin.
+        assert!(explosion.damaged.contains(&alice));
+        assert!(
+            explosion
+                .pushed
+                .contains(&(alice, Point { x: 12, y: 3 }, Point { x: 14, y: 3 }))
+        );
+        assert_eq!(
+            state
+                .entities
+                .get(&alice)
+                .expect("alice")
+                .health
+                .map(|h| h.current),
+            Some(combat::PLAYER_HEALTH - 20)
+        );
+    }
+
+    #[test]
+    fn bombs_need_range_and_sight() {
+        let mut state = GameState::create_test_world("w".into());
+        let alice = spawn_player(&mut state, "Alice".into());
+        state.place_entity(alice, Point { x: 10, y: 10 });
+        let throw = |state: &mut GameState, x, y| {
+            let action = GameAction::Throw {
+                item: explosion::BOMB.into(),
+                target: Point { x, y },
+            };
+            apply(state, alice, &action)
+        };
+
+        // No bomb yet.
+        assert!(throw(&mut state, 12, 10).is_empty());
+        state
+            .entities
+            .get_mut(&alice)
+            .expect("alice")
+            .inventory
+            .add(explosion::BOMB, 1);
+        // Too far, and behind the tree at (10, 15).
+        assert!(throw(&mut state, 10, 17).is_empty());
+        assert!(throw(&mut state, 10, 16).is_empty());
+        assert!(matches!(
+            throw(&mut state, 13, 10)[..],
+            [GameEvent::Exploded(_)]
+        ));
+        assert_eq!(
+            state
+                .entities
+                .get(&alice)
+                .expect("alice")
+                .inventory
+                .count(explosion::BOMB),
+            0
+        );
+        assert!(throw(&mut state, 13, 10).is_empty());
+    }
+
+    #[test]
+    fn pings_need_sight_and_expire() {
+        let mut state = GameState::create_test_world("w".into());
+        let alice = spawn_player(&mut state, "Alice".into());
+        state.place_entity(alice, Point { x: 10, y: 10 });
+        let ping_at = |state: &mut GameState, x, y| {
+            let action = GameAction::Ping {
+                pos: Point { x, y },
+                kind: PingKind::Go,
+            };
+            apply(state, alice, &action)
+        };
+
+        // Behind the tree at (10, 15), and out of view.
+        assert!(ping_at(&mut state, 10, 16).is_empty());
+        assert!(ping_at(&mut state, 10 + fov::VIEW_RADIUS + 1, 10).is_empty());
+        let [GameEvent::Pinged(ping)] = ping_at(&mut state, 13, 10)[..] else {
+            panic!("expected a ping");
+        };
+        assert_eq!(ping.from, alice);
+        assert!(!ping.expired(state.tick + ping::PING_TICKS - 1));
+        assert!(ping.expired(state.tick + ping::PING_TICKS));
+    }
+
+    // -- debris --------------------------------------------------------------
+
+    #[test]
+    fn destroyed_terrain_leaves_debris() {
+        let mut state = GameState::create_test_world("w".into());
+        let tree = state
+            .entities
+            .iter()
+            .find(|(_, e)| e.position == Point { x: 10, y: 5 })
+            .map(|(eid, _)| *eid)
+            .expect("tree");
+        let at = Point { x: 10, y: 5 };
+
+        assert_eq!(debris::destroy(&mut state, tree), Some(EntityType::Stump));
+        assert!(!fov::blocks_sight(&state, at));
+        assert!(collision::is_blocked(&state, at));
+
+        assert_eq!(debris::destroy(&mut state, tree), Some(EntityType::Rubble));
+        assert!(!collision::is_blocked(&state, at));
+        assert!(EntityType::Rubble.is_anchored());
+
+        assert_eq!(debris::destroy(&mut state, tree), None);
+        assert!(!state.entities.contains_key(&tree));
+    }
+
+    #[test]
+    fn views_are_recomputed_only_for_changes_in_sight() {
+        let mut state = GameState::create_test_world("w".into());
+        let alice = spawn_player(&mut state, "Alice".into());
+        state.place_entity(alice, Point { x: 10, y: 10 });
+        let mut opaque = fov::OpaqueSet::of(&state.entities);
+        let mut view = fov::PlayerFov::new(alice);
+        let behind_tree = Point { x: 10, y: 4 };
+
+        assert!(view.update(&state.entities, &opaque, &state.terrain));
+        assert!(!view.update(&state.entities, &opaque, &state.terrain));
+        assert!(!view.mask.contains(behind_tree));
+
+        // A new tree far out of sight leaves the view alone.
+        let far = state.entity_gen.next();
+        state.insert_entity(
+            far,
+            Entity::new(EntityType::Tree, Point { x: 100, y: 100 }, None),
+        );
+        assert!(opaque.sync(&state.entities));
+        assert!(!opaque.sync(&state.entities));
+        assert!(!view.update(&state.entities, &opaque, &state.terrain));
+
+        // Felling the tree in front of Alice opens the view behind it.
+        let tree = state
+            .entities
+            .iter()
+            .find(|(_, e)| e.position == Point { x: 10, y: 5 })
+            .map(|(eid, _)| *eid)
+            .expect("tree");
+        debris::destroy(&mut state, tree);
+        assert!(opaque.sync(&state.entities));
+        assert_eq!(opaque.changed(), [Point { x: 10, y: 5 }]);
+        assert!(view.update(&state.entities, &opaque, &state.terrain));
+        assert!(view.mask.contains(behind_tree));
+
+        // Missing more than one version forces a recompute, as does moving.
+        state.remove_entity(far);
+        opaque.sync(&state.entities);
+        state.insert_entity(
+            far,
+            Entity::new(EntityType::Tree, Point { x: 100, y: 100 }, None),
+        );
+        opaque.sync(&state.entities);
+        assert!(view.update(&state.entities, &opaque, &state.terrain));
+        state.place_entity(alice, Point { x: 11, y: 10 });
+        assert!(view.update(&state.entities, &opaque, &state.terrain));
+    }
+
+    // -- bridges -------------------------------------------------------------
+
+    #[test]
+    fn bridges_make_water_walkable() {
+        let mut state = empty_state();
+        let alice = spawn_player(&mut state, "Alice".into());
+        let start = state.entities.get(&alice).expect("alice").position;
+        let east = path::step(start, Direction::Right);
+        let water = bridge::flood(&mut state, east);
+
+        assert!(apply(&mut state, alice, &GameAction::Move(Direction::Right)).is_empty());
+        assert_eq!(state.entities.get(&alice).expect("alice").position, start);
+        assert_eq!(collision::cost_at(&state, east), None);
+
+        let span = bridge::build(&mut state, east).expect("open water");
+        assert_eq!(bridge::build(&mut state, east), None);
+        assert_eq!(collision::cost_at(&state, east), Some(bridge::BRIDGE_COST));
+        apply(&mut state, alice, &GameAction::Move(Direction::Right));
+        assert_eq!(state.entities.get(&alice).expect("alice").position, east);
+
+        // Blasts take the bridge and leave the water.
+        debris::destroy(&mut state, span);
+        assert!(collision::is_open_water(&state, east));
+        assert!(!EntityType::Water.is_destructible());
+        assert!(state.entities.contains_key(&water));
+    }
+
+    #[test]
+    fn paths_prefer_cheap_tiles_and_cross_rivers_by_bridge() {
+        let from = Point { x: 0, y: 0 };
+        let to = Point { x: 2, y: 0 };
+        let middle = Point { x: 1, y: 0 };
+        let costly = |cost| move |p: Point| Some(if p == middle { cost } else { 1 });
+        assert_eq!(
+            path::first_step(from, to, costly(2)),
+            Some(Direction::Right)
+        );
+        assert_eq!(path::first_step(from, to, costly(5)), Some(Direction::Up));
+
+        let mut state = empty_state();
+        for y in -20..=20 {
+            bridge::flood(&mut state, Point { x: 1, y });
+        }
+        bridge::build(&mut state, Point { x: 1, y: 3 });
+        let mut point = from;
+        let mut crossed = false;
+        while let Some(direction) = path::first_step(point, to, |p| collision::cost_at(&state, p)) {
+            point = path::step(point, direction);
+            crossed |= point == Point { x: 1, y: 3 };
+        }
+        assert_eq!(point, to);
+        assert!(crossed);
+    }
+
+    #[test]
+    fn pathfind_routes_around_trees_and_over_bridges() {
+        let mut state = empty_state();
+        let tree = |x, y| Entity::new(EntityType::Tree, Point { x, y }, None);
+        for y in -1..=1 {
+            let id = state.entity_gen.next();
+            state.insert_entity(id, tree(1, y));
+        }
+        let (from, to) = (Point { x: 0, y: 0 }, Point { x: 2, y: 0 });
+        let path = pathfind(&state, from, to).expect("a way around");
+        assert_eq!(path.len(), 6);
+        assert_eq!(path.last(), Some(&to));
+        assert!(
+            path.windows(2)
+                .all(|w| matches!(w, [a, b] if math::manhattan(*a, *b) == 1))
+        );
+        assert_eq!(pathfind(&state, from, from), Some(Vec::new()));
+
+        // Walled in on every side, there is no way out.
+        for at in [
+            Point { x: 0, y: 1 },
+            Point { x: 0, y: -1 },
+            Point { x: -1, y: 0 },
+        ] {
+            let id = state.entity_gen.next();
+            state.insert_entity(id, tree(at.x, at.y));
+        }
+        assert_eq!(pathfind(&state, from, to), None);
+    }
+
+    // -- terrain -------------------------------------------------------------
+
+    #[test]
+    fn terrain_blocks_movement_and_sight_and_takes_bridges() {
+        let mut terrain = TileMap::default();
+        let far = Point { x: -70, y: 300 };
+        assert_eq!(terrain.get(far), Terrain::Grass);
+        terrain.set(far, Terrain::Grass);
+        assert_eq!(terrain.chunks().count(), 0);
+        terrain.set(far, Terrain::Sand);
+        assert_eq!(terrain.get(far), Terrain::Sand);
+        assert_eq!(terrain.get(Point { x: -69, y: 300 }), Terrain::Grass);
+        assert!(!terrain.insert_chunk(ChunkId::of(far), vec![Terrain::Rock; 3]));
+
+        let mut state = empty_state();
+        let alice = spawn_player(&mut state, "Alice".into());
+        let start = state.entities.get(&alice).expect("alice").position;
+        let east = path::step(start, Direction::Right);
+        let west = path::step(start, Direction::Left);
+        state.terrain.set(east, Terrain::Rock);
+        state.terrain.set(west, Terrain::Water);
+        apply(&mut state, alice, &GameAction::Move(Direction::Right));
+        apply(&mut state, alice, &GameAction::Move(Direction::Left));
+        assert_eq!(state.entities.get(&alice).expect("alice").position, start);
+        assert!(bridge::build(&mut state, east).is_none());
+
+        let beyond = path::step(east, Direction::Right);
+        let mut view = fov::PlayerFov::new(alice);
+        view.update(
+            &state.entities,
+            &fov::OpaqueSet::of(&state.entities),
+            &state.terrain,
+        );
+        assert!(view.mask.contains(east));
+        assert!(!view.mask.contains(beyond));
+
+        assert_eq!(collision::cost_at(&state, west), None);
+        bridge::build(&mut state, west).expect("open water");
+        assert_eq!(collision::cost_at(&state, west), Some(bridge::BRIDGE_COST));
+        apply(&mut state, alice, &GameAction::Move(Direction::Left));
+        assert_eq!(state.entities.get(&alice).expect("alice").position, west);
+    }
+
+    #[test]
+    fn generated_terrain_keeps_trees_on_grass_and_is_saved() {
+        let config = worldgen::WorldGenConfig {
+            width: 200,
+            height: 200,
+            tree_density: 1000,
+            ..Default::default()
+        };
+        let state = worldgen::generate("w".into(), 2, &config);
+        let count = |terrain| {
+            (0..200)
+                .flat_map(|y| (0..200).map(move |x| Point { x, y }))
+                .filter(|p| state.terrain.get(*p) == terrain)
+                .count()
+        };
+        for terrain in [Terrain::Water, Terrain::Rock, Terrain::Sand] {
+            assert!(count(terrain) > 0, "{terrain:?}");
+        }
+        assert!(
+            state
+                .entities
+                .values()
+                .all(|e| state.terrain.get(e.position) == Terrain::Grass)
+        );
+        assert!(
+            worldgen::preview(2, &config, 200)
+                .terrain
+                .contains(&Terrain::Water)
+        );
+
+        let dir = std::env::temp_dir().join(format!("gamik-terrain-{}", WorldId::generate()));
+        std::fs::create_dir_all(&dir).expect("temp dir is writable");
+        let path = dir.join("w.world");
+        save_to_path(&state, &path).expect("saves");
+        assert_eq!(load_from_file(&path).expect("loads").terrain, state.terrain);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    // -- get_playable_entities -----------------------------------------------
+
+    #[test]
+    fn rosters_keep_characters_to_their_player() {
+        let (alice, bob) = (PlayerKey(1), PlayerKey(2));
+        let mut state = GameState::create_test_world("test".into());
+        let first = spawn_player(&mut state, "First".into());
+        assert_eq!(roster::claim(&mut state.rosters, alice, first), Ok(()));
+        assert_eq!(
+            roster::claim(&mut state.rosters, bob, first),
+            Err("that character belongs to another player".into())
+        );
+
+        for i in 1..roster::MAX_ROSTER_SIZE {
+            let eid = spawn_player(&mut state, format!("Alt {i}"));
+            assert_eq!(roster::claim(&mut state.rosters, alice, eid), Ok(()));
+        }
+        let extra = spawn_player(&mut state, "Extra".into());
+        assert!(roster::claim(&mut state.rosters, alice, extra).is_err());
+        assert_eq!(roster::owner_of(&state.rosters, extra), None);
+
+        // Going back to an owned character makes it active again.
+        assert_eq!(roster::claim(&mut state.rosters, alice, first), Ok(()));
+        assert_eq!(
+            state.rosters.get(&alice).expect("alice").active,
+            Some(first)
+        );
+        assert_eq!(
+            state.rosters.get(&alice).expect("alice").characters.len(),
+            roster::MAX_ROSTER_SIZE
+        );
+
+        apply(&mut state, first, &GameAction::Move(Direction::Left));
+        apply(&mut state, extra, &GameAction::Move(Direction::Left));
+        let stats = |eid| {
+            state
+                .rosters
+                .get(&alice)
+                .expect("alice")
+                .characters
+                .iter()
+                .find(|(id, _)| *id == eid)
+                .map(|(_, stats)| stats.steps)
+        };
+        assert_eq!(stats(first), Some(1));
+        assert_eq!(stats(extra), None);
+    }
+
+    #[test]
+    fn characters_carry_over_under_the_new_worlds_rules() {
+        let mut old = GameState::create_test_world("old".into());
+        let hero = spawn_player(&mut old, "Hero".into());
+        if let Some(entity) = old.entity_mut(hero) {
+            entity.tags.insert("veteran").ok();
+            entity.metadata.set("title", "Dragonslayer").ok();
+            entity.inventory.add(explosion::BOMB, 5);
+            entity.inventory.add("moonstone", 1);
+            entity.position = Point { x: 40, y: 40 };
+        }
+        let text = transfer::export(&old, hero)
+            .and_then(|c| c.to_ron())
+            .unwrap_or_default();
+        let character = PortableCharacter::from_ron(&text)
+            .unwrap_or_else(|e| panic!("export does not parse: {e}\n{text}"));
+
+        let mut new = GameState::create_test_world("new".into());
+        new.transfer.max_item_count = 30;
+        new.transfer.banned_items.insert(explosion::BOMB.to_owned());
+        let registry = ContentRegistry::builtin();
+        let imported = transfer::import(&mut new, &registry, &character);
+        let Ok(imported) = imported else {
+            panic!("import failed: {imported:?}");
+        };
+        assert_eq!(
+            imported.stripped,
+            vec![
+                (explosion::BOMB.to_owned(), 5),
+                ("coin".to_owned(), 20),
+                ("moonstone".to_owned(), 1)
+            ]
+        );
+        let entity = new.entities.get(&imported.entity_id).expect("entity");
+        assert_eq!(entity.name.as_deref(), Some("Hero"));
+        assert_eq!(entity.position, SPAWN_POINT);
+        assert!(entity.tags.contains("veteran"));
+        assert_eq!(entity.inventory.count(explosion::BOMB), 0);
+        assert_eq!(entity.inventory.count("coin"), 30);
+
+        new.transfer.allow_import = false;
+        let before = new.entities.len();
+        assert!(transfer::import(&mut new, &registry, &character).is_err());
+        assert_eq!(new.entities.len(), before);
+        old.transfer.allow_export = false;
+        assert!(transfer::export(&old, hero).is_err());
+    }
 
     #[test]
     fn get_playable_entities_returns_only_players() {
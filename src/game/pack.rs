@@ -0,0 +1,75 @@
+//! Content packs: sets of content files a server runs with.
+//!
+//! Each pack is known by a [`PackManifest`], its ID and a hash of its files.
+//! Clients name the packs they have when joining, and servers turn away
+//! clients missing any of theirs (see [`missing`]), rather than send them
+//! entities and items they could not look up or draw. The content embedded
+//! in the binary is the [`BUILTIN`] pack, and so far the only one.
+
+use super::content::Sources;
+use super::{FNV_OFFSET, fnv1a, fnv1a_str};
+
+use bitcode::{Decode, Encode};
+use std::fmt;
+
+/// ID of the content embedded in the binary.
+pub const BUILTIN: &str = "builtin";
+
+/// Most packs a client may name when joining.
+pub const MAX_PACKS: usize = 64;
+
+/// Names one version of a content pack.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Encode, Decode)]
+pub struct PackManifest {
+    pub id: String,
+    /// Hash of the pack's files; two builds with the same files agree on it.
+    pub hash: u64,
+}
+
+impl PackManifest {
+    /// The manifest of pack `id`, made of `sources`.
+    pub fn of(id: &str, sources: Sources<'_>) -> Self {
+        let hash = sources
+            .files()
+            .into_iter()
+            .fold(FNV_OFFSET, |hash, (_, source)| fnv1a_str(hash, source));
+        Self {
+            id: id.to_owned(),
+            hash,
+        }
+    }
+}
+
+impl fmt::Display for PackManifest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({:016x})", self.id, self.hash)
+    }
+}
+
+/// Hash of a pack file, or any other blob sent to clients; the same on
+/// every platform.
+pub fn hash_bytes(bytes: &[u8]) -> u64 {
+    fnv1a(FNV_OFFSET, bytes)
+}
+
+/// The packs this build runs with.
+pub fn active() -> Vec<PackManifest> {
+    vec![PackManifest::of(BUILTIN, Sources::BUILTIN)]
+}
+
+/// Packs in `required` that are not in `available`, in order. A pack with
+/// the right ID but other files counts as missing.
+pub fn missing(required: &[PackManifest], available: &[PackManifest]) -> Vec<PackManifest> {
+    required
+        .iter()
+        .filter(|pack| !available.contains(pack))
+        .cloned()
+        .collect()
+}
+
+/// Tell a player which packs they need, e.g. "missing content packs:
+/// builtin (00ab…)".
+pub fn describe_missing(missing: &[PackManifest]) -> String {
+    let packs: Vec<String> = missing.iter().map(ToString::to_string).collect();
+    format!("missing content packs: {}", packs.join(", "))
+}
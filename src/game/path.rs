@@ -0,0 +1,163 @@
+//! Grid pathfinding.
+//!
+//! Paths are found with a uniform-cost search over the four cardinal
+//! directions, where each tile has its own cost to step onto. Ties are
+//! expanded in the order tiles were found, so the same world always gives
+//! the same path, and with every cost at 1 the search is a plain
+//! breadth-first search. Searches are capped at [`MAX_SEARCH_NODES`]
+//! tiles; when the goal is unreachable or too far, the path leads to the
+//! closest tile the search found instead.
+//!
+//! [`find`] plans a whole route instead, with an A* search guided by the
+//! distance left, and gives up when the goal cannot be reached.
+
+use super::math::manhattan;
+use super::{Direction, Point};
+
+use rustc_hash::FxHashMap;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// Most tiles a single search visits.
+pub const MAX_SEARCH_NODES: usize = 4096;
+
+/// The four ways to step, in the order searches try them.
+pub const DIRECTIONS: [Direction; 4] = [
+    Direction::Up,
+    Direction::Down,
+    Direction::Left,
+    Direction::Right,
+];
+
+/// The tile one step from `point` in `direction`.
+pub fn step(point: Point, direction: Direction) -> Point {
+    let (dx, dy) = direction.delta();
+    Point {
+        x: point.x.saturating_add(dx),
+        y: point.y.saturating_add(dy),
+    }
+}
+
+/// The direction that best points from `from` to `to`: along the longer
+/// axis, horizontally on a tie. `None` if they are the same tile.
+pub fn direction_towards(from: Point, to: Point) -> Option<Direction> {
+    let (dx, dy) = (to.x - from.x, to.y - from.y);
+    if dx == 0 && dy == 0 {
+        None
+    } else if dx.abs() >= dy.abs() {
+        Some(if dx > 0 {
+            Direction::Right
+        } else {
+            Direction::Left
+        })
+    } else {
+        Some(if dy > 0 {
+            Direction::Down
+        } else {
+            Direction::Up
+        })
+    }
+}
+
+/// First step of a cheapest path from `from` towards `to`. `cost` gives
+/// the cost of stepping onto a tile, or `None` if it cannot be entered.
+///
+/// Returns `None` if `from` is already the closest reachable tile to `to`.
+pub fn first_step(
+    from: Point,
+    to: Point,
+    cost: impl Fn(Point) -> Option<u32>,
+) -> Option<Direction> {
+    // Each reached tile with the tile it was reached from, the step taken
+    // and the total cost so far.
+    let mut came_from: FxHashMap<Point, (Point, Direction, u32)> = FxHashMap::default();
+    // Tiles to expand, cheapest first, then in the order they were found.
+    let mut queue = BinaryHeap::from([Reverse((0, 0, from.x, from.y))]);
+    let mut found = 0_u64;
+    let mut best = (manhattan(from, to), from);
+
+    while let Some(Reverse((spent, _, x, y))) = queue.pop() {
+        let point = Point { x, y };
+        if point == to || came_from.len() >= MAX_SEARCH_NODES {
+            break;
+        }
+        if came_from.get(&point).is_some_and(|(_, _, c)| spent > *c) {
+            continue;
+        }
+        for direction in DIRECTIONS {
+            let next = step(point, direction);
+            let Some(total) = cost(next).map(|c| spent.saturating_add(c)) else {
+                continue;
+            };
+            if next == from || came_from.get(&next).is_some_and(|(_, _, c)| *c <= total) {
+                continue;
+            }
+            came_from.insert(next, (point, direction, total));
+            if manhattan(next, to) < best.0 {
+                best = (manhattan(next, to), next);
+            }
+            found += 1;
+            queue.push(Reverse((total, found, next.x, next.y)));
+        }
+    }
+
+    // Walk back from the best tile to the one next to `from`.
+    let mut point = best.1;
+    loop {
+        let (previous, direction, _) = *came_from.get(&point)?;
+        if previous == from {
+            return Some(direction);
+        }
+        point = previous;
+    }
+}
+
+/// A cheapest path from `from` to `to`, as the tiles stepped onto in order.
+///
+/// Empty if they are the same tile, `None` if `to` cannot be reached within
+/// [`MAX_SEARCH_NODES`] tiles. `cost` gives the cost of stepping
+/// onto a tile, at least 1, or `None` if it cannot be entered.
+pub fn find(from: Point, to: Point, cost: impl Fn(Point) -> Option<u32>) -> Option<Vec<Point>> {
+    // Each reached tile with the tile it was reached from and the total
+    // cost so far.
+    let mut came_from: FxHashMap<Point, (Point, u32)> = FxHashMap::default();
+    // Tiles to expand, lowest estimate first, then in the order they were
+    // found.
+    let mut queue = BinaryHeap::from([Reverse((manhattan(from, to), 0, 0, from.x, from.y))]);
+    let mut found = 0_u64;
+
+    while let Some(Reverse((_, spent, _, x, y))) = queue.pop() {
+        let point = Point { x, y };
+        if point == to {
+            break;
+        }
+        if came_from.len() >= MAX_SEARCH_NODES {
+            return None;
+        }
+        if came_from.get(&point).is_some_and(|(_, c)| spent > *c) {
+            continue;
+        }
+        for direction in DIRECTIONS {
+            let next = step(point, direction);
+            let Some(total) = cost(next).map(|c| spent.saturating_add(c)) else {
+                continue;
+            };
+            if next == from || came_from.get(&next).is_some_and(|(_, c)| *c <= total) {
+                continue;
+            }
+            came_from.insert(next, (point, total));
+            found += 1;
+            let estimate = total.saturating_add(manhattan(next, to));
+            queue.push(Reverse((estimate, total, found, next.x, next.y)));
+        }
+    }
+
+    let mut path = Vec::new();
+    let mut point = to;
+    while point != from {
+        path.push(point);
+        point = came_from.get(&point)?.0;
+    }
+    path.reverse();
+    Some(path)
+}
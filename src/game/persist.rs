@@ -0,0 +1,737 @@
+//! Saving, loading and checking worlds.
+//!
+//! A `.world` file starts with [`SAVE_MAGIC`] and the [`SAVE_FORMAT`] it was
+//! written in, then holds a [`SaveHeader`] followed by the encoded
+//! [`GameState`]. Saves in older formats are brought up to date as they
+//! load by [`migrate`], one [`Migration`] per format, each decoding the
+//! state as that format laid it out, and what goes wrong loading one is a
+//! [`SaveError`]. [`validate`] looks for data that decodes fine but breaks the
+//! game's invariants, and [`repair`] fixes it, so broken saves can be
+//! salvaged instead of thrown away. [`upgrade`] does both and writes a save
+//! back in the newest [`SAVE_FORMAT`], after updating the game.
+//!
+//! Saves are written to a temporary file that is then renamed over the old
+//! one, so a crash halfway through leaves the last save whole. The file
+//! replaced is kept as a backup, `<name>.world.bak1`, and older backups move
+//! down to `.bak2` and on, as many as [`set_backups`] says.
+//!
+//! Every save and load goes through the [`SaveSync`] installed with
+//! [`install_sync`], so saves can be kept in sync with a remote store; by
+//! default they stay on this machine.
+
+mod format0;
+mod format1;
+mod format2;
+mod format3;
+mod format4;
+
+use super::{EntityID, EntityType, GameState, Point, SPAWN_POINT, WorldId};
+
+use bitcode::{Decode, Encode};
+use rustc_hash::FxHashMap;
+use std::borrow::Cow;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write as _};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Largest coordinate, on either axis, that an entity may sit at. Worlds are
+/// much smaller than this; anything beyond it comes from corrupt data or an
+/// overflowing move.
+pub const MAX_COORDINATE: i32 = 1 << 20;
+
+/// Layout version of the `.world` files this build writes. Format 0 is the
+/// layout from before saves recorded theirs, and format 1 the one from
+/// before [`SAVE_MAGIC`].
+///
+/// Any change to how the state encodes, down to a field or variant added to
+/// a type saved with it, needs a new format: the old layout is kept as it
+/// was in a module of its own and given a [`Migration`] to the new one.
+pub const SAVE_FORMAT: u32 = 5;
+
+/// Bytes every `.world` file since format 2 starts with.
+pub const SAVE_MAGIC: [u8; 4] = *b"GMKW";
+
+/// Backups kept of each `.world` file, unless [`set_backups`] says
+/// otherwise.
+pub const DEFAULT_SAVE_BACKUPS: u32 = 3;
+
+/// Most backups that can be kept of each `.world` file.
+pub const MAX_SAVE_BACKUPS: u32 = 10;
+
+static SAVE_BACKUPS: AtomicU32 = AtomicU32::new(DEFAULT_SAVE_BACKUPS);
+
+/// Identity of a saved world, stored at the start of its `.world` file so it
+/// can be read without decoding the whole world.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct SaveHeader {
+    pub world_id: WorldId,
+    pub world_name: String,
+    /// [`SAVE_FORMAT`] of the build that wrote the file.
+    pub format: u32,
+}
+
+/// The header as stored since format 2, which keeps the format outside it,
+/// and as stored by format 0, which did not keep it at all.
+#[derive(Encode, Decode)]
+struct StoredHeader {
+    world_id: WorldId,
+    world_name: String,
+}
+
+/// Why a `.world` file could not be loaded.
+#[derive(Debug)]
+pub enum SaveError {
+    /// The file could not be read.
+    Io(io::Error),
+    /// The file ends before its header does.
+    TruncatedHeader,
+    /// The header does not decode.
+    BadHeader(bitcode::Error),
+    /// The file was written in this format, by a newer build.
+    TooNew(u32),
+    /// The world does not decode.
+    BadWorld(bitcode::Error),
+    /// The header names another world than the one the file holds.
+    WrongWorld,
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{e}"),
+            Self::TruncatedHeader => write!(f, "truncated save header"),
+            Self::BadHeader(e) => write!(f, "unreadable save header: {e}"),
+            Self::TooNew(format) => write!(
+                f,
+                "saved in format {format}, newer than this build's {SAVE_FORMAT}; update the game"
+            ),
+            Self::BadWorld(e) => write!(f, "unreadable world: {e}"),
+            Self::WrongWorld => write!(f, "save header does not match world"),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::BadHeader(e) | Self::BadWorld(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SaveError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<SaveError> for io::Error {
+    fn from(e: SaveError) -> Self {
+        match e {
+            SaveError::Io(e) => e,
+            e => invalid_data(e),
+        }
+    }
+}
+
+fn invalid_data(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+/// Split `bytes` at a length given by their first four, as a little-endian
+/// `u32`.
+fn split_prefixed(bytes: &[u8]) -> Result<(&[u8], &[u8]), SaveError> {
+    let (len, rest) = bytes
+        .split_first_chunk::<4>()
+        .ok_or(SaveError::TruncatedHeader)?;
+    let len = u32::from_le_bytes(*len) as usize;
+    if len > rest.len() {
+        return Err(SaveError::TruncatedHeader);
+    }
+    Ok(rest.split_at(len))
+}
+
+/// Split a `.world` file into its decoded header and the encoded state.
+///
+/// After [`SAVE_MAGIC`] and the format, as a little-endian `u32`, comes the
+/// header length, then the header. Files of formats 0 and 1 start with the
+/// header length. Files from before the header hold the state alone; they
+/// are given the header of a format 0 save, and their state is encoded as
+/// format 0 encodes it. Saves of a format newer than [`SAVE_FORMAT`] are
+/// refused.
+pub(super) fn split_header(bytes: &[u8]) -> Result<(SaveHeader, Cow<'_, [u8]>), SaveError> {
+    let (header, body) = if let Some(rest) = bytes.strip_prefix(&SAVE_MAGIC) {
+        let (format, rest) = rest
+            .split_first_chunk::<4>()
+            .ok_or(SaveError::TruncatedHeader)?;
+        let (header, body) = split_prefixed(rest)?;
+        let stored: StoredHeader = bitcode::decode(header).map_err(SaveError::BadHeader)?;
+        let header = SaveHeader {
+            world_id: stored.world_id,
+            world_name: stored.world_name,
+            format: u32::from_le_bytes(*format),
+        };
+        (header, Cow::Borrowed(body))
+    } else {
+        match split_unmarked(bytes) {
+            Ok((header, body)) => (header, Cow::Borrowed(body)),
+            Err(e) => match bitcode::decode::<format0::Headerless>(bytes) {
+                Ok(headerless) => {
+                    let (header, body) = headerless.into_format0();
+                    (header, Cow::Owned(body))
+                }
+                Err(_) => return Err(e),
+            },
+        }
+    };
+    if header.format > SAVE_FORMAT {
+        return Err(SaveError::TooNew(header.format));
+    }
+    Ok((header, body))
+}
+
+/// Split a `.world` file of format 0 or 1, which starts with the header
+/// length.
+fn split_unmarked(bytes: &[u8]) -> Result<(SaveHeader, &[u8]), SaveError> {
+    let (header, body) = split_prefixed(bytes)?;
+    let header = bitcode::decode(header)
+        .or_else(|e| {
+            bitcode::decode(header)
+                .map(|legacy: StoredHeader| SaveHeader {
+                    world_id: legacy.world_id,
+                    world_name: legacy.world_name,
+                    format: 0,
+                })
+                .or(Err(e))
+        })
+        .map_err(SaveError::BadHeader)?;
+    Ok((header, body))
+}
+
+/// Rewrites the encoded state of a save in one format as the next format
+/// encodes it.
+pub type Migration = fn(&[u8]) -> Result<Vec<u8>, SaveError>;
+
+/// The [`Migration`] out of each format older than [`SAVE_FORMAT`], by the
+/// format it starts from.
+const MIGRATIONS: [Migration; SAVE_FORMAT as usize] = [
+    format0::migrate,
+    format1::migrate,
+    format2::migrate,
+    format3::migrate,
+    format4::migrate,
+];
+
+/// Decode the state `body` of a save written in `format`, running it
+/// through every [`Migration`] from there to [`SAVE_FORMAT`] first.
+///
+/// # Errors
+/// If the format is newer than [`SAVE_FORMAT`], a migration fails, or
+/// the body does not decode.
+pub fn migrate(format: u32, body: &[u8]) -> Result<GameState, SaveError> {
+    let steps = MIGRATIONS
+        .get(format as usize..)
+        .ok_or(SaveError::TooNew(format))?;
+    if steps.is_empty() {
+        return bitcode::decode(body).map_err(SaveError::BadWorld);
+    }
+    let body = steps
+        .iter()
+        .try_fold(body.to_vec(), |body, migration| migration(&body))?;
+    bitcode::decode(&body).map_err(SaveError::BadWorld)
+}
+
+/// Saves the [`GameState`] to a `.world` file in the `worlds` directory.
+///
+/// # Errors
+/// If the file cannot be written.
+pub fn save_to_file(state: &GameState) -> io::Result<()> {
+    let worlds_dir = PathBuf::from("worlds");
+    fs::create_dir_all(&worlds_dir)?;
+    save_to_path(
+        state,
+        &worlds_dir.join(format!("{}.world", state.world_name)),
+    )
+}
+
+/// The bytes of a `.world` file holding `state`.
+///
+/// # Errors
+/// If the state cannot be encoded.
+pub fn encode(state: &GameState) -> io::Result<Vec<u8>> {
+    let encoded_header = bitcode::encode(&StoredHeader {
+        world_id: state.world_id,
+        world_name: state.world_name.clone(),
+    });
+    let header_len = u32::try_from(encoded_header.len()).map_err(invalid_data)?;
+
+    let mut encoded = SAVE_MAGIC.to_vec();
+    encoded.extend_from_slice(&SAVE_FORMAT.to_le_bytes());
+    encoded.extend_from_slice(&header_len.to_le_bytes());
+    encoded.extend_from_slice(&encoded_header);
+    encoded.extend_from_slice(&bitcode::encode(state));
+    Ok(encoded)
+}
+
+fn header_of(state: &GameState) -> SaveHeader {
+    SaveHeader {
+        world_id: state.world_id,
+        world_name: state.world_name.clone(),
+        format: SAVE_FORMAT,
+    }
+}
+
+/// Saves the [`GameState`] as a `.world` file at `file_path`, keeping the
+/// file it replaces as a backup, then hands it to the installed
+/// [`SaveSync`].
+///
+/// # Errors
+/// If the file or its backup cannot be written.
+pub fn save_to_path(state: &GameState, file_path: &Path) -> io::Result<()> {
+    write_atomically(file_path, &encode(state)?, backups())?;
+    let header = header_of(state);
+
+    sync()
+        .after_save(file_path, &header)
+        .map_err(|e| io::Error::new(e.kind(), format!("saved, but not synced: {e}")))
+}
+
+/// Keep `count` backups of each `.world` file saved from now on, up to
+/// [`MAX_SAVE_BACKUPS`]; none with 0. Backups beyond the count are deleted
+/// as their world is next saved.
+pub fn set_backups(count: u32) {
+    SAVE_BACKUPS.store(count.min(MAX_SAVE_BACKUPS), Ordering::Relaxed);
+}
+
+/// Backups kept of each `.world` file; see [`set_backups`].
+pub fn backups() -> u32 {
+    SAVE_BACKUPS.load(Ordering::Relaxed)
+}
+
+/// `path` with `suffix` after its extension.
+fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Where backup `n` of the save at `path` is kept, 1 being the newest:
+/// `worlds/forest.world.bak1` for `worlds/forest.world`.
+pub fn backup_path(path: &Path, n: u32) -> PathBuf {
+    suffixed(path, &format!(".bak{n}"))
+}
+
+/// Replace the file at `path` with `bytes`, so that a crash leaves either
+/// the old file or the new one whole: they are written to a temporary file
+/// beside it and flushed to disk, which is then renamed over it. The old
+/// file first becomes the newest of `backups` backups.
+fn write_atomically(path: &Path, bytes: &[u8], backups: u32) -> io::Result<()> {
+    let temporary = suffixed(path, ".tmp");
+    let mut file = fs::File::create(&temporary)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    drop(file);
+
+    if backups > 0 && path.exists() {
+        for n in (1..backups).rev() {
+            let older = backup_path(path, n);
+            if older.exists() {
+                fs::rename(&older, backup_path(path, n + 1))?;
+            }
+        }
+        fs::copy(path, backup_path(path, 1))?;
+    }
+    for n in backups + 1..=MAX_SAVE_BACKUPS {
+        match fs::remove_file(backup_path(path, n)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    fs::rename(&temporary, path)
+}
+
+/// Reads just the [`SaveHeader`] of a `.world` file.
+///
+/// # Errors
+/// If the file cannot be read or its header is bad.
+pub fn read_save_header(file_path: &Path) -> Result<SaveHeader, SaveError> {
+    let bytes = fs::read(file_path)?;
+    split_header(&bytes).map(|(header, _)| header)
+}
+
+/// Loads a [`GameState`] from a `.world` file of any format up to
+/// [`SAVE_FORMAT`], once the installed [`SaveSync`] had its say.
+///
+/// # Errors
+/// If the file cannot be read, is too new, or holds no world.
+pub fn load_from_file(file_path: &Path) -> Result<GameState, SaveError> {
+    sync().before_load(file_path)?;
+    let bytes = fs::read(file_path)?;
+    let (header, body) = split_header(&bytes)?;
+    let state = migrate(header.format, &body)?;
+    if state.world_id != header.world_id {
+        return Err(SaveError::WrongWorld);
+    }
+    Ok(state)
+}
+
+// ---------------------------------------------------------------------------
+// Sync hooks
+// ---------------------------------------------------------------------------
+
+/// Hooks around saving and loading `.world` files, for keeping them in sync
+/// with a remote store.
+///
+/// The local file stays the one played from; a hook
+/// only copies it out, or brings a newer copy in before it is read.
+pub trait SaveSync: Send + Sync {
+    /// Called after the world `header` describes was written to `path`.
+    ///
+    /// # Errors
+    /// If the save could not be handed on; the local file is written all
+    /// the same, and the save reports the error.
+    fn after_save(&self, path: &Path, header: &SaveHeader) -> io::Result<()> {
+        let _: (&Path, &SaveHeader) = (path, header);
+        Ok(())
+    }
+
+    /// Called before `path` is read.
+    ///
+    /// # Errors
+    /// If the local file must not be loaded, which fails the load. A store
+    /// that cannot be reached should not fail it.
+    fn before_load(&self, path: &Path) -> io::Result<()> {
+        let _: &Path = path;
+        Ok(())
+    }
+}
+
+/// The default [`SaveSync`]: saves stay on this machine.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LocalOnly;
+
+impl SaveSync for LocalOnly {}
+
+static SAVE_SYNC: OnceLock<Box<dyn SaveSync>> = OnceLock::new();
+
+/// Route every later save and load of this process through `sync`. Only
+/// one can be installed; if one already is, `sync` is handed back.
+///
+/// # Errors
+/// With `sync` if another one is already installed.
+pub fn install_sync(sync: Box<dyn SaveSync>) -> Result<(), Box<dyn SaveSync>> {
+    SAVE_SYNC.set(sync)
+}
+
+fn sync() -> &'static dyn SaveSync {
+    match SAVE_SYNC.get() {
+        Some(sync) => sync.as_ref(),
+        None => &LocalOnly,
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Validation & repair
+// ---------------------------------------------------------------------------
+
+/// Something wrong with a world's data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Issue {
+    /// Several sight-blocking entities share a tile; all but the first are
+    /// redundant.
+    StackedBlockers {
+        position: Point,
+        entities: Vec<EntityID>,
+    },
+    /// An entity lies beyond [`MAX_COORDINATE`].
+    OutOfBounds { entity: EntityID, position: Point },
+    /// The entity generator would hand out an ID that is already in use.
+    StaleEntityGenerator { next: u32, highest: u32 },
+    /// A player entity has no name.
+    UnnamedPlayer(EntityID),
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::StackedBlockers { position, entities } => write!(
+                f,
+                "{} blocking entities stacked at ({}, {})",
+                entities.len(),
+                position.x,
+                position.y
+            ),
+            Self::OutOfBounds { entity, position } => write!(
+                f,
+                "entity {} out of bounds at ({}, {})",
+                entity.0, position.x, position.y
+            ),
+            Self::StaleEntityGenerator { next, highest } => write!(
+                f,
+                "entity generator at {next} is behind the highest entity id {highest}"
+            ),
+            Self::UnnamedPlayer(eid) => write!(f, "player {} has no name", eid.0),
+        }
+    }
+}
+
+fn in_bounds(point: Point) -> bool {
+    (-MAX_COORDINATE..=MAX_COORDINATE).contains(&point.x)
+        && (-MAX_COORDINATE..=MAX_COORDINATE).contains(&point.y)
+}
+
+/// Check `state` for corrupt or inconsistent data. An empty list means the
+/// world is sound.
+pub fn validate(state: &GameState) -> Vec<Issue> {
+    let mut issues = Vec::new();
+
+    let mut blockers: FxHashMap<Point, Vec<EntityID>> = FxHashMap::default();
+    for (eid, entity) in &state.entities {
+        if entity.entity_type.blocks_sight() {
+            blockers.entry(entity.position).or_default().push(*eid);
+        }
+    }
+    // Report stacks in entity order, so the list is the same on every run.
+    for (eid, entity) in &state.entities {
+        if let Some(stack) = blockers.get(&entity.position)
+            && stack.len() > 1
+            && stack.first() == Some(eid)
+        {
+            issues.push(Issue::StackedBlockers {
+                position: entity.position,
+                entities: stack.clone(),
+            });
+        }
+    }
+
+    for (eid, entity) in &state.entities {
+        if !in_bounds(entity.position) {
+            issues.push(Issue::OutOfBounds {
+                entity: *eid,
+                position: entity.position,
+            });
+        }
+        if entity.entity_type == EntityType::Player && entity.name.is_none() {
+            issues.push(Issue::UnnamedPlayer(*eid));
+        }
+    }
+
+    if let Some(highest) = state.entities.keys().next_back()
+        && highest.0 > state.entity_gen.0
+    {
+        issues.push(Issue::StaleEntityGenerator {
+            next: state.entity_gen.0,
+            highest: highest.0,
+        });
+    }
+
+    issues
+}
+
+/// Fix every issue [`validate`] finds and return them.
+///
+/// Redundant stacked blockers are removed, stray entities are moved to the
+/// spawn point, unnamed players get a placeholder name and the entity
+/// generator is moved past the highest ID.
+pub fn repair(state: &mut GameState) -> Vec<Issue> {
+    let issues = validate(state);
+    for issue in &issues {
+        match issue {
+            Issue::StackedBlockers { entities, .. } => {
+                for eid in entities.iter().skip(1) {
+                    state.remove_entity(*eid);
+                }
+            }
+            Issue::OutOfBounds { entity, .. } => {
+                state.place_entity(*entity, SPAWN_POINT);
+            }
+            Issue::StaleEntityGenerator { highest, .. } => {
+                state.entity_gen.0 = *highest;
+            }
+            Issue::UnnamedPlayer(eid) => {
+                if let Some(entity) = state.entity_mut(*eid) {
+                    entity.name = Some(format!("Player {}", eid.0));
+                }
+            }
+        }
+    }
+    issues
+}
+
+// ---------------------------------------------------------------------------
+// Upgrade
+// ---------------------------------------------------------------------------
+
+/// What [`upgrade`] did to a save.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Upgrade {
+    /// [`SAVE_FORMAT`] the save was in.
+    pub from_format: u32,
+    /// What [`repair`] fixed.
+    pub repaired: Vec<Issue>,
+    /// Why the world can no longer be extended from its seed, if it was
+    /// generated by another version; see
+    /// [`GenManifest::check`](super::GenManifest::check).
+    pub generator: Option<String>,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+impl fmt::Display for Upgrade {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "format {} → {SAVE_FORMAT}, {} issue(s) repaired, {} → {} bytes",
+            self.from_format,
+            self.repaired.len(),
+            self.bytes_before,
+            self.bytes_after
+        )?;
+        for issue in &self.repaired {
+            write!(f, "\n  fixed: {issue}")?;
+        }
+        if let Some(note) = &self.generator {
+            write!(f, "\n  note: {note}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Bring the save at `path` up to date.
+///
+/// It is loaded, whatever its format, [`repair`]ed and written to `out` in
+/// [`SAVE_FORMAT`], checking that the new file loads back the same. Nothing derived from the world is saved,
+/// as indexes are built when it loads, so there is nothing else to rebuild.
+///
+/// # Errors
+/// If the save does not load, the new file cannot be written, or it
+/// does not load back the same.
+pub fn upgrade(path: &Path, out: &Path) -> io::Result<Upgrade> {
+    let bytes_before = fs::metadata(path)?.len();
+    let from_format = read_save_header(path)?.format;
+    let mut state = load_from_file(path)?;
+    let repaired = repair(&mut state);
+    let generator = state.gen_manifest().and_then(|m| m.check().err());
+    save_to_path(&state, out)?;
+    if load_from_file(out)? != state {
+        return Err(invalid_data(
+            "the upgraded save does not load back the same",
+        ));
+    }
+    Ok(Upgrade {
+        from_format,
+        repaired,
+        generator,
+        bytes_before,
+        bytes_after: fs::metadata(out)?.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::combat::{self, Health};
+    use crate::game::{ChunkId, Terrain};
+
+    #[test]
+    fn saves_of_every_format_load_from_what_their_builds_wrote() {
+        let load = |bytes: &[u8]| {
+            let (header, body) = split_header(bytes).expect("valid header");
+            let state = migrate(header.format, &body).expect("migrates");
+            assert_eq!(header.world_name, "Old Oak");
+            assert_eq!(state.world_id, header.world_id);
+            assert_eq!(state.tick, 42);
+            assert_eq!(state.entities.len(), 7);
+            assert_eq!(validate(&state), []);
+            let ayla = state
+                .entities
+                .values()
+                .find(|e| e.name.as_deref() == Some("Ayla"))
+                .expect("Ayla was saved");
+            assert_eq!(ayla.entity_type, EntityType::Player);
+            assert_eq!(ayla.health, Some(Health::new(combat::PLAYER_HEALTH)));
+            (header.format, state)
+        };
+
+        let (format, state) = load(include_bytes!("fixtures/format0.world"));
+        assert_eq!(format, 0);
+        assert!(state.generation.is_none() && state.turns.is_none());
+
+        let (format, state) = load(include_bytes!("fixtures/format1.world"));
+        assert_eq!(format, 1);
+        let config = state.gen_manifest().map(|m| m.config.clone());
+        assert_eq!(config.as_ref().map(|c| c.width), Some(16));
+        assert_eq!(
+            config.map(|c| (c.river_chance, c.lake_chance)),
+            Some((0, 0))
+        );
+
+        let (format, mut state) = load(include_bytes!("fixtures/format2.world"));
+        assert_eq!(format, 2);
+        let turns = state.turns.as_mut().expect("runs in turns");
+        let actor = turns.next_actor(&state.entities).expect("Ayla acts");
+        assert!(!turns.is_waiting(actor));
+
+        let (format, state) = load(include_bytes!("fixtures/format3.world"));
+        assert_eq!(format, 3);
+        let ayla = state
+            .entities
+            .values()
+            .find(|e| e.name.as_deref() == Some("Ayla"));
+        let inventory = &ayla.expect("Ayla was saved").inventory;
+        assert_eq!(inventory.count("bread"), 23);
+        assert_eq!(inventory.split_stacks().count(), 0);
+
+        let (format, state) = load(include_bytes!("fixtures/format4.world"));
+        assert_eq!(format, 4);
+        let (rock, water) = (Point { x: 3, y: 3 }, Point { x: 170, y: 170 });
+        assert_eq!(state.terrain.get(rock), Terrain::Rock, "loaded, kept");
+        assert_eq!(state.terrain.get(water), Terrain::Grass, "unloaded, moved");
+        let chunks = state.chunks.as_ref().expect("infinite");
+        let stored = chunks.stored_terrain(ChunkId::of(water));
+        assert!(stored.is_some_and(|tiles| tiles.contains(&Terrain::Water)));
+        assert_eq!(chunks.stored_entities().count(), 1);
+    }
+
+    #[test]
+    fn saves_that_do_not_decode_in_their_format_are_refused() {
+        let bytes = include_bytes!("fixtures/format4.world");
+        let (header, body) = split_header(bytes).expect("valid header");
+        for format in 0..SAVE_FORMAT {
+            if format != header.format {
+                assert!(migrate(format, &body).is_err(), "read as format {format}");
+            }
+        }
+        assert!(matches!(
+            migrate(SAVE_FORMAT + 1, &body),
+            Err(SaveError::TooNew(_))
+        ));
+        let cut = body.get(..body.len() / 2).unwrap_or_default();
+        assert!(matches!(
+            migrate(header.format, cut),
+            Err(SaveError::BadWorld(_))
+        ));
+    }
+
+    #[test]
+    fn saves_from_before_the_header_load_as_format_0() {
+        let bytes = include_bytes!("fixtures/headerless.world");
+        let (header, body) = split_header(bytes).expect("read as format 0");
+        assert_eq!(header.format, 0);
+        assert_eq!(header.world_name, "Old Oak");
+        let state = migrate(header.format, &body).expect("migrates");
+        assert_eq!(state.world_id, header.world_id);
+        assert_eq!((state.tick, state.entities.len()), (0, 7));
+        assert_eq!(validate(&state), []);
+        // The id made up for the world stays until it is saved with one.
+        assert_eq!(split_header(bytes).expect("read again").0, header);
+
+        assert!(split_header(bytes.get(..20).unwrap_or_default()).is_err());
+    }
+}
@@ -0,0 +1,100 @@
+//! The state as format 0 saves hold it: entities with a position, a name
+//! and a type, player or tree, and the world's identity and tick. Before
+//! that, saves held only the state, without identity or tick.
+
+use super::{SaveError, SaveHeader, format1};
+use crate::game::combat::{self, Health};
+use crate::game::{
+    EntityGenerator, EntityID, FNV_OFFSET, Point, Rosters, TransferRules, WorldId, fnv1a,
+};
+
+use bitcode::{Decode, Encode};
+use rustc_hash::FxHashMap;
+
+#[derive(Encode, Decode)]
+pub(super) struct GameState {
+    pub(super) entity_gen: EntityGenerator,
+    pub(super) entities: FxHashMap<EntityID, Entity>,
+    pub(super) world_id: WorldId,
+    pub(super) world_name: String,
+    pub(super) tick: u64,
+}
+
+/// A save from before the header.
+#[derive(Encode, Decode)]
+pub(super) struct Headerless {
+    entity_gen: EntityGenerator,
+    entities: FxHashMap<EntityID, Entity>,
+    world_name: String,
+}
+
+impl Headerless {
+    /// The header of the save as format 0, and its state as format 0
+    /// encodes it, at tick 0. The world's id is made from its name, so it
+    /// is the same each time the file is read until it is saved again.
+    pub(super) fn into_format0(self) -> (SaveHeader, Vec<u8>) {
+        let world_id = WorldId(u128::from(fnv1a(FNV_OFFSET, self.world_name.as_bytes())));
+        let header = SaveHeader {
+            world_id,
+            world_name: self.world_name.clone(),
+            format: 0,
+        };
+        let state = GameState {
+            entity_gen: self.entity_gen,
+            entities: self.entities,
+            world_id,
+            world_name: self.world_name,
+            tick: 0,
+        };
+        (header, bitcode::encode(&state))
+    }
+}
+
+#[derive(Encode, Decode)]
+pub(super) struct Entity {
+    pub(super) position: Point,
+    pub(super) name: Option<String>,
+    pub(super) entity_type: EntityType,
+}
+
+#[derive(Encode, Decode)]
+pub(super) enum EntityType {
+    Player,
+    Tree,
+}
+
+/// Rewrite a format 0 state as format 1 encodes it. Entities get no tags,
+/// items or orders, players full health, and the world no events, rosters,
+/// transfer rules or generator.
+pub(super) fn migrate(body: &[u8]) -> Result<Vec<u8>, SaveError> {
+    let old: GameState = bitcode::decode(body).map_err(SaveError::BadWorld)?;
+    let entities = old
+        .entities
+        .into_iter()
+        .map(|(eid, entity)| {
+            let (entity_type, health) = match entity.entity_type {
+                EntityType::Player => (
+                    format1::EntityType::Player,
+                    Some(Health::new(combat::PLAYER_HEALTH)),
+                ),
+                EntityType::Tree => (format1::EntityType::Tree, None),
+            };
+            let entity = format1::Entity {
+                health,
+                ..format1::Entity::new(entity_type, entity.position, entity.name)
+            };
+            (eid, entity)
+        })
+        .collect();
+    Ok(bitcode::encode(&format1::GameState {
+        entity_gen: old.entity_gen,
+        entities,
+        world_id: old.world_id,
+        world_name: old.world_name,
+        tick: old.tick,
+        world_events: Vec::new(),
+        rosters: Rosters::new(),
+        transfer: TransferRules::default(),
+        generation: None,
+    }))
+}
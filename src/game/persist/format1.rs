@@ -0,0 +1,171 @@
+//! The state as format 1 saves hold it, from before combat stats, colors,
+//! creature behaviors, turns, chunks and terrain, and before rivers and
+//! lakes could be generated.
+
+use super::format3::{self, Inventory};
+use super::{SaveError, format2};
+use crate::game::worldgen::{GenManifest, WorldGenConfig};
+use crate::game::{
+    ActionQueue, ActiveEvent, Conversation, EntityGenerator, EntityID, Follow, Health, Metadata,
+    Point, Reputation, Rosters, Shop, Tags, TileMap, TransferRules, WorldId,
+};
+
+use bitcode::{Decode, Encode};
+use std::collections::BTreeMap;
+
+#[derive(Encode, Decode)]
+pub(super) struct GameState {
+    pub(super) entity_gen: EntityGenerator,
+    pub(super) entities: BTreeMap<EntityID, Entity>,
+    pub(super) world_id: WorldId,
+    pub(super) world_name: String,
+    pub(super) tick: u64,
+    pub(super) world_events: Vec<ActiveEvent>,
+    pub(super) rosters: Rosters,
+    pub(super) transfer: TransferRules,
+    pub(super) generation: Option<Manifest>,
+}
+
+#[derive(Encode, Decode)]
+pub(super) struct Entity {
+    pub(super) position: Point,
+    pub(super) name: Option<String>,
+    pub(super) entity_type: EntityType,
+    pub(super) tags: Tags,
+    pub(super) metadata: Metadata,
+    pub(super) faction: Option<String>,
+    pub(super) reputation: Reputation,
+    pub(super) dialogue: Option<String>,
+    pub(super) conversation: Option<Conversation>,
+    pub(super) inventory: Inventory,
+    pub(super) shop: Option<Shop>,
+    pub(super) owner: Option<EntityID>,
+    pub(super) destination: Option<Point>,
+    pub(super) follow: Option<Follow>,
+    pub(super) health: Option<Health>,
+    pub(super) queue: ActionQueue,
+}
+
+impl Entity {
+    pub(super) fn new(entity_type: EntityType, position: Point, name: Option<String>) -> Self {
+        Self {
+            position,
+            name,
+            entity_type,
+            tags: Tags::default(),
+            metadata: Metadata::default(),
+            faction: None,
+            reputation: Reputation::default(),
+            dialogue: None,
+            conversation: None,
+            inventory: Inventory::default(),
+            shop: None,
+            owner: None,
+            destination: None,
+            follow: None,
+            health: None,
+            queue: ActionQueue::default(),
+        }
+    }
+}
+
+#[derive(Encode, Decode)]
+pub(super) enum EntityType {
+    Player,
+    Tree,
+    Npc,
+    Stump,
+    Rubble,
+    Water,
+    Bridge,
+}
+
+#[derive(Encode, Decode)]
+pub(super) struct Manifest {
+    version: u32,
+    seed: u64,
+    config: GenConfig,
+}
+
+#[derive(Encode, Decode)]
+struct GenConfig {
+    width: u32,
+    height: u32,
+    tree_density: u32,
+    grove_size: u32,
+    grove_chance: u32,
+    grove_density: u32,
+    clearing_radius: u32,
+}
+
+/// Rewrite a format 1 state as format 2 encodes it. Entities get no combat
+/// stats, color or behavior, the world runs in real time and has no edge
+/// or terrain, and a generator config asks for no rivers or lakes, which
+/// the generator did not make yet.
+pub(super) fn migrate(body: &[u8]) -> Result<Vec<u8>, SaveError> {
+    use crate::game::EntityType as Kind;
+
+    let old: GameState = bitcode::decode(body).map_err(SaveError::BadWorld)?;
+    let entities = old
+        .entities
+        .into_iter()
+        .map(|(eid, entity)| {
+            let entity_type = match entity.entity_type {
+                EntityType::Player => Kind::Player,
+                EntityType::Tree => Kind::Tree,
+                EntityType::Npc => Kind::Npc,
+                EntityType::Stump => Kind::Stump,
+                EntityType::Rubble => Kind::Rubble,
+                EntityType::Water => Kind::Water,
+                EntityType::Bridge => Kind::Bridge,
+            };
+            let entity = format3::Entity {
+                tags: entity.tags,
+                metadata: entity.metadata,
+                faction: entity.faction,
+                reputation: entity.reputation,
+                dialogue: entity.dialogue,
+                conversation: entity.conversation,
+                inventory: entity.inventory,
+                shop: entity.shop,
+                owner: entity.owner,
+                destination: entity.destination,
+                follow: entity.follow,
+                health: entity.health,
+                queue: entity.queue,
+                ..format3::Entity::new(entity_type, entity.position, entity.name)
+            };
+            (eid, entity)
+        })
+        .collect();
+    let generation = old.generation.map(|manifest| GenManifest {
+        version: manifest.version,
+        seed: manifest.seed,
+        config: WorldGenConfig {
+            width: manifest.config.width,
+            height: manifest.config.height,
+            tree_density: manifest.config.tree_density,
+            grove_size: manifest.config.grove_size,
+            grove_chance: manifest.config.grove_chance,
+            grove_density: manifest.config.grove_density,
+            clearing_radius: manifest.config.clearing_radius,
+            river_chance: 0,
+            lake_chance: 0,
+            infinite: false,
+        },
+    });
+    Ok(bitcode::encode(&format2::GameState {
+        entity_gen: old.entity_gen,
+        entities,
+        world_id: old.world_id,
+        world_name: old.world_name,
+        tick: old.tick,
+        world_events: old.world_events,
+        rosters: old.rosters,
+        transfer: old.transfer,
+        generation,
+        turns: None,
+        chunks: None,
+        terrain: TileMap::default(),
+    }))
+}
@@ -0,0 +1,54 @@
+//! The state as format 2 saves hold it, from before actors could wait out
+//! their turns.
+
+use super::{SaveError, format3};
+use crate::game::{
+    ActiveEvent, EntityGenerator, EntityID, GenManifest, Rosters, Scheduler, TileMap,
+    TransferRules, WorldId,
+};
+
+use bitcode::{Decode, Encode};
+use std::collections::BTreeMap;
+
+#[derive(Encode, Decode)]
+pub(super) struct GameState {
+    pub(super) entity_gen: EntityGenerator,
+    pub(super) entities: BTreeMap<EntityID, format3::Entity>,
+    pub(super) world_id: WorldId,
+    pub(super) world_name: String,
+    pub(super) tick: u64,
+    pub(super) world_events: Vec<ActiveEvent>,
+    pub(super) rosters: Rosters,
+    pub(super) transfer: TransferRules,
+    pub(super) generation: Option<GenManifest>,
+    pub(super) turns: Option<Turns>,
+    pub(super) chunks: Option<format3::Chunks>,
+    pub(super) terrain: TileMap,
+}
+
+#[derive(Encode, Decode)]
+pub(super) struct Turns {
+    energy: BTreeMap<EntityID, u32>,
+    elapsed: u64,
+}
+
+/// Rewrite a format 2 state as format 3 encodes it: no one is waiting.
+pub(super) fn migrate(body: &[u8]) -> Result<Vec<u8>, SaveError> {
+    let old: GameState = bitcode::decode(body).map_err(SaveError::BadWorld)?;
+    Ok(bitcode::encode(&format3::GameState {
+        entity_gen: old.entity_gen,
+        entities: old.entities,
+        world_id: old.world_id,
+        world_name: old.world_name,
+        tick: old.tick,
+        world_events: old.world_events,
+        rosters: old.rosters,
+        transfer: old.transfer,
+        generation: old.generation,
+        turns: old
+            .turns
+            .map(|turns| Scheduler::with_energy(turns.energy, turns.elapsed)),
+        chunks: old.chunks,
+        terrain: old.terrain,
+    }))
+}
@@ -0,0 +1,157 @@
+//! The state as format 3 saves hold it, from before stacks could be split
+//! off by hand.
+
+use super::{SaveError, format4};
+use crate::game::{
+    ActionQueue, ActiveEvent, AiBehavior, ChunkId, CombatStats, Conversation, EntityGenerator,
+    EntityID, EntityType, Follow, GenManifest, Health, Metadata, PlayerColor, Point, Reputation,
+    Rosters, Scheduler, Shop, Tags, TileMap, TransferRules, WorldId,
+};
+
+use bitcode::{Decode, Encode};
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Encode, Decode)]
+pub(super) struct GameState {
+    pub(super) entity_gen: EntityGenerator,
+    pub(super) entities: BTreeMap<EntityID, Entity>,
+    pub(super) world_id: WorldId,
+    pub(super) world_name: String,
+    pub(super) tick: u64,
+    pub(super) world_events: Vec<ActiveEvent>,
+    pub(super) rosters: Rosters,
+    pub(super) transfer: TransferRules,
+    pub(super) generation: Option<GenManifest>,
+    pub(super) turns: Option<Scheduler>,
+    pub(super) chunks: Option<Chunks>,
+    pub(super) terrain: TileMap,
+}
+
+#[derive(Encode, Decode)]
+pub(super) struct Entity {
+    pub(super) position: Point,
+    pub(super) name: Option<String>,
+    pub(super) entity_type: EntityType,
+    pub(super) tags: Tags,
+    pub(super) metadata: Metadata,
+    pub(super) faction: Option<String>,
+    pub(super) reputation: Reputation,
+    pub(super) dialogue: Option<String>,
+    pub(super) conversation: Option<Conversation>,
+    pub(super) inventory: Inventory,
+    pub(super) shop: Option<Shop>,
+    pub(super) owner: Option<EntityID>,
+    pub(super) destination: Option<Point>,
+    pub(super) blocked_since: Option<u64>,
+    pub(super) follow: Option<Follow>,
+    pub(super) health: Option<Health>,
+    pub(super) combat: CombatStats,
+    pub(super) queue: ActionQueue,
+    pub(super) color: Option<PlayerColor>,
+    pub(super) ai: Option<AiBehavior>,
+}
+
+impl Entity {
+    pub(super) fn new(entity_type: EntityType, position: Point, name: Option<String>) -> Self {
+        Self {
+            position,
+            name,
+            entity_type,
+            tags: Tags::default(),
+            metadata: Metadata::default(),
+            faction: None,
+            reputation: Reputation::default(),
+            dialogue: None,
+            conversation: None,
+            inventory: Inventory::default(),
+            shop: None,
+            owner: None,
+            destination: None,
+            blocked_since: None,
+            follow: None,
+            health: None,
+            combat: CombatStats::default(),
+            queue: ActionQueue::default(),
+            color: None,
+            ai: None,
+        }
+    }
+}
+
+#[derive(Encode, Decode)]
+pub(super) struct Chunks {
+    loaded: BTreeSet<ChunkId>,
+    stored: BTreeMap<ChunkId, Vec<(EntityID, Entity)>>,
+}
+
+#[derive(Default, Encode, Decode)]
+pub(super) struct Inventory {
+    items: BTreeMap<String, u32>,
+    wear: BTreeMap<String, u32>,
+}
+
+/// Rewrite a format 3 state as format 4 encodes it: no stacks are split
+/// off.
+pub(super) fn migrate(body: &[u8]) -> Result<Vec<u8>, SaveError> {
+    let old: GameState = bitcode::decode(body).map_err(SaveError::BadWorld)?;
+    let upgrade_all = |entities: Vec<(EntityID, Entity)>| {
+        entities
+            .into_iter()
+            .map(|(eid, entity)| (eid, upgrade(entity)))
+            .collect()
+    };
+    let chunks = old.chunks.map(|chunks| format4::Chunks {
+        loaded: chunks.loaded,
+        stored: chunks
+            .stored
+            .into_iter()
+            .map(|(chunk, entities)| (chunk, upgrade_all(entities)))
+            .collect(),
+    });
+    Ok(bitcode::encode(&format4::GameState {
+        entity_gen: old.entity_gen,
+        entities: old
+            .entities
+            .into_iter()
+            .map(|(eid, entity)| (eid, upgrade(entity)))
+            .collect(),
+        world_id: old.world_id,
+        world_name: old.world_name,
+        tick: old.tick,
+        world_events: old.world_events,
+        rosters: old.rosters,
+        transfer: old.transfer,
+        generation: old.generation,
+        turns: old.turns,
+        chunks,
+        terrain: old.terrain,
+    }))
+}
+
+/// `entity` as format 4 encodes it.
+fn upgrade(entity: Entity) -> crate::game::Entity {
+    use crate::game::{Entity as Current, Inventory as Items};
+
+    Current {
+        position: entity.position,
+        name: entity.name,
+        entity_type: entity.entity_type,
+        tags: entity.tags,
+        metadata: entity.metadata,
+        faction: entity.faction,
+        reputation: entity.reputation,
+        dialogue: entity.dialogue,
+        conversation: entity.conversation,
+        inventory: Items::unsplit(entity.inventory.items, entity.inventory.wear),
+        shop: entity.shop,
+        owner: entity.owner,
+        destination: entity.destination,
+        blocked_since: entity.blocked_since,
+        follow: entity.follow,
+        health: entity.health,
+        combat: entity.combat,
+        queue: entity.queue,
+        color: entity.color,
+        ai: entity.ai,
+    }
+}
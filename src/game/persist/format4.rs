@@ -0,0 +1,73 @@
+//! The state as format 4 saves hold it, from before unloaded chunks took
+//! their terrain with them.
+
+use super::SaveError;
+use crate::game::{
+    ActiveEvent, ChunkId, Entity, EntityGenerator, EntityID, GenManifest, Rosters, Scheduler,
+    SpatialIndex, TileMap, TransferRules, WorldId,
+};
+
+use bitcode::{Decode, Encode};
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Encode, Decode)]
+pub(super) struct GameState {
+    pub(super) entity_gen: EntityGenerator,
+    pub(super) entities: BTreeMap<EntityID, Entity>,
+    pub(super) world_id: WorldId,
+    pub(super) world_name: String,
+    pub(super) tick: u64,
+    pub(super) world_events: Vec<ActiveEvent>,
+    pub(super) rosters: Rosters,
+    pub(super) transfer: TransferRules,
+    pub(super) generation: Option<GenManifest>,
+    pub(super) turns: Option<Scheduler>,
+    pub(super) chunks: Option<Chunks>,
+    pub(super) terrain: TileMap,
+}
+
+#[derive(Encode, Decode)]
+pub(super) struct Chunks {
+    pub(super) loaded: BTreeSet<ChunkId>,
+    pub(super) stored: BTreeMap<ChunkId, Vec<(EntityID, Entity)>>,
+}
+
+/// Rewrite a format 4 state as format 5 encodes it: the terrain of chunks
+/// that were planted and unloaded moves out of the world, to be kept with
+/// their entities.
+pub(super) fn migrate(body: &[u8]) -> Result<Vec<u8>, SaveError> {
+    use crate::game::Chunks as Current;
+
+    let old: GameState = bitcode::decode(body).map_err(SaveError::BadWorld)?;
+    let mut terrain = old.terrain;
+    let chunks = old.chunks.map(|chunks| {
+        let unloaded: Vec<ChunkId> = terrain
+            .chunks()
+            .map(|(chunk, _)| chunk)
+            .filter(|chunk| chunks.stored.contains_key(chunk))
+            .collect();
+        Current {
+            terrain: unloaded
+                .into_iter()
+                .filter_map(|chunk| Some((chunk, terrain.remove_chunk(chunk)?)))
+                .collect(),
+            loaded: chunks.loaded,
+            stored: chunks.stored,
+        }
+    });
+    Ok(bitcode::encode(&crate::game::GameState {
+        entity_gen: old.entity_gen,
+        entities: old.entities,
+        world_id: old.world_id,
+        world_name: old.world_name,
+        tick: old.tick,
+        world_events: old.world_events,
+        rosters: old.rosters,
+        transfer: old.transfer,
+        generation: old.generation,
+        turns: old.turns,
+        chunks,
+        terrain,
+        index: SpatialIndex::default(),
+    }))
+}
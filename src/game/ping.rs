@@ -0,0 +1,83 @@
+//! Pings: markers a player puts on a tile in sight for their team.
+//!
+//! A ping changes nothing in the world. [`ping`] checks the tile can be
+//! seen and reports a [`GameEvent::Pinged`]; servers pass it on to the
+//! players on the pinger's team (see [`same_team`]), and clients show it
+//! until [`PING_TICKS`] have passed.
+
+use super::fov;
+use super::world_events;
+use super::{Entity, EntityID, GameEvent, GameState, Point};
+
+use bitcode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+/// Ticks a ping stays up: six seconds.
+pub const PING_TICKS: u64 = 120;
+
+/// What a ping says about its tile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Encode, Decode, Serialize, Deserialize)]
+pub enum PingKind {
+    /// Look here.
+    Look,
+    /// Go here.
+    Go,
+    /// Danger here.
+    Danger,
+    /// Something worth picking up here.
+    Loot,
+}
+
+impl PingKind {
+    pub const ALL: [Self; 4] = [Self::Look, Self::Go, Self::Danger, Self::Loot];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Look => "Look",
+            Self::Go => "Go",
+            Self::Danger => "Danger",
+            Self::Loot => "Loot",
+        }
+    }
+}
+
+/// A ping as teammates see it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub struct Ping {
+    /// Who placed it.
+    pub from: EntityID,
+    pub pos: Point,
+    pub kind: PingKind,
+    /// Tick it was placed in.
+    pub tick: u64,
+}
+
+impl Ping {
+    /// Returns `true` once the ping should no longer be shown at `tick`.
+    pub const fn expired(&self, tick: u64) -> bool {
+        tick >= self.tick + PING_TICKS
+    }
+}
+
+/// `pinger` marks `pos` as `kind`, if it can see the tile from where it
+/// stands.
+pub fn ping(state: &GameState, pinger: EntityID, pos: Point, kind: PingKind) -> Option<GameEvent> {
+    let from = state.entities.get(&pinger)?.position;
+    let radius = world_events::view_radius(state.world_events.iter().map(|e| e.id.as_str()));
+    let is_opaque = |p| fov::blocks_sight(state, p);
+    if !fov::compute_fov(from, radius, is_opaque).contains(&pos) {
+        return None;
+    }
+    Some(GameEvent::Pinged(Ping {
+        from: pinger,
+        pos,
+        kind,
+        tick: state.tick,
+    }))
+}
+
+/// Returns `true` if `a` and `b` see each other's pings: they are of the
+/// same faction, or both of none.
+pub fn same_team(a: &Entity, b: &Entity) -> bool {
+    a.faction == b.faction
+}
@@ -0,0 +1,91 @@
+//! Simulation regions: the parts of the world simulated every tick.
+//!
+//! The world is cut into square regions of [`REGION_SIZE`] tiles. A region
+//! is active while it lies within [`ACTIVE_RADIUS`] regions of a player's
+//! character, or of an entity a player owns, such as a pet. Only entities
+//! in active regions work on their intents, walk group orders or follow
+//! their owner; the rest of the world waits, so a big world costs little
+//! beyond where players are.
+//!
+//! Which regions are active follows from the state alone, so re-running a
+//! tick gives the same result. When a region wakes, what fell due while it
+//! slept is caught up on its first tick back: channelled intents whose time
+//! ran out complete then (see [`intent::advance`](super::intent::advance)).
+
+use super::{Entity, EntityType, GameState, Point};
+
+use rustc_hash::FxHashSet;
+
+/// Width and height of a region, in tiles.
+pub const REGION_SIZE: i32 = 32;
+
+/// Regions around a player, in each direction, that stay active.
+pub const ACTIVE_RADIUS: i32 = 1;
+
+/// The region a tile is in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RegionId {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl RegionId {
+    pub const fn of(point: Point) -> Self {
+        Self {
+            x: point.x.div_euclid(REGION_SIZE),
+            y: point.y.div_euclid(REGION_SIZE),
+        }
+    }
+}
+
+/// Returns `true` if `entity` keeps the world around it going: a player's
+/// character, or an entity a player owns.
+pub fn is_anchor(entity: &Entity) -> bool {
+    entity.entity_type == EntityType::Player || entity.owner.is_some()
+}
+
+/// The regions simulated this tick.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveRegions {
+    /// `None` when the whole world is.
+    regions: Option<FxHashSet<RegionId>>,
+}
+
+impl ActiveRegions {
+    /// The regions around the players' characters and what they own.
+    pub fn of(state: &GameState) -> Self {
+        let mut regions = FxHashSet::default();
+        let anchors = state.entities.values().filter(|e| is_anchor(e));
+        for entity in anchors {
+            let center = RegionId::of(entity.position);
+            for dy in -ACTIVE_RADIUS..=ACTIVE_RADIUS {
+                for dx in -ACTIVE_RADIUS..=ACTIVE_RADIUS {
+                    regions.insert(RegionId {
+                        x: center.x.saturating_add(dx),
+                        y: center.y.saturating_add(dy),
+                    });
+                }
+            }
+        }
+        Self {
+            regions: Some(regions),
+        }
+    }
+
+    /// The whole world, for running one system on its own.
+    pub const fn everywhere() -> Self {
+        Self { regions: None }
+    }
+
+    /// Returns `true` if the tile `point` is simulated.
+    pub fn contains(&self, point: Point) -> bool {
+        self.regions
+            .as_ref()
+            .is_none_or(|regions| regions.contains(&RegionId::of(point)))
+    }
+
+    /// Number of active regions, or `None` if the whole world is.
+    pub fn count(&self) -> Option<usize> {
+        self.regions.as_ref().map(FxHashSet::len)
+    }
+}
@@ -0,0 +1,91 @@
+//! How entities are drawn.
+//!
+//! Every entity type has an [`AppearanceDef`] in `appearance.ron` with its
+//! glyph, colors, style and idle animation, so renderers look entity types
+//! up by name instead of matching on [`EntityType`]. When
+//! several entities share a tile, the one drawn is the one on the highest
+//! [`RenderLayer`], and among entities on the same layer the one with the
+//! highest ID, so stacks look the same on every client regardless of the
+//! order entities arrived in.
+
+use super::{ContentRegistry, EntityID, EntityType};
+
+use serde::Deserialize;
+
+/// Render layers, from the bottom up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+pub enum RenderLayer {
+    /// The ground itself: trees, water, stumps.
+    Terrain,
+    /// Things laid over the ground that can be walked on: rubble, bridges.
+    FloorDecal,
+    /// Items lying on the ground.
+    Item,
+    /// Players and other creatures.
+    Creature,
+    /// Short-lived effects such as blasts.
+    Effect,
+    /// Interface markers drawn over everything.
+    Marker,
+}
+
+/// Appearance of one entity type.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct AppearanceDef {
+    /// [`EntityType::name`] of the type.
+    pub entity_type: String,
+    pub layer: RenderLayer,
+    pub glyph: String,
+    /// Foreground color as `(red, green, blue)`.
+    pub fg: (u8, u8, u8),
+    /// Background color; black unless given.
+    #[serde(default)]
+    pub bg: (u8, u8, u8),
+    #[serde(default)]
+    pub style: Style,
+    #[serde(default)]
+    pub animation: Option<AnimationDef>,
+}
+
+/// How a glyph is set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Style {
+    /// Drawn in a strong weight.
+    pub bold: bool,
+    /// Drawn at half size, like the floor.
+    pub small: bool,
+    /// Brightness in percent when remembered rather than in sight.
+    pub remembered: u8,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            bold: false,
+            small: false,
+            remembered: 35,
+        }
+    }
+}
+
+/// An idle animation: glyphs shown in turn, with a brightness shimmer.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct AnimationDef {
+    pub frames: Vec<String>,
+    /// How long each frame is shown, in milliseconds.
+    pub frame_ms: u32,
+    /// Percentage by which the brightness dips over one cycle.
+    #[serde(default)]
+    pub shimmer: u8,
+}
+
+/// Stacking key of an entity: the entity with the greatest key on a tile is
+/// the one drawn.
+pub fn stack_key(
+    registry: &ContentRegistry,
+    eid: EntityID,
+    entity_type: &EntityType,
+) -> (RenderLayer, EntityID) {
+    (registry.layer(entity_type), eid)
+}
@@ -0,0 +1,265 @@
+//! Deterministic replay fixtures.
+//!
+//! A fixture is a small text file holding a world seed, a script of actions
+//! and the [`GameState::checksum`] expected after applying them:
+//!
+//! ```text
+//! # Comments start with '#'.
+//! seed 7
+//! spawn Alice
+//! move Alice up
+//! tick 3
+//! checksum 0x1234abcd5678ef00
+//! ```
+//!
+//! The world is generated from the seed with the default
+//! [`WorldGenConfig`]. The script then runs, line by line:
+//!
+//! - `spawn <name>` spawns a player;
+//! - `move <who> <up|down|left|right>` moves an entity;
+//! - `attack <who> <direction>` hits whatever is on the next tile;
+//! - `give <who> <item> <count>` puts items in an entity's inventory;
+//! - `throw <who> <item> <x> <y>` throws an item at a tile;
+//! - `turns` runs the world in turns from then on;
+//! - `tick [count]` finishes one tick, or as many as given, as the server
+//!   does after applying actions.
+//!
+//! `<who>` is the name of a player the script spawned, or an entity ID.
+//! Running the same fixture must give the same checksum on every platform,
+//! so any change to the simulation that breaks determinism (or changes
+//! behavior) shows up as a checksum mismatch.
+
+use super::worldgen::{self, WorldGenConfig};
+use super::{
+    ContentRegistry, Direction, EntityID, GameAction, GameEvent, GameState, Point, Scheduler,
+};
+
+use std::collections::BTreeMap;
+
+/// Fixtures shipped with the crate, as `(name, source)`.
+pub const FIXTURES: &[(&str, &str)] = &[
+    ("movement", include_str!("fixtures/movement.replay")),
+    ("spawning", include_str!("fixtures/spawning.replay")),
+    ("worldgen", include_str!("fixtures/worldgen.replay")),
+    ("combat", include_str!("fixtures/combat.replay")),
+    ("explosion", include_str!("fixtures/explosion.replay")),
+];
+
+/// Who a line of the script is about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Actor {
+    /// A player the script spawned, by name.
+    Named(String),
+    Id(EntityID),
+}
+
+/// A line of a fixture's script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// Apply an action as the actor, or with no actor for spawns.
+    Act(Option<Actor>, GameAction),
+    /// Put `count` of `item` in the actor's inventory.
+    Give(Actor, String, u32),
+    /// Run the world in turns from now on.
+    Turns,
+    /// Finish this many ticks.
+    Tick(u32),
+}
+
+/// A parsed replay fixture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fixture {
+    pub seed: u64,
+    pub steps: Vec<Step>,
+    pub checksum: u64,
+}
+
+impl Fixture {
+    /// Parse a fixture, reporting the first bad line.
+    ///
+    /// # Errors
+    /// With the number and reason of the first bad line.
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut seed = None;
+        let mut steps = Vec::new();
+        let mut checksum = None;
+
+        for (number, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let bad = |what: &str| format!("line {}: {what}: {line}", number + 1);
+            let number = |value: &str, what: &str| {
+                value
+                    .parse::<i64>()
+                    .map_err(|e| bad(&format!("invalid {what} ({e})")))
+            };
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let step = match words.as_slice() {
+                ["seed", value] => {
+                    seed = Some(
+                        value
+                            .parse()
+                            .map_err(|e| bad(&format!("invalid seed ({e})")))?,
+                    );
+                    continue;
+                }
+                ["checksum", value] => {
+                    let hex = value.trim_start_matches("0x");
+                    checksum = Some(
+                        u64::from_str_radix(hex, 16)
+                            .map_err(|e| bad(&format!("invalid checksum ({e})")))?,
+                    );
+                    continue;
+                }
+                ["spawn", name] => Step::Act(None, GameAction::SpawnPlayer((*name).to_owned())),
+                ["move", who, direction] => {
+                    let direction =
+                        parse_direction(direction).ok_or_else(|| bad("invalid direction"))?;
+                    Step::Act(Some(parse_actor(who)), GameAction::Move(direction))
+                }
+                ["attack", who, direction] => {
+                    let direction =
+                        parse_direction(direction).ok_or_else(|| bad("invalid direction"))?;
+                    Step::Act(Some(parse_actor(who)), GameAction::AttackToward(direction))
+                }
+                ["give", who, item, count] => {
+                    let count = u32::try_from(number(count, "count")?)
+                        .map_err(|e| bad(&format!("invalid count ({e})")))?;
+                    Step::Give(parse_actor(who), (*item).to_owned(), count)
+                }
+                ["throw", who, item, x, y] => {
+                    let coordinate = |value| {
+                        i32::try_from(number(value, "coordinate")?)
+                            .map_err(|e| bad(&format!("invalid coordinate ({e})")))
+                    };
+                    let target = Point {
+                        x: coordinate(x)?,
+                        y: coordinate(y)?,
+                    };
+                    let item = (*item).to_owned();
+                    Step::Act(Some(parse_actor(who)), GameAction::Throw { item, target })
+                }
+                ["turns"] => Step::Turns,
+                ["tick"] => Step::Tick(1),
+                ["tick", count] => Step::Tick(
+                    u32::try_from(number(count, "count")?)
+                        .map_err(|e| bad(&format!("invalid count ({e})")))?,
+                ),
+                _ => return Err(bad("unknown command")),
+            };
+            steps.push(step);
+        }
+
+        Ok(Self {
+            seed: seed.ok_or("missing seed")?,
+            steps,
+            checksum: checksum.ok_or("missing checksum")?,
+        })
+    }
+
+    /// Generate the fixture's world from its seed and run the script on
+    /// it. Lines about players the script has not spawned do nothing.
+    pub fn run(&self) -> GameState {
+        let mut state = worldgen::generate("replay".into(), self.seed, &WorldGenConfig::default());
+        let registry = ContentRegistry::builtin();
+        let mut players = BTreeMap::new();
+        let id_of = |players: &BTreeMap<String, EntityID>, actor: &Actor| match actor {
+            Actor::Named(name) => players.get(name).copied(),
+            Actor::Id(eid) => Some(*eid),
+        };
+        for step in &self.steps {
+            match step {
+                Step::Act(actor, action) => {
+                    let eid = match actor {
+                        Some(actor) => id_of(&players, actor),
+                        None => Some(EntityID(0)),
+                    };
+                    let Some(eid) = eid else {
+                        continue;
+                    };
+                    for event in super::apply(&mut state, eid, action) {
+                        if let (
+                            GameEvent::PlayerSpawned { entity_id },
+                            GameAction::SpawnPlayer(name),
+                        ) = (event, action)
+                        {
+                            players.insert(name.clone(), entity_id);
+                        }
+                    }
+                }
+                Step::Give(actor, item, count) => {
+                    if let Some(entity) =
+                        id_of(&players, actor).and_then(|eid| state.entity_mut(eid))
+                    {
+                        entity.inventory.add(item, *count);
+                    }
+                }
+                Step::Turns => state.turns = Some(Scheduler::default()),
+                Step::Tick(count) => {
+                    for _ in 0..*count {
+                        super::advance(&mut state, &registry);
+                    }
+                }
+            }
+        }
+        state
+    }
+
+    /// Run the fixture and compare the final checksum with the expected one.
+    ///
+    /// # Errors
+    /// If the checksums differ.
+    pub fn verify(&self) -> Result<(), String> {
+        let actual = self.run().checksum();
+        if actual == self.checksum {
+            Ok(())
+        } else {
+            Err(format!(
+                "checksum mismatch: expected {:#018x}, got {actual:#018x}",
+                self.checksum
+            ))
+        }
+    }
+}
+
+/// A spawned player's name, or an entity ID.
+fn parse_actor(word: &str) -> Actor {
+    word.parse().map_or_else(
+        |_| Actor::Named(word.to_owned()),
+        |id| Actor::Id(EntityID(id)),
+    )
+}
+
+fn parse_direction(word: &str) -> Option<Direction> {
+    match word {
+        "up" => Some(Direction::Up),
+        "down" => Some(Direction::Down),
+        "left" => Some(Direction::Left),
+        "right" => Some(Direction::Right),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replay_fixtures_match_their_checksums() {
+        for (name, source) in FIXTURES {
+            let fixture = Fixture::parse(source)
+                .unwrap_or_else(|e| panic!("fixture {name} does not parse: {e}"));
+            if let Err(e) = fixture.verify() {
+                panic!("fixture {name}: {e}");
+            }
+        }
+    }
+
+    #[test]
+    fn replay_parse_reports_bad_lines() {
+        let err = Fixture::parse("seed 1\nmove 1 sideways\nchecksum 0").expect_err("a bad line");
+        assert!(err.starts_with("line 2"), "{err}");
+    }
+}
@@ -0,0 +1,109 @@
+//! Deterministic random rolls.
+//!
+//! The world keeps no random state of its own. A roll is derived from the
+//! world's tick and entity counter, the entity it is for and what it is
+//! for, hashed like [`GameState::checksum`]. Re-simulating the same actions
+//! from the same state therefore rolls the same numbers on every platform,
+//! and a [`Roll`] carries the seed it came from, so whoever holds it can
+//! check the outcome with [`Roll::from_seed`].
+//!
+//! Rolls that decide something in the world go through [`rolled`] and are
+//! reported as [`GameEvent::Rolled`](super::GameEvent::Rolled), with what
+//! they were derived from. Recordings keep them, so a
+//! [`Recording::audit`](crate::net::Recording::audit) can derive each one
+//! again and tell a fair roll from a forged one. Creatures that wander roll
+//! this way to pick when and where to step; loot is meant to as well.
+
+use super::{EntityID, FNV_OFFSET, GameState, fnv1a, fnv1a_str};
+
+use bitcode::{Decode, Encode};
+
+/// One random roll: the seed and the number it gives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub struct Roll {
+    pub seed: u64,
+    pub value: u64,
+}
+
+impl Roll {
+    /// The roll a seed gives.
+    pub fn from_seed(seed: u64) -> Self {
+        Self {
+            seed,
+            value: splitmix64(seed),
+        }
+    }
+
+    /// A number in `0..n`, without modulo bias; `0` if `n` is `0`.
+    pub fn below(&self, n: u64) -> u64 {
+        ((u128::from(self.value) * u128::from(n)) >> 64) as u64
+    }
+
+    /// Returns `true` with a chance of `percent` in 100.
+    pub fn chance(&self, percent: u32) -> bool {
+        self.below(100) < u64::from(percent)
+    }
+}
+
+/// A roll that decided something, with everything it was derived from.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Rolled {
+    pub subject: EntityID,
+    pub purpose: String,
+    /// The tick it was rolled in.
+    pub tick: u64,
+    /// The world's entity counter at the time.
+    pub counter: u32,
+    /// Mixed into the seed, such as the world seed creatures roll with.
+    pub salt: u64,
+    pub roll: Roll,
+}
+
+impl Rolled {
+    /// Returns `true` if the roll is the one its inputs give.
+    pub fn is_fair(&self) -> bool {
+        derive(
+            self.tick,
+            self.counter,
+            self.subject,
+            &self.purpose,
+            self.salt,
+        ) == self.roll
+    }
+}
+
+/// Roll for `subject` in the current tick.
+///
+/// `purpose` tells the rolls of one tick apart, e.g. `"loot/0"` and `"loot/1"` for two drops; the same
+/// purpose for the same subject in the same tick rolls the same number.
+pub fn roll(state: &GameState, subject: EntityID, purpose: &str) -> Roll {
+    derive(state.tick, state.entity_gen.0, subject, purpose, 0)
+}
+
+/// Like [`roll`], with `salt` mixed into the seed, kept along with what it
+/// was derived from to report in a [`GameEvent::Rolled`](super::GameEvent::Rolled).
+pub fn rolled(state: &GameState, subject: EntityID, purpose: &str, salt: u64) -> Rolled {
+    Rolled {
+        subject,
+        purpose: purpose.to_owned(),
+        tick: state.tick,
+        counter: state.entity_gen.0,
+        salt,
+        roll: derive(state.tick, state.entity_gen.0, subject, purpose, salt),
+    }
+}
+
+fn derive(tick: u64, counter: u32, subject: EntityID, purpose: &str, salt: u64) -> Roll {
+    let mut seed = fnv1a(FNV_OFFSET, &tick.to_le_bytes());
+    seed = fnv1a(seed, &counter.to_le_bytes());
+    seed = fnv1a(seed, &subject.0.to_le_bytes());
+    Roll::from_seed(fnv1a_str(seed, purpose) ^ salt)
+}
+
+/// The output function of `SplitMix64`, spreading similar seeds apart.
+fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,178 @@
+//! Character rosters: the characters each player owns in a world.
+//!
+//! Players are told apart by a [`PlayerKey`] their client picks once and
+//! keeps. Each key owns up to [`MAX_ROSTER_SIZE`] characters in a world and
+//! plays one of them at a time. Rosters are part of the [`GameState`], so
+//! they are saved with the world, along with statistics for every
+//! character.
+//!
+//! Keys are not secret; like the server console, rosters assume players do
+//! not impersonate each other.
+//!
+//! Each key also gives its player a [`PlayerColor`], which their characters
+//! are drawn in so that two `@` can be told apart; players may pick another.
+
+use super::{EntityID, GameState};
+
+use bitcode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher as _;
+
+/// Most characters one player can own in a world.
+pub const MAX_ROSTER_SIZE: usize = 8;
+
+/// Identity of a player, kept by the client across connections.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Encode, Decode, Serialize, Deserialize,
+)]
+pub struct PlayerKey(pub u64);
+
+impl PlayerKey {
+    /// Generate a fresh, unpredictable key.
+    pub fn generate() -> Self {
+        Self(RandomState::new().hash_one(0u8))
+    }
+}
+
+/// Brightest channel a [`PlayerColor`] is lifted to at least, so that it
+/// shows on the dark map.
+pub const MIN_COLOR_BRIGHTNESS: u8 = 128;
+
+/// Color a player's characters are drawn in, as RGB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Encode, Decode, Serialize, Deserialize)]
+pub struct PlayerColor(pub [u8; 3]);
+
+impl PlayerColor {
+    /// The color of `key` until its player picks another: a bright hue
+    /// from the key, the same on every machine.
+    pub fn of(key: PlayerKey) -> Self {
+        // SplitMix64's finalizer, so that close keys get far-apart hues.
+        let mut h = key.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        h ^= h >> 31;
+        Self::from_hue((h % 360) as u16)
+    }
+
+    /// A bright, fairly saturated color of `hue`, in degrees.
+    pub fn from_hue(hue: u16) -> Self {
+        const HIGH: u16 = 255;
+        const LOW: u16 = 90;
+        let hue = hue % 360;
+        let step = (HIGH - LOW) * (hue % 60) / 60;
+        let (rising, falling) = ((LOW + step) as u8, (HIGH - step) as u8);
+        let (high, low) = (HIGH as u8, LOW as u8);
+        Self(match hue / 60 {
+            0 => [high, rising, low],
+            1 => [falling, high, low],
+            2 => [low, high, rising],
+            3 => [low, falling, high],
+            4 => [rising, low, high],
+            _ => [high, low, falling],
+        })
+    }
+
+    /// The color lightened, if need be, so that its brightest channel is
+    /// at least [`MIN_COLOR_BRIGHTNESS`].
+    pub fn legible(self) -> Self {
+        let brightest = self.0.into_iter().max().unwrap_or(0);
+        if brightest >= MIN_COLOR_BRIGHTNESS {
+            return self;
+        }
+        if brightest == 0 {
+            return Self([MIN_COLOR_BRIGHTNESS; 3]);
+        }
+        let lift =
+            |c: u8| (u16::from(c) * u16::from(MIN_COLOR_BRIGHTNESS) / u16::from(brightest)) as u8;
+        Self(self.0.map(lift))
+    }
+}
+
+/// What a character has done, for the character-select screen.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Encode, Decode)]
+pub struct CharacterStats {
+    /// Ticks the character was played for.
+    pub ticks_played: u64,
+    pub steps: u64,
+    pub attacks: u64,
+}
+
+/// The characters of one player.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Encode, Decode)]
+pub struct Roster {
+    /// Characters in the order they joined the roster.
+    pub characters: Vec<(EntityID, CharacterStats)>,
+    /// The character being played, if any.
+    pub active: Option<EntityID>,
+}
+
+impl Roster {
+    pub fn contains(&self, eid: EntityID) -> bool {
+        self.characters.iter().any(|(id, _)| *id == eid)
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.characters.len() >= MAX_ROSTER_SIZE
+    }
+}
+
+/// Every player's roster in a world.
+pub type Rosters = BTreeMap<PlayerKey, Roster>;
+
+/// The player whose roster holds `eid`.
+pub fn owner_of(rosters: &Rosters, eid: EntityID) -> Option<PlayerKey> {
+    rosters
+        .iter()
+        .find(|(_, roster)| roster.contains(eid))
+        .map(|(key, _)| *key)
+}
+
+/// Check that `key` can add another character to its roster.
+///
+/// # Errors
+/// If the roster of `key` is full.
+pub fn check_room(rosters: &Rosters, key: PlayerKey) -> Result<(), String> {
+    if rosters.get(&key).is_some_and(Roster::is_full) {
+        Err(format!(
+            "your roster is full ({MAX_ROSTER_SIZE} characters)"
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Make `eid` the active character of `key`, adding it to the roster if it
+/// belongs to no one yet.
+///
+/// # Errors
+/// If another player owns the character or the roster is full.
+pub fn claim(rosters: &mut Rosters, key: PlayerKey, eid: EntityID) -> Result<(), String> {
+    match owner_of(rosters, eid) {
+        Some(owner) if owner != key => {
+            return Err("that character belongs to another player".to_owned());
+        }
+        Some(_) => {}
+        None => {
+            check_room(rosters, key)?;
+            let roster = rosters.entry(key).or_default();
+            roster.characters.push((eid, CharacterStats::default()));
+        }
+    }
+    rosters.entry(key).or_default().active = Some(eid);
+    Ok(())
+}
+
+/// Update the statistics of `eid`, if it is on a roster.
+pub fn record(state: &mut GameState, eid: EntityID, update: impl FnOnce(&mut CharacterStats)) {
+    if let Some(stats) = state
+        .rosters
+        .values_mut()
+        .flat_map(|roster| roster.characters.iter_mut())
+        .find(|(id, _)| *id == eid)
+        .map(|(_, stats)| stats)
+    {
+        update(stats);
+    }
+}
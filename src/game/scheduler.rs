@@ -0,0 +1,300 @@
+//! Turns, for worlds that wait on their players instead of running in real
+//! time.
+//!
+//! While a world has a [`Scheduler`], every actor has an energy pool. Each
+//! tick that passes fills it by the actor's [`speed`]; whoever holds at
+//! least [`TURN_COST`] may act, the fullest first and the lowest ID on a
+//! tie, so the order never depends on who sent their action first. Acting
+//! with an action that [takes a turn](super::GameAction::takes_turn), or
+//! [`GameAction::EndTurn`](super::GameAction::EndTurn), spends the cost;
+//! actions by any other actor are ignored until their turn. Creatures run
+//! by [`ai`](super::ai) are no actors, and act once per tick that passes.
+//!
+//! Time only passes as turns use it: [`advance`](super::advance) simulates
+//! the ticks the scheduler handed out, and none while it waits on an actor.
+//!
+//! An actor can [wait](super::GameAction::Wait) a number of turns, or until
+//! something comes into view or hurts it. The scheduler then passes its
+//! turns for it, one each time [`pass_waiting`] is called, so a long wait
+//! still takes a tick per turn and whoever is watching sees it go by. Any
+//! other action that takes a turn, or a cancel, ends the wait.
+
+use super::fov::{self, FovMask};
+use super::{Entity, EntityID, EntityMap, EntityType, GameEvent, GameState, fnv1a};
+
+use bitcode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Energy spent by a turn.
+pub const TURN_COST: u32 = 100;
+
+/// Energy an actor of ordinary speed gains each tick.
+pub const NORMAL_SPEED: u32 = 25;
+
+/// Most turns a single [`Wait::Turns`] passes.
+pub const MAX_WAIT_TURNS: u32 = 100;
+
+/// How long an actor [waits](super::GameAction::Wait).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub enum Wait {
+    /// Pass this many turns, up to [`MAX_WAIT_TURNS`], the first right away.
+    Turns(u32),
+    /// Pass turns until something new comes into view or the actor is
+    /// hurt.
+    UntilInterrupted,
+}
+
+/// How a wait ended, other than by the actor doing something else.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub enum WaitEnd {
+    /// Every turn asked for passed.
+    Done,
+    /// This entity came into view.
+    Noticed(EntityID),
+    /// The actor lost health.
+    Hurt,
+    /// The world runs in real time, so there were no turns to wait.
+    NoTurns,
+}
+
+/// An actor whose turns the scheduler passes.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+struct Waiting {
+    /// Turns left to pass, or `None` until interrupted.
+    turns: Option<u32>,
+    /// The actor's health when it began.
+    health: Option<u32>,
+    /// Entities the actor has been aware of since it began.
+    known: BTreeSet<EntityID>,
+}
+
+/// Returns `true` if `entity` takes turns.
+pub fn is_actor(entity: &Entity) -> bool {
+    entity.entity_type == EntityType::Player && entity.health.as_ref().is_none_or(|h| !h.is_dead())
+}
+
+/// Energy `entity` gains each tick.
+pub fn speed(_entity: &Entity) -> u32 {
+    NORMAL_SPEED
+}
+
+/// Who acts next, by energy.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Encode, Decode)]
+pub struct Scheduler {
+    /// Energy of each actor.
+    energy: BTreeMap<EntityID, u32>,
+    /// Ticks handed out for turns that [`advance`](super::advance) has not
+    /// simulated yet.
+    elapsed: u64,
+    /// Actors waiting, and for how long.
+    waiting: BTreeMap<EntityID, Waiting>,
+}
+
+impl Scheduler {
+    /// Fold the energy, elapsed ticks and waits into `hash`, for
+    /// [`GameState::checksum`].
+    pub(super) fn checksum(&self, mut hash: u64) -> u64 {
+        for (eid, energy) in &self.energy {
+            hash = fnv1a(fnv1a(hash, &eid.0.to_le_bytes()), &energy.to_le_bytes());
+        }
+        hash = fnv1a(hash, &self.elapsed.to_le_bytes());
+        for (eid, waiting) in &self.waiting {
+            hash = fnv1a(hash, &eid.0.to_le_bytes());
+            hash = fnv1a(hash, &waiting.turns.unwrap_or(u32::MAX).to_le_bytes());
+        }
+        hash
+    }
+
+    /// The actor whose turn it is, passing ticks until one has the energy
+    /// to act; `None` if there are no actors. Actors that appeared in
+    /// `entities` join with no energy, and those gone leave.
+    pub fn next_actor(&mut self, entities: &EntityMap) -> Option<EntityID> {
+        self.energy
+            .retain(|eid, _| entities.get(eid).is_some_and(is_actor));
+        self.waiting.retain(|eid, _| self.energy.contains_key(eid));
+        for (eid, _) in entities.iter().filter(|(_, e)| is_actor(e)) {
+            self.energy.entry(*eid).or_default();
+        }
+        loop {
+            let ready = self
+                .energy
+                .iter()
+                .filter(|(_, energy)| **energy >= TURN_COST)
+                .max_by_key(|(eid, energy)| (**energy, Reverse(**eid)));
+            if let Some((eid, _)) = ready {
+                return Some(*eid);
+            }
+            if self.energy.is_empty() {
+                return None;
+            }
+            for (eid, energy) in &mut self.energy {
+                let gain = entities.get(eid).map_or(NORMAL_SPEED, speed).max(1);
+                *energy = energy.saturating_add(gain);
+            }
+            self.elapsed += 1;
+        }
+    }
+
+    /// `eid` took its turn.
+    pub fn spend(&mut self, eid: EntityID) {
+        if let Some(energy) = self.energy.get_mut(&eid) {
+            *energy = energy.saturating_sub(TURN_COST);
+        }
+    }
+
+    pub fn energy(&self, eid: EntityID) -> Option<u32> {
+        self.energy.get(&eid).copied()
+    }
+
+    /// The ticks handed out since last asked.
+    pub fn take_elapsed(&mut self) -> u64 {
+        std::mem::take(&mut self.elapsed)
+    }
+
+    /// A scheduler whose actors hold `energy`, with `elapsed` ticks handed
+    /// out and no one waiting, as saves from before waiting load.
+    pub(crate) fn with_energy(energy: BTreeMap<EntityID, u32>, elapsed: u64) -> Self {
+        Self {
+            energy,
+            elapsed,
+            waiting: BTreeMap::new(),
+        }
+    }
+
+    /// Have `eid`, which just spent a turn waiting, pass its next turns as
+    /// `wait` asks. `health` is its health now, and `aware` what it is
+    /// aware of.
+    pub fn start_waiting(
+        &mut self,
+        eid: EntityID,
+        wait: Wait,
+        health: Option<u32>,
+        aware: BTreeSet<EntityID>,
+    ) {
+        let turns = match wait {
+            Wait::Turns(turns) => match turns.min(MAX_WAIT_TURNS).checked_sub(1) {
+                Some(left) if left > 0 => Some(left),
+                _ => return,
+            },
+            Wait::UntilInterrupted => None,
+        };
+        self.waiting.insert(
+            eid,
+            Waiting {
+                turns,
+                health,
+                known: aware,
+            },
+        );
+    }
+
+    /// End the wait of `eid`, if it is waiting.
+    pub fn stop_waiting(&mut self, eid: EntityID) {
+        self.waiting.remove(&eid);
+    }
+
+    pub fn is_waiting(&self, eid: EntityID) -> bool {
+        self.waiting.contains_key(&eid)
+    }
+
+    /// Pass the turn of waiting actor `eid`, whose health is now `health`
+    /// and who is aware of `aware`, unless it was hurt or noticed someone
+    /// new. Returns how the wait ended, if it did.
+    fn pass(
+        &mut self,
+        eid: EntityID,
+        health: Option<u32>,
+        aware: &BTreeSet<EntityID>,
+    ) -> Option<WaitEnd> {
+        let waiting = self.waiting.get_mut(&eid)?;
+        let end = if health < waiting.health {
+            Some(WaitEnd::Hurt)
+        } else if let Some(new) = aware.difference(&waiting.known).next() {
+            Some(WaitEnd::Noticed(*new))
+        } else {
+            waiting.turns = waiting.turns.map(|turns| turns.saturating_sub(1));
+            (waiting.turns == Some(0)).then_some(WaitEnd::Done)
+        };
+        if matches!(end, None | Some(WaitEnd::Done)) {
+            self.spend(eid);
+        }
+        if end.is_some() {
+            self.waiting.remove(&eid);
+        }
+        end
+    }
+}
+
+/// What `eid` is aware of that could end its wait: everything but scenery
+/// in [`fov::build_awareness`], itself aside.
+fn aware_of(state: &GameState, eid: EntityID) -> BTreeSet<EntityID> {
+    let Some(from) = state.entities.get(&eid).map(|e| e.position) else {
+        return BTreeSet::new();
+    };
+    let mask = FovMask::compute(from, fov::VIEW_RADIUS, |p| fov::blocks_sight(state, p));
+    fov::build_awareness(&state.entities, &mask, fov::AWARENESS_MARGIN)
+        .into_iter()
+        .filter(|other| {
+            *other != eid
+                && state
+                    .entities
+                    .get(other)
+                    .is_some_and(|e| !e.entity_type.is_terrain())
+        })
+        .collect()
+}
+
+/// Have `eid`, which just spent a turn, wait as `wait` asks. In a world
+/// running in real time there are no turns to wait, which ends the wait
+/// right away.
+pub fn wait(state: &mut GameState, eid: EntityID, wait: Wait) -> Option<GameEvent> {
+    if state.turns.is_none() {
+        return Some(GameEvent::WaitEnded {
+            entity_id: eid,
+            end: WaitEnd::NoTurns,
+        });
+    }
+    let health = state
+        .entities
+        .get(&eid)
+        .and_then(|e| e.health)
+        .map(|h| h.current);
+    let aware = aware_of(state, eid);
+    if let Some(turns) = &mut state.turns {
+        turns.start_waiting(eid, wait, health, aware);
+    }
+    None
+}
+
+/// If the actor whose turn it is waits, pass its turn, or end the wait if
+/// something interrupts it. Returns a [`GameEvent::WaitEnded`] if it ended.
+pub fn pass_waiting(state: &mut GameState) -> Vec<GameEvent> {
+    let Some(turns) = &mut state.turns else {
+        return Vec::new();
+    };
+    let Some(eid) = turns
+        .next_actor(&state.entities)
+        .filter(|eid| turns.is_waiting(*eid))
+    else {
+        return Vec::new();
+    };
+    let health = state
+        .entities
+        .get(&eid)
+        .and_then(|e| e.health)
+        .map(|h| h.current);
+    let aware = aware_of(state, eid);
+    let Some(turns) = &mut state.turns else {
+        return Vec::new();
+    };
+    let end = turns.pass(eid, health, &aware);
+    turns.next_actor(&state.entities);
+    end.map(|end| GameEvent::WaitEnded {
+        entity_id: eid,
+        end,
+    })
+    .into_iter()
+    .collect()
+}
@@ -0,0 +1,302 @@
+//! Vendors and trading.
+//!
+//! An entity becomes a vendor by carrying a [`Shop`], whose stock is
+//! defined by a [`ShopDef`] in the [`ContentRegistry`]. Players buy and sell
+//! with [`GameAction::Buy`] and [`GameAction::Sell`], paying in the
+//! registry's currency item; both are checked here against the player's
+//! inventory, what it can carry and the shop's stock. Vendors have
+//! unlimited money. Stock is
+//! reset on a fixed tick interval by [`restock`], so every server restocks
+//! at the same ticks.
+//!
+//! [`GameAction::Buy`]: super::GameAction::Buy
+//! [`GameAction::Sell`]: super::GameAction::Sell
+
+use super::content::ContentRegistry;
+use super::dialogue;
+use super::{EntityID, GameState, Inventory};
+use crate::profile;
+
+use bitcode::{Decode, Encode};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// A shop, as written in `assets/content/shops.ron`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ShopDef {
+    pub id: String,
+    pub name: String,
+    /// Ticks between two restocks.
+    pub restock_ticks: u64,
+    pub stock: Vec<StockEntry>,
+}
+
+impl ShopDef {
+    pub fn entry(&self, item: &str) -> Option<&StockEntry> {
+        self.stock.iter().find(|e| e.item == item)
+    }
+}
+
+/// What a shop does with one item.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct StockEntry {
+    pub item: String,
+    /// What the shop charges per item.
+    #[serde(default)]
+    pub price: u32,
+    /// What the shop pays per item; 0 if it does not buy it.
+    #[serde(default)]
+    pub sell_price: u32,
+    /// Stock after a restock; 0 if the shop does not sell the item.
+    #[serde(default)]
+    pub count: u32,
+}
+
+/// A vendor's shop and what is left in stock.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Encode, Decode)]
+pub struct Shop {
+    pub id: String,
+    stock: BTreeMap<String, u32>,
+}
+
+impl Shop {
+    /// A fully stocked shop.
+    pub fn new(def: &ShopDef) -> Self {
+        Self {
+            id: def.id.clone(),
+            stock: full_stock(def),
+        }
+    }
+
+    /// Items left for sale.
+    pub fn stock(&self, item: &str) -> u32 {
+        self.stock.get(item).copied().unwrap_or(0)
+    }
+
+    /// Items left for sale, sorted by ID.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u32)> {
+        self.stock
+            .iter()
+            .map(|(item, count)| (item.as_str(), *count))
+    }
+
+    /// Bytes the shop owns on the heap.
+    pub fn heap_bytes(&self) -> usize {
+        self.id.capacity()
+            + profile::btree_map_bytes(&self.stock)
+            + self.stock.keys().map(String::capacity).sum::<usize>()
+    }
+}
+
+fn full_stock(def: &ShopDef) -> BTreeMap<String, u32> {
+    def.stock
+        .iter()
+        .filter(|e| e.count > 0)
+        .map(|e| (e.item.clone(), e.count))
+        .collect()
+}
+
+/// Why a trade was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TradeError {
+    /// The other entity is not a vendor, or the trader is trading with
+    /// themselves.
+    NotAVendor,
+    /// The vendor is further than [`dialogue::TALK_RANGE`].
+    OutOfRange,
+    InvalidCount,
+    /// The shop does not sell this item.
+    NotSold,
+    /// The shop does not buy this item.
+    NotBought,
+    OutOfStock,
+    CannotAfford,
+    /// The seller does not carry enough of the item.
+    NotCarried,
+    /// The trader cannot carry what they would get; see
+    /// [`CarryLimits`](super::item::CarryLimits).
+    TooHeavy,
+}
+
+impl fmt::Display for TradeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::NotAVendor => "not a vendor",
+            Self::OutOfRange => "the vendor is too far away",
+            Self::InvalidCount => "nothing to trade",
+            Self::NotSold => "the shop does not sell that",
+            Self::NotBought => "the shop does not buy that",
+            Self::OutOfStock => "not enough in stock",
+            Self::CannotAfford => "not enough money",
+            Self::NotCarried => "you do not have that many",
+            Self::TooHeavy => "you cannot carry that much",
+        })
+    }
+}
+
+impl std::error::Error for TradeError {}
+
+/// The vendor's shop and its definition, if `trader` may trade with it.
+fn vendor_shop<'a>(
+    state: &'a GameState,
+    registry: &'a ContentRegistry,
+    trader: EntityID,
+    vendor: EntityID,
+) -> Result<(&'a Shop, &'a ShopDef), TradeError> {
+    let entity = state
+        .entities
+        .get(&vendor)
+        .filter(|_| vendor != trader)
+        .ok_or(TradeError::NotAVendor)?;
+    let shop = entity.shop.as_ref().ok_or(TradeError::NotAVendor)?;
+    let def = registry.shop(&shop.id).ok_or(TradeError::NotAVendor)?;
+    let trader = state.entities.get(&trader).ok_or(TradeError::NotAVendor)?;
+    if !dialogue::in_range(trader.position, entity.position) {
+        return Err(TradeError::OutOfRange);
+    }
+    Ok((shop, def))
+}
+
+/// Buy `count` of `item` from `vendor`.
+///
+/// # Errors
+/// If the vendor is out of range, does not sell `item`, has too few
+/// left, or the buyer cannot pay.
+pub fn buy(
+    state: &mut GameState,
+    registry: &ContentRegistry,
+    buyer: EntityID,
+    vendor: EntityID,
+    item: &str,
+    count: u32,
+) -> Result<(), TradeError> {
+    if count == 0 {
+        return Err(TradeError::InvalidCount);
+    }
+    let (shop, def) = vendor_shop(state, registry, buyer, vendor)?;
+    let entry = def
+        .entry(item)
+        .filter(|e| e.count > 0)
+        .ok_or(TradeError::NotSold)?;
+    if shop.stock(item) < count {
+        return Err(TradeError::OutOfStock);
+    }
+    let cost = entry
+        .price
+        .checked_mul(count)
+        .ok_or(TradeError::CannotAfford)?;
+
+    let Some(buyer) = state.entity_mut(buyer) else {
+        return Err(TradeError::NotAVendor);
+    };
+    if buyer.inventory.count(&registry.currency) < cost {
+        return Err(TradeError::CannotAfford);
+    }
+    let mut after = buyer.inventory.clone();
+    after.remove(&registry.currency, cost);
+    if !after.can_take(registry, item, count) {
+        return Err(TradeError::TooHeavy);
+    }
+    after.add(item, count);
+    buyer.inventory = after;
+    if let Some(shop) = state
+        .entities
+        .get_mut(&vendor)
+        .and_then(|v| v.shop.as_mut())
+        && let Some(stock) = shop.stock.get_mut(item)
+    {
+        *stock -= count;
+    }
+    Ok(())
+}
+
+/// Sell `count` of `item` to `vendor`. Items the shop also sells go back
+/// into its stock.
+///
+/// # Errors
+/// If the vendor is out of range, does not buy `item`, or the seller
+/// has too few.
+pub fn sell(
+    state: &mut GameState,
+    registry: &ContentRegistry,
+    seller: EntityID,
+    vendor: EntityID,
+    item: &str,
+    count: u32,
+) -> Result<(), TradeError> {
+    if count == 0 {
+        return Err(TradeError::InvalidCount);
+    }
+    let (_, def) = vendor_shop(state, registry, seller, vendor)?;
+    let entry = def
+        .entry(item)
+        .filter(|e| e.sell_price > 0)
+        .ok_or(TradeError::NotBought)?;
+    let held = &state
+        .entities
+        .get(&seller)
+        .ok_or(TradeError::NotAVendor)?
+        .inventory;
+    let payment = sale_price(registry, held, entry, count).ok_or(TradeError::NotCarried)?;
+    let resold = entry.count > 0;
+
+    let Some(seller) = state.entity_mut(seller) else {
+        return Err(TradeError::NotAVendor);
+    };
+    let mut after = seller.inventory.clone();
+    if !after.remove(item, count) {
+        return Err(TradeError::NotCarried);
+    }
+    if !after.can_take(registry, &registry.currency, payment) {
+        return Err(TradeError::TooHeavy);
+    }
+    after.add(&registry.currency, payment);
+    seller.inventory = after;
+    if resold
+        && let Some(shop) = state
+            .entities
+            .get_mut(&vendor)
+            .and_then(|v| v.shop.as_mut())
+    {
+        let stock = shop.stock.entry(item.to_owned()).or_default();
+        *stock = stock.saturating_add(count);
+    }
+    Ok(())
+}
+
+/// What a shop pays for `count` of its `entry`'s item from `inventory`.
+/// Selling the last of a worn item sells it for less; see
+/// [`ItemDef::worn_price`](super::item::ItemDef::worn_price).
+pub fn sale_price(
+    registry: &ContentRegistry,
+    inventory: &Inventory,
+    entry: &StockEntry,
+    count: u32,
+) -> Option<u32> {
+    let full = entry.sell_price.checked_mul(count)?;
+    match registry.item(&entry.item) {
+        Some(def) if count > 0 && count == inventory.count(&entry.item) => {
+            let uses = inventory
+                .condition(registry, &entry.item)
+                .map_or(0, |(uses, _)| uses);
+            let worn = def.worn_price(entry.sell_price, uses);
+            Some(full - entry.sell_price + worn)
+        }
+        _ => Some(full),
+    }
+}
+
+/// Reset the stock of every shop whose restock interval divides the
+/// current tick.
+pub fn restock(state: &mut GameState, registry: &ContentRegistry) {
+    let tick = state.tick;
+    for (_, entity) in state.entities_mut() {
+        if let Some(shop) = &mut entity.shop
+            && let Some(def) = registry.shop(&shop.id)
+            && tick.is_multiple_of(def.restock_ticks)
+        {
+            shop.stock = full_stock(def);
+        }
+    }
+}
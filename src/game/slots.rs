@@ -0,0 +1,185 @@
+//! Save slots: named snapshots of a world, kept apart from its own save.
+//!
+//! A world's own `.world` file is overwritten whenever it is saved. A slot
+//! is a copy made on request under a name of the player's choosing, in
+//! `saves/<world id>/<name>.world`, with a PNG thumbnail of the map beside
+//! it. Loading a slot plays on from it, and saving then writes the world's
+//! own file as usual; the slot stays as it was.
+
+use super::persist;
+use super::{GameState, WorldId};
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Directory the slots of every world are kept in.
+pub const SLOTS_DIR: &str = "saves";
+
+/// Longest slot name, in characters.
+pub const MAX_SLOT_NAME_LEN: usize = 48;
+
+/// Most slots kept per world.
+pub const MAX_SLOTS: usize = 32;
+
+/// A saved snapshot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SaveSlot {
+    pub name: String,
+    pub world_name: String,
+    pub path: PathBuf,
+    /// When it was saved, in seconds since the Unix epoch.
+    pub saved_at: u64,
+}
+
+impl SaveSlot {
+    /// Where the slot's map thumbnail is kept.
+    pub fn thumbnail_path(&self) -> PathBuf {
+        self.path.with_extension("png")
+    }
+}
+
+/// Directory of the slots of world `id`, under `root`.
+fn dir(root: &Path, id: WorldId) -> PathBuf {
+    root.join(id.to_string())
+}
+
+/// Where the map thumbnail of world `id`'s slot `name` in [`SLOTS_DIR`] is
+/// kept; written by the client, which has the map.
+pub fn thumbnail_path(id: WorldId, name: &str) -> PathBuf {
+    dir(Path::new(SLOTS_DIR), id).join(format!("{name}.png"))
+}
+
+/// `name` trimmed, if it makes a good slot name: not empty, at most
+/// [`MAX_SLOT_NAME_LEN`] characters, and only letters, digits, spaces,
+/// `-` and `_`, so it is safe as a file name.
+///
+/// # Errors
+/// With why the name is refused.
+pub fn slot_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("the name is empty".to_owned());
+    }
+    if name.chars().count() > MAX_SLOT_NAME_LEN {
+        return Err(format!("names are at most {MAX_SLOT_NAME_LEN} characters"));
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_'))
+    {
+        return Err("names may only hold letters, digits, spaces, - and _".to_owned());
+    }
+    Ok(name.to_owned())
+}
+
+/// Save `state` to the slot `name` in [`SLOTS_DIR`]; see [`save_in`].
+///
+/// # Errors
+/// As [`save_in`].
+pub fn save(state: &GameState, name: &str) -> Result<SaveSlot, String> {
+    save_in(Path::new(SLOTS_DIR), state, name)
+}
+
+/// Save `state` to the slot `name` under `root`, replacing a slot of that
+/// name. Refused if the name is bad or the world has [`MAX_SLOTS`] others.
+///
+/// # Errors
+/// If the name is bad, the world has too many slots, or the slot
+/// cannot be written.
+pub fn save_in(root: &Path, state: &GameState, name: &str) -> Result<SaveSlot, String> {
+    let name = slot_name(name)?;
+    let dir = dir(root, state.world_id);
+    let path = dir.join(format!("{name}.world"));
+    let others = slots_in(&dir).iter().filter(|s| s.path != path).count();
+    if others >= MAX_SLOTS {
+        return Err(format!(
+            "this world has {MAX_SLOTS} saves already; delete one first"
+        ));
+    }
+    fs::create_dir_all(&dir)
+        .and_then(|()| persist::save_to_path(state, &path))
+        .map_err(|e| format!("could not write {}: {e}", path.display()))?;
+    read(&path).map_err(|e| format!("could not read back {}: {e}", path.display()))
+}
+
+/// The slot at `path`.
+///
+/// # Errors
+/// If the slot cannot be read.
+pub fn read(path: &Path) -> io::Result<SaveSlot> {
+    let header = persist::read_save_header(path)?;
+    let saved_at = fs::metadata(path)?
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let name = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default()
+        .to_owned();
+    Ok(SaveSlot {
+        name,
+        world_name: header.world_name,
+        path: path.to_owned(),
+        saved_at,
+    })
+}
+
+/// The slots in `dir`, newest first; unreadable ones are left out.
+fn slots_in(dir: &Path) -> Vec<SaveSlot> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut slots: Vec<SaveSlot> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().and_then(|s| s.to_str()) == Some("world"))
+        .filter_map(|path| read(&path).ok())
+        .collect();
+    slots.sort_by(|a, b| {
+        b.saved_at
+            .cmp(&a.saved_at)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    slots
+}
+
+/// The slots of every world in [`SLOTS_DIR`], newest first.
+pub fn list() -> Vec<SaveSlot> {
+    list_in(Path::new(SLOTS_DIR))
+}
+
+/// The slots of every world under `root`, newest first.
+pub fn list_in(root: &Path) -> Vec<SaveSlot> {
+    let Ok(entries) = fs::read_dir(root) else {
+        return Vec::new();
+    };
+    let mut slots: Vec<SaveSlot> = entries
+        .filter_map(Result::ok)
+        .flat_map(|entry| slots_in(&entry.path()))
+        .collect();
+    slots.sort_by(|a, b| {
+        b.saved_at
+            .cmp(&a.saved_at)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    slots
+}
+
+/// Delete `slot`, its thumbnail and its backups.
+///
+/// # Errors
+/// If one of the files cannot be removed.
+pub fn delete(slot: &SaveSlot) -> io::Result<()> {
+    fs::remove_file(&slot.path)?;
+    let backups = (1..=persist::MAX_SAVE_BACKUPS).map(|n| persist::backup_path(&slot.path, n));
+    for path in std::iter::once(slot.thumbnail_path()).chain(backups) {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    Ok(())
+}
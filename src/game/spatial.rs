@@ -0,0 +1,165 @@
+//! Entities by tile, for lookups by position without scanning the world.
+//!
+//! Every [`GameState`](super::GameState) holds one, as its
+//! [`index`](super::GameState::index). New worlds are generated with it
+//! built; saves leave it out, so a loaded world's index is rebuilt from
+//! scratch on its first [`sync`](SpatialIndex::sync).
+//! [`apply`](super::apply) and [`advance`](super::advance) sync it before
+//! they run. The entities are only changed through the state, which keeps
+//! the index in step: [`GameState::insert_entity`],
+//! [`place_entity`](super::GameState::place_entity) and
+//! [`remove_entity`](super::GameState::remove_entity) update it right
+//! away, while [`entity_mut`](super::GameState::entity_mut) and
+//! [`edit_entities`](super::GameState::edit_entities), as clients apply
+//! deltas with, [note](SpatialIndex::note) what they change for the next
+//! sync. A frontend can also keep an index of its own, to learn from each
+//! sync what moved.
+
+use super::{EntityID, EntityMap, Point};
+use crate::profile;
+
+use rustc_hash::FxHashMap;
+
+/// Entities by tile, kept in step with the world from the IDs that changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpatialIndex {
+    tiles: FxHashMap<Point, Vec<EntityID>>,
+    positions: FxHashMap<EntityID, Point>,
+    /// Entities changed since the last sync; `None` until the index is
+    /// built, or after the whole world was replaced.
+    pending: Option<Vec<EntityID>>,
+    /// Tiles the entities changed by the last sync were on or moved to,
+    /// or `None` if it rebuilt the index.
+    changed_tiles: Option<Vec<Point>>,
+}
+
+impl SpatialIndex {
+    /// An index of `entities`, built right away.
+    pub fn of(entities: &EntityMap) -> Self {
+        let mut index = Self::default();
+        index.sync(entities);
+        index
+    }
+
+    /// Whether the index was built, rather than waiting to be rebuilt.
+    pub fn is_built(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Put `eid` on `position`, or take it out of the index with `None`,
+    /// right away, as the entity is added, moved or removed. Does nothing
+    /// while the index waits to be rebuilt. Unlike a noted change, the next
+    /// sync does not report it.
+    pub fn set(&mut self, eid: EntityID, position: Option<Point>) {
+        if !self.is_built() {
+            return;
+        }
+        let old = self.positions.get(&eid).copied();
+        if old == position {
+            return;
+        }
+        if let Some(old) = old {
+            self.remove(eid, old);
+        }
+        if let Some(new) = position {
+            self.place(eid, new);
+        }
+    }
+
+    /// Note entities that were added, changed or removed.
+    pub fn note(&mut self, changed: impl IntoIterator<Item = EntityID>) {
+        if let Some(pending) = &mut self.pending {
+            pending.extend(changed);
+        }
+    }
+
+    /// Rebuild from scratch at the next sync, after the whole world was
+    /// replaced.
+    pub fn invalidate(&mut self) {
+        self.pending = None;
+    }
+
+    /// Bring the index up to date with `entities`. Returns the entities
+    /// that appeared, disappeared or changed tile, sorted by ID, or `None`
+    /// if the index was rebuilt.
+    pub fn sync(&mut self, entities: &EntityMap) -> Option<Vec<EntityID>> {
+        let Some(mut changed) = self.pending.replace(Vec::new()) else {
+            self.tiles.clear();
+            self.positions.clear();
+            for (eid, entity) in entities {
+                self.place(*eid, entity.position);
+            }
+            self.changed_tiles = None;
+            return None;
+        };
+        changed.sort_unstable();
+        changed.dedup();
+        let mut changed_tiles = Vec::new();
+        changed.retain(|eid| {
+            let old = self.positions.get(eid).copied();
+            let new = entities.get(eid).map(|e| e.position);
+            changed_tiles.extend(old.into_iter().chain(new));
+            if old == new {
+                return false;
+            }
+            if let Some(old) = old {
+                self.remove(*eid, old);
+            }
+            if let Some(new) = new {
+                self.place(*eid, new);
+            }
+            true
+        });
+        changed_tiles.sort_by_key(|p| (p.y, p.x));
+        changed_tiles.dedup();
+        self.changed_tiles = Some(changed_tiles);
+        Some(changed)
+    }
+
+    fn place(&mut self, eid: EntityID, point: Point) {
+        self.positions.insert(eid, point);
+        self.tiles.entry(point).or_default().push(eid);
+    }
+
+    fn remove(&mut self, eid: EntityID, point: Point) {
+        self.positions.remove(&eid);
+        if let Some(on_tile) = self.tiles.get_mut(&point) {
+            on_tile.retain(|id| *id != eid);
+            if on_tile.is_empty() {
+                self.tiles.remove(&point);
+            }
+        }
+    }
+
+    /// Entities on `point`.
+    pub fn entities_at(&self, point: Point) -> &[EntityID] {
+        self.tiles.get(&point).map_or(&[], Vec::as_slice)
+    }
+
+    /// Entities within the rectangle from `min` to `max`, both included,
+    /// row by row. Looks up each tile of the rectangle, so keep it small.
+    pub fn entities_in_rect(&self, min: Point, max: Point) -> impl Iterator<Item = EntityID> + '_ {
+        (min.y..=max.y)
+            .flat_map(move |y| (min.x..=max.x).map(move |x| Point { x, y }))
+            .flat_map(|point| self.entities_at(point).iter().copied())
+    }
+
+    /// Where `eid` is.
+    pub fn position(&self, eid: EntityID) -> Option<Point> {
+        self.positions.get(&eid).copied()
+    }
+
+    /// Tiles that the entities changed in the last sync were on before or
+    /// are on now, sorted by row then column; `None` if the last sync
+    /// rebuilt the index.
+    pub fn changed_tiles(&self) -> Option<&[Point]> {
+        self.changed_tiles.as_deref()
+    }
+
+    /// Estimated bytes held by the index.
+    pub fn memory_bytes(&self) -> usize {
+        profile::hash_map_bytes(&self.tiles)
+            + self.tiles.values().map(profile::vec_bytes).sum::<usize>()
+            + profile::hash_map_bytes(&self.positions)
+    }
+}
@@ -0,0 +1,170 @@
+//! Freeform tags and metadata on entities.
+//!
+//! Scenario designers mark entities with short tags (`quest_target`, `boss`)
+//! and attach key→value metadata without new Rust types. Both are capped, so
+//! a client cannot grow an entity without bound.
+
+use crate::profile;
+
+use bitcode::{Decode, Encode};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Most tags one entity can carry.
+pub const MAX_TAGS: usize = 16;
+
+/// Longest tag, in bytes.
+pub const MAX_TAG_LEN: usize = 32;
+
+/// Most metadata entries one entity can carry.
+pub const MAX_METADATA: usize = 32;
+
+/// Longest metadata key, in bytes.
+pub const MAX_KEY_LEN: usize = 32;
+
+/// Longest metadata value, in bytes.
+pub const MAX_VALUE_LEN: usize = 256;
+
+/// Why a tag or metadata entry was refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagError {
+    /// Tags and keys are non-empty and limited to ASCII letters, digits,
+    /// `_`, `-` and `.`.
+    InvalidName(String),
+    /// The name or value exceeds its length limit.
+    TooLong,
+    /// The entity already has as many tags or entries as allowed.
+    Full,
+}
+
+impl fmt::Display for TagError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidName(name) => write!(f, "invalid name `{name}`"),
+            Self::TooLong => write!(f, "too long"),
+            Self::Full => write!(f, "limit reached"),
+        }
+    }
+}
+
+impl std::error::Error for TagError {}
+
+fn check_name(name: &str, max_len: usize) -> Result<(), TagError> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if !valid {
+        return Err(TagError::InvalidName(name.to_owned()));
+    }
+    if name.len() > max_len {
+        return Err(TagError::TooLong);
+    }
+    Ok(())
+}
+
+/// A small sorted set of tags.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Encode, Decode)]
+pub struct Tags(Vec<String>);
+
+impl Tags {
+    /// Add `tag`. Returns `false` if the entity already had it.
+    ///
+    /// # Errors
+    /// If the tag is malformed or the set is full.
+    pub fn insert(&mut self, tag: &str) -> Result<bool, TagError> {
+        check_name(tag, MAX_TAG_LEN)?;
+        match self.0.binary_search_by(|t| t.as_str().cmp(tag)) {
+            Ok(_) => Ok(false),
+            Err(_) if self.0.len() >= MAX_TAGS => Err(TagError::Full),
+            Err(index) => {
+                self.0.insert(index, tag.to_owned());
+                Ok(true)
+            }
+        }
+    }
+
+    /// Remove `tag`. Returns `false` if the entity did not have it.
+    pub fn remove(&mut self, tag: &str) -> bool {
+        match self.0.binary_search_by(|t| t.as_str().cmp(tag)) {
+            Ok(index) => {
+                self.0.remove(index);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    pub fn contains(&self, tag: &str) -> bool {
+        self.0.binary_search_by(|t| t.as_str().cmp(tag)).is_ok()
+    }
+
+    /// Tags in sorted order.
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Bytes the tags own on the heap.
+    pub fn heap_bytes(&self) -> usize {
+        profile::vec_bytes(&self.0) + self.0.iter().map(String::capacity).sum::<usize>()
+    }
+}
+
+/// Key→value metadata, ordered by key.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Encode, Decode)]
+pub struct Metadata(BTreeMap<String, String>);
+
+impl Metadata {
+    /// Set `key` to `value`, returning the previous value.
+    ///
+    /// # Errors
+    /// If the key or value is malformed, or there are too many keys.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<Option<String>, TagError> {
+        check_name(key, MAX_KEY_LEN)?;
+        if value.len() > MAX_VALUE_LEN {
+            return Err(TagError::TooLong);
+        }
+        if !self.0.contains_key(key) && self.0.len() >= MAX_METADATA {
+            return Err(TagError::Full);
+        }
+        Ok(self.0.insert(key.to_owned(), value.to_owned()))
+    }
+
+    /// Remove `key`, returning its value.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        self.0.remove(key)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    /// Entries in key order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Bytes the entries own on the heap.
+    pub fn heap_bytes(&self) -> usize {
+        self.0
+            .iter()
+            .map(|(k, v)| std::mem::size_of::<(String, String)>() + k.capacity() + v.capacity())
+            .sum()
+    }
+}
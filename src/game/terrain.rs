@@ -0,0 +1,134 @@
+//! The ground under the entities.
+//!
+//! Every tile has a [`Terrain`]: grass unless [`worldgen`](super::worldgen)
+//! or a tool put something else there. Terrain is not an entity, so it
+//! cannot be chopped, pushed or blown up, and costs nothing for the tiles
+//! that are plain grass. It takes part in [collision](super::collision) and
+//! [sight](super::fov) alongside the entities: rock blocks both, and water
+//! blocks movement until a [bridge](super::bridge) is built over it.
+//!
+//! A [`TileMap`] keeps the terrain in chunks of
+//! [`CHUNK_SIZE`](super::chunks::CHUNK_SIZE) tiles a side, the same as the
+//! [chunks](super::chunks) of an infinite world; chunks that are all grass
+//! are not stored. The map is saved with the world and sent to clients
+//! chunk by chunk, as it does not change while the world runs.
+
+use super::Point;
+use super::chunks::{CHUNK_SIZE, ChunkId};
+use crate::profile;
+
+use bitcode::{Decode, Encode};
+use std::collections::BTreeMap;
+
+/// Tiles in a chunk.
+const CHUNK_TILES: usize = (CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+/// What the ground of a tile is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Encode, Decode)]
+pub enum Terrain {
+    #[default]
+    Grass,
+    /// Impassable unless bridged.
+    Water,
+    /// Impassable, and hides what lies behind it.
+    Rock,
+    /// Open ground around water, where nothing grows.
+    Sand,
+}
+
+impl Terrain {
+    /// Stable numeric tag, used by
+    /// [`GameState::checksum`](super::GameState::checksum).
+    pub(super) const fn tag(self) -> u8 {
+        match self {
+            Self::Grass => 0,
+            Self::Water => 1,
+            Self::Rock => 2,
+            Self::Sand => 3,
+        }
+    }
+
+    pub fn blocks_sight(self) -> bool {
+        self == Self::Rock
+    }
+
+    /// Returns `true` if nothing can stand on the tile; water only until it
+    /// is bridged.
+    pub fn blocks_movement(self) -> bool {
+        matches!(self, Self::Water | Self::Rock)
+    }
+}
+
+/// The terrain of every tile, by chunk.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Encode, Decode)]
+pub struct TileMap {
+    /// Tiles of each chunk with something other than grass, row by row.
+    chunks: BTreeMap<ChunkId, Vec<Terrain>>,
+}
+
+/// Index of `point` within its chunk.
+fn index_in_chunk(point: Point) -> usize {
+    let x = point.x.rem_euclid(CHUNK_SIZE);
+    let y = point.y.rem_euclid(CHUNK_SIZE);
+    (y * CHUNK_SIZE + x) as usize
+}
+
+impl TileMap {
+    /// The terrain of `point`.
+    pub fn get(&self, point: Point) -> Terrain {
+        self.chunks
+            .get(&ChunkId::of(point))
+            .and_then(|tiles| tiles.get(index_in_chunk(point)))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Make `point` `terrain`.
+    pub fn set(&mut self, point: Point, terrain: Terrain) {
+        let chunk = ChunkId::of(point);
+        if terrain == Terrain::Grass && !self.chunks.contains_key(&chunk) {
+            return;
+        }
+        let tiles = self
+            .chunks
+            .entry(chunk)
+            .or_insert_with(|| vec![Terrain::Grass; CHUNK_TILES]);
+        if let Some(tile) = tiles.get_mut(index_in_chunk(point)) {
+            *tile = terrain;
+        }
+    }
+
+    /// The tiles of `chunk`, row by row, if it has anything but grass.
+    pub fn chunk(&self, chunk: ChunkId) -> Option<&[Terrain]> {
+        self.chunks.get(&chunk).map(Vec::as_slice)
+    }
+
+    /// The stored chunks and their tiles, by row then column.
+    pub fn chunks(&self) -> impl Iterator<Item = (ChunkId, &[Terrain])> {
+        self.chunks
+            .iter()
+            .map(|(chunk, tiles)| (*chunk, tiles.as_slice()))
+    }
+
+    /// Replace the tiles of `chunk` with `tiles`, as a server sent them.
+    /// Returns `false`, changing nothing, unless there is one per tile.
+    pub fn insert_chunk(&mut self, chunk: ChunkId, tiles: Vec<Terrain>) -> bool {
+        if tiles.len() != CHUNK_TILES {
+            return false;
+        }
+        self.chunks.insert(chunk, tiles);
+        true
+    }
+
+    /// Take the tiles of `chunk` out of the map, leaving it grass, and
+    /// return them if it had anything but grass.
+    pub fn remove_chunk(&mut self, chunk: ChunkId) -> Option<Vec<Terrain>> {
+        self.chunks.remove(&chunk)
+    }
+
+    /// Bytes the map owns on the heap.
+    pub fn heap_bytes(&self) -> usize {
+        profile::btree_map_bytes(&self.chunks)
+            + self.chunks.values().map(profile::vec_bytes).sum::<usize>()
+    }
+}
@@ -0,0 +1,182 @@
+//! Carrying characters between worlds.
+//!
+//! A character leaves a world as a [`PortableCharacter`]: a small RON
+//! document with its name, tags, metadata and inventory. Nothing tied to
+//! one world travels with it: position, reputation, faction, followers and
+//! health all start fresh in the new world.
+//!
+//! Every world has [`TransferRules`] set by its host, saved with the world.
+//! They can forbid exports or imports outright, ban items and cap how many
+//! of each item arrives; banned, unknown and excess items are stripped on
+//! import rather than refusing the whole character.
+
+use super::{ContentRegistry, EntityID, GameState, Inventory, Metadata, Tags, spawn_player};
+
+use bitcode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Version of the [`PortableCharacter`] format written by this build.
+pub const CHARACTER_FORMAT_VERSION: u32 = 1;
+
+/// Longest name an imported character may have, in characters.
+pub const MAX_NAME_LEN: usize = 64;
+
+/// A character as written to a `.character` file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PortableCharacter {
+    /// [`CHARACTER_FORMAT_VERSION`] of the writer.
+    pub version: u32,
+    pub name: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+    /// Items carried, by item ID.
+    #[serde(default)]
+    pub inventory: BTreeMap<String, u32>,
+}
+
+impl PortableCharacter {
+    /// The character as RON.
+    ///
+    /// # Errors
+    /// If the character cannot be serialized.
+    pub fn to_ron(&self) -> Result<String, String> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| e.to_string())
+    }
+
+    /// Read a character written by [`Self::to_ron`].
+    ///
+    /// # Errors
+    /// If `text` is not a character.
+    pub fn from_ron(text: &str) -> Result<Self, String> {
+        ron::from_str(text).map_err(|e| format!("not a character file: {e}"))
+    }
+}
+
+/// What a host allows to move in and out of their world.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct TransferRules {
+    pub allow_export: bool,
+    pub allow_import: bool,
+    /// Items stripped from imported characters.
+    pub banned_items: BTreeSet<String>,
+    /// Most of any one item an imported character keeps.
+    pub max_item_count: u32,
+}
+
+impl Default for TransferRules {
+    fn default() -> Self {
+        Self {
+            allow_export: true,
+            allow_import: true,
+            banned_items: BTreeSet::new(),
+            max_item_count: u32::MAX,
+        }
+    }
+}
+
+/// Write out the character `eid`, if the world allows exports.
+///
+/// # Errors
+/// If the world forbids exports or `eid` is not a player.
+pub fn export(state: &GameState, eid: EntityID) -> Result<PortableCharacter, String> {
+    if !state.transfer.allow_export {
+        return Err("this world does not allow exporting characters".to_owned());
+    }
+    let entity = state
+        .entities
+        .get(&eid)
+        .ok_or_else(|| "no such character".to_owned())?;
+    Ok(PortableCharacter {
+        version: CHARACTER_FORMAT_VERSION,
+        name: entity.name.clone().unwrap_or_default(),
+        tags: entity.tags.iter().map(str::to_owned).collect(),
+        metadata: entity
+            .metadata
+            .iter()
+            .map(|(key, value)| (key.to_owned(), value.to_owned()))
+            .collect(),
+        inventory: entity
+            .inventory
+            .iter()
+            .map(|(item, count)| (item.to_owned(), count))
+            .collect(),
+    })
+}
+
+/// A character brought into the world by [`import`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Imported {
+    pub entity_id: EntityID,
+    /// Items taken away under the world's rules, with how many.
+    pub stripped: Vec<(String, u32)>,
+}
+
+/// Spawn `character` as a new player in `state`, under the world's
+/// [`TransferRules`].
+///
+/// Items the world does not accept are stripped.
+///
+/// # Errors
+/// If imports are forbidden or the character is malformed; nothing is
+/// spawned then.
+pub fn import(
+    state: &mut GameState,
+    registry: &ContentRegistry,
+    character: &PortableCharacter,
+) -> Result<Imported, String> {
+    let rules = &state.transfer;
+    if !rules.allow_import {
+        return Err("this world does not accept imported characters".to_owned());
+    }
+    if character.version != CHARACTER_FORMAT_VERSION {
+        return Err(format!(
+            "unsupported character format version {}",
+            character.version
+        ));
+    }
+    let name = character.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+        return Err("invalid character name".to_owned());
+    }
+
+    // Build everything before spawning, so a bad tag spawns nothing.
+    let mut tags = Tags::default();
+    for tag in &character.tags {
+        tags.insert(tag)
+            .map_err(|e| format!("invalid tag `{tag}`: {e}"))?;
+    }
+    let mut metadata = Metadata::default();
+    for (key, value) in &character.metadata {
+        metadata
+            .set(key, value)
+            .map_err(|e| format!("invalid metadata `{key}`: {e}"))?;
+    }
+    let mut inventory = Inventory::default();
+    let mut stripped = Vec::new();
+    for (item, count) in &character.inventory {
+        let kept = if rules.banned_items.contains(item) || registry.item(item).is_none() {
+            0
+        } else {
+            (*count).min(rules.max_item_count)
+        };
+        inventory.add(item, kept);
+        if kept < *count {
+            stripped.push((item.clone(), count - kept));
+        }
+    }
+
+    let entity_id = spawn_player(state, name.to_owned());
+    if let Some(entity) = state.entity_mut(entity_id) {
+        entity.tags = tags;
+        entity.metadata = metadata;
+        entity.inventory = inventory;
+    }
+    Ok(Imported {
+        entity_id,
+        stripped,
+    })
+}
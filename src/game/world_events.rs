@@ -0,0 +1,211 @@
+//! Scheduled world events.
+//!
+//! Events are listed in `assets/content/events.ron` with a period, an offset
+//! and a duration in ticks: an event starts on every tick where
+//! `tick % every == offset` and ends `duration` ticks later. The schedule
+//! depends only on the tick, so every server running the same world sees the
+//! same events at the same time.
+//!
+//! What an event does is up to its [`WorldEventHook`]. Hooks are registered
+//! in [`HOOKS`] and looked up by the event's ID; content that names an event
+//! without a hook is rejected when the registry is loaded.
+
+use super::collision;
+use super::fov::VIEW_RADIUS;
+use super::shop::Shop;
+use super::{ContentRegistry, Entity, EntityType, GameState, SPAWN_POINT};
+
+use bitcode::{Decode, Encode};
+use serde::Deserialize;
+
+/// When an event happens, as listed in `events.ron`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct WorldEventDef {
+    pub id: String,
+    /// Ticks between two starts.
+    pub every: u64,
+    /// Tick within each period at which the event starts.
+    #[serde(default)]
+    pub offset: u64,
+    /// Ticks the event lasts.
+    pub duration: u64,
+}
+
+/// An event currently running in a world.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct ActiveEvent {
+    pub id: String,
+    /// Tick at which the event ends.
+    pub ends_at: u64,
+}
+
+/// Tells players that an event started or ended.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Announcement {
+    pub id: String,
+    pub active: bool,
+    pub text: String,
+}
+
+/// The behavior behind a world event.
+pub trait WorldEventHook: Sync {
+    /// ID of the event in `events.ron`.
+    fn id(&self) -> &'static str;
+
+    /// Name shown to players.
+    fn name(&self) -> &'static str;
+
+    /// Change the world as the event starts; returns the announcement text.
+    fn start(&self, state: &mut GameState, registry: &ContentRegistry) -> String;
+
+    /// Undo the event's changes; returns the announcement text.
+    fn end(&self, state: &mut GameState) -> String;
+
+    /// Tiles added to players' view radius while the event runs.
+    fn view_radius_bonus(&self) -> i32 {
+        0
+    }
+}
+
+/// Every known event hook.
+pub static HOOKS: &[&dyn WorldEventHook] = &[&WanderingTrader, &Aurora];
+
+/// The hook for event `id`.
+pub fn hook(id: &str) -> Option<&'static dyn WorldEventHook> {
+    HOOKS.iter().copied().find(|hook| hook.id() == id)
+}
+
+/// End the events that ran out and start the ones due this tick.
+pub fn advance(state: &mut GameState, registry: &ContentRegistry) -> Vec<Announcement> {
+    let tick = state.tick;
+    let mut announcements = Vec::new();
+
+    let (ended, running): (Vec<_>, Vec<_>) = std::mem::take(&mut state.world_events)
+        .into_iter()
+        .partition(|event| event.ends_at <= tick);
+    state.world_events = running;
+    for event in ended {
+        if let Some(hook) = hook(&event.id) {
+            let text = hook.end(state);
+            announcements.push(Announcement {
+                id: event.id,
+                active: false,
+                text,
+            });
+        }
+    }
+
+    for def in &registry.world_events {
+        if tick % def.every != def.offset || state.world_events.iter().any(|e| e.id == def.id) {
+            continue;
+        }
+        let Some(hook) = hook(&def.id) else {
+            continue;
+        };
+        let text = hook.start(state, registry);
+        state.world_events.push(ActiveEvent {
+            id: def.id.clone(),
+            ends_at: tick + def.duration,
+        });
+        announcements.push(Announcement {
+            id: def.id.clone(),
+            active: true,
+            text,
+        });
+    }
+    announcements
+}
+
+/// Announcements of the events already running, for players who just
+/// joined.
+pub fn running(state: &GameState) -> Vec<Announcement> {
+    state
+        .world_events
+        .iter()
+        .filter_map(|event| {
+            let hook = hook(&event.id)?;
+            Some(Announcement {
+                id: event.id.clone(),
+                active: true,
+                text: format!("Under way: {}.", hook.name()),
+            })
+        })
+        .collect()
+}
+
+/// Players' view radius while the events `active` are running.
+pub fn view_radius<'a>(active: impl IntoIterator<Item = &'a str>) -> i32 {
+    VIEW_RADIUS
+        + active
+            .into_iter()
+            .filter_map(hook)
+            .map(|hook| hook.view_radius_bonus())
+            .sum::<i32>()
+}
+
+/// A travelling vendor who sets up shop near the spawn point for a while.
+pub struct WanderingTrader;
+
+impl WanderingTrader {
+    /// Tag on the trader, so it can be found again when the event ends.
+    pub const TAG: &'static str = "event.wandering_trader";
+    /// Shop the trader runs.
+    pub const SHOP: &'static str = "wandering_trader";
+}
+
+impl WorldEventHook for WanderingTrader {
+    fn id(&self) -> &'static str {
+        "wandering_trader"
+    }
+
+    fn name(&self) -> &'static str {
+        "Wandering trader"
+    }
+
+    fn start(&self, state: &mut GameState, registry: &ContentRegistry) -> String {
+        let mut trader = Entity::new(
+            EntityType::Npc,
+            collision::free_tile_near(state, SPAWN_POINT),
+            Some("Wandering Trader".to_owned()),
+        );
+        trader.shop = registry.shop(Self::SHOP).map(Shop::new);
+        // The tag is a valid literal, so this cannot fail.
+        trader.tags.insert(Self::TAG).ok();
+        let id = state.entity_gen.next();
+        state.insert_entity(id, trader);
+        "A wandering trader has set up shop near the village.".to_owned()
+    }
+
+    fn end(&self, state: &mut GameState) -> String {
+        let traders: Vec<_> = state.tagged(Self::TAG).collect();
+        for eid in traders {
+            state.remove_entity(eid);
+        }
+        "The wandering trader packs up and moves on.".to_owned()
+    }
+}
+
+/// Lights in the sky that let players see further.
+pub struct Aurora;
+
+impl WorldEventHook for Aurora {
+    fn id(&self) -> &'static str {
+        "aurora"
+    }
+
+    fn name(&self) -> &'static str {
+        "Aurora"
+    }
+
+    fn start(&self, _state: &mut GameState, _registry: &ContentRegistry) -> String {
+        "An aurora lights up the sky.".to_owned()
+    }
+
+    fn end(&self, _state: &mut GameState) -> String {
+        "The aurora fades.".to_owned()
+    }
+
+    fn view_radius_bonus(&self) -> i32 {
+        6
+    }
+}
@@ -0,0 +1,651 @@
+//! Seeded world generation.
+//!
+//! Worlds are generated from a seed and a [`WorldGenConfig`], read from a
+//! small biome file:
+//!
+//! ```text
+//! # Comments start with '#'.
+//! width 256
+//! height 256
+//! tree_density 40
+//! grove_size 8
+//! grove_chance 300
+//! grove_density 450
+//! clearing_radius 4
+//! river_chance 300
+//! lake_chance 150
+//! # infinite 1
+//! ```
+//!
+//! Densities and chances are per mille. The map is cut into square groves of
+//! `grove_size` tiles; each is dense forest with probability `grove_chance`,
+//! and open land otherwise. Independently of that, a few groves are ponds,
+//! water ringed with sand, and a few are strewn with rock; trees and
+//! hermits only grow on grass. The area around [`SPAWN_POINT`] is kept
+//! clear, and all grass.
+//!
+//! Over that, water is carved in a pass of its own. The map is cut into
+//! strips [`RIVER_SPACING`] tiles wide, each way; each strip has a river
+//! running its length with probability `river_chance`, meandering between
+//! bends every [`RIVER_BEND`] tiles and forded by sand here and there. Each
+//! cell of [`LAKE_SPACING`] tiles a side holds a round lake with
+//! probability `lake_chance`. Rivers and lakes have sand banks. Every tile
+//! of them follows from the seed and its own position alone, so they carry
+//! on unbroken across the chunks of an infinite world.
+//!
+//! Generation uses integer hashing only, so the same seed and config give
+//! the same world on every platform. Some forest groves have a hermit at
+//! their center, named by [`namegen`] from the seed, as are the regions of
+//! [`REGION_SIZE`] tiles the map is divided into. [`preview`] runs the same rolls for a
+//! coarse grid of the terrain alone, cheap enough to redo on every change
+//! to the parameters.
+//!
+//! A generated world keeps its seed and config in a [`GenManifest`], so it
+//! can be generated again or grown with [`extend`]. Changes that make the
+//! same seed give another world bump [`GENERATOR_VERSION`]; worlds from
+//! another version are not extended, as the new tiles would not match.
+//!
+//! With `infinite 1` the world has no edge: nothing is planted up front,
+//! and [`chunks`](super::chunks) plants the land around players as they
+//! go, tile for tile as it would have been planted here. Width and height
+//! then only frame the [`preview`].
+
+pub mod dungeon;
+pub mod namegen;
+
+use super::chunks::{self, Chunks};
+use super::math;
+use super::{
+    Entity, EntityGenerator, EntityMap, EntityType, GameState, Point, Rosters, SPAWN_POINT,
+    SpatialIndex, Terrain, TileMap, TransferRules, WorldId,
+};
+
+use bitcode::{Decode, Encode};
+use rustc_hash::FxHashSet;
+use std::fmt;
+
+/// Largest width or height a generated world may have.
+pub const MAX_WORLD_SIDE: u32 = 4096;
+
+/// Version of the generator, bumped whenever a seed and config would give
+/// another world than before.
+pub const GENERATOR_VERSION: u32 = 4;
+
+/// Side of a named region, in tiles.
+pub const REGION_SIZE: u32 = 64;
+
+/// Chance that a forest grove has a hermit at its center, per mille.
+const HERMIT_CHANCE: u32 = 125;
+
+/// Chance that a grove is a pond, per mille.
+const POND_CHANCE: u32 = 60;
+
+/// Chance that a grove is rocky, per mille.
+const ROCKY_CHANCE: u32 = 50;
+
+/// Chance of rock on each tile of a rocky grove, per mille.
+const ROCK_DENSITY: u32 = 350;
+
+/// Width of the strips a river may run along, in tiles.
+pub const RIVER_SPACING: i32 = 64;
+
+/// Tiles between the bends of a river.
+pub const RIVER_BEND: i32 = 16;
+
+/// Furthest a river strays from the middle of its strip.
+const RIVER_MEANDER: i32 = 20;
+
+/// Tiles of water either side of the middle of a river.
+const RIVER_HALF_WIDTH: u32 = 1;
+
+/// Chance that a river has a ford just past a bend, per mille.
+const FORD_CHANCE: u32 = 250;
+
+/// Rows of sand a ford is wide.
+const FORD_WIDTH: i32 = 3;
+
+/// Side of the cells a lake may lie in, in tiles.
+pub const LAKE_SPACING: i32 = 48;
+
+/// Smallest and largest radius of a lake.
+const LAKE_RADIUS: (u32, u32) = (4, 10);
+
+/// Parameters of the world generator.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct WorldGenConfig {
+    pub width: u32,
+    pub height: u32,
+    /// Chance of a tree on open land, per mille.
+    pub tree_density: u32,
+    /// Side of a grove, in tiles.
+    pub grove_size: u32,
+    /// Chance that a grove is forest, per mille.
+    pub grove_chance: u32,
+    /// Chance of a tree inside a forest grove, per mille.
+    pub grove_density: u32,
+    /// Tiles around the spawn point kept free of trees.
+    pub clearing_radius: u32,
+    /// Chance that a strip of the map has a river, per mille.
+    pub river_chance: u32,
+    /// Chance that a lake cell has a lake, per mille.
+    pub lake_chance: u32,
+    /// Whether the world has no edge, and is planted in chunks as players
+    /// explore it.
+    pub infinite: bool,
+}
+
+impl Default for WorldGenConfig {
+    fn default() -> Self {
+        Self {
+            width: 64,
+            height: 64,
+            tree_density: 40,
+            grove_size: 8,
+            grove_chance: 300,
+            grove_density: 450,
+            clearing_radius: 4,
+            river_chance: 300,
+            lake_chance: 150,
+            infinite: false,
+        }
+    }
+}
+
+impl WorldGenConfig {
+    /// Parse a biome file, reporting the first bad line. Keys that are not
+    /// given keep their default value.
+    ///
+    /// # Errors
+    /// With the number and reason of the first bad line.
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+
+        for (number, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let bad = |what: &str| format!("line {}: {what}: {line}", number + 1);
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let [key, value] = words.as_slice() else {
+                return Err(bad("expected `<key> <value>`"));
+            };
+            let value: u32 = value
+                .parse()
+                .map_err(|e| bad(&format!("invalid number ({e})")))?;
+            if *key == "infinite" {
+                config.infinite = match value {
+                    0 => false,
+                    1 => true,
+                    _ => return Err(bad("expected 0 or 1")),
+                };
+                continue;
+            }
+            let field = match *key {
+                "width" => &mut config.width,
+                "height" => &mut config.height,
+                "tree_density" => &mut config.tree_density,
+                "grove_size" => &mut config.grove_size,
+                "grove_chance" => &mut config.grove_chance,
+                "grove_density" => &mut config.grove_density,
+                "clearing_radius" => &mut config.clearing_radius,
+                "river_chance" => &mut config.river_chance,
+                "lake_chance" => &mut config.lake_chance,
+                _ => return Err(bad("unknown key")),
+            };
+            *field = value;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check that the parameters describe a world that can be generated.
+    ///
+    /// # Errors
+    /// With the first parameter out of range.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.width == 0 || self.height == 0 {
+            return Err("world size must not be zero".into());
+        }
+        if self.width > MAX_WORLD_SIDE || self.height > MAX_WORLD_SIDE {
+            return Err(format!("world sides are limited to {MAX_WORLD_SIDE} tiles"));
+        }
+        if self.grove_size == 0 {
+            return Err("grove_size must not be zero".into());
+        }
+        let per_mille = [
+            ("tree_density", self.tree_density),
+            ("grove_chance", self.grove_chance),
+            ("grove_density", self.grove_density),
+            ("river_chance", self.river_chance),
+            ("lake_chance", self.lake_chance),
+        ];
+        match per_mille.iter().find(|(_, value)| *value > 1000) {
+            Some((key, _)) => Err(format!("{key} is per mille and must be at most 1000")),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Writes the config as a biome file that [`WorldGenConfig::parse`] reads
+/// back.
+impl fmt::Display for WorldGenConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "width {}", self.width)?;
+        writeln!(f, "height {}", self.height)?;
+        writeln!(f, "tree_density {}", self.tree_density)?;
+        writeln!(f, "grove_size {}", self.grove_size)?;
+        writeln!(f, "grove_chance {}", self.grove_chance)?;
+        writeln!(f, "grove_density {}", self.grove_density)?;
+        writeln!(f, "clearing_radius {}", self.clearing_radius)?;
+        writeln!(f, "river_chance {}", self.river_chance)?;
+        writeln!(f, "lake_chance {}", self.lake_chance)?;
+        if self.infinite {
+            writeln!(f, "infinite 1")?;
+        }
+        Ok(())
+    }
+}
+
+/// What a world was generated from.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct GenManifest {
+    /// [`GENERATOR_VERSION`] of the build that generated the world.
+    pub version: u32,
+    pub seed: u64,
+    pub config: WorldGenConfig,
+}
+
+impl GenManifest {
+    /// Check that this build generates the same worlds as the one that
+    /// wrote the manifest.
+    ///
+    /// # Errors
+    /// If this build's generator version or parameters differ.
+    pub fn check(&self) -> Result<(), String> {
+        if self.version == GENERATOR_VERSION {
+            Ok(())
+        } else {
+            Err(format!(
+                "world was made by generator version {}, this build has version {GENERATOR_VERSION}",
+                self.version
+            ))
+        }
+    }
+}
+
+/// Writes the manifest as a biome file, with the version and seed in a
+/// comment on top.
+impl fmt::Display for GenManifest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "# generator version {}, seed {}",
+            self.version, self.seed
+        )?;
+        self.config.fmt(f)
+    }
+}
+
+/// Generate a world named `name` from `seed`.
+///
+/// Terrain and trees fill `0..width` × `0..height`, or for an infinite
+/// world the chunks around [`SPAWN_POINT`]; the world gets a fresh
+/// [`WorldId`].
+pub fn generate(name: String, seed: u64, config: &WorldGenConfig) -> GameState {
+    let mut entity_gen = EntityGenerator::default();
+    let mut entities = EntityMap::default();
+    let mut terrain = TileMap::default();
+    if !config.infinite {
+        plant_trees(
+            &mut entity_gen,
+            &mut entities,
+            &mut terrain,
+            seed,
+            config,
+            (0, 0),
+        );
+    }
+
+    let mut state = GameState {
+        entity_gen,
+        entities,
+        world_id: WorldId::generate(),
+        world_name: name,
+        tick: 0,
+        world_events: Vec::new(),
+        rosters: Rosters::new(),
+        transfer: TransferRules::default(),
+        generation: Some(GenManifest {
+            version: GENERATOR_VERSION,
+            seed,
+            config: config.clone(),
+        }),
+        turns: None,
+        chunks: config.infinite.then(Chunks::default),
+        terrain,
+        index: SpatialIndex::default(),
+    };
+    state.sync_index();
+    chunks::update(&mut state);
+    state
+}
+
+/// Grow a generated world to at least `width` × `height` tiles, planting
+/// the new tiles as [`generate`] would have. Tiles that already hold an
+/// entity are left alone, and stay grass.
+///
+/// # Errors
+/// For worlds that were not generated, by another version of the
+/// generator, or that are infinite.
+pub fn extend(state: &mut GameState, width: u32, height: u32) -> Result<(), String> {
+    let manifest = state
+        .generation
+        .as_ref()
+        .ok_or("world was not generated from a seed")?;
+    manifest.check()?;
+    if manifest.config.infinite {
+        return Err("infinite worlds grow on their own".into());
+    }
+    let seed = manifest.seed;
+    let old = (manifest.config.width, manifest.config.height);
+    let config = WorldGenConfig {
+        width: width.max(old.0),
+        height: height.max(old.1),
+        ..manifest.config.clone()
+    };
+    config.validate()?;
+
+    let occupied: FxHashSet<Point> = state.entities.values().map(|e| e.position).collect();
+    let mut entities = EntityMap::default();
+    plant_trees(
+        &mut state.entity_gen,
+        &mut entities,
+        &mut state.terrain,
+        seed,
+        &config,
+        old,
+    );
+    entities.retain(|_, tree| !occupied.contains(&tree.position));
+    let new = |p: &&Point| p.x >= old.0 as i32 || p.y >= old.1 as i32;
+    for point in occupied.iter().filter(new) {
+        state.terrain.set(*point, Terrain::Grass);
+    }
+    for (eid, tree) in entities {
+        state.insert_entity(eid, tree);
+    }
+    state.generation = Some(GenManifest {
+        version: GENERATOR_VERSION,
+        seed,
+        config,
+    });
+    Ok(())
+}
+
+/// Lay the terrain and plant the trees and hermits of `config`'s area,
+/// except in the `skip` width × height corner at the origin, which was
+/// planted before.
+fn plant_trees(
+    entity_gen: &mut EntityGenerator,
+    entities: &mut EntityMap,
+    terrain: &mut TileMap,
+    seed: u64,
+    config: &WorldGenConfig,
+    skip: (u32, u32),
+) {
+    for y in 0..config.height {
+        for x in 0..config.width {
+            if x < skip.0 && y < skip.1 {
+                continue;
+            }
+            let point = Point {
+                x: x as i32,
+                y: y as i32,
+            };
+            plant_tile(entity_gen, entities, terrain, seed, config, point);
+        }
+    }
+}
+
+/// Lay the terrain and plant the trees and hermits of the `side` × `side`
+/// square whose top-left tile is `min`, for [`chunks`].
+pub(super) fn plant_chunk(
+    entity_gen: &mut EntityGenerator,
+    entities: &mut EntityMap,
+    terrain: &mut TileMap,
+    seed: u64,
+    config: &WorldGenConfig,
+    min: Point,
+    side: i32,
+) {
+    for y in min.y..min.y.saturating_add(side) {
+        for x in min.x..min.x.saturating_add(side) {
+            plant_tile(entity_gen, entities, terrain, seed, config, Point { x, y });
+        }
+    }
+}
+
+/// Lay the terrain of `point`, and plant the hermit or tree, if any, that
+/// grows on it.
+fn plant_tile(
+    entity_gen: &mut EntityGenerator,
+    entities: &mut EntityMap,
+    terrain: &mut TileMap,
+    seed: u64,
+    config: &WorldGenConfig,
+    point: Point,
+) {
+    let ground = terrain_at(seed, config, point);
+    terrain.set(point, ground);
+    if ground != Terrain::Grass {
+        return;
+    }
+    if has_hermit(seed, config, point) {
+        let name = namegen::npc_name(seed, point);
+        let hermit = Entity::new(EntityType::Npc, point, Some(name));
+        entities.insert(entity_gen.next(), hermit);
+    } else if roll(seed, point, 0) < tree_chance(seed, config, point) {
+        let tree = Entity::new(EntityType::Tree, point, None);
+        entities.insert(entity_gen.next(), tree);
+    }
+}
+
+/// Chance of a tree on `point`, per mille: none in the spawn clearing, and
+/// the grove's density elsewhere.
+fn tree_chance(seed: u64, config: &WorldGenConfig, point: Point) -> u32 {
+    if math::chebyshev(point, SPAWN_POINT) <= config.clearing_radius {
+        return 0;
+    }
+    let grove = Point {
+        x: point.x.div_euclid(config.grove_size as i32),
+        y: point.y.div_euclid(config.grove_size as i32),
+    };
+    if roll(seed, grove, 1) < config.grove_chance {
+        config.grove_density
+    } else {
+        config.tree_density
+    }
+}
+
+/// Terrain of `point`: the water and banks of rivers and lakes; otherwise
+/// water in the middle of a pond grove, ringed with sand; rock on some
+/// tiles of a rocky grove; grass elsewhere, and all over the spawn
+/// clearing.
+fn terrain_at(seed: u64, config: &WorldGenConfig, point: Point) -> Terrain {
+    if math::chebyshev(point, SPAWN_POINT) <= config.clearing_radius {
+        return Terrain::Grass;
+    }
+    let wet = [
+        river(seed, config, point.x, point.y, 6),
+        river(seed, config, point.y, point.x, 9),
+        lake(seed, config, point),
+    ];
+    if wet.contains(&Some(Terrain::Water)) {
+        return Terrain::Water;
+    }
+    if wet.contains(&Some(Terrain::Sand)) {
+        return Terrain::Sand;
+    }
+    let size = config.grove_size as i32;
+    let grove = Point {
+        x: point.x.div_euclid(size),
+        y: point.y.div_euclid(size),
+    };
+    if roll(seed, grove, 3) < POND_CHANCE {
+        let center = Point {
+            x: grove.x.saturating_mul(size).saturating_add(size / 2),
+            y: grove.y.saturating_mul(size).saturating_add(size / 2),
+        };
+        let radius = config.grove_size / 4;
+        let distance = math::chebyshev(point, center);
+        if distance <= radius {
+            Terrain::Water
+        } else if distance == radius + 1 {
+            Terrain::Sand
+        } else {
+            Terrain::Grass
+        }
+    } else if roll(seed, grove, 4) < ROCKY_CHANCE && roll(seed, point, 5) < ROCK_DENSITY {
+        Terrain::Rock
+    } else {
+        Terrain::Grass
+    }
+}
+
+/// What the river of the strip `across` lies in puts on the tile `along`
+/// its length, if there is a river: water in its bed, sand on its banks and
+/// across its fords. Rivers running down the map and across it roll in
+/// their own `layer`s, and the two after it.
+fn river(
+    seed: u64,
+    config: &WorldGenConfig,
+    across: i32,
+    along: i32,
+    layer: u64,
+) -> Option<Terrain> {
+    let strip = across.div_euclid(RIVER_SPACING);
+    if roll(seed, Point { x: strip, y: 0 }, layer) >= config.river_chance {
+        return None;
+    }
+    let bend = along.div_euclid(RIVER_BEND);
+    let past = along.rem_euclid(RIVER_BEND);
+    let stray = |bend: i32| {
+        let roll = roll(seed, Point { x: strip, y: bend }, layer + 1) as i32;
+        roll * (2 * RIVER_MEANDER + 1) / 1000 - RIVER_MEANDER
+    };
+    let middle = strip * RIVER_SPACING
+        + RIVER_SPACING / 2
+        + (stray(bend) * (RIVER_BEND - past) + stray(bend + 1) * past).div_euclid(RIVER_BEND);
+    let ford =
+        past < FORD_WIDTH && roll(seed, Point { x: strip, y: bend }, layer + 2) < FORD_CHANCE;
+    match (across - middle).unsigned_abs() {
+        distance if distance <= RIVER_HALF_WIDTH && !ford => Some(Terrain::Water),
+        distance if distance <= RIVER_HALF_WIDTH + 1 => Some(Terrain::Sand),
+        _ => None,
+    }
+}
+
+/// What the lake of the cell `point` lies in puts on it, if there is a
+/// lake: water within its radius, ringed with sand.
+fn lake(seed: u64, config: &WorldGenConfig, point: Point) -> Option<Terrain> {
+    let cell = Point {
+        x: point.x.div_euclid(LAKE_SPACING),
+        y: point.y.div_euclid(LAKE_SPACING),
+    };
+    if roll(seed, cell, 12) >= config.lake_chance {
+        return None;
+    }
+    let (smallest, largest) = LAKE_RADIUS;
+    let radius = smallest + roll(seed, cell, 13) * (largest - smallest + 1) / 1000;
+    // Room for the largest lake and its bank, either side of the middle.
+    let slack = LAKE_SPACING / 2 - largest as i32 - 2;
+    let offset = |layer| roll(seed, cell, layer) as i32 * (2 * slack + 1) / 1000 - slack;
+    let (dx, dy) = (
+        point.x - (cell.x * LAKE_SPACING + LAKE_SPACING / 2 + offset(14)),
+        point.y - (cell.y * LAKE_SPACING + LAKE_SPACING / 2 + offset(15)),
+    );
+    let distance = (dx * dx + dy * dy).unsigned_abs();
+    if distance <= radius * radius {
+        Some(Terrain::Water)
+    } else if distance <= (radius + 1) * (radius + 1) {
+        Some(Terrain::Sand)
+    } else {
+        None
+    }
+}
+
+/// Returns `true` if a hermit lives on `point`: the center of a forest
+/// grove, outside the spawn clearing, if that grove's roll says so.
+fn has_hermit(seed: u64, config: &WorldGenConfig, point: Point) -> bool {
+    let size = config.grove_size as i32;
+    let grove = Point {
+        x: point.x.div_euclid(size),
+        y: point.y.div_euclid(size),
+    };
+    point.x.rem_euclid(size) == size / 2
+        && point.y.rem_euclid(size) == size / 2
+        && math::chebyshev(point, SPAWN_POINT) > config.clearing_radius
+        && roll(seed, grove, 1) < config.grove_chance
+        && roll(seed, grove, 2) < HERMIT_CHANCE
+}
+
+/// Coarse map of the terrain a seed and config would give.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preview {
+    /// Cells per row and per column.
+    pub width: usize,
+    pub height: usize,
+    /// Side of a cell, in tiles.
+    pub scale: u32,
+    /// Chance of a tree in each cell, per mille, row by row.
+    pub tree_chance: Vec<u32>,
+    /// Terrain at the center of each cell, row by row.
+    pub terrain: Vec<Terrain>,
+}
+
+/// Preview the world [`generate`] would make from `seed` and `config`.
+///
+/// Cells are as many tiles as it takes to fit `max_side` cells a side. Each
+/// cell shows the terrain and the chance of a tree at its center; no trees
+/// are rolled.
+pub fn preview(seed: u64, config: &WorldGenConfig, max_side: u32) -> Preview {
+    let side = config.width.max(config.height);
+    let scale = side.div_ceil(max_side.max(1)).max(1);
+    let (width, height) = (config.width.div_ceil(scale), config.height.div_ceil(scale));
+    let mut tree_chance_at = Vec::with_capacity((width * height) as usize);
+    let mut terrain = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let center = Point {
+                x: ((x * scale + scale / 2).min(config.width - 1)) as i32,
+                y: ((y * scale + scale / 2).min(config.height - 1)) as i32,
+            };
+            tree_chance_at.push(tree_chance(seed, config, center));
+            terrain.push(terrain_at(seed, config, center));
+        }
+    }
+    Preview {
+        width: width as usize,
+        height: height as usize,
+        scale,
+        tree_chance: tree_chance_at,
+        terrain,
+    }
+}
+
+/// Deterministic roll in `0..1000` for `point` in layer `layer`.
+fn roll(seed: u64, point: Point, layer: u64) -> u32 {
+    let mut hash = seed ^ layer.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    hash = mix(hash ^ u64::from(point.x as u32));
+    hash = mix(hash ^ (u64::from(point.y as u32) << 32));
+    (hash % 1000) as u32
+}
+
+/// `SplitMix64` finalizer.
+pub(crate) fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,255 @@
+//! Dungeons: rooms of bare floor cut out of solid rock.
+//!
+//! The map is split in two, again and again, at rolled places, until each
+//! part is too small to split; each part then gets a room of rolled size
+//! somewhere inside it. Rooms are joined one by one to the nearest room
+//! joined before, by L-shaped corridors, so every room can be reached. The
+//! first room is a small one around [`SPAWN_POINT`], where players appear.
+//!
+//! Walls are [`Terrain::Rock`], which blocks sight as well as movement, so
+//! players only see the room or corridor they are in. Floors are plain
+//! ground. As with the forests of [`worldgen`](super), the same seed and
+//! size always give the same dungeon.
+
+use super::{MAX_WORLD_SIDE, mix};
+use crate::game::math;
+use crate::game::{
+    EntityGenerator, EntityMap, GameState, Point, Rosters, SPAWN_POINT, SpatialIndex, Terrain,
+    TileMap, TransferRules, WorldId,
+};
+
+/// Smallest width or height of a dungeon.
+pub const MIN_DUNGEON_SIDE: u32 = 24;
+
+/// Smallest side of a part of the map, which a room is kept inside of.
+const MIN_PART: i32 = 10;
+
+/// Smallest side of a room.
+const MIN_ROOM: i32 = 4;
+
+/// Tiles of floor either side of the spawn point in the first room.
+const SPAWN_ROOM_RADIUS: i32 = 1;
+
+/// Rolls drawn one after another from a seed.
+struct Dice {
+    seed: u64,
+    drawn: u64,
+}
+
+impl Dice {
+    /// A roll in `0..sides`, or 0 if there are none.
+    fn roll(&mut self, sides: i32) -> i32 {
+        self.drawn += 1;
+        let hash = mix(self.seed ^ mix(self.drawn));
+        match u64::try_from(sides) {
+            Ok(sides) if sides > 0 => (hash % sides) as i32,
+            _ => 0,
+        }
+    }
+}
+
+/// A rectangle of tiles, corners included.
+#[derive(Debug, Clone, Copy)]
+struct Area {
+    min: Point,
+    max: Point,
+}
+
+impl Area {
+    fn width(self) -> i32 {
+        self.max.x - self.min.x + 1
+    }
+
+    fn height(self) -> i32 {
+        self.max.y - self.min.y + 1
+    }
+
+    fn center(self) -> Point {
+        Point {
+            x: i32::midpoint(self.min.x, self.max.x),
+            y: i32::midpoint(self.min.y, self.max.y),
+        }
+    }
+
+    fn tiles(self) -> impl Iterator<Item = Point> {
+        (self.min.y..=self.max.y)
+            .flat_map(move |y| (self.min.x..=self.max.x).map(move |x| Point { x, y }))
+    }
+}
+
+/// Generate a dungeon named `name`, `width` × `height` tiles, from `seed`.
+/// Sides are kept between [`MIN_DUNGEON_SIDE`] and [`MAX_WORLD_SIDE`].
+pub fn generate(name: String, width: u32, height: u32, seed: u64) -> GameState {
+    let width = width.clamp(MIN_DUNGEON_SIDE, MAX_WORLD_SIDE) as i32;
+    let height = height.clamp(MIN_DUNGEON_SIDE, MAX_WORLD_SIDE) as i32;
+    let mut dice = Dice { seed, drawn: 0 };
+
+    let mut terrain = TileMap::default();
+    let map = Area {
+        min: Point { x: 0, y: 0 },
+        max: Point {
+            x: width - 1,
+            y: height - 1,
+        },
+    };
+    for point in map.tiles() {
+        terrain.set(point, Terrain::Rock);
+    }
+
+    // The outermost ring of tiles stays rock all around.
+    let inside = Area {
+        min: Point { x: 1, y: 1 },
+        max: Point {
+            x: width - 2,
+            y: height - 2,
+        },
+    };
+    let spawn_room = Area {
+        min: Point {
+            x: SPAWN_POINT.x - SPAWN_ROOM_RADIUS,
+            y: SPAWN_POINT.y - SPAWN_ROOM_RADIUS,
+        },
+        max: Point {
+            x: SPAWN_POINT.x + SPAWN_ROOM_RADIUS,
+            y: SPAWN_POINT.y + SPAWN_ROOM_RADIUS,
+        },
+    };
+    let mut rooms = vec![spawn_room];
+    rooms.extend(
+        split(&mut dice, inside)
+            .into_iter()
+            .map(|part| room(&mut dice, part)),
+    );
+
+    for room in &rooms {
+        for point in room.tiles() {
+            terrain.set(point, Terrain::Grass);
+        }
+    }
+    for (index, room) in rooms.iter().enumerate().skip(1) {
+        let from = room.center();
+        let Some(to) = rooms
+            .iter()
+            .take(index)
+            .map(|joined| joined.center())
+            .min_by_key(|center| math::manhattan(from, *center))
+        else {
+            continue;
+        };
+        for point in corridor(&mut dice, from, to) {
+            terrain.set(point, Terrain::Grass);
+        }
+    }
+
+    GameState {
+        entity_gen: EntityGenerator::default(),
+        entities: EntityMap::default(),
+        world_id: WorldId::generate(),
+        world_name: name,
+        tick: 0,
+        world_events: Vec::new(),
+        rosters: Rosters::new(),
+        transfer: TransferRules::default(),
+        generation: None,
+        turns: None,
+        chunks: None,
+        terrain,
+        index: SpatialIndex::of(&EntityMap::default()),
+    }
+}
+
+/// Split `area` at rolled places until no part has a side long enough for
+/// two, and return the parts.
+fn split(dice: &mut Dice, area: Area) -> Vec<Area> {
+    let mut parts = Vec::new();
+    let mut pending = vec![area];
+    while let Some(part) = pending.pop() {
+        let across = part.width() >= 2 * MIN_PART;
+        let down = part.height() >= 2 * MIN_PART;
+        let vertical = match (across, down) {
+            (false, false) => {
+                parts.push(part);
+                continue;
+            }
+            (true, true) => dice.roll(2) == 0,
+            (across, _) => across,
+        };
+        let (first, second) = if vertical {
+            let x = part.min.x + MIN_PART + dice.roll(part.width() - 2 * MIN_PART + 1);
+            (
+                Area {
+                    max: Point {
+                        x: x - 1,
+                        y: part.max.y,
+                    },
+                    ..part
+                },
+                Area {
+                    min: Point { x, y: part.min.y },
+                    ..part
+                },
+            )
+        } else {
+            let y = part.min.y + MIN_PART + dice.roll(part.height() - 2 * MIN_PART + 1);
+            (
+                Area {
+                    max: Point {
+                        x: part.max.x,
+                        y: y - 1,
+                    },
+                    ..part
+                },
+                Area {
+                    min: Point { x: part.min.x, y },
+                    ..part
+                },
+            )
+        };
+        pending.push(second);
+        pending.push(first);
+    }
+    parts
+}
+
+/// A room of rolled size and place inside `part`, a tile clear of its
+/// edges so that rooms of neighboring parts do not run together.
+fn room(dice: &mut Dice, part: Area) -> Area {
+    let (inner_width, inner_height) = (part.width() - 2, part.height() - 2);
+    let width = MIN_ROOM + dice.roll(inner_width - MIN_ROOM + 1);
+    let height = MIN_ROOM + dice.roll(inner_height - MIN_ROOM + 1);
+    let min = Point {
+        x: part.min.x + 1 + dice.roll(inner_width - width + 1),
+        y: part.min.y + 1 + dice.roll(inner_height - height + 1),
+    };
+    Area {
+        min,
+        max: Point {
+            x: min.x + width - 1,
+            y: min.y + height - 1,
+        },
+    }
+}
+
+/// The tiles of a corridor from `from` to `to`: straight along one axis,
+/// then the other, which of them first rolled.
+fn corridor(dice: &mut Dice, from: Point, to: Point) -> Vec<Point> {
+    let corner = if dice.roll(2) == 0 {
+        Point { x: to.x, y: from.y }
+    } else {
+        Point { x: from.x, y: to.y }
+    };
+    let leg = |a: Point, b: Point| Area {
+        min: Point {
+            x: a.x.min(b.x),
+            y: a.y.min(b.y),
+        },
+        max: Point {
+            x: a.x.max(b.x),
+            y: a.y.max(b.y),
+        },
+    };
+    leg(from, corner)
+        .tiles()
+        .chain(leg(corner, to).tiles())
+        .collect()
+}
@@ -0,0 +1,101 @@
+//! Syllable-based names for worlds, regions and NPCs.
+//!
+//! Each [`NameStyle`] in `names.ron` lists the pieces its syllables are made
+//! of. [`name`] picks them by integer hashing of a seed and an index, so a
+//! world seed gives its regions and NPCs the same names on every platform.
+
+use super::{REGION_SIZE, mix};
+use crate::game::{ContentRegistry, Point};
+
+use serde::Deserialize;
+
+/// Style of suggested world names.
+pub const WORLD: &str = "world";
+/// Style of region names; see [`region_name`].
+pub const REGION: &str = "region";
+/// Style of the names of generated NPCs.
+pub const NPC: &str = "npc";
+/// Styles the game names things in, which content must define.
+pub const STYLES: [&str; 3] = [WORLD, REGION, NPC];
+
+/// How the names of one kind of thing are made.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct NameStyle {
+    pub id: String,
+    /// Fewest and most syllables in a name.
+    pub syllables: (u32, u32),
+    /// Starts of syllables.
+    pub onsets: Vec<String>,
+    /// Middles of syllables; must not be empty.
+    pub vowels: Vec<String>,
+    /// Ends of syllables.
+    pub codas: Vec<String>,
+    /// Words put after the name, one picked per name, if any.
+    #[serde(default)]
+    pub suffixes: Vec<String>,
+}
+
+/// Name number `index` in `style` for `seed`.
+pub fn name(style: &NameStyle, seed: u64, index: u64) -> String {
+    let mut hash = mix(seed ^ mix(index));
+    let (min, max) = style.syllables;
+    let count = min + roll(&mut hash, u64::from(max.saturating_sub(min)) + 1) as u32;
+    let mut name = String::new();
+    for _ in 0..count {
+        for part in [&style.onsets, &style.vowels, &style.codas] {
+            name.push_str(pick(part, &mut hash));
+        }
+    }
+    let mut chars = name.chars();
+    let mut name: String = chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default();
+    name.push_str(pick(&style.suffixes, &mut hash));
+    name
+}
+
+/// A name for a world generated from `seed`.
+pub fn world_name(seed: u64) -> String {
+    styled(WORLD, seed, 0)
+}
+
+/// Name of the region `point` lies in, in a world generated from `seed`.
+/// Regions are squares of [`REGION_SIZE`] tiles.
+pub fn region_name(seed: u64, point: Point) -> String {
+    let side = REGION_SIZE as i32;
+    let region = Point {
+        x: point.x.div_euclid(side),
+        y: point.y.div_euclid(side),
+    };
+    styled(REGION, seed, point_index(region))
+}
+
+/// Name of an NPC generated at `point` from `seed`.
+pub fn npc_name(seed: u64, point: Point) -> String {
+    styled(NPC, seed, point_index(point))
+}
+
+/// A name in the built-in style `id`, which validation makes sure exists.
+fn styled(id: &str, seed: u64, index: u64) -> String {
+    ContentRegistry::builtin()
+        .name_style(id)
+        .map(|style| name(style, seed, index))
+        .unwrap_or_default()
+}
+
+fn point_index(point: Point) -> u64 {
+    u64::from(point.x as u32) | (u64::from(point.y as u32) << 32)
+}
+
+/// Next roll in `0..n`.
+fn roll(hash: &mut u64, n: u64) -> u64 {
+    *hash = mix(*hash);
+    *hash % n.max(1)
+}
+
+/// One of `parts` at random, or `""` if there are none.
+fn pick<'a>(parts: &'a [String], hash: &mut u64) -> &'a str {
+    let index = roll(hash, parts.len() as u64) as usize;
+    parts.get(index).map_or("", String::as_str)
+}
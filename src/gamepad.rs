@@ -0,0 +1,365 @@
+//! Gamepad input, read through `gilrs` in builds with the `gamepad`
+//! feature; without it no gamepad is ever connected.
+//!
+//! The left stick and the d-pad walk: a step as soon as they are pushed,
+//! then, after [`GamepadConfig::repeat_delay_ms`], one every
+//! [`GamepadConfig::repeat_interval_ms`] for as long as they are held.
+//! Buttons are bound to the [`Command`]s of the [keymap](crate::keymap). A
+//! button may have several, and does the first one the current [`Context`]
+//! [offers](Context::offers), so `B` closes what is open and cancels the
+//! queued action otherwise.
+//!
+//! Holding [`GamepadConfig::quick_menu`] opens a radial menu of
+//! [`QUICK_COMMANDS`], aimed with either stick and picked by letting go,
+//! so the commands without a button of their own are a flick away.
+
+#[cfg(feature = "gamepad")]
+use crate::game::Direction;
+use crate::keymap::{Command, Context};
+use crate::ui;
+
+#[cfg(feature = "gamepad")]
+use gilrs::{Axis, Button, EventType, Gilrs};
+use serde::{Deserialize, Serialize};
+
+/// Commands of the radial quick menu, clockwise from the top.
+pub const QUICK_COMMANDS: [Command; 8] = [
+    Command::Talk,
+    Command::Trade,
+    Command::Attack,
+    Command::Chop,
+    Command::Cancel,
+    Command::Chat,
+    Command::Map,
+    Command::CharacterSheet,
+];
+
+/// How far a stick must lean to aim the quick menu, as a fraction of the
+/// way out.
+const QUICK_DEAD_ZONE: f32 = 0.5;
+
+/// A gamepad button, named after where it sits on the pad.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PadButton {
+    South,
+    East,
+    West,
+    North,
+    LeftBumper,
+    RightBumper,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    LeftStick,
+    RightStick,
+}
+
+impl PadButton {
+    pub const ALL: [Self; 12] = [
+        Self::South,
+        Self::East,
+        Self::West,
+        Self::North,
+        Self::LeftBumper,
+        Self::RightBumper,
+        Self::LeftTrigger,
+        Self::RightTrigger,
+        Self::Select,
+        Self::Start,
+        Self::LeftStick,
+        Self::RightStick,
+    ];
+
+    /// Name of the button, as on an Xbox pad.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::South => "A",
+            Self::East => "B",
+            Self::West => "X",
+            Self::North => "Y",
+            Self::LeftBumper => "LB",
+            Self::RightBumper => "RB",
+            Self::LeftTrigger => "LT",
+            Self::RightTrigger => "RT",
+            Self::Select => "Back",
+            Self::Start => "Start",
+            Self::LeftStick => "Left stick",
+            Self::RightStick => "Right stick",
+        }
+    }
+
+    /// The button `gilrs` reports as `button`, if it is not one of the
+    /// d-pad's.
+    #[cfg(feature = "gamepad")]
+    fn of(button: Button) -> Option<Self> {
+        Some(match button {
+            Button::South => Self::South,
+            Button::East => Self::East,
+            Button::West => Self::West,
+            Button::North => Self::North,
+            Button::LeftTrigger => Self::LeftBumper,
+            Button::RightTrigger => Self::RightBumper,
+            Button::LeftTrigger2 => Self::LeftTrigger,
+            Button::RightTrigger2 => Self::RightTrigger,
+            Button::Select => Self::Select,
+            Button::Start => Self::Start,
+            Button::LeftThumb => Self::LeftStick,
+            Button::RightThumb => Self::RightStick,
+            _ => return None,
+        })
+    }
+}
+
+/// Gamepad settings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GamepadConfig {
+    /// How far the left stick must lean to walk, in percent of the way out.
+    pub dead_zone_percent: u32,
+    /// Time a direction is held before it repeats.
+    pub repeat_delay_ms: u32,
+    /// Time between two repeated steps.
+    pub repeat_interval_ms: u32,
+    /// Commands of each button; see [`command`](Self::command).
+    pub buttons: Vec<(PadButton, Command)>,
+    /// Button held for the radial quick menu.
+    pub quick_menu: PadButton,
+}
+
+impl Default for GamepadConfig {
+    fn default() -> Self {
+        Self {
+            dead_zone_percent: 40,
+            repeat_delay_ms: 300,
+            repeat_interval_ms: 150,
+            buttons: vec![
+                (PadButton::South, Command::Talk),
+                (PadButton::East, Command::Close),
+                (PadButton::East, Command::Cancel),
+                (PadButton::West, Command::Attack),
+                (PadButton::North, Command::Chop),
+                (PadButton::RightBumper, Command::CharacterSheet),
+                (PadButton::Select, Command::Map),
+                (PadButton::Start, Command::Pause),
+                (PadButton::Start, Command::Close),
+            ],
+            quick_menu: PadButton::LeftBumper,
+        }
+    }
+}
+
+impl GamepadConfig {
+    /// What `button` does in `context`: the first of its commands that the
+    /// context offers.
+    pub fn command(&self, button: PadButton, context: Context) -> Option<Command> {
+        self.buttons
+            .iter()
+            .filter(|(b, _)| *b == button)
+            .map(|(_, command)| *command)
+            .find(|command| context.offers(*command))
+    }
+
+    /// Give `button` the `command` it has while walking about, in place of
+    /// the one it had, keeping those it has elsewhere; `None` to unbind.
+    pub fn bind_gameplay(&mut self, button: PadButton, command: Option<Command>) {
+        self.buttons
+            .retain(|(b, c)| *b != button || !Context::Gameplay.commands().contains(c));
+        if let Some(command) = command {
+            self.buttons.push((button, command));
+        }
+    }
+
+    /// The direction the stick at `x`, `y` leans, up being positive, or
+    /// `None` within the dead zone.
+    #[cfg(feature = "gamepad")]
+    pub fn stick_direction(&self, x: f32, y: f32) -> Option<Direction> {
+        let dead_zone = self.dead_zone_percent as f32 / 100.0;
+        if x.abs().max(y.abs()) < dead_zone {
+            None
+        } else if x.abs() > y.abs() {
+            Some(if x > 0.0 {
+                Direction::Right
+            } else {
+                Direction::Left
+            })
+        } else {
+            Some(if y > 0.0 {
+                Direction::Up
+            } else {
+                Direction::Down
+            })
+        }
+    }
+}
+
+/// Steps of a direction held on the stick or d-pad.
+#[cfg(feature = "gamepad")]
+#[derive(Debug, Clone, Default)]
+pub struct Repeat {
+    held: Option<Direction>,
+    /// Time the next step is due, in seconds.
+    next: f64,
+}
+
+#[cfg(feature = "gamepad")]
+impl Repeat {
+    /// The step to take at `now`, in seconds, with `direction` held, if
+    /// one is due.
+    pub fn step(
+        &mut self,
+        direction: Direction,
+        now: f64,
+        config: &GamepadConfig,
+    ) -> Option<Direction> {
+        if self.held == Some(direction) {
+            if now < self.next {
+                return None;
+            }
+            self.next = now + f64::from(config.repeat_interval_ms) / 1000.0;
+        } else {
+            self.next = now + f64::from(config.repeat_delay_ms) / 1000.0;
+        }
+        self.held = Some(direction);
+        Some(direction)
+    }
+
+    /// Let go of the held direction.
+    pub fn release(&mut self) {
+        self.held = None;
+    }
+}
+
+/// The gamepads plugged in, and what their sticks and buttons are doing.
+#[cfg_attr(not(feature = "gamepad"), derive(Default))]
+pub struct Gamepads {
+    /// `None` where gamepads cannot be read.
+    #[cfg(feature = "gamepad")]
+    gilrs: Option<Gilrs>,
+    #[cfg(feature = "gamepad")]
+    repeat: Repeat,
+    /// Where the stick aims the quick menu, while it is open.
+    quick: Option<egui::Vec2>,
+}
+
+#[cfg(feature = "gamepad")]
+impl Default for Gamepads {
+    fn default() -> Self {
+        Self {
+            gilrs: Gilrs::new().ok(),
+            repeat: Repeat::default(),
+            quick: None,
+        }
+    }
+}
+
+impl Gamepads {
+    /// Returns `true` if a gamepad is plugged in.
+    #[cfg(feature = "gamepad")]
+    pub fn connected(&self) -> bool {
+        self.gilrs
+            .as_ref()
+            .is_some_and(|gilrs| gilrs.gamepads().next().is_some())
+    }
+
+    /// Returns `true` if a gamepad is plugged in: never, in this build.
+    #[cfg(not(feature = "gamepad"))]
+    #[expect(clippy::unused_self, reason = "the same call as with gamepads")]
+    pub fn connected(&self) -> bool {
+        false
+    }
+
+    /// Where the quick menu is aimed, each axis in `-1.0..=1.0` and down
+    /// positive, while it is open.
+    pub fn quick_aim(&self) -> Option<egui::Vec2> {
+        self.quick
+    }
+
+    /// The quick menu option `aim` picks, if it leans far enough.
+    pub fn quick_choice(aim: egui::Vec2) -> Option<usize> {
+        ui::radial_choice(
+            egui::Pos2::ZERO,
+            aim.to_pos2(),
+            QUICK_COMMANDS.len(),
+            QUICK_DEAD_ZONE,
+        )
+    }
+
+    /// What the gamepads asked for since the last call, at `now`, in
+    /// seconds, in `context`. Walking is only read where `context` offers
+    /// it, and the quick menu only opens where walking is.
+    #[cfg(feature = "gamepad")]
+    pub fn poll(&mut self, now: f64, config: &GamepadConfig, context: Context) -> Vec<Command> {
+        let Some(gilrs) = &mut self.gilrs else {
+            return Vec::new();
+        };
+        let walking = context.offers(Command::Move(Direction::Up));
+        let mut commands = Vec::new();
+        while let Some(event) = gilrs.next_event() {
+            match event.event {
+                EventType::ButtonPressed(button, _) => {
+                    let Some(button) = PadButton::of(button) else {
+                        continue;
+                    };
+                    if button == config.quick_menu {
+                        if walking {
+                            self.quick = Some(egui::Vec2::ZERO);
+                        }
+                    } else if let Some(command) = config.command(button, context) {
+                        commands.push(command);
+                    }
+                }
+                EventType::ButtonReleased(button, _)
+                    if PadButton::of(button) == Some(config.quick_menu) =>
+                {
+                    let picked = self.quick.take().and_then(Self::quick_choice);
+                    commands.extend(picked.and_then(|index| QUICK_COMMANDS.get(index)));
+                }
+                _ => {}
+            }
+        }
+        if !walking {
+            self.quick = None;
+        }
+
+        let mut held = None;
+        for (_, pad) in gilrs.gamepads() {
+            let stick = (pad.value(Axis::LeftStickX), pad.value(Axis::LeftStickY));
+            if let Some(aim) = &mut self.quick {
+                let right = egui::vec2(pad.value(Axis::RightStickX), -pad.value(Axis::RightStickY));
+                let left = egui::vec2(stick.0, -stick.1);
+                *aim = if right.length() > left.length() {
+                    right
+                } else {
+                    left
+                };
+                continue;
+            }
+            let dpad = [
+                (Button::DPadUp, Direction::Up),
+                (Button::DPadDown, Direction::Down),
+                (Button::DPadLeft, Direction::Left),
+                (Button::DPadRight, Direction::Right),
+            ]
+            .into_iter()
+            .find(|(button, _)| pad.is_pressed(*button))
+            .map(|(_, direction)| direction);
+            held = held
+                .or(dpad)
+                .or_else(|| config.stick_direction(stick.0, stick.1));
+        }
+        if let Some(direction) = held.filter(|_| walking) {
+            commands.extend(self.repeat.step(direction, now, config).map(Command::Move));
+        } else {
+            self.repeat.release();
+        }
+        commands
+    }
+
+    /// What the gamepads asked for: nothing, in this build.
+    #[cfg(not(feature = "gamepad"))]
+    pub fn poll(&mut self, _now: f64, _config: &GamepadConfig, _context: Context) -> Vec<Command> {
+        self.quick = None;
+        Vec::new()
+    }
+}
@@ -0,0 +1,315 @@
+//! Raw input recording and playback, for reproducing UI bugs.
+//!
+//! Bugs in focus handling, key repeat or targeting modes depend on exactly
+//! which events arrived and when, which is hard to do twice by hand. In
+//! debug builds, `F7` starts recording the raw events egui receives, with
+//! their modifiers and when each frame came, and stops, saving them to
+//! `<INPUTS_DIR>/<time>.ron`; `F8` plays the newest recording back, or
+//! stops playing. While playing, real input is ignored and the recorded
+//! events reach the app at the times they were recorded, through the same
+//! input handling. Pointer positions are in points, so recordings replay
+//! faithfully in a window of the size they were recorded in.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where input recordings are saved.
+pub const INPUTS_DIR: &str = "inputs";
+
+/// Layout version of an [`InputRecording`]; others are refused.
+pub const INPUT_FORMAT: u32 = 1;
+
+/// Key starting and stopping a recording.
+pub const RECORD_KEY: egui::Key = egui::Key::F7;
+
+/// Key playing back the newest recording, or stopping playback.
+pub const PLAY_KEY: egui::Key = egui::Key::F8;
+
+/// The input of one frame.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputFrame {
+    /// Seconds since the recording started.
+    pub time: f64,
+    pub modifiers: egui::Modifiers,
+    pub events: Vec<egui::Event>,
+}
+
+/// A recorded stretch of input: the frames that had events.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputRecording {
+    /// [`INPUT_FORMAT`] when saved.
+    pub format: u32,
+    /// Size of the window, in points, when recording started.
+    pub screen: [f32; 2],
+    pub frames: Vec<InputFrame>,
+}
+
+impl InputRecording {
+    /// Save to a new file in [`INPUTS_DIR`], returning its path.
+    pub fn save(&self, name: &str) -> io::Result<PathBuf> {
+        fs::create_dir_all(INPUTS_DIR)?;
+        let path = Path::new(INPUTS_DIR).join(format!("{name}.ron"));
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(io::Error::other)?;
+        fs::write(&path, text)?;
+        Ok(path)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let recording: Self =
+            ron::from_str(&fs::read_to_string(path)?).map_err(io::Error::other)?;
+        if recording.format != INPUT_FORMAT {
+            return Err(io::Error::other(format!(
+                "recorded in format {}, this build reads {INPUT_FORMAT}",
+                recording.format
+            )));
+        }
+        Ok(recording)
+    }
+
+    /// The most recently saved recording in [`INPUTS_DIR`].
+    pub fn newest() -> Option<PathBuf> {
+        fs::read_dir(INPUTS_DIR)
+            .ok()?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().and_then(|s| s.to_str()) == Some("ron"))
+            .max_by_key(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+    }
+}
+
+#[derive(Debug, Default)]
+enum State {
+    #[default]
+    Idle,
+    /// Recording since the first frame's time.
+    Recording {
+        started: Option<f64>,
+        recording: InputRecording,
+    },
+    /// Playing `recording`, whose frames before `next` were fed, since the
+    /// first frame's time, with the modifiers they left held.
+    Playing {
+        started: Option<f64>,
+        next: usize,
+        modifiers: egui::Modifiers,
+        recording: InputRecording,
+    },
+}
+
+/// Records raw input, or plays it back in place of the real input.
+#[derive(Debug, Default)]
+pub struct InputReplay {
+    state: State,
+}
+
+impl InputReplay {
+    pub fn is_recording(&self) -> bool {
+        matches!(self.state, State::Recording { .. })
+    }
+
+    pub fn is_playing(&self) -> bool {
+        matches!(self.state, State::Playing { .. })
+    }
+
+    /// Start recording, in a window of `screen` points; stops playback.
+    pub fn record(&mut self, screen: egui::Vec2) {
+        self.state = State::Recording {
+            started: None,
+            recording: InputRecording {
+                format: INPUT_FORMAT,
+                screen: [screen.x, screen.y],
+                frames: Vec::new(),
+            },
+        };
+    }
+
+    /// Play `recording` from the next frame on.
+    pub fn play(&mut self, recording: InputRecording) {
+        self.state = State::Playing {
+            started: None,
+            next: 0,
+            modifiers: egui::Modifiers::NONE,
+            recording,
+        };
+    }
+
+    /// Stop recording or playing, returning what was recorded.
+    pub fn stop(&mut self) -> Option<InputRecording> {
+        match std::mem::take(&mut self.state) {
+            State::Recording { recording, .. } => Some(recording),
+            State::Idle | State::Playing { .. } => None,
+        }
+    }
+
+    /// Record the frame's input in `raw`, or replace it with what was
+    /// recorded by now. Returns `true` once playback reached the end.
+    pub fn hook(&mut self, raw: &mut egui::RawInput) -> bool {
+        let now = raw.time.unwrap_or_default();
+        match &mut self.state {
+            State::Idle => false,
+            State::Recording { started, recording } => {
+                let started = *started.get_or_insert(now);
+                if !raw.events.is_empty() {
+                    recording.frames.push(InputFrame {
+                        time: now - started,
+                        modifiers: raw.modifiers,
+                        events: raw.events.clone(),
+                    });
+                }
+                false
+            }
+            State::Playing {
+                started,
+                next,
+                modifiers,
+                recording,
+            } => {
+                let elapsed = now - *started.get_or_insert(now);
+                raw.events.clear();
+                let due = recording
+                    .frames
+                    .iter()
+                    .skip(*next)
+                    .take_while(|frame| frame.time <= elapsed);
+                for frame in due {
+                    *modifiers = frame.modifiers;
+                    raw.events.extend(frame.events.iter().cloned());
+                    *next += 1;
+                }
+                raw.modifiers = *modifiers;
+                let done = *next >= recording.frames.len();
+                if done {
+                    self.state = State::Idle;
+                }
+                done
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::WorldId;
+
+    /// Show a text field for one frame of `raw` input, returning where it
+    /// is.
+    fn frame(ctx: &egui::Context, raw: egui::RawInput, text: &mut String) -> egui::Rect {
+        let mut rect = egui::Rect::NOTHING;
+        let _frame = ctx.run(raw, |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                rect = ui.text_edit_singleline(text).rect;
+            });
+        });
+        rect
+    }
+
+    fn at(time: f64, events: Vec<egui::Event>) -> egui::RawInput {
+        egui::RawInput {
+            time: Some(time),
+            events,
+            ..egui::RawInput::default()
+        }
+    }
+
+    /// Click the text field at `rect`, type, take a letter back and type
+    /// again, recording it all.
+    fn record_typing(rect: egui::Rect) -> (InputRecording, String) {
+        let button = |pressed| egui::Event::PointerButton {
+            pos: rect.center(),
+            button: egui::PointerButton::Primary,
+            pressed,
+            modifiers: egui::Modifiers::NONE,
+        };
+        let backspace = egui::Event::Key {
+            key: egui::Key::Backspace,
+            physical_key: None,
+            pressed: true,
+            repeat: false,
+            modifiers: egui::Modifiers::NONE,
+        };
+        let script = [
+            vec![],
+            vec![egui::Event::PointerMoved(rect.center())],
+            vec![button(true)],
+            vec![button(false)],
+            vec![],
+            vec![egui::Event::Text("hello".into())],
+            vec![backspace],
+            vec![egui::Event::Text("!".into())],
+        ];
+
+        let ctx = egui::Context::default();
+        let mut replay = InputReplay::default();
+        let mut typed = String::new();
+        frame(&ctx, at(9.0, Vec::new()), &mut typed);
+        replay.record(egui::vec2(800.0, 600.0));
+        assert!(replay.is_recording());
+        for (i, events) in script.into_iter().enumerate() {
+            let mut raw = at(10.0 + i as f64 * 0.1, events);
+            assert!(!replay.hook(&mut raw));
+            frame(&ctx, raw, &mut typed);
+        }
+        (replay.stop().expect("a recording"), typed)
+    }
+
+    #[test]
+    fn recorded_input_replays_to_the_same_state() {
+        let rect = frame(
+            &egui::Context::default(),
+            at(0.0, Vec::new()),
+            &mut String::new(),
+        );
+        let (recording, typed) = record_typing(rect);
+        assert_eq!(typed, "hell!", "the field took focus and the typing");
+        assert_eq!(recording.frames.len(), 6, "only frames with events");
+        assert!(recording.frames.first().is_some_and(|f| f.time > 0.0));
+
+        let path = std::env::temp_dir().join(format!("gamik-inputs-{}.ron", WorldId::generate()));
+        let text = ron::to_string(&recording).expect("recording serializes");
+        fs::write(&path, text).expect("written");
+        let loaded = InputRecording::load(&path).expect("loads");
+        fs::remove_file(&path).ok();
+        assert_eq!(loaded, recording);
+
+        // Real input is ignored, and frames come faster than recorded.
+        let ctx = egui::Context::default();
+        let mut replay = InputReplay::default();
+        replay.play(loaded);
+        let mut replayed = String::new();
+        let mut time = 100.0;
+        let mut done = false;
+        while !done {
+            assert!(replay.is_playing() && time < 102.0, "playback ends");
+            let mut raw = at(time, vec![egui::Event::Text("noise".into())]);
+            done = replay.hook(&mut raw);
+            frame(&ctx, raw, &mut replayed);
+            time += 0.03;
+        }
+        assert!(!replay.is_playing());
+        assert_eq!(replay.stop(), None, "playing records nothing");
+        assert_eq!(replayed, typed);
+    }
+
+    #[test]
+    fn recordings_of_another_format_or_broken_ones_are_refused() {
+        let path = std::env::temp_dir().join(format!("gamik-inputs-{}.ron", WorldId::generate()));
+        assert!(InputRecording::load(&path).is_err(), "missing");
+
+        fs::write(&path, "(format: 1, screen: (800.0").expect("written");
+        assert!(InputRecording::load(&path).is_err(), "cut short");
+
+        let newer = InputRecording {
+            format: INPUT_FORMAT + 1,
+            screen: [800.0, 600.0],
+            frames: Vec::new(),
+        };
+        fs::write(&path, ron::to_string(&newer).expect("serializes")).expect("written");
+        let err = InputRecording::load(&path).expect_err("another format");
+        assert!(err.to_string().contains("this build reads"), "{err}");
+        fs::remove_file(&path).ok();
+    }
+}
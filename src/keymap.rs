@@ -0,0 +1,419 @@
+//! Keys bound to what they do, by input context.
+//!
+//! What a key does depends on what the player is doing: walking about
+//! ([`Context::Gameplay`]), picking a target from the ping menu, typing in
+//! the console or the chat, or looking at a menu. Each [`Context`] has its
+//! own bindings, so a key can mean something else in each. A key that a
+//! context leaves unbound [falls through](Context::fallthrough) to the one
+//! beneath it, if any: while the ping menu is open the player still walks
+//! with the gameplay keys, but `Esc` closes the menu instead of pausing.
+//!
+//! Two commands on one key in the same context
+//! [conflict](Keymap::conflicts): the settings point them out, and only the
+//! command listed first works. A [`Keymap`] keeps only the bindings the
+//! player changed, so commands added later come with their default keys.
+
+use crate::client::Control;
+use crate::game::Direction;
+
+use serde::{Deserialize, Serialize};
+
+/// What the player is doing, which decides what keys do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Context {
+    /// Walking about the world.
+    Gameplay,
+    /// Picking a kind of ping from the ping menu.
+    Targeting,
+    /// Typing in the console.
+    Console,
+    /// Typing in the chat.
+    Editor,
+    /// In the pause menu, or a screen outside the world.
+    Menus,
+}
+
+impl Context {
+    pub const ALL: [Self; 5] = [
+        Self::Gameplay,
+        Self::Targeting,
+        Self::Console,
+        Self::Editor,
+        Self::Menus,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Gameplay => "Gameplay",
+            Self::Targeting => "Targeting",
+            Self::Console => "Console",
+            Self::Editor => "Chat",
+            Self::Menus => "Menus",
+        }
+    }
+
+    /// The context whose bindings apply to keys this one leaves unbound.
+    /// Those typing text have none, so their keys reach the text.
+    pub fn fallthrough(self) -> Option<Self> {
+        match self {
+            Self::Targeting => Some(Self::Gameplay),
+            Self::Gameplay | Self::Console | Self::Editor | Self::Menus => None,
+        }
+    }
+
+    /// Returns `true` if `command` works in this context, or in one it
+    /// falls through to.
+    pub fn offers(self, command: Command) -> bool {
+        let mut at = Some(self);
+        while let Some(context) = at {
+            if context.commands().contains(&command) {
+                return true;
+            }
+            at = context.fallthrough();
+        }
+        false
+    }
+
+    /// Commands that can be bound in this context, in the order their
+    /// bindings are looked at.
+    pub fn commands(self) -> &'static [Command] {
+        match self {
+            Self::Gameplay => &[
+                Command::Move(Direction::Up),
+                Command::Move(Direction::Down),
+                Command::Move(Direction::Left),
+                Command::Move(Direction::Right),
+                Command::Save,
+                Command::Talk,
+                Command::Attack,
+                Command::Chop,
+                Command::Cancel,
+                Command::Wait,
+                Command::WaitLong,
+                Command::Rest,
+                Command::Explore,
+                Command::CharacterSheet,
+                Command::Map,
+                Command::Trade,
+                Command::RecordMacro,
+                Command::PlayMacro,
+                Command::ProfilerOverlay,
+                Command::SpatialOverlay,
+                Command::FovOverlay,
+                Command::PathOverlay,
+                Command::ChunkOverlay,
+                Command::Chat,
+                Command::Console,
+                Command::Pause,
+            ],
+            Self::Targeting | Self::Editor => &[Command::Close],
+            Self::Console | Self::Menus => &[Command::Close, Command::Console],
+        }
+    }
+}
+
+/// What a key does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Command {
+    Move(Direction),
+    /// Save the world, when hosting.
+    Save,
+    Talk,
+    Attack,
+    Chop,
+    /// Cancel the queued action.
+    Cancel,
+    /// Pass a turn.
+    Wait,
+    /// Pass several turns.
+    WaitLong,
+    /// Pass turns until something comes into view or hurts the player,
+    /// once confirmed.
+    Rest,
+    /// Start or stop auto-exploring.
+    Explore,
+    CharacterSheet,
+    Map,
+    /// Trade with the nearest vendor, or stop.
+    Trade,
+    /// Start or stop recording a macro.
+    RecordMacro,
+    /// Play the newest macro, over and over with `Shift`.
+    PlayMacro,
+    ProfilerOverlay,
+    /// Show the spatial index, in debug builds.
+    SpatialOverlay,
+    /// Show what is in sight and what only in the awareness margin, in
+    /// debug builds.
+    FovOverlay,
+    /// Show the routes the server plans, in debug builds.
+    PathOverlay,
+    /// Show the chunk boundaries, in debug builds.
+    ChunkOverlay,
+    /// Open the chat.
+    Chat,
+    /// Open or close the console.
+    Console,
+    Pause,
+    /// Close what is open: the ping menu, the console, the chat, the pause
+    /// menu or a screen.
+    Close,
+}
+
+impl Command {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Move(Direction::Up) => "Move up",
+            Self::Move(Direction::Down) => "Move down",
+            Self::Move(Direction::Left) => "Move left",
+            Self::Move(Direction::Right) => "Move right",
+            Self::Save => "Save the world",
+            Self::Talk => "Talk",
+            Self::Attack => "Attack",
+            Self::Chop => "Chop",
+            Self::Cancel => "Cancel action",
+            Self::Wait => "Wait a turn",
+            Self::WaitLong => "Wait several turns",
+            Self::Rest => "Rest",
+            Self::Explore => "Auto-explore",
+            Self::CharacterSheet => "Character sheet",
+            Self::Map => "Map",
+            Self::Trade => "Trade",
+            Self::RecordMacro => "Record macro",
+            Self::PlayMacro => "Play macro",
+            Self::ProfilerOverlay => "Profiler",
+            Self::SpatialOverlay => "Spatial index",
+            Self::FovOverlay => "Field of view",
+            Self::PathOverlay => "Planned paths",
+            Self::ChunkOverlay => "Chunk boundaries",
+            Self::Chat => "Chat",
+            Self::Console => "Console",
+            Self::Pause => "Pause",
+            Self::Close => "Close",
+        }
+    }
+
+    /// The [`Control`] this command asks for, if it is one every frontend
+    /// offers.
+    pub fn control(self) -> Option<Control> {
+        match self {
+            Self::Move(direction) => Some(Control::Move(direction)),
+            Self::Save => Some(Control::Save),
+            Self::Talk => Some(Control::Talk),
+            Self::Attack => Some(Control::Attack),
+            Self::Chop => Some(Control::Chop),
+            Self::Cancel => Some(Control::Cancel),
+            Self::Wait => Some(Control::Wait),
+            _ => None,
+        }
+    }
+}
+
+/// Keys `command` has in `context` until the player binds others.
+pub fn default_keys(context: Context, command: Command) -> &'static [egui::Key] {
+    use egui::Key;
+    match (context, command) {
+        (Context::Gameplay, Command::Move(Direction::Up)) => &[Key::W, Key::ArrowUp],
+        (Context::Gameplay, Command::Move(Direction::Down)) => &[Key::S, Key::ArrowDown],
+        (Context::Gameplay, Command::Move(Direction::Left)) => &[Key::A, Key::ArrowLeft],
+        (Context::Gameplay, Command::Move(Direction::Right)) => &[Key::D, Key::ArrowRight],
+        (Context::Gameplay, Command::Save) => &[Key::R],
+        (Context::Gameplay, Command::Talk) => &[Key::T],
+        (Context::Gameplay, Command::Attack) => &[Key::X],
+        (Context::Gameplay, Command::Chop) => &[Key::G],
+        (Context::Gameplay, Command::Cancel) => &[Key::Q],
+        (Context::Gameplay, Command::Wait) => &[Key::Period],
+        (Context::Gameplay, Command::WaitLong) => &[Key::Comma],
+        (Context::Gameplay, Command::Rest) => &[Key::Semicolon],
+        (Context::Gameplay, Command::Explore) => &[Key::Z],
+        (Context::Gameplay, Command::CharacterSheet) => &[Key::C],
+        (Context::Gameplay, Command::Map) => &[Key::M],
+        (Context::Gameplay, Command::Trade) => &[Key::B],
+        (Context::Gameplay, Command::RecordMacro) => &[Key::F5],
+        (Context::Gameplay, Command::PlayMacro) => &[Key::F6],
+        (Context::Gameplay, Command::ProfilerOverlay) => &[Key::F4],
+        (Context::Gameplay, Command::SpatialOverlay) => &[Key::F3],
+        (Context::Gameplay, Command::FovOverlay) => &[Key::F9],
+        (Context::Gameplay, Command::PathOverlay) => &[Key::F10],
+        (Context::Gameplay, Command::ChunkOverlay) => &[Key::F11],
+        (Context::Gameplay, Command::Chat) => &[Key::Enter],
+        (Context::Gameplay | Context::Console | Context::Menus, Command::Console) => {
+            &[Key::Backtick]
+        }
+        (Context::Gameplay, Command::Pause) | (_, Command::Close) => &[Key::Escape],
+        _ => &[],
+    }
+}
+
+/// Two commands bound to the same key in one context.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Conflict {
+    pub context: Context,
+    pub key: egui::Key,
+    /// The command that works, then the one it hides.
+    pub commands: [Command; 2],
+}
+
+/// The keys of a command in a context, as the player bound them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Binding {
+    context: Context,
+    command: Command,
+    keys: Vec<egui::Key>,
+}
+
+/// The player's key bindings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Keymap {
+    /// Bindings changed from the [defaults](default_keys).
+    changed: Vec<Binding>,
+}
+
+impl Keymap {
+    /// Keys bound to `command` in `context`.
+    pub fn keys(&self, context: Context, command: Command) -> Vec<egui::Key> {
+        self.changed
+            .iter()
+            .find(|b| b.context == context && b.command == command)
+            .map_or_else(
+                || default_keys(context, command).to_vec(),
+                |b| b.keys.clone(),
+            )
+    }
+
+    /// Bind `command` in `context` to `keys` alone. Binding the default
+    /// keys, in any order, forgets the change.
+    pub fn bind(&mut self, context: Context, command: Command, keys: Vec<egui::Key>) {
+        self.changed
+            .retain(|b| b.context != context || b.command != command);
+        let defaults = default_keys(context, command);
+        let is_default = keys.len() == defaults.len() && keys.iter().all(|k| defaults.contains(k));
+        if !is_default {
+            self.changed.push(Binding {
+                context,
+                command,
+                keys,
+            });
+        }
+    }
+
+    /// Add `key` to the keys of `command` in `context`.
+    pub fn add_key(&mut self, context: Context, command: Command, key: egui::Key) {
+        let mut keys = self.keys(context, command);
+        if !keys.contains(&key) {
+            keys.push(key);
+            self.bind(context, command, keys);
+        }
+    }
+
+    /// Take `key` from the keys of `command` in `context`.
+    pub fn remove_key(&mut self, context: Context, command: Command, key: egui::Key) {
+        let mut keys = self.keys(context, command);
+        keys.retain(|k| *k != key);
+        self.bind(context, command, keys);
+    }
+
+    /// Go back to the default bindings.
+    pub fn reset(&mut self) {
+        self.changed.clear();
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.changed.is_empty()
+    }
+
+    /// Commands whose keys `is_pressed` says were pressed, in `context` and
+    /// those it falls through to, each once. A key bound in a context does
+    /// nothing in those beneath it, and only the first of two commands on
+    /// one key works.
+    pub fn pressed(
+        &self,
+        context: Context,
+        is_pressed: impl Fn(egui::Key) -> bool,
+    ) -> Vec<Command> {
+        let mut commands = Vec::new();
+        let mut taken: Vec<egui::Key> = Vec::new();
+        let mut at = Some(context);
+        while let Some(context) = at {
+            let mut bound_here = Vec::new();
+            for command in context.commands() {
+                for key in self.keys(context, *command) {
+                    if taken.contains(&key) || bound_here.contains(&key) {
+                        continue;
+                    }
+                    bound_here.push(key);
+                    if is_pressed(key) && !commands.contains(command) {
+                        commands.push(*command);
+                    }
+                }
+            }
+            taken.extend(bound_here);
+            at = context.fallthrough();
+        }
+        commands
+    }
+
+    /// Returns `true` if a key of `command` in `context` is held down, as
+    /// `is_down` says.
+    pub fn held(
+        &self,
+        context: Context,
+        command: Command,
+        is_down: impl Fn(egui::Key) -> bool,
+    ) -> bool {
+        self.keys(context, command).into_iter().any(is_down)
+    }
+
+    /// Every key bound to two commands in one context.
+    pub fn conflicts(&self) -> Vec<Conflict> {
+        let mut conflicts = Vec::new();
+        for context in Context::ALL {
+            let mut seen: Vec<(egui::Key, Command)> = Vec::new();
+            for command in context.commands() {
+                for key in self.keys(context, *command) {
+                    match seen.iter().find(|(k, _)| *k == key) {
+                        Some((_, first)) if first != command => conflicts.push(Conflict {
+                            context,
+                            key,
+                            commands: [*first, *command],
+                        }),
+                        Some(_) => {}
+                        None => seen.push((key, *command)),
+                    }
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// The command `key` would have in a context beneath `context`, which
+    /// binding it in `context` hides.
+    pub fn overrides(&self, context: Context, key: egui::Key) -> Option<(Context, Command)> {
+        let mut at = context.fallthrough();
+        while let Some(context) = at {
+            let bound = context
+                .commands()
+                .iter()
+                .find(|command| self.keys(context, **command).contains(&key));
+            if let Some(command) = bound {
+                return Some((context, *command));
+            }
+            at = context.fallthrough();
+        }
+        None
+    }
+
+    /// Names of the keys of `command` in `context`, for hints: "F5", or
+    /// "W / ↑"; "unbound" if there are none.
+    pub fn describe(&self, context: Context, command: Command) -> String {
+        let keys = self.keys(context, command);
+        if keys.is_empty() {
+            return "unbound".to_owned();
+        }
+        keys.iter()
+            .map(|key| key.symbol_or_name())
+            .collect::<Vec<_>>()
+            .join(" / ")
+    }
+}
@@ -2,7 +2,19 @@
 
 pub mod game;
 pub mod net;
+pub mod profile;
+pub mod server;
 pub mod ui;
+pub mod watch;
 
+mod alert;
 mod app;
+pub mod client;
+mod config;
+pub mod crash;
+pub mod export;
+mod gamepad;
+mod input_replay;
+mod keymap;
+mod power;
 pub use app::GamikApp;
@@ -5,6 +5,10 @@
 #[cfg(not(target_arch = "wasm32"))]
 #[tokio::main]
 async fn main() -> eframe::Result {
+    // Log to stderr; `RUST_LOG=debug` shows more.
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn,gamik=info"))
+        .init();
+
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([400.0, 300.0])
@@ -16,11 +20,16 @@ async fn main() -> eframe::Result {
             ),
         ..Default::default()
     };
-    eframe::run_native(
+    gamik::crash::install();
+    let result = eframe::run_native(
         "gamik",
         native_options,
         Box::new(|cc| Ok(Box::new(gamik::GamikApp::new(cc)))),
-    )
+    );
+    if let Err(e) = &result {
+        gamik::crash::fatal(&format!("the window could not be run: {e}")).ok();
+    }
+    result
 }
 
 // When compiling to web using trunk:
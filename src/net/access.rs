@@ -0,0 +1,429 @@
+//! Protected servers: an optional password and invite codes.
+//!
+//! A client sends the password or code it was given in its
+//! [`ClientMessage::Sync`](super::ClientMessage::Sync); the server admits it
+//! or answers with [`ServerMessage::AccessDenied`](super::ServerMessage::AccessDenied)
+//! and sends no world. Until admitted, a client can do nothing but try
+//! again. Only Argon2 hashes of the secrets, under a random salt, are
+//! kept, in memory and in `<world id>.ron` under [`ACCESS_DIR`]. Hosts set
+//! them from the console, or with
+//! [`ServerBuilder::password`](crate::server::ServerBuilder::password) and
+//! [`invite`](crate::server::ServerBuilder::invite).
+//!
+//! Wrong guesses are limited per endpoint and, since a fresh endpoint costs
+//! nothing to make, across the whole server too. The limits count wall
+//! clock time, not ticks, so they run out even while an empty server is
+//! paused. The same file keeps the player cap, if any; see
+//! [`queue`](super::queue).
+
+use crate::game::{PlayerKey, WorldId};
+
+use argon2::Argon2;
+use bitcode::{Decode, Encode};
+use iroh::EndpointId;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Where servers keep who may join each world.
+pub const ACCESS_DIR: &str = "access";
+
+/// Longest password or invite code accepted, in bytes.
+pub const MAX_SECRET_LEN: usize = 128;
+
+/// Characters in a generated invite code.
+pub const INVITE_CODE_LEN: usize = 10;
+
+/// Wrong guesses an endpoint may make per [`FAILURE_WINDOW`].
+pub const MAX_FAILURES: u32 = 5;
+
+/// Wrong guesses the whole server takes per [`FAILURE_WINDOW`].
+pub const MAX_SERVER_FAILURES: u32 = 50;
+
+/// Length of the window wrong guesses are counted in.
+pub const FAILURE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Bytes of the salt secrets are hashed with.
+const SALT_LEN: usize = 16;
+
+/// A password or invite code as kept: its Argon2id hash.
+type Digest = [u8; 32];
+
+/// Why a client was not let in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub enum Denied {
+    /// The client sent no password or code.
+    PasswordRequired,
+    WrongPassword,
+    /// Too many wrong guesses lately, from this endpoint or all of them.
+    TooManyAttempts,
+    /// The world is at its player cap and the queue to join is full too.
+    ServerFull,
+}
+
+impl fmt::Display for Denied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::PasswordRequired => "this server needs a password or invite code",
+            Self::WrongPassword => "wrong password or invite code",
+            Self::TooManyAttempts => "too many wrong attempts; try again in a minute",
+            Self::ServerFull => "the server is full, and so is its queue",
+        })
+    }
+}
+
+/// The secrets that let clients in, as configured by the host.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessRules {
+    /// Salt of every hash below, picked when the first secret is set.
+    salt: Option<[u8; SALT_LEN]>,
+    /// Hash of the password, if there is one.
+    password: Option<Digest>,
+    /// Hashes of the invite codes, with the uses each has left; `None` for
+    /// no limit.
+    invites: BTreeMap<Digest, Option<u32>>,
+    /// Most players in the world at once, if capped; clients past it wait
+    /// in the [`JoinQueue`](super::queue::JoinQueue).
+    #[serde(default)]
+    pub max_players: Option<u32>,
+    /// Players besides the [host](Gate::host) who may run every console
+    /// command. See [`console`](super::console).
+    #[serde(default)]
+    pub admins: BTreeSet<PlayerKey>,
+}
+
+impl AccessRules {
+    /// Path of the rules of world `world_id`.
+    pub fn path(world_id: WorldId) -> PathBuf {
+        PathBuf::from(ACCESS_DIR).join(format!("{world_id}.ron"))
+    }
+
+    /// Load the rules of world `world_id`, or open ones if none were saved.
+    ///
+    /// # Errors
+    /// If the rules cannot be read or parsed.
+    pub fn load(world_id: WorldId) -> io::Result<Self> {
+        match fs::read_to_string(Self::path(world_id)) {
+            Ok(text) => ron::from_str(&text).map_err(io::Error::other),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Save the rules of world `world_id`.
+    ///
+    /// # Errors
+    /// If the rules cannot be written.
+    pub fn save(&self, world_id: WorldId) -> io::Result<()> {
+        fs::create_dir_all(ACCESS_DIR)?;
+        let text = ron::to_string(self).map_err(io::Error::other)?;
+        fs::write(Self::path(world_id), text)
+    }
+
+    /// Returns `true` if anyone may join.
+    pub fn is_open(&self) -> bool {
+        self.password.is_none() && self.invites.is_empty()
+    }
+
+    pub fn has_password(&self) -> bool {
+        self.password.is_some()
+    }
+
+    /// Invite codes still usable.
+    pub fn invite_count(&self) -> usize {
+        self.invites.len()
+    }
+
+    /// Require `password`, or stop requiring one.
+    ///
+    /// # Panics
+    /// If the OS has no random numbers to give for the salt.
+    pub fn set_password(&mut self, password: Option<&str>) {
+        self.password = password.map(|p| self.digest(p));
+    }
+
+    /// Make an invite code good for `uses` joins, or any number.
+    ///
+    /// # Panics
+    /// If the OS has no random numbers to give.
+    pub fn create_invite(&mut self, uses: Option<u32>) -> String {
+        // 32 letters and digits, so each random byte picks one evenly.
+        const ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+        let mut bytes = [0; INVITE_CODE_LEN];
+        getrandom::fill(&mut bytes).expect("the OS gives random numbers");
+        let code: String = bytes
+            .iter()
+            .map(|byte| {
+                let index = usize::from(*byte) % ALPHABET.len();
+                char::from(ALPHABET.get(index).copied().unwrap_or(b'A'))
+            })
+            .collect();
+        self.add_invite(&code, uses);
+        code
+    }
+
+    /// Let in clients with invite code `code`, for `uses` joins or any
+    /// number.
+    ///
+    /// # Panics
+    /// If the OS has no random numbers to give for the salt.
+    pub fn add_invite(&mut self, code: &str, uses: Option<u32>) {
+        let hash = self.digest(code);
+        self.invites.insert(hash, uses);
+    }
+
+    /// Withdraw invite code `code`. Returns `false` if there is no such code.
+    pub fn revoke(&mut self, code: &str) -> bool {
+        let hash = self.digest(code);
+        self.invites.remove(&hash).is_some()
+    }
+
+    /// Whether `secret` lets a client in, using up an invite if it is one.
+    fn admit(&mut self, secret: &str) -> bool {
+        let hash = self.digest(secret);
+        if self.password == Some(hash) {
+            return true;
+        }
+        match self.invites.get_mut(&hash) {
+            None => false,
+            Some(None) => true,
+            Some(Some(uses)) => {
+                *uses = uses.saturating_sub(1);
+                if *uses == 0 {
+                    self.invites.remove(&hash);
+                }
+                true
+            }
+        }
+    }
+
+    /// The hash of `secret` under the rules' salt, picking the salt first
+    /// if there is none yet.
+    fn digest(&mut self, secret: &str) -> Digest {
+        let salt = self.salt.get_or_insert_with(|| {
+            let mut salt = [0; SALT_LEN];
+            getrandom::fill(&mut salt).expect("the OS gives random numbers");
+            salt
+        });
+        let mut hash = Digest::default();
+        Argon2::default()
+            .hash_password_into(secret.as_bytes(), salt, &mut hash)
+            .expect("Argon2 takes any secret of at most 4 GiB with this salt and output");
+        hash
+    }
+}
+
+/// Wrong guesses in the current window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Failures {
+    since: Instant,
+    count: u32,
+}
+
+impl Failures {
+    const fn new(now: Instant) -> Self {
+        Self {
+            since: now,
+            count: 0,
+        }
+    }
+
+    /// Whether the window has passed at `now`.
+    fn expired(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.since) >= FAILURE_WINDOW
+    }
+}
+
+/// The [`AccessRules`] of a server, and the wrong guesses made lately.
+#[derive(Debug, Default)]
+pub struct Gate {
+    pub rules: AccessRules,
+    /// The endpoint the game hosting the server plays from, whose player
+    /// may run every console command whoever the
+    /// [`admins`](AccessRules::admins) are. Told by the endpoint ID the
+    /// connection proved, since player keys are sent to every server
+    /// joined. Not saved: a server hosted without the game has no such
+    /// player, and the game plays from a fresh endpoint each time.
+    pub host: Option<EndpointId>,
+    failures: FxHashMap<EndpointId, Failures>,
+    /// Wrong guesses from every endpoint, once there were any.
+    server_failures: Option<Failures>,
+}
+
+impl Gate {
+    /// Check the `secret` `endpoint` sent at `now`.
+    ///
+    /// # Errors
+    /// With why the endpoint is refused.
+    pub fn check(
+        &mut self,
+        endpoint: EndpointId,
+        secret: Option<&str>,
+        now: Instant,
+    ) -> Result<(), Denied> {
+        if self.rules.is_open() {
+            return Ok(());
+        }
+        self.failures.retain(|_, f| !f.expired(now));
+        if self.server_failures.is_some_and(|f| f.expired(now)) {
+            self.server_failures = None;
+        }
+        let own = self.failures.get(&endpoint).map_or(0, |f| f.count);
+        let all = self.server_failures.map_or(0, |f| f.count);
+        if own >= MAX_FAILURES || all >= MAX_SERVER_FAILURES {
+            return Err(Denied::TooManyAttempts);
+        }
+        let Some(secret) = secret else {
+            return Err(Denied::PasswordRequired);
+        };
+        if self.rules.admit(secret) {
+            return Ok(());
+        }
+        let new = || Failures::new(now);
+        self.failures.entry(endpoint).or_insert_with(new).count += 1;
+        self.server_failures.get_or_insert_with(new).count += 1;
+        Err(Denied::WrongPassword)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoint(n: u8) -> EndpointId {
+        iroh::SecretKey::from_bytes(&[n; 32]).public()
+    }
+
+    fn protected(password: &str) -> Gate {
+        let mut gate = Gate::default();
+        gate.rules.set_password(Some(password));
+        gate
+    }
+
+    #[test]
+    fn secrets_are_kept_only_as_salted_hashes() {
+        let mut rules = AccessRules::default();
+        rules.set_password(Some("hunter2"));
+        let code = rules.create_invite(None);
+        let text = ron::to_string(&rules).expect("rules serialize");
+        assert!(!text.contains("hunter2") && !text.contains(&code), "{text}");
+
+        // The same password hashes differently under another salt.
+        let mut other = AccessRules::default();
+        other.set_password(Some("hunter2"));
+        assert_ne!(rules.password, other.password);
+
+        let mut loaded: AccessRules = ron::from_str(&text).expect("rules parse");
+        assert_eq!(loaded, rules);
+        assert!(loaded.admit("hunter2"));
+        assert!(loaded.admit(&code));
+        assert!(!loaded.admit("hunter3"));
+    }
+
+    #[test]
+    fn rules_from_before_argon2_do_not_parse() {
+        let old = "(salt: 12345, password: Some(678), invites: {})";
+        assert!(ron::from_str::<AccessRules>(old).is_err());
+    }
+
+    #[test]
+    fn invite_codes_are_random_and_used_up() {
+        let mut rules = AccessRules::default();
+        let once = rules.create_invite(Some(1));
+        let forever = rules.create_invite(None);
+        assert_ne!(once, forever);
+        for code in [&once, &forever] {
+            assert_eq!(code.len(), INVITE_CODE_LEN);
+            assert!(
+                code.chars()
+                    .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+            );
+        }
+
+        assert!(rules.admit(&once));
+        assert!(!rules.admit(&once), "good for one join");
+        assert!(rules.admit(&forever) && rules.admit(&forever));
+
+        assert!(rules.revoke(&forever));
+        assert!(!rules.revoke(&forever), "already withdrawn");
+        assert!(!rules.admit(&forever));
+        assert!(rules.is_open());
+
+        rules.add_invite("FRIENDS", Some(2));
+        assert!(!rules.is_open());
+        assert!(rules.admit("FRIENDS"));
+        assert!(!rules.admit("friends"), "codes are exact");
+    }
+
+    #[test]
+    fn gate_tells_a_missing_secret_from_a_wrong_one() {
+        let a = endpoint(1);
+        let now = Instant::now();
+        assert_eq!(Gate::default().check(a, None, now), Ok(()), "open");
+
+        let mut gate = protected("hunter2");
+        assert_eq!(gate.check(a, None, now), Err(Denied::PasswordRequired));
+        assert_eq!(
+            gate.check(a, Some("hunter3"), now),
+            Err(Denied::WrongPassword)
+        );
+        assert_eq!(gate.check(a, Some("hunter2"), now), Ok(()));
+
+        gate.rules.set_password(None);
+        assert_eq!(gate.check(a, None, now), Ok(()), "open again");
+    }
+
+    #[test]
+    fn wrong_guesses_lock_out_an_endpoint_for_a_window_of_wall_clock_time() {
+        let mut gate = protected("hunter2");
+        let (a, b) = (endpoint(1), endpoint(2));
+        let start = Instant::now();
+        for _ in 0..MAX_FAILURES {
+            assert_eq!(
+                gate.check(a, Some("guess"), start),
+                Err(Denied::WrongPassword)
+            );
+        }
+        let later = start + FAILURE_WINDOW / 2;
+        assert_eq!(
+            gate.check(a, Some("hunter2"), later),
+            Err(Denied::TooManyAttempts)
+        );
+        assert_eq!(gate.check(b, Some("hunter2"), later), Ok(()));
+
+        // However long the server was paused, the window runs out.
+        assert_eq!(
+            gate.check(a, Some("hunter2"), start + FAILURE_WINDOW),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn wrong_guesses_from_many_endpoints_lock_out_everyone_for_a_while() {
+        let mut gate = protected("hunter2");
+        let start = Instant::now();
+        let guessers = (0..=u8::MAX)
+            .map(endpoint)
+            .take(MAX_SERVER_FAILURES as usize);
+        for guesser in guessers {
+            assert_eq!(
+                gate.check(guesser, Some("guess"), start),
+                Err(Denied::WrongPassword)
+            );
+        }
+        let fresh = endpoint(u8::MAX);
+        assert_eq!(
+            gate.check(fresh, Some("hunter2"), start),
+            Err(Denied::TooManyAttempts)
+        );
+        assert_eq!(
+            gate.check(fresh, Some("hunter2"), start + FAILURE_WINDOW),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,406 @@
+//! Side channel for larger blobs: content pack files, tilesets, world
+//! previews.
+//!
+//! Assets travel on a connection of their own, under [`ASSETS_ALPN`], so a
+//! download never holds up game updates, and serving one never takes the
+//! [`ServerState`](super::ServerState) lock: the [`AssetStore`] is fixed
+//! when the server starts.
+//!
+//! Each request is one bidirectional stream. The client sends an
+//! [`AssetRequest`] naming the asset and the offset to start from; the
+//! server answers with an [`AssetHeader`], length-prefixed, and then the
+//! asset's bytes from that offset in chunks of [`ASSET_CHUNK_SIZE`]. A
+//! [`Download`] keeps what arrived, so a dropped transfer resumes where it
+//! stopped, and checks the whole against the header's hash before handing
+//! it out.
+
+use super::{RECONNECT_DELAY, decode, recv_one_way};
+use crate::game::content::Sources;
+use crate::game::pack::{self, BUILTIN};
+
+use bitcode::{Decode, Encode};
+use iroh::{
+    Endpoint, EndpointAddr,
+    endpoint::{Connection, RecvStream, SendStream, VarInt},
+    protocol::{AcceptError, ProtocolHandler},
+};
+use n0_error::StdResultExt as _;
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::Arc;
+
+/// ALPN of asset connections, next to the game's own.
+pub const ASSETS_ALPN: &[u8] = b"gamik/assets/0";
+
+/// Bytes written to the stream at a time, and between progress reports.
+pub const ASSET_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Longest asset name, in characters.
+pub const MAX_ASSET_NAME_LEN: usize = 128;
+
+/// Largest asset a client accepts.
+pub const MAX_ASSET_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Connections tried per download before giving up.
+const FETCH_ATTEMPTS: u32 = 5;
+
+/// Asks for an asset, or the rest of one.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct AssetRequest {
+    /// e.g. `builtin/items.ron`; see [`AssetStore::names`].
+    pub name: String,
+    /// Bytes the client already has.
+    pub offset: u64,
+}
+
+/// The server's answer to an [`AssetRequest`], before the bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub enum AssetHeader {
+    /// The asset's full length and hash; the bytes from the requested
+    /// offset follow.
+    Found { len: u64, hash: u64 },
+    /// The server has no asset by that name.
+    Missing,
+}
+
+#[derive(Debug, Clone)]
+struct Asset {
+    bytes: Arc<[u8]>,
+    hash: u64,
+}
+
+/// Assets a server offers, by name.
+#[derive(Debug, Clone, Default)]
+pub struct AssetStore {
+    assets: BTreeMap<String, Asset>,
+}
+
+impl AssetStore {
+    /// A store with the files of the [`BUILTIN`] pack, as
+    /// `builtin/<file>`.
+    pub fn builtin() -> Self {
+        let mut store = Self::default();
+        for (file, source) in Sources::BUILTIN.files() {
+            store.insert(format!("{BUILTIN}/{file}"), source.as_bytes().to_vec());
+        }
+        store
+    }
+
+    /// Offer `bytes` under `name`, replacing any asset by that name.
+    pub fn insert(&mut self, name: String, bytes: Vec<u8>) {
+        let hash = pack::hash_bytes(&bytes);
+        let bytes = bytes.into();
+        self.assets.insert(name, Asset { bytes, hash });
+    }
+
+    /// Names of the assets on offer, in order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.assets.keys().map(String::as_str)
+    }
+
+    /// What to send in reply to `request`.
+    pub fn answer(&self, request: &AssetRequest) -> Answer {
+        match self.assets.get(&request.name) {
+            Some(asset) => {
+                let len = asset.bytes.len();
+                Answer {
+                    header: AssetHeader::Found {
+                        len: len as u64,
+                        hash: asset.hash,
+                    },
+                    bytes: asset.bytes.clone(),
+                    from: usize::try_from(request.offset).map_or(len, |offset| offset.min(len)),
+                }
+            }
+            None => Answer {
+                header: AssetHeader::Missing,
+                bytes: Arc::from([]),
+                from: 0,
+            },
+        }
+    }
+}
+
+/// A reply to one [`AssetRequest`]: the header, then the asset from the
+/// requested offset on.
+#[derive(Debug, Clone)]
+pub struct Answer {
+    pub header: AssetHeader,
+    bytes: Arc<[u8]>,
+    from: usize,
+}
+
+impl Answer {
+    /// The bytes to send after the header, [`ASSET_CHUNK_SIZE`] at a time.
+    pub fn chunks(&self) -> impl Iterator<Item = &[u8]> {
+        self.bytes
+            .get(self.from..)
+            .unwrap_or_default()
+            .chunks(ASSET_CHUNK_SIZE)
+    }
+}
+
+/// How far a download has got.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub received: u64,
+    /// Full length of the asset, once the server said.
+    pub total: Option<u64>,
+}
+
+impl Progress {
+    /// Share received, from 0 to 1; 0 until the length is known.
+    pub fn fraction(self) -> f32 {
+        match self.total {
+            Some(0) => 1.0,
+            Some(total) => (self.received as f64 / total as f64) as f32,
+            None => 0.0,
+        }
+    }
+}
+
+/// An asset being downloaded, kept across dropped connections.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Download {
+    name: String,
+    /// Length and hash of the asset, from the first header.
+    expected: Option<(u64, u64)>,
+    bytes: Vec<u8>,
+}
+
+impl Download {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            expected: None,
+            bytes: Vec::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The request that continues the download.
+    pub fn request(&self) -> AssetRequest {
+        AssetRequest {
+            name: self.name.clone(),
+            offset: self.bytes.len() as u64,
+        }
+    }
+
+    pub fn progress(&self) -> Progress {
+        Progress {
+            received: self.bytes.len() as u64,
+            total: self.expected.map(|(len, _)| len),
+        }
+    }
+
+    /// Take the server's header for the last [`Download::request`].
+    /// Returns `Ok(false)` if the asset changed since the download started:
+    /// what had arrived is dropped, and the download has to be requested
+    /// again from the start.
+    ///
+    /// # Errors
+    /// If the server has no such asset, or it is larger than
+    /// [`MAX_ASSET_SIZE`].
+    pub fn begin(&mut self, header: AssetHeader) -> Result<bool, String> {
+        let AssetHeader::Found { len, hash } = header else {
+            return Err(format!("the server has no asset `{}`", self.name));
+        };
+        if len > MAX_ASSET_SIZE {
+            return Err(format!("asset `{}` is too large ({len} bytes)", self.name));
+        }
+        match self.expected {
+            Some(expected) if expected != (len, hash) => {
+                self.expected = Some((len, hash));
+                self.bytes.clear();
+                Ok(false)
+            }
+            Some(_) => Ok(true),
+            None => {
+                self.expected = Some((len, hash));
+                self.bytes.reserve(usize::try_from(len).unwrap_or_default());
+                Ok(true)
+            }
+        }
+    }
+
+    /// Add bytes that arrived after the header.
+    ///
+    /// # Errors
+    /// If they run past the asset's length.
+    pub fn push(&mut self, chunk: &[u8]) -> Result<Progress, String> {
+        let len = self.expected.map_or(0, |(len, _)| len);
+        if (self.bytes.len() + chunk.len()) as u64 > len {
+            return Err(format!("asset `{}` is longer than announced", self.name));
+        }
+        self.bytes.extend_from_slice(chunk);
+        Ok(self.progress())
+    }
+
+    /// Returns `true` once every byte arrived.
+    pub fn is_complete(&self) -> bool {
+        self.expected
+            .is_some_and(|(len, _)| self.bytes.len() as u64 == len)
+    }
+
+    /// Take the downloaded asset, leaving the download to start over.
+    ///
+    /// # Errors
+    /// If it is incomplete, or does not match the announced hash; a
+    /// corrupt download is dropped.
+    pub fn finish(&mut self) -> Result<Vec<u8>, String> {
+        let Some((_, hash)) = self.expected.filter(|_| self.is_complete()) else {
+            return Err(format!("asset `{}` is incomplete", self.name));
+        };
+        self.expected = None;
+        let bytes = std::mem::take(&mut self.bytes);
+        if pack::hash_bytes(&bytes) == hash {
+            Ok(bytes)
+        } else {
+            Err(format!("asset `{}` is corrupt", self.name))
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Server
+// ---------------------------------------------------------------------------
+
+/// Serves an [`AssetStore`] to anyone who connects under [`ASSETS_ALPN`].
+#[derive(Debug, Clone)]
+pub struct AssetServer {
+    store: Arc<AssetStore>,
+}
+
+impl AssetServer {
+    pub fn new(store: AssetStore) -> Self {
+        Self {
+            store: Arc::new(store),
+        }
+    }
+}
+
+impl ProtocolHandler for AssetServer {
+    async fn accept(&self, connection: Connection) -> std::result::Result<(), AcceptError> {
+        while let Ok((send, recv)) = connection.accept_bi().await {
+            let store = self.store.clone();
+            tokio::spawn(async move {
+                if let Err(e) = serve(&store, send, recv).await {
+                    log::warn!("Error sending asset: {e}");
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Answer the request on one stream.
+async fn serve(store: &AssetStore, mut send: SendStream, recv: RecvStream) -> n0_error::Result<()> {
+    let bytes = recv_one_way(recv, decode::MAX_ASSET_REQUEST_SIZE).await?;
+    let request = decode::decode_asset_request(&bytes).anyerr()?;
+    let answer = store.answer(&request);
+    let header = bitcode::encode(&answer.header);
+    let header_len = u16::try_from(header.len()).anyerr()?;
+    send.write_all(&header_len.to_le_bytes()).await.anyerr()?;
+    send.write_all(&header).await.anyerr()?;
+    for chunk in answer.chunks() {
+        send.write_all(chunk).await.anyerr()?;
+    }
+    send.finish().anyerr()?;
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Client
+// ---------------------------------------------------------------------------
+
+/// How one connection's worth of a download went.
+enum Attempt {
+    Done,
+    /// The asset changed on the server; ask again from the start.
+    Restart,
+    Refused(String),
+}
+
+/// Download the asset `download` names from the server at `addr`, on a
+/// connection of its own.
+///
+/// `on_progress` is called as chunks arrive. A dropped
+/// connection is retried, resuming where it stopped; `download` keeps what
+/// arrived if it still fails, to resume later.
+///
+/// # Errors
+/// If the server refuses the asset, it arrives corrupt, or the server
+/// cannot be reached.
+pub async fn fetch(
+    addr: impl Into<EndpointAddr>,
+    download: &mut Download,
+    mut on_progress: impl FnMut(Progress),
+) -> Result<Vec<u8>, String> {
+    let addr = addr.into();
+    let endpoint = Endpoint::bind().await.map_err(|e| e.to_string())?;
+    let mut last_error = String::new();
+    for _ in 0..FETCH_ATTEMPTS {
+        match fetch_once(&endpoint, addr.clone(), download, &mut on_progress).await {
+            Ok(Attempt::Done) => return download.finish(),
+            Ok(Attempt::Restart) => {}
+            Ok(Attempt::Refused(reason)) => return Err(reason),
+            Err(e) => {
+                last_error = e.to_string();
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+    Err(format!(
+        "could not download `{}`: {last_error}",
+        download.name()
+    ))
+}
+
+/// Connect, ask for the rest of `download` and take what arrives.
+async fn fetch_once(
+    endpoint: &Endpoint,
+    addr: EndpointAddr,
+    download: &mut Download,
+    on_progress: &mut impl FnMut(Progress),
+) -> n0_error::Result<Attempt> {
+    let conn = endpoint.connect(addr, ASSETS_ALPN).await?;
+    let (mut send, mut recv) = conn.open_bi().await.anyerr()?;
+    send.write_all(&bitcode::encode(&download.request()))
+        .await
+        .anyerr()?;
+    send.finish().anyerr()?;
+
+    let mut header_len = [0; 2];
+    recv.read_exact(&mut header_len).await.anyerr()?;
+    let mut header = vec![0; usize::from(u16::from_le_bytes(header_len))];
+    recv.read_exact(&mut header).await.anyerr()?;
+    let header = decode::decode_asset_header(&header).anyerr()?;
+    match download.begin(header) {
+        Ok(true) => {}
+        Ok(false) => return Ok(Attempt::Restart),
+        Err(reason) => return Ok(Attempt::Refused(reason)),
+    }
+    on_progress(download.progress());
+
+    let mut buffer = vec![0; ASSET_CHUNK_SIZE];
+    while !download.is_complete() {
+        let Some(read) = recv.read(&mut buffer).await.anyerr()? else {
+            break;
+        };
+        let chunk = buffer.get(..read).unwrap_or_default();
+        match download.push(chunk) {
+            Ok(progress) => on_progress(progress),
+            Err(reason) => return Ok(Attempt::Refused(reason)),
+        }
+    }
+    conn.close(VarInt::from_u32(0), b"done");
+    if download.is_complete() {
+        Ok(Attempt::Done)
+    } else {
+        // The stream ended early; the next attempt picks up from here.
+        Err(io::Error::from(io::ErrorKind::UnexpectedEof)).anyerr()
+    }
+}
@@ -0,0 +1,322 @@
+//! A reference [`SaveSync`]: saves kept on another iroh node, such as a
+//! personal backup peer. Built with the `backup-peer` feature.
+//!
+//! The peer runs a [`BackupServer`] (the `backup` binary), which keeps the
+//! saves of every node connecting under [`BACKUP_ALPN`] in a directory of
+//! that node's own. [`PeerSync`], installed with
+//! [`persist::install_sync`](crate::game::persist::install_sync), sends
+//! each save there once written and, before a world is loaded, brings in
+//! the peer's copy if it is newer than the local one, e.g. after playing on
+//! another machine. A peer that cannot be reached is skipped: the game
+//! plays from local files either way.
+//!
+//! Each transfer is one bidirectional stream: a length-prefixed
+//! [`BackupRequest`] and, when storing, the file's bytes. The peer answers
+//! with a length-prefixed [`BackupHeader`] and, when fetching, the bytes.
+//! Files are named by their path relative to the game's directory, e.g.
+//! `worlds/woods.world`.
+
+use super::decode;
+use crate::game::{SaveHeader, SaveSync};
+
+use bitcode::{Decode, Encode};
+use iroh::{
+    Endpoint, EndpointAddr, EndpointId,
+    endpoint::{Connection, RecvStream, SendStream, VarInt},
+    protocol::{AcceptError, ProtocolHandler},
+};
+use n0_error::StdResultExt as _;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+use tokio::runtime::Handle;
+
+/// ALPN of backup connections.
+pub const BACKUP_ALPN: &[u8] = b"gamik/backup/0";
+
+/// Largest file a backup peer stores or a client takes back.
+pub const MAX_BACKUP_SIZE: usize = 256 * 1024 * 1024;
+
+/// Longest file name in a request, in characters.
+pub const MAX_BACKUP_NAME_LEN: usize = 256;
+
+/// How long a load waits for the peer's copy before going on without it.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What a node asks of its backup peer.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum BackupRequest {
+    /// Keep the file `name`, last written `modified` seconds after the Unix
+    /// epoch; its bytes follow.
+    Store { name: String, modified: u64 },
+    /// Send the file `name`.
+    Fetch { name: String },
+}
+
+/// The peer's answer: the file it keeps by that name, or that it has none.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub enum BackupHeader {
+    Found { modified: u64, len: u64 },
+    Missing,
+}
+
+/// The name `path` is backed up under: its components joined by `/`, or
+/// `None` unless it is a plain relative path.
+pub fn backup_name(path: &Path) -> Option<String> {
+    let parts: Option<Vec<&str>> = path
+        .components()
+        .map(|component| match component {
+            Component::Normal(part) => part.to_str(),
+            _ => None,
+        })
+        .collect();
+    let parts = parts?;
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
+
+/// When the file at `path` was last written, in seconds since the Unix
+/// epoch.
+fn modified_secs(path: &Path) -> io::Result<u64> {
+    let modified = fs::metadata(path)?.modified()?;
+    Ok(modified
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs()))
+}
+
+/// Write `bytes` to `path` by way of a temporary file, so a transfer cut
+/// short never leaves half a save, and date it `modified`.
+fn write_dated(path: &Path, bytes: &[u8], modified: u64) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let partial = path.with_extension("partial");
+    fs::write(&partial, bytes)?;
+    File::options()
+        .write(true)
+        .open(&partial)?
+        .set_modified(UNIX_EPOCH + Duration::from_secs(modified))?;
+    fs::rename(partial, path)
+}
+
+async fn write_framed(send: &mut SendStream, encoded: &[u8]) -> n0_error::Result<()> {
+    let len = u16::try_from(encoded.len()).anyerr()?;
+    send.write_all(&len.to_le_bytes()).await.anyerr()?;
+    send.write_all(encoded).await.anyerr()?;
+    Ok(())
+}
+
+async fn read_framed(recv: &mut RecvStream) -> n0_error::Result<Vec<u8>> {
+    let mut len = [0; 2];
+    recv.read_exact(&mut len).await.anyerr()?;
+    let mut bytes = vec![0; usize::from(u16::from_le_bytes(len))];
+    recv.read_exact(&mut bytes).await.anyerr()?;
+    Ok(bytes)
+}
+
+// ---------------------------------------------------------------------------
+// Peer
+// ---------------------------------------------------------------------------
+
+/// Keeps the saves of the nodes that connect, under
+/// `<root>/<endpoint id>/<name>`.
+#[derive(Debug, Clone)]
+pub struct BackupServer {
+    root: Arc<PathBuf>,
+}
+
+impl BackupServer {
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root: Arc::new(root),
+        }
+    }
+
+    /// Where the file `name` of node `from` is kept, or `None` if `name`
+    /// would lead out of that node's directory.
+    fn path_of(&self, from: EndpointId, name: &str) -> Option<PathBuf> {
+        let name = Path::new(name);
+        backup_name(name)?;
+        Some(self.root.join(from.to_string()).join(name))
+    }
+
+    /// Answer the request on one stream.
+    async fn serve(
+        &self,
+        from: EndpointId,
+        mut send: SendStream,
+        mut recv: RecvStream,
+    ) -> n0_error::Result<()> {
+        let request = decode::decode_backup_request(&read_framed(&mut recv).await?).anyerr()?;
+        match request {
+            BackupRequest::Store { name, modified } => {
+                let path = self
+                    .path_of(from, &name)
+                    .ok_or_else(|| io::Error::other("bad file name"))
+                    .anyerr()?;
+                let bytes = recv.read_to_end(MAX_BACKUP_SIZE).await.anyerr()?;
+                write_dated(&path, &bytes, modified).anyerr()?;
+                let header = BackupHeader::Found {
+                    modified,
+                    len: bytes.len() as u64,
+                };
+                write_framed(&mut send, &bitcode::encode(&header)).await?;
+            }
+            BackupRequest::Fetch { name } => {
+                let kept = self
+                    .path_of(from, &name)
+                    .and_then(|path| Some((modified_secs(&path).ok()?, fs::read(&path).ok()?)));
+                match kept {
+                    Some((modified, bytes)) => {
+                        let header = BackupHeader::Found {
+                            modified,
+                            len: bytes.len() as u64,
+                        };
+                        write_framed(&mut send, &bitcode::encode(&header)).await?;
+                        send.write_all(&bytes).await.anyerr()?;
+                    }
+                    None => {
+                        write_framed(&mut send, &bitcode::encode(&BackupHeader::Missing)).await?;
+                    }
+                }
+            }
+        }
+        send.finish().anyerr()?;
+        Ok(())
+    }
+}
+
+impl ProtocolHandler for BackupServer {
+    async fn accept(&self, connection: Connection) -> std::result::Result<(), AcceptError> {
+        let from = connection.remote_id();
+        while let Ok((send, recv)) = connection.accept_bi().await {
+            let server = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = server.serve(from, send, recv).await {
+                    log::warn!("Error serving backup of {from}: {e}");
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Client
+// ---------------------------------------------------------------------------
+
+/// Keeps this node's saves in sync with a backup peer.
+#[derive(Debug, Clone)]
+pub struct PeerSync {
+    peer: EndpointAddr,
+    runtime: Handle,
+}
+
+impl PeerSync {
+    /// Sync with the peer at `peer`, running transfers on the current tokio
+    /// runtime; `None` outside of one.
+    pub fn new(peer: impl Into<EndpointAddr>) -> Option<Self> {
+        Some(Self {
+            peer: peer.into(),
+            runtime: Handle::try_current().ok()?,
+        })
+    }
+}
+
+impl SaveSync for PeerSync {
+    /// Send the save off in the background; the game does not wait for it.
+    fn after_save(&self, path: &Path, _header: &SaveHeader) -> io::Result<()> {
+        let Some(name) = backup_name(path) else {
+            return Ok(());
+        };
+        let bytes = fs::read(path)?;
+        let modified = modified_secs(path)?;
+        let peer = self.peer.clone();
+        self.runtime.spawn(async move {
+            if let Err(e) = store(peer, &name, modified, bytes).await {
+                log::warn!("Could not back up {name}: {e}");
+            }
+        });
+        Ok(())
+    }
+
+    /// Take the peer's copy if it is newer, waiting up to
+    /// [`FETCH_TIMEOUT`] for it.
+    fn before_load(&self, path: &Path) -> io::Result<()> {
+        let Some(name) = backup_name(path) else {
+            return Ok(());
+        };
+        let peer = self.peer.clone();
+        let runtime = self.runtime.clone();
+        // Loads may run on a runtime thread, which must not block on it.
+        let fetched = std::thread::spawn(move || {
+            runtime.block_on(tokio::time::timeout(FETCH_TIMEOUT, fetch(peer, &name)))
+        })
+        .join()
+        .map_err(|panic| io::Error::other(format!("backup fetch panicked: {panic:?}")))?;
+        match fetched {
+            Ok(Ok(Some((modified, bytes)))) => {
+                let local = modified_secs(path).ok();
+                if local.is_none_or(|local| modified > local) {
+                    write_dated(path, &bytes, modified)?;
+                }
+            }
+            Ok(Ok(None)) => {}
+            Ok(Err(e)) => log::warn!("Could not reach the backup peer: {e}"),
+            Err(_) => log::warn!("The backup peer did not answer in time"),
+        }
+        Ok(())
+    }
+}
+
+async fn store(
+    peer: EndpointAddr,
+    name: &str,
+    modified: u64,
+    bytes: Vec<u8>,
+) -> n0_error::Result<()> {
+    let endpoint = Endpoint::bind().await?;
+    let conn = endpoint.connect(peer, BACKUP_ALPN).await?;
+    let (mut send, mut recv) = conn.open_bi().await.anyerr()?;
+    let request = BackupRequest::Store {
+        name: name.to_owned(),
+        modified,
+    };
+    write_framed(&mut send, &bitcode::encode(&request)).await?;
+    send.write_all(&bytes).await.anyerr()?;
+    send.finish().anyerr()?;
+    let header = decode::decode_backup_header(&read_framed(&mut recv).await?).anyerr()?;
+    conn.close(VarInt::from_u32(0), b"done");
+    match header {
+        BackupHeader::Found { len, .. } if len == bytes.len() as u64 => Ok(()),
+        BackupHeader::Found { .. } | BackupHeader::Missing => {
+            Err(io::Error::other("the peer did not keep the whole file")).anyerr()
+        }
+    }
+}
+
+/// The peer's copy of `name` and when it was written, if it has one.
+async fn fetch(peer: EndpointAddr, name: &str) -> n0_error::Result<Option<(u64, Vec<u8>)>> {
+    let endpoint = Endpoint::bind().await?;
+    let conn = endpoint.connect(peer, BACKUP_ALPN).await?;
+    let (mut send, mut recv) = conn.open_bi().await.anyerr()?;
+    let request = BackupRequest::Fetch {
+        name: name.to_owned(),
+    };
+    write_framed(&mut send, &bitcode::encode(&request)).await?;
+    send.finish().anyerr()?;
+    let header = decode::decode_backup_header(&read_framed(&mut recv).await?).anyerr()?;
+    let found = match header {
+        BackupHeader::Found { modified, len } => {
+            let bytes = recv.read_to_end(MAX_BACKUP_SIZE).await.anyerr()?;
+            if bytes.len() as u64 != len {
+                return Err(io::Error::other("the peer's copy arrived cut short")).anyerr();
+            }
+            Some((modified, bytes))
+        }
+        BackupHeader::Missing => None,
+    };
+    conn.close(VarInt::from_u32(0), b"done");
+    Ok(found)
+}
@@ -0,0 +1,194 @@
+//! Scripted clients, for load testing and as stand-in players.
+//!
+//! A [`Bot`] decides what to send from the messages it receives, at most
+//! one message per tick. It knows nothing about connections, so tests can
+//! drive it directly against a [`ServerState`](super::ServerState). A
+//! [`BotClient`] runs a bot over a real connection, made by the same
+//! [client core](crate::client) as the game client's.
+
+use super::{ClientMessage, Message, ServerMessage, TICK_INTERVAL};
+use crate::client;
+use crate::game::worldgen::mix;
+use crate::game::{Direction, EntityID, GameAction, PlayerKey};
+
+use iroh::EndpointAddr;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// What a bot does once it has a character.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Behavior {
+    /// Stay connected without acting.
+    Idle,
+    /// Send these actions in order, then start over.
+    Script(Vec<GameAction>),
+    /// Move in a random direction every tick. Bots with the same seed walk
+    /// the same way.
+    RandomWalk { seed: u64 },
+}
+
+/// Counters for one bot.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BotStats {
+    /// Server messages observed.
+    pub received: u64,
+    /// Client messages sent.
+    pub sent: u64,
+    /// Whether the bot controls a character.
+    pub spawned: bool,
+}
+
+/// A client that plays on its own.
+#[derive(Debug, Clone)]
+pub struct Bot {
+    name: String,
+    behavior: Behavior,
+    player_id: Option<EntityID>,
+    spawn_requested: bool,
+    step: usize,
+    stats: BotStats,
+}
+
+impl Bot {
+    pub fn new(name: String, behavior: Behavior) -> Self {
+        Self {
+            name,
+            behavior,
+            player_id: None,
+            spawn_requested: false,
+            step: 0,
+            stats: BotStats::default(),
+        }
+    }
+
+    /// The character the bot controls, once the server assigned one.
+    pub fn player_id(&self) -> Option<EntityID> {
+        self.player_id
+    }
+
+    pub fn stats(&self) -> BotStats {
+        self.stats
+    }
+
+    /// Take note of a message from the server.
+    pub fn observe(&mut self, msg: &ServerMessage) {
+        self.stats.received += 1;
+        match msg {
+            ServerMessage::PlayerID(eid) | ServerMessage::Resumed(Some(eid)) => {
+                self.player_id = Some(*eid);
+            }
+            // The server forgot the bot; spawn a new character.
+            ServerMessage::ResumeRejected => {
+                self.player_id = None;
+                self.spawn_requested = false;
+            }
+            _ => {}
+        }
+        self.stats.spawned = self.player_id.is_some();
+    }
+
+    /// The message to send this tick, if any. Until the bot has a character
+    /// that is a request to spawn one.
+    pub fn next_message(&mut self) -> Option<ClientMessage> {
+        let action = if self.player_id.is_some() {
+            self.next_action()?
+        } else if self.spawn_requested {
+            return None;
+        } else {
+            self.spawn_requested = true;
+            GameAction::SpawnPlayer(self.name.clone())
+        };
+        self.stats.sent += 1;
+        Some(ClientMessage::Action(action))
+    }
+
+    fn next_action(&mut self) -> Option<GameAction> {
+        let step = self.step;
+        self.step += 1;
+        match &self.behavior {
+            Behavior::Idle => None,
+            Behavior::Script(actions) => step
+                .checked_rem(actions.len())
+                .and_then(|i| actions.get(i))
+                .cloned(),
+            Behavior::RandomWalk { seed } => {
+                let direction = match mix(seed ^ step as u64) % 4 {
+                    0 => Direction::Up,
+                    1 => Direction::Down,
+                    2 => Direction::Left,
+                    _ => Direction::Right,
+                };
+                Some(GameAction::Move(direction))
+            }
+        }
+    }
+}
+
+/// A [`Bot`] playing over a real connection, one message per server tick.
+///
+/// The bot disconnects when the handle is dropped.
+#[derive(Debug)]
+pub struct BotClient {
+    stats: watch::Receiver<BotStats>,
+    task: JoinHandle<()>,
+}
+
+impl BotClient {
+    /// Connect `bot` to the server at `addr` and start playing. Must be
+    /// called from within a Tokio runtime.
+    pub fn spawn(addr: impl Into<EndpointAddr>, mut bot: Bot) -> Self {
+        let mut connection = client::connect(addr, PlayerKey::generate(), None);
+        let (stats_tx, stats) = watch::channel(BotStats::default());
+
+        let task = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(TICK_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Ok(reason) = connection.failed.try_recv() {
+                    log::warn!("Bot connection failed: {reason}");
+                    break;
+                }
+                while let Ok(msg) = connection.rx.try_recv() {
+                    if let Message::Server(msg) = msg {
+                        bot.observe(&msg);
+                    }
+                }
+                if let Some(msg) = bot.next_message()
+                    && connection.tx.send(msg).is_err()
+                {
+                    break;
+                }
+                stats_tx.send_replace(bot.stats());
+            }
+        });
+
+        Self { stats, task }
+    }
+
+    /// Connect `count` bots named "Bot 1", "Bot 2", …, each behaving as
+    /// `behavior` says for its index.
+    pub fn swarm(
+        addr: &EndpointAddr,
+        count: usize,
+        behavior: impl Fn(usize) -> Behavior,
+    ) -> Vec<Self> {
+        (0..count)
+            .map(|n| {
+                let bot = Bot::new(format!("Bot {}", n + 1), behavior(n));
+                Self::spawn(addr.clone(), bot)
+            })
+            .collect()
+    }
+
+    /// The bot's counters as of its last tick.
+    pub fn stats(&self) -> BotStats {
+        *self.stats.borrow()
+    }
+}
+
+impl Drop for BotClient {
+    fn drop(&mut self) {
+        // Dropping the bot's sender also ends its connection.
+        self.task.abort();
+    }
+}
@@ -0,0 +1,190 @@
+//! Client-side cache of the last known world state.
+//!
+//! When rejoining a server, the client loads the cache for that world and
+//! tells the server which tick it was taken at; the server then sends only
+//! what changed since, instead of the whole world.
+//!
+//! The player's own [`Markers`] on a world's map are kept next to it.
+
+use crate::game::{EntityMap, Point, WorldId};
+
+use bitcode::{Decode, Encode};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+const CACHE_DIR: &str = "cache";
+
+/// Most markers a player may keep per world.
+pub const MAX_MARKERS: usize = 64;
+
+/// Longest marker name, in characters.
+pub const MAX_MARKER_NAME_LEN: usize = 32;
+
+/// Everything the client knew about a world at a given tick.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct WorldCache {
+    pub world_id: WorldId,
+    pub tick: u64,
+    pub entities: EntityMap,
+}
+
+impl WorldCache {
+    fn path(world_id: WorldId) -> PathBuf {
+        PathBuf::from(CACHE_DIR).join(format!("{world_id}.cache"))
+    }
+
+    /// Load the cache for `world_id`, if a readable one exists.
+    pub fn load(world_id: WorldId) -> Option<Self> {
+        let bytes = fs::read(Self::path(world_id)).ok()?;
+        let cache: Self = bitcode::decode(&bytes).ok()?;
+        (cache.world_id == world_id).then_some(cache)
+    }
+
+    /// Write the cache to disk, replacing the previous one for this world.
+    ///
+    /// # Errors
+    /// If the file cannot be written.
+    pub fn save(&self) -> io::Result<()> {
+        fs::create_dir_all(CACHE_DIR)?;
+        fs::write(Self::path(self.world_id), bitcode::encode(self))
+    }
+}
+
+/// Colors a marker can be drawn in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub enum MarkerColor {
+    Red,
+    Yellow,
+    Green,
+    Cyan,
+    Blue,
+    Purple,
+}
+
+impl MarkerColor {
+    pub const ALL: [Self; 6] = [
+        Self::Red,
+        Self::Yellow,
+        Self::Green,
+        Self::Cyan,
+        Self::Blue,
+        Self::Purple,
+    ];
+
+    /// The `index`th color, starting over after the last.
+    fn nth(index: usize) -> Self {
+        Self::ALL
+            .get(index % Self::ALL.len())
+            .copied()
+            .unwrap_or(Self::Red)
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Red => "Red",
+            Self::Yellow => "Yellow",
+            Self::Green => "Green",
+            Self::Cyan => "Cyan",
+            Self::Blue => "Blue",
+            Self::Purple => "Purple",
+        }
+    }
+}
+
+/// A named spot a player put on their map.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Marker {
+    pub pos: Point,
+    pub name: String,
+    pub color: MarkerColor,
+}
+
+/// A player's markers on one world's map, kept on their machine only.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Markers {
+    pub world_id: WorldId,
+    markers: Vec<Marker>,
+}
+
+impl Markers {
+    pub fn new(world_id: WorldId) -> Self {
+        Self {
+            world_id,
+            markers: Vec::new(),
+        }
+    }
+
+    fn path(world_id: WorldId) -> PathBuf {
+        PathBuf::from(CACHE_DIR).join(format!("{world_id}.markers"))
+    }
+
+    /// Load the markers for `world_id`, or none if there are no readable
+    /// ones.
+    pub fn load(world_id: WorldId) -> Self {
+        fs::read(Self::path(world_id))
+            .ok()
+            .and_then(|bytes| bitcode::decode::<Self>(&bytes).ok())
+            .filter(|markers| markers.world_id == world_id)
+            .unwrap_or_else(|| Self::new(world_id))
+    }
+
+    /// Write the markers to disk, replacing the previous ones for this
+    /// world, after cutting names that grew too long.
+    ///
+    /// # Errors
+    /// If the file cannot be written.
+    pub fn save(&mut self) -> io::Result<()> {
+        for marker in &mut self.markers {
+            if let Some((cut, _)) = marker.name.char_indices().nth(MAX_MARKER_NAME_LEN) {
+                marker.name.truncate(cut);
+            }
+        }
+        fs::create_dir_all(CACHE_DIR)?;
+        fs::write(Self::path(self.world_id), bitcode::encode(&*self))
+    }
+
+    /// Put a marker on `pos`, named after how many there are and in the
+    /// next color, replacing one already there. Returns its index, or
+    /// `None` if there are [`MAX_MARKERS`] already.
+    pub fn add(&mut self, pos: Point) -> Option<usize> {
+        if let Some(index) = self.markers.iter().position(|m| m.pos == pos) {
+            return Some(index);
+        }
+        if self.markers.len() >= MAX_MARKERS {
+            return None;
+        }
+        let count = self.markers.len();
+        self.markers.push(Marker {
+            pos,
+            name: format!("Marker {}", count + 1),
+            color: MarkerColor::nth(count),
+        });
+        Some(count)
+    }
+
+    /// Marker `index`, to rename or recolor. Names longer than
+    /// [`MAX_MARKER_NAME_LEN`] are cut when saved.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Marker> {
+        self.markers.get_mut(index)
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.markers.len() {
+            self.markers.remove(index);
+        }
+    }
+
+    /// The markers, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &Marker> {
+        self.markers.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.markers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.markers.is_empty()
+    }
+}
@@ -0,0 +1,366 @@
+//! Chat and the log of major events, kept per world on the server.
+//!
+//! Chat lines and notable events (joins and leaves, deaths, characters
+//! claimed) go through one [`ChatLog`]. It keeps the last [`KEPT_LINES`] in
+//! memory, so clients that connect late can ask for what they missed, and
+//! appends every line to `<world id>.log` in [`LOGS_DIR`]. Once that file
+//! grows past [`MAX_LOG_BYTES`] it is rotated to `.log.1`, the older ones
+//! shifting up to `.log.<LOG_ROTATIONS>`, and the oldest dropped.
+//!
+//! Log files are plain text, one line per entry, so they can be read
+//! without the game: `<tick>\t<kind>\t<text>`.
+//!
+//! Clients keep what they saw in a [`MessageLog`] of their own, with lines
+//! they add themselves, such as blasts and regions entered. Its lines fall
+//! in [`Category`]s the chat window filters by.
+
+use crate::game::WorldId;
+use crate::profile;
+
+use bitcode::{Decode, Encode};
+use std::collections::{BTreeSet, VecDeque};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write as _};
+use std::path::{Path, PathBuf};
+
+/// Where servers keep their world logs.
+pub const LOGS_DIR: &str = "logs";
+
+/// Lines kept in memory to send to clients that ask.
+pub const KEPT_LINES: usize = 200;
+
+/// Size past which a log file is rotated.
+pub const MAX_LOG_BYTES: u64 = 1024 * 1024;
+
+/// Rotated log files kept per world.
+pub const LOG_ROTATIONS: u32 = 3;
+
+/// Longest chat message accepted, in characters.
+pub const MAX_CHAT_LEN: usize = 256;
+
+/// Lines a client keeps of its session's log.
+pub const MAX_SESSION_LINES: usize = 2000;
+
+/// What a log line is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub enum LineKind {
+    Chat,
+    Join,
+    Leave,
+    Death,
+    Claim,
+}
+
+impl LineKind {
+    const ALL: [Self; 5] = [
+        Self::Chat,
+        Self::Join,
+        Self::Leave,
+        Self::Death,
+        Self::Claim,
+    ];
+
+    /// Name of the kind in log files.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Chat => "chat",
+            Self::Join => "join",
+            Self::Leave => "leave",
+            Self::Death => "death",
+            Self::Claim => "claim",
+        }
+    }
+}
+
+/// One line of the log.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct ChatLine {
+    /// Tick the line was logged in.
+    pub tick: u64,
+    pub kind: LineKind,
+    /// The message, prefixed with who said it, or what happened.
+    pub text: String,
+}
+
+impl ChatLine {
+    /// The line as written to a log file, without the newline.
+    fn to_log(&self) -> String {
+        let text: String = self
+            .text
+            .chars()
+            .map(|c| if c.is_control() { ' ' } else { c })
+            .collect();
+        format!("{}\t{}\t{text}", self.tick, self.kind.name())
+    }
+
+    /// Parse a line of a log file; `None` if it is not one.
+    fn from_log(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(3, '\t');
+        let tick = fields.next()?.parse().ok()?;
+        let kind = fields.next()?;
+        let kind = LineKind::ALL.into_iter().find(|k| k.name() == kind)?;
+        let text = fields.next()?.to_owned();
+        Some(Self { tick, kind, text })
+    }
+}
+
+/// What a line of a [`MessageLog`] is about, for filtering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Category {
+    Combat,
+    Chat,
+    /// Players coming and going, and world events.
+    System,
+    /// Where the player went.
+    Exploration,
+}
+
+impl Category {
+    pub const ALL: [Self; 4] = [Self::Combat, Self::Chat, Self::System, Self::Exploration];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Combat => "Combat",
+            Self::Chat => "Chat",
+            Self::System => "System",
+            Self::Exploration => "Exploration",
+        }
+    }
+}
+
+impl LineKind {
+    pub fn category(self) -> Category {
+        match self {
+            Self::Chat => Category::Chat,
+            Self::Death => Category::Combat,
+            Self::Join | Self::Leave | Self::Claim => Category::System,
+        }
+    }
+}
+
+/// One line of a [`MessageLog`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionLine {
+    /// Tick of the world the line was logged in.
+    pub tick: u64,
+    pub category: Category,
+    /// Kind of the line in the server's log, or `None` if the client wrote
+    /// it.
+    pub kind: Option<LineKind>,
+    pub text: String,
+}
+
+/// What a client saw logged this session, the last [`MAX_SESSION_LINES`],
+/// and which of it the chat window shows.
+#[derive(Debug, Clone)]
+pub struct MessageLog {
+    lines: VecDeque<SessionLine>,
+    /// Categories shown.
+    shown: BTreeSet<Category>,
+    /// Text the lines shown contain, ignoring case; all lines if empty.
+    pub search: String,
+}
+
+impl Default for MessageLog {
+    fn default() -> Self {
+        Self {
+            lines: VecDeque::new(),
+            shown: Category::ALL.into_iter().collect(),
+            search: String::new(),
+        }
+    }
+}
+
+impl MessageLog {
+    fn remember(&mut self, line: SessionLine) {
+        if self.lines.len() >= MAX_SESSION_LINES {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+
+    /// Add a line of the server's log.
+    pub fn push(&mut self, line: ChatLine) {
+        self.remember(SessionLine {
+            tick: line.tick,
+            category: line.kind.category(),
+            kind: Some(line.kind),
+            text: line.text,
+        });
+    }
+
+    /// Add a line the client wrote.
+    pub fn note(&mut self, tick: u64, category: Category, text: String) {
+        self.remember(SessionLine {
+            tick,
+            category,
+            kind: None,
+            text,
+        });
+    }
+
+    /// Replace the lines of the server's log with `lines` it sent after
+    /// joining, keeping ours, in the order of their ticks.
+    pub fn history(&mut self, lines: Vec<ChatLine>) {
+        self.lines.retain(|line| line.kind.is_none());
+        for line in lines {
+            self.push(line);
+        }
+        self.lines.make_contiguous().sort_by_key(|line| line.tick);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    pub fn shows(&self, category: Category) -> bool {
+        self.shown.contains(&category)
+    }
+
+    /// Show or hide the lines of `category`.
+    pub fn show(&mut self, category: Category, shown: bool) {
+        if shown {
+            self.shown.insert(category);
+        } else {
+            self.shown.remove(&category);
+        }
+    }
+
+    /// The lines in a shown category that match the search, oldest first.
+    pub fn visible(&self) -> impl Iterator<Item = &SessionLine> {
+        let search = self.search.trim().to_lowercase();
+        self.lines.iter().filter(move |line| {
+            self.shown.contains(&line.category)
+                && (search.is_empty() || line.text.to_lowercase().contains(&search))
+        })
+    }
+
+    /// Every line, filtered or not, as text: `<time>\t<category>\t<text>`
+    /// per line, with `time` giving the time of a tick.
+    pub fn to_text(&self, time: impl Fn(u64) -> String) -> String {
+        self.lines
+            .iter()
+            .map(|line| {
+                let text = line.text.replace(['\n', '\r'], " ");
+                format!("{}\t{}\t{text}\n", time(line.tick), line.category.name())
+            })
+            .collect()
+    }
+}
+
+/// Recent lines of a world's log, written through to disk if opened with a
+/// file.
+#[derive(Debug, Default)]
+pub struct ChatLog {
+    lines: VecDeque<ChatLine>,
+    /// The current log file and its size, if the log is kept on disk.
+    file: Option<(PathBuf, u64)>,
+}
+
+impl ChatLog {
+    fn path(world_id: WorldId) -> PathBuf {
+        Path::new(LOGS_DIR).join(format!("{world_id}.log"))
+    }
+
+    /// Open the log of `world_id`, picking up the last lines written to it.
+    ///
+    /// # Errors
+    /// If the log cannot be opened or read.
+    pub fn open(world_id: WorldId) -> io::Result<Self> {
+        Self::open_at(Self::path(world_id))
+    }
+
+    /// Open the log file at `path`, creating it on the first line.
+    ///
+    /// # Errors
+    /// If the file exists but cannot be read.
+    pub fn open_at(path: PathBuf) -> io::Result<Self> {
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let mut log = Self::default();
+        for line in text.lines().filter_map(ChatLine::from_log) {
+            log.remember(line);
+        }
+        log.file = Some((path, text.len() as u64));
+        Ok(log)
+    }
+
+    fn remember(&mut self, line: ChatLine) {
+        if self.lines.len() >= KEPT_LINES {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+
+    /// Add a line, and append it to the log file if there is one. The line
+    /// is kept in memory even if writing it fails.
+    ///
+    /// # Errors
+    /// If the line cannot be appended to the file.
+    pub fn push(&mut self, line: ChatLine) -> io::Result<()> {
+        let written = self.file.is_some().then(|| format!("{}\n", line.to_log()));
+        self.remember(line);
+        let (Some(written), Some((path, size))) = (written, &mut self.file) else {
+            return Ok(());
+        };
+        if *size > 0 && *size + written.len() as u64 > MAX_LOG_BYTES {
+            rotate(path)?;
+            *size = 0;
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&*path)?
+            .write_all(written.as_bytes())?;
+        *size += written.len() as u64;
+        Ok(())
+    }
+
+    /// The last `count` lines, oldest first.
+    pub fn recent(&self, count: usize) -> Vec<ChatLine> {
+        let skip = self.lines.len().saturating_sub(count);
+        self.lines.iter().skip(skip).cloned().collect()
+    }
+
+    /// Estimated bytes held in memory.
+    pub fn memory_bytes(&self) -> usize {
+        profile::deque_bytes(&self.lines)
+            + self.lines.iter().map(|l| l.text.capacity()).sum::<usize>()
+            + self
+                .file
+                .as_ref()
+                .map_or(0, |(path, _)| path.as_os_str().len())
+    }
+}
+
+/// `path` with `.<index>` appended, or `path` itself for index 0.
+fn rotated(path: &Path, index: u32) -> PathBuf {
+    if index == 0 {
+        return path.to_owned();
+    }
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{index}"));
+    PathBuf::from(name)
+}
+
+/// Shift the rotated copies of the log at `path` up by one, dropping the
+/// oldest, and move the log itself to `.1`.
+fn rotate(path: &Path) -> io::Result<()> {
+    let oldest = rotated(path, LOG_ROTATIONS);
+    if oldest.exists() {
+        fs::remove_file(oldest)?;
+    }
+    for index in (0..LOG_ROTATIONS).rev() {
+        let from = rotated(path, index);
+        if from.exists() {
+            fs::rename(from, rotated(path, index + 1))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,1040 @@
+//! Server console: text commands sent by clients with
+//! [`ClientMessage::Command`](super::ClientMessage::Command), answered with
+//! [`ServerMessage::ConsoleOutput`](super::ServerMessage::ConsoleOutput).
+//!
+//! Only admins may run the built-in commands: the host, from the server's
+//! own console or as the player hosting from the game, and the players the
+//! host names (`admins add`). Commands added by [plugins](super::plugin)
+//! say who may run them.
+
+use super::access::MAX_SECRET_LEN;
+use super::history::{DEFAULT_HISTORY_TICKS, History};
+use super::plugin::{self, Caller};
+use super::recording::{self, Recording};
+use super::trace::{DEFAULT_TRACE_LEN, EntityTrace};
+use super::{ServerState, ViewLimits, WhenEmpty};
+use crate::game::bridge;
+use crate::game::explosion::{self, Blast};
+use crate::game::faction::Tier;
+use crate::game::follow::{self, Follow};
+use crate::game::persist::{self, Issue};
+use crate::game::worldgen::{self, namegen};
+use crate::game::{
+    AiBehavior, ContentRegistry, Entity, EntityID, EntityType, GameEvent, Health, Point, Scheduler,
+    Shop,
+};
+use crate::profile::{Profiler, System};
+
+use std::path::PathBuf;
+
+const HELP: &str = "\
+Commands:
+  help      Show this list
+  validate  Check the world for corrupt or inconsistent data
+  repair    Fix what `validate` reports
+  upgrade   Repair the world and save it in the newest format
+  worldgen  Show the seed and config the world was generated from
+  worldgen extend <width> <height>
+            Grow a generated world, planting the new tiles from its seed
+  chunks    Show how many chunks of an infinite world are loaded
+  memory    Show estimated memory used by the world and server caches
+  profile   Show average time per tick spent in each system
+  profile csv [name]
+            Write the recent per-tick timings to profiles/<name>.csv
+  inspect <id>            Show an entity with its tags and metadata
+  tag <id> <tag>          Tag an entity
+  untag <id> <tag>        Remove a tag
+  meta <id> <key> <value> Set a metadata entry
+  unmeta <id> <key>       Remove a metadata entry
+  find <tag>              List the entities with a tag
+  own <id> <owner|none>   Let a player give an entity orders
+  follow <id> [leash|none]
+                          Make an owned entity follow its owner
+  health <id> <max|none>  Give an entity full health, or make it unhurtable
+  ai <id> <wander|flee|chase|none>
+                          Make a non-player entity act on its own, or stop
+  explode <x> <y>         Set off a bomb blast at a tile
+  water <x> <y>           Flood a tile
+  bridge <x> <y>          Build a bridge over a flooded tile
+  faction <id> <faction|none>
+                          Set the faction an entity belongs to
+  rep <id> <faction> <delta>
+                          Change an entity's reputation with a faction
+  dialogue <id> <tree|none>
+                          Set what an entity says when talked to
+  shop <id> <shop|none>   Make an entity a vendor, with full stock
+  give <id> <item> <count>
+                          Put items in an entity's inventory
+  transfer                Show what characters may carry between worlds
+  transfer <import|export> <on|off>
+                          Allow or forbid moving characters in or out
+  transfer <ban|unban> <item>
+                          Strip an item from imported characters, or stop
+  transfer cap <count|none>
+                          Limit how many of each item an import keeps
+  access                  Show whether joining needs a password or invite
+  access password <secret|none>
+                          Require a password to join, or stop requiring one
+  access invite [uses]    Make an invite code, good for <uses> joins or any
+  access revoke <code>    Withdraw an invite code
+  admins                  Show who may run every command
+  admins <add|remove> <id>
+                          Make the player controlling an entity an admin,
+                          or not; only admins and the host may run these
+                          commands
+  players                 Show the players in the world, the cap and the queue
+  players max <count|none>
+                          Cap the players at once; the rest wait in line
+  idle                    Show what the world does while no one is connected
+  idle <run|pause|catch-up>
+                          Keep running, pause, or pause and catch up on join
+  turns                   Show whether the world runs in turns, and whose
+  turns <on|off>          Run the world in turns, or in real time
+  view                    Show the view radii granted and asked for
+  view <min> <max>        Grant clients asking for a view radius this range
+  record                  Show whether the session is being recorded
+  record start            Start recording the session
+  record stop [name]      Stop and save to recordings/<name>.recording
+  history                 Show the ticks kept for stepping back
+  history on [ticks]      Keep the last <ticks> ticks (default 600)
+  history off             Stop keeping history, going live from the tick shown
+  history back [n]        Step back n ticks (default 1) and pause there
+  history forward [n]     Step forward n ticks (default 1)
+  history goto <tick>     Show the world at a kept tick
+  history resume          Go live from the tick shown, dropping later history
+  trace                   Show whether entity traces are kept
+  trace on [n]            Keep the last n actions, events and moves of each
+                          entity (default 32)
+  trace off               Stop keeping entity traces
+  trace <id>              Show what lately happened to an entity";
+
+/// Returns `true` if `name` is a built-in command, listed in `help`.
+pub fn is_builtin(name: &str) -> bool {
+    HELP.lines()
+        .filter_map(|line| line.strip_prefix("  "))
+        .filter(|line| !line.starts_with(' '))
+        .any(|line| line.split_whitespace().next() == Some(name))
+}
+
+/// Run one command line for `caller` against the server state and return
+/// its output.
+pub fn run(state: &mut ServerState, caller: &Caller, line: &str) -> String {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    if let Some(output) = plugin::dispatch(state, caller, &words) {
+        return output;
+    }
+    if !caller.admin && !matches!(words.as_slice(), [] | ["help"]) {
+        return "Only admins may run the built-in commands".to_owned();
+    }
+    match words.as_slice() {
+        [] => String::new(),
+        ["help"] if !caller.admin => {
+            let added = state.plugins.help(caller);
+            if added.is_empty() {
+                "Only admins may run commands on this server".to_owned()
+            } else {
+                format!("Commands:{added}")
+            }
+        }
+        ["help"] => format!("{HELP}{}", state.plugins.help(caller)),
+        ["validate"] => report(
+            "issue(s) found",
+            &persist::validate(&state.game),
+            "No issues found",
+        ),
+        ["repair"] => report(
+            "issue(s) repaired",
+            &persist::repair(&mut state.game),
+            "Nothing to repair",
+        ),
+        ["upgrade"] => {
+            let repaired = report(
+                "issue(s) repaired",
+                &persist::repair(&mut state.game),
+                "Nothing to repair",
+            );
+            match persist::save_to_file(&state.game) {
+                Ok(()) => format!(
+                    "{repaired}\nSaved worlds/{}.world in format {}",
+                    state.game.world_name,
+                    persist::SAVE_FORMAT
+                ),
+                Err(e) => format!("{repaired}\nFailed to save the world: {e}"),
+            }
+        }
+        ["memory"] => state.memory_report().to_string(),
+        ["profile"] => averages(&state.profiler),
+        ["profile", "csv"] => dump_csv(&state.profiler, &format!("tick-{}", state.game.tick)),
+        ["profile", "csv", name] => dump_csv(&state.profiler, name),
+        ["inspect", id] => {
+            let seed = state.game.gen_manifest().map(|m| m.seed);
+            with_entity(state, id, |eid, entity| match seed {
+                Some(seed) => format!(
+                    "{}\n  in {}",
+                    describe(eid, entity),
+                    namegen::region_name(seed, entity.position)
+                ),
+                None => describe(eid, entity),
+            })
+        }
+        _ => worldgen_command(state, &words)
+            .or_else(|| tags_command(state, &words))
+            .or_else(|| entity_command(state, &words))
+            .or_else(|| content_command(state, &words))
+            .or_else(|| transfer_command(state, &words))
+            .or_else(|| access_command(state, &words))
+            .or_else(|| admins_command(state, &words))
+            .or_else(|| players_command(state, &words))
+            .or_else(|| idle_command(state, &words))
+            .or_else(|| turns_command(state, &words))
+            .or_else(|| view_command(state, &words))
+            .or_else(|| record_command(state, &words))
+            .or_else(|| history_command(state, &words))
+            .or_else(|| trace_command(state, &words))
+            .unwrap_or_else(|| format!("Unknown command `{line}`; try `help`")),
+    }
+}
+
+/// Commands about the generator of the world and how far it
+/// reaches.
+fn worldgen_command(state: &mut ServerState, words: &[&str]) -> Option<String> {
+    let output = match words {
+        ["worldgen"] => state.game.gen_manifest().map_or_else(
+            || "The world was not generated from a seed".to_owned(),
+            ToString::to_string,
+        ),
+        ["worldgen", "extend", width, height] => {
+            let (Ok(width), Ok(height)) = (width.parse(), height.parse()) else {
+                return Some("Usage: worldgen extend <width> <height>".to_owned());
+            };
+            let before = state.game.entities().len();
+            match worldgen::extend(&mut state.game, width, height) {
+                Ok(()) => {
+                    state.resend_terrain();
+                    format!(
+                        "Extended to {width}×{height}; {} tree(s) planted",
+                        state.game.entities().len() - before
+                    )
+                }
+                Err(e) => format!("Cannot extend: {e}"),
+            }
+        }
+        ["chunks"] => state.game.chunks.as_ref().map_or_else(
+            || "The world is not infinite".to_owned(),
+            |chunks| {
+                format!(
+                    "{} chunk(s) loaded, {} put aside",
+                    chunks.loaded().count(),
+                    chunks.stored()
+                )
+            },
+        ),
+        _ => return None,
+    };
+    Some(output)
+}
+
+/// Commands that tag entities, give them metadata and find them by
+/// tag.
+fn tags_command(state: &mut ServerState, words: &[&str]) -> Option<String> {
+    let output = match words {
+        ["tag", id, tag] => with_entity(state, id, |eid, entity| match entity.tags.insert(tag) {
+            Ok(true) => format!("Tagged {} `{tag}`", eid.0),
+            Ok(false) => format!("{} is already tagged `{tag}`", eid.0),
+            Err(e) => format!("Cannot tag {}: {e}", eid.0),
+        }),
+        ["untag", id, tag] => with_entity(state, id, |eid, entity| {
+            if entity.tags.remove(tag) {
+                format!("Removed `{tag}` from {}", eid.0)
+            } else {
+                format!("{} is not tagged `{tag}`", eid.0)
+            }
+        }),
+        ["meta", id, key, value @ ..] if !value.is_empty() => {
+            let value = value.join(" ");
+            with_entity(state, id, |eid, entity| {
+                match entity.metadata.set(key, &value) {
+                    Ok(_) => format!("Set {}.{key} = {value}", eid.0),
+                    Err(e) => format!("Cannot set {}.{key}: {e}", eid.0),
+                }
+            })
+        }
+        ["unmeta", id, key] => {
+            with_entity(state, id, |eid, entity| match entity.metadata.remove(key) {
+                Some(_) => format!("Removed {}.{key}", eid.0),
+                None => format!("{} has no `{key}`", eid.0),
+            })
+        }
+        ["find", tag] => {
+            let ids: Vec<String> = state
+                .game
+                .tagged(tag)
+                .map(|eid| eid.0.to_string())
+                .collect();
+            if ids.is_empty() {
+                format!("No entity is tagged `{tag}`")
+            } else {
+                format!("Tagged `{tag}`: {}", ids.join(", "))
+            }
+        }
+        _ => return None,
+    };
+    Some(output)
+}
+
+/// Commands that set up ownership, following, minds and health.
+fn entity_command(state: &mut ServerState, words: &[&str]) -> Option<String> {
+    let output = match words {
+        ["own", id, "none"] => with_entity(state, id, |eid, entity| {
+            entity.owner = None;
+            format!("{} has no owner", eid.0)
+        }),
+        ["own", id, owner] => {
+            let owner = match owner.parse() {
+                Ok(owner) if state.game.entities().contains_key(&EntityID(owner)) => owner,
+                _ => return Some(format!("No entity {owner}")),
+            };
+            with_entity(state, id, |eid, entity| {
+                entity.owner = Some(EntityID(owner));
+                format!("{} is now owned by {owner}", eid.0)
+            })
+        }
+        ["follow", id, "none"] => with_entity(state, id, |eid, entity| {
+            entity.follow = None;
+            format!("{} no longer follows anyone", eid.0)
+        }),
+        ["follow", id, leash @ ..] if leash.len() <= 1 => {
+            let leash = match leash.first().map(|l| l.parse()) {
+                None => follow::DEFAULT_LEASH,
+                Some(Ok(leash)) => leash,
+                Some(Err(_)) => return Some(format!("Invalid leash `{}`", leash.join(" "))),
+            };
+            with_entity(state, id, |eid, entity| match entity.owner {
+                Some(owner) => {
+                    entity.follow = Some(Follow::new(leash));
+                    format!("{} follows {} within {leash} tiles", eid.0, owner.0)
+                }
+                None => format!("{} has no owner to follow; use `own` first", eid.0),
+            })
+        }
+        ["ai", id, "none"] => with_entity(state, id, |eid, entity| {
+            entity.ai = None;
+            format!("{} no longer acts on its own", eid.0)
+        }),
+        ["ai", id, name] => {
+            let Some(behavior) = AiBehavior::ALL.into_iter().find(|b| b.name() == *name) else {
+                return Some(format!("Unknown behavior `{name}`"));
+            };
+            with_entity(state, id, |eid, entity| {
+                if entity.entity_type == EntityType::Player {
+                    return format!("{} is a player", eid.0);
+                }
+                entity.ai = Some(behavior);
+                format!("{} will {name}", eid.0)
+            })
+        }
+        ["health", id, "none"] => with_entity(state, id, |eid, entity| {
+            entity.health = None;
+            format!("{} can no longer be hurt", eid.0)
+        }),
+        ["health", id, max] => {
+            let Ok(max) = max.parse() else {
+                return Some(format!("Invalid health `{max}`"));
+            };
+            with_entity(state, id, |eid, entity| {
+                entity.health = Some(Health::new(max));
+                format!("{} has {max} health", eid.0)
+            })
+        }
+        ["explode", x, y] => {
+            let (Ok(x), Ok(y)) = (x.parse(), y.parse()) else {
+                return Some(format!("Invalid tile `{x} {y}`"));
+            };
+            let GameEvent::Exploded(explosion) =
+                explosion::explode(&mut state.game, &Blast::bomb(Point { x, y }))
+            else {
+                return Some("The blast fizzled".to_owned());
+            };
+            state.announce_explosion(&explosion);
+            format!(
+                "Blast at ({x}, {y}) reached {} tiles, hurt {}, destroyed {}",
+                explosion.tiles.len(),
+                explosion.damaged.len(),
+                explosion.destroyed.len()
+            )
+        }
+        ["water" | "bridge", x, y] => {
+            let (Ok(x), Ok(y)) = (x.parse(), y.parse()) else {
+                return Some(format!("Invalid tile `{x} {y}`"));
+            };
+            let at = Point { x, y };
+            let built = if words.first() == Some(&"water") {
+                Some(bridge::flood(&mut state.game, at))
+            } else {
+                bridge::build(&mut state.game, at)
+            };
+            match built {
+                Some(id) => format!("Built entity {} at ({x}, {y})", id.0),
+                None => format!("No open water at ({x}, {y})"),
+            }
+        }
+        _ => return None,
+    };
+    Some(output)
+}
+
+/// Commands that give entities content from the [`ContentRegistry`]:
+/// factions, reputation, dialogue, shops and items.
+fn content_command(state: &mut ServerState, words: &[&str]) -> Option<String> {
+    let registry = ContentRegistry::builtin();
+    let output = match words {
+        ["faction", id, "none"] => with_entity(state, id, |eid, entity| {
+            entity.faction = None;
+            format!("{} no longer belongs to a faction", eid.0)
+        }),
+        ["faction", id, faction] => {
+            let Some(def) = registry.faction(faction) else {
+                return Some(format!("Unknown faction `{faction}`"));
+            };
+            with_entity(state, id, |eid, entity| {
+                entity.faction = Some(def.id.clone());
+                format!("{} now belongs to {}", eid.0, def.name)
+            })
+        }
+        ["rep", id, faction, delta] => {
+            if registry.faction(faction).is_none() {
+                return Some(format!("Unknown faction `{faction}`"));
+            }
+            let Ok(delta) = delta.parse() else {
+                return Some(format!("Invalid reputation change `{delta}`"));
+            };
+            with_entity(state, id, |eid, entity| {
+                let value = entity.reputation.adjust(faction, delta);
+                format!("{} is {} with {faction} ({value})", eid.0, Tier::of(value))
+            })
+        }
+        ["dialogue", id, "none"] => with_entity(state, id, |eid, entity| {
+            entity.dialogue = None;
+            format!("{} has nothing to say", eid.0)
+        }),
+        ["dialogue", id, tree] => {
+            if registry.dialogue(tree).is_none() {
+                return Some(format!("Unknown dialogue `{tree}`"));
+            }
+            with_entity(state, id, |eid, entity| {
+                entity.dialogue = Some((*tree).to_owned());
+                format!("{} now uses dialogue `{tree}`", eid.0)
+            })
+        }
+        ["shop", id, "none"] => with_entity(state, id, |eid, entity| {
+            entity.shop = None;
+            format!("{} no longer trades", eid.0)
+        }),
+        ["shop", id, shop] => {
+            let Some(def) = registry.shop(shop) else {
+                return Some(format!("Unknown shop `{shop}`"));
+            };
+            with_entity(state, id, |eid, entity| {
+                entity.shop = Some(Shop::new(def));
+                format!("{} now runs {}", eid.0, def.name)
+            })
+        }
+        ["give", id, item, count] => {
+            if registry.item(item).is_none() {
+                return Some(format!("Unknown item `{item}`"));
+            }
+            let Ok(count) = count.parse() else {
+                return Some(format!("Invalid count `{count}`"));
+            };
+            with_entity(state, id, |eid, entity| {
+                entity.inventory.add(item, count);
+                format!("{} now has {} {item}", eid.0, entity.inventory.count(item))
+            })
+        }
+        _ => return None,
+    };
+    Some(output)
+}
+
+/// Commands that set the world's
+/// [`TransferRules`](crate::game::TransferRules).
+fn transfer_command(state: &mut ServerState, words: &[&str]) -> Option<String> {
+    let rules = &mut state.game.transfer;
+    let output = match words {
+        ["transfer"] => {
+            let on_off = |allowed| if allowed { "on" } else { "off" };
+            let banned: Vec<&str> = rules.banned_items.iter().map(String::as_str).collect();
+            let cap = if rules.max_item_count == u32::MAX {
+                "none".to_owned()
+            } else {
+                rules.max_item_count.to_string()
+            };
+            format!(
+                "Import: {}, export: {}, banned: {}, cap: {cap}",
+                on_off(rules.allow_import),
+                on_off(rules.allow_export),
+                if banned.is_empty() {
+                    "nothing".to_owned()
+                } else {
+                    banned.join(", ")
+                },
+            )
+        }
+        [
+            "transfer",
+            which @ ("import" | "export"),
+            setting @ ("on" | "off"),
+        ] => {
+            let allowed = *setting == "on";
+            if *which == "import" {
+                rules.allow_import = allowed;
+            } else {
+                rules.allow_export = allowed;
+            }
+            format!("Character {which}s are now {setting}")
+        }
+        ["transfer", "ban", item] => {
+            if ContentRegistry::builtin().item(item).is_none() {
+                return Some(format!("Unknown item `{item}`"));
+            }
+            rules.banned_items.insert((*item).to_owned());
+            format!("Imported characters lose their {item}")
+        }
+        ["transfer", "unban", item] => {
+            if rules.banned_items.remove(*item) {
+                format!("Imported characters keep their {item}")
+            } else {
+                format!("`{item}` is not banned")
+            }
+        }
+        ["transfer", "cap", "none"] => {
+            rules.max_item_count = u32::MAX;
+            "Imported characters keep all their items".to_owned()
+        }
+        ["transfer", "cap", count] => {
+            let Ok(count) = count.parse() else {
+                return Some(format!("Invalid count `{count}`"));
+            };
+            rules.max_item_count = count;
+            format!("Imported characters keep at most {count} of each item")
+        }
+        _ => return None,
+    };
+    Some(output)
+}
+
+/// Commands that set who may join; see [`access`](super::access).
+fn access_command(state: &mut ServerState, words: &[&str]) -> Option<String> {
+    let rules = &mut state.access.rules;
+    let output = match words {
+        ["access"] => {
+            if rules.is_open() {
+                return Some("Anyone may join".to_owned());
+            }
+            return Some(format!(
+                "Password: {}, invites: {}",
+                if rules.has_password() { "set" } else { "none" },
+                rules.invite_count()
+            ));
+        }
+        ["access", "password", "none"] => {
+            rules.set_password(None);
+            "Joining no longer needs a password".to_owned()
+        }
+        ["access", "password", secret] => {
+            if secret.len() > MAX_SECRET_LEN {
+                return Some(format!("Passwords are at most {MAX_SECRET_LEN} bytes"));
+            }
+            rules.set_password(Some(secret));
+            "Joining now needs the password".to_owned()
+        }
+        ["access", "invite", uses @ ..] if uses.len() <= 1 => {
+            let uses = match uses.first().map(|uses| uses.parse()) {
+                None => None,
+                Some(Ok(uses)) => Some(uses),
+                Some(Err(_)) => return Some(format!("Invalid count `{}`", uses.join(" "))),
+            };
+            let code = rules.create_invite(uses);
+            match uses {
+                Some(uses) => format!("Invite code {code}, good for {uses} joins"),
+                None => format!("Invite code {code}"),
+            }
+        }
+        ["access", "revoke", code] => {
+            if !rules.revoke(code) {
+                return Some(format!("No invite code `{code}`"));
+            }
+            format!("Withdrew invite code {code}")
+        }
+        _ => return None,
+    };
+    if let Err(e) = rules.save(state.game.world_id) {
+        return Some(format!("{output}, but could not save it: {e}"));
+    }
+    Some(output)
+}
+
+/// Commands that name the server's admins.
+fn admins_command(state: &mut ServerState, words: &[&str]) -> Option<String> {
+    let output = match words {
+        ["admins"] => {
+            let admins = &state.access.rules.admins;
+            if admins.is_empty() {
+                return Some("No admins; only the host may run every command".to_owned());
+            }
+            let online: Vec<String> = state
+                .sessions
+                .connected()
+                .filter_map(|endpoint_id| {
+                    let session = state.sessions.get(endpoint_id)?;
+                    admins
+                        .contains(&session.player?)
+                        .then_some(session.entity_id?)
+                })
+                .map(|eid| state.name_of(eid))
+                .collect();
+            return Some(format!(
+                "{} admin(s); online: {}",
+                admins.len(),
+                if online.is_empty() {
+                    "none".to_owned()
+                } else {
+                    online.join(", ")
+                }
+            ));
+        }
+        ["admins", verb @ ("add" | "remove"), id] => {
+            let Ok(id) = id.parse() else {
+                return Some(format!("Invalid entity id `{id}`"));
+            };
+            let eid = EntityID(id);
+            let player = state
+                .sessions
+                .connected()
+                .filter_map(|endpoint_id| state.sessions.get(endpoint_id))
+                .find(|session| session.entity_id == Some(eid))
+                .and_then(|session| session.player);
+            let Some(player) = player else {
+                return Some(format!("No player controls entity {id}"));
+            };
+            let name = state.name_of(eid);
+            let admins = &mut state.access.rules.admins;
+            if *verb == "add" {
+                admins.insert(player);
+                format!("{name} is an admin")
+            } else {
+                admins.remove(&player);
+                format!("{name} is no longer an admin")
+            }
+        }
+        _ => return None,
+    };
+    if let Err(e) = state.access.rules.save(state.game.world_id) {
+        return Some(format!("{output}, but could not save it: {e}"));
+    }
+    Some(output)
+}
+
+/// Commands that cap the players in the world; see [`queue`](super::queue).
+fn players_command(state: &mut ServerState, words: &[&str]) -> Option<String> {
+    let output = match words {
+        ["players"] => {
+            let cap = state
+                .access
+                .rules
+                .max_players
+                .map_or_else(|| "none".to_owned(), |max| max.to_string());
+            return Some(format!(
+                "{} playing, cap: {cap}, {} waiting",
+                state.sessions.admitted(),
+                state.queue.len()
+            ));
+        }
+        ["players", "max", "none"] => {
+            state.access.rules.max_players = None;
+            "No more player cap".to_owned()
+        }
+        ["players", "max", count] => {
+            let Some(max) = count.parse().ok().filter(|max| *max > 0) else {
+                return Some(format!("Invalid count `{count}`"));
+            };
+            state.access.rules.max_players = Some(max);
+            format!("At most {max} players at once")
+        }
+        _ => return None,
+    };
+    state.fill_slots();
+    if let Err(e) = state.access.rules.save(state.game.world_id) {
+        return Some(format!("{output}, but could not save it: {e}"));
+    }
+    Some(output)
+}
+
+/// Commands that switch between turns and real time; see
+/// [`scheduler`](crate::game::scheduler).
+fn turns_command(state: &mut ServerState, words: &[&str]) -> Option<String> {
+    match words {
+        ["turns"] => {}
+        ["turns", "on"] => {
+            state.game.turns.get_or_insert_with(Scheduler::default);
+        }
+        ["turns", "off"] => state.game.turns = None,
+        _ => return None,
+    }
+    let Some(mut turns) = state.game.turns.take() else {
+        return Some("The world runs in real time".to_owned());
+    };
+    let next = turns.next_actor(state.game.entities());
+    state.game.turns = Some(turns);
+    Some(match next {
+        Some(eid) => format!("The world runs in turns; {}'s turn", state.name_of(eid)),
+        None => "The world runs in turns, waiting for players".to_owned(),
+    })
+}
+
+/// Commands that set what the world does while empty; see
+/// [`idle`](super::idle).
+fn idle_command(state: &mut ServerState, words: &[&str]) -> Option<String> {
+    let idle = &mut state.idle;
+    let output = match words {
+        ["idle"] => format!("When empty: {}", idle.when_empty.label().to_lowercase()),
+        ["idle", name] => {
+            let Some(when_empty) = WhenEmpty::from_name(name) else {
+                return Some(format!(
+                    "Unknown setting `{name}`; try run, pause or catch-up"
+                ));
+            };
+            idle.when_empty = when_empty;
+            format!("When empty: {}", when_empty.label().to_lowercase())
+        }
+        _ => return None,
+    };
+    Some(output)
+}
+
+/// Commands that set the view radii granted to clients; see
+/// [`view`](super::view).
+fn view_command(state: &mut ServerState, words: &[&str]) -> Option<String> {
+    match words {
+        ["view"] => {
+            let mut asked: Vec<u32> = state
+                .sessions
+                .connected()
+                .filter_map(|endpoint_id| state.sessions.get(endpoint_id)?.view_radius)
+                .collect();
+            asked.sort_unstable();
+            let asked: Vec<String> = asked.iter().map(u32::to_string).collect();
+            let asked = if asked.is_empty() {
+                "none".to_owned()
+            } else {
+                asked.join(", ")
+            };
+            let ViewLimits { min, max } = state.view;
+            Some(format!("View radius: {min} to {max}; asked for: {asked}"))
+        }
+        ["view", min, max] => {
+            let (Ok(min), Ok(max)) = (min.parse(), max.parse()) else {
+                return Some(format!("Invalid radii `{min} {max}`"));
+            };
+            let Some(limits) = ViewLimits::new(min, max) else {
+                return Some(format!(
+                    "The smallest radius {min} is above the largest {max}"
+                ));
+            };
+            state.view = limits;
+            state.regrant_views();
+            Some(format!("View radius: {} to {}", limits.min, limits.max))
+        }
+        _ => None,
+    }
+}
+
+/// Commands that start and stop recording the session.
+fn record_command(state: &mut ServerState, words: &[&str]) -> Option<String> {
+    let output = match (words, &state.recording) {
+        (["record"] | ["record", "stop", ..], None) => "Not recording".to_owned(),
+        (["record"], Some(recording)) => format!(
+            "Recording since tick {} ({} ticks, {} keyframes)",
+            recording.start_tick(),
+            recording.ticks.len(),
+            recording.keyframes.len()
+        ),
+        (["record", "start"], Some(_)) => "Already recording".to_owned(),
+        (["record", "start"], None) => {
+            state.recording = Some(Recording::start(&state.game));
+            format!("Recording from tick {}", state.game.tick)
+        }
+        (["record", "stop", name @ ..], Some(_)) if name.len() <= 1 => {
+            let name = name.first().map_or_else(
+                || {
+                    let world: String = state
+                        .game
+                        .world_name
+                        .chars()
+                        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+                        .collect();
+                    format!("{world}-{}", state.game.tick)
+                },
+                |name| (*name).to_owned(),
+            );
+            if !recording::is_valid_name(&name) {
+                return Some(format!(
+                    "Invalid name `{name}`: use letters, digits, `-` and `_`"
+                ));
+            }
+            let recording = state.recording.take()?;
+            match recording.save(&name) {
+                Ok(path) => format!("Saved {}", path.display()),
+                Err(e) => {
+                    // Keep recording rather than lose what was recorded.
+                    state.recording = Some(recording);
+                    format!("Failed to save {name}: {e}")
+                }
+            }
+        }
+        _ => return None,
+    };
+    Some(output)
+}
+
+/// Commands for stepping the simulation back and forth; see
+/// [`history`](super::history).
+fn history_command(state: &mut ServerState, words: &[&str]) -> Option<String> {
+    let output = match words {
+        ["history"] => match &state.history {
+            None => "History is off".to_owned(),
+            Some(history) => {
+                let shown = history.viewing().map_or_else(
+                    || "live".to_owned(),
+                    |tick| format!("paused at tick {tick}"),
+                );
+                format!(
+                    "Keeping {} ticks: {}..={} ({} keyframes), {shown}",
+                    history.capacity(),
+                    history.oldest_tick(),
+                    history.newest_tick(),
+                    history.keyframe_count()
+                )
+            }
+        },
+        ["history", "on", ticks @ ..] if ticks.len() <= 1 => {
+            let capacity = match ticks.first().map(|t| t.parse()) {
+                None => DEFAULT_HISTORY_TICKS,
+                Some(Ok(capacity)) if capacity > 0 => capacity,
+                Some(_) => return Some(format!("Invalid tick count `{}`", ticks.join(" "))),
+            };
+            if state.history.as_ref().is_some_and(History::is_travelling) {
+                return Some("Resume before changing the history".to_owned());
+            }
+            state.history = Some(History::new(capacity));
+            format!("Keeping the last {capacity} ticks")
+        }
+        ["history", "off"] => match state.history.take() {
+            Some(history) if history.is_travelling() => {
+                format!("History off; live from tick {}", state.game.tick)
+            }
+            Some(_) => "History off".to_owned(),
+            None => "History is off".to_owned(),
+        },
+        ["history", step @ ("back" | "forward"), n @ ..] if n.len() <= 1 => {
+            let Ok(n) = n.first().map_or(Ok(1), |n| n.parse::<u64>()) else {
+                return Some(format!("Invalid tick count `{}`", n.join(" ")));
+            };
+            let Some(history) = &state.history else {
+                return Some("History is off; start it with `history on`".to_owned());
+            };
+            let tick = if *step == "back" {
+                state.game.tick.saturating_sub(n).max(history.oldest_tick())
+            } else {
+                state.game.tick.saturating_add(n).min(history.newest_tick())
+            };
+            travel(state, tick)
+        }
+        ["history", "goto", tick] => match tick.parse() {
+            Ok(tick) => travel(state, tick),
+            Err(_) => format!("Invalid tick `{tick}`"),
+        },
+        ["history", "resume"] => match &mut state.history {
+            Some(history) if history.is_travelling() => {
+                history.resume();
+                format!("Live from tick {}", state.game.tick)
+            }
+            _ => "Not paused".to_owned(),
+        },
+        _ => return None,
+    };
+    Some(output)
+}
+
+/// Commands that keep and show what lately happened to each entity; see
+/// [`trace`](super::trace).
+fn trace_command(state: &mut ServerState, words: &[&str]) -> Option<String> {
+    let output = match words {
+        ["trace"] => state.trace.as_ref().map_or_else(
+            || "Entity traces are off".to_owned(),
+            |trace| {
+                format!(
+                    "Keeping the last {} entries of {} entities",
+                    trace.capacity(),
+                    trace.entity_count()
+                )
+            },
+        ),
+        ["trace", "on", len @ ..] if len.len() <= 1 => {
+            let len = match len.first().map(|l| l.parse()) {
+                None => DEFAULT_TRACE_LEN,
+                Some(Ok(len)) if len > 0 => len,
+                Some(_) => return Some(format!("Invalid entry count `{}`", len.join(" "))),
+            };
+            let trace = EntityTrace::new(len);
+            let output = format!(
+                "Keeping the last {} entries of each entity",
+                trace.capacity()
+            );
+            state.trace = Some(trace);
+            output
+        }
+        ["trace", "off"] => {
+            state.trace = None;
+            "Entity traces off".to_owned()
+        }
+        ["trace", id] => {
+            let Ok(id) = id.parse() else {
+                return Some(format!("Invalid entity `{id}`"));
+            };
+            let Some(trace) = &state.trace else {
+                return Some("Entity traces are off; start them with `trace on`".to_owned());
+            };
+            let entries: Vec<String> = trace.of(EntityID(id)).map(ToString::to_string).collect();
+            if entries.is_empty() {
+                format!("Nothing traced for {id}")
+            } else {
+                format!("{id}, oldest first:\n  {}", entries.join("\n  "))
+            }
+        }
+        _ => return None,
+    };
+    Some(output)
+}
+
+/// Show the world at `tick`, if only one client is connected to see it.
+fn travel(state: &mut ServerState, tick: u64) -> String {
+    if state.sessions.connected().count() > 1 {
+        return "Stepping through history needs a single-player session".to_owned();
+    }
+    if state.recording.is_some() {
+        return "Stop recording before stepping through history".to_owned();
+    }
+    match state.travel_to(tick) {
+        Ok(None) => format!("Paused at tick {tick}"),
+        Ok(Some(diverged)) => format!(
+            "Paused at tick {tick}; tick {diverged} did not re-simulate to its recorded checksum"
+        ),
+        Err(reason) => reason,
+    }
+}
+
+/// Run `f` on the entity with ID `id`, or explain why there is none.
+fn with_entity(
+    state: &mut ServerState,
+    id: &str,
+    f: impl FnOnce(EntityID, &mut Entity) -> String,
+) -> String {
+    let Ok(id) = id.parse() else {
+        return format!("Invalid entity id `{id}`");
+    };
+    let eid = EntityID(id);
+    match state.game.entity_mut(eid) {
+        Some(entity) => f(eid, entity),
+        None => format!("No entity {id}"),
+    }
+}
+
+/// An entity's kind, position, faction, tags, metadata, reputation and
+/// inventory.
+fn describe(eid: EntityID, entity: &Entity) -> String {
+    let name = entity.name.as_deref().unwrap_or("-");
+    let faction = entity.faction.as_deref().unwrap_or("-");
+    let owner = entity.owner.map_or("-".to_owned(), |o| o.0.to_string());
+    let health = entity
+        .health
+        .map_or("-".to_owned(), |h| format!("{}/{}", h.current, h.max));
+    let tags: Vec<&str> = entity.tags.iter().collect();
+    let out = entity.metadata.iter().fold(
+        format!(
+            "{} {:?} `{name}` at ({}, {}), faction {faction}, owner {owner}, health {health}\n  tags: {}",
+            eid.0,
+            entity.entity_type,
+            entity.position.x,
+            entity.position.y,
+            tags.join(", ")
+        ),
+        |out, (key, value)| format!("{out}\n  {key} = {value}"),
+    );
+    let out = entity.reputation.iter().fold(out, |out, (faction, value)| {
+        format!(
+            "{out}\n  reputation with {faction}: {value} ({})",
+            Tier::of(value)
+        )
+    });
+    entity.inventory.iter().fold(out, |out, (item, count)| {
+        format!("{out}\n  carries {count} {item}")
+    })
+}
+
+/// Average time per tick of each system, with a text bar scaled to the
+/// slowest one.
+fn averages(profiler: &Profiler) -> String {
+    let averages = System::ALL.map(|system| (system, profiler.average(system)));
+    let slowest = averages.iter().map(|(_, d)| *d).max().unwrap_or_default();
+    averages.iter().fold(
+        format!("Average over {} ticks:", profiler.history().len()),
+        |out, (system, average)| {
+            let width = if slowest.is_zero() {
+                0
+            } else {
+                (average.as_secs_f64() / slowest.as_secs_f64() * 20.0).round() as usize
+            };
+            format!(
+                "{out}\n  {:<10} {:>8} µs {}",
+                system.name(),
+                average.as_micros(),
+                "█".repeat(width)
+            )
+        },
+    )
+}
+
+/// Write the profiler history to `profiles/<name>.csv`. Names are limited to
+/// letters, digits, `-` and `_`, so a client cannot pick where the file goes.
+fn dump_csv(profiler: &Profiler, name: &str) -> String {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return format!("Invalid file name `{name}`");
+    }
+    let dir = PathBuf::from("profiles");
+    let path = dir.join(format!("{name}.csv"));
+    match std::fs::create_dir_all(&dir).and_then(|()| std::fs::write(&path, profiler.to_csv())) {
+        Ok(()) => format!(
+            "Wrote {} ticks to {}",
+            profiler.history().len(),
+            path.display()
+        ),
+        Err(e) => format!("Failed to write {}: {e}", path.display()),
+    }
+}
+
+/// One line per issue under a count, or `none` if there are none.
+fn report(what: &str, issues: &[Issue], none: &str) -> String {
+    if issues.is_empty() {
+        return none.to_owned();
+    }
+    issues
+        .iter()
+        .fold(format!("{} {what}:", issues.len()), |out, issue| {
+            format!("{out}\n  {issue}")
+        })
+}
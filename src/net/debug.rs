@@ -0,0 +1,45 @@
+//! Debug data: what the server knows but never sends to play, for the
+//! client's debug overlays.
+//!
+//! A client asks for it with
+//! [`ClientMessage::Debug`](super::ClientMessage::Debug) and is then sent
+//! a [`DebugData`] with every world update. Only servers built with debug
+//! assertions answer; release servers ignore the request, so players
+//! cannot see where others are headed.
+
+use crate::game::{self, EntityID, GameState, Point};
+
+use bitcode::{Decode, Encode};
+
+/// Most routes sent in one [`DebugData`].
+pub const MAX_DEBUG_PATHS: usize = 64;
+
+/// Whether this server answers requests for debug data.
+pub const ENABLED: bool = cfg!(debug_assertions);
+
+/// What the server planned this tick.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Encode, Decode)]
+pub struct DebugData {
+    /// The route of each entity walking to a destination, as the tiles it
+    /// steps onto in order, by entity ID; at most [`MAX_DEBUG_PATHS`].
+    /// Entities with no way there are left out.
+    pub paths: Vec<(EntityID, Vec<Point>)>,
+}
+
+impl DebugData {
+    /// The routes of the entities in `game` walking to a destination,
+    /// planned with the blockers in its synced index.
+    pub fn collect(game: &GameState) -> Self {
+        let paths = game
+            .entities()
+            .iter()
+            .filter_map(|(eid, entity)| {
+                let destination = entity.destination?;
+                let path = game::pathfind(game, entity.position, destination)?;
+                Some((*eid, path))
+            })
+            .take(MAX_DEBUG_PATHS)
+            .collect();
+        Self { paths }
+    }
+}
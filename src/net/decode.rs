@@ -0,0 +1,267 @@
+//! Decode boundary for bytes received from the network.
+//!
+//! Everything a peer sends is untrusted. Messages are size-checked before
+//! decoding and their collections and strings are capped afterwards; any
+//! failure is reported as a [`DecodeError`] so the caller can drop the
+//! connection instead of panicking or allocating without bound.
+//!
+//! The protocol types are not recursive, so there is no nesting depth to
+//! limit beyond the caps below.
+
+use super::access::MAX_SECRET_LEN;
+use super::assets::{AssetHeader, AssetRequest, MAX_ASSET_NAME_LEN};
+#[cfg(feature = "backup-peer")]
+use super::backup::{BackupHeader, BackupRequest, MAX_BACKUP_NAME_LEN};
+use super::chat::MAX_CHAT_LEN;
+use super::presence::{MAX_PRESENCE_NAME_LEN, Presence};
+use super::recording::MAX_RECORDING_NAME_LEN;
+use super::status::{MAX_STATUS_NAME_LEN, ServerStatus};
+use super::whisper::MAX_BLOCKED;
+use super::{ClientMessage, MAX_MESSAGE_SIZE, Message, ServerMessage};
+use crate::game::formation::MAX_GROUP_SIZE;
+use crate::game::pack::MAX_PACKS;
+use crate::game::slots::MAX_SLOT_NAME_LEN;
+use crate::game::{GameAction, Intent};
+
+use std::fmt;
+
+/// Largest message a client may send. Client messages are small; anything
+/// near this size is hostile.
+pub const MAX_CLIENT_MESSAGE_SIZE: usize = 64 * 1024;
+
+/// Largest asset request a client may send.
+pub const MAX_ASSET_REQUEST_SIZE: usize = 1024;
+
+/// Largest status a server may answer a ping with.
+pub const MAX_STATUS_SIZE: usize = 1024;
+
+/// Largest presence a friend may answer a probe with.
+pub const MAX_PRESENCE_SIZE: usize = 1024;
+
+/// Longest player name accepted, in characters.
+pub const MAX_NAME_LEN: usize = 64;
+
+/// Longest console command accepted, in characters.
+pub const MAX_COMMAND_LEN: usize = 256;
+
+/// Longest item ID accepted in an action, in characters.
+pub const MAX_ITEM_ID_LEN: usize = 64;
+
+/// Longest character file accepted for import, in bytes.
+pub const MAX_CHARACTER_LEN: usize = 16 * 1024;
+
+/// Longest content pack ID accepted, in characters.
+pub const MAX_PACK_ID_LEN: usize = 64;
+
+/// Most snapshot parts a client may ask for in one request.
+pub const MAX_REQUESTED_PARTS: usize = 4096;
+
+/// Why inbound bytes were rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The message exceeds the size limit for its direction.
+    TooLarge(usize),
+    /// The bytes are not a valid encoded [`Message`].
+    Malformed,
+    /// A client sent a server message or vice versa.
+    WrongDirection,
+    /// A decoded field exceeds its cap.
+    FieldTooLarge(&'static str),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooLarge(len) => write!(f, "message too large ({len} bytes)"),
+            Self::Malformed => write!(f, "malformed message"),
+            Self::WrongDirection => write!(f, "message sent in the wrong direction"),
+            Self::FieldTooLarge(field) => write!(f, "field `{field}` exceeds its limit"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+fn decode_message(bytes: &[u8], limit: usize) -> Result<Message, DecodeError> {
+    if bytes.len() > limit {
+        return Err(DecodeError::TooLarge(bytes.len()));
+    }
+    bitcode::decode(bytes).ok().ok_or(DecodeError::Malformed)
+}
+
+/// Decode and validate a message received by the server.
+///
+/// # Errors
+/// If the message is too large, malformed, for the client, or breaks a
+/// cap.
+pub fn decode_client_message(bytes: &[u8]) -> Result<ClientMessage, DecodeError> {
+    let Message::Client(msg) = decode_message(bytes, MAX_CLIENT_MESSAGE_SIZE)? else {
+        return Err(DecodeError::WrongDirection);
+    };
+    validate_client_message(&msg)?;
+    Ok(msg)
+}
+
+/// Decode a message received by the client.
+///
+/// # Errors
+/// If the message is too large, malformed, or for the server.
+pub fn decode_server_message(bytes: &[u8]) -> Result<ServerMessage, DecodeError> {
+    match decode_message(bytes, MAX_MESSAGE_SIZE)? {
+        Message::Server(msg) => Ok(msg),
+        Message::Client(_) => Err(DecodeError::WrongDirection),
+    }
+}
+
+/// Decode and validate an asset request received by the server.
+///
+/// # Errors
+/// If the request is too large, malformed, or breaks a cap.
+pub fn decode_asset_request(bytes: &[u8]) -> Result<AssetRequest, DecodeError> {
+    if bytes.len() > MAX_ASSET_REQUEST_SIZE {
+        return Err(DecodeError::TooLarge(bytes.len()));
+    }
+    let request: AssetRequest = bitcode::decode(bytes).ok().ok_or(DecodeError::Malformed)?;
+    if request.name.chars().count() > MAX_ASSET_NAME_LEN {
+        return Err(DecodeError::FieldTooLarge("name"));
+    }
+    Ok(request)
+}
+
+/// Decode the header of an asset received by the client.
+///
+/// # Errors
+/// If the header is too large, malformed, or breaks a cap.
+pub fn decode_asset_header(bytes: &[u8]) -> Result<AssetHeader, DecodeError> {
+    bitcode::decode(bytes).ok().ok_or(DecodeError::Malformed)
+}
+
+/// Decode and validate a request received by a backup peer.
+///
+/// # Errors
+/// If the request is malformed or its name too long.
+#[cfg(feature = "backup-peer")]
+pub fn decode_backup_request(bytes: &[u8]) -> Result<BackupRequest, DecodeError> {
+    let request: BackupRequest = bitcode::decode(bytes).ok().ok_or(DecodeError::Malformed)?;
+    let (BackupRequest::Store { name, .. } | BackupRequest::Fetch { name }) = &request;
+    if name.chars().count() > MAX_BACKUP_NAME_LEN {
+        return Err(DecodeError::FieldTooLarge("name"));
+    }
+    Ok(request)
+}
+
+/// Decode a backup peer's answer.
+///
+/// # Errors
+/// If the answer is malformed.
+#[cfg(feature = "backup-peer")]
+pub fn decode_backup_header(bytes: &[u8]) -> Result<BackupHeader, DecodeError> {
+    bitcode::decode(bytes).ok().ok_or(DecodeError::Malformed)
+}
+
+/// Decode and validate a server's answer to a status ping.
+///
+/// # Errors
+/// If the status is too large, malformed, or breaks a cap.
+pub fn decode_server_status(bytes: &[u8]) -> Result<ServerStatus, DecodeError> {
+    if bytes.len() > MAX_STATUS_SIZE {
+        return Err(DecodeError::TooLarge(bytes.len()));
+    }
+    let status: ServerStatus = bitcode::decode(bytes).ok().ok_or(DecodeError::Malformed)?;
+    if status.world_name.chars().count() > MAX_STATUS_NAME_LEN {
+        return Err(DecodeError::FieldTooLarge("world_name"));
+    }
+    Ok(status)
+}
+
+/// Decode and validate a friend's answer to a presence probe.
+///
+/// # Errors
+/// If the presence is too large, malformed, or breaks a cap.
+pub fn decode_presence(bytes: &[u8]) -> Result<Presence, DecodeError> {
+    if bytes.len() > MAX_PRESENCE_SIZE {
+        return Err(DecodeError::TooLarge(bytes.len()));
+    }
+    let presence: Presence = bitcode::decode(bytes).ok().ok_or(DecodeError::Malformed)?;
+    if let Presence::Hosting { world, .. } | Presence::Playing { world, .. } = &presence
+        && world.chars().count() > MAX_PRESENCE_NAME_LEN
+    {
+        return Err(DecodeError::FieldTooLarge("world"));
+    }
+    Ok(presence)
+}
+
+/// Check the caps on a decoded client message.
+///
+/// # Errors
+/// With the first field over its cap.
+pub fn validate_client_message(msg: &ClientMessage) -> Result<(), DecodeError> {
+    match msg {
+        ClientMessage::Action(GameAction::SpawnPlayer(name))
+            if name.chars().count() > MAX_NAME_LEN =>
+        {
+            Err(DecodeError::FieldTooLarge("name"))
+        }
+        ClientMessage::RequestSnapshotParts { missing, .. }
+            if missing.len() > MAX_REQUESTED_PARTS =>
+        {
+            Err(DecodeError::FieldTooLarge("missing"))
+        }
+        ClientMessage::Command(line) if line.chars().count() > MAX_COMMAND_LEN => {
+            Err(DecodeError::FieldTooLarge("command"))
+        }
+        ClientMessage::Chat(text) if text.chars().count() > MAX_CHAT_LEN => {
+            Err(DecodeError::FieldTooLarge("chat"))
+        }
+        ClientMessage::Whisper { to, .. } if to.chars().count() > MAX_NAME_LEN => {
+            Err(DecodeError::FieldTooLarge("to"))
+        }
+        ClientMessage::Whisper { text, .. } if text.chars().count() > MAX_CHAT_LEN => {
+            Err(DecodeError::FieldTooLarge("chat"))
+        }
+        ClientMessage::Block(names)
+            if names.len() > MAX_BLOCKED
+                || names.iter().any(|n| n.chars().count() > MAX_NAME_LEN) =>
+        {
+            Err(DecodeError::FieldTooLarge("blocked"))
+        }
+        ClientMessage::Action(
+            GameAction::Buy { item, .. }
+            | GameAction::Sell { item, .. }
+            | GameAction::Throw { item, .. }
+            | GameAction::Repair(item)
+            | GameAction::Queue(Intent::Craft(item))
+            | GameAction::SplitStack { item, .. }
+            | GameAction::MergeStacks(item)
+            | GameAction::Stash { item, .. }
+            | GameAction::Retrieve { item, .. },
+        ) if item.chars().count() > MAX_ITEM_ID_LEN => Err(DecodeError::FieldTooLarge("item")),
+        ClientMessage::FetchRecording { name, .. }
+            if name.chars().count() > MAX_RECORDING_NAME_LEN =>
+        {
+            Err(DecodeError::FieldTooLarge("name"))
+        }
+        ClientMessage::ImportCharacter(text) if text.len() > MAX_CHARACTER_LEN => {
+            Err(DecodeError::FieldTooLarge("character"))
+        }
+        ClientMessage::SaveSlot(name) if name.chars().count() > MAX_SLOT_NAME_LEN => {
+            Err(DecodeError::FieldTooLarge("name"))
+        }
+        ClientMessage::Action(GameAction::MoveGroup { members, .. })
+            if members.len() > MAX_GROUP_SIZE =>
+        {
+            Err(DecodeError::FieldTooLarge("members"))
+        }
+        ClientMessage::Sync { packs, .. }
+            if packs.len() > MAX_PACKS
+                || packs.iter().any(|p| p.id.chars().count() > MAX_PACK_ID_LEN) =>
+        {
+            Err(DecodeError::FieldTooLarge("packs"))
+        }
+        ClientMessage::Sync {
+            secret: Some(secret),
+            ..
+        } if secret.len() > MAX_SECRET_LEN => Err(DecodeError::FieldTooLarge("secret")),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,223 @@
+//! Connectivity checks for the network diagnostics screen.
+//!
+//! "Can't connect" has many causes: no network at all, a firewall keeping
+//! the relays out, a server that is offline or a mistyped ID, or a NAT that
+//! forces traffic through a relay. [`run`] checks each in turn from a fresh
+//! endpoint and returns a [`Report`] saying what passed, what failed and
+//! what the player can do about it.
+
+use super::status::{self, ServerStatus};
+use super::{decode, recv_one_way};
+
+use iroh::endpoint::{Connection, ConnectionType, VarInt};
+use iroh::{Endpoint, EndpointId, Watcher as _};
+use n0_error::StdResultExt as _;
+use std::time::{Duration, Instant};
+
+/// How long to wait for a relay before calling it unreachable.
+pub const RELAY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a connection to the server may stay relayed before the direct
+/// check fails; hole punching takes a moment.
+pub const DIRECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How a check went.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Passed(String),
+    /// Failed, with what was seen and what the player can do about it.
+    Failed {
+        detail: String,
+        advice: &'static str,
+    },
+    /// Not run, e.g. for want of a server to check against.
+    Skipped(String),
+}
+
+/// One check and how it went.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub name: &'static str,
+    pub outcome: Outcome,
+}
+
+/// The checks run, in order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    fn push(&mut self, name: &'static str, outcome: Outcome) {
+        self.checks.push(Check { name, outcome });
+    }
+
+    fn passed(&mut self, name: &'static str, detail: String) {
+        self.push(name, Outcome::Passed(detail));
+    }
+
+    fn failed(&mut self, name: &'static str, detail: String, advice: &'static str) {
+        self.push(name, Outcome::Failed { detail, advice });
+    }
+
+    fn skipped(&mut self, name: &'static str, why: &str) {
+        self.push(name, Outcome::Skipped(why.to_owned()));
+    }
+
+    /// Returns `true` if no check failed.
+    pub fn all_passed(&self) -> bool {
+        !self
+            .checks
+            .iter()
+            .any(|check| matches!(check.outcome, Outcome::Failed { .. }))
+    }
+
+    /// What to do first: the advice of the first failed check, whose
+    /// failure likely caused the later ones.
+    pub fn verdict(&self) -> &'static str {
+        self.checks
+            .iter()
+            .find_map(|check| match check.outcome {
+                Outcome::Failed { advice, .. } => Some(advice),
+                Outcome::Passed(_) | Outcome::Skipped(_) => None,
+            })
+            .unwrap_or("Everything checked works; you should be able to connect.")
+    }
+}
+
+const NO_ENDPOINT: &str = "The game could not open a network socket. Check that a firewall or \
+                           security program lets it use the network.";
+const NO_RELAY: &str = "No relay server could be reached, so only players on your own network \
+                        can connect. Check your internet connection, and that a firewall or \
+                        proxy does not block the game.";
+const NO_ADDRESSES: &str = "Your machine has no network address others could connect to \
+                            directly; all traffic will go through a relay.";
+const NO_SERVER: &str = "The server did not answer. Check the server ID, and that the server \
+                         is running; if it is, its host's network may block the game.";
+const RELAYED: &str = "Traffic to the server goes through a relay, which adds latency. A \
+                       strict NAT or firewall on either side blocks direct connections; \
+                       allowing UDP traffic for the game, or playing from another network, \
+                       may help.";
+
+/// Run every check, against `server` if given.
+pub async fn run(server: Option<EndpointId>) -> Report {
+    let mut report = Report::default();
+    let endpoint = match Endpoint::bind().await {
+        Ok(endpoint) => endpoint,
+        Err(e) => {
+            report.failed("Network socket", e.to_string(), NO_ENDPOINT);
+            return report;
+        }
+    };
+    report.passed("Network socket", format!("bound as {}", endpoint.id()));
+
+    match tokio::time::timeout(RELAY_TIMEOUT, endpoint.online()).await {
+        Ok(()) => {
+            let addr = endpoint.addr();
+            let relays: Vec<String> = addr.relay_urls().map(ToString::to_string).collect();
+            report.passed("Relay", format!("reached {}", relays.join(", ")));
+        }
+        Err(_) => report.failed(
+            "Relay",
+            format!("none answered within {} s", RELAY_TIMEOUT.as_secs()),
+            NO_RELAY,
+        ),
+    }
+
+    let direct: Vec<String> = endpoint
+        .addr()
+        .ip_addrs()
+        .map(ToString::to_string)
+        .collect();
+    if direct.is_empty() {
+        report.failed("Direct addresses", "none found".to_owned(), NO_ADDRESSES);
+    } else {
+        report.passed("Direct addresses", direct.join(", "));
+    }
+
+    if let Some(server) = server {
+        check_server(&endpoint, server, &mut report).await;
+    } else {
+        report.skipped("Server", "no server ID given");
+        report.skipped("Direct connection", "no server ID given");
+    }
+    endpoint.close().await;
+    report
+}
+
+/// Connect to `server` as the server browser does, for its status.
+async fn reach(
+    endpoint: &Endpoint,
+    server: EndpointId,
+) -> n0_error::Result<(Connection, ServerStatus)> {
+    let conn = endpoint.connect(server, status::STATUS_ALPN).await?;
+    let recv = conn.accept_uni().await.anyerr()?;
+    let bytes = recv_one_way(recv, decode::MAX_STATUS_SIZE).await?;
+    let status = decode::decode_server_status(&bytes).anyerr()?;
+    Ok((conn, status))
+}
+
+/// Reach `server`, see how long it takes to answer, and whether the
+/// connection goes direct.
+async fn check_server(endpoint: &Endpoint, server: EndpointId, report: &mut Report) {
+    let start = Instant::now();
+    let reached = tokio::time::timeout(status::PROBE_TIMEOUT, reach(endpoint, server)).await;
+    let (conn, status) = match reached {
+        Ok(Ok(reached)) => reached,
+        Ok(Err(e)) => {
+            report.failed("Server", e.to_string(), NO_SERVER);
+            report.skipped("Direct connection", "the server was not reached");
+            return;
+        }
+        Err(_) => {
+            report.failed(
+                "Server",
+                format!("no answer within {} s", status::PROBE_TIMEOUT.as_secs()),
+                NO_SERVER,
+            );
+            report.skipped("Direct connection", "the server was not reached");
+            return;
+        }
+    };
+    report.passed(
+        "Server",
+        format!(
+            "{} answered in {} ms",
+            status.world_name,
+            start.elapsed().as_millis()
+        ),
+    );
+
+    let deadline = Instant::now() + DIRECT_TIMEOUT;
+    let mut kind = None;
+    while Instant::now() < deadline {
+        kind = endpoint.conn_type(server).map(|mut watcher| watcher.get());
+        if matches!(
+            kind,
+            Some(ConnectionType::Direct(_) | ConnectionType::Mixed(..))
+        ) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+    let rtt = conn.rtt().as_millis();
+    match kind {
+        Some(ConnectionType::Direct(addr) | ConnectionType::Mixed(addr, _)) => {
+            report.passed(
+                "Direct connection",
+                format!("via {addr}, {rtt} ms round trip"),
+            );
+        }
+        Some(ConnectionType::Relay(relay)) => report.failed(
+            "Direct connection",
+            format!("relayed through {relay}, {rtt} ms round trip"),
+            RELAYED,
+        ),
+        Some(ConnectionType::None) | None => report.failed(
+            "Direct connection",
+            "no path to the server was found".to_owned(),
+            RELAYED,
+        ),
+    }
+    conn.close(VarInt::from_u32(0), b"done");
+}
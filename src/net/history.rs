@@ -0,0 +1,209 @@
+//! Time-travel debugging: stepping the simulation back and forth.
+//!
+//! While enabled from the console, the server keeps the last few hundred
+//! ticks of its history: the actions applied each tick with the
+//! [`GameState::checksum`] they led to, and a full copy of the state every
+//! [`HISTORY_KEYFRAME_INTERVAL`] ticks. Any tick in that window is rebuilt
+//! by re-simulating from the keyframe before it, and every re-simulated
+//! tick is checked against its recorded checksum, so the first tick that
+//! does not reproduce is reported.
+//!
+//! Spawns, imports and console commands change the world outside a tick;
+//! the history notices when the state no longer matches the last recorded
+//! checksum and takes a keyframe there, so re-simulation never has to
+//! replay them.
+//!
+//! Keyframes hold the whole [`GameState`], so history is meant for
+//! single-player and development sessions, not busy servers.
+
+use super::apply_actions;
+use crate::game::{self, ContentRegistry, EntityID, GameAction, GameState};
+use crate::profile;
+
+use std::collections::VecDeque;
+
+/// Ticks kept when no length is given: 30 seconds.
+pub const DEFAULT_HISTORY_TICKS: usize = 600;
+
+/// Ticks between two keyframes, at most.
+pub const HISTORY_KEYFRAME_INTERVAL: u64 = 50;
+
+/// One tick of history.
+#[derive(Debug, Clone, PartialEq)]
+struct HistoryTick {
+    /// The tick this one brought the world to.
+    tick: u64,
+    actions: Vec<(EntityID, GameAction)>,
+    /// Checksum of the state at the end of the tick.
+    checksum: u64,
+}
+
+/// The world rebuilt at a past tick.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rebuilt {
+    pub state: GameState,
+    /// First tick whose re-simulation did not match its checksum.
+    pub diverged_at: Option<u64>,
+}
+
+/// A bounded history of the simulation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct History {
+    capacity: usize,
+    /// Full states, oldest first; never empty once a tick was recorded.
+    keyframes: VecDeque<GameState>,
+    ticks: VecDeque<HistoryTick>,
+    /// The past tick being shown, while travelling.
+    viewing: Option<u64>,
+}
+
+impl History {
+    /// Keep about `capacity` ticks.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            keyframes: VecDeque::new(),
+            ticks: VecDeque::new(),
+            viewing: None,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Call before a tick is simulated: takes a keyframe if one is due or
+    /// the state changed since the last recorded tick.
+    pub fn before_tick(&mut self, state: &GameState) {
+        let changed = self
+            .ticks
+            .back()
+            .is_none_or(|t| t.tick != state.tick || t.checksum != state.checksum());
+        let due = self
+            .keyframes
+            .back()
+            .is_none_or(|k| state.tick.saturating_sub(k.tick) >= HISTORY_KEYFRAME_INTERVAL);
+        if changed || due {
+            self.keyframes.push_back(state.clone());
+        }
+    }
+
+    /// Call after a tick was simulated, with the actions it applied.
+    pub fn after_tick(&mut self, mut actions: Vec<(EntityID, GameAction)>, state: &GameState) {
+        // Re-simulating must not write save files; saving changes nothing.
+        actions.retain(|(_, action)| !matches!(action, GameAction::SaveWorld));
+        self.ticks.push_back(HistoryTick {
+            tick: state.tick,
+            actions,
+            checksum: state.checksum(),
+        });
+
+        let horizon = state.tick.saturating_sub(self.capacity as u64);
+        while self.keyframes.get(1).is_some_and(|k| k.tick <= horizon) {
+            self.keyframes.pop_front();
+        }
+        let oldest = self.oldest_tick();
+        while self.ticks.front().is_some_and(|t| t.tick <= oldest) {
+            self.ticks.pop_front();
+        }
+    }
+
+    /// Oldest tick that can be rebuilt.
+    pub fn oldest_tick(&self) -> u64 {
+        self.keyframes.front().map_or(0, |k| k.tick)
+    }
+
+    /// Newest tick that can be rebuilt.
+    pub fn newest_tick(&self) -> u64 {
+        self.ticks
+            .back()
+            .map_or_else(|| self.oldest_tick(), |t| t.tick)
+    }
+
+    /// The past tick being shown, or `None` while the world runs live.
+    pub fn viewing(&self) -> Option<u64> {
+        self.viewing
+    }
+
+    /// Returns `true` while a past tick is shown and the simulation is
+    /// paused.
+    pub fn is_travelling(&self) -> bool {
+        self.viewing.is_some()
+    }
+
+    /// Rebuild the world at `tick` by re-simulating the recorded actions
+    /// from the keyframe before it.
+    ///
+    /// # Errors
+    /// If `tick` is not in the history.
+    pub fn rebuild(&self, tick: u64) -> Result<Rebuilt, String> {
+        if self.keyframes.is_empty() || tick < self.oldest_tick() || tick > self.newest_tick() {
+            return Err(format!(
+                "Tick {tick} is not in the history ({}..={})",
+                self.oldest_tick(),
+                self.newest_tick()
+            ));
+        }
+        let keyframe = self.keyframes.partition_point(|k| k.tick <= tick);
+        let mut state = self
+            .keyframes
+            .get(keyframe.saturating_sub(1))
+            .cloned()
+            .ok_or_else(|| "History has no keyframe".to_owned())?;
+
+        let registry = ContentRegistry::builtin();
+        let from = state.tick;
+        let mut diverged_at = None;
+        for recorded in self
+            .ticks
+            .iter()
+            .skip_while(|t| t.tick <= from)
+            .take_while(|t| t.tick <= tick)
+        {
+            apply_actions(&mut state, &recorded.actions);
+            game::advance(&mut state, &registry);
+            if diverged_at.is_none() && state.checksum() != recorded.checksum {
+                diverged_at = Some(recorded.tick);
+            }
+        }
+        Ok(Rebuilt { state, diverged_at })
+    }
+
+    /// Mark `tick` as the past tick being shown.
+    pub fn travel(&mut self, tick: u64) {
+        self.viewing = Some(tick);
+    }
+
+    /// Go live again from the tick being shown, forgetting the ticks after
+    /// it.
+    pub fn resume(&mut self) {
+        if let Some(tick) = self.viewing.take() {
+            while self.ticks.back().is_some_and(|t| t.tick > tick) {
+                self.ticks.pop_back();
+            }
+            while self.keyframes.len() > 1 && self.keyframes.back().is_some_and(|k| k.tick > tick) {
+                self.keyframes.pop_back();
+            }
+        }
+    }
+
+    /// Number of keyframes held.
+    pub fn keyframe_count(&self) -> usize {
+        self.keyframes.len()
+    }
+
+    /// Estimated bytes held by the history.
+    pub fn memory_bytes(&self) -> usize {
+        self.keyframes
+            .iter()
+            .map(|k| k.memory_report().total())
+            .sum::<usize>()
+            + self
+                .ticks
+                .iter()
+                .map(|t| profile::vec_bytes(&t.actions))
+                .sum::<usize>()
+            + profile::deque_bytes(&self.keyframes)
+            + profile::deque_bytes(&self.ticks)
+    }
+}
@@ -0,0 +1,93 @@
+//! What the world does while no one is connected.
+//!
+//! By default it runs on regardless. A host may instead have it stop
+//! ticking once the last player leaves, so an idle server costs next to no
+//! CPU: effects, growth and creatures all wait. When someone connects
+//! again, the world either picks up where it stopped or, with
+//! [`WhenEmpty::CatchUp`], first simulates the ticks it missed, up to
+//! [`MAX_CATCH_UP_TICKS`].
+
+use serde::{Deserialize, Serialize};
+
+/// Most ticks simulated when catching up: five minutes.
+pub const MAX_CATCH_UP_TICKS: u64 = 6000;
+
+/// What the world does while no one is connected.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WhenEmpty {
+    /// Keep ticking.
+    #[default]
+    Run,
+    /// Stop ticking, and carry on from there.
+    Pause,
+    /// Stop ticking, and simulate the ticks missed on the next join.
+    CatchUp,
+}
+
+impl WhenEmpty {
+    pub const ALL: [Self; 3] = [Self::Run, Self::Pause, Self::CatchUp];
+
+    /// Name in console commands.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Run => "run",
+            Self::Pause => "pause",
+            Self::CatchUp => "catch-up",
+        }
+    }
+
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::Run => "Keep running",
+            Self::Pause => "Pause",
+            Self::CatchUp => "Pause, then catch up",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|w| w.name() == name)
+    }
+}
+
+/// Whether the world is paused for lack of players, and for how long.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Idle {
+    pub when_empty: WhenEmpty,
+    /// Ticks skipped since the world paused.
+    missed: u64,
+}
+
+impl Idle {
+    pub const fn new(when_empty: WhenEmpty) -> Self {
+        Self {
+            when_empty,
+            missed: 0,
+        }
+    }
+
+    /// Returns `true` if the world should skip this tick, with `connected`
+    /// telling whether anyone is.
+    pub const fn pauses(&self, connected: bool) -> bool {
+        !connected && !matches!(self.when_empty, WhenEmpty::Run)
+    }
+
+    /// Ticks skipped since the world paused; 0 while it runs.
+    pub const fn missed(&self) -> u64 {
+        self.missed
+    }
+
+    /// Note a skipped tick.
+    pub const fn skip(&mut self) {
+        self.missed += 1;
+    }
+
+    /// Start ticking again. Returns how many of the missed ticks to
+    /// simulate first.
+    pub fn wake(&mut self) -> u64 {
+        let missed = std::mem::take(&mut self.missed);
+        match self.when_empty {
+            WhenEmpty::CatchUp => missed.min(MAX_CATCH_UP_TICKS),
+            WhenEmpty::Run | WhenEmpty::Pause => 0,
+        }
+    }
+}
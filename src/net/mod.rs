@@ -0,0 +1,4197 @@
+//! Networking layer.
+//!
+//! Provides a [`Transport`] trait abstracting over real sockets and test
+//! channels, protocol message types, and the iroh-based server/client.
+
+pub mod access;
+pub mod assets;
+#[cfg(feature = "backup-peer")]
+pub mod backup;
+pub mod bot;
+pub mod cache;
+pub mod chat;
+pub mod console;
+pub mod debug;
+pub mod decode;
+pub mod diagnostics;
+pub mod history;
+pub mod idle;
+pub mod plugin;
+pub mod presence;
+pub mod queue;
+pub mod recording;
+pub mod session;
+pub mod snapshot;
+pub mod status;
+pub mod trace;
+pub mod view;
+pub mod whisper;
+
+pub use access::{AccessRules, Denied, Gate};
+pub use assets::{AssetServer, AssetStore, Download};
+pub use cache::{Marker, MarkerColor, Markers, WorldCache};
+pub use chat::{ChatLine, ChatLog, LineKind, MessageLog};
+pub use debug::DebugData;
+pub use history::History;
+pub use idle::{Idle, WhenEmpty};
+pub use plugin::{Caller, Plugins, ServerPlugin};
+pub use presence::{Presence, PresenceBoard};
+pub use queue::JoinQueue;
+pub use recording::{Playback, Recording};
+pub use session::{SessionTable, SessionToken};
+pub use snapshot::{
+    ChangeIndex, ChunkedSnapshot, DeltaLog, Silhouette, SilhouetteDelta, SnapshotAssembler,
+    WorldDelta,
+};
+pub use status::{ServerStatus, StatusServer};
+pub use trace::EntityTrace;
+pub use view::{ViewLimits, ViewWindow};
+pub use whisper::{Whisper, WhisperLimiter};
+
+use crate::game::content::ContentWatcher;
+use crate::game::world_events::{self, Announcement};
+use crate::game::{
+    self, ChunkId, ContentRegistry, DialogueView, EntityID, EntityMap, Explosion, GameAction,
+    GameEvent, GameState, PackManifest, Ping, PlayerColor, PlayerKey, Point, PortableCharacter,
+    Roster, Terrain, WaitEnd, WorldId, pack, ping, roster, slots, transfer,
+};
+use crate::profile::{self, MemoryReport, Profiler, System};
+
+use bitcode::{Decode, Encode};
+use iroh::{
+    Endpoint, EndpointAddr, EndpointId, SecretKey,
+    endpoint::{Connection, RecvStream, VarInt},
+    protocol::{AcceptError, ProtocolHandler, Router},
+};
+use n0_error::{Result, StdResultExt};
+use rustc_hash::FxHashMap;
+use std::{
+    any::Any,
+    collections::BTreeSet,
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+
+use tokio::sync::{mpsc, oneshot, watch};
+
+// ---------------------------------------------------------------------------
+// Constants
+// ---------------------------------------------------------------------------
+
+const ALPN: &[u8] = b"iroh-example/echo/0";
+const MAX_MESSAGE_SIZE: usize = 10 * 1024 * 1024; // 10 MB
+/// Time between two server ticks.
+pub const TICK_INTERVAL: Duration = Duration::from_millis(50);
+const RECONNECT_ATTEMPTS: u32 = 5;
+const RECONNECT_DELAY: Duration = Duration::from_millis(500);
+/// Game actions accepted from one client per tick; extra ones are dropped.
+pub const MAX_ACTIONS_PER_TICK: u32 = 4;
+
+// ---------------------------------------------------------------------------
+// Type aliases
+// ---------------------------------------------------------------------------
+
+/// Maps connected endpoints to the entity they control.
+pub type EndpointMap = FxHashMap<EndpointId, EntityID>;
+
+// ---------------------------------------------------------------------------
+// Protocol messages
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Encode, Decode)]
+pub enum ServerMessage {
+    /// Full copy of the world as of `tick`.
+    Snapshot {
+        tick: u64,
+        entities: EntityMap,
+    },
+    /// Announces a full snapshot too big for one message; its parts follow.
+    SnapshotManifest {
+        tick: u64,
+        parts: u32,
+        total_len: u64,
+    },
+    /// One part of a chunked snapshot.
+    SnapshotPart {
+        tick: u64,
+        index: u32,
+        bytes: Vec<u8>,
+    },
+    /// Changes since the last update the client received.
+    Delta(WorldDelta),
+    /// Changes to the silhouettes of entities near a client with a limited
+    /// view but out of its sight; see [`view`].
+    Silhouettes(SilhouetteDelta),
+    PlayerID(EntityID),
+    /// Token to present in [`ClientMessage::Resume`] after a dropped connection.
+    Session(SessionToken),
+    /// The session was resumed; missed deltas follow in the next update.
+    Resumed(Option<EntityID>),
+    /// The session could not be resumed; the client has to join from scratch.
+    ResumeRejected,
+    /// Sent on connect so the client can look up its cache of this world.
+    WorldInfo {
+        id: WorldId,
+        name: String,
+    },
+    /// The terrain of a chunk, row by row; sent on connect for every chunk
+    /// that is not all grass, and for chunks planted later.
+    Terrain {
+        chunk: ChunkId,
+        tiles: Vec<Terrain>,
+    },
+    /// Output of a [`ClientMessage::Command`].
+    ConsoleOutput(String),
+    /// The node of the player's conversation, or `None` once it ended.
+    Dialogue(Option<DialogueView>),
+    /// Why the player's last buy or sell was refused.
+    TradeRejected(String),
+    /// Why the player's last repair was refused.
+    RepairRejected(String),
+    /// Why the player's last split, merge or move of items was refused.
+    StackRejected(String),
+    /// The player's item with this ID wore through and broke.
+    ItemBroke(String),
+    /// Why a [`GameAction::SpawnPlayer`] or [`GameAction::SpawnAs`] was
+    /// refused; the client stays without a character.
+    SpawnRejected(String),
+    /// The player's character as a [`PortableCharacter`] in RON, in reply
+    /// to [`ClientMessage::ExportCharacter`].
+    CharacterExport(String),
+    /// Why an export was refused.
+    ExportRejected(String),
+    /// The world was saved to the slot with this name, in reply to
+    /// [`ClientMessage::SaveSlot`].
+    SlotSaved(String),
+    /// Why saving to a slot was refused.
+    SlotRejected(String),
+    /// An imported character arrived without these items, with how many,
+    /// under the world's transfer rules. Its [`ServerMessage::PlayerID`]
+    /// follows.
+    Imported {
+        stripped: Vec<(String, u32)>,
+    },
+    /// Names of the recordings saved on the server.
+    Recordings(Vec<String>),
+    /// Part `index` of the `parts` parts of a recording file, in reply to
+    /// [`ClientMessage::FetchRecording`].
+    RecordingPart {
+        name: String,
+        index: u32,
+        parts: u32,
+        bytes: Vec<u8>,
+    },
+    /// Why a recording could not be sent.
+    RecordingUnavailable(String),
+    /// The identified player's characters in this world; sent after
+    /// [`ClientMessage::Identify`] and whenever the roster changes.
+    Roster(Roster),
+    /// A world event started or ended.
+    WorldEvent(Announcement),
+    /// A blast went off, reaching `tiles`; what it destroyed arrives in the
+    /// next delta.
+    Explosion {
+        origin: Point,
+        tiles: Vec<Point>,
+    },
+    /// An entity was knocked back rather than walking; the move itself
+    /// arrives in the next delta.
+    Pushed {
+        entity_id: EntityID,
+        from: Point,
+        to: Point,
+    },
+    /// Share of its channelled intent an entity has done, from 0 to 1. Sent
+    /// as the intent starts and a few times on the way, not every tick.
+    ActionProgress {
+        entity_id: EntityID,
+        fraction: f32,
+    },
+    /// The player's character, or an entity it owns, gave up walking to
+    /// `destination`, the way there blocked.
+    PathBlocked {
+        entity_id: EntityID,
+        destination: Point,
+    },
+    /// The player's character stopped waiting, as `end` says.
+    WaitEnded {
+        entity_id: EntityID,
+        end: WaitEnd,
+    },
+    /// A teammate marked a tile; shown until it expires.
+    Ping(Ping),
+    /// A line of chat or a notable event, as it happens; see [`chat`].
+    Chat(ChatLine),
+    /// The last lines of chat and events, oldest first, in reply to
+    /// [`ClientMessage::ChatHistory`].
+    ChatHistory(Vec<ChatLine>),
+    /// The client lacks these content packs of the server's, so it was not
+    /// let in; no world follows.
+    PacksRejected(Vec<PackManifest>),
+    /// The client was not let in: the server is protected and its password
+    /// or invite code did not do, or the server is full with no room left
+    /// to wait. No world follows. See [`access`].
+    AccessDenied(Denied),
+    /// The server is full; the client waits for a slot at this place in
+    /// line, 1 being next. Sent again whenever the place changes. See
+    /// [`queue`].
+    Queued(u32),
+    /// A slot freed up for the waiting client; the world follows.
+    Admitted,
+    /// The view radius granted in reply to [`ClientMessage::ViewDistance`],
+    /// or `None` if the client took its request back, and again whenever
+    /// the server's limits change it. See [`view`].
+    ViewDistance(Option<u32>),
+    /// A private message to or from the client; see [`whisper`].
+    Whisper(Whisper),
+    /// A whisper was not sent: no player of that name is here, or the
+    /// client sends them too fast.
+    WhisperFailed(String),
+    /// What the server planned this tick, for a client that asked with
+    /// [`ClientMessage::Debug`].
+    Debug(DebugData),
+}
+
+#[derive(Debug, Clone, Encode, Decode)]
+pub enum ClientMessage {
+    Action(GameAction),
+    /// Re-attach to a previous session, replaying everything after `last_tick`
+    /// (or sending a full snapshot if the client has no complete world yet).
+    Resume {
+        token: SessionToken,
+        last_tick: Option<u64>,
+    },
+    /// Ask again for snapshot parts that never arrived.
+    RequestSnapshotParts {
+        tick: u64,
+        missing: Vec<u32>,
+    },
+    /// Reply to [`ServerMessage::WorldInfo`]: the tick of the client's cached
+    /// copy of the world, if it has one, and the content packs the client
+    /// has. World updates start after this, if it has every pack the server
+    /// runs with, and, on protected servers, a password or invite code
+    /// that lets it in.
+    Sync {
+        since_tick: Option<u64>,
+        packs: Vec<PackManifest>,
+        secret: Option<String>,
+    },
+    /// A line typed into the console; see [`console`].
+    Command(String),
+    /// Say which player is connecting, so the server can keep their
+    /// characters on one roster. Sent before spawning.
+    Identify(PlayerKey),
+    /// Ask for the controlled character as a [`PortableCharacter`].
+    ExportCharacter,
+    /// Spawn a character brought from another world, given as the RON of
+    /// a [`PortableCharacter`]. Refused like a [`GameAction::SpawnPlayer`].
+    ImportCharacter(String),
+    /// Ask which recordings the server has saved.
+    ListRecordings,
+    /// Ask for one part of a saved recording; clients fetch the parts one
+    /// after the other.
+    FetchRecording {
+        name: String,
+        part: u32,
+    },
+    /// Say something to everyone in the world; needs a character.
+    Chat(String),
+    /// Ask for up to this many of the last lines of chat and events, e.g.
+    /// after joining.
+    ChatHistory(u32),
+    /// Ask to be sent only the world within this radius of the controlled
+    /// character, and to see that far, or with `None` for the whole world
+    /// again; sent with [`ClientMessage::Sync`] or at any time after. The
+    /// server grants a radius within its limits.
+    ViewDistance(Option<u32>),
+    /// Save the world to the named slot, apart from its own save; see
+    /// [`slots`](crate::game::slots).
+    SaveSlot(String),
+    /// Say something to the player whose character is named `to` only;
+    /// needs a character. See [`whisper`].
+    Whisper {
+        to: String,
+        text: String,
+    },
+    /// Drop whispers from the players of these names from now on,
+    /// replacing the list sent before.
+    Block(Vec<String>),
+    /// Ask for [`ServerMessage::Debug`] with every world update, or stop;
+    /// see [`debug`].
+    Debug(bool),
+}
+
+#[derive(Debug, Clone, Encode, Decode)]
+pub enum Message {
+    Client(ClientMessage),
+    Server(ServerMessage),
+}
+
+// ---------------------------------------------------------------------------
+// Transport trait
+// ---------------------------------------------------------------------------
+
+/// Abstraction over a network transport so that game logic and tests can work
+/// with both real sockets and in-memory channels.
+pub trait Transport: Send + Sync + 'static {
+    /// Send a message to the remote peer.
+    fn send(&self, msg: Message) -> std::result::Result<(), Box<dyn std::error::Error + Send>>;
+    /// Try to receive a message (non-blocking).
+    fn try_recv(&mut self) -> Option<Message>;
+}
+
+/// In-memory transport for testing.
+pub struct MockTransport {
+    pub tx: mpsc::UnboundedSender<Message>,
+    pub rx: mpsc::UnboundedReceiver<Message>,
+}
+
+impl Transport for MockTransport {
+    fn send(&self, msg: Message) -> std::result::Result<(), Box<dyn std::error::Error + Send>> {
+        self.tx
+            .send(msg)
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send>)
+    }
+
+    fn try_recv(&mut self) -> Option<Message> {
+        self.rx.try_recv().ok()
+    }
+}
+
+/// Create a pair of connected [`MockTransport`]s for testing.
+pub fn mock_transport_pair() -> (MockTransport, MockTransport) {
+    let (tx_a, rx_b) = mpsc::unbounded_channel();
+    let (tx_b, rx_a) = mpsc::unbounded_channel();
+    (
+        MockTransport { tx: tx_a, rx: rx_a },
+        MockTransport { tx: tx_b, rx: rx_b },
+    )
+}
+
+// ---------------------------------------------------------------------------
+// Server state (game state + networking bookkeeping)
+// ---------------------------------------------------------------------------
+
+/// State owned by the server: the authoritative game state plus networking
+/// metadata that does not belong in the pure game layer.
+#[derive(Debug)]
+pub struct ServerState {
+    pub game: GameState,
+    pub endpoints: EndpointMap,
+    pub unique_server_messages: FxHashMap<EndpointId, Vec<ServerMessage>>,
+    pub event_queue: Vec<(EntityID, GameAction)>,
+    /// Game actions accepted from each endpoint during the current tick.
+    pub actions_this_tick: FxHashMap<EndpointId, u32>,
+    pub sessions: SessionTable,
+    pub deltas: DeltaLog,
+    /// When each entity last changed, for syncing clients with a cached world.
+    pub changes: ChangeIndex,
+    /// Most recent chunked snapshot, kept so lost parts can be re-sent.
+    pub snapshot_cache: Option<ChunkedSnapshot>,
+    /// Time spent per tick in applying, encoding and sending.
+    pub profiler: Profiler,
+    /// The session being recorded, if any; see [`recording`].
+    pub recording: Option<Recording>,
+    /// Recent ticks kept for stepping back, if enabled; see [`history`].
+    pub history: Option<History>,
+    /// What lately happened to each entity, if enabled; see [`trace`].
+    pub trace: Option<EntityTrace>,
+    /// Recent chat and notable events, kept in memory only unless opened
+    /// on a file; see [`chat`].
+    pub chat: ChatLog,
+    /// Content packs a client needs to join; see [`pack`].
+    pub packs: Vec<PackManifest>,
+    /// Who may join, and the wrong guesses made lately; see [`access`].
+    pub access: Gate,
+    /// Clients waiting for a slot while the world is at its player cap.
+    pub queue: JoinQueue,
+    /// Whether the world pauses while no one is connected; see [`idle`].
+    pub idle: Idle,
+    /// The view radii granted to clients asking for one; see [`view`].
+    pub view: ViewLimits,
+    /// Whispers sent lately, to rate limit them; see [`whisper`].
+    pub whispers: WhisperLimiter,
+    /// Commands added by plugins; see [`plugin`].
+    pub plugins: Plugins,
+    /// Content files read again as they change, if watched; see
+    /// [`ContentWatcher`].
+    pub content_watch: Option<ContentWatcher>,
+    /// Entity map as of the previous tick, used to compute the next delta.
+    last_entities: EntityMap,
+}
+
+impl ServerState {
+    pub fn new(mut game: GameState) -> Self {
+        game.sync_index();
+        let last_entities = game.entities().clone();
+        let changes = ChangeIndex::new(game.tick);
+        Self {
+            game,
+            endpoints: EndpointMap::default(),
+            unique_server_messages: FxHashMap::default(),
+            event_queue: Vec::new(),
+            actions_this_tick: FxHashMap::default(),
+            sessions: SessionTable::default(),
+            deltas: DeltaLog::new(session::SESSION_RESUME_TICKS as usize),
+            changes,
+            snapshot_cache: None,
+            profiler: Profiler::default(),
+            recording: None,
+            history: None,
+            trace: None,
+            chat: ChatLog::default(),
+            packs: pack::active(),
+            access: Gate::default(),
+            queue: JoinQueue::default(),
+            idle: Idle::default(),
+            view: ViewLimits::default(),
+            whispers: WhisperLimiter::default(),
+            plugins: Plugins::default(),
+            content_watch: None,
+            last_entities,
+        }
+    }
+
+    /// Estimated memory held by the server: the world plus the history and
+    /// caches kept for syncing clients.
+    pub fn memory_report(&self) -> MemoryReport {
+        let queued: usize = self
+            .unique_server_messages
+            .values()
+            .map(profile::vec_bytes)
+            .sum();
+        MemoryReport::default()
+            .nest("game", self.game.memory_report())
+            .with("last entities", game::entity_map_bytes(&self.last_entities))
+            .with("delta log", self.deltas.memory_bytes())
+            .with("change index", self.changes.memory_bytes())
+            .with(
+                "snapshot cache",
+                self.snapshot_cache
+                    .as_ref()
+                    .map_or(0, ChunkedSnapshot::memory_bytes),
+            )
+            .with(
+                "history",
+                self.history.as_ref().map_or(0, History::memory_bytes),
+            )
+            .with(
+                "entity trace",
+                self.trace.as_ref().map_or(0, EntityTrace::memory_bytes),
+            )
+            .with("sessions", self.sessions.memory_bytes())
+            .with("chat", self.chat.memory_bytes())
+            .with(
+                "recording",
+                self.recording.as_ref().map_or(0, Recording::memory_bytes),
+            )
+            .with(
+                "queued messages",
+                profile::hash_map_bytes(&self.unique_server_messages) + queued,
+            )
+    }
+
+    /// Drain the event queue and apply each action to the game state.
+    ///
+    /// An action that panics is rejected: the error is logged, the acting
+    /// entity is restored to its state before the action, and the remaining
+    /// actions still run, so one client's bad input cannot stop the tick loop.
+    pub fn process_events(&mut self) {
+        let events: Vec<(EntityID, GameAction)> = self.event_queue.drain(..).collect();
+        let mut dialogue_changed = Vec::new();
+        let mut rejected_trades = Vec::new();
+        let mut item_notices = Vec::new();
+        let mut pushes = Vec::new();
+        let mut explosions = Vec::new();
+        let mut deaths = Vec::new();
+        let mut pings = Vec::new();
+
+        let applied = apply_actions(&mut self.game, &events);
+        if let Some(trace) = &mut self.trace {
+            // The actions are part of the tick about to run.
+            let tick = self.game.tick + 1;
+            trace.actions(tick, &events);
+            trace.events(tick, &applied);
+        }
+        for event in applied {
+            match event {
+                GameEvent::DialogueChanged { entity_id } => {
+                    dialogue_changed.push(entity_id);
+                }
+                GameEvent::TradeRejected { entity_id, reason } => {
+                    rejected_trades.push((entity_id, reason));
+                }
+                GameEvent::RepairRejected { entity_id, reason } => {
+                    item_notices
+                        .push((entity_id, ServerMessage::RepairRejected(reason.to_string())));
+                }
+                GameEvent::StackRejected { entity_id, reason } => {
+                    item_notices
+                        .push((entity_id, ServerMessage::StackRejected(reason.to_string())));
+                }
+                GameEvent::ItemBroke { entity_id, item } => {
+                    item_notices.push((entity_id, ServerMessage::ItemBroke(item)));
+                }
+                GameEvent::Pushed {
+                    entity_id,
+                    from,
+                    to,
+                    ..
+                } => pushes.push(ServerMessage::Pushed {
+                    entity_id,
+                    from,
+                    to,
+                }),
+                GameEvent::Exploded(explosion) => {
+                    deaths.extend(explosion.killed.iter().filter_map(|(_, name)| name.clone()));
+                    explosions.push(explosion);
+                }
+                GameEvent::Died {
+                    name: Some(name), ..
+                } => deaths.push(name),
+                GameEvent::Pinged(ping) => pings.push(ping),
+                _ => {}
+            }
+        }
+
+        dialogue_changed.sort();
+        dialogue_changed.dedup();
+        for eid in dialogue_changed {
+            let view = game::dialogue::view(&self.game, &ContentRegistry::builtin(), eid);
+            self.send_to_controllers(eid, &ServerMessage::Dialogue(view));
+        }
+        for (eid, reason) in rejected_trades {
+            self.send_to_controllers(eid, &ServerMessage::TradeRejected(reason.to_string()));
+        }
+        for (eid, notice) in item_notices {
+            self.send_to_controllers(eid, &notice);
+        }
+        for push in pushes {
+            self.broadcast(&push);
+        }
+        for explosion in explosions {
+            self.announce_explosion(&explosion);
+        }
+        for name in deaths {
+            self.log(LineKind::Death, format!("{name} died"));
+        }
+        for ping in pings {
+            self.send_to_team(ping.from, &ServerMessage::Ping(ping));
+        }
+    }
+
+    /// Log a line of chat or a notable event and send it to everyone.
+    fn log(&mut self, kind: LineKind, text: String) {
+        let line = ChatLine {
+            tick: self.game.tick,
+            kind,
+            text,
+        };
+        self.broadcast(&ServerMessage::Chat(line.clone()));
+        if let Err(e) = self.chat.push(line) {
+            log::warn!("Failed to write the chat log: {e}");
+        }
+    }
+
+    /// Name of `eid` for the chat log.
+    fn name_of(&self, eid: EntityID) -> String {
+        self.game
+            .entities()
+            .get(&eid)
+            .and_then(|e| e.name.clone())
+            .unwrap_or_else(|| format!("#{}", eid.0))
+    }
+
+    /// Queue a message for every endpoint controlling `eid`.
+    fn send_to_controllers(&mut self, eid: EntityID, msg: &ServerMessage) {
+        let controllers: Vec<EndpointId> = self
+            .endpoints
+            .iter()
+            .filter(|(_, controlled)| **controlled == eid)
+            .map(|(endpoint_id, _)| *endpoint_id)
+            .collect();
+        for endpoint_id in controllers {
+            self.send_to(endpoint_id, msg.clone());
+        }
+    }
+
+    /// Queue a message for every endpoint controlling an entity on the
+    /// team of `eid`, itself included.
+    fn send_to_team(&mut self, eid: EntityID, msg: &ServerMessage) {
+        let Some(sender) = self.game.entities().get(&eid) else {
+            return;
+        };
+        let team: Vec<EndpointId> = self
+            .endpoints
+            .iter()
+            .filter(|(_, controlled)| {
+                self.game
+                    .entities()
+                    .get(controlled)
+                    .is_some_and(|e| ping::same_team(sender, e))
+            })
+            .map(|(endpoint_id, _)| *endpoint_id)
+            .collect();
+        for endpoint_id in team {
+            self.send_to(endpoint_id, msg.clone());
+        }
+    }
+
+    /// Queue a message for every connected endpoint.
+    fn broadcast(&mut self, msg: &ServerMessage) {
+        let endpoints: Vec<EndpointId> = self.sessions.connected().copied().collect();
+        for endpoint_id in endpoints {
+            self.send_to(endpoint_id, msg.clone());
+        }
+    }
+
+    /// Tell every client about a blast and the knockbacks it caused.
+    pub fn announce_explosion(&mut self, explosion: &Explosion) {
+        self.broadcast(&ServerMessage::Explosion {
+            origin: explosion.origin,
+            tiles: explosion.tiles.clone(),
+        });
+        for (entity_id, from, to) in &explosion.pushed {
+            self.broadcast(&ServerMessage::Pushed {
+                entity_id: *entity_id,
+                from: *from,
+                to: *to,
+            });
+        }
+    }
+
+    /// Advance the server by one tick: apply queued actions, work on queued
+    /// intents, walk group moves and followers one step, restock shops, run scheduled world events, record
+    /// what changed (and record it, while recording), count play time on rosters,
+    /// and forget sessions that can no longer be resumed.
+    ///
+    /// Nothing happens while a past tick of the [`history`] is shown, or
+    /// while the world is paused for lack of players (see [`idle`]); actions
+    /// sent meanwhile are dropped.
+    ///
+    /// The tick's profile is closed here, so it holds this tick's apply time
+    /// and the encode and send time of the updates sent after the previous one.
+    /// Watched content files are reloaded first, even while paused.
+    pub fn step(&mut self) {
+        // Sessions run out in real time, so even while the world is paused.
+        self.sessions.expire(Instant::now());
+        self.reload_content();
+        // Recordings and history need every tick, so keep the world going.
+        let connected = self.sessions.connected().next().is_some();
+        let idle =
+            self.recording.is_none() && self.history.is_none() && self.idle.pauses(connected);
+        if idle {
+            self.idle.skip();
+        }
+        if idle || self.history.as_ref().is_some_and(History::is_travelling) {
+            self.event_queue.clear();
+            self.actions_this_tick.clear();
+            return;
+        }
+        let missed = self.idle.wake();
+        if missed > 0 {
+            self.catch_up(missed);
+        }
+        let start = Instant::now();
+        let actions =
+            (self.recording.is_some() || self.history.is_some()).then(|| self.event_queue.clone());
+        if let Some(history) = &mut self.history {
+            history.before_tick(&self.game);
+        }
+        self.process_events();
+        self.profiler.record(System::Apply, start.elapsed());
+        let events = game::advance_profiled(
+            &mut self.game,
+            &ContentRegistry::builtin(),
+            &mut self.profiler,
+        );
+        self.actions_this_tick.clear();
+        if let Some(trace) = &mut self.trace {
+            trace.events(self.game.tick, &events);
+        }
+        let mut rolls = Vec::new();
+        for event in events {
+            match event {
+                GameEvent::Rolled(rolled) if self.recording.is_some() => rolls.push(rolled),
+                GameEvent::Announced(announcement) => {
+                    self.broadcast(&ServerMessage::WorldEvent(announcement));
+                }
+                GameEvent::ActionProgress {
+                    entity_id,
+                    done,
+                    total,
+                } => self.broadcast(&ServerMessage::ActionProgress {
+                    entity_id,
+                    fraction: done as f32 / total.max(1) as f32,
+                }),
+                GameEvent::ChunkLoaded(chunk) => self.send_chunk_terrain(chunk),
+                GameEvent::PathBlocked {
+                    entity_id,
+                    destination,
+                } => {
+                    let controller = self
+                        .game
+                        .entities()
+                        .get(&entity_id)
+                        .and_then(|e| e.owner)
+                        .unwrap_or(entity_id);
+                    let msg = ServerMessage::PathBlocked {
+                        entity_id,
+                        destination,
+                    };
+                    self.send_to_controllers(controller, &msg);
+                }
+                GameEvent::WaitEnded { entity_id, end } => {
+                    self.send_to_controllers(
+                        entity_id,
+                        &ServerMessage::WaitEnded { entity_id, end },
+                    );
+                }
+                _ => {}
+            }
+        }
+        if let Some(history) = &mut self.history {
+            history.after_tick(actions.clone().unwrap_or_default(), &self.game);
+        }
+
+        let delta = WorldDelta::between(self.game.tick, &self.last_entities, self.game.entities());
+        if let Some(trace) = &mut self.trace {
+            trace.changes(&delta, &self.last_entities);
+        }
+        self.last_entities.clone_from(self.game.entities());
+        self.changes.record(&delta);
+        self.game.sync_index();
+        if let Some(recording) = &mut self.recording {
+            let actions = actions.unwrap_or_default();
+            recording.record(delta.clone(), actions, rolls, self.game.entities());
+        }
+        self.deltas.push(delta);
+
+        // Every played character gets this tick on its roster.
+        let mut played: Vec<EntityID> = self.endpoints.values().copied().collect();
+        played.sort_unstable();
+        played.dedup();
+        for eid in played {
+            roster::record(&mut self.game, eid, |stats| stats.ticks_played += 1);
+        }
+        self.profiler.finish_tick(self.game.tick);
+    }
+
+    /// Install the content files under [`content_watch`](Self::content_watch)
+    /// once they change, if they parse and agree with each other. Broken
+    /// files are logged and the content in use is kept, so a typo while
+    /// editing does not take the server down.
+    fn reload_content(&mut self) {
+        let Some(reloaded) = self
+            .content_watch
+            .as_mut()
+            .and_then(|watch| watch.poll(Instant::now()))
+        else {
+            return;
+        };
+        match reloaded {
+            Ok(content) => {
+                content.install();
+                log::info!("Reloaded the content files");
+            }
+            Err(e) => log::warn!("Kept the content in use, the files are broken: {e}"),
+        }
+    }
+
+    /// Simulate `ticks` missed while the world was paused, as if no one had
+    /// done anything, and send everyone the world as it is now.
+    fn catch_up(&mut self, ticks: u64) {
+        for _ in 0..ticks {
+            let registry = ContentRegistry::builtin();
+            for event in game::advance_profiled(&mut self.game, &registry, &mut self.profiler) {
+                match event {
+                    GameEvent::Announced(announcement) => {
+                        self.broadcast(&ServerMessage::WorldEvent(announcement));
+                    }
+                    GameEvent::ChunkLoaded(chunk) => self.send_chunk_terrain(chunk),
+                    _ => {}
+                }
+            }
+        }
+        self.resync_clients();
+    }
+
+    /// Show the world at `tick`, rebuilt from the history, and pause there.
+    /// Returns the first tick that did not re-simulate to its recorded
+    /// checksum, if any.
+    ///
+    /// # Errors
+    /// If the history is off or does not hold `tick`.
+    pub fn travel_to(&mut self, tick: u64) -> std::result::Result<Option<u64>, String> {
+        let history = self
+            .history
+            .as_mut()
+            .ok_or_else(|| "History is off; start it with `history on`".to_owned())?;
+        let rebuilt = history.rebuild(tick)?;
+        history.travel(tick);
+        self.game = rebuilt.state;
+        self.resync_clients();
+        Ok(rebuilt.diverged_at)
+    }
+
+    /// Drop everything kept for sending deltas and send every client a full
+    /// snapshot next, after the world jumped to another tick.
+    fn resync_clients(&mut self) {
+        self.last_entities.clone_from(self.game.entities());
+        self.deltas = DeltaLog::new(session::SESSION_RESUME_TICKS as usize);
+        self.changes = ChangeIndex::new(self.game.tick);
+        self.game.rebuild_index();
+        self.snapshot_cache = None;
+        self.sessions.resend_snapshots();
+    }
+
+    /// Queue a message for a single endpoint.
+    fn send_to(&mut self, endpoint_id: EndpointId, msg: ServerMessage) {
+        self.unique_server_messages
+            .entry(endpoint_id)
+            .or_default()
+            .push(msg);
+    }
+
+    /// Make `endpoint_id` control `eid`, drawn in its player's color if it
+    /// has none yet, and tell everyone who joined.
+    fn bind_entity(&mut self, endpoint_id: EndpointId, eid: EntityID) {
+        self.endpoints.insert(endpoint_id, eid);
+        if let Some(key) = self.player_of(&endpoint_id)
+            && let Some(entity) = self.game.entity_mut(eid)
+        {
+            entity.color.get_or_insert(PlayerColor::of(key));
+        }
+        if let Some(session) = self.sessions.get_mut(&endpoint_id) {
+            session.entity_id = Some(eid);
+        }
+        self.send_to(endpoint_id, ServerMessage::PlayerID(eid));
+        let name = self.name_of(eid);
+        self.log(LineKind::Join, format!("{name} joined"));
+    }
+
+    /// The player `endpoint_id` identified as, if any.
+    fn player_of(&self, endpoint_id: &EndpointId) -> Option<PlayerKey> {
+        self.sessions.get(endpoint_id)?.player
+    }
+
+    /// Send the roster of the player behind `endpoint_id`, if identified.
+    fn send_roster(&mut self, endpoint_id: EndpointId) {
+        if let Some(key) = self.player_of(&endpoint_id) {
+            let roster = self.game.rosters.get(&key).cloned().unwrap_or_default();
+            self.send_to(endpoint_id, ServerMessage::Roster(roster));
+        }
+    }
+
+    /// Create a new character for `endpoint_id`, unless its player's roster
+    /// is full.
+    fn spawn_new(&mut self, endpoint_id: EndpointId, name: String) {
+        let player = self.player_of(&endpoint_id);
+        if let Some(key) = player
+            && let Err(reason) = roster::check_room(&self.game.rosters, key)
+        {
+            self.send_to(endpoint_id, ServerMessage::SpawnRejected(reason));
+            return;
+        }
+        let pid = game::spawn_player(&mut self.game, name);
+        if let Some(key) = player {
+            roster::claim(&mut self.game.rosters, key, pid).ok();
+        }
+        self.bind_entity(endpoint_id, pid);
+        self.send_roster(endpoint_id);
+    }
+
+    /// Send the character `endpoint_id` controls as a portable character.
+    fn export_character(&mut self, endpoint_id: EndpointId) {
+        let reply = match self.endpoints.get(&endpoint_id) {
+            Some(eid) => transfer::export(&self.game, *eid)
+                .and_then(|character| character.to_ron())
+                .map_or_else(
+                    ServerMessage::ExportRejected,
+                    ServerMessage::CharacterExport,
+                ),
+            None => ServerMessage::ExportRejected("you have no character".to_owned()),
+        };
+        self.send_to(endpoint_id, reply);
+    }
+
+    /// Bring a character from another world in for `endpoint_id`, under
+    /// the world's transfer rules and the player's roster limit.
+    fn import_character(&mut self, endpoint_id: EndpointId, text: &str) {
+        let player = self.player_of(&endpoint_id);
+        let imported = player
+            .map_or(Ok(()), |key| roster::check_room(&self.game.rosters, key))
+            .and_then(|()| PortableCharacter::from_ron(text))
+            .and_then(|character| {
+                transfer::import(&mut self.game, &ContentRegistry::builtin(), &character)
+            });
+        let imported = match imported {
+            Ok(imported) => imported,
+            Err(reason) => {
+                self.send_to(endpoint_id, ServerMessage::SpawnRejected(reason));
+                return;
+            }
+        };
+        if let Some(key) = player {
+            roster::claim(&mut self.game.rosters, key, imported.entity_id).ok();
+        }
+        let stripped = imported.stripped;
+        self.send_to(endpoint_id, ServerMessage::Imported { stripped });
+        self.bind_entity(endpoint_id, imported.entity_id);
+        self.send_roster(endpoint_id);
+    }
+
+    /// Let `endpoint_id` take over the existing character `eid`. It must be
+    /// a playable entity that no other session, connected or resumable,
+    /// controls, and that is on no other player's roster; otherwise the
+    /// client is told why not. Identified players add it to their roster,
+    /// which is logged if it was no one's yet.
+    fn spawn_as(&mut self, endpoint_id: EndpointId, eid: EntityID) {
+        let result = if !self.game.get_playable_entities().contains(&eid) {
+            Err("no such character".to_owned())
+        } else if self.sessions.controlled_elsewhere(eid, &endpoint_id)
+            || self
+                .endpoints
+                .iter()
+                .any(|(other, controlled)| *controlled == eid && *other != endpoint_id)
+        {
+            Err("that character is already in play".to_owned())
+        } else {
+            let owner = roster::owner_of(&self.game.rosters, eid);
+            match self.player_of(&endpoint_id) {
+                Some(key) => {
+                    roster::claim(&mut self.game.rosters, key, eid).map(|()| owner.is_none())
+                }
+                None if owner.is_some() => {
+                    Err("that character belongs to another player".to_owned())
+                }
+                None => Ok(false),
+            }
+        };
+        match result {
+            Err(reason) => self.send_to(endpoint_id, ServerMessage::SpawnRejected(reason)),
+            Ok(claimed) => {
+                if claimed {
+                    let name = self.name_of(eid);
+                    self.log(LineKind::Claim, format!("{name} was claimed by a player"));
+                }
+                self.bind_entity(endpoint_id, eid);
+                self.send_roster(endpoint_id);
+            }
+        }
+    }
+
+    /// Register a newly accepted connection, hand it a session token and
+    /// tell it which world it joined, what its terrain is and which events
+    /// are running.
+    pub fn connect(&mut self, endpoint_id: EndpointId) {
+        let token = self.sessions.open(endpoint_id);
+        self.send_to(endpoint_id, ServerMessage::Session(token));
+        let id = self.game.world_id;
+        let name = self.game.world_name.clone();
+        self.send_to(endpoint_id, ServerMessage::WorldInfo { id, name });
+        for msg in self.terrain_messages() {
+            self.send_to(endpoint_id, msg);
+        }
+        for announcement in world_events::running(&self.game) {
+            self.send_to(endpoint_id, ServerMessage::WorldEvent(announcement));
+        }
+    }
+
+    /// A [`ServerMessage::Terrain`] for every chunk of the terrain.
+    fn terrain_messages(&self) -> Vec<ServerMessage> {
+        self.game
+            .terrain
+            .chunks()
+            .map(|(chunk, tiles)| ServerMessage::Terrain {
+                chunk,
+                tiles: tiles.to_vec(),
+            })
+            .collect()
+    }
+
+    /// Send everyone the terrain of `chunk`, which was just loaded, if it
+    /// has any.
+    fn send_chunk_terrain(&mut self, chunk: ChunkId) {
+        if let Some(tiles) = self.game.terrain.chunk(chunk) {
+            let tiles = tiles.to_vec();
+            self.broadcast(&ServerMessage::Terrain { chunk, tiles });
+        }
+    }
+
+    /// Send everyone the whole terrain again, after the world grew.
+    pub fn resend_terrain(&mut self) {
+        for msg in self.terrain_messages() {
+            self.broadcast(&msg);
+        }
+    }
+
+    /// Forget a closed connection, telling everyone if it had a character,
+    /// and hand its slot to whoever waits next. Its session stays
+    /// resumable for a while.
+    pub fn disconnect(&mut self, endpoint_id: EndpointId) {
+        let left = self.endpoints.remove(&endpoint_id);
+        self.unique_server_messages.remove(&endpoint_id);
+        self.sessions.disconnect(&endpoint_id, Instant::now());
+        self.queue.remove(&endpoint_id);
+        self.whispers.forget(&endpoint_id);
+        if let Some(eid) = left {
+            let name = self.name_of(eid);
+            self.log(LineKind::Leave, format!("{name} left"));
+        }
+        self.fill_slots();
+    }
+
+    /// Who `endpoint_id` is, to the console: an admin if its player is
+    /// the host or one the server names.
+    pub fn caller(&self, endpoint_id: EndpointId) -> Caller {
+        let player = self.sessions.get(&endpoint_id).and_then(|s| s.player);
+        let admin = self.access.host == Some(endpoint_id)
+            || player.is_some_and(|p| self.access.rules.admins.contains(&p));
+        Caller {
+            endpoint: endpoint_id,
+            player,
+            entity: self.endpoints.get(&endpoint_id).copied(),
+            admin,
+        }
+    }
+
+    /// Returns `true` if one more player fits under the player cap.
+    fn has_room(&self) -> bool {
+        self.access
+            .rules
+            .max_players
+            .is_none_or(|max| self.sessions.admitted() < max as usize)
+    }
+
+    /// Start sending `endpoint_id` the world, as a delta against
+    /// `since_tick` if it has that tick cached.
+    fn admit(&mut self, endpoint_id: EndpointId, since_tick: Option<u64>) {
+        let now = self.game.tick;
+        if let Some(session) = self.sessions.get_mut(&endpoint_id) {
+            session.awaiting_sync = false;
+            session.last_sent_tick = since_tick.filter(|t| *t <= now);
+        }
+    }
+
+    /// Let waiting clients in while there is room, and tell the rest their
+    /// place in line.
+    fn fill_slots(&mut self) {
+        while self.has_room()
+            && let Some((endpoint_id, since_tick)) = self.queue.pop()
+        {
+            self.admit(endpoint_id, since_tick);
+            self.send_to(endpoint_id, ServerMessage::Admitted);
+        }
+        for (endpoint_id, position) in self.queue.positions() {
+            self.send_to(endpoint_id, ServerMessage::Queued(position));
+        }
+    }
+
+    /// Handle a message received from `endpoint_id`.
+    pub fn handle_client_message(&mut self, endpoint_id: EndpointId, msg: ClientMessage) {
+        let let_in = self
+            .sessions
+            .get(&endpoint_id)
+            .is_some_and(|s| !s.awaiting_sync)
+            || (self.access.rules.is_open() && self.queue.position(&endpoint_id).is_none());
+        if !let_in
+            && !matches!(
+                msg,
+                ClientMessage::Sync { .. }
+                    | ClientMessage::Identify(_)
+                    | ClientMessage::Resume { .. }
+                    | ClientMessage::ViewDistance(_)
+            )
+        {
+            // Not let in yet, or waiting in line; all it can do is try again.
+            return;
+        }
+        match msg {
+            ClientMessage::Action(GameAction::SpawnPlayer(name)) => {
+                self.spawn_new(endpoint_id, name);
+            }
+            ClientMessage::Action(GameAction::SpawnAs(eid)) => {
+                self.spawn_as(endpoint_id, eid);
+            }
+            ClientMessage::Action(other) => {
+                let count = self.actions_this_tick.entry(endpoint_id).or_default();
+                if *count >= MAX_ACTIONS_PER_TICK {
+                    return;
+                }
+                *count += 1;
+                if let Some(pid) = self.endpoints.get(&endpoint_id).copied() {
+                    self.event_queue.push((pid, other));
+                }
+            }
+            ClientMessage::Resume { token, last_tick } => {
+                self.resume(endpoint_id, token, last_tick);
+            }
+            ClientMessage::Sync {
+                since_tick,
+                packs,
+                secret,
+            } => self.sync(endpoint_id, let_in, since_tick, &packs, secret.as_deref()),
+            ClientMessage::RequestSnapshotParts { tick, missing } => {
+                self.resend_snapshot_parts(endpoint_id, tick, missing);
+            }
+            ClientMessage::Command(line) => {
+                let caller = self.caller(endpoint_id);
+                let output = isolate(|| console::run(self, &caller, &line))
+                    .unwrap_or_else(|e| format!("Command failed: {e}"));
+                self.send_to(endpoint_id, ServerMessage::ConsoleOutput(output));
+            }
+            ClientMessage::Identify(key) => {
+                if let Some(session) = self.sessions.get_mut(&endpoint_id) {
+                    session.player = Some(key);
+                }
+                self.send_roster(endpoint_id);
+            }
+            ClientMessage::ListRecordings => {
+                self.send_to(endpoint_id, ServerMessage::Recordings(recording::list()));
+            }
+            ClientMessage::FetchRecording { name, part } => {
+                let reply = match recording::read_part(&name, part) {
+                    Ok((parts, bytes)) => ServerMessage::RecordingPart {
+                        name,
+                        index: part,
+                        parts,
+                        bytes,
+                    },
+                    Err(reason) => ServerMessage::RecordingUnavailable(reason),
+                };
+                self.send_to(endpoint_id, reply);
+            }
+            ClientMessage::ExportCharacter => self.export_character(endpoint_id),
+            ClientMessage::SaveSlot(name) => {
+                let reply = slots::save(&self.game, &name)
+                    .map_or_else(ServerMessage::SlotRejected, |slot| {
+                        ServerMessage::SlotSaved(slot.name)
+                    });
+                self.send_to(endpoint_id, reply);
+            }
+            ClientMessage::ImportCharacter(text) => self.import_character(endpoint_id, &text),
+            ClientMessage::Chat(text) => self.say(endpoint_id, &text),
+            ClientMessage::Whisper { to, text } => self.whisper(endpoint_id, &to, &text),
+            msg @ (ClientMessage::Block(_) | ClientMessage::Debug(_)) => {
+                self.set_preference(endpoint_id, msg);
+            }
+            ClientMessage::ChatHistory(count) => {
+                let count = (count as usize).min(chat::KEPT_LINES);
+                let lines = self.chat.recent(count);
+                self.send_to(endpoint_id, ServerMessage::ChatHistory(lines));
+            }
+            ClientMessage::ViewDistance(requested) => {
+                if let Some(session) = self.sessions.get_mut(&endpoint_id) {
+                    session.view_radius = requested;
+                }
+                let granted = requested.map(|radius| self.view.grant(radius));
+                self.send_to(endpoint_id, ServerMessage::ViewDistance(granted));
+            }
+        }
+    }
+
+    /// Remember what the client of `endpoint_id` asked for in `msg`, for
+    /// its session.
+    fn set_preference(&mut self, endpoint_id: EndpointId, msg: ClientMessage) {
+        let Some(session) = self.sessions.get_mut(&endpoint_id) else {
+            return;
+        };
+        match msg {
+            ClientMessage::Block(names) => {
+                session.blocked = names.iter().map(|name| whisper::name_key(name)).collect();
+            }
+            ClientMessage::Debug(on) => session.debug = on && debug::ENABLED,
+            _ => {}
+        }
+    }
+
+    /// Let `endpoint_id` in once it has the content packs, and the password
+    /// or invite code if the server wants one, or put it in line.
+    fn sync(
+        &mut self,
+        endpoint_id: EndpointId,
+        let_in: bool,
+        since_tick: Option<u64>,
+        packs: &[PackManifest],
+        secret: Option<&str>,
+    ) {
+        let missing = pack::missing(&self.packs, packs);
+        if !missing.is_empty() {
+            self.send_to(endpoint_id, ServerMessage::PacksRejected(missing));
+            return;
+        }
+        if !let_in && let Err(denied) = self.access.check(endpoint_id, secret, Instant::now()) {
+            self.send_to(endpoint_id, ServerMessage::AccessDenied(denied));
+            return;
+        }
+        if self
+            .sessions
+            .get(&endpoint_id)
+            .is_none_or(|s| !s.awaiting_sync)
+        {
+            return;
+        }
+        if self.has_room() && self.queue.is_empty() {
+            self.admit(endpoint_id, since_tick);
+            return;
+        }
+        let reply = self.queue.push(endpoint_id, since_tick).map_or(
+            ServerMessage::AccessDenied(Denied::ServerFull),
+            ServerMessage::Queued,
+        );
+        self.send_to(endpoint_id, reply);
+    }
+
+    /// Send `endpoint_id` the parts of the snapshot of `tick` it is
+    /// missing, or a fresh snapshot if that one is gone.
+    fn resend_snapshot_parts(&mut self, endpoint_id: EndpointId, tick: u64, missing: Vec<u32>) {
+        let parts: Vec<ServerMessage> = match &self.snapshot_cache {
+            Some(cache) if cache.tick == tick => missing
+                .into_iter()
+                .filter_map(|index| {
+                    let bytes = cache.parts.get(index as usize)?.clone();
+                    Some(ServerMessage::SnapshotPart { tick, index, bytes })
+                })
+                .collect(),
+            _ => {
+                // The snapshot is gone; start over with a fresh one.
+                if let Some(session) = self.sessions.get_mut(&endpoint_id) {
+                    session.last_sent_tick = None;
+                }
+                Vec::new()
+            }
+        };
+        for part in parts {
+            self.send_to(endpoint_id, part);
+        }
+    }
+
+    /// Pick up the session `token` names where `endpoint_id` left it, or
+    /// tell it to start over.
+    fn resume(&mut self, endpoint_id: EndpointId, token: SessionToken, last_tick: Option<u64>) {
+        if let Some(entity_id) = self
+            .sessions
+            .resume(endpoint_id, token, last_tick, Instant::now())
+            .map(|s| s.entity_id)
+        {
+            if let Some(eid) = entity_id {
+                self.endpoints.insert(endpoint_id, eid);
+            }
+            self.send_to(endpoint_id, ServerMessage::Resumed(entity_id));
+            self.send_to(endpoint_id, ServerMessage::Session(token));
+        } else {
+            self.send_to(endpoint_id, ServerMessage::ResumeRejected);
+            // Re-send the token issued on connect, which the client
+            // forgets on rejection.
+            if let Some(fresh) = self.sessions.token_of(&endpoint_id) {
+                self.send_to(endpoint_id, ServerMessage::Session(fresh));
+            }
+        }
+    }
+
+    /// Tell every client that asked for a view radius what it is granted
+    /// now, after the limits changed.
+    fn regrant_views(&mut self) {
+        let requests: Vec<(EndpointId, u32)> = self
+            .sessions
+            .connected()
+            .filter_map(|endpoint_id| {
+                let requested = self.sessions.get(endpoint_id)?.view_radius?;
+                Some((*endpoint_id, requested))
+            })
+            .collect();
+        for (endpoint_id, requested) in requests {
+            let granted = self.view.grant(requested);
+            self.send_to(endpoint_id, ServerMessage::ViewDistance(Some(granted)));
+        }
+    }
+
+    /// Log what `endpoint_id`'s character says. Chat counts towards the
+    /// actions a client may send per tick.
+    fn say(&mut self, endpoint_id: EndpointId, text: &str) {
+        let text = text.trim();
+        let Some(eid) = self.endpoints.get(&endpoint_id).copied() else {
+            return;
+        };
+        let count = self.actions_this_tick.entry(endpoint_id).or_default();
+        if text.is_empty() || *count >= MAX_ACTIONS_PER_TICK {
+            return;
+        }
+        *count += 1;
+        let name = self.name_of(eid);
+        self.log(LineKind::Chat, format!("{name}: {text}"));
+    }
+
+    /// Hand what `endpoint_id`'s character whispers to the players whose
+    /// characters are named `to`, unless they blocked it, and echo it back.
+    /// Whispers count towards the actions a client may send per tick.
+    fn whisper(&mut self, endpoint_id: EndpointId, to: &str, text: &str) {
+        let text = text.trim();
+        let Some(eid) = self.endpoints.get(&endpoint_id).copied() else {
+            return;
+        };
+        let count = self.actions_this_tick.entry(endpoint_id).or_default();
+        if text.is_empty() || *count >= MAX_ACTIONS_PER_TICK {
+            return;
+        }
+        *count += 1;
+        let key = whisper::name_key(to);
+        let recipients: Vec<(EndpointId, String)> = self
+            .endpoints
+            .iter()
+            .map(|(id, controlled)| (*id, self.name_of(*controlled)))
+            .filter(|(_, name)| whisper::name_key(name) == key)
+            .collect();
+        let Some((_, to)) = recipients.first().cloned() else {
+            let reply = ServerMessage::WhisperFailed(format!("No player named {to} is here"));
+            self.send_to(endpoint_id, reply);
+            return;
+        };
+        if !self.whispers.allow(endpoint_id, Instant::now()) {
+            let reply = ServerMessage::WhisperFailed(
+                "You are sending messages too fast; wait a moment".to_owned(),
+            );
+            self.send_to(endpoint_id, reply);
+            return;
+        }
+        let from = self.name_of(eid);
+        let from_key = whisper::name_key(&from);
+        let message = ServerMessage::Whisper(Whisper {
+            tick: self.game.tick,
+            from,
+            to,
+            text: text.to_owned(),
+        });
+        for (recipient, _) in recipients {
+            let blocked = self
+                .sessions
+                .get(&recipient)
+                .is_some_and(|s| s.blocked.contains(&from_key));
+            if recipient != endpoint_id && !blocked {
+                self.send_to(recipient, message.clone());
+            }
+        }
+        self.send_to(endpoint_id, message);
+    }
+
+    /// Everything that changed after tick `since`, from the recent delta log
+    /// if it reaches back that far, otherwise from the change index.
+    fn delta_since(&self, since: u64) -> Option<WorldDelta> {
+        self.deltas.since(since).or_else(|| {
+            self.changes
+                .since(since, self.game.entities(), self.game.tick)
+        })
+    }
+
+    /// Build a full snapshot of the current tick: a single message if it is
+    /// small enough, otherwise a manifest followed by its parts.
+    fn full_snapshot(&mut self) -> Vec<ServerMessage> {
+        if self
+            .snapshot_cache
+            .as_ref()
+            .is_none_or(|cache| cache.tick != self.game.tick)
+        {
+            let chunked = ChunkedSnapshot::split(self.game.tick, self.game.entities());
+            if chunked.parts.len() <= 1 {
+                return vec![ServerMessage::Snapshot {
+                    tick: self.game.tick,
+                    entities: self.game.entities().clone(),
+                }];
+            }
+            self.snapshot_cache = Some(chunked);
+        }
+
+        let Some(cache) = &self.snapshot_cache else {
+            return Vec::new();
+        };
+        let mut out = vec![ServerMessage::SnapshotManifest {
+            tick: cache.tick,
+            parts: cache.parts.len() as u32,
+            total_len: cache.total_len() as u64,
+        }];
+        out.extend(
+            (0u32..)
+                .zip(&cache.parts)
+                .map(|(index, bytes)| ServerMessage::SnapshotPart {
+                    tick: cache.tick,
+                    index,
+                    bytes: bytes.clone(),
+                }),
+        );
+        out
+    }
+
+    /// Collect everything that should be sent to `endpoint_id` this tick:
+    /// queued per-endpoint messages followed by a world update.
+    ///
+    /// The world update is a delta since the last tick the session was sent
+    /// (or the tick of its cache), or a full snapshot if there is none or it
+    /// is too old. Nothing is sent until the client has synced. A client
+    /// with a limited view that controls a character is sent only the
+    /// [`ViewWindow`] around it.
+    pub fn drain_updates(&mut self, endpoint_id: EndpointId) -> Vec<ServerMessage> {
+        let mut out = self
+            .unique_server_messages
+            .remove(&endpoint_id)
+            .unwrap_or_default();
+
+        let Some(session) = self.sessions.get(&endpoint_id).filter(|s| !s.awaiting_sync) else {
+            return out;
+        };
+        if session.debug {
+            out.push(ServerMessage::Debug(DebugData::collect(&self.game)));
+        }
+        let mut last_sent_tick = session.last_sent_tick;
+        let window = session
+            .view_radius
+            .zip(session.entity_id)
+            .and_then(|(radius, eid)| ViewWindow::around(&self.game, eid, self.view.grant(radius)));
+        if let Some(window) = window {
+            out.extend(self.windowed_update(endpoint_id, last_sent_tick, &window));
+            return out;
+        }
+        if let Some(session) = self.sessions.get_mut(&endpoint_id)
+            && session.in_view.take().is_some()
+        {
+            // Back to the whole world, most of which the client lacks.
+            last_sent_tick = None;
+        }
+        let delta = last_sent_tick.and_then(|t| self.delta_since(t));
+        if let Some(session) = self.sessions.get_mut(&endpoint_id) {
+            session.last_sent_tick = Some(self.game.tick);
+        }
+        match delta {
+            Some(delta) => out.push(ServerMessage::Delta(delta)),
+            None => out.extend(self.full_snapshot()),
+        }
+        out
+    }
+
+    /// The world update of a client with a limited view: what changed in
+    /// `window` since `last_sent_tick`, or a snapshot of it if the client
+    /// holds no window yet or the delta is too old, each followed by the
+    /// changes to its silhouettes, if any.
+    fn windowed_update(
+        &mut self,
+        endpoint_id: EndpointId,
+        last_sent_tick: Option<u64>,
+        window: &ViewWindow,
+    ) -> Vec<ServerMessage> {
+        let delta = last_sent_tick.and_then(|t| self.delta_since(t));
+        let tick = self.game.tick;
+        let Some(session) = self.sessions.get_mut(&endpoint_id) else {
+            return Vec::new();
+        };
+        session.last_sent_tick = Some(tick);
+        let (update, silhouettes) = if let (Some(sent), Some(delta)) = (&mut session.in_view, delta)
+        {
+            let (delta, silhouettes) = window.narrow(&delta, self.game.entities(), sent);
+            (ServerMessage::Delta(delta), silhouettes)
+        } else {
+            let (entities, silhouettes, state) = window.snapshot(self.game.entities());
+            session.in_view = Some(state);
+            (ServerMessage::Snapshot { tick, entities }, silhouettes)
+        };
+        let mut out = vec![update];
+        if !silhouettes.is_empty() {
+            out.push(ServerMessage::Silhouettes(silhouettes));
+        }
+        out
+    }
+}
+
+/// Apply `actions` to `game` in order and return what happened. An action
+/// that panics is logged and the world put back as it was before it, so
+/// one bad action cannot take the server down or leave it half done.
+fn apply_actions(game: &mut GameState, actions: &[(EntityID, GameAction)]) -> Vec<GameEvent> {
+    let mut out = Vec::new();
+    for (eid, action) in actions {
+        let outcome = isolate_world(game, |state| match action {
+            GameAction::Move(_)
+            | GameAction::Talk(_)
+            | GameAction::Choose(_)
+            | GameAction::Buy { .. }
+            | GameAction::Sell { .. }
+            | GameAction::MoveGroup { .. }
+            | GameAction::Attack(_)
+            | GameAction::AttackToward(_)
+            | GameAction::Throw { .. }
+            | GameAction::Repair(_)
+            | GameAction::Queue(_)
+            | GameAction::Cancel
+            | GameAction::Ping { .. }
+            | GameAction::EndTurn
+            | GameAction::Wait(_)
+            | GameAction::SetColor(_)
+            | GameAction::SplitStack { .. }
+            | GameAction::MergeStacks(_)
+            | GameAction::Stash { .. }
+            | GameAction::Retrieve { .. } => game::apply(state, *eid, action),
+            GameAction::SpawnPlayer(_) | GameAction::SpawnAs(_) => {
+                // Handled at connection time in the protocol handler.
+                Vec::new()
+            }
+            GameAction::SaveWorld => {
+                game::save_to_file(state).ok();
+                Vec::new()
+            }
+        });
+        match outcome {
+            Ok(events) => out.extend(events),
+            Err(e) => log::warn!("Rejected {action:?} from {eid:?}: {e}"),
+        }
+    }
+    out
+}
+
+/// Run `f` on `game` like [`isolate`], putting all of `game` back as it
+/// was if `f` panics. An action may touch any entity, the terrain and the
+/// world's counters, so nothing short of the whole world is safe to keep.
+fn isolate_world<T>(
+    game: &mut GameState,
+    f: impl FnOnce(&mut GameState) -> T,
+) -> std::result::Result<T, String> {
+    let before = game.clone();
+    isolate(|| f(game)).inspect_err(|_| *game = before)
+}
+
+/// Run `f`, turning a panic into an error message.
+///
+/// Used around code driven by client input, so a bug it triggers only
+/// affects that input. Callers must leave the state they hand to `f`
+/// consistent if it fails halfway.
+fn isolate<T>(f: impl FnOnce() -> T) -> std::result::Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|payload| panic_message(&*payload))
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| (*s).to_owned())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "panicked".to_owned())
+}
+
+// ---------------------------------------------------------------------------
+// Iroh helpers
+// ---------------------------------------------------------------------------
+
+/// Send one message on a new unidirectional stream.
+async fn send_one_way(conn: &Connection, msg: &Message) -> Result<()> {
+    send_encoded(conn, &bitcode::encode(msg)).await
+}
+
+/// Send one already encoded message on a new unidirectional stream.
+async fn send_encoded(conn: &Connection, encoded: &[u8]) -> Result<()> {
+    let mut send = conn.open_uni().await.anyerr()?;
+    send.write_all(encoded).await.anyerr()?;
+    send.finish().anyerr()?;
+    Ok(())
+}
+
+/// Receive the raw bytes of one message from a unidirectional stream,
+/// failing if the peer sends more than `limit` bytes.
+///
+/// The bytes are untrusted; decode them with the [`decode`] module.
+async fn recv_one_way(mut recv: RecvStream, limit: usize) -> Result<Vec<u8>> {
+    recv.read_to_end(limit).await.anyerr()
+}
+
+// ---------------------------------------------------------------------------
+// Server
+// ---------------------------------------------------------------------------
+
+/// Server state for `game`, with its chat log and who may join loaded from
+/// disk, and idling as `when_empty` says.
+pub(crate) fn open_server_state(game: GameState, when_empty: WhenEmpty) -> ServerState {
+    let mut server = ServerState::new(game);
+    server.idle = Idle::new(when_empty);
+    match ChatLog::open(server.game.world_id) {
+        Ok(log) => server.chat = log,
+        Err(e) => log::warn!("Failed to open the chat log, keeping it in memory: {e}"),
+    }
+    match AccessRules::load(server.game.world_id) {
+        Ok(rules) => server.access.rules = rules,
+        Err(e) => log::warn!("Failed to load who may join, leaving the server open: {e}"),
+    }
+    server
+}
+
+/// Accept players, asset downloads and status probes on `endpoint`, and
+/// step `state` every `tick_interval`.
+pub(crate) fn serve(
+    endpoint: Endpoint,
+    state: Arc<Mutex<ServerState>>,
+    tick_interval: Duration,
+) -> Router {
+    let (tick_tx, ticks) = watch::channel(0);
+    spawn_tick_loop(state.clone(), tick_tx, tick_interval);
+    let status = StatusServer::new(state.clone());
+    let handler = Echo { state, ticks };
+
+    Router::builder(endpoint)
+        .accept(ALPN, handler)
+        .accept(assets::ASSETS_ALPN, AssetServer::new(AssetStore::builtin()))
+        .accept(status::STATUS_ALPN, status)
+        .spawn()
+}
+
+/// Step the shared server state every `tick_interval` and wake up the
+/// per-connection send loops. Stops once every receiver has been dropped.
+fn spawn_tick_loop(
+    state: Arc<Mutex<ServerState>>,
+    tick_tx: watch::Sender<u64>,
+    tick_interval: Duration,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(tick_interval);
+
+        while !tick_tx.is_closed() {
+            interval.tick().await;
+            let tick = {
+                let mut guard = state.lock().await;
+                guard.step();
+                guard.game.tick
+            };
+            tick_tx.send_replace(tick);
+        }
+    });
+}
+
+#[derive(Debug, Clone)]
+struct Echo {
+    state: Arc<Mutex<ServerState>>,
+    ticks: watch::Receiver<u64>,
+}
+
+impl ProtocolHandler for Echo {
+    async fn accept(&self, connection: Connection) -> std::result::Result<(), AcceptError> {
+        let endpoint_id = connection.remote_id();
+        self.state.lock().await.connect(endpoint_id);
+
+        let state = self.state.clone();
+        let conn_clone = connection.clone();
+        let mut ticks = self.ticks.clone();
+        // Send updates after every server tick
+        tokio::spawn(async move {
+            while ticks.changed().await.is_ok() {
+                let encoded: Vec<Vec<u8>> = {
+                    let mut guard = state.lock().await;
+                    let start = Instant::now();
+                    let encoded = guard
+                        .drain_updates(endpoint_id)
+                        .into_iter()
+                        .map(|r| bitcode::encode(&Message::Server(r)))
+                        .collect();
+                    guard.profiler.record(System::Encode, start.elapsed());
+                    encoded
+                };
+
+                let start = Instant::now();
+                for bytes in &encoded {
+                    if let Err(e) = send_encoded(&conn_clone, bytes).await {
+                        log::warn!("Error sending periodic update to client: {e}");
+                        return;
+                    }
+                }
+                state
+                    .lock()
+                    .await
+                    .profiler
+                    .record(System::Send, start.elapsed());
+            }
+        });
+
+        // Accept incoming streams
+        loop {
+            match connection.accept_uni().await {
+                Ok(recv) => {
+                    let state = self.state.clone();
+                    let conn = connection.clone();
+
+                    tokio::spawn(async move {
+                        let msg = recv_one_way(recv, decode::MAX_CLIENT_MESSAGE_SIZE)
+                            .await
+                            .and_then(|bytes| decode::decode_client_message(&bytes).anyerr());
+                        match msg {
+                            Ok(msg) => {
+                                state.lock().await.handle_client_message(endpoint_id, msg);
+                            }
+                            Err(e) => {
+                                // Never trust a peer that sends garbage: drop it
+                                // rather than guess what it meant.
+                                log::warn!("Disconnecting client after bad message: {e}");
+                                conn.close(VarInt::from_u32(1), b"malformed message");
+                            }
+                        }
+                    });
+                }
+                Err(_) => {
+                    break;
+                }
+            }
+        }
+
+        self.state.lock().await.disconnect(endpoint_id);
+        Ok(())
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Client
+// ---------------------------------------------------------------------------
+
+/// What the client needs to remember to resume its session after a drop.
+#[derive(Debug, Default, Clone)]
+struct ResumeInfo {
+    token: Option<SessionToken>,
+    /// Last tick of a complete world the client has applied.
+    last_tick: Option<u64>,
+    /// Tick and still-missing part indices of a chunked snapshot in flight.
+    pending_snapshot: Option<(u64, BTreeSet<u32>)>,
+}
+
+impl ResumeInfo {
+    fn observe(&mut self, msg: &ServerMessage) {
+        match msg {
+            ServerMessage::Session(token) => self.token = Some(*token),
+            ServerMessage::Snapshot { tick, .. } => {
+                self.last_tick = Some(*tick);
+                self.pending_snapshot = None;
+            }
+            ServerMessage::SnapshotManifest { tick, parts, .. } => {
+                self.last_tick = None;
+                self.pending_snapshot = Some((*tick, (0..*parts).collect()));
+            }
+            ServerMessage::SnapshotPart { tick, index, .. } => {
+                if let Some((pending_tick, missing)) = &mut self.pending_snapshot
+                    && pending_tick == tick
+                {
+                    missing.remove(index);
+                    if missing.is_empty() {
+                        self.last_tick = Some(*tick);
+                        self.pending_snapshot = None;
+                    }
+                }
+            }
+            ServerMessage::Delta(delta) => {
+                if self.pending_snapshot.is_none() {
+                    self.last_tick = Some(delta.tick);
+                }
+            }
+            ServerMessage::ResumeRejected => *self = Self::default(),
+            ServerMessage::PlayerID(_)
+            | ServerMessage::Resumed(_)
+            | ServerMessage::WorldInfo { .. }
+            | ServerMessage::Terrain { .. }
+            | ServerMessage::ConsoleOutput(_)
+            | ServerMessage::Dialogue(_)
+            | ServerMessage::TradeRejected(_)
+            | ServerMessage::RepairRejected(_)
+            | ServerMessage::StackRejected(_)
+            | ServerMessage::ItemBroke(_)
+            | ServerMessage::SpawnRejected(_)
+            | ServerMessage::Roster(_)
+            | ServerMessage::CharacterExport(_)
+            | ServerMessage::ExportRejected(_)
+            | ServerMessage::SlotSaved(_)
+            | ServerMessage::SlotRejected(_)
+            | ServerMessage::Imported { .. }
+            | ServerMessage::Recordings(_)
+            | ServerMessage::RecordingPart { .. }
+            | ServerMessage::RecordingUnavailable(_)
+            | ServerMessage::WorldEvent(_)
+            | ServerMessage::Pushed { .. }
+            | ServerMessage::Explosion { .. }
+            | ServerMessage::ActionProgress { .. }
+            | ServerMessage::PathBlocked { .. }
+            | ServerMessage::WaitEnded { .. }
+            | ServerMessage::Chat(_)
+            | ServerMessage::ChatHistory(_)
+            | ServerMessage::PacksRejected(_)
+            | ServerMessage::AccessDenied(_)
+            | ServerMessage::Queued(_)
+            | ServerMessage::Admitted
+            | ServerMessage::ViewDistance(_)
+            | ServerMessage::Silhouettes(_)
+            | ServerMessage::Whisper(_)
+            | ServerMessage::WhisperFailed(_)
+            | ServerMessage::Debug(_)
+            | ServerMessage::Ping(_) => {}
+        }
+    }
+}
+
+/// Connect to the server and shuttle messages until `rx` is closed.
+///
+/// If the connection drops, the client reconnects with a fresh endpoint and
+/// asks the server to resume its session, so the app only sees a short gap
+/// in updates rather than having to rejoin. Endpoints are bound with `key`
+/// if given, so the server knows the client by the same endpoint ID
+/// throughout, or with a new key each time.
+pub async fn run_client_internal(
+    addr: impl Into<EndpointAddr>,
+    key: Option<SecretKey>,
+    tx: mpsc::UnboundedSender<Message>,
+    mut rx: mpsc::UnboundedReceiver<ClientMessage>,
+) -> Result<()> {
+    let addr = addr.into();
+    let resume = Arc::new(Mutex::new(ResumeInfo::default()));
+    let mut attempts = 0;
+
+    loop {
+        let resumable = resume.lock().await.token.is_some();
+        let endpoint = match &key {
+            Some(key) => Endpoint::builder().secret_key(key.clone()).bind().await?,
+            None => Endpoint::bind().await?,
+        };
+        let conn = match endpoint.connect(addr.clone(), ALPN).await {
+            Ok(conn) => conn,
+            Err(e) if resumable && attempts < RECONNECT_ATTEMPTS => {
+                log::warn!("Reconnect failed: {e}");
+                attempts += 1;
+                tokio::time::sleep(RECONNECT_DELAY).await;
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        attempts = 0;
+
+        let (token, last_tick) = {
+            let info = resume.lock().await;
+            (info.token, info.last_tick)
+        };
+        if let Some(token) = token {
+            let msg = Message::Client(ClientMessage::Resume { token, last_tick });
+            if let Err(e) = send_one_way(&conn, &msg).await {
+                log::warn!("Error sending resume request: {e}");
+            }
+        }
+
+        // Receive loop
+        let conn_clone = conn.clone();
+        let tx = tx.clone();
+        let resume_clone = resume.clone();
+        let (lost_tx, mut lost_rx) = oneshot::channel::<()>();
+        tokio::spawn(async move {
+            loop {
+                match conn_clone.accept_uni().await {
+                    Ok(recv) => match recv_one_way(recv, MAX_MESSAGE_SIZE)
+                        .await
+                        .and_then(|bytes| decode::decode_server_message(&bytes).anyerr())
+                    {
+                        Ok(msg) => {
+                            resume_clone.lock().await.observe(&msg);
+                            tx.send(Message::Server(msg)).ok();
+                        }
+                        Err(e) => {
+                            log::warn!("Error receiving server message: {e}");
+                        }
+                    },
+                    Err(_) => {
+                        break;
+                    }
+                }
+            }
+            lost_tx.send(()).ok();
+        });
+
+        // Send loop
+        loop {
+            tokio::select! {
+                msg = rx.recv() => match msg {
+                    Some(msg) => {
+                        let msg = Message::Client(msg);
+
+                        if let Err(e) = send_one_way(&conn, &msg).await {
+                            log::warn!("Error sending message: {e}");
+                            break;
+                        }
+                    }
+                    None => {
+                        return Ok(());
+                    }
+                },
+                _ = &mut lost_rx => {
+                    break;
+                }
+            }
+        }
+
+        log::info!("Connection to server lost, reconnecting");
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::PingKind;
+
+    #[cfg(feature = "backup-peer")]
+    #[test]
+    fn only_plain_relative_paths_are_backed_up() {
+        use backup::backup_name;
+        use std::path::Path;
+
+        assert_eq!(
+            backup_name(Path::new("worlds/woods.world")).as_deref(),
+            Some("worlds/woods.world")
+        );
+        assert_eq!(backup_name(Path::new("../woods.world")), None);
+        assert_eq!(backup_name(Path::new("/tmp/woods.world")), None);
+        assert_eq!(backup_name(Path::new("")), None);
+    }
+
+    #[test]
+    fn mock_transport_pair_round_trips() {
+        let (a, mut b) = mock_transport_pair();
+
+        a.send(Message::Client(ClientMessage::Action(
+            GameAction::SaveWorld,
+        )))
+        .expect("send should succeed");
+        let received = b.try_recv();
+        assert!(received.is_some());
+    }
+
+    #[test]
+    fn protocol_message_encodes_and_decodes() {
+        let original = Message::Server(ServerMessage::PlayerID(EntityID(42)));
+        let bytes = bitcode::encode(&original);
+        let decoded: Message = bitcode::decode(&bytes).expect("decode should succeed");
+
+        match decoded {
+            Message::Server(ServerMessage::PlayerID(id)) => assert_eq!(id, EntityID(42)),
+            other => panic!("unexpected variant: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn decode_boundary_rejects_hostile_input() {
+        let encode = |msg: Message| bitcode::encode(&msg);
+
+        assert_eq!(
+            decode::decode_client_message(&[0xff; 16]).err(),
+            Some(decode::DecodeError::Malformed)
+        );
+        assert!(matches!(
+            decode::decode_client_message(&vec![0; decode::MAX_CLIENT_MESSAGE_SIZE + 1]),
+            Err(decode::DecodeError::TooLarge(_))
+        ));
+        assert_eq!(
+            decode::decode_client_message(&encode(Message::Server(ServerMessage::ResumeRejected)))
+                .err(),
+            Some(decode::DecodeError::WrongDirection)
+        );
+
+        let long_name = "x".repeat(decode::MAX_NAME_LEN + 1);
+        let spawn = Message::Client(ClientMessage::Action(GameAction::SpawnPlayer(long_name)));
+        assert_eq!(
+            decode::decode_client_message(&encode(spawn)).err(),
+            Some(decode::DecodeError::FieldTooLarge("name"))
+        );
+        let buy = Message::Client(ClientMessage::Action(GameAction::Buy {
+            vendor: EntityID(1),
+            item: "x".repeat(decode::MAX_ITEM_ID_LEN + 1),
+            count: 1,
+        }));
+        assert_eq!(
+            decode::decode_client_message(&encode(buy)).err(),
+            Some(decode::DecodeError::FieldTooLarge("item"))
+        );
+        let group = Message::Client(ClientMessage::Action(GameAction::MoveGroup {
+            members: vec![EntityID(1); game::formation::MAX_GROUP_SIZE + 1],
+            target: game::Point { x: 0, y: 0 },
+        }));
+        assert_eq!(
+            decode::decode_client_message(&encode(group)).err(),
+            Some(decode::DecodeError::FieldTooLarge("members"))
+        );
+
+        let ok = Message::Client(sync(None));
+        assert!(decode::decode_client_message(&encode(ok)).is_ok());
+    }
+
+    #[test]
+    fn decode_boundary_rejects_long_item_ids_in_stack_and_container_actions() {
+        let long = "x".repeat(decode::MAX_ITEM_ID_LEN + 1);
+        let decode = |action: GameAction| {
+            decode::decode_client_message(&bitcode::encode(&Message::Client(
+                ClientMessage::Action(action),
+            )))
+        };
+        for action in [
+            GameAction::SplitStack {
+                item: long.clone(),
+                count: 1,
+            },
+            GameAction::MergeStacks(long.clone()),
+            GameAction::Stash {
+                container: EntityID(1),
+                item: long.clone(),
+                count: 1,
+            },
+            GameAction::Retrieve {
+                container: EntityID(1),
+                item: long.clone(),
+                count: 1,
+            },
+        ] {
+            assert_eq!(
+                decode(action.clone()).err(),
+                Some(decode::DecodeError::FieldTooLarge("item")),
+                "{action:?}"
+            );
+        }
+        let fits = "x".repeat(decode::MAX_ITEM_ID_LEN);
+        assert!(decode(GameAction::MergeStacks(fits)).is_ok());
+    }
+
+    #[test]
+    fn isolate_turns_panics_into_errors() {
+        assert_eq!(isolate(|| 1), Ok(1));
+        assert_eq!(
+            isolate(|| -> u32 { panic!("bad action") }),
+            Err("bad action".to_owned())
+        );
+    }
+
+    #[test]
+    fn a_panicking_action_leaves_nothing_it_touched_behind() {
+        let mut game = GameState::create_test_world("test".into());
+        let alice = game::spawn_player(&mut game, "Alice".into());
+        let bob = game::spawn_player(&mut game, "Bob".into());
+        let before = game.clone();
+
+        let outcome = isolate_world(&mut game, |state| -> u32 {
+            for eid in [alice, bob] {
+                state.place_entity(eid, game::Point { x: 9, y: 9 });
+            }
+            state
+                .terrain
+                .set(game::Point { x: 3, y: 3 }, game::Terrain::Rock);
+            game::spawn_player(state, "Carol".into());
+            state.tick += 1;
+            panic!("bad action");
+        });
+        assert_eq!(outcome, Err("bad action".to_owned()));
+        assert_eq!(game, before, "the whole world is put back");
+        for eid in [alice, bob] {
+            assert_eq!(
+                game.index().position(eid),
+                before.entities().get(&eid).map(|e| e.position),
+                "the index is put back with it"
+            );
+        }
+        assert_eq!(
+            isolate_world(&mut game, |state| state.tick),
+            Ok(before.tick)
+        );
+    }
+
+    fn endpoint(n: u8) -> EndpointId {
+        iroh::SecretKey::from_bytes(&[n; 32]).public()
+    }
+
+    /// A client's reply to [`ServerMessage::WorldInfo`], with every pack.
+    fn sync(since_tick: Option<u64>) -> ClientMessage {
+        ClientMessage::Sync {
+            since_tick,
+            packs: pack::active(),
+            secret: None,
+        }
+    }
+
+    fn session_token(msgs: &[ServerMessage]) -> SessionToken {
+        msgs.iter()
+            .find_map(|m| match m {
+                ServerMessage::Session(token) => Some(*token),
+                _ => None,
+            })
+            .expect("session token should be sent on connect")
+    }
+
+    /// Let every dropped session outlive its resume window.
+    fn run_out_sessions(server: &mut ServerState) {
+        let later = Instant::now() + session::SESSION_RESUME + Duration::from_secs(1);
+        server.sessions.expire(later);
+    }
+
+    #[test]
+    fn world_delta_round_trips() {
+        let old = GameState::create_test_world("test".into())
+            .entities()
+            .clone();
+        let mut new = old.clone();
+        let moved = *new.keys().next().expect("test world has trees");
+        new.get_mut(&moved).expect("exists").position.x += 1;
+        let removed = *new.keys().find(|eid| **eid != moved).expect("more trees");
+        new.remove(&removed);
+
+        let delta = WorldDelta::between(1, &old, &new);
+        assert_eq!(delta.changed.len(), 1);
+        assert_eq!(delta.removed, vec![removed]);
+
+        let mut applied = old;
+        delta.apply_to(&mut applied);
+        assert_eq!(applied, new);
+    }
+
+    #[test]
+    fn delta_log_merges_and_forgets_old_ticks() {
+        let mut log = DeltaLog::new(3);
+        for tick in 1..=4 {
+            log.push(WorldDelta {
+                tick,
+                removed: vec![EntityID(tick as u32)],
+                ..Default::default()
+            });
+        }
+
+        assert!(log.since(0).is_none(), "tick 1 was dropped from the log");
+        let merged = log.since(1).expect("ticks 2..=4 are still logged");
+        assert_eq!(merged.tick, 4);
+        assert_eq!(merged.removed, vec![EntityID(2), EntityID(3), EntityID(4)]);
+        assert!(log.since(4).expect("up to date").is_empty());
+    }
+
+    #[test]
+    fn resumed_session_keeps_entity_and_gets_delta() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        let (a, b) = (endpoint(1), endpoint(2));
+
+        server.connect(a);
+        server.handle_client_message(a, sync(None));
+        server.handle_client_message(
+            a,
+            ClientMessage::Action(GameAction::SpawnPlayer("A".into())),
+        );
+        let first = server.drain_updates(a);
+        let token = session_token(&first);
+        let pid = server.endpoints.get(&a).copied().expect("a");
+        assert!(matches!(first.last(), Some(ServerMessage::Snapshot { .. })));
+
+        server.step();
+        let last_tick = Some(server.game.tick);
+        server.disconnect(a);
+        server.step();
+
+        server.connect(b);
+        server.handle_client_message(b, ClientMessage::Resume { token, last_tick });
+        let updates = server.drain_updates(b);
+
+        assert_eq!(server.endpoints.get(&b), Some(&pid));
+        assert!(
+            updates
+                .iter()
+                .any(|m| matches!(m, ServerMessage::Resumed(Some(eid)) if *eid == pid))
+        );
+        assert!(matches!(updates.last(), Some(ServerMessage::Delta(_))));
+    }
+
+    #[test]
+    fn live_session_cannot_be_taken_over() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        let (a, b) = (endpoint(1), endpoint(2));
+
+        server.connect(a);
+        server.handle_client_message(
+            a,
+            ClientMessage::Action(GameAction::SpawnPlayer("A".into())),
+        );
+        let token = session_token(&server.drain_updates(a));
+        let pid = server.endpoints.get(&a).copied().expect("a");
+
+        server.connect(b);
+        server.handle_client_message(
+            b,
+            ClientMessage::Resume {
+                token,
+                last_tick: None,
+            },
+        );
+        assert!(
+            server
+                .drain_updates(b)
+                .iter()
+                .any(|m| matches!(m, ServerMessage::ResumeRejected))
+        );
+        assert_eq!(server.endpoints.get(&a), Some(&pid));
+        assert_eq!(server.endpoints.get(&b), None);
+        assert_eq!(server.sessions.token_of(&a), Some(token));
+    }
+
+    #[test]
+    fn client_core_keeps_its_world_in_step() {
+        use crate::client::{Applied, Control, Start, WorldSync};
+        use crate::game::Direction;
+
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        let a = endpoint(1);
+        let mut world = WorldSync::default();
+        let mut game = GameState::create_test_world("placeholder".into());
+
+        server.connect(a);
+        for msg in server.drain_updates(a) {
+            if let ServerMessage::WorldInfo { id, name } = msg {
+                let start = world.join(&mut game, id, name, false);
+                assert_eq!(start, Start::Fresh, "nothing is cached of a new world");
+                server.handle_client_message(a, start.sync(None));
+            }
+        }
+        server.handle_client_message(
+            a,
+            ClientMessage::Action(GameAction::SpawnPlayer("A".into())),
+        );
+        let applied: Vec<Applied> = server
+            .drain_updates(a)
+            .into_iter()
+            .filter_map(|msg| world.receive(msg, &mut game).ok())
+            .collect();
+        assert!(applied.contains(&Applied::Replaced));
+        assert!(world.is_loaded());
+
+        let pid = server.endpoints.get(&a).copied().expect("a");
+        let step = Control::Move(Direction::Right)
+            .action(game.entities(), pid)
+            .expect("moving needs no target");
+        server.handle_client_message(a, ClientMessage::Action(step));
+        server.step();
+        for msg in server.drain_updates(a) {
+            world.receive(msg, &mut game).ok();
+        }
+        assert_eq!(game.entities(), server.game.entities());
+        assert_eq!(game.tick, server.game.tick);
+
+        // Rejoining after a reconnect only needs what changed since.
+        let (id, tick) = (server.game.world_id, server.game.tick);
+        assert_eq!(
+            world.join(&mut game, id, "test".into(), false),
+            Start::Held(tick)
+        );
+        assert!(world.cache(&game, false).is_some());
+        assert!(
+            world.cache(&game, true).is_none(),
+            "part of a world is not kept"
+        );
+    }
+
+    #[test]
+    fn expired_session_is_rejected() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        let (a, b) = (endpoint(1), endpoint(2));
+
+        server.connect(a);
+        let token = session_token(&server.drain_updates(a));
+        server.disconnect(a);
+        run_out_sessions(&mut server);
+
+        server.connect(b);
+        server.handle_client_message(
+            b,
+            ClientMessage::Resume {
+                token,
+                last_tick: Some(0),
+            },
+        );
+        server.handle_client_message(b, sync(None));
+        let updates = server.drain_updates(b);
+        assert!(
+            updates
+                .iter()
+                .any(|m| matches!(m, ServerMessage::ResumeRejected))
+        );
+        assert!(matches!(
+            updates.last(),
+            Some(ServerMessage::Snapshot { .. })
+        ));
+    }
+
+    #[test]
+    fn change_index_reports_changes_since_any_tick() {
+        let mut entities = EntityMap::default();
+        let mut index = ChangeIndex::new(0);
+        for id in 1..=3 {
+            let tick = u64::from(id);
+            let old = entities.clone();
+            let origin = game::Point { x: 0, y: 0 };
+            let tree = game::Entity::new(game::EntityType::Tree, origin, None);
+            entities.insert(EntityID(id), tree);
+            entities.remove(&EntityID(id - 1));
+            index.record(&WorldDelta::between(tick, &old, &entities));
+        }
+
+        let delta = index.since(1, &entities, 3).expect("within horizon");
+        assert_eq!(delta.tick, 3);
+        assert_eq!(delta.changed.len(), 1);
+        assert_eq!(
+            delta.changed.first().map(|(eid, _)| *eid),
+            Some(EntityID(3))
+        );
+        assert_eq!(delta.removed, vec![EntityID(1), EntityID(2)]);
+        assert!(index.since(4, &entities, 3).is_none());
+    }
+
+    #[test]
+    fn client_with_cached_world_is_sent_only_changes() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        let (a, b) = (endpoint(1), endpoint(2));
+        let cached_tick = server.game.tick;
+        for _ in 0..session::SESSION_RESUME_TICKS + 10 {
+            server.step();
+        }
+        game::spawn_player(&mut server.game, "Late".into());
+        server.step();
+
+        server.connect(a);
+        server.handle_client_message(a, sync(Some(cached_tick)));
+        let updates = server.drain_updates(a);
+        assert!(updates.iter().any(
+            |m| matches!(m, ServerMessage::WorldInfo { id, .. } if *id == server.game.world_id)
+        ));
+        let Some(ServerMessage::Delta(delta)) = updates.last() else {
+            panic!("expected a delta, got {updates:?}");
+        };
+        assert_eq!(delta.changed.len(), 1);
+
+        // A tick from the future belongs to another run of the world.
+        server.connect(b);
+        server.handle_client_message(b, sync(Some(server.game.tick + 1)));
+        assert!(matches!(
+            server.drain_updates(b).last(),
+            Some(ServerMessage::Snapshot { .. })
+        ));
+    }
+
+    #[test]
+    fn client_missing_content_packs_is_told_which_and_sent_no_world() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        let extra = PackManifest {
+            id: "more_trees".into(),
+            hash: 7,
+        };
+        server.packs.push(extra.clone());
+        let a = endpoint(1);
+
+        server.connect(a);
+        server.handle_client_message(a, sync(None));
+        let updates = server.drain_updates(a);
+        assert!(updates.iter().any(
+            |m| matches!(m, ServerMessage::PacksRejected(missing) if *missing == [extra.clone()])
+        ));
+        assert!(
+            !updates
+                .iter()
+                .any(|m| matches!(m, ServerMessage::Snapshot { .. }))
+        );
+
+        // Another version of the pack is no better.
+        let mut packs = pack::active();
+        packs.push(PackManifest {
+            hash: 8,
+            ..extra.clone()
+        });
+        server.handle_client_message(
+            a,
+            ClientMessage::Sync {
+                since_tick: None,
+                packs: packs.clone(),
+                secret: None,
+            },
+        );
+        assert!(matches!(
+            server.drain_updates(a).last(),
+            Some(ServerMessage::PacksRejected(_))
+        ));
+
+        packs.push(extra);
+        server.handle_client_message(
+            a,
+            ClientMessage::Sync {
+                since_tick: None,
+                packs,
+                secret: None,
+            },
+        );
+        assert!(matches!(
+            server.drain_updates(a).last(),
+            Some(ServerMessage::Snapshot { .. })
+        ));
+    }
+
+    #[test]
+    fn entity_traces_show_the_action_behind_a_move() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        server.trace = Some(EntityTrace::new(4));
+        let a = endpoint(1);
+        server.connect(a);
+        server.handle_client_message(
+            a,
+            ClientMessage::Action(GameAction::SpawnPlayer("A".into())),
+        );
+        let pid = server.endpoints.get(&a).copied().expect("a");
+        server.step();
+        server.handle_client_message(
+            a,
+            ClientMessage::Action(GameAction::Move(game::Direction::Right)),
+        );
+        server.step();
+
+        let tick = server.game.tick;
+        let entries: Vec<&trace::TraceEntry> = server.trace.as_ref().expect("on").of(pid).collect();
+        assert!(
+            entries
+                .first()
+                .is_some_and(|e| e.what.starts_with("appeared"))
+        );
+        assert!(
+            entries
+                .iter()
+                .any(|e| e.tick == tick && e.what == "did Move(Right)")
+        );
+        assert!(
+            entries
+                .iter()
+                .any(|e| e.tick == tick && e.what.starts_with("moved"))
+        );
+
+        // Only the last few are kept.
+        for _ in 0..3 {
+            server.handle_client_message(
+                a,
+                ClientMessage::Action(GameAction::Move(game::Direction::Left)),
+            );
+            server.step();
+        }
+        let trace = server.trace.as_ref().expect("on");
+        assert_eq!(trace.of(pid).count(), 4);
+        assert!(trace.of(pid).all(|e| e.tick > tick));
+    }
+
+    #[test]
+    fn limited_views_are_sent_only_the_world_nearby() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        let a = endpoint(1);
+        server.connect(a);
+        server.handle_client_message(a, ClientMessage::ViewDistance(Some(1)));
+        server.handle_client_message(a, sync(None));
+        server.handle_client_message(
+            a,
+            ClientMessage::Action(GameAction::SpawnPlayer("A".into())),
+        );
+        let pid = server.endpoints.get(&a).copied().expect("a");
+        let tree = *server
+            .game
+            .entities()
+            .keys()
+            .find(|eid| **eid != pid)
+            .expect("trees");
+        let at = server.game.entities().get(&pid).expect("pid").position;
+        let place = |server: &mut ServerState, dx: i32| {
+            server.game.place_entity(
+                tree,
+                game::Point {
+                    x: at.x + dx,
+                    y: at.y,
+                },
+            );
+            server.step();
+            server.drain_updates(a)
+        };
+
+        // The tree far away is left out of the first snapshot.
+        let updates = place(&mut server, 50);
+        assert!(
+            updates
+                .iter()
+                .any(|m| matches!(m, ServerMessage::ViewDistance(Some(view::MIN_VIEW_RADIUS))))
+        );
+        assert!(updates.iter().any(|m| matches!(
+            m,
+            ServerMessage::Snapshot { entities, .. }
+                if entities.contains_key(&pid) && !entities.contains_key(&tree)
+        )));
+
+        // It is sent whole when it comes into view, and removed as it leaves.
+        let updates = place(&mut server, 1);
+        assert!(updates.iter().any(|m| matches!(
+            m,
+            ServerMessage::Delta(delta) if delta.changed.iter().any(|(eid, _)| *eid == tree)
+        )));
+        let updates = place(&mut server, 50);
+        assert!(updates.iter().any(|m| matches!(
+            m,
+            ServerMessage::Delta(delta) if delta.removed == [tree]
+        )));
+
+        // Taking the request back brings the whole world again.
+        server.handle_client_message(a, ClientMessage::ViewDistance(None));
+        let updates = place(&mut server, 50);
+        assert!(updates.iter().any(|m| matches!(
+            m,
+            ServerMessage::Snapshot { entities, .. } if entities.contains_key(&tree)
+        )));
+    }
+
+    #[test]
+    fn entities_out_of_sight_are_sent_as_silhouettes() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        let (a, b) = (endpoint(1), endpoint(2));
+        for (endpoint_id, name) in [(a, "A"), (b, "B")] {
+            server.connect(endpoint_id);
+            server.handle_client_message(endpoint_id, sync(None));
+            server.handle_client_message(
+                endpoint_id,
+                ClientMessage::Action(GameAction::SpawnPlayer(name.into())),
+            );
+        }
+        server.handle_client_message(a, ClientMessage::ViewDistance(Some(4)));
+        let other = server.endpoints.get(&b).copied().expect("b");
+        let at = server
+            .game
+            .entities()
+            .get(&server.endpoints.get(&a).copied().expect("a"))
+            .expect("entity")
+            .position;
+        let place = |server: &mut ServerState, dx: i32| {
+            server.game.place_entity(
+                other,
+                game::Point {
+                    x: at.x + dx,
+                    y: at.y,
+                },
+            );
+            server.step();
+            server.drain_updates(a)
+        };
+
+        // Past the radius but within the margin, B is only an outline.
+        let updates = place(&mut server, 5);
+        assert!(updates.iter().any(|m| matches!(
+            m,
+            ServerMessage::Snapshot { entities, .. } if !entities.contains_key(&other)
+        )));
+        assert!(updates.iter().any(|m| matches!(
+            m,
+            ServerMessage::Silhouettes(delta)
+                if delta.changed.iter().any(|(eid, s)| *eid == other
+                    && s.entity_type == game::EntityType::Player)
+        )));
+
+        // In sight, it is sent whole and its outline dropped.
+        let updates = place(&mut server, 1);
+        assert!(updates.iter().any(|m| matches!(
+            m,
+            ServerMessage::Delta(delta) if delta.changed.iter().any(|(eid, _)| *eid == other)
+        )));
+        assert!(updates.iter().any(|m| matches!(
+            m,
+            ServerMessage::Silhouettes(delta) if delta.removed == [other]
+        )));
+    }
+
+    #[test]
+    fn empty_servers_pause_and_catch_up_when_someone_joins() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        server.idle = Idle::new(WhenEmpty::Pause);
+        let a = endpoint(1);
+        let start = server.game.tick;
+
+        for _ in 0..10 {
+            server.step();
+        }
+        assert_eq!(server.game.tick, start);
+        assert_eq!(server.idle.missed(), 10);
+
+        // Paused worlds carry on from where they stopped.
+        server.connect(a);
+        server.step();
+        assert_eq!(server.game.tick, start + 1);
+        assert_eq!(server.idle.missed(), 0);
+
+        server.disconnect(a);
+        server.idle.when_empty = WhenEmpty::CatchUp;
+        for _ in 0..10 {
+            server.step();
+        }
+        assert_eq!(server.game.tick, start + 1);
+
+        // Catching up simulates the ticks missed, and the world is sent
+        // afresh.
+        server.connect(a);
+        server.handle_client_message(a, sync(Some(start + 1)));
+        server.step();
+        assert_eq!(server.game.tick, start + 12);
+        assert!(matches!(
+            server.drain_updates(a).last(),
+            Some(ServerMessage::Snapshot { .. })
+        ));
+    }
+
+    #[test]
+    fn full_servers_queue_clients_and_let_them_in_as_players_leave() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        server.access.rules.max_players = Some(1);
+        let (a, b, c) = (endpoint(1), endpoint(2), endpoint(3));
+
+        server.connect(a);
+        server.handle_client_message(a, sync(None));
+        server.handle_client_message(
+            a,
+            ClientMessage::Action(GameAction::SpawnPlayer("A".into())),
+        );
+        server.drain_updates(a);
+
+        for (n, client) in [b, c].into_iter().enumerate() {
+            server.connect(client);
+            server.handle_client_message(client, sync(None));
+            let updates = server.drain_updates(client);
+            assert!(
+                updates
+                    .iter()
+                    .any(|m| matches!(m, ServerMessage::Queued(p) if *p as usize == n + 1))
+            );
+            assert!(
+                !updates
+                    .iter()
+                    .any(|m| matches!(m, ServerMessage::Snapshot { .. }))
+            );
+        }
+
+        // Waiting clients cannot play, nor skip the line by resuming.
+        server.handle_client_message(
+            b,
+            ClientMessage::Action(GameAction::SpawnPlayer("B".into())),
+        );
+        assert!(!server.endpoints.contains_key(&b));
+        let token = server.sessions.token_of(&c).expect("connected");
+        server.disconnect(c);
+        let c = endpoint(4);
+        server.connect(c);
+        server.handle_client_message(
+            c,
+            ClientMessage::Resume {
+                token,
+                last_tick: None,
+            },
+        );
+        server.drain_updates(c);
+        assert_eq!(server.sessions.admitted(), 1);
+
+        // A leaving player hands their slot to the next in line.
+        server.handle_client_message(c, sync(None));
+        assert!(matches!(
+            server.drain_updates(c).last(),
+            Some(ServerMessage::Queued(2))
+        ));
+        server.disconnect(a);
+        let updates = server.drain_updates(b);
+        assert!(updates.iter().any(|m| matches!(m, ServerMessage::Admitted)));
+        assert!(matches!(
+            updates.last(),
+            Some(ServerMessage::Snapshot { .. })
+        ));
+        assert!(matches!(
+            server.drain_updates(c).last(),
+            Some(ServerMessage::Queued(1))
+        ));
+        let texts: Vec<String> = server.chat.recent(2).into_iter().map(|l| l.text).collect();
+        assert_eq!(texts, ["A joined", "A left"]);
+
+        // Past the queue's length, clients are turned away.
+        let last = endpoint(10 + u8::try_from(queue::MAX_QUEUE_LEN - 1).expect("small"));
+        for n in 0..queue::MAX_QUEUE_LEN {
+            let client = endpoint(10 + u8::try_from(n).expect("small"));
+            server.connect(client);
+            server.handle_client_message(client, sync(None));
+        }
+        assert!(
+            server
+                .drain_updates(last)
+                .iter()
+                .any(|m| matches!(m, ServerMessage::AccessDenied(Denied::ServerFull)))
+        );
+    }
+
+    #[test]
+    fn protected_servers_admit_with_password_or_invite_and_limit_attempts() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        server.access.rules.set_password(Some("hunter2"));
+        let code = server.access.rules.create_invite(Some(1));
+        let with = |secret: &str| ClientMessage::Sync {
+            since_tick: None,
+            packs: pack::active(),
+            secret: Some(secret.to_owned()),
+        };
+        let (a, b, c) = (endpoint(1), endpoint(2), endpoint(3));
+
+        server.connect(a);
+        server.handle_client_message(a, sync(None));
+        let updates = server.drain_updates(a);
+        assert!(
+            updates
+                .iter()
+                .any(|m| matches!(m, ServerMessage::AccessDenied(Denied::PasswordRequired)))
+        );
+        assert!(
+            !updates
+                .iter()
+                .any(|m| matches!(m, ServerMessage::Snapshot { .. }))
+        );
+
+        // Nothing but another try gets through until let in.
+        server.handle_client_message(a, ClientMessage::Command("help".into()));
+        assert!(server.drain_updates(a).is_empty());
+
+        server.handle_client_message(a, with("hunter3"));
+        assert!(matches!(
+            server.drain_updates(a).last(),
+            Some(ServerMessage::AccessDenied(Denied::WrongPassword))
+        ));
+        server.handle_client_message(a, with("hunter2"));
+        assert!(matches!(
+            server.drain_updates(a).last(),
+            Some(ServerMessage::Snapshot { .. })
+        ));
+
+        // An invite code good for one join lets in one client.
+        server.connect(b);
+        server.handle_client_message(b, with(&code));
+        assert!(matches!(
+            server.drain_updates(b).last(),
+            Some(ServerMessage::Snapshot { .. })
+        ));
+        server.connect(c);
+        server.handle_client_message(c, with(&code));
+        assert!(matches!(
+            server.drain_updates(c).last(),
+            Some(ServerMessage::AccessDenied(Denied::WrongPassword))
+        ));
+
+        // Past the limit even the right password is turned away.
+        for _ in 1..access::MAX_FAILURES {
+            server.handle_client_message(c, with("guess"));
+        }
+        server.handle_client_message(c, with("hunter2"));
+        assert!(matches!(
+            server.drain_updates(c).last(),
+            Some(ServerMessage::AccessDenied(Denied::TooManyAttempts))
+        ));
+    }
+
+    #[test]
+    fn status_counts_players_and_is_capped_when_decoded() {
+        let mut server = ServerState::new(GameState::create_test_world("w".repeat(100)));
+        let a = endpoint(1);
+        server.connect(a);
+        server.connect(endpoint(2));
+        server.handle_client_message(a, sync(None));
+        server.handle_client_message(
+            a,
+            ClientMessage::Action(GameAction::SpawnPlayer("A".into())),
+        );
+
+        let status = ServerStatus::of(&server);
+        assert_eq!(status.players, 1);
+        assert_eq!(
+            status.world_name.chars().count(),
+            status::MAX_STATUS_NAME_LEN
+        );
+        let encoded = bitcode::encode(&status);
+        assert_eq!(decode::decode_server_status(&encoded), Ok(status.clone()));
+
+        let long = ServerStatus {
+            world_name: "w".repeat(100),
+            ..status
+        };
+        assert_eq!(
+            decode::decode_server_status(&bitcode::encode(&long)),
+            Err(decode::DecodeError::FieldTooLarge("world_name"))
+        );
+    }
+
+    #[test]
+    fn presence_is_told_to_friends_only() {
+        let board = PresenceBoard::default();
+        let (friend, stranger) = (endpoint(1), endpoint(2));
+        board.set_friends([friend]);
+        assert_eq!(board.answer_to(friend), Some(Presence::InMenus));
+        assert_eq!(board.answer_to(stranger), None);
+
+        let hosting = Presence::Hosting {
+            world: "woods".to_owned(),
+            server: endpoint(3).to_string(),
+        };
+        board.set(hosting.clone());
+        let told = board.answer_to(friend).expect("a friend");
+        assert_eq!(told.server(), Some(endpoint(3).to_string().as_str()));
+        assert_eq!(
+            decode::decode_presence(&bitcode::encode(&told)),
+            Ok(hosting)
+        );
+
+        // Unfriending stops the answers.
+        board.set_friends([]);
+        assert_eq!(board.answer_to(friend), None);
+        let long = Presence::Playing {
+            world: "w".repeat(presence::MAX_PRESENCE_NAME_LEN + 1),
+            server: String::new(),
+        };
+        assert_eq!(
+            decode::decode_presence(&bitcode::encode(&long)),
+            Err(decode::DecodeError::FieldTooLarge("world"))
+        );
+    }
+
+    #[test]
+    fn diagnostics_lead_with_the_first_failure() {
+        use diagnostics::{Check, Outcome, Report};
+
+        let check = |name, outcome| Check { name, outcome };
+        let mut report = Report {
+            checks: vec![
+                check("Network socket", Outcome::Passed("bound".to_owned())),
+                check("Server", Outcome::Skipped("no server ID given".to_owned())),
+            ],
+        };
+        assert!(report.all_passed());
+        assert!(report.verdict().starts_with("Everything checked works"));
+
+        // A relay failure explains the relayed connection after it.
+        report.checks.insert(
+            1,
+            check(
+                "Relay",
+                Outcome::Failed {
+                    detail: "none answered".to_owned(),
+                    advice: "check your firewall",
+                },
+            ),
+        );
+        report.checks.push(check(
+            "Direct connection",
+            Outcome::Failed {
+                detail: "relayed".to_owned(),
+                advice: "allow UDP",
+            },
+        ));
+        assert!(!report.all_passed());
+        assert_eq!(report.verdict(), "check your firewall");
+    }
+
+    #[test]
+    fn asset_downloads_resume_and_check_the_hash() {
+        let mut store = AssetStore::builtin();
+        assert!(store.names().any(|name| name == "builtin/items.ron"));
+        let blob: Vec<u8> = (0..3 * assets::ASSET_CHUNK_SIZE + 5)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        store.insert("preview.png".into(), blob.clone());
+
+        // The connection drops after the first chunk.
+        let mut download = Download::new("preview.png".into());
+        let answer = store.answer(&download.request());
+        assert_eq!(download.begin(answer.header), Ok(true));
+        let first = answer.chunks().next().expect("a chunk");
+        let progress = download.push(first).expect("fits");
+        assert_eq!(progress.received, assets::ASSET_CHUNK_SIZE as u64);
+        let expected = assets::ASSET_CHUNK_SIZE as f32 / blob.len() as f32;
+        assert!((progress.fraction() - expected).abs() < 1e-6);
+
+        // Resuming asks only for the rest.
+        let request = download.request();
+        assert_eq!(request.offset, assets::ASSET_CHUNK_SIZE as u64);
+        let answer = store.answer(&request);
+        assert_eq!(download.begin(answer.header), Ok(true));
+        assert_eq!(answer.chunks().count(), 3);
+        for chunk in answer.chunks() {
+            download.push(chunk).expect("fits");
+        }
+        assert!(download.is_complete());
+        assert_eq!(download.finish(), Ok(blob.clone()));
+
+        // A changed asset starts over; a missing one is refused.
+        let mut download = Download::new("preview.png".into());
+        let answer = store.answer(&download.request());
+        assert_eq!(download.begin(answer.header), Ok(true));
+        download
+            .push(blob.get(..10).expect("ten bytes"))
+            .expect("fits");
+        store.insert("preview.png".into(), vec![1, 2, 3]);
+        let answer = store.answer(&download.request());
+        assert_eq!(download.begin(answer.header), Ok(false));
+        assert_eq!(download.request().offset, 0);
+        assert!(download.push(&[0; 4]).is_err());
+        let mut missing = Download::new("nope".into());
+        assert!(
+            missing
+                .begin(store.answer(&missing.request()).header)
+                .is_err()
+        );
+
+        // Bytes that do not match the hash are caught.
+        let mut download = Download::new("preview.png".into());
+        assert_eq!(
+            download.begin(store.answer(&download.request()).header),
+            Ok(true)
+        );
+        download.push(&[3, 2, 1]).expect("fits");
+        assert!(download.finish().is_err());
+    }
+
+    #[test]
+    fn large_snapshot_is_chunked_and_lost_parts_are_resent() {
+        let mut game = GameState::create_test_world("test".into());
+        for i in 0..2_000 {
+            game::spawn_player(&mut game, format!("player number {i} with a long name"));
+        }
+        let mut server = ServerState::new(game);
+        let a = endpoint(1);
+        server.connect(a);
+        server.handle_client_message(a, sync(None));
+
+        let mut assembler = None;
+        let mut parts = Vec::new();
+        for msg in server.drain_updates(a) {
+            match msg {
+                ServerMessage::SnapshotManifest { tick, parts, .. } => {
+                    assembler = Some(SnapshotAssembler::new(tick, parts));
+                }
+                ServerMessage::SnapshotPart { tick, index, bytes } => {
+                    parts.push((tick, index, bytes));
+                }
+                _ => {}
+            }
+        }
+        let mut assembler = assembler.expect("big world should be chunked");
+        assert!(parts.len() > 1);
+
+        // Lose the first part.
+        for (tick, index, bytes) in parts.drain(1..) {
+            assembler.insert(tick, index, bytes);
+        }
+        assert!(assembler.finish().is_none());
+        assert!(assembler.progress() < 1.0);
+
+        let mut missing = None;
+        for _ in 0..snapshot::PART_TIMEOUT_TICKS {
+            server.step();
+            let delta = server.drain_updates(a).into_iter().find_map(|m| match m {
+                ServerMessage::Delta(delta) => Some(delta),
+                _ => None,
+            });
+            missing = assembler.defer(delta.expect("client is past the snapshot"));
+        }
+        let missing = missing.expect("timeout should report missing parts");
+        assert_eq!(missing, vec![0]);
+
+        let tick = assembler.tick();
+        server.handle_client_message(a, ClientMessage::RequestSnapshotParts { tick, missing });
+        for msg in server.drain_updates(a) {
+            if let ServerMessage::SnapshotPart { tick, index, bytes } = msg {
+                assembler.insert(tick, index, bytes);
+            }
+        }
+
+        let (_, entities) = assembler.finish().expect("all parts received");
+        assert_eq!(&entities, server.game.entities());
+    }
+
+    #[test]
+    fn actions_beyond_the_per_tick_limit_are_dropped() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        let a = endpoint(1);
+        server.connect(a);
+        server.handle_client_message(
+            a,
+            ClientMessage::Action(GameAction::SpawnPlayer("A".into())),
+        );
+
+        let up = || ClientMessage::Action(GameAction::Move(game::Direction::Up));
+        for _ in 0..MAX_ACTIONS_PER_TICK + 3 {
+            server.handle_client_message(a, up());
+        }
+        assert_eq!(server.event_queue.len(), MAX_ACTIONS_PER_TICK as usize);
+
+        server.step();
+        server.handle_client_message(a, up());
+        assert_eq!(server.event_queue.len(), 1);
+    }
+
+    /// Make `endpoint_id` the host, who may run every command.
+    fn host(server: &mut ServerState, endpoint_id: EndpointId) {
+        server.access.host = Some(endpoint_id);
+    }
+
+    /// Run `line` as `endpoint_id` and return the console output, passing
+    /// over the chat lines queued for them meanwhile, such as joins.
+    fn run_command(server: &mut ServerState, endpoint_id: EndpointId, line: &str) -> String {
+        server.handle_client_message(endpoint_id, ClientMessage::Command(line.into()));
+        let messages: Vec<ServerMessage> = server
+            .unique_server_messages
+            .remove(&endpoint_id)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|message| !matches!(message, ServerMessage::Chat(_)))
+            .collect();
+        match messages.as_slice() {
+            [ServerMessage::ConsoleOutput(output)] => output.clone(),
+            other => panic!("expected console output, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn plugin_commands_check_permissions_and_arguments() {
+        use plugin::{ArgKind, ArgSpec, Args, CommandSpec, Permission};
+
+        struct Bounties;
+        impl ServerPlugin for Bounties {
+            fn name(&self) -> &'static str {
+                "bounties"
+            }
+
+            fn commands(&self) -> Vec<CommandSpec> {
+                vec![
+                    CommandSpec {
+                        name: "bounty",
+                        about: "Put a price on an entity",
+                        args: vec![
+                            ArgSpec::required("target", ArgKind::Entity),
+                            ArgSpec::optional("reward", ArgKind::Int),
+                        ],
+                        permission: Permission::Anyone,
+                    },
+                    CommandSpec {
+                        name: "pardon",
+                        about: "Clear a bounty",
+                        args: vec![ArgSpec::required("reason", ArgKind::Text)],
+                        permission: Permission::Admin,
+                    },
+                ]
+            }
+
+            fn run(
+                &self,
+                state: &mut ServerState,
+                _caller: &Caller,
+                command: &str,
+                args: &Args,
+            ) -> String {
+                match command {
+                    "bounty" => format!(
+                        "{} on {}",
+                        args.int("reward").unwrap_or(10),
+                        args.entity("target").map_or(0, |eid| eid.0)
+                    ),
+                    _ => format!(
+                        "Pardoned at tick {}: {}",
+                        state.game.tick,
+                        args.text("reason").unwrap_or_default()
+                    ),
+                }
+            }
+        }
+
+        struct Clash;
+        impl ServerPlugin for Clash {
+            fn name(&self) -> &'static str {
+                "clash"
+            }
+
+            fn commands(&self) -> Vec<CommandSpec> {
+                vec![CommandSpec {
+                    name: "repair",
+                    about: "",
+                    args: Vec::new(),
+                    permission: Permission::Anyone,
+                }]
+            }
+
+            fn run(&self, _: &mut ServerState, _: &Caller, _: &str, _: &Args) -> String {
+                String::new()
+            }
+        }
+
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        assert!(server.plugins.register(Clash).is_err());
+        assert_eq!(server.plugins.register(Bounties), Ok(()));
+        assert!(
+            server.plugins.register(Bounties).is_err(),
+            "names are taken"
+        );
+
+        let (a, b) = (endpoint(1), endpoint(2));
+        for (endpoint_id, key) in [(a, 1), (b, 2)] {
+            server.connect(endpoint_id);
+            server.handle_client_message(endpoint_id, ClientMessage::Identify(PlayerKey(key)));
+            server.drain_updates(endpoint_id);
+        }
+
+        assert_eq!(run_command(&mut server, b, "bounty 7"), "10 on 7");
+        assert_eq!(run_command(&mut server, b, "bounty 7 25"), "25 on 7");
+        assert_eq!(
+            run_command(&mut server, b, "bounty"),
+            "Usage: bounty <target> [reward]"
+        );
+        assert!(run_command(&mut server, b, "bounty seven").contains("not an entity id"));
+        assert!(run_command(&mut server, b, "bounty 7 25 more").starts_with("Usage"));
+
+        // Those not admins keep only the commands for anyone.
+        server.access.host = Some(a);
+        assert_eq!(
+            run_command(&mut server, b, "pardon he paid up"),
+            "Only admins may run `pardon`"
+        );
+        let help = run_command(&mut server, b, "help");
+        assert!(help.contains("bounty <target> [reward]") && !help.contains("pardon"));
+        assert!(run_command(&mut server, a, "help").contains("pardon <reason>"));
+        assert_eq!(run_command(&mut server, a, "validate"), "No issues found");
+        assert_eq!(
+            run_command(&mut server, a, "pardon he paid up"),
+            "Pardoned at tick 0: he paid up"
+        );
+    }
+
+    #[test]
+    fn only_the_host_runs_admin_commands_until_it_names_admins() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        let (a, b) = (endpoint(1), endpoint(2));
+        for (endpoint_id, key) in [(a, 1), (b, 2)] {
+            server.connect(endpoint_id);
+            server.handle_client_message(endpoint_id, ClientMessage::Identify(PlayerKey(key)));
+            server.handle_client_message(
+                endpoint_id,
+                ClientMessage::Action(GameAction::SpawnPlayer(format!("P{key}"))),
+            );
+            server.drain_updates(endpoint_id);
+        }
+        let add_b = format!("admins add {}", server.endpoints.get(&b).expect("b").0);
+
+        // Without a host no client is an admin, as on a dedicated server
+        // run from its console; an empty list makes no one one either.
+        for endpoint_id in [a, b] {
+            assert!(!server.caller(endpoint_id).admin);
+            assert!(run_command(&mut server, endpoint_id, &add_b).starts_with("Only admins"));
+        }
+
+        server.access.host = Some(a);
+        assert!(run_command(&mut server, b, "validate").starts_with("Only admins"));
+        assert!(run_command(&mut server, b, &add_b).starts_with("Only admins"));
+        assert_eq!(
+            run_command(&mut server, a, "admins"),
+            "No admins; only the host may run every command"
+        );
+        assert_eq!(run_command(&mut server, a, "validate"), "No issues found");
+
+        server.access.rules.admins.insert(PlayerKey(2));
+        assert_eq!(run_command(&mut server, b, "validate"), "No issues found");
+    }
+
+    #[test]
+    fn the_host_is_told_by_its_endpoint_not_by_a_replayed_player_key() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        let (host_endpoint, impostor) = (endpoint(1), endpoint(2));
+        server.access.host = Some(host_endpoint);
+        for endpoint_id in [host_endpoint, impostor] {
+            server.connect(endpoint_id);
+            // The impostor learned the host's key from a server the host
+            // joined, and identifies with it.
+            server.handle_client_message(endpoint_id, ClientMessage::Identify(PlayerKey(1)));
+            server.drain_updates(endpoint_id);
+        }
+        assert!(server.caller(host_endpoint).admin);
+        assert!(!server.caller(impostor).admin);
+        assert!(run_command(&mut server, impostor, "validate").starts_with("Only admins"));
+        assert_eq!(
+            run_command(&mut server, host_endpoint, "validate"),
+            "No issues found"
+        );
+    }
+
+    #[test]
+    fn console_commands_validate_and_repair_the_world() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        let a = endpoint(1);
+        server.connect(a);
+        host(&mut server, a);
+        server.drain_updates(a);
+
+        assert_eq!(run_command(&mut server, a, "validate"), "No issues found");
+        assert!(run_command(&mut server, a, "frobnicate").starts_with("Unknown command"));
+
+        server.game.entity_gen = game::EntityGenerator::default();
+        assert!(run_command(&mut server, a, "repair").starts_with("1 issue(s) repaired"));
+        assert_eq!(run_command(&mut server, a, "validate"), "No issues found");
+    }
+
+    #[test]
+    fn console_tags_and_annotates_entities() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        let a = endpoint(1);
+        server.connect(a);
+        host(&mut server, a);
+        server.drain_updates(a);
+
+        assert_eq!(run_command(&mut server, a, "tag 2 boss"), "Tagged 2 `boss`");
+        assert_eq!(
+            run_command(&mut server, a, "meta 2 greeting well met"),
+            "Set 2.greeting = well met"
+        );
+        assert_eq!(run_command(&mut server, a, "find boss"), "Tagged `boss`: 2");
+        assert!(run_command(&mut server, a, "inspect 2").contains("greeting = well met"));
+        assert_eq!(run_command(&mut server, a, "tag 99 boss"), "No entity 99");
+        assert!(run_command(&mut server, a, "tag 2 a/b").starts_with("Cannot tag"));
+
+        server.step();
+        let entity = server.game.entities().get(&EntityID(2)).expect("entity");
+        assert!(entity.tags.contains("boss"));
+        assert_eq!(entity.metadata.get("greeting"), Some("well met"));
+    }
+
+    #[test]
+    fn console_sets_factions_and_reputation() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        let a = endpoint(1);
+        server.connect(a);
+        host(&mut server, a);
+        server.drain_updates(a);
+
+        assert_eq!(
+            run_command(&mut server, a, "faction 2 bandits"),
+            "2 now belongs to Bandits"
+        );
+        assert_eq!(
+            run_command(&mut server, a, "faction 2 pirates"),
+            "Unknown faction `pirates`"
+        );
+        assert_eq!(
+            run_command(&mut server, a, "rep 2 villagers -40"),
+            "2 is hostile with villagers (-40)"
+        );
+        assert_eq!(
+            run_command(&mut server, a, "rep 2 villagers -400"),
+            "2 is hostile with villagers (-100)"
+        );
+        assert!(
+            run_command(&mut server, a, "inspect 2").contains("reputation with villagers: -100")
+        );
+
+        let entity = server.game.entities().get(&EntityID(2)).expect("entity");
+        assert_eq!(entity.faction.as_deref(), Some("bandits"));
+        assert!(entity.reputation.is_hostile("villagers"));
+    }
+
+    #[test]
+    fn dialogue_is_streamed_to_the_talking_player() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        let (a, b) = (endpoint(1), endpoint(2));
+        for (client, name) in [(a, "Alice"), (b, "Bob")] {
+            server.connect(client);
+            server.handle_client_message(
+                client,
+                ClientMessage::Action(GameAction::SpawnPlayer(name.into())),
+            );
+            server.drain_updates(client);
+        }
+        host(&mut server, a);
+        let bob = server.endpoints.get(&b).copied().expect("b");
+        assert_eq!(
+            run_command(&mut server, a, &format!("dialogue {} old_oak", bob.0)),
+            format!("{} now uses dialogue `old_oak`", bob.0)
+        );
+
+        server.handle_client_message(a, ClientMessage::Action(GameAction::Talk(bob)));
+        server.step();
+        let dialogue = |msgs: Vec<ServerMessage>| {
+            msgs.into_iter()
+                .find_map(|msg| match msg {
+                    ServerMessage::Dialogue(view) => Some(view),
+                    _ => None,
+                })
+                .expect("dialogue update")
+        };
+        let view = dialogue(server.drain_updates(a)).expect("conversation started");
+        assert_eq!(view.speaker, "Bob");
+        assert!(
+            !server
+                .drain_updates(b)
+                .iter()
+                .any(|msg| matches!(msg, ServerMessage::Dialogue(_)))
+        );
+
+        server.handle_client_message(a, ClientMessage::Action(GameAction::Choose(1)));
+        server.step();
+        assert_eq!(dialogue(server.drain_updates(a)), None);
+    }
+
+    #[test]
+    fn spawn_as_only_takes_free_characters() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        let (a, b) = (endpoint(1), endpoint(2));
+        server.connect(a);
+        server.connect(b);
+        server.handle_client_message(
+            a,
+            ClientMessage::Action(GameAction::SpawnPlayer("Alice".into())),
+        );
+        let alice = server.endpoints.get(&a).copied().expect("a");
+        server.drain_updates(b);
+        let spawn_as = |server: &mut ServerState, eid| {
+            server.handle_client_message(b, ClientMessage::Action(GameAction::SpawnAs(eid)));
+            server
+                .drain_updates(b)
+                .into_iter()
+                .find_map(|msg| match msg {
+                    ServerMessage::PlayerID(eid) => Some(Ok(eid)),
+                    ServerMessage::SpawnRejected(reason) => Some(Err(reason)),
+                    _ => None,
+                })
+        };
+
+        // Trees are not playable, and Alice is taken, even while her
+        // session waits to be resumed.
+        assert_eq!(
+            spawn_as(&mut server, EntityID(1)),
+            Some(Err("no such character".into()))
+        );
+        let taken = Some(Err("that character is already in play".into()));
+        assert_eq!(spawn_as(&mut server, alice), taken);
+        server.disconnect(a);
+        assert_eq!(spawn_as(&mut server, alice), taken);
+
+        run_out_sessions(&mut server);
+        assert_eq!(spawn_as(&mut server, alice), Some(Ok(alice)));
+        assert_eq!(server.endpoints.get(&b), Some(&alice));
+    }
+
+    #[test]
+    fn characters_take_their_players_color_until_they_pick_one() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        let a = endpoint(1);
+        server.connect(a);
+        server.handle_client_message(a, ClientMessage::Identify(PlayerKey(1)));
+        server.handle_client_message(
+            a,
+            ClientMessage::Action(GameAction::SpawnPlayer("Alice".into())),
+        );
+        let pid = server.endpoints.get(&a).copied().expect("a");
+        let color = |server: &ServerState| server.game.entities().get(&pid).expect("pid").color;
+        assert_eq!(color(&server), Some(PlayerColor::of(PlayerKey(1))));
+
+        let picked = PlayerColor([200, 40, 40]);
+        server.handle_client_message(a, ClientMessage::Action(GameAction::SetColor(picked)));
+        server.step();
+        assert_eq!(color(&server), Some(picked));
+    }
+
+    #[test]
+    fn identified_players_keep_their_characters() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        let (a, b) = (endpoint(1), endpoint(2));
+        server.connect(a);
+        server.connect(b);
+        server.handle_client_message(a, ClientMessage::Identify(PlayerKey(1)));
+        server.handle_client_message(b, ClientMessage::Identify(PlayerKey(2)));
+        let roster = |server: &mut ServerState, endpoint| {
+            server
+                .drain_updates(endpoint)
+                .into_iter()
+                .filter_map(|msg| match msg {
+                    ServerMessage::Roster(roster) => Some(roster),
+                    _ => None,
+                })
+                .next_back()
+        };
+        assert_eq!(roster(&mut server, a), Some(Roster::default()));
+
+        for i in 0..roster::MAX_ROSTER_SIZE {
+            server.handle_client_message(
+                a,
+                ClientMessage::Action(GameAction::SpawnPlayer(format!("Alice {i}"))),
+            );
+        }
+        let full = roster(&mut server, a).unwrap_or_default();
+        assert!(full.is_full());
+        server.handle_client_message(
+            a,
+            ClientMessage::Action(GameAction::SpawnPlayer("One too many".into())),
+        );
+        assert!(server.drain_updates(a).iter().any(
+            |msg| matches!(msg, ServerMessage::SpawnRejected(reason) if reason.contains("full"))
+        ));
+
+        // Once Alice leaves, her characters are free to play but still
+        // hers: Bob cannot take them.
+        server.step();
+        let first = full.characters.first().map_or(EntityID(0), |(eid, _)| *eid);
+        server.disconnect(a);
+        run_out_sessions(&mut server);
+        server.drain_updates(b);
+        server.handle_client_message(b, ClientMessage::Action(GameAction::SpawnAs(first)));
+        assert!(server.drain_updates(b).iter().any(|msg| matches!(
+            msg,
+            ServerMessage::SpawnRejected(reason) if reason == "that character belongs to another player"
+        )));
+
+        let c = endpoint(3);
+        server.connect(c);
+        server.handle_client_message(c, ClientMessage::Identify(PlayerKey(1)));
+        server.handle_client_message(c, ClientMessage::Action(GameAction::SpawnAs(first)));
+        let back = roster(&mut server, c).unwrap_or_default();
+        assert_eq!(back.active, Some(first));
+        assert!(
+            back.characters
+                .last()
+                .is_some_and(|(_, stats)| stats.ticks_played > 0)
+        );
+    }
+
+    #[test]
+    fn characters_are_exported_and_imported_over_the_protocol() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        let a = endpoint(1);
+        server.connect(a);
+        server.handle_client_message(a, ClientMessage::ExportCharacter);
+        assert!(
+            server
+                .drain_updates(a)
+                .iter()
+                .any(|msg| matches!(msg, ServerMessage::ExportRejected(_)))
+        );
+
+        server.handle_client_message(
+            a,
+            ClientMessage::Action(GameAction::SpawnPlayer("Alice".into())),
+        );
+        server.handle_client_message(a, ClientMessage::ExportCharacter);
+        let Some(text) = server
+            .drain_updates(a)
+            .into_iter()
+            .find_map(|msg| match msg {
+                ServerMessage::CharacterExport(text) => Some(text),
+                _ => None,
+            })
+        else {
+            panic!("no export");
+        };
+
+        let b = endpoint(2);
+        server.connect(b);
+        server.handle_client_message(b, ClientMessage::ImportCharacter(text.clone()));
+        let updates = server.drain_updates(b);
+        assert!(
+            updates.iter().any(
+                |msg| matches!(msg, ServerMessage::Imported { stripped } if stripped.is_empty())
+            )
+        );
+        let imported = server.endpoints.get(&b).copied().expect("b");
+        assert_ne!(imported, server.endpoints.get(&a).copied().expect("a"));
+        assert_eq!(
+            server
+                .game
+                .entities()
+                .get(&imported)
+                .expect("imported")
+                .name
+                .as_deref(),
+            Some("Alice")
+        );
+
+        // Hosts can turn imports off; garbage never spawns anything.
+        let c = endpoint(3);
+        server.connect(c);
+        host(&mut server, c);
+        for (command, text) in [
+            ("transfer import off", text),
+            ("transfer import on", "(".into()),
+        ] {
+            server.handle_client_message(c, ClientMessage::Command(command.into()));
+            server.handle_client_message(c, ClientMessage::ImportCharacter(text));
+            assert!(
+                server
+                    .drain_updates(c)
+                    .iter()
+                    .any(|msg| matches!(msg, ServerMessage::SpawnRejected(_)))
+            );
+            assert!(!server.endpoints.contains_key(&c));
+        }
+    }
+
+    #[test]
+    fn recordings_play_back_every_recorded_tick() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        let a = endpoint(1);
+        server.connect(a);
+        host(&mut server, a);
+        server.handle_client_message(a, ClientMessage::Command("record start".into()));
+        let mut worlds = vec![(server.game.tick, server.game.entities().clone())];
+        for i in 0..recording::KEYFRAME_INTERVAL * 5 / 2 {
+            if i == 3 {
+                server.handle_client_message(
+                    a,
+                    ClientMessage::Action(GameAction::SpawnPlayer("Alice".into())),
+                );
+            }
+            let direction = if i % 20 < 10 {
+                game::Direction::Right
+            } else {
+                game::Direction::Left
+            };
+            server.handle_client_message(a, ClientMessage::Action(GameAction::Move(direction)));
+            server.step();
+            worlds.push((server.game.tick, server.game.entities().clone()));
+        }
+
+        let Some(recording) = server.recording.take() else {
+            panic!("not recording");
+        };
+        assert_eq!(recording.keyframes.len(), 3);
+        assert!(recording.tick(10).is_some_and(|t| t.actions.len() == 1));
+
+        // Forwards tick by tick, then jumping about.
+        let mut playback = Playback::new(recording);
+        for (tick, entities) in &worlds {
+            playback.seek(*tick);
+            assert_eq!(playback.entities(), entities, "tick {tick}");
+        }
+        assert!(playback.is_at_end());
+        for (tick, entities) in worlds.iter().rev().step_by(37) {
+            playback.seek(*tick);
+            assert_eq!(playback.entities(), entities, "tick {tick}");
+        }
+    }
+
+    #[test]
+    fn recorded_rolls_are_derived_again_and_forged_ones_caught() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        let a = endpoint(1);
+        server.connect(a);
+        host(&mut server, a);
+        server.handle_client_message(
+            a,
+            ClientMessage::Action(GameAction::SpawnPlayer("Alice".into())),
+        );
+        server.step();
+        server.drain_updates(a);
+        let tree = server
+            .game
+            .entities()
+            .iter()
+            .filter(|(_, e)| e.entity_type == game::EntityType::Tree)
+            .min_by_key(|(_, e)| game::math::manhattan(e.position, game::SPAWN_POINT))
+            .map(|(eid, _)| *eid)
+            .expect("test world has trees");
+        run_command(&mut server, a, &format!("ai {} wander", tree.0));
+        run_command(&mut server, a, "record start");
+        for _ in 0..20 {
+            server.step();
+        }
+
+        let Some(mut recording) = server.recording.take() else {
+            panic!("not recording");
+        };
+        let rolls: Vec<&game::rng::Rolled> =
+            recording.ticks.iter().flat_map(|t| &t.rolls).collect();
+        assert_eq!(rolls.len(), 20, "one roll a tick");
+        assert!(rolls.iter().all(|rolled| rolled.subject == tree));
+        assert_eq!(recording.audit(), []);
+
+        // A roll made up, and a fair one replayed in a later tick.
+        let earlier = rolls.first().map(|rolled| (*rolled).clone());
+        let forged = recording.ticks.get_mut(5).expect("recorded");
+        let forged_tick = forged.delta.tick;
+        let roll = &mut forged.rolls.first_mut().expect("rolled").roll;
+        roll.value = roll.value.wrapping_add(1);
+        let replayed = recording.ticks.get_mut(9).expect("recorded");
+        let replayed_tick = replayed.delta.tick;
+        replayed.rolls.extend(earlier);
+        let unfair: Vec<(u64, bool)> = recording
+            .audit()
+            .iter()
+            .map(|(tick, rolled)| (*tick, rolled.is_fair()))
+            .collect();
+        assert_eq!(unfair, [(forged_tick, false), (replayed_tick, true)]);
+    }
+
+    #[test]
+    fn history_steps_back_and_forth_through_ticks() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        let a = endpoint(1);
+        server.connect(a);
+        host(&mut server, a);
+        server.handle_client_message(a, sync(None));
+        server.drain_updates(a);
+        assert_eq!(
+            run_command(&mut server, a, "history on 100"),
+            "Keeping the last 100 ticks"
+        );
+        let mut checksums = vec![(server.game.tick, server.game.checksum())];
+        for i in 0..150 {
+            if i == 3 {
+                server.handle_client_message(
+                    a,
+                    ClientMessage::Action(GameAction::SpawnPlayer("Alice".into())),
+                );
+                server.drain_updates(a);
+            }
+            let direction = if i % 20 < 10 {
+                game::Direction::Right
+            } else {
+                game::Direction::Left
+            };
+            server.handle_client_message(a, ClientMessage::Action(GameAction::Move(direction)));
+            server.step();
+            checksums.push((server.game.tick, server.game.checksum()));
+        }
+        let checksum_at = |tick: u64| checksums.iter().find(|(t, _)| *t == tick).map(|(_, c)| *c);
+
+        server.drain_updates(a);
+        assert_eq!(
+            run_command(&mut server, a, "history back 30"),
+            "Paused at tick 120"
+        );
+        assert_eq!(Some(server.game.checksum()), checksum_at(120));
+        server.step();
+        assert_eq!(server.game.tick, 120, "paused while travelling");
+        assert!(run_command(&mut server, a, "history goto 2").starts_with("Tick 2 is not"));
+        assert_eq!(
+            run_command(&mut server, a, "history forward 7"),
+            "Paused at tick 127"
+        );
+        assert_eq!(Some(server.game.checksum()), checksum_at(127));
+        assert!(matches!(
+            server.drain_updates(a).last(),
+            Some(ServerMessage::Snapshot { tick: 127, .. })
+        ));
+
+        let b = endpoint(2);
+        server.connect(b);
+        assert!(run_command(&mut server, a, "history back").contains("single-player"));
+        server.disconnect(b);
+
+        assert_eq!(
+            run_command(&mut server, a, "history resume"),
+            "Live from tick 127"
+        );
+        server.step();
+        assert_eq!(server.game.tick, 128);
+        assert_eq!(
+            run_command(&mut server, a, "history back"),
+            "Paused at tick 127"
+        );
+        assert_eq!(Some(server.game.checksum()), checksum_at(127));
+    }
+
+    #[test]
+    fn refused_trades_are_reported_to_the_trader() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        let a = endpoint(1);
+        server.connect(a);
+        host(&mut server, a);
+        server.handle_client_message(
+            a,
+            ClientMessage::Action(GameAction::SpawnPlayer("Alice".into())),
+        );
+        server.drain_updates(a);
+        assert_eq!(
+            run_command(&mut server, a, "shop 1 general_store"),
+            "1 now runs General Store"
+        );
+
+        let buy = GameAction::Buy {
+            vendor: EntityID(1),
+            item: "bread".into(),
+            count: 1,
+        };
+        server.handle_client_message(a, ClientMessage::Action(buy));
+        server.step();
+        assert!(server.drain_updates(a).iter().any(|msg| matches!(
+            msg,
+            ServerMessage::TradeRejected(reason) if reason == "the vendor is too far away"
+        )));
+    }
+
+    #[test]
+    fn world_events_are_announced_to_everyone() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        let (a, b) = (endpoint(1), endpoint(2));
+        server.connect(a);
+        server.game.tick = 2999;
+        server.step();
+        let started = |msgs: &[ServerMessage]| {
+            msgs.iter().any(|msg| {
+                matches!(msg, ServerMessage::WorldEvent(e) if e.id == "wandering_trader" && e.active)
+            })
+        };
+        assert!(started(&server.drain_updates(a)));
+
+        // Players joining later hear about events already under way.
+        server.connect(b);
+        assert!(started(&server.drain_updates(b)));
+    }
+
+    #[test]
+    fn knockbacks_are_announced_to_everyone() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        let (a, b) = (endpoint(1), endpoint(2));
+        for (endpoint_id, name) in [(a, "Alice"), (b, "Bob")] {
+            server.connect(endpoint_id);
+            server.handle_client_message(
+                endpoint_id,
+                ClientMessage::Action(GameAction::SpawnPlayer(name.into())),
+            );
+        }
+        let bob = server.endpoints.get(&b).copied().expect("b");
+        if let Some(entity) = server.game.entities().get(&bob) {
+            let to = game::Point {
+                x: entity.position.x + 1,
+                ..entity.position
+            };
+            server.game.place_entity(bob, to);
+        }
+        server.step();
+        server.drain_updates(b);
+
+        server.handle_client_message(a, ClientMessage::Action(GameAction::Attack(bob)));
+        server.step();
+        assert!(server.drain_updates(b).iter().any(|msg| matches!(
+            msg,
+            ServerMessage::Pushed { entity_id, .. } if *entity_id == bob
+        )));
+    }
+
+    #[test]
+    fn hundreds_of_bots_spawn_and_walk() {
+        use bot::{Behavior, Bot};
+
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        let mut bots: Vec<(EndpointId, Bot)> = (0..200u8)
+            .map(|n| {
+                let seed = u64::from(n);
+                let behavior = if n == 0 {
+                    Behavior::Script(vec![GameAction::Move(game::Direction::Up)])
+                } else {
+                    Behavior::RandomWalk { seed }
+                };
+                (
+                    iroh::SecretKey::from_bytes(&[n; 32]).public(),
+                    Bot::new(format!("Bot {n}"), behavior),
+                )
+            })
+            .collect();
+        for (endpoint_id, _) in &bots {
+            server.connect(*endpoint_id);
+        }
+
+        for _ in 0..10 {
+            for (endpoint_id, bot) in &mut bots {
+                for msg in server.drain_updates(*endpoint_id) {
+                    bot.observe(&msg);
+                }
+                if let Some(msg) = bot.next_message() {
+                    server.handle_client_message(*endpoint_id, msg);
+                }
+            }
+            server.step();
+        }
+
+        assert!(bots.iter().all(|(_, bot)| bot.stats().spawned));
+        assert_eq!(server.game.get_playable_entities().len(), bots.len());
+        let scripted = bots.first().expect("bots").1.player_id().expect("spawned");
+        assert!(
+            server
+                .game
+                .entities()
+                .get(&scripted)
+                .expect("scripted")
+                .position
+                .y
+                < game::SPAWN_POINT.y
+        );
+        let spread: BTreeSet<(i32, i32)> = server
+            .game
+            .entities()
+            .values()
+            .map(|e| (e.position.x, e.position.y))
+            .collect();
+        assert!(spread.len() > 20, "bots should wander apart");
+    }
+
+    #[test]
+    fn server_ticks_are_profiled() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        let a = endpoint(1);
+        server.connect(a);
+        host(&mut server, a);
+        server.drain_updates(a);
+        server.step();
+        server.step();
+        assert_eq!(
+            server
+                .profiler
+                .history()
+                .map(|t| t.tick)
+                .collect::<Vec<_>>(),
+            [1, 2]
+        );
+        assert!(run_command(&mut server, a, "profile").starts_with("Average over 2 ticks"));
+        assert!(run_command(&mut server, a, "profile csv ../etc/passwd").starts_with("Invalid"));
+    }
+
+    #[test]
+    fn broken_content_files_leave_the_content_in_use_and_the_server_running() {
+        use crate::game::content::Sources;
+        use crate::game::{ContentRegistry, WorldId};
+
+        let dir = std::env::temp_dir().join(format!("gamik-reload-{}", WorldId::generate()));
+        std::fs::create_dir_all(&dir).expect("temp dir is writable");
+        for (name, source) in Sources::BUILTIN.files() {
+            std::fs::write(dir.join(name), source).expect("temp dir is writable");
+        }
+        std::fs::write(dir.join("shops.ron"), "[(").expect("temp dir is writable");
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        server.content_watch = Some(ContentWatcher::new(&dir));
+        let before = ContentRegistry::builtin();
+        let start = server.game.tick;
+
+        server.step();
+        assert_eq!(server.game.tick, start + 1);
+        assert_eq!(*ContentRegistry::builtin(), *before);
+        let later = Instant::now() + crate::watch::WATCH_INTERVAL;
+        let watch = server.content_watch.as_mut().expect("still watching");
+        assert!(
+            watch.poll(later).is_none(),
+            "broken files are reported once"
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn memory_report_grows_with_the_world() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        server.step();
+        let before = server.memory_report();
+        assert!(
+            before
+                .parts()
+                .iter()
+                .any(|(name, _)| name == "game.entities")
+        );
+
+        for n in 0..100 {
+            game::spawn_player(&mut server.game, format!("Player {n}"));
+        }
+        server.step();
+        let after = server.memory_report();
+        assert!(after.total() > before.total());
+        assert!(after.to_string().contains("delta log"));
+    }
+
+    #[test]
+    fn server_state_process_events_applies_moves() {
+        let game = GameState::create_test_world("test".into());
+        let mut server = ServerState::new(game);
+
+        let pid = game::spawn_player(&mut server.game, "Alice".into());
+        let start = server.game.entities().get(&pid).expect("pid").position;
+
+        server
+            .event_queue
+            .push((pid, GameAction::Move(game::Direction::Right)));
+        server.process_events();
+
+        assert_eq!(
+            server.game.entities().get(&pid).expect("pid").position,
+            game::Point {
+                x: start.x + 1,
+                y: start.y
+            }
+        );
+    }
+
+    #[test]
+    fn chat_is_logged_rotated_and_replayed_to_late_joiners() {
+        let dir = std::env::temp_dir().join(format!("gamik-chat-{}", WorldId::generate()));
+        let path = dir.join("world.log");
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        server.chat = ChatLog::open_at(path.clone()).expect("no log yet");
+        let (a, b) = (endpoint(1), endpoint(2));
+
+        server.connect(a);
+        server.handle_client_message(a, ClientMessage::Chat("nobody hears this".into()));
+        server.handle_client_message(
+            a,
+            ClientMessage::Action(GameAction::SpawnPlayer("A".into())),
+        );
+        server.handle_client_message(a, ClientMessage::Chat("  hello  ".into()));
+        let texts = |lines: &[ChatLine]| lines.iter().map(|l| l.text.clone()).collect::<Vec<_>>();
+        assert_eq!(texts(&server.chat.recent(10)), ["A joined", "A: hello"]);
+
+        server.connect(b);
+        server.handle_client_message(b, ClientMessage::ChatHistory(1));
+        let history = server
+            .drain_updates(b)
+            .into_iter()
+            .find_map(|msg| match msg {
+                ServerMessage::ChatHistory(lines) => Some(lines),
+                _ => None,
+            });
+        assert_eq!(
+            history.map(|lines| texts(&lines)),
+            Some(vec!["A: hello".to_owned()])
+        );
+
+        // A restarted server picks up where the log left off.
+        let reopened = ChatLog::open_at(path.clone()).expect("log was written");
+        assert_eq!(reopened.recent(10), server.chat.recent(10));
+
+        let long = "x".repeat(chat::MAX_CHAT_LEN);
+        let lines = chat::MAX_LOG_BYTES as usize / long.len() + 1;
+        for _ in 0..lines {
+            server.actions_this_tick.clear();
+            server.handle_client_message(a, ClientMessage::Chat(long.clone()));
+        }
+        let rotated = dir.join("world.log.1");
+        assert!(rotated.exists(), "a full log is rotated");
+        let size = |path: &std::path::Path| std::fs::metadata(path).map_or(0, |m| m.len());
+        assert!(size(&path) < size(&rotated));
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn message_log_filters_searches_and_keeps_its_own_lines() {
+        use chat::{Category, MessageLog};
+
+        let said = |tick, kind, text: &str| ChatLine {
+            tick,
+            kind,
+            text: text.into(),
+        };
+        let mut log = MessageLog::default();
+        log.push(said(5, LineKind::Chat, "A: hello"));
+        log.note(7, Category::Exploration, "Entered region (0, 0)".into());
+        log.note(9, Category::Combat, "You were knocked back".into());
+        let texts = |log: &MessageLog| log.visible().map(|l| l.text.clone()).collect::<Vec<_>>();
+
+        log.history(vec![
+            said(2, LineKind::Join, "A joined"),
+            said(5, LineKind::Chat, "A: hello"),
+        ]);
+        assert_eq!(
+            texts(&log),
+            [
+                "A joined",
+                "A: hello",
+                "Entered region (0, 0)",
+                "You were knocked back"
+            ],
+            "history replaces the server's lines and keeps ours, in order"
+        );
+
+        log.show(Category::System, false);
+        log.show(Category::Exploration, false);
+        assert_eq!(texts(&log), ["A: hello", "You were knocked back"]);
+        log.search = " HELLO ".into();
+        assert_eq!(texts(&log), ["A: hello"]);
+
+        let exported = log.to_text(|tick| format!("t{tick}"));
+        assert_eq!(exported.lines().count(), 4, "exports ignore the filters");
+        assert!(exported.starts_with("t2\tSystem\tA joined\n"));
+    }
+
+    #[test]
+    fn debug_data_is_sent_only_to_clients_asking_for_it() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        let (asker, other) = (endpoint(1), endpoint(2));
+        for endpoint_id in [asker, other] {
+            server.connect(endpoint_id);
+            server.handle_client_message(endpoint_id, sync(None));
+        }
+        server.handle_client_message(asker, ClientMessage::Debug(true));
+        let debug = |server: &mut ServerState, endpoint_id| {
+            server
+                .drain_updates(endpoint_id)
+                .into_iter()
+                .find_map(|msg| match msg {
+                    ServerMessage::Debug(data) => Some(data),
+                    _ => None,
+                })
+        };
+
+        let walker = game::spawn_player(&mut server.game, "Walker".into());
+        let from = server
+            .game
+            .entities()
+            .get(&walker)
+            .expect("spawned")
+            .position;
+        let to = Point {
+            x: from.x + 3,
+            y: from.y,
+        };
+        server.game.entity_mut(walker).expect("spawned").destination = Some(to);
+
+        let data = debug(&mut server, asker).expect("asked for debug data");
+        let path = game::pathfind(&server.game, from, to).expect("a way there");
+        assert_eq!(data.paths, vec![(walker, path)]);
+        assert_eq!(debug(&mut server, other), None);
+
+        server.handle_client_message(asker, ClientMessage::Debug(false));
+        assert_eq!(debug(&mut server, asker), None);
+    }
+
+    #[test]
+    fn whispers_reach_only_their_recipient_unless_blocked() {
+        use whisper::{ChatCommand, MAX_WHISPERS, parse_chat};
+
+        assert_eq!(
+            parse_chat(r#"/msg "Old Tom" meet at the well"#),
+            Ok(ChatCommand::Whisper {
+                to: "Old Tom".into(),
+                text: "meet at the well".into()
+            })
+        );
+        assert_eq!(parse_chat(" hi "), Ok(ChatCommand::Say("hi".into())));
+        assert!(parse_chat("/msg Tom").is_err());
+        assert_eq!(
+            parse_chat("/bounty 12 50"),
+            Ok(ChatCommand::Server("bounty 12 50".into()))
+        );
+
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        let players = [endpoint(1), endpoint(2), endpoint(3)];
+        for (endpoint_id, name) in players.into_iter().zip(["Ann", "Bob", "Cy"]) {
+            server.connect(endpoint_id);
+            server.handle_client_message(
+                endpoint_id,
+                ClientMessage::Action(GameAction::SpawnPlayer(name.into())),
+            );
+        }
+        let [a, b, c] = players;
+        for endpoint_id in players {
+            server.drain_updates(endpoint_id);
+        }
+        let whispers = |server: &mut ServerState, endpoint_id| {
+            server
+                .drain_updates(endpoint_id)
+                .into_iter()
+                .filter_map(|msg| match msg {
+                    ServerMessage::Whisper(w) => Some((w.from, w.to, w.text)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let whisper = |to: &str, text: &str| ClientMessage::Whisper {
+            to: to.into(),
+            text: text.into(),
+        };
+
+        server.handle_client_message(a, whisper("bob", " psst "));
+        let sent = vec![("Ann".to_owned(), "Bob".to_owned(), "psst".to_owned())];
+        assert_eq!(whispers(&mut server, b), sent);
+        assert_eq!(whispers(&mut server, a), sent, "echoed to the sender");
+        assert!(whispers(&mut server, c).is_empty());
+        assert!(server.chat.recent(10).iter().all(|l| l.text != "Ann: psst"));
+
+        server.handle_client_message(a, whisper("Dee", "anyone?"));
+        assert!(
+            server
+                .drain_updates(a)
+                .iter()
+                .any(|msg| matches!(msg, ServerMessage::WhisperFailed(_)))
+        );
+
+        // Bob blocks Ann; she is not told.
+        server.handle_client_message(b, ClientMessage::Block(vec!["ANN".into()]));
+        server.actions_this_tick.clear();
+        server.handle_client_message(a, whisper("Bob", "hello?"));
+        assert!(whispers(&mut server, b).is_empty());
+        assert_eq!(whispers(&mut server, a).len(), 1);
+
+        // Past the rate limit whispers are refused.
+        for _ in 1..MAX_WHISPERS {
+            server.actions_this_tick.clear();
+            server.handle_client_message(a, whisper("Cy", "spam"));
+        }
+        assert_eq!(whispers(&mut server, c).len(), MAX_WHISPERS - 2);
+        server.actions_this_tick.clear();
+        server.handle_client_message(a, whisper("Cy", "spam"));
+        assert!(whispers(&mut server, c).is_empty());
+    }
+
+    #[test]
+    fn pings_reach_only_the_pinger_s_team() {
+        let mut server = ServerState::new(GameState::create_test_world("test".into()));
+        let players = [endpoint(1), endpoint(2), endpoint(3)];
+        for (endpoint_id, name) in players.into_iter().zip(["A", "B", "C"]) {
+            server.connect(endpoint_id);
+            server.handle_client_message(
+                endpoint_id,
+                ClientMessage::Action(GameAction::SpawnPlayer(name.into())),
+            );
+        }
+        let [a, b, c] = players;
+        let eid = |server: &ServerState, endpoint_id| {
+            server
+                .endpoints
+                .get(&endpoint_id)
+                .copied()
+                .expect("endpoint_id")
+        };
+        let carol = eid(&server, c);
+        server.game.entity_mut(carol).expect("carol").faction = Some("bandits".into());
+        let pos = server
+            .game
+            .entities()
+            .get(&eid(&server, a))
+            .expect("entity")
+            .position;
+        server.handle_client_message(
+            a,
+            ClientMessage::Action(GameAction::Ping {
+                pos,
+                kind: PingKind::Danger,
+            }),
+        );
+        server.step();
+
+        let pinged = |updates: Vec<ServerMessage>| {
+            updates.into_iter().any(|m| {
+                matches!(m, ServerMessage::Ping(ping) if ping.pos == pos && ping.kind == PingKind::Danger)
+            })
+        };
+        assert!(pinged(server.drain_updates(a)));
+        assert!(pinged(server.drain_updates(b)));
+        assert!(!pinged(server.drain_updates(c)));
+    }
+
+    #[test]
+    fn markers_take_turns_with_colors_and_are_capped() {
+        let mut markers = Markers::new(WorldId(1));
+        let at = |x| Point { x, y: 0 };
+        assert_eq!(markers.add(at(0)), Some(0));
+        assert_eq!(markers.add(at(1)), Some(1));
+        assert_eq!(markers.add(at(0)), Some(0), "one marker per tile");
+        let colors: Vec<MarkerColor> = markers.iter().map(|m| m.color).collect();
+        assert_eq!(colors, [MarkerColor::Red, MarkerColor::Yellow]);
+        assert_eq!(
+            markers.iter().nth(1).map(|m| m.name.as_str()),
+            Some("Marker 2")
+        );
+
+        for x in 2..cache::MAX_MARKERS as i32 {
+            assert!(markers.add(at(x)).is_some());
+        }
+        assert_eq!(markers.add(at(-1)), None);
+        markers.remove(0);
+        assert_eq!(markers.len(), cache::MAX_MARKERS - 1);
+        assert_eq!(markers.iter().next().map(|m| m.pos), Some(at(1)));
+    }
+}
@@ -0,0 +1,299 @@
+//! Server plugins: commands added to a server without forking it.
+//!
+//! A [`ServerPlugin`] names its commands with a [`CommandSpec`] each: the
+//! arguments it takes and who may run it. Once [registered](Plugins::register)
+//! on a [`ServerState`], its commands run from the console like the
+//! built-in ones, and from the chat as `/<command> ...`. The
+//! [console](super::console) checks the caller's [`Permission`], parses the
+//! arguments against the spec, answering with the usage if they do not fit,
+//! and hands the plugin the parsed [`Args`].
+
+use super::ServerState;
+use crate::game::{EntityID, PlayerKey};
+
+use iroh::EndpointId;
+use std::fmt;
+use std::sync::Arc;
+
+/// Who may run a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Permission {
+    /// Every player.
+    Anyone,
+    /// Only the server's host and the admins it named.
+    Admin,
+}
+
+/// What an argument must look like.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgKind {
+    /// A whole number.
+    Int,
+    /// An entity ID.
+    Entity,
+    /// One word.
+    Word,
+    /// The rest of the line; only as the last argument.
+    Text,
+}
+
+/// One argument of a command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArgSpec {
+    pub name: &'static str,
+    pub kind: ArgKind,
+    /// Whether it may be left out; only trailing arguments may.
+    pub optional: bool,
+}
+
+impl ArgSpec {
+    pub fn required(name: &'static str, kind: ArgKind) -> Self {
+        Self {
+            name,
+            kind,
+            optional: false,
+        }
+    }
+
+    pub fn optional(name: &'static str, kind: ArgKind) -> Self {
+        Self {
+            name,
+            kind,
+            optional: true,
+        }
+    }
+}
+
+/// A command a plugin adds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandSpec {
+    /// The word it is run by; must not be a built-in command's.
+    pub name: &'static str,
+    /// What it does, for `help`.
+    pub about: &'static str,
+    pub args: Vec<ArgSpec>,
+    pub permission: Permission,
+}
+
+impl CommandSpec {
+    /// The command as `help` lists it, e.g. `bounty <target> [reward]`.
+    pub fn usage(&self) -> String {
+        let mut usage = self.name.to_owned();
+        for arg in &self.args {
+            if arg.optional {
+                usage.push_str(&format!(" [{}]", arg.name));
+            } else {
+                usage.push_str(&format!(" <{}>", arg.name));
+            }
+        }
+        usage
+    }
+
+    /// Parse the words after the command's name.
+    ///
+    /// # Errors
+    /// With the usage, if an argument is missing, malformed or extra.
+    pub fn parse(&self, words: &[&str]) -> Result<Args, String> {
+        let usage = || format!("Usage: {}", self.usage());
+        let mut values = Vec::new();
+        let mut rest = words;
+        for arg in &self.args {
+            let Some((word, after)) = rest.split_first() else {
+                if arg.optional {
+                    break;
+                }
+                return Err(usage());
+            };
+            let value = match arg.kind {
+                ArgKind::Int => ArgValue::Int(word.parse().map_err(|e| {
+                    format!(
+                        "`{word}` is not a number for <{}> ({e}); {}",
+                        arg.name,
+                        usage()
+                    )
+                })?),
+                ArgKind::Entity => ArgValue::Entity(EntityID(word.parse().map_err(|e| {
+                    format!(
+                        "`{word}` is not an entity id for <{}> ({e}); {}",
+                        arg.name,
+                        usage()
+                    )
+                })?)),
+                ArgKind::Word => ArgValue::Text((*word).to_owned()),
+                ArgKind::Text => {
+                    values.push((arg.name, ArgValue::Text(rest.join(" "))));
+                    rest = &[];
+                    break;
+                }
+            };
+            values.push((arg.name, value));
+            rest = after;
+        }
+        if !rest.is_empty() {
+            return Err(usage());
+        }
+        Ok(Args { values })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ArgValue {
+    Int(i64),
+    Entity(EntityID),
+    Text(String),
+}
+
+/// The arguments a command was run with, by name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Args {
+    values: Vec<(&'static str, ArgValue)>,
+}
+
+impl Args {
+    fn get(&self, name: &str) -> Option<&ArgValue> {
+        self.values
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, value)| value)
+    }
+
+    /// The [`ArgKind::Int`] argument `name`, if given.
+    pub fn int(&self, name: &str) -> Option<i64> {
+        match self.get(name)? {
+            ArgValue::Int(n) => Some(*n),
+            ArgValue::Entity(_) | ArgValue::Text(_) => None,
+        }
+    }
+
+    /// The [`ArgKind::Entity`] argument `name`, if given.
+    pub fn entity(&self, name: &str) -> Option<EntityID> {
+        match self.get(name)? {
+            ArgValue::Entity(eid) => Some(*eid),
+            ArgValue::Int(_) | ArgValue::Text(_) => None,
+        }
+    }
+
+    /// The [`ArgKind::Word`] or [`ArgKind::Text`] argument `name`, if
+    /// given.
+    pub fn text(&self, name: &str) -> Option<&str> {
+        match self.get(name)? {
+            ArgValue::Text(text) => Some(text),
+            ArgValue::Int(_) | ArgValue::Entity(_) => None,
+        }
+    }
+}
+
+/// Who runs a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Caller {
+    pub endpoint: EndpointId,
+    /// The player, if their client identified itself.
+    pub player: Option<PlayerKey>,
+    /// The character they control, if any.
+    pub entity: Option<EntityID>,
+    /// Whether they may run [`Permission::Admin`] commands.
+    pub admin: bool,
+}
+
+impl Caller {
+    pub fn may_run(&self, permission: Permission) -> bool {
+        match permission {
+            Permission::Anyone => true,
+            Permission::Admin => self.admin,
+        }
+    }
+}
+
+/// Adds commands to a server.
+pub trait ServerPlugin: Send + Sync {
+    /// Name of the plugin, for `help`.
+    fn name(&self) -> &str;
+
+    /// The commands it adds; asked once, on registering.
+    fn commands(&self) -> Vec<CommandSpec>;
+
+    /// Run `command`, one of [`commands`](Self::commands), for `caller`,
+    /// who may, with `args` parsed against its spec. Returns the output.
+    fn run(&self, state: &mut ServerState, caller: &Caller, command: &str, args: &Args) -> String;
+}
+
+/// The plugins registered on a server, and their commands.
+#[derive(Clone, Default)]
+pub struct Plugins {
+    plugins: Vec<Arc<dyn ServerPlugin>>,
+    /// Each command with the index of its plugin.
+    commands: Vec<(CommandSpec, usize)>,
+}
+
+impl fmt::Debug for Plugins {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.plugins.iter().map(|plugin| plugin.name()))
+            .finish()
+    }
+}
+
+impl Plugins {
+    /// Add `plugin`'s commands.
+    ///
+    /// # Errors
+    /// If one of them has the name of a built-in command or of another
+    /// plugin's; nothing is added then.
+    pub fn register(&mut self, plugin: impl ServerPlugin + 'static) -> Result<(), String> {
+        let commands = plugin.commands();
+        for (n, spec) in commands.iter().enumerate() {
+            let taken = super::console::is_builtin(spec.name)
+                || self.find(spec.name).is_some()
+                || commands.iter().take(n).any(|c| c.name == spec.name);
+            if taken {
+                return Err(format!(
+                    "{} cannot add `{}`: a command of that name exists",
+                    plugin.name(),
+                    spec.name
+                ));
+            }
+        }
+        let index = self.plugins.len();
+        self.plugins.push(Arc::new(plugin));
+        self.commands
+            .extend(commands.into_iter().map(|spec| (spec, index)));
+        Ok(())
+    }
+
+    /// The command `name` and the plugin running it.
+    pub fn find(&self, name: &str) -> Option<(&CommandSpec, Arc<dyn ServerPlugin>)> {
+        let (spec, index) = self.commands.iter().find(|(spec, _)| spec.name == name)?;
+        Some((spec, Arc::clone(self.plugins.get(*index)?)))
+    }
+
+    /// The commands `caller` may run, as `help` lists them.
+    pub fn help(&self, caller: &Caller) -> String {
+        let mut help = String::new();
+        for (spec, index) in &self.commands {
+            if !caller.may_run(spec.permission) {
+                continue;
+            }
+            let plugin = self.plugins.get(*index).map_or("", |p| p.name());
+            help.push_str(&format!(
+                "\n  {:<24}{} ({plugin})",
+                spec.usage(),
+                spec.about
+            ));
+        }
+        help
+    }
+}
+
+/// Run the plugin command `words` starts with, if it is one.
+pub fn dispatch(state: &mut ServerState, caller: &Caller, words: &[&str]) -> Option<String> {
+    let (name, args) = words.split_first()?;
+    let (spec, plugin) = state.plugins.find(name)?;
+    if !caller.may_run(spec.permission) {
+        return Some(format!("Only admins may run `{name}`"));
+    }
+    let parsed = match spec.parse(args) {
+        Ok(parsed) => parsed,
+        Err(usage) => return Some(usage),
+    };
+    Some(plugin.run(state, caller, name, &parsed))
+}
@@ -0,0 +1,164 @@
+//! Friends' presence: which friends are online, and where they play.
+//!
+//! Each client has a lasting identity, a [`SecretKey`] kept in its
+//! settings, and answers presence probes under [`PRESENCE_ALPN`] with its
+//! [`Presence`]. Friends are found by endpoint ID through iroh's discovery,
+//! wherever they are. Only the nodes on a player's own friends list are
+//! answered, so two players see each other once both added the other; a
+//! friend who is offline, or has not added us back, shows as offline after
+//! at most [`PROBE_TIMEOUT`].
+
+use super::{decode, recv_one_way, send_encoded};
+
+use bitcode::{Decode, Encode};
+use iroh::{
+    Endpoint, EndpointId, SecretKey,
+    endpoint::{Connection, VarInt},
+    protocol::{AcceptError, ProtocolHandler, Router},
+};
+use n0_error::StdResultExt as _;
+use rustc_hash::FxHashSet;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+/// ALPN of presence connections.
+pub const PRESENCE_ALPN: &[u8] = b"gamik/presence/0";
+
+/// Longest world name in a presence, in characters; longer ones are cut.
+pub const MAX_PRESENCE_NAME_LEN: usize = 64;
+
+/// How long [`probe`] waits for an answer before calling a friend offline.
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What a player is doing, as their friends see it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Encode, Decode)]
+pub enum Presence {
+    /// Online, but not in a world.
+    #[default]
+    InMenus,
+    /// Hosting `world`, which friends join at the endpoint ID `server`.
+    Hosting { world: String, server: String },
+    /// Playing in `world` on the server with endpoint ID `server`.
+    Playing { world: String, server: String },
+}
+
+impl Presence {
+    /// The server friends would join to play along, if any.
+    pub fn server(&self) -> Option<&str> {
+        match self {
+            Self::InMenus => None,
+            Self::Hosting { server, .. } | Self::Playing { server, .. } => Some(server),
+        }
+    }
+
+    /// The presence as the friends list shows it, e.g. "Hosting woods".
+    pub fn describe(&self) -> String {
+        match self {
+            Self::InMenus => "Online".to_owned(),
+            Self::Hosting { world, .. } => format!("Hosting {world}"),
+            Self::Playing { world, .. } => format!("Playing in {world}"),
+        }
+    }
+}
+
+/// A fresh identity for [`serve`].
+pub fn generate_key() -> [u8; 32] {
+    let mut key = [0; 32];
+    let (first, second) = key.split_at_mut(16);
+    first.copy_from_slice(&uuid::Uuid::new_v4().as_u128().to_le_bytes());
+    second.copy_from_slice(&uuid::Uuid::new_v4().as_u128().to_le_bytes());
+    key
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    presence: Presence,
+    friends: FxHashSet<EndpointId>,
+}
+
+/// The presence a client shows its friends, and who they are; cloned
+/// handles share them.
+#[derive(Debug, Clone, Default)]
+pub struct PresenceBoard {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl PresenceBoard {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Shared> {
+        self.shared.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn set(&self, presence: Presence) {
+        self.lock().presence = presence;
+    }
+
+    /// Answer only `friends` from now on.
+    pub fn set_friends(&self, friends: impl IntoIterator<Item = EndpointId>) {
+        self.lock().friends = friends.into_iter().collect();
+    }
+
+    /// What `asker` is told, or `None` if they are not a friend.
+    pub fn answer_to(&self, asker: EndpointId) -> Option<Presence> {
+        let shared = self.lock();
+        shared
+            .friends
+            .contains(&asker)
+            .then(|| shared.presence.clone())
+    }
+}
+
+/// Answers the presence probes of friends.
+#[derive(Debug, Clone)]
+pub struct PresenceServer {
+    board: PresenceBoard,
+}
+
+impl ProtocolHandler for PresenceServer {
+    async fn accept(&self, connection: Connection) -> std::result::Result<(), AcceptError> {
+        let Some(presence) = self.board.answer_to(connection.remote_id()) else {
+            connection.close(VarInt::from_u32(1), b"not a friend");
+            return Ok(());
+        };
+        if let Err(e) = send_encoded(&connection, &bitcode::encode(&presence)).await {
+            log::warn!("Error sending presence: {e}");
+        }
+        connection.closed().await;
+        Ok(())
+    }
+}
+
+/// Bind the endpoint with identity `key`, answering friends with what
+/// `board` holds. Probes are sent from it too, so friends know who asks.
+///
+/// # Errors
+/// If the endpoint cannot be bound.
+pub async fn serve(key: &[u8; 32], board: PresenceBoard) -> n0_error::Result<Router> {
+    let endpoint = Endpoint::builder()
+        .secret_key(SecretKey::from_bytes(key))
+        .bind()
+        .await?;
+    Ok(Router::builder(endpoint)
+        .accept(PRESENCE_ALPN, PresenceServer { board })
+        .spawn())
+}
+
+/// Ask `friend` what they are doing, from `endpoint`.
+///
+/// # Errors
+/// If the friend cannot be reached, does not answer within
+/// [`PROBE_TIMEOUT`], has not added us, or answers with something else
+/// than a presence.
+pub async fn probe(endpoint: &Endpoint, friend: EndpointId) -> Result<Presence, String> {
+    tokio::time::timeout(PROBE_TIMEOUT, probe_once(endpoint, friend))
+        .await
+        .map_err(|e| format!("no answer: {e}"))?
+        .map_err(|e| e.to_string())
+}
+
+async fn probe_once(endpoint: &Endpoint, friend: EndpointId) -> n0_error::Result<Presence> {
+    let conn = endpoint.connect(friend, PRESENCE_ALPN).await?;
+    let recv = conn.accept_uni().await.anyerr()?;
+    let bytes = recv_one_way(recv, decode::MAX_PRESENCE_SIZE).await?;
+    conn.close(VarInt::from_u32(0), b"done");
+    decode::decode_presence(&bytes).anyerr()
+}
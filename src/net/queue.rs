@@ -0,0 +1,79 @@
+//! Join queue: clients waiting for a slot on a full server.
+//!
+//! A server may cap the players in its world at once (see
+//! [`AccessRules::max_players`](super::AccessRules::max_players)). A client
+//! that syncs while it is full waits in the [`JoinQueue`] instead of being
+//! sent the world, is told its place with
+//! [`ServerMessage::Queued`](super::ServerMessage::Queued) whenever it
+//! changes, and is let in, first come first served, as players leave.
+
+use iroh::EndpointId;
+use std::collections::VecDeque;
+
+/// Most clients that may wait at once; past it, joining is refused.
+pub const MAX_QUEUE_LEN: usize = 64;
+
+/// A client waiting for a slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Waiting {
+    endpoint: EndpointId,
+    /// The world tick the client has cached, as it said when syncing.
+    since_tick: Option<u64>,
+}
+
+/// Clients waiting for a slot, in the order they came.
+#[derive(Debug, Default)]
+pub struct JoinQueue {
+    waiting: VecDeque<Waiting>,
+}
+
+impl JoinQueue {
+    pub fn len(&self) -> usize {
+        self.waiting.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.waiting.is_empty()
+    }
+
+    /// Place of `endpoint` in the queue, from 1 for the next in.
+    pub fn position(&self, endpoint: &EndpointId) -> Option<u32> {
+        let index = self.waiting.iter().position(|w| w.endpoint == *endpoint)?;
+        Some(u32::try_from(index + 1).unwrap_or(u32::MAX))
+    }
+
+    /// Put `endpoint` at the back, or keep its place if it is already
+    /// waiting. Returns its place, or `None` if the queue is full.
+    pub fn push(&mut self, endpoint: EndpointId, since_tick: Option<u64>) -> Option<u32> {
+        if let Some(waiting) = self.waiting.iter_mut().find(|w| w.endpoint == endpoint) {
+            waiting.since_tick = since_tick;
+        } else if self.waiting.len() < MAX_QUEUE_LEN {
+            self.waiting.push_back(Waiting {
+                endpoint,
+                since_tick,
+            });
+        }
+        self.position(&endpoint)
+    }
+
+    /// Take the next client in, with the tick it has cached.
+    pub fn pop(&mut self) -> Option<(EndpointId, Option<u64>)> {
+        let next = self.waiting.pop_front()?;
+        Some((next.endpoint, next.since_tick))
+    }
+
+    /// Stop `endpoint` waiting. Returns `false` if it was not.
+    pub fn remove(&mut self, endpoint: &EndpointId) -> bool {
+        let before = self.waiting.len();
+        self.waiting.retain(|w| w.endpoint != *endpoint);
+        self.waiting.len() != before
+    }
+
+    /// Every waiting client with its place, front first.
+    pub fn positions(&self) -> Vec<(EndpointId, u32)> {
+        (1..)
+            .zip(&self.waiting)
+            .map(|(position, w)| (w.endpoint, position))
+            .collect()
+    }
+}
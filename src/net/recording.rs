@@ -0,0 +1,344 @@
+//! Recordings of server sessions, watched later as replays.
+//!
+//! While recording, the server keeps every tick's [`WorldDelta`] together
+//! with the actions players sent that tick, plus a full copy of the entity
+//! map every [`KEYFRAME_INTERVAL`] ticks. Playback applies the recorded
+//! deltas rather than re-simulating the actions: spawns and console
+//! commands change the world outside [`game::apply`](crate::game::apply),
+//! so only the deltas reproduce it exactly. The actions are kept to show
+//! what players did; deterministic re-simulation is covered by the
+//! [`replay`](crate::game::replay) fixtures instead.
+//!
+//! Keyframes make seeking cheap: a [`Playback`] jumps to the closest
+//! keyframe before the target tick and applies at most
+//! [`KEYFRAME_INTERVAL`] deltas from there.
+//!
+//! Each tick also keeps the [`Rolled`] rolls that decided something in it,
+//! so [`Recording::audit`] can derive them again when someone claims a
+//! session's luck was not what the server says.
+//!
+//! Recordings are saved as `.recording` files in [`RECORDINGS_DIR`], and
+//! clients download them in parts of [`RECORDING_PART_SIZE`] bytes.
+
+use super::WorldDelta;
+use crate::game::rng::Rolled;
+use crate::game::{self, EntityID, EntityMap, GameAction, GameState, WorldId};
+use crate::profile;
+
+use bitcode::{Decode, Encode};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Ticks between two full copies of the world in a recording.
+pub const KEYFRAME_INTERVAL: u64 = 100;
+
+/// Where recordings are saved, on the server and on clients.
+pub const RECORDINGS_DIR: &str = "recordings";
+
+/// Largest part of a recording sent in one message.
+pub const RECORDING_PART_SIZE: usize = 1024 * 1024;
+
+/// Longest recording name, in characters.
+pub const MAX_RECORDING_NAME_LEN: usize = 64;
+
+/// One recorded tick.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct RecordedTick {
+    /// What changed; its tick is the tick it brings the world to.
+    pub delta: WorldDelta,
+    /// Actions players sent, applied during this tick.
+    pub actions: Vec<(EntityID, GameAction)>,
+    /// Rolls that decided something during this tick.
+    pub rolls: Vec<Rolled>,
+}
+
+/// A recorded stretch of a world's history.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Recording {
+    pub world_id: WorldId,
+    pub world_name: String,
+    /// Full entity maps by tick, oldest first; the first is where the
+    /// recording starts.
+    pub keyframes: Vec<(u64, EntityMap)>,
+    /// Every tick after the first keyframe, in order.
+    pub ticks: Vec<RecordedTick>,
+}
+
+impl Recording {
+    /// Start recording `state` from its current tick.
+    pub fn start(state: &GameState) -> Self {
+        Self {
+            world_id: state.world_id,
+            world_name: state.world_name.clone(),
+            keyframes: vec![(state.tick, state.entities().clone())],
+            ticks: Vec::new(),
+        }
+    }
+
+    /// Add a tick. `entities` is the world after `delta`, kept as a
+    /// keyframe when the last one is [`KEYFRAME_INTERVAL`] ticks old.
+    pub fn record(
+        &mut self,
+        delta: WorldDelta,
+        actions: Vec<(EntityID, GameAction)>,
+        rolls: Vec<Rolled>,
+        entities: &EntityMap,
+    ) {
+        let tick = delta.tick;
+        self.ticks.push(RecordedTick {
+            delta,
+            actions,
+            rolls,
+        });
+        if tick.saturating_sub(self.last_keyframe_tick()) >= KEYFRAME_INTERVAL {
+            self.keyframes.push((tick, entities.clone()));
+        }
+    }
+
+    fn last_keyframe_tick(&self) -> u64 {
+        self.keyframes.last().map_or(0, |(tick, _)| *tick)
+    }
+
+    /// Bytes the recording holds, including its keyframes.
+    pub fn memory_bytes(&self) -> usize {
+        self.keyframes
+            .iter()
+            .map(|(_, entities)| game::entity_map_bytes(entities))
+            .sum::<usize>()
+            + self
+                .ticks
+                .iter()
+                .map(|t| {
+                    t.delta.heap_bytes()
+                        + profile::vec_bytes(&t.actions)
+                        + profile::vec_bytes(&t.rolls)
+                })
+                .sum::<usize>()
+            + profile::vec_bytes(&self.ticks)
+    }
+
+    /// First tick of the recording.
+    pub fn start_tick(&self) -> u64 {
+        self.keyframes.first().map_or(0, |(tick, _)| *tick)
+    }
+
+    /// Last tick of the recording.
+    pub fn end_tick(&self) -> u64 {
+        self.ticks
+            .last()
+            .map_or_else(|| self.start_tick(), |t| t.delta.tick)
+    }
+
+    /// Ticks of every keyframe, for the timeline.
+    pub fn keyframe_ticks(&self) -> impl Iterator<Item = u64> + '_ {
+        self.keyframes.iter().map(|(tick, _)| *tick)
+    }
+
+    /// The recorded tick that brought the world to `tick`.
+    pub fn tick(&self, tick: u64) -> Option<&RecordedTick> {
+        let index = self.ticks.partition_point(|t| t.delta.tick < tick);
+        self.ticks.get(index).filter(|t| t.delta.tick == tick)
+    }
+
+    /// The recorded rolls that do not hold up, with the tick they were
+    /// recorded in: rolls that are not what their inputs give, or that
+    /// claim a tick other than one the recorded tick went through.
+    pub fn audit(&self) -> Vec<(u64, &Rolled)> {
+        let mut after = self.start_tick();
+        let mut unfair = Vec::new();
+        for recorded in &self.ticks {
+            let ticks = after..recorded.delta.tick;
+            unfair.extend(
+                recorded
+                    .rolls
+                    .iter()
+                    .filter(|rolled| !rolled.is_fair() || !ticks.contains(&rolled.tick))
+                    .map(|rolled| (recorded.delta.tick, rolled)),
+            );
+            after = recorded.delta.tick;
+        }
+        unfair
+    }
+
+    /// The world as of `tick`, clamped to the recorded range.
+    pub fn world_at(&self, tick: u64) -> EntityMap {
+        let keyframe = self.keyframes.partition_point(|(t, _)| *t <= tick);
+        let Some((from, entities)) = self.keyframes.get(keyframe.saturating_sub(1)) else {
+            return EntityMap::default();
+        };
+        let mut entities = entities.clone();
+        self.apply_between(&mut entities, *from, tick);
+        entities
+    }
+
+    /// Apply the deltas after tick `from` up to and including `to`.
+    fn apply_between(&self, entities: &mut EntityMap, from: u64, to: u64) {
+        let first = self.ticks.partition_point(|t| t.delta.tick <= from);
+        for recorded in self.ticks.iter().skip(first) {
+            if recorded.delta.tick > to {
+                break;
+            }
+            recorded.delta.apply_to(entities);
+        }
+    }
+
+    fn path(name: &str) -> PathBuf {
+        Path::new(RECORDINGS_DIR).join(format!("{name}.recording"))
+    }
+
+    /// Save the recording as `name` in [`RECORDINGS_DIR`].
+    ///
+    /// # Errors
+    /// If the name is bad or the file cannot be written.
+    pub fn save(&self, name: &str) -> io::Result<PathBuf> {
+        save_bytes(name, &bitcode::encode(self))
+    }
+
+    /// Load the recording saved as `name`.
+    ///
+    /// # Errors
+    /// If the name is bad or the file cannot be read or decoded.
+    pub fn load(name: &str) -> io::Result<Self> {
+        Self::decode(&read_bytes(name)?)
+    }
+
+    /// Decode a recording from the bytes of its file.
+    ///
+    /// # Errors
+    /// If the bytes are not a recording.
+    pub fn decode(bytes: &[u8]) -> io::Result<Self> {
+        bitcode::decode(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Returns `true` if `name` can name a recording file: short, and only
+/// letters, digits, `-` and `_`.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.chars().count() <= MAX_RECORDING_NAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+}
+
+fn checked_path(name: &str) -> io::Result<PathBuf> {
+    if is_valid_name(name) {
+        Ok(Recording::path(name))
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid recording name `{name}`"),
+        ))
+    }
+}
+
+/// Write the bytes of a recording file named `name`.
+///
+/// # Errors
+/// If the name is bad or the file cannot be written.
+pub fn save_bytes(name: &str, bytes: &[u8]) -> io::Result<PathBuf> {
+    let path = checked_path(name)?;
+    fs::create_dir_all(RECORDINGS_DIR)?;
+    fs::write(&path, bytes)?;
+    Ok(path)
+}
+
+/// Read the bytes of the recording file named `name`.
+///
+/// # Errors
+/// If the name is bad or the file cannot be read.
+pub fn read_bytes(name: &str) -> io::Result<Vec<u8>> {
+    fs::read(checked_path(name)?)
+}
+
+/// Part `index` of the recording file named `name`, with the number of
+/// parts it has.
+///
+/// # Errors
+/// If the name is bad, the file cannot be read, or it has no part
+/// `index`.
+pub fn read_part(name: &str, index: u32) -> Result<(u32, Vec<u8>), String> {
+    let bytes = read_bytes(name).map_err(|e| format!("cannot read recording `{name}`: {e}"))?;
+    let parts = bytes.len().div_ceil(RECORDING_PART_SIZE).max(1);
+    let parts =
+        u32::try_from(parts).map_err(|e| format!("recording `{name}` is too large: {e}"))?;
+    if index >= parts {
+        return Err(format!("recording `{name}` has no part {index}"));
+    }
+    let part = bytes
+        .chunks(RECORDING_PART_SIZE)
+        .nth(index as usize)
+        .unwrap_or_default();
+    Ok((parts, part.to_vec()))
+}
+
+/// Names of the saved recordings, sorted.
+pub fn list() -> Vec<String> {
+    let Ok(entries) = fs::read_dir(RECORDINGS_DIR) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().and_then(|s| s.to_str()) == Some("recording"))
+        .filter_map(|path| Some(path.file_stem()?.to_str()?.to_owned()))
+        .filter(|name| is_valid_name(name))
+        .collect();
+    names.sort();
+    names
+}
+
+/// A recording being watched, positioned at one tick.
+#[derive(Debug, Clone)]
+pub struct Playback {
+    recording: Recording,
+    tick: u64,
+    entities: EntityMap,
+}
+
+impl Playback {
+    /// Watch `recording` from its start.
+    pub fn new(recording: Recording) -> Self {
+        let tick = recording.start_tick();
+        let entities = recording.world_at(tick);
+        Self {
+            recording,
+            tick,
+            entities,
+        }
+    }
+
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    /// The tick being shown.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// The world at the tick being shown.
+    pub fn entities(&self) -> &EntityMap {
+        &self.entities
+    }
+
+    /// Show the world at `tick`, clamped to the recorded range. Moving
+    /// forward by less than a keyframe interval applies the deltas in
+    /// between; anything else starts over from a keyframe.
+    pub fn seek(&mut self, tick: u64) {
+        let tick = tick.clamp(self.recording.start_tick(), self.recording.end_tick());
+        if tick >= self.tick && tick - self.tick < KEYFRAME_INTERVAL {
+            self.recording
+                .apply_between(&mut self.entities, self.tick, tick);
+        } else {
+            self.entities = self.recording.world_at(tick);
+        }
+        self.tick = tick;
+    }
+
+    /// Returns `true` once the last recorded tick is shown.
+    pub fn is_at_end(&self) -> bool {
+        self.tick >= self.recording.end_tick()
+    }
+}
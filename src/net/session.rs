@@ -0,0 +1,221 @@
+//! Resumable client sessions.
+//!
+//! Every accepted connection is assigned a [`SessionToken`]. If the transport
+//! drops briefly, the client reconnects and presents the token: the server
+//! re-binds the session's entity to the new endpoint and replays the world
+//! deltas it missed, instead of sending a full snapshot and re-running the
+//! spawn flow.
+
+use crate::game::{EntityID, PlayerKey};
+use crate::profile;
+
+use super::view::ViewState;
+use bitcode::{Decode, Encode};
+use iroh::EndpointId;
+use rustc_hash::FxHashMap;
+
+use std::time::{Duration, Instant};
+
+/// How long a dropped session can still be resumed, paused world or not.
+pub const SESSION_RESUME: Duration = Duration::from_secs(30);
+
+/// Deltas kept for clients that resume: [`SESSION_RESUME`] at 50 ms per
+/// tick.
+pub const SESSION_RESUME_TICKS: u64 = 600;
+
+/// Opaque token identifying a session across reconnects. Whoever holds it
+/// can take the session over, so it is 128 bits from the OS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Encode, Decode)]
+pub struct SessionToken(pub u128);
+
+impl SessionToken {
+    /// Generate a fresh, unpredictable token.
+    ///
+    /// # Panics
+    /// If the OS has no random numbers to give.
+    pub fn generate() -> Self {
+        let mut bytes = [0; 16];
+        getrandom::fill(&mut bytes).expect("the OS gives random numbers");
+        Self(u128::from_le_bytes(bytes))
+    }
+}
+
+/// Server-side bookkeeping for one client session.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Session {
+    /// Endpoint currently attached to the session, if connected.
+    pub endpoint: Option<EndpointId>,
+    /// Entity controlled by the session, once spawned.
+    pub entity_id: Option<EntityID>,
+    /// The player, once the client identified itself.
+    pub player: Option<PlayerKey>,
+    /// Last tick whose world update was sent to the client.
+    pub last_sent_tick: Option<u64>,
+    /// When the connection dropped.
+    pub disconnected_at: Option<Instant>,
+    /// Set until the client says which world tick it already has, so the
+    /// first update can be a delta against its cache.
+    pub awaiting_sync: bool,
+    /// The view radius the client asked for, if it did; see
+    /// [`view`](super::view).
+    pub view_radius: Option<u32>,
+    /// What the client was sent while its view is limited, so the next
+    /// update can add what came into view and remove what left it. `None`
+    /// while it is sent the whole world, or needs a fresh snapshot.
+    pub in_view: Option<ViewState>,
+    /// Names of the players whose whispers are not delivered to this
+    /// client, as [`name_key`](super::whisper::name_key)s.
+    pub blocked: Vec<String>,
+    /// Whether the client asked for [`DebugData`](super::DebugData) with
+    /// its updates.
+    pub debug: bool,
+}
+
+/// All live and recently dropped sessions.
+#[derive(Debug, Default)]
+pub struct SessionTable {
+    sessions: FxHashMap<SessionToken, Session>,
+    by_endpoint: FxHashMap<EndpointId, SessionToken>,
+}
+
+impl SessionTable {
+    /// Estimated bytes held by the table.
+    pub fn memory_bytes(&self) -> usize {
+        profile::hash_map_bytes(&self.sessions) + profile::hash_map_bytes(&self.by_endpoint)
+    }
+
+    /// Start a new session for a freshly accepted connection.
+    pub fn open(&mut self, endpoint: EndpointId) -> SessionToken {
+        let token = SessionToken::generate();
+        self.sessions.insert(
+            token,
+            Session {
+                endpoint: Some(endpoint),
+                awaiting_sync: true,
+                ..Default::default()
+            },
+        );
+        self.by_endpoint.insert(endpoint, token);
+        token
+    }
+
+    /// Token of the session attached to `endpoint`.
+    pub fn token_of(&self, endpoint: &EndpointId) -> Option<SessionToken> {
+        self.by_endpoint.get(endpoint).copied()
+    }
+
+    /// The session attached to `endpoint`.
+    pub fn get(&self, endpoint: &EndpointId) -> Option<&Session> {
+        let token = self.by_endpoint.get(endpoint)?;
+        self.sessions.get(token)
+    }
+
+    /// The session attached to `endpoint`, mutably.
+    pub fn get_mut(&mut self, endpoint: &EndpointId) -> Option<&mut Session> {
+        let token = self.by_endpoint.get(endpoint)?;
+        self.sessions.get_mut(token)
+    }
+
+    /// Attach `endpoint` to the existing session `token`, discarding the
+    /// session it was given on connect.
+    ///
+    /// `last_tick` is the last world tick the client applied; the next update
+    /// will carry everything after it, or a full snapshot if it is `None`.
+    /// A session that never synced still has to, so resuming gets no one
+    /// past a password or the join queue. Returns `None` if the token is
+    /// unknown, the session has expired or another endpoint is still
+    /// attached to it; that one keeps it until its connection closes.
+    pub fn resume(
+        &mut self,
+        endpoint: EndpointId,
+        token: SessionToken,
+        last_tick: Option<u64>,
+        now: Instant,
+    ) -> Option<&Session> {
+        let session = self.sessions.get(&token)?;
+        if session
+            .disconnected_at
+            .is_some_and(|t| now.saturating_duration_since(t) > SESSION_RESUME)
+            || session.endpoint.is_some_and(|live| live != endpoint)
+        {
+            return None;
+        }
+
+        if let Some(fresh) = self.by_endpoint.insert(endpoint, token)
+            && fresh != token
+        {
+            self.sessions.remove(&fresh);
+        }
+
+        let session = self.sessions.get_mut(&token)?;
+        session.endpoint = Some(endpoint);
+        session.last_sent_tick = last_tick;
+        session.in_view = None;
+        session.disconnected_at = None;
+        Some(session)
+    }
+
+    /// Returns `true` if a session other than the one attached to
+    /// `endpoint`, connected or still resumable, controls `eid`.
+    pub fn controlled_elsewhere(&self, eid: EntityID, endpoint: &EndpointId) -> bool {
+        let own = self.by_endpoint.get(endpoint);
+        self.sessions
+            .iter()
+            .any(|(token, session)| session.entity_id == Some(eid) && Some(token) != own)
+    }
+
+    /// Connected sessions past their sync: the players taking up a slot.
+    pub fn admitted(&self) -> usize {
+        self.by_endpoint
+            .values()
+            .filter_map(|token| self.sessions.get(token))
+            .filter(|session| !session.awaiting_sync)
+            .count()
+    }
+
+    /// Endpoints currently attached to a session.
+    pub fn connected(&self) -> impl Iterator<Item = &EndpointId> {
+        self.by_endpoint.keys()
+    }
+
+    /// Detach `endpoint` from its session, keeping the session resumable.
+    pub fn disconnect(&mut self, endpoint: &EndpointId, now: Instant) {
+        let Some(token) = self.by_endpoint.remove(endpoint) else {
+            return;
+        };
+        if let Some(session) = self.sessions.get_mut(&token) {
+            session.endpoint = None;
+            session.disconnected_at = Some(now);
+        }
+    }
+
+    /// Send every session a full snapshot with its next update.
+    pub fn resend_snapshots(&mut self) {
+        self.sessions.values_mut().for_each(|session| {
+            session.last_sent_tick = None;
+            session.in_view = None;
+        });
+    }
+
+    /// Forget sessions that have been disconnected for too long.
+    pub fn expire(&mut self, now: Instant) {
+        self.sessions.retain(|_, session| {
+            session
+                .disconnected_at
+                .is_none_or(|t| now.saturating_duration_since(t) <= SESSION_RESUME)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn session_tokens_are_not_guessable() {
+        let tokens: BTreeSet<u128> = (0..64).map(|_| SessionToken::generate().0).collect();
+        assert_eq!(tokens.len(), 64);
+        assert!(tokens.iter().any(|t| *t > u128::from(u64::MAX)));
+    }
+}
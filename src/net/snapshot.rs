@@ -0,0 +1,457 @@
+//! World deltas sent from the server to clients.
+//!
+//! Instead of broadcasting the whole [`EntityMap`] every tick, the server
+//! records what changed per tick in a bounded [`DeltaLog`]. A client that is
+//! up to date receives just the latest [`WorldDelta`]; a client that missed
+//! some ticks (e.g. while reconnecting) receives the merged delta since the
+//! last tick it saw, as long as the log still reaches back that far.
+//!
+//! For longer gaps, such as a client rejoining with a cached world, the
+//! [`ChangeIndex`] remembers when each entity last changed so the server can
+//! still send only what changed since the client's tick.
+//!
+//! Full snapshots of big worlds can exceed the message size limit, so they
+//! are split into numbered parts ([`ChunkedSnapshot`]) announced by a
+//! manifest, and reassembled client-side by a [`SnapshotAssembler`].
+//!
+//! Clients with a limited view (see [`view`](super::view)) get entities at
+//! two levels of detail: whole, as above, when in sight, and as a
+//! [`Silhouette`] when only near enough to notice, in a
+//! [`SilhouetteDelta`] of their own.
+
+use crate::game::{Entity, EntityID, EntityMap, EntityType, Point};
+use crate::profile;
+
+use bitcode::{Decode, Encode};
+use rustc_hash::FxHashMap;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+/// Maximum payload of a single snapshot part.
+pub const SNAPSHOT_PART_SIZE: usize = 64 * 1024;
+
+/// Updates a client waits for a missing part before asking for it again.
+pub const PART_TIMEOUT_TICKS: u32 = 20;
+
+/// Number of removals a [`ChangeIndex`] remembers.
+pub const REMOVAL_HISTORY: usize = 4096;
+
+/// Size of the grid silhouettes' positions are rounded to.
+pub const SILHOUETTE_GRID: i32 = 2;
+
+/// Ticks between updates of silhouettes that stay silhouettes; entities
+/// turning into or out of one are sent straight away.
+pub const SILHOUETTE_INTERVAL: u64 = 5;
+
+/// Changes to the entity map that bring a client up to [`WorldDelta::tick`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Encode, Decode)]
+pub struct WorldDelta {
+    pub tick: u64,
+    /// Entities that were added or modified, sorted by ID.
+    pub changed: Vec<(EntityID, Entity)>,
+    /// Entities that were removed, sorted by ID.
+    pub removed: Vec<EntityID>,
+}
+
+impl WorldDelta {
+    /// Compute the delta that turns `old` into `new`.
+    pub fn between(tick: u64, old: &EntityMap, new: &EntityMap) -> Self {
+        let changed = new
+            .iter()
+            .filter(|(eid, entity)| old.get(eid) != Some(entity))
+            .map(|(eid, entity)| (*eid, entity.clone()))
+            .collect();
+        let removed = old
+            .keys()
+            .filter(|eid| !new.contains_key(eid))
+            .copied()
+            .collect();
+
+        Self {
+            tick,
+            changed,
+            removed,
+        }
+    }
+
+    /// Bytes the delta owns on the heap.
+    pub fn heap_bytes(&self) -> usize {
+        profile::vec_bytes(&self.changed)
+            + self
+                .changed
+                .iter()
+                .map(|(_, entity)| entity.heap_bytes())
+                .sum::<usize>()
+            + profile::vec_bytes(&self.removed)
+    }
+
+    /// Returns `true` if the delta carries no changes.
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.removed.is_empty()
+    }
+
+    /// IDs of every entity the delta adds, changes or removes.
+    pub fn touched(&self) -> impl Iterator<Item = EntityID> + '_ {
+        self.changed
+            .iter()
+            .map(|(eid, _)| *eid)
+            .chain(self.removed.iter().copied())
+    }
+
+    /// Apply the delta to a client-side copy of the entity map.
+    pub fn apply_to(&self, entities: &mut EntityMap) {
+        for eid in &self.removed {
+            entities.remove(eid);
+        }
+        for (eid, entity) in &self.changed {
+            entities.insert(*eid, entity.clone());
+        }
+    }
+}
+
+/// An entity out of sight but near enough to notice: what it is and
+/// roughly where, to the [`SILHOUETTE_GRID`].
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Silhouette {
+    pub entity_type: EntityType,
+    pub position: Point,
+}
+
+impl Silhouette {
+    pub fn of(entity: &Entity) -> Self {
+        let round = |v: i32| v.div_euclid(SILHOUETTE_GRID) * SILHOUETTE_GRID;
+        Self {
+            entity_type: entity.entity_type.clone(),
+            position: Point {
+                x: round(entity.position.x),
+                y: round(entity.position.y),
+            },
+        }
+    }
+}
+
+/// Changes to the silhouettes a client holds. A snapshot drops them all.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Encode, Decode)]
+pub struct SilhouetteDelta {
+    /// Silhouettes that appeared or moved, sorted by ID.
+    pub changed: Vec<(EntityID, Silhouette)>,
+    /// Silhouettes gone, out of the view or into sight, sorted by ID.
+    pub removed: Vec<EntityID>,
+}
+
+impl SilhouetteDelta {
+    /// Returns `true` if the delta carries no changes.
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.removed.is_empty()
+    }
+
+    /// Apply the delta to a client-side set of silhouettes.
+    pub fn apply_to(&self, silhouettes: &mut BTreeMap<EntityID, Silhouette>) {
+        for eid in &self.removed {
+            silhouettes.remove(eid);
+        }
+        for (eid, silhouette) in &self.changed {
+            silhouettes.insert(*eid, silhouette.clone());
+        }
+    }
+}
+
+/// Bounded history of per-tick [`WorldDelta`]s.
+#[derive(Debug, Clone)]
+pub struct DeltaLog {
+    deltas: VecDeque<WorldDelta>,
+    capacity: usize,
+}
+
+impl DeltaLog {
+    /// Create an empty log that keeps at most `capacity` ticks of history.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            deltas: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Record the delta for a new tick, dropping the oldest one if full.
+    pub fn push(&mut self, delta: WorldDelta) {
+        if self.deltas.len() == self.capacity {
+            self.deltas.pop_front();
+        }
+        self.deltas.push_back(delta);
+    }
+
+    /// Estimated bytes held by the log.
+    pub fn memory_bytes(&self) -> usize {
+        profile::deque_bytes(&self.deltas)
+            + self
+                .deltas
+                .iter()
+                .map(WorldDelta::heap_bytes)
+                .sum::<usize>()
+    }
+
+    /// Tick of the most recent delta, if any.
+    pub fn latest_tick(&self) -> Option<u64> {
+        self.deltas.back().map(|d| d.tick)
+    }
+
+    /// Merge every delta after tick `since` into one.
+    ///
+    /// Returns `None` if the log no longer reaches back to `since`, in which
+    /// case the caller has to fall back to a full snapshot.
+    pub fn since(&self, since: u64) -> Option<WorldDelta> {
+        let latest = self.latest_tick()?;
+        if since >= latest {
+            return Some(WorldDelta {
+                tick: latest,
+                ..Default::default()
+            });
+        }
+        let oldest = self.deltas.front().map(|d| d.tick)?;
+        if oldest > since + 1 {
+            return None;
+        }
+
+        let mut changed = BTreeMap::new();
+        let mut removed = BTreeSet::new();
+        for delta in self.deltas.iter().filter(|d| d.tick > since) {
+            for eid in &delta.removed {
+                changed.remove(eid);
+                removed.insert(*eid);
+            }
+            for (eid, entity) in &delta.changed {
+                removed.remove(eid);
+                changed.insert(*eid, entity.clone());
+            }
+        }
+
+        Some(WorldDelta {
+            tick: latest,
+            changed: changed.into_iter().collect(),
+            removed: removed.into_iter().collect(),
+        })
+    }
+}
+
+/// Tick at which every live entity last changed, plus recent removals.
+///
+/// Answers "what changed since tick T" for any T back to its horizon: the
+/// tick the server started at, or the oldest removal it had to forget.
+#[derive(Debug, Clone)]
+pub struct ChangeIndex {
+    changed_at: FxHashMap<EntityID, u64>,
+    removed: VecDeque<(u64, EntityID)>,
+    horizon: u64,
+}
+
+impl ChangeIndex {
+    /// Create an index that can answer queries from `horizon` onwards.
+    pub fn new(horizon: u64) -> Self {
+        Self {
+            changed_at: FxHashMap::default(),
+            removed: VecDeque::new(),
+            horizon,
+        }
+    }
+
+    /// Estimated bytes held by the index.
+    pub fn memory_bytes(&self) -> usize {
+        profile::hash_map_bytes(&self.changed_at) + profile::deque_bytes(&self.removed)
+    }
+
+    /// Record the changes of one tick.
+    pub fn record(&mut self, delta: &WorldDelta) {
+        for (eid, _) in &delta.changed {
+            self.changed_at.insert(*eid, delta.tick);
+        }
+        for eid in &delta.removed {
+            self.changed_at.remove(eid);
+            self.removed.push_back((delta.tick, *eid));
+        }
+        while self.removed.len() > REMOVAL_HISTORY {
+            if let Some((tick, _)) = self.removed.pop_front() {
+                self.horizon = self.horizon.max(tick);
+            }
+        }
+    }
+
+    /// Everything that changed in `entities` after tick `since`, up to `now`.
+    ///
+    /// Returns `None` if `since` is older than the horizon or newer than
+    /// `now` (a cache from another run of the world).
+    pub fn since(&self, since: u64, entities: &EntityMap, now: u64) -> Option<WorldDelta> {
+        if since < self.horizon || since > now {
+            return None;
+        }
+
+        let changed = entities
+            .iter()
+            .filter(|(eid, _)| self.changed_at.get(eid).is_some_and(|t| *t > since))
+            .map(|(eid, entity)| (*eid, entity.clone()))
+            .collect();
+
+        let removed: BTreeSet<EntityID> = self
+            .removed
+            .iter()
+            .filter(|(tick, _)| *tick > since)
+            .map(|(_, eid)| *eid)
+            .collect();
+
+        Some(WorldDelta {
+            tick: now,
+            changed,
+            removed: removed.into_iter().collect(),
+        })
+    }
+}
+
+/// A full snapshot, encoded and split into parts of at most
+/// [`SNAPSHOT_PART_SIZE`] bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkedSnapshot {
+    pub tick: u64,
+    pub parts: Vec<Vec<u8>>,
+}
+
+impl ChunkedSnapshot {
+    /// Encode `entities` and split the bytes into parts.
+    pub fn split(tick: u64, entities: &EntityMap) -> Self {
+        let bytes = bitcode::encode(entities);
+        Self {
+            tick,
+            parts: bytes
+                .chunks(SNAPSHOT_PART_SIZE)
+                .map(<[u8]>::to_vec)
+                .collect(),
+        }
+    }
+
+    /// Total encoded size in bytes.
+    pub fn total_len(&self) -> usize {
+        self.parts.iter().map(Vec::len).sum()
+    }
+
+    /// Bytes held by the parts.
+    pub fn memory_bytes(&self) -> usize {
+        profile::vec_bytes(&self.parts) + self.parts.iter().map(profile::vec_bytes).sum::<usize>()
+    }
+}
+
+/// Client-side reassembly of a [`ChunkedSnapshot`].
+///
+/// Deltas that arrive while parts are still missing are held back and
+/// applied on top of the snapshot once it is complete.
+#[derive(Debug, Clone)]
+pub struct SnapshotAssembler {
+    tick: u64,
+    parts: Vec<Option<Vec<u8>>>,
+    received: usize,
+    deferred: Vec<WorldDelta>,
+    updates_waited: u32,
+}
+
+impl SnapshotAssembler {
+    /// Start assembling the snapshot announced by a manifest.
+    pub fn new(tick: u64, part_count: u32) -> Self {
+        Self {
+            tick,
+            parts: vec![None; part_count as usize],
+            received: 0,
+            deferred: Vec::new(),
+            updates_waited: 0,
+        }
+    }
+
+    /// Tick of the snapshot being assembled.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Estimated bytes held by received parts and deferred deltas.
+    pub fn memory_bytes(&self) -> usize {
+        profile::vec_bytes(&self.parts)
+            + self
+                .parts
+                .iter()
+                .flatten()
+                .map(profile::vec_bytes)
+                .sum::<usize>()
+            + profile::vec_bytes(&self.deferred)
+            + self
+                .deferred
+                .iter()
+                .map(WorldDelta::heap_bytes)
+                .sum::<usize>()
+    }
+
+    /// Fraction of parts received so far, for a loading bar.
+    pub fn progress(&self) -> f32 {
+        if self.parts.is_empty() {
+            1.0
+        } else {
+            self.received as f32 / self.parts.len() as f32
+        }
+    }
+
+    /// Store a received part. Parts for other snapshots are ignored.
+    pub fn insert(&mut self, tick: u64, index: u32, bytes: Vec<u8>) {
+        if tick != self.tick {
+            return;
+        }
+        if let Some(slot) = self.parts.get_mut(index as usize)
+            && slot.is_none()
+        {
+            *slot = Some(bytes);
+            self.received += 1;
+            self.updates_waited = 0;
+        }
+    }
+
+    /// Hold back a delta until the snapshot is complete.
+    ///
+    /// Returns the indices of missing parts once the client has waited
+    /// [`PART_TIMEOUT_TICKS`] updates without progress, so they can be
+    /// requested again.
+    pub fn defer(&mut self, delta: WorldDelta) -> Option<Vec<u32>> {
+        self.deferred.push(delta);
+        self.updates_waited += 1;
+        if self.updates_waited < PART_TIMEOUT_TICKS {
+            return None;
+        }
+        self.updates_waited = 0;
+        Some(self.missing())
+    }
+
+    /// Indices of the parts not received yet.
+    pub fn missing(&self) -> Vec<u32> {
+        (0u32..)
+            .zip(&self.parts)
+            .filter(|(_, part)| part.is_none())
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Decode the snapshot once every part has arrived, with deferred deltas
+    /// applied on top.
+    ///
+    /// Returns `None` while parts are missing. If the reassembled bytes fail
+    /// to decode, every part is discarded so the next timeout asks for all
+    /// of them again.
+    pub fn finish(&mut self) -> Option<(u64, EntityMap)> {
+        if self.received < self.parts.len() {
+            return None;
+        }
+        let bytes: Vec<u8> = self.parts.iter().flatten().flatten().copied().collect();
+        let Ok(mut entities) = bitcode::decode::<EntityMap>(&bytes) else {
+            self.parts.fill(None);
+            self.received = 0;
+            return None;
+        };
+
+        let mut tick = self.tick;
+        for delta in self.deferred.drain(..).filter(|d| d.tick > self.tick) {
+            delta.apply_to(&mut entities);
+            tick = delta.tick;
+        }
+        Some((tick, entities))
+    }
+}
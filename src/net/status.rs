@@ -0,0 +1,108 @@
+//! Status pings for the server browser.
+//!
+//! A client connects under [`STATUS_ALPN`]; the server answers with a
+//! [`ServerStatus`] on a unidirectional stream and waits for the client to
+//! hang up. No session is opened, so probing a server costs it one lock of
+//! the [`ServerState`] and nothing more.
+
+use super::{ServerState, decode, recv_one_way, send_encoded};
+
+use bitcode::{Decode, Encode};
+use iroh::{
+    Endpoint, EndpointAddr,
+    endpoint::{Connection, VarInt},
+    protocol::{AcceptError, ProtocolHandler},
+};
+use n0_error::StdResultExt as _;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// ALPN of status connections, next to the game's own.
+pub const STATUS_ALPN: &[u8] = b"gamik/status/0";
+
+/// Longest world name in a status, in characters; longer ones are cut.
+pub const MAX_STATUS_NAME_LEN: usize = 64;
+
+/// How long [`probe`] waits for an answer before calling a server offline.
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What a server tells the browser about itself.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct ServerStatus {
+    pub world_name: String,
+    /// Players in the world right now.
+    pub players: u32,
+    /// The player cap, if any.
+    pub max_players: Option<u32>,
+    /// Clients waiting for a slot.
+    pub queued: u32,
+    pub tick: u64,
+}
+
+impl ServerStatus {
+    /// The status of `state`.
+    pub fn of(state: &ServerState) -> Self {
+        Self {
+            world_name: state
+                .game
+                .world_name
+                .chars()
+                .take(MAX_STATUS_NAME_LEN)
+                .collect(),
+            players: u32::try_from(state.endpoints.len()).unwrap_or(u32::MAX),
+            max_players: state.access.rules.max_players,
+            queued: u32::try_from(state.queue.len()).unwrap_or(u32::MAX),
+            tick: state.game.tick,
+        }
+    }
+}
+
+/// Answers status pings for the server sharing `state`.
+#[derive(Debug, Clone)]
+pub struct StatusServer {
+    state: Arc<Mutex<ServerState>>,
+}
+
+impl StatusServer {
+    pub const fn new(state: Arc<Mutex<ServerState>>) -> Self {
+        Self { state }
+    }
+}
+
+impl ProtocolHandler for StatusServer {
+    async fn accept(&self, connection: Connection) -> std::result::Result<(), AcceptError> {
+        let status = ServerStatus::of(&*self.state.lock().await);
+        if let Err(e) = send_encoded(&connection, &bitcode::encode(&status)).await {
+            log::warn!("Error sending status: {e}");
+        }
+        connection.closed().await;
+        Ok(())
+    }
+}
+
+/// Ask the server at `addr` how it is doing, returning its status and the
+/// round trip time.
+///
+/// # Errors
+/// If the server cannot be reached, does not answer within
+/// [`PROBE_TIMEOUT`], or answers with something else than a status.
+pub async fn probe(addr: impl Into<EndpointAddr>) -> Result<(ServerStatus, Duration), String> {
+    let addr = addr.into();
+    tokio::time::timeout(PROBE_TIMEOUT, probe_once(addr))
+        .await
+        .map_err(|e| format!("no answer: {e}"))?
+        .map_err(|e| e.to_string())
+}
+
+async fn probe_once(addr: EndpointAddr) -> n0_error::Result<(ServerStatus, Duration)> {
+    let endpoint = Endpoint::bind().await?;
+    let start = Instant::now();
+    let conn = endpoint.connect(addr, STATUS_ALPN).await?;
+    let recv = conn.accept_uni().await.anyerr()?;
+    let bytes = recv_one_way(recv, decode::MAX_STATUS_SIZE).await?;
+    let rtt = start.elapsed();
+    conn.close(VarInt::from_u32(0), b"done");
+    let status = decode::decode_server_status(&bytes).anyerr()?;
+    Ok((status, rtt))
+}
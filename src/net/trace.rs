@@ -0,0 +1,192 @@
+//! Entity traces: what lately happened to each entity, for debugging.
+//!
+//! While enabled from the console, the server keeps the last few actions,
+//! events and moves concerning each entity, so `trace <id>` can answer "why
+//! did this move?" without trawling a recording. Moves are read off each
+//! tick's [`WorldDelta`], so they show up whatever caused them, next to the
+//! action or event that did. Traces of entities that are gone are kept
+//! too, to answer "why did this die?"; past [`MAX_TRACED_ENTITIES`], no new
+//! entities are traced.
+
+use super::WorldDelta;
+use crate::game::{EntityID, EntityMap, GameAction, GameEvent, Point};
+use crate::profile;
+
+use rustc_hash::FxHashMap;
+use std::collections::VecDeque;
+use std::fmt;
+
+/// Entries kept per entity when no length is given.
+pub const DEFAULT_TRACE_LEN: usize = 32;
+
+/// Most entries kept per entity.
+pub const MAX_TRACE_LEN: usize = 1024;
+
+/// Most entities traced at once.
+pub const MAX_TRACED_ENTITIES: usize = 65_536;
+
+/// One thing that happened to an entity.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+    /// The tick it led to.
+    pub tick: u64,
+    pub what: String,
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.tick, self.what)
+    }
+}
+
+/// The recent entries of every traced entity, oldest first.
+#[derive(Debug, Clone, Default)]
+pub struct EntityTrace {
+    len: usize,
+    entries: FxHashMap<EntityID, VecDeque<TraceEntry>>,
+}
+
+impl EntityTrace {
+    /// Keep the last `len` entries of each entity, up to [`MAX_TRACE_LEN`].
+    pub fn new(len: usize) -> Self {
+        Self {
+            len: len.clamp(1, MAX_TRACE_LEN),
+            entries: FxHashMap::default(),
+        }
+    }
+
+    /// Entries kept per entity.
+    pub const fn capacity(&self) -> usize {
+        self.len
+    }
+
+    /// Entities with entries.
+    pub fn entity_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Estimated bytes held by the trace.
+    pub fn memory_bytes(&self) -> usize {
+        profile::hash_map_bytes(&self.entries)
+            + self
+                .entries
+                .values()
+                .flatten()
+                .map(|entry| entry.what.capacity())
+                .sum::<usize>()
+            + self
+                .entries
+                .values()
+                .map(profile::deque_bytes)
+                .sum::<usize>()
+    }
+
+    /// The entries of `eid`, oldest first.
+    pub fn of(&self, eid: EntityID) -> impl Iterator<Item = &TraceEntry> {
+        self.entries.get(&eid).into_iter().flatten()
+    }
+
+    fn push(&mut self, eid: EntityID, tick: u64, what: String) {
+        if self.entries.len() >= MAX_TRACED_ENTITIES && !self.entries.contains_key(&eid) {
+            return;
+        }
+        let entries = self.entries.entry(eid).or_default();
+        if entries.len() >= self.len {
+            entries.pop_front();
+        }
+        entries.push_back(TraceEntry { tick, what });
+    }
+
+    /// Note the actions applied in the tick leading to `tick`.
+    pub fn actions(&mut self, tick: u64, actions: &[(EntityID, GameAction)]) {
+        for (eid, action) in actions {
+            self.push(*eid, tick, format!("did {action:?}"));
+        }
+    }
+
+    /// Note `events`, from the tick leading to `tick`, on every entity they
+    /// concern.
+    pub fn events(&mut self, tick: u64, events: &[GameEvent]) {
+        for event in events {
+            let what = match event {
+                GameEvent::Exploded(explosion) => {
+                    format!("caught in an explosion at {}", at(explosion.origin))
+                }
+                event => format!("{event:?}"),
+            };
+            for eid in concerned(event) {
+                self.push(eid, tick, what.clone());
+            }
+        }
+    }
+
+    /// Note the moves, arrivals and removals in `delta`, against the
+    /// entities as they were `before` it.
+    pub fn changes(&mut self, delta: &WorldDelta, before: &EntityMap) {
+        for (eid, entity) in &delta.changed {
+            let what = match before.get(eid) {
+                None => format!("appeared at {}", at(entity.position)),
+                Some(old) if old.position != entity.position => {
+                    format!("moved {} → {}", at(old.position), at(entity.position))
+                }
+                Some(_) => continue,
+            };
+            self.push(*eid, delta.tick, what);
+        }
+        for eid in &delta.removed {
+            self.push(*eid, delta.tick, "removed".to_owned());
+        }
+    }
+}
+
+fn at(point: Point) -> String {
+    format!("({}, {})", point.x, point.y)
+}
+
+/// Entities `event` is about. Intent progress is left out, being reported
+/// every few ticks with nothing new in it.
+fn concerned(event: &GameEvent) -> Vec<EntityID> {
+    match event {
+        GameEvent::EntityMoved { entity_id }
+        | GameEvent::PlayerSpawned { entity_id }
+        | GameEvent::SpawnAsRequested { entity_id }
+        | GameEvent::DialogueChanged { entity_id }
+        | GameEvent::Traded { entity_id }
+        | GameEvent::TradeRejected { entity_id, .. }
+        | GameEvent::Damaged { entity_id, .. }
+        | GameEvent::Died { entity_id, .. }
+        | GameEvent::ItemBroke { entity_id, .. }
+        | GameEvent::Repaired { entity_id, .. }
+        | GameEvent::RepairRejected { entity_id, .. }
+        | GameEvent::StacksChanged { entity_id }
+        | GameEvent::StackRejected { entity_id, .. }
+        | GameEvent::IntentEnded { entity_id, .. }
+        | GameEvent::PathBlocked { entity_id, .. }
+        | GameEvent::WaitEnded { entity_id, .. } => vec![*entity_id],
+        GameEvent::Attacked { entity_id, target } => vec![*entity_id, *target],
+        GameEvent::Pushed {
+            entity_id,
+            obstacle,
+            ..
+        } => std::iter::once(*entity_id).chain(*obstacle).collect(),
+        GameEvent::Exploded(explosion) => {
+            let mut hit: Vec<EntityID> = explosion
+                .damaged
+                .iter()
+                .chain(&explosion.destroyed)
+                .copied()
+                .collect();
+            hit.sort_unstable();
+            hit.dedup();
+            hit
+        }
+        GameEvent::Pinged(ping) => vec![ping.from],
+        GameEvent::SaveRequested
+        | GameEvent::ActionProgress { .. }
+        | GameEvent::Announced(_)
+        | GameEvent::ChunkLoaded(_)
+        | GameEvent::ChunkUnloaded(_)
+        // Wandering creatures roll every tick; their moves say enough.
+        | GameEvent::Rolled(_) => Vec::new(),
+    }
+}
@@ -0,0 +1,196 @@
+//! View distance: how much of the world around its character a client is
+//! sent.
+//!
+//! By default a client is sent the whole world and sees [`VIEW_RADIUS`]
+//! tiles. A client may instead ask, with
+//! [`ClientMessage::ViewDistance`](super::ClientMessage::ViewDistance), for
+//! a radius of its own, which the server grants within its [`ViewLimits`].
+//! Once it controls a character, such a client is only sent the entities
+//! in a [`ViewWindow`] around it: its radius, widened by running events and
+//! the [`AWARENESS_MARGIN`]. Entities coming into the window are sent
+//! whole, and those leaving it are removed, so a client on a slow link gets
+//! small updates while one on a LAN can see further. Of the entities in the
+//! window but out of sight, only a [`Silhouette`] is sent, seldom.
+
+use super::WorldDelta;
+use super::snapshot::{SILHOUETTE_INTERVAL, Silhouette, SilhouetteDelta};
+use crate::game::fov::{self, AWARENESS_MARGIN, FovMask, VIEW_RADIUS};
+use crate::game::{Entity, EntityID, EntityMap, GameState, Point, math, world_events};
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Smallest view radius a server may grant.
+pub const MIN_VIEW_RADIUS: u32 = 4;
+
+/// Largest view radius a server may grant.
+pub const MAX_VIEW_RADIUS: u32 = 32;
+
+/// The view radii a server grants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ViewLimits {
+    pub min: u32,
+    pub max: u32,
+}
+
+impl Default for ViewLimits {
+    fn default() -> Self {
+        Self {
+            min: MIN_VIEW_RADIUS,
+            max: MAX_VIEW_RADIUS,
+        }
+    }
+}
+
+impl ViewLimits {
+    /// Limits from `min` to `max`, kept within [`MIN_VIEW_RADIUS`] and
+    /// [`MAX_VIEW_RADIUS`]. Returns `None` if `min` is above `max`.
+    pub fn new(min: u32, max: u32) -> Option<Self> {
+        let min = min.clamp(MIN_VIEW_RADIUS, MAX_VIEW_RADIUS);
+        let max = max.clamp(MIN_VIEW_RADIUS, MAX_VIEW_RADIUS);
+        (min <= max).then_some(Self { min, max })
+    }
+
+    /// The radius granted to a client asking for `requested`.
+    pub fn grant(&self, requested: u32) -> u32 {
+        requested.clamp(self.min, self.max)
+    }
+}
+
+/// How far a player granted `radius` sees while the events `active` are
+/// running; [`world_events::view_radius`] with `radius` in place of
+/// [`VIEW_RADIUS`].
+pub fn sight<'a>(radius: u32, active: impl IntoIterator<Item = &'a str>) -> i32 {
+    let base = i32::try_from(radius).unwrap_or(i32::MAX);
+    base.saturating_add(world_events::view_radius(active) - VIEW_RADIUS)
+}
+
+/// What a client with a limited view holds: the entities it was sent
+/// whole, and the silhouettes it was sent of the rest.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ViewState {
+    pub whole: BTreeSet<EntityID>,
+    pub silhouettes: BTreeMap<EntityID, Silhouette>,
+}
+
+/// The square of the world a client with a limited view is sent, with what
+/// its character can see in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ViewWindow {
+    origin: Point,
+    reach: i32,
+    sight: FovMask,
+}
+
+impl ViewWindow {
+    /// The window of a client granted `radius` and controlling `eid`, or
+    /// `None` if `eid` is gone. Sight blockers are looked up in the game's
+    /// index, which must be synced, and in the terrain.
+    pub fn around(game: &GameState, eid: EntityID, radius: u32) -> Option<Self> {
+        let origin = game.entities().get(&eid)?.position;
+        let active = game.world_events.iter().map(|e| e.id.as_str());
+        let radius = sight(radius, active);
+        let reach = radius.saturating_add(AWARENESS_MARGIN);
+        Some(Self {
+            origin,
+            reach,
+            sight: FovMask::compute(origin, radius, |p| fov::blocks_sight(game, p)),
+        })
+    }
+
+    pub fn contains(&self, point: Point) -> bool {
+        math::within_square(self.origin, point, self.reach)
+    }
+
+    /// Returns `true` if `entity` is sent whole: it is in sight, or is
+    /// terrain or blocks sight, which the client's own view is cast from.
+    fn in_detail(&self, entity: &Entity) -> bool {
+        self.sight.contains(entity.position)
+            || entity.entity_type.is_terrain()
+            || entity.entity_type.blocks_sight()
+    }
+
+    /// What the client should hold of `entities`.
+    fn state(&self, entities: &EntityMap) -> ViewState {
+        let mut state = ViewState::default();
+        for (eid, entity) in entities.iter().filter(|(_, e)| self.contains(e.position)) {
+            if self.in_detail(entity) {
+                state.whole.insert(*eid);
+            } else {
+                state.silhouettes.insert(*eid, Silhouette::of(entity));
+            }
+        }
+        state
+    }
+
+    /// A snapshot of the window: the entities sent whole, and every
+    /// silhouette. Returns what the client then holds too.
+    pub fn snapshot(&self, entities: &EntityMap) -> (EntityMap, SilhouetteDelta, ViewState) {
+        let state = self.state(entities);
+        let whole = state
+            .whole
+            .iter()
+            .filter_map(|eid| Some((*eid, entities.get(eid)?.clone())))
+            .collect();
+        let silhouettes = SilhouetteDelta {
+            changed: state.silhouettes.clone().into_iter().collect(),
+            removed: Vec::new(),
+        };
+        (whole, silhouettes, state)
+    }
+
+    /// Narrow `delta`, the world's changes since a client was last sent
+    /// what it holds in `sent`, to the window: what changed in sight,
+    /// whatever came into sight whole, and removals of whatever left it;
+    /// and likewise for silhouettes, which are only moved every
+    /// [`SILHOUETTE_INTERVAL`] ticks. `sent` becomes what the client holds
+    /// after.
+    pub fn narrow(
+        &self,
+        delta: &WorldDelta,
+        entities: &EntityMap,
+        sent: &mut ViewState,
+    ) -> (WorldDelta, SilhouetteDelta) {
+        let mut now = self.state(entities);
+        let changed = now
+            .whole
+            .iter()
+            .filter_map(|eid| {
+                if sent.whole.contains(eid) {
+                    let index = delta
+                        .changed
+                        .binary_search_by_key(eid, |(id, _)| *id)
+                        .ok()?;
+                    delta.changed.get(index).cloned()
+                } else {
+                    Some((*eid, entities.get(eid)?.clone()))
+                }
+            })
+            .collect();
+        let removed = sent.whole.difference(&now.whole).copied().collect();
+
+        let refresh = delta.tick % SILHOUETTE_INTERVAL == 0;
+        let mut silhouettes = SilhouetteDelta::default();
+        for (eid, silhouette) in &mut now.silhouettes {
+            match sent.silhouettes.get(eid) {
+                Some(old) if !refresh => silhouette.clone_from(old),
+                Some(old) if old == silhouette => {}
+                _ => silhouettes.changed.push((*eid, silhouette.clone())),
+            }
+        }
+        silhouettes.removed = sent
+            .silhouettes
+            .keys()
+            .filter(|eid| !now.silhouettes.contains_key(eid))
+            .copied()
+            .collect();
+        *sent = now;
+        (
+            WorldDelta {
+                tick: delta.tick,
+                changed,
+                removed,
+            },
+            silhouettes,
+        )
+    }
+}
@@ -0,0 +1,166 @@
+//! Private messages between players.
+//!
+//! Typing `/msg <player> <text>` in the chat line sends a
+//! [`ClientMessage::Whisper`](super::ClientMessage::Whisper). The server
+//! hands it only to the connections controlling a character of that name,
+//! over their own encrypted connection, and echoes it back to the sender;
+//! unlike chat it is neither broadcast nor written to the world's log.
+//!
+//! Each player tells the server whose whispers to drop with
+//! [`ClientMessage::Block`](super::ClientMessage::Block), from the list kept
+//! in their settings (`/block` and `/unblock`). A sender past
+//! [`MAX_WHISPERS`] in [`WHISPER_WINDOW`] is told to slow down.
+
+use bitcode::{Decode, Encode};
+use iroh::EndpointId;
+use rustc_hash::FxHashMap;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Most whispers a client may send within [`WHISPER_WINDOW`].
+pub const MAX_WHISPERS: usize = 5;
+
+/// Time over which [`MAX_WHISPERS`] is counted.
+pub const WHISPER_WINDOW: Duration = Duration::from_secs(5);
+
+/// Most players a client may block.
+pub const MAX_BLOCKED: usize = 64;
+
+/// A private message, as delivered to its recipient and echoed to its
+/// sender.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Whisper {
+    /// Tick the message was sent in.
+    pub tick: u64,
+    /// Name of the sender's character.
+    pub from: String,
+    /// Name of the recipient's character.
+    pub to: String,
+    pub text: String,
+}
+
+/// What a line typed into the chat asks for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChatCommand {
+    /// Say it to everyone.
+    Say(String),
+    /// `/msg <player> <text>`.
+    Whisper { to: String, text: String },
+    /// `/block <player>`.
+    Block(String),
+    /// `/unblock <player>`.
+    Unblock(String),
+    /// Any other `/<command> ...`, run as a console command, e.g. one a
+    /// [plugin](super::plugin) added.
+    Server(String),
+}
+
+/// Parse a line typed into the chat. Player names with spaces are quoted,
+/// e.g. `/msg "Old Tom" hello`.
+///
+/// # Errors
+/// With the usage, if a command is malformed or unknown.
+pub fn parse_chat(line: &str) -> Result<ChatCommand, String> {
+    let line = line.trim();
+    let Some(command) = line.strip_prefix('/') else {
+        return Ok(ChatCommand::Say(line.to_owned()));
+    };
+    let (verb, rest) = command.split_once(' ').unwrap_or((command, ""));
+    let (name, text) = split_name(rest.trim_start());
+    let need_name = || format!("usage: /{verb} <player>");
+    match verb {
+        "msg" | "w" => {
+            let name = name.ok_or_else(|| "usage: /msg <player> <text>".to_owned())?;
+            if text.is_empty() {
+                return Err(format!("what do you want to tell {name}?"));
+            }
+            Ok(ChatCommand::Whisper {
+                to: name,
+                text: text.to_owned(),
+            })
+        }
+        "block" => name.map(ChatCommand::Block).ok_or_else(need_name),
+        "unblock" => name.map(ChatCommand::Unblock).ok_or_else(need_name),
+        "" => Err("usage: /<command> [arguments]".to_owned()),
+        _ => Ok(ChatCommand::Server(command.to_owned())),
+    }
+}
+
+/// The player name at the start of `rest`, quoted or up to the first
+/// space, and what follows it.
+fn split_name(rest: &str) -> (Option<String>, &str) {
+    let (name, text) = match rest.strip_prefix('"') {
+        Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+        None => rest.split_once(' ').unwrap_or((rest, "")),
+    };
+    let name = name.trim();
+    ((!name.is_empty()).then(|| name.to_owned()), text.trim())
+}
+
+/// `name` as compared with others: names match whatever their case.
+pub fn name_key(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+/// The whispers each client sent lately, to hold them to [`MAX_WHISPERS`].
+#[derive(Debug, Default)]
+pub struct WhisperLimiter {
+    sent: FxHashMap<EndpointId, VecDeque<Instant>>,
+}
+
+impl WhisperLimiter {
+    /// Count a whisper from `endpoint_id` at `now`, or return `false` if
+    /// it sent too many lately.
+    pub fn allow(&mut self, endpoint_id: EndpointId, now: Instant) -> bool {
+        let sent = self.sent.entry(endpoint_id).or_default();
+        while sent
+            .front()
+            .is_some_and(|t| now.saturating_duration_since(*t) >= WHISPER_WINDOW)
+        {
+            sent.pop_front();
+        }
+        if sent.len() >= MAX_WHISPERS {
+            return false;
+        }
+        sent.push_back(now);
+        true
+    }
+
+    /// Forget a closed connection.
+    pub fn forget(&mut self, endpoint_id: &EndpointId) {
+        self.sent.remove(endpoint_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoint(n: u8) -> EndpointId {
+        iroh::SecretKey::from_bytes(&[n; 32]).public()
+    }
+
+    #[test]
+    fn whispers_past_the_limit_wait_out_the_window_in_wall_clock_time() {
+        let mut limiter = WhisperLimiter::default();
+        let (a, b) = (endpoint(1), endpoint(2));
+        let start = Instant::now();
+        for _ in 0..MAX_WHISPERS {
+            assert!(limiter.allow(a, start));
+        }
+        assert!(!limiter.allow(a, start), "one past the limit");
+        assert!(
+            !limiter.allow(a, start + WHISPER_WINDOW / 2),
+            "still within the window"
+        );
+        assert!(limiter.allow(b, start), "others are not held back");
+
+        // No ticks pass, as on a paused server; time alone opens the window.
+        assert!(limiter.allow(a, start + WHISPER_WINDOW));
+
+        limiter.forget(&a);
+        for _ in 0..MAX_WHISPERS {
+            assert!(limiter.allow(a, start + WHISPER_WINDOW), "forgotten");
+        }
+    }
+}
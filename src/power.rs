@@ -0,0 +1,68 @@
+//! Whether the machine runs on battery, for the client's low-power mode.
+//!
+//! Only Linux says, through `/sys/class/power_supply`; elsewhere the answer
+//! is unknown and the mode is left to the player's setting.
+
+use std::fs;
+use std::path::Path;
+
+/// Seconds between two looks at the power supply.
+pub const CHECK_SECONDS: f64 = 30.0;
+
+/// Returns `Some(true)` if the machine runs on battery, `Some(false)` if on
+/// mains power, and `None` if it cannot tell.
+pub fn on_battery() -> Option<bool> {
+    if cfg!(target_os = "linux") {
+        on_battery_in(Path::new("/sys/class/power_supply"))
+    } else {
+        None
+    }
+}
+
+/// [`on_battery`] from the power supplies listed under `dir`.
+fn on_battery_in(dir: &Path) -> Option<bool> {
+    let read = |supply: &Path, file: &str| {
+        fs::read_to_string(supply.join(file))
+            .map(|s| s.trim().to_owned())
+            .ok()
+    };
+    let mut known = None;
+    for supply in fs::read_dir(dir).ok()?.filter_map(Result::ok) {
+        let supply = supply.path();
+        match read(&supply, "type").as_deref() {
+            Some("Battery") => match read(&supply, "status").as_deref() {
+                Some("Discharging") => return Some(true),
+                Some(_) => known = Some(false),
+                None => {}
+            },
+            Some("Mains") if read(&supply, "online").as_deref() == Some("1") => {
+                return Some(false);
+            }
+            _ => {}
+        }
+    }
+    known
+}
+
+/// Looks at the power supply now and then, answering from the last look
+/// in between.
+#[derive(Debug, Clone, Default)]
+pub struct PowerWatch {
+    checked_at: Option<f64>,
+    on_battery: Option<bool>,
+}
+
+impl PowerWatch {
+    /// Whether the machine runs on battery, as of at most
+    /// [`CHECK_SECONDS`] before `now`, in seconds.
+    pub fn poll(&mut self, now: f64) -> Option<bool> {
+        if self
+            .checked_at
+            .is_none_or(|at| now - at >= CHECK_SECONDS || now < at)
+        {
+            self.checked_at = Some(now);
+            self.on_battery = on_battery();
+        }
+        self.on_battery
+    }
+}
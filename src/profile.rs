@@ -0,0 +1,305 @@
+//! Lightweight profiling: per-tick timings and memory estimates.
+//!
+//! A [`Profiler`] sums the time spent in each [`System`] during a tick and
+//! keeps the last few hundred ticks in a ring buffer. The server profiles its
+//! tick loop (apply, AI, encode, send) and the client its frame (FOV,
+//! awareness); both are cheap enough to leave on.
+//!
+//! A [`MemoryReport`] lists the estimated bytes held by the parts of a
+//! structure, so hosts can see what grows with world size.
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt::{self, Write as _};
+use std::mem::size_of;
+use std::time::{Duration, Instant};
+
+/// Ticks of history kept by a [`Profiler`].
+pub const PROFILE_HISTORY: usize = 300;
+
+/// A part of the tick whose time is measured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum System {
+    /// Applying queued game actions and finishing the tick.
+    Apply,
+    /// Creatures deciding what to do.
+    Ai,
+    /// Field of view.
+    Fov,
+    /// Awareness lists.
+    Awareness,
+    /// Building and encoding world updates.
+    Encode,
+    /// Writing updates to connections.
+    Send,
+}
+
+impl System {
+    pub const ALL: [Self; 6] = [
+        Self::Apply,
+        Self::Ai,
+        Self::Fov,
+        Self::Awareness,
+        Self::Encode,
+        Self::Send,
+    ];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Apply => "apply",
+            Self::Ai => "ai",
+            Self::Fov => "fov",
+            Self::Awareness => "awareness",
+            Self::Encode => "encode",
+            Self::Send => "send",
+        }
+    }
+
+    const fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Time spent in each [`System`] during one tick.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TickTimings {
+    pub tick: u64,
+    durations: [Duration; System::ALL.len()],
+}
+
+impl TickTimings {
+    pub fn get(&self, system: System) -> Duration {
+        self.durations
+            .get(system.index())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Time spent in all systems together.
+    pub fn total(&self) -> Duration {
+        self.durations.iter().sum()
+    }
+}
+
+/// Ring buffer of [`TickTimings`].
+#[derive(Debug, Clone)]
+pub struct Profiler {
+    current: TickTimings,
+    history: VecDeque<TickTimings>,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self {
+            current: TickTimings::default(),
+            history: VecDeque::with_capacity(PROFILE_HISTORY),
+        }
+    }
+}
+
+impl Profiler {
+    /// Add `duration` to the time spent in `system` this tick.
+    pub fn record(&mut self, system: System, duration: Duration) {
+        if let Some(spent) = self.current.durations.get_mut(system.index()) {
+            *spent += duration;
+        }
+    }
+
+    /// Run `f`, counting its time towards `system`.
+    pub fn time<T>(&mut self, system: System, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let out = f();
+        self.record(system, start.elapsed());
+        out
+    }
+
+    /// Close the current tick as `tick` and start the next one, dropping the
+    /// oldest tick beyond [`PROFILE_HISTORY`].
+    pub fn finish_tick(&mut self, tick: u64) {
+        if self.history.len() == PROFILE_HISTORY {
+            self.history.pop_front();
+        }
+        let mut finished = std::mem::take(&mut self.current);
+        finished.tick = tick;
+        self.history.push_back(finished);
+    }
+
+    /// Finished ticks, oldest first.
+    pub fn history(&self) -> impl ExactSizeIterator<Item = &TickTimings> {
+        self.history.iter()
+    }
+
+    /// Average time per tick spent in `system` over the history.
+    pub fn average(&self, system: System) -> Duration {
+        let total: Duration = self.history.iter().map(|t| t.get(system)).sum();
+        total / u32::try_from(self.history.len().max(1)).unwrap_or(u32::MAX)
+    }
+
+    /// The history as CSV: a `tick` column and one column per system, in
+    /// microseconds.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("tick");
+        for system in System::ALL {
+            write!(csv, ",{}_us", system.name()).ok();
+        }
+        for timings in &self.history {
+            write!(csv, "\n{}", timings.tick).ok();
+            for system in System::ALL {
+                write!(csv, ",{}", timings.get(system).as_micros()).ok();
+            }
+        }
+        csv.push('\n');
+        csv
+    }
+}
+
+/// Estimated memory held by the named parts of a structure.
+///
+/// Estimates count the inline size of stored elements and the capacity of
+/// their buffers, but not allocator overhead, so real usage is somewhat
+/// higher.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+    parts: Vec<(String, usize)>,
+}
+
+impl MemoryReport {
+    /// Add a part of `bytes` bytes.
+    #[must_use]
+    pub fn with(mut self, name: impl Into<String>, bytes: usize) -> Self {
+        self.parts.push((name.into(), bytes));
+        self
+    }
+
+    /// Add every part of `other`, named `<prefix>.<part>`.
+    #[must_use]
+    pub fn nest(mut self, prefix: &str, other: Self) -> Self {
+        self.parts.extend(
+            other
+                .parts
+                .into_iter()
+                .map(|(name, bytes)| (format!("{prefix}.{name}"), bytes)),
+        );
+        self
+    }
+
+    pub fn parts(&self) -> &[(String, usize)] {
+        &self.parts
+    }
+
+    pub fn total(&self) -> usize {
+        self.parts.iter().map(|(_, bytes)| bytes).sum()
+    }
+}
+
+impl fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, bytes) in &self.parts {
+            writeln!(f, "{name:<24} {:>10}", format_bytes(*bytes))?;
+        }
+        write!(f, "{:<24} {:>10}", "total", format_bytes(self.total()))
+    }
+}
+
+/// `bytes` in B, KiB, MiB or GiB, whichever reads best.
+pub fn format_bytes(bytes: usize) -> String {
+    let mut value = bytes as f64;
+    let mut unit = "B";
+    for larger in ["KiB", "MiB", "GiB"] {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = larger;
+    }
+    if unit == "B" {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {unit}")
+    }
+}
+
+/// Bytes reserved by a vector's buffer.
+pub fn vec_bytes<T>(vec: &Vec<T>) -> usize {
+    vec.capacity() * size_of::<T>()
+}
+
+/// Bytes reserved by a deque's buffer.
+pub fn deque_bytes<T>(deque: &VecDeque<T>) -> usize {
+    deque.capacity() * size_of::<T>()
+}
+
+/// Bytes reserved by a hash map's table, including one control byte per slot.
+pub fn hash_map_bytes<K, V, S>(map: &HashMap<K, V, S>) -> usize {
+    map.capacity() * (size_of::<(K, V)>() + 1)
+}
+
+/// Bytes reserved by a hash set's table, including one control byte per slot.
+pub fn hash_set_bytes<T, S>(set: &HashSet<T, S>) -> usize {
+    set.capacity() * (size_of::<T>() + 1)
+}
+
+/// Bytes of the entries of a B-tree map, ignoring partially filled nodes.
+pub fn btree_map_bytes<K, V>(map: &BTreeMap<K, V>) -> usize {
+    map.len() * size_of::<(K, V)>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticks_accumulate_and_roll_over() {
+        let mut profiler = Profiler::default();
+        profiler.record(System::Apply, Duration::from_micros(300));
+        profiler.record(System::Apply, Duration::from_micros(200));
+        profiler.record(System::Send, Duration::from_micros(100));
+        profiler.finish_tick(1);
+
+        let first = profiler.history().next().copied().expect("one tick");
+        assert_eq!(first.tick, 1);
+        assert_eq!(first.get(System::Apply), Duration::from_micros(500));
+        assert_eq!(first.total(), Duration::from_micros(600));
+
+        for tick in 2..=PROFILE_HISTORY as u64 + 1 {
+            profiler.finish_tick(tick);
+        }
+        assert_eq!(profiler.history().len(), PROFILE_HISTORY);
+        assert_eq!(profiler.history().next().map(|t| t.tick), Some(2));
+        assert!(profiler.average(System::Apply).is_zero());
+    }
+
+    #[test]
+    fn memory_reports_nest_and_total() {
+        let fov = MemoryReport::default().with("mask", 1024);
+        let report = MemoryReport::default()
+            .with("entities", 3 * 1024 * 1024)
+            .nest("fov", fov);
+        assert_eq!(report.total(), 3 * 1024 * 1024 + 1024);
+        assert_eq!(
+            report.parts().get(1).map(|(name, _)| name.as_str()),
+            Some("fov.mask")
+        );
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert!(report.to_string().ends_with("3.0 MiB"));
+    }
+
+    #[test]
+    fn csv_has_one_row_per_tick() {
+        let mut profiler = Profiler::default();
+        profiler.record(System::Fov, Duration::from_micros(42));
+        profiler.finish_tick(7);
+        profiler.finish_tick(8);
+
+        let csv = profiler.to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "tick,apply_us,ai_us,fov_us,awareness_us,encode_us,send_us",
+                "7,0,0,42,0,0,0",
+                "8,0,0,0,0,0,0",
+            ]
+        );
+    }
+}
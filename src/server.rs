@@ -0,0 +1,580 @@
+//! The game server as a library, for embedding it in another program.
+//!
+//! ```no_run
+//! # async fn host() -> Result<(), String> {
+//! use gamik::game::GameState;
+//! use gamik::server::Server;
+//! use std::time::Duration;
+//!
+//! let server = Server::builder()
+//!     .world(GameState::create_test_world("Woods".into()))
+//!     .tick_rate(Duration::from_millis(100))
+//!     .spawn()
+//!     .await?;
+//! println!("Join with {}", server.id());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The server runs on Tokio: a task steps the world at the tick rate while
+//! others serve each connection, exactly as when hosting from the game.
+//! [`ServerBuilder::spawn`] starts it on the caller's runtime, and
+//! [`spawn_on`](ServerBuilder::spawn_on) on a given one, awaited from any
+//! executor. Programs without Tokio use
+//! [`spawn_thread`](ServerBuilder::spawn_thread), which runs the server on
+//! a thread of its own and is driven through a blocking [`ServerThread`].
+//! Plugins added with [`ServerBuilder::plugin`] run
+//! from the console and the chat; the host runs console commands with
+//! [`Server::command`], and reaches anything else through
+//! [`Server::state`].
+
+use crate::game::GameState;
+use crate::game::content::ContentWatcher;
+use crate::net::access::MAX_SECRET_LEN;
+use crate::net::{
+    self, Caller, Plugins, ServerPlugin, ServerState, TICK_INTERVAL, WhenEmpty, console,
+};
+
+use iroh::protocol::Router;
+use iroh::{Endpoint, EndpointAddr, EndpointId};
+use std::mem;
+use std::path::PathBuf;
+use std::sync::{Arc, mpsc};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tokio::runtime::{self, Handle};
+use tokio::sync::{Mutex, oneshot};
+
+/// Configures a [`Server`]; see [`Server::builder`].
+#[derive(Debug)]
+pub struct ServerBuilder {
+    world: Option<GameState>,
+    tick_interval: Duration,
+    endpoint: Option<Endpoint>,
+    when_empty: WhenEmpty,
+    plugins: Plugins,
+    /// The first plugin that could not be registered, and why.
+    plugin_error: Option<String>,
+    content_watch: Option<ContentWatcher>,
+    /// Password joining needs, in place of the one saved with the world.
+    password: Option<String>,
+    /// Invite codes let in besides the saved ones, with their uses.
+    invites: Vec<(String, Option<u32>)>,
+    /// The endpoint the game hosting the server plays from, if any.
+    host: Option<EndpointId>,
+}
+
+impl Default for ServerBuilder {
+    fn default() -> Self {
+        Self {
+            world: None,
+            tick_interval: TICK_INTERVAL,
+            endpoint: None,
+            when_empty: WhenEmpty::default(),
+            plugins: Plugins::default(),
+            plugin_error: None,
+            content_watch: None,
+            password: None,
+            invites: Vec::new(),
+            host: None,
+        }
+    }
+}
+
+impl ServerBuilder {
+    /// The world to host; a small test world if not given.
+    #[must_use]
+    pub fn world(mut self, world: GameState) -> Self {
+        self.world = Some(world);
+        self
+    }
+
+    /// Time between two ticks; [`TICK_INTERVAL`] if not given. Clients
+    /// time some effects by ticks, so they look right only at the default.
+    #[must_use]
+    pub fn tick_rate(mut self, interval: Duration) -> Self {
+        self.tick_interval = interval;
+        self
+    }
+
+    /// Serve on `endpoint`, e.g. one bound with a lasting secret key or
+    /// custom relays; a fresh endpoint is bound if not given.
+    #[must_use]
+    pub fn transport(mut self, endpoint: Endpoint) -> Self {
+        self.endpoint = Some(endpoint);
+        self
+    }
+
+    /// What to do while no one is connected.
+    #[must_use]
+    pub fn when_empty(mut self, when_empty: WhenEmpty) -> Self {
+        self.when_empty = when_empty;
+        self
+    }
+
+    /// Add `plugin`'s commands. A plugin whose commands clash with others
+    /// makes [`spawn`](Self::spawn) fail.
+    #[must_use]
+    pub fn plugin(mut self, plugin: impl ServerPlugin + 'static) -> Self {
+        if let Err(e) = self.plugins.register(plugin)
+            && self.plugin_error.is_none()
+        {
+            self.plugin_error = Some(e);
+        }
+        self
+    }
+
+    /// Read the content files under `dir` again whenever they change, and
+    /// use them if they still make sense together; see
+    /// [`ContentWatcher`]. Meant for working on content, as
+    /// [`content::CONTENT_DIR`](crate::game::content::CONTENT_DIR) from
+    /// the project.
+    #[must_use]
+    pub fn watch_content(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.content_watch = Some(ContentWatcher::new(dir));
+        self
+    }
+
+    /// Make joining need `password`, in place of any saved with the world;
+    /// see [`access`](crate::net::access).
+    #[must_use]
+    pub fn password(mut self, password: impl Into<String>) -> Self {
+        self.password = Some(password.into());
+        self
+    }
+
+    /// Let in clients with invite code `code`, for `uses` joins or any
+    /// number, besides those saved with the world.
+    #[must_use]
+    pub fn invite(mut self, code: impl Into<String>, uses: Option<u32>) -> Self {
+        self.invites.push((code.into(), uses));
+        self
+    }
+
+    /// Let the player on `endpoint` run every console command, as the host
+    /// does from [`Server::command`]; for a host who also plays, from an
+    /// endpoint bound with a secret key only it holds. Other players run
+    /// only those for anyone until made admins.
+    #[must_use]
+    pub fn host(mut self, endpoint: EndpointId) -> Self {
+        self.host = Some(endpoint);
+        self
+    }
+
+    /// Start serving on the current Tokio runtime.
+    ///
+    /// # Errors
+    /// If a plugin could not be registered, a password or invite code is
+    /// empty or too long, or no endpoint could be bound.
+    pub async fn spawn(mut self) -> Result<Server, String> {
+        let state = self.server_state()?;
+        let endpoint = match self.endpoint {
+            Some(endpoint) => endpoint,
+            None => Endpoint::bind().await.map_err(|e| e.to_string())?,
+        };
+        let state = Arc::new(Mutex::new(state));
+        let router = net::serve(endpoint, state.clone(), self.tick_interval);
+        Ok(Server { router, state })
+    }
+
+    /// The state to serve, with the world, plugins and secrets configured.
+    fn server_state(&mut self) -> Result<ServerState, String> {
+        if let Some(e) = self.plugin_error.take() {
+            return Err(e);
+        }
+        let secrets = self
+            .password
+            .iter()
+            .chain(self.invites.iter().map(|(code, _)| code));
+        for secret in secrets {
+            if secret.is_empty() || secret.len() > MAX_SECRET_LEN {
+                return Err(format!(
+                    "passwords and invite codes take 1 to {MAX_SECRET_LEN} bytes"
+                ));
+            }
+        }
+        let world = self
+            .world
+            .take()
+            .unwrap_or_else(|| GameState::create_test_world("test_world".into()));
+        let mut state = net::open_server_state(world, self.when_empty);
+        state.plugins = mem::take(&mut self.plugins);
+        state.content_watch = self.content_watch.take();
+        state.access.host = self.host;
+        if let Some(password) = &self.password {
+            state.access.rules.set_password(Some(password));
+        }
+        for (code, uses) in &self.invites {
+            state.access.rules.add_invite(code, *uses);
+        }
+        Ok(state)
+    }
+
+    /// Start serving on `runtime`. The future may be awaited on any
+    /// executor, and the server's methods too; only a
+    /// [`transport`](Self::transport) endpoint must be bound on `runtime`.
+    ///
+    /// # Errors
+    /// As [`spawn`](Self::spawn), or if `runtime` shut down.
+    pub async fn spawn_on(self, runtime: &Handle) -> Result<Server, String> {
+        runtime
+            .spawn(self.spawn())
+            .await
+            .map_err(|e| e.to_string())?
+    }
+
+    /// Start serving on a thread of its own, with a runtime of its own,
+    /// for programs that do not run Tokio. Blocks until the server is up.
+    ///
+    /// # Errors
+    /// As [`spawn`](Self::spawn), or if the thread or its runtime could not
+    /// be started.
+    pub fn spawn_thread(self) -> Result<ServerThread, String> {
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let (stop_tx, stop_rx) = oneshot::channel::<()>();
+        let thread = thread::Builder::new()
+            .name("gamik-server".into())
+            .spawn(move || {
+                let runtime = match runtime::Builder::new_multi_thread().enable_all().build() {
+                    Ok(runtime) => runtime,
+                    Err(e) => {
+                        ready_tx.send(Err(e.to_string())).ok();
+                        return;
+                    }
+                };
+                runtime.block_on(async move {
+                    let spawned = self.spawn().await;
+                    let up = spawned.is_ok();
+                    ready_tx
+                        .send(spawned.map(|server| (server, Handle::current())))
+                        .ok();
+                    if up {
+                        // Serve until stopped, or until the handle is dropped.
+                        stop_rx.await.ok();
+                    }
+                });
+            })
+            .map_err(|e| e.to_string())?;
+        let (server, runtime) = ready_rx
+            .recv()
+            .map_err(|e| format!("the server thread stopped: {e}"))??;
+        Ok(ServerThread {
+            server,
+            runtime,
+            stop: Some(stop_tx),
+            thread: Some(thread),
+        })
+    }
+}
+
+/// A running game server. Keep it for as long as it should serve;
+/// [`shutdown`](Self::shutdown) stops it cleanly.
+#[derive(Debug, Clone)]
+pub struct Server {
+    router: Router,
+    state: Arc<Mutex<ServerState>>,
+}
+
+impl Server {
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::default()
+    }
+
+    /// The ID players join with.
+    pub fn id(&self) -> EndpointId {
+        self.router.endpoint().id()
+    }
+
+    /// Where to reach the server, with its relay and direct addresses.
+    pub fn addr(&self) -> EndpointAddr {
+        self.router.endpoint().addr()
+    }
+
+    pub fn endpoint(&self) -> &Endpoint {
+        self.router.endpoint()
+    }
+
+    /// The state the server runs on. Hold the lock briefly: ticks and
+    /// connections wait for it.
+    pub fn state(&self) -> &Arc<Mutex<ServerState>> {
+        &self.state
+    }
+
+    /// Run a console command as the host, who may run any, and return its
+    /// output.
+    pub async fn command(&self, line: &str) -> String {
+        let caller = Caller {
+            endpoint: self.id(),
+            player: None,
+            entity: None,
+            admin: true,
+        };
+        console::run(&mut *self.state.lock().await, &caller, line)
+    }
+
+    /// Close every connection and stop serving.
+    ///
+    /// # Errors
+    /// If a protocol handler failed to shut down.
+    pub async fn shutdown(&self) -> Result<(), String> {
+        self.router.shutdown().await.map_err(|e| e.to_string())
+    }
+}
+
+/// A [`Server`] running on a thread of its own; see
+/// [`ServerBuilder::spawn_thread`]. Its methods block, so call them from
+/// outside any async runtime. Dropping it stops the server.
+#[derive(Debug)]
+pub struct ServerThread {
+    server: Server,
+    /// Runtime of the server's thread, to run calls on.
+    runtime: Handle,
+    stop: Option<oneshot::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ServerThread {
+    /// The server, whose async methods may be run on any executor.
+    pub fn server(&self) -> &Server {
+        &self.server
+    }
+
+    /// The ID players join with.
+    pub fn id(&self) -> EndpointId {
+        self.server.id()
+    }
+
+    /// Run a console command as the host and return its output; see
+    /// [`Server::command`].
+    pub fn command(&self, line: &str) -> String {
+        self.runtime.block_on(self.server.command(line))
+    }
+
+    /// Run `f` on the server's state, holding its lock; see
+    /// [`Server::state`].
+    pub fn with_state<T>(&self, f: impl FnOnce(&mut ServerState) -> T) -> T {
+        f(&mut self.server.state.blocking_lock())
+    }
+
+    /// Close every connection, stop serving and wait for the thread.
+    ///
+    /// # Errors
+    /// As [`Server::shutdown`], or if the thread panicked.
+    pub fn shutdown(mut self) -> Result<(), String> {
+        let result = self.runtime.block_on(self.server.shutdown());
+        result.and(self.stop())
+    }
+
+    fn stop(&mut self) -> Result<(), String> {
+        if let Some(stop) = self.stop.take() {
+            stop.send(()).ok();
+        }
+        match self.thread.take() {
+            Some(thread) => match thread.join() {
+                Ok(()) => Ok(()),
+                Err(_) => Err("the server thread panicked".to_owned()),
+            },
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for ServerThread {
+    fn drop(&mut self) {
+        self.stop().ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::Denied;
+    use crate::net::plugin::{Args, CommandSpec, Permission};
+
+    use iroh::RelayMode;
+    use std::time::Instant;
+
+    /// Tells the world's tick, to the host only.
+    struct Clock;
+
+    impl ServerPlugin for Clock {
+        fn name(&self) -> &'static str {
+            "clock"
+        }
+
+        fn commands(&self) -> Vec<CommandSpec> {
+            vec![CommandSpec {
+                name: "clock",
+                about: "Tell the tick",
+                args: Vec::new(),
+                permission: Permission::Admin,
+            }]
+        }
+
+        fn run(&self, state: &mut ServerState, _: &Caller, _: &str, _: &Args) -> String {
+            format!("tick {}", state.game.tick)
+        }
+    }
+
+    /// An endpoint that stays off the relays, so tests need no network.
+    async fn local_endpoint() -> Endpoint {
+        Endpoint::empty_builder(RelayMode::Disabled)
+            .bind()
+            .await
+            .expect("a local endpoint binds")
+    }
+
+    #[test]
+    fn builder_sets_the_host_and_the_secrets_to_join_with() {
+        let endpoint = |n| iroh::SecretKey::from_bytes(&[n; 32]).public();
+        let mut builder = Server::builder()
+            .world(GameState::create_test_world("Gated".into()))
+            .password("hunter2")
+            .invite("FRIENDS", Some(1))
+            .host(endpoint(7));
+        let mut state = builder.server_state().expect("the config is valid");
+        assert_eq!(state.access.host, Some(endpoint(7)));
+        let gate = &mut state.access;
+        let now = Instant::now();
+        assert_eq!(
+            gate.check(endpoint(1), None, now),
+            Err(Denied::PasswordRequired)
+        );
+        assert_eq!(gate.check(endpoint(1), Some("hunter2"), now), Ok(()));
+        assert_eq!(gate.check(endpoint(2), Some("FRIENDS"), now), Ok(()));
+        assert_eq!(
+            gate.check(endpoint(3), Some("FRIENDS"), now),
+            Err(Denied::WrongPassword),
+            "good for one join"
+        );
+    }
+
+    #[test]
+    fn builder_refuses_empty_or_overlong_secrets() {
+        let long = "x".repeat(MAX_SECRET_LEN + 1);
+        for mut builder in [
+            Server::builder().password(""),
+            Server::builder().password(long.as_str()),
+            Server::builder().invite(long.as_str(), None),
+        ] {
+            assert!(builder.server_state().is_err());
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_built_server_steps_its_world_and_runs_plugins() {
+        let endpoint = local_endpoint().await;
+        let id = endpoint.id();
+        let server = Server::builder()
+            .world(GameState::create_test_world("Embedded".into()))
+            .tick_rate(Duration::from_millis(5))
+            .transport(endpoint)
+            .plugin(Clock)
+            .spawn()
+            .await
+            .expect("the server starts");
+        assert_eq!(server.id(), id, "served on the endpoint given");
+
+        let start = server.state().lock().await.game.tick;
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while server.state().lock().await.game.tick < start + 3 {
+            assert!(Instant::now() < deadline, "the world stopped at {start}");
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert!(server.command("clock").await.starts_with("tick "));
+        assert_eq!(server.state().lock().await.game.world_name, "Embedded");
+        server.shutdown().await.expect("the server stops");
+    }
+
+    #[tokio::test]
+    async fn spawning_fails_on_clashing_plugins_or_bad_secrets() {
+        let clash = Server::builder().plugin(Clock).plugin(Clock).spawn().await;
+        assert!(clash.is_err(), "two plugins add `clock`");
+        let empty = Server::builder().invite("", Some(1)).spawn().await;
+        assert!(empty.is_err());
+    }
+
+    /// Wait, blocking, until `tick` shows the world stepped past `start`.
+    fn wait_for_ticks(start: u64, tick: impl Fn() -> u64) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while tick() < start + 3 {
+            assert!(Instant::now() < deadline, "the world stopped at {start}");
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn a_server_runs_on_the_callers_runtime_awaited_from_another() {
+        let runtime = runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("a runtime");
+        // The caller's own executor, which the server does not run on.
+        let caller = runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("a runtime");
+        let endpoint = runtime.block_on(local_endpoint());
+        let server = caller
+            .block_on(
+                Server::builder()
+                    .tick_rate(Duration::from_millis(5))
+                    .transport(endpoint)
+                    .plugin(Clock)
+                    .spawn_on(runtime.handle()),
+            )
+            .expect("the server starts");
+
+        let start = server.state().blocking_lock().game.tick;
+        wait_for_ticks(start, || server.state().blocking_lock().game.tick);
+        assert!(
+            caller
+                .block_on(server.command("clock"))
+                .starts_with("tick ")
+        );
+        caller
+            .block_on(server.shutdown())
+            .expect("the server stops");
+
+        let clash = caller.block_on(
+            Server::builder()
+                .plugin(Clock)
+                .plugin(Clock)
+                .spawn_on(runtime.handle()),
+        );
+        assert!(clash.is_err(), "two plugins add `clock`");
+
+        let gone = runtime.handle().clone();
+        drop(runtime);
+        let stopped = caller.block_on(Server::builder().spawn_on(&gone));
+        assert!(stopped.is_err(), "the runtime shut down");
+    }
+
+    #[test]
+    fn a_server_thread_serves_blocking_callers() {
+        let server = Server::builder()
+            .world(GameState::create_test_world("Threaded".into()))
+            .tick_rate(Duration::from_millis(5))
+            .plugin(Clock)
+            .spawn_thread()
+            .expect("the server starts");
+        assert_eq!(server.server().id(), server.id());
+
+        let start = server.with_state(|state| state.game.tick);
+        wait_for_ticks(start, || server.with_state(|state| state.game.tick));
+        assert!(server.command("clock").starts_with("tick "));
+        assert_eq!(
+            server.with_state(|state| state.game.world_name.clone()),
+            "Threaded"
+        );
+        server.shutdown().expect("the server and its thread stop");
+
+        // Dropping the handle stops the thread too, without hanging.
+        drop(Server::builder().spawn_thread().expect("the server starts"));
+
+        let clash = Server::builder().plugin(Clock).plugin(Clock).spawn_thread();
+        assert!(clash.is_err(), "two plugins add `clock`");
+        let empty = Server::builder().password("").spawn_thread();
+        assert!(empty.is_err());
+    }
+}
@@ -4,9 +4,20 @@
 //! It reads [`GameState`](crate::game::GameState) and produces visual output —
 //! no game logic lives here.
 
-use crate::game::{Entity, EntityType, Point};
+use crate::game::chunks::CHUNK_SIZE;
+use crate::game::fov::ExploredMap;
+use crate::game::math;
+use crate::game::render::{self, AppearanceDef, RenderLayer};
+use crate::game::worldgen::Preview;
+use crate::game::{
+    ContentRegistry, Direction, Entity, EntityID, EntityMap, EntityType, PingKind, PlayerColor,
+    Point, Terrain, TileMap,
+};
+use crate::net::{Marker, MarkerColor};
+use crate::profile::{Profiler, System};
 use egui::Color32;
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::sync::{Arc, OnceLock, PoisonError, RwLock};
 
 /// Visual representation of a single grid cell.
 pub struct Glyph {
@@ -14,41 +25,1201 @@ pub struct Glyph {
     pub fg_color: Color32,
     pub bg_color: Color32,
     pub size_mod: f32,
+    /// Drawn in a strong weight.
+    pub bold: bool,
+    /// Foreground brightness when the cell is out of sight.
+    pub remembered: f32,
+}
+
+impl Glyph {
+    /// The glyph of an entity type, as given by its appearance.
+    pub fn of(appearance: &AppearanceDef) -> Self {
+        let rgb = |(r, g, b)| Color32::from_rgb(r, g, b);
+        Self {
+            character: intern(&appearance.glyph),
+            fg_color: rgb(appearance.fg),
+            bg_color: rgb(appearance.bg),
+            size_mod: if appearance.style.small { 2.0 } else { 1.0 },
+            bold: appearance.style.bold,
+            remembered: f32::from(appearance.style.remembered) / 100.0,
+        }
+    }
+}
+
+/// `text` for as long as the program runs, to draw as a glyph.
+///
+/// Reloaded content replaces the appearances glyphs were taken from, so
+/// each distinct glyph text is kept here instead; there are only a handful.
+fn intern(text: &str) -> &'static str {
+    static TEXTS: OnceLock<RwLock<FxHashSet<&'static str>>> = OnceLock::new();
+    let texts = TEXTS.get_or_init(RwLock::default);
+    if let Some(text) = texts
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(text)
+    {
+        return text;
+    }
+    let mut texts = texts.write().unwrap_or_else(PoisonError::into_inner);
+    if let Some(text) = texts.get(text) {
+        return text;
+    }
+    let text: &'static str = Box::leak(text.into());
+    texts.insert(text);
+    text
 }
 
 /// Pre-computed spatial index mapping positions to entities.
 pub type SpatialIndex<'a> = FxHashMap<Point, &'a Entity>;
 
 /// Build a spatial index from the entity map for O(1) lookups per cell.
-pub fn build_spatial_index(entities: &crate::game::EntityMap) -> SpatialIndex<'_> {
+pub fn build_spatial_index(entities: &EntityMap) -> SpatialIndex<'_> {
+    stack(entities.iter().map(|(eid, e)| (*eid, e)))
+}
+
+/// Build a spatial index of the entities the player is aware of, plus
+/// terrain, which stays on the map once out of view.
+pub fn build_visible_index<'a>(
+    entities: &'a EntityMap,
+    awareness: &[EntityID],
+) -> SpatialIndex<'a> {
+    let terrain = entities
+        .iter()
+        .filter(|(_, e)| e.entity_type.is_terrain())
+        .map(|(eid, e)| (*eid, e));
+    let aware = awareness
+        .iter()
+        .filter_map(|eid| Some((*eid, entities.get(eid)?)));
+    stack(terrain.chain(aware))
+}
+
+/// Index the entity drawn on each tile: the top of the stack by
+/// [`render::stack_key`].
+fn stack<'a>(entities: impl Iterator<Item = (EntityID, &'a Entity)>) -> SpatialIndex<'a> {
+    let registry = ContentRegistry::builtin();
+    let mut top: FxHashMap<Point, ((RenderLayer, EntityID), &'a Entity)> = FxHashMap::default();
+    for (eid, entity) in entities {
+        let key = render::stack_key(&registry, eid, &entity.entity_type);
+        let slot = top.entry(entity.position).or_insert((key, entity));
+        if key > slot.0 {
+            *slot = (key, entity);
+        }
+    }
+    top.into_iter()
+        .map(|(point, (_, entity))| (point, entity))
+        .collect()
+}
+
+/// Darken a glyph for a cell outside the player's field of view.
+pub fn dim(glyph: &mut Glyph) {
+    glyph.fg_color = glyph.fg_color.gamma_multiply(glyph.remembered);
+}
+
+/// Brightness of an entity only known by its silhouette.
+const SILHOUETTE_FADE: f32 = 0.3;
+
+/// Fade the glyph of an entity out of sight, known only by its
+/// [`Silhouette`](crate::net::Silhouette).
+pub fn silhouette(entity_type: &EntityType) -> Glyph {
+    let glyph = glyph(entity_type);
+    Glyph {
+        fg_color: glyph.fg_color.gamma_multiply(SILHOUETTE_FADE),
+        bg_color: FLOOR.bg_color,
+        bold: false,
+        ..glyph
+    }
+}
+
+/// The glyph of `entity_type`, as its appearance in the content in use
+/// gives it.
+pub fn glyph(entity_type: &EntityType) -> Glyph {
+    ContentRegistry::builtin()
+        .appearance(entity_type)
+        .map_or(UNKNOWN, Glyph::of)
+}
+
+/// Apply the idle animation of `entity_type` at `time` (in seconds) to the
+/// glyph drawn at `point`.
+pub fn animate(glyph: Glyph, entity_type: &EntityType, point: &Point, time: f64) -> Glyph {
+    let content = ContentRegistry::builtin();
+    let Some(animation) = content
+        .appearance(entity_type)
+        .and_then(|a| a.animation.as_ref())
+    else {
+        return glyph;
+    };
+    // Offset each cell's cycle so neighbours don't move in unison.
+    let seed =
+        (point.x as u32).wrapping_mul(73_856_093) ^ (point.y as u32).wrapping_mul(19_349_663);
+    let phase = f64::from(seed % 1000) / 1000.0;
+
+    let cycle = animation.frames.len().max(1) as f64;
+    let t = time * 1000.0 / f64::from(animation.frame_ms.max(1)) + phase * cycle;
+    let frame = t.rem_euclid(cycle) as usize;
+    let wave = (t / cycle * std::f64::consts::TAU).sin() as f32;
+
+    Glyph {
+        character: animation
+            .frames
+            .get(frame)
+            .map_or(glyph.character, |frame| intern(frame)),
+        fg_color: glyph
+            .fg_color
+            .gamma_multiply(1.0 - f32::from(animation.shimmer) / 100.0 * (0.5 + 0.5 * wave)),
+        ..glyph
+    }
+}
+
+/// Return the visual representation of whatever occupies `point` in the
+/// world, over the ground of its `terrain`.
+pub fn glyph_at(index: &SpatialIndex<'_>, terrain: &TileMap, point: &Point) -> Glyph {
+    let ground = ground(terrain.get(*point));
+    match index.get(point) {
+        Some(entity) => {
+            let mut glyph = glyph(&entity.entity_type);
+            if let Some(color) = entity.color {
+                glyph.fg_color = player_color(color);
+            }
+            // Entities without a background of their own stand on the
+            // ground's.
+            if glyph.bg_color == Color32::BLACK {
+                glyph.bg_color = ground.bg_color;
+            }
+            glyph
+        }
+        None => ground,
+    }
+}
+
+/// The glyph of a tile of `terrain` with nothing on it.
+pub fn ground(terrain: Terrain) -> Glyph {
+    match terrain {
+        Terrain::Grass => FLOOR,
+        Terrain::Water => WATER,
+        Terrain::Rock => ROCK,
+        Terrain::Sand => SAND,
+    }
+}
+
+/// Color of `terrain` on maps, where nothing was seen on it.
+pub fn terrain_color(terrain: Terrain) -> Color32 {
+    match terrain {
+        Terrain::Grass => map_color(None),
+        Terrain::Water => WATER.fg_color,
+        Terrain::Rock => ROCK.fg_color,
+        Terrain::Sand => SAND.fg_color,
+    }
+}
+
+/// Color a player's characters, name and chat are drawn in.
+pub fn player_color(color: PlayerColor) -> Color32 {
+    let [r, g, b] = color.0;
+    Color32::from_rgb(r, g, b)
+}
+
+/// Color `entity` is drawn in: its player's, or its appearance's.
+pub fn entity_color(entity: &Entity) -> Color32 {
+    match entity.color {
+        Some(color) => player_color(color),
+        None => glyph(&entity.entity_type).fg_color,
+    }
+}
+
+/// An empty tile.
+const FLOOR: Glyph = Glyph {
+    character: ".",
+    fg_color: Color32::WHITE,
+    bg_color: Color32::BLACK,
+    size_mod: 2.0,
+    bold: false,
+    remembered: 0.35,
+};
+
+const WATER: Glyph = Glyph {
+    character: "~",
+    fg_color: Color32::from_rgb(40, 90, 200),
+    bg_color: Color32::from_rgb(8, 18, 48),
+    size_mod: 1.0,
+    bold: false,
+    remembered: 0.35,
+};
+
+const ROCK: Glyph = Glyph {
+    character: "#",
+    fg_color: Color32::from_rgb(130, 130, 130),
+    bg_color: Color32::from_rgb(36, 36, 36),
+    size_mod: 1.0,
+    bold: true,
+    remembered: 0.35,
+};
+
+const SAND: Glyph = Glyph {
+    character: ".",
+    fg_color: Color32::from_rgb(200, 180, 120),
+    bg_color: Color32::from_rgb(40, 34, 20),
+    size_mod: 2.0,
+    bold: false,
+    remembered: 0.35,
+};
+
+/// An entity with no appearance, which validated content never has.
+const UNKNOWN: Glyph = Glyph {
+    character: "?",
+    fg_color: Color32::from_rgb(255, 0, 255),
+    bg_color: Color32::BLACK,
+    size_mod: 1.0,
+    bold: true,
+    remembered: 0.35,
+};
+
+/// What one map tile looks like in a frame: a [`Glyph`] with animation,
+/// flashes and dimming applied.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TileDraw {
+    pub character: &'static str,
+    pub fg_color: Color32,
+    pub bg_color: Color32,
+    pub size_mod: f32,
+    pub bold: bool,
+}
+
+impl TileDraw {
+    /// Whether `other` lays out to the same text; colors are applied when
+    /// painting.
+    fn same_text(&self, other: &Self) -> bool {
+        self.character == other.character
+            && self.size_mod == other.size_mod
+            && self.bold == other.bold
+    }
+}
+
+impl From<Glyph> for TileDraw {
+    fn from(glyph: Glyph) -> Self {
+        Self {
+            character: glyph.character,
+            fg_color: glyph.fg_color,
+            bg_color: glyph.bg_color,
+            size_mod: glyph.size_mod,
+            bold: glyph.bold,
+        }
+    }
+}
+
+/// The map tiles of the viewport, with the text of each already laid out.
+///
+/// Every frame the renderer fills in each tile with [`FrameBuffer::set`]
+/// and paints them all at once. A tile's text is laid out again only when
+/// its character, size or weight differs from the previous frame; colors
+/// change freely, so animations that only shimmer cost nothing. At large
+/// viewports this replaces a widget and a text layout per tile with a
+/// handful of layouts per frame.
+#[derive(Debug, Default)]
+pub struct FrameBuffer {
+    cols: usize,
+    rows: usize,
+    font_size: f32,
+    tiles: Vec<Option<TileDraw>>,
+    galleys: Vec<Option<Arc<egui::Galley>>>,
+    /// Tiles laid out in the latest frame.
+    laid_out: usize,
+}
+
+impl FrameBuffer {
+    /// Start a frame of `cols` × `rows` tiles. A new size or font size
+    /// drops everything laid out.
+    pub fn begin(&mut self, cols: usize, rows: usize, font_size: f32) {
+        if (cols, rows) != (self.cols, self.rows) || font_size != self.font_size {
+            *self = Self {
+                cols,
+                rows,
+                font_size,
+                tiles: vec![None; cols * rows],
+                galleys: vec![None; cols * rows],
+                laid_out: 0,
+            };
+        }
+        self.laid_out = 0;
+    }
+
+    /// Set the tile in column `col` of row `row` for this frame.
+    pub fn set(&mut self, col: usize, row: usize, tile: TileDraw) {
+        if col >= self.cols {
+            return;
+        }
+        let index = row * self.cols + col;
+        if let Some(slot) = self.tiles.get_mut(index) {
+            if slot.is_none_or(|old| !old.same_text(&tile))
+                && let Some(galley) = self.galleys.get_mut(index)
+            {
+                *galley = None;
+            }
+            *slot = Some(tile);
+        }
+    }
+
+    /// Lay out the tiles whose text changed and paint the frame into
+    /// `rect`, each tile a `cell`-pixel square.
+    pub fn paint(&mut self, ui: &egui::Ui, rect: egui::Rect, cell: f32) {
+        let painter = ui.painter_at(rect);
+        for (index, (tile, galley)) in self.tiles.iter().zip(&mut self.galleys).enumerate() {
+            let Some(tile) = tile else {
+                continue;
+            };
+            let (col, row) = (index % self.cols, index / self.cols);
+            let min = rect.min + egui::vec2(col as f32 * cell, row as f32 * cell);
+            let tile_rect = egui::Rect::from_min_size(min, egui::Vec2::splat(cell));
+            painter.rect_filled(tile_rect, 0.0, tile.bg_color);
+
+            let galley = galley.get_or_insert_with(|| {
+                self.laid_out += 1;
+                layout_tile(ui, tile, self.font_size)
+            });
+            let pos = tile_rect.center() - galley.size() / 2.0;
+            painter.galley(pos, Arc::clone(galley), tile.fg_color);
+        }
+    }
+
+    /// The tile under `pos`, as `(col, row)`, if `pos` lies in `rect`.
+    pub fn tile_at(&self, rect: egui::Rect, cell: f32, pos: egui::Pos2) -> Option<(usize, usize)> {
+        if !rect.contains(pos) || cell <= 0.0 {
+            return None;
+        }
+        let offset = (pos - rect.min) / cell;
+        let (col, row) = (offset.x as usize, offset.y as usize);
+        (col < self.cols && row < self.rows).then_some((col, row))
+    }
+
+    /// Number of tiles in the viewport.
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    /// Tiles whose text was laid out in the latest frame.
+    pub fn laid_out(&self) -> usize {
+        self.laid_out
+    }
+}
+
+/// Lay out the text of `tile` with a placeholder color, so one layout
+/// serves every color the tile is painted in.
+fn layout_tile(ui: &egui::Ui, tile: &TileDraw, font_size: f32) -> Arc<egui::Galley> {
+    let mut text = egui::RichText::new(tile.character)
+        .color(Color32::PLACEHOLDER)
+        .font(egui::FontId::proportional(font_size / tile.size_mod));
+    if tile.bold {
+        text = text.strong();
+    }
+    egui::WidgetText::from(text).into_galley(
+        ui,
+        Some(egui::TextWrapMode::Extend),
+        f32::INFINITY,
+        egui::FontSelection::Default,
+    )
+}
+
+/// Which debug overlays are drawn over the map (dev builds only).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DebugOverlays {
+    /// Tint cells by how many entities share their spatial-index bucket.
+    pub spatial_index: bool,
+    /// Per-frame timings of the client's systems.
+    pub profiler: bool,
+    /// Tint the tiles in sight apart from those only in the awareness
+    /// margin around the player.
+    pub fov: bool,
+    /// Draw the routes the server plans for entities walking somewhere;
+    /// needs its [`DebugData`](crate::net::DebugData).
+    pub paths: bool,
+    /// Outline the chunks the world is split into.
+    pub chunks: bool,
+}
+
+impl DebugOverlays {
+    /// Returns `true` if any overlay is enabled.
+    pub fn any(&self) -> bool {
+        self.spatial_index || self.profiler || self.fov || self.paths || self.chunks
+    }
+}
+
+/// Number of entities in each occupied cell.
+///
+/// [`SpatialIndex`] keeps one entity per cell, so stacked entities are
+/// invisible in normal rendering; this shows them.
+pub fn bucket_occupancy(entities: &EntityMap) -> FxHashMap<Point, usize> {
     entities
         .values()
-        .map(|e| (e.position, e))
-        .collect()
+        .fold(FxHashMap::default(), |mut occupancy, entity| {
+            *occupancy.entry(entity.position).or_default() += 1;
+            occupancy
+        })
+}
+
+/// Background tint for a cell holding `count` entities.
+pub fn occupancy_color(count: usize) -> Color32 {
+    match count {
+        0 => Color32::BLACK,
+        1 => Color32::from_rgb(0, 40, 80),
+        _ => Color32::from_rgb(120, 0, 0),
+    }
+}
+
+/// Background tint of a tile for the FOV overlay: in sight, only within
+/// the awareness margin, or neither.
+pub fn fov_color(in_sight: bool, in_margin: bool) -> Option<Color32> {
+    match (in_sight, in_margin) {
+        (true, _) => Some(Color32::from_rgb(0, 60, 20)),
+        (false, true) => Some(Color32::from_rgb(80, 60, 0)),
+        (false, false) => None,
+    }
+}
+
+/// Draw each of `paths`, tiles relative to `origin`, as a line through its
+/// tiles from the entity at `from`.
+pub fn debug_paths<'a>(
+    painter: &egui::Painter,
+    origin: egui::Pos2,
+    cell: f32,
+    paths: impl Iterator<Item = (Point, &'a [Point])>,
+) {
+    let center =
+        |p: &Point| origin + (egui::vec2(p.x as f32, p.y as f32) + egui::vec2(0.5, 0.5)) * cell;
+    let stroke = egui::Stroke::new(2.0, Color32::from_rgba_unmultiplied(80, 200, 255, 200));
+    for (from, path) in paths {
+        let points = std::iter::once(&from).chain(path).map(center).collect();
+        painter.add(egui::Shape::line(points, stroke));
+    }
+}
+
+/// Outline the chunks that tiles `min` to `max` overlap, tiles relative to
+/// `origin`.
+pub fn chunk_boundaries(
+    painter: &egui::Painter,
+    origin: egui::Pos2,
+    cell: f32,
+    min: Point,
+    max: Point,
+) {
+    let stroke = egui::Stroke::new(1.0, Color32::from_rgba_unmultiplied(255, 0, 255, 140));
+    let at = |x: i32, y: i32| origin + egui::vec2(x as f32, y as f32) * cell;
+    let first = |from: i32| from.div_euclid(CHUNK_SIZE) * CHUNK_SIZE;
+    for x in (first(min.x)..=max.x).step_by(CHUNK_SIZE as usize) {
+        painter.line_segment([at(x, min.y), at(x, max.y + 1)], stroke);
+    }
+    for y in (first(min.y)..=max.y).step_by(CHUNK_SIZE as usize) {
+        painter.line_segment([at(min.x, y), at(max.x + 1, y)], stroke);
+    }
+}
+
+/// Largest width or height, in pixels, of an exported map image.
+pub const MAX_MAP_IMAGE_SIDE: usize = 8192;
+
+/// Pixel color of an explored tile, given the entity last seen on it.
+pub fn map_color(entity_type: Option<&EntityType>) -> Color32 {
+    match entity_type {
+        None => Color32::from_gray(64),
+        Some(entity_type) => glyph(entity_type).fg_color,
+    }
+}
+
+/// Render the explored map with each tile as a `scale`×`scale` block of
+/// pixels; unexplored tiles are black.
+///
+/// Returns `None` if nothing was explored yet or the image would exceed
+/// [`MAX_MAP_IMAGE_SIDE`].
+pub fn map_image(explored: &ExploredMap, scale: usize) -> Option<egui::ColorImage> {
+    let (min, max) = explored.bounds()?;
+    let scale = scale.max(1);
+    let tiles_wide = usize::try_from(i64::from(max.x) - i64::from(min.x) + 1).ok()?;
+    let tiles_high = usize::try_from(i64::from(max.y) - i64::from(min.y) + 1).ok()?;
+    let width = tiles_wide.checked_mul(scale)?;
+    let height = tiles_high.checked_mul(scale)?;
+    if width > MAX_MAP_IMAGE_SIDE || height > MAX_MAP_IMAGE_SIDE {
+        return None;
+    }
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in min.y..=max.y {
+        let row: Vec<Color32> = (min.x..=max.x)
+            .flat_map(|x| {
+                let color = explored
+                    .get(Point { x, y })
+                    .map_or(Color32::BLACK, map_color);
+                std::iter::repeat_n(color, scale)
+            })
+            .collect();
+        for _ in 0..scale {
+            pixels.extend_from_slice(&row);
+        }
+    }
+    Some(egui::ColorImage::new([width, height], pixels))
+}
+
+/// Render the explored map shrunk to fit within `side`×`side` pixels, each
+/// pixel the color of the tile at its top left; unexplored tiles are black.
+///
+/// Returns `None` if nothing was explored yet.
+pub fn map_thumbnail(explored: &ExploredMap, side: usize) -> Option<egui::ColorImage> {
+    let (min, max) = explored.bounds()?;
+    let tiles_wide = i64::from(max.x) - i64::from(min.x) + 1;
+    let tiles_high = i64::from(max.y) - i64::from(min.y) + 1;
+    let side = i64::try_from(side.max(1)).ok()?;
+    // Tiles per pixel, rounded up so the image fits.
+    let step = (tiles_wide.max(tiles_high) + side - 1) / side;
+    let columns = (tiles_wide + step - 1) / step;
+    let rows = (tiles_high + step - 1) / step;
+    let width = usize::try_from(columns).ok()?;
+    let height = usize::try_from(rows).ok()?;
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in 0..rows {
+        for column in 0..columns {
+            let point = Point {
+                x: i32::try_from(i64::from(min.x) + column * step).ok()?,
+                y: i32::try_from(i64::from(min.y) + row * step).ok()?,
+            };
+            pixels.push(explored.get(point).map_or(Color32::BLACK, map_color));
+        }
+    }
+    Some(egui::ColorImage::new([width, height], pixels))
+}
+
+/// Render a world preview with a pixel per cell, shaded from open land to
+/// forest by the chance of a tree, or in the color of its terrain.
+pub fn preview_image(preview: &Preview) -> egui::ColorImage {
+    let open = map_color(None);
+    let forest = map_color(Some(&EntityType::Tree));
+    let pixels = preview
+        .tree_chance
+        .iter()
+        .zip(&preview.terrain)
+        .map(|(&chance, &terrain)| match terrain {
+            Terrain::Grass => open.lerp_to_gamma(forest, chance as f32 / 1000.0),
+            other => terrain_color(other),
+        })
+        .collect();
+    egui::ColorImage::new([preview.width, preview.height], pixels)
+}
+
+/// Color of `system` in the profiler chart.
+pub fn system_color(system: System) -> Color32 {
+    match system {
+        System::Apply => Color32::from_rgb(230, 160, 40),
+        System::Ai => Color32::from_rgb(230, 220, 80),
+        System::Fov => Color32::from_rgb(70, 160, 230),
+        System::Awareness => Color32::from_rgb(110, 200, 110),
+        System::Encode => Color32::from_rgb(200, 90, 200),
+        System::Send => Color32::from_rgb(220, 70, 70),
+    }
+}
+
+/// Draw the profiler history as stacked bars, one per tick, scaled to the
+/// slowest tick, with a legend of per-system averages.
+pub fn profile_chart(ui: &mut egui::Ui, profiler: &Profiler) {
+    let size = egui::vec2(ui.available_width().max(200.0), 80.0);
+    let (response, painter) = ui.allocate_painter(size, egui::Sense::hover());
+    let rect = response.rect;
+    painter.rect_filled(rect, 0.0, Color32::from_gray(20));
+
+    let slowest = profiler
+        .history()
+        .map(|t| t.total().as_secs_f32())
+        .fold(0.0, f32::max);
+    if slowest > 0.0 {
+        let bar_width = rect.width() / crate::profile::PROFILE_HISTORY as f32;
+        for (i, timings) in profiler.history().enumerate() {
+            let x = rect.left() + i as f32 * bar_width;
+            let mut y = rect.bottom();
+            for system in System::ALL {
+                let height = timings.get(system).as_secs_f32() / slowest * rect.height();
+                let bar = egui::Rect::from_min_max(
+                    egui::pos2(x, y - height),
+                    egui::pos2(x + bar_width, y),
+                );
+                painter.rect_filled(bar, 0.0, system_color(system));
+                y -= height;
+            }
+        }
+    }
+
+    for system in System::ALL {
+        let average = profiler.average(system);
+        if !average.is_zero() {
+            ui.colored_label(
+                system_color(system),
+                format!("■ {} {} µs", system.name(), average.as_micros()),
+            );
+        }
+    }
+    ui.label(format!("Slowest: {:.0} µs", slowest * 1e6));
+}
+
+/// Draw a progress bar filled to `fraction` along the top of the tile at
+/// `min`.
+pub fn progress_bar(painter: &egui::Painter, min: egui::Pos2, cell: f32, fraction: f32) {
+    let height = (cell / 8.0).max(2.0);
+    let back = egui::Rect::from_min_size(min, egui::vec2(cell, height));
+    let fraction = fraction.clamp(0.0, 1.0);
+    let front = egui::Rect::from_min_size(min, egui::vec2(cell * fraction, height));
+    painter.rect_filled(back, 0.0, egui::Color32::from_black_alpha(160));
+    painter.rect_filled(front, 0.0, egui::Color32::from_rgb(80, 200, 80));
+}
+
+/// Draw `route`, tiles relative to `origin`, as a trail of dots ending on
+/// `target`, or a cross on `target` if there is no route.
+pub fn route(
+    painter: &egui::Painter,
+    origin: egui::Pos2,
+    cell: f32,
+    route: Option<&[Point]>,
+    target: Point,
+) {
+    let center =
+        |p: Point| origin + (egui::vec2(p.x as f32, p.y as f32) + egui::vec2(0.5, 0.5)) * cell;
+    let Some(route) = route else {
+        let (c, r) = (center(target), cell * 0.25);
+        let stroke = egui::Stroke::new(2.0, Color32::from_rgb(230, 70, 70));
+        painter.line_segment([c - egui::vec2(r, r), c + egui::vec2(r, r)], stroke);
+        painter.line_segment([c - egui::vec2(r, -r), c + egui::vec2(r, -r)], stroke);
+        return;
+    };
+    let color = Color32::from_rgba_unmultiplied(240, 220, 80, 180);
+    for point in route {
+        painter.circle_filled(center(*point), (cell * 0.1).max(1.5), color);
+    }
+}
+
+/// Draw the breadcrumbs of `trail`, oldest first, tiles relative to
+/// `origin`, fainter the older they are. The last tile, where the player
+/// stands, is left bare.
+pub fn trail(
+    painter: &egui::Painter,
+    origin: egui::Pos2,
+    cell: f32,
+    trail: impl ExactSizeIterator<Item = Point>,
+) {
+    let len = trail.len();
+    for (age, point) in trail.take(len.saturating_sub(1)).enumerate() {
+        let alpha = (40 + 120 * (age + 1) / len) as u8;
+        let center =
+            origin + (egui::vec2(point.x as f32, point.y as f32) + egui::vec2(0.5, 0.5)) * cell;
+        let color = Color32::from_rgba_unmultiplied(200, 170, 120, alpha);
+        painter.circle_filled(center, (cell * 0.08).max(1.0), color);
+    }
+}
+
+/// Flash the edges of `rect` red, as strongly as `strength` (0 to 1), to
+/// warn of danger.
+pub fn edge_flash(painter: &egui::Painter, rect: egui::Rect, strength: f32) {
+    const WIDTH: f32 = 12.0;
+    let alpha = (strength.clamp(0.0, 1.0) * 180.0) as u8;
+    painter.rect_stroke(
+        rect.shrink(WIDTH / 2.0),
+        0.0,
+        egui::Stroke::new(WIDTH, Color32::from_rgba_unmultiplied(255, 60, 40, alpha)),
+        egui::StrokeKind::Middle,
+    );
+}
+
+/// Color pings of `kind` are drawn in.
+pub fn ping_color(kind: PingKind) -> Color32 {
+    match kind {
+        PingKind::Look => Color32::from_rgb(120, 200, 255),
+        PingKind::Go => Color32::from_rgb(120, 230, 120),
+        PingKind::Danger => Color32::from_rgb(255, 90, 70),
+        PingKind::Loot => Color32::GOLD,
+    }
+}
+
+/// Symbol drawn on a ping's tile.
+pub fn ping_symbol(kind: PingKind) -> &'static str {
+    match kind {
+        PingKind::Look => "?",
+        PingKind::Go => ">",
+        PingKind::Danger => "!",
+        PingKind::Loot => "$",
+    }
 }
 
-/// Return the visual representation of whatever occupies `point` in the world.
-pub fn glyph_at(index: &SpatialIndex<'_>, point: &Point) -> Glyph {
-    if let Some(entity) = index.get(point) {
-        return match entity.entity_type {
-            EntityType::Player => Glyph {
-                character: "@",
-                fg_color: Color32::WHITE,
-                bg_color: Color32::BLACK,
-                size_mod: 1.0,
-            },
-            EntityType::Tree => Glyph {
-                character: "木",
-                fg_color: Color32::DARK_GREEN,
-                bg_color: Color32::BLACK,
-                size_mod: 1.0,
-            },
+/// Draw a ping pulsing around the tile at `min`, with its distance in
+/// tiles below it if known.
+pub fn ping_marker(
+    painter: &egui::Painter,
+    min: egui::Pos2,
+    cell: f32,
+    kind: PingKind,
+    distance: Option<u32>,
+    time: f64,
+) {
+    let center = min + egui::vec2(cell, cell) / 2.0;
+    let pulse = ((time * 4.0).sin() * 0.5 + 0.5) as f32;
+    let color = ping_color(kind);
+    let ring = egui::Stroke::new(2.0, color.gamma_multiply(1.0 - pulse / 2.0));
+    painter.circle_stroke(center, cell * (0.5 + 0.3 * pulse), ring);
+    painter.text(
+        center,
+        egui::Align2::CENTER_CENTER,
+        ping_symbol(kind),
+        egui::FontId::monospace(cell * 0.6),
+        color,
+    );
+    if let Some(distance) = distance {
+        painter.text(
+            center + egui::vec2(0.0, cell * 0.8),
+            egui::Align2::CENTER_TOP,
+            distance.to_string(),
+            egui::FontId::proportional(cell * 0.4),
+            color,
+        );
+    }
+}
+
+/// Angle of option `index` of `count` in a radial menu: the first at the
+/// top, the others clockwise.
+fn radial_angle(index: usize, count: usize) -> f32 {
+    std::f32::consts::TAU * index as f32 / count.max(1) as f32 - std::f32::consts::FRAC_PI_2
+}
+
+/// The option of a radial menu of `count` options around `center` that
+/// `pointer` is over, or `None` within `dead_zone` of the center.
+pub fn radial_choice(
+    center: egui::Pos2,
+    pointer: egui::Pos2,
+    count: usize,
+    dead_zone: f32,
+) -> Option<usize> {
+    let offset = pointer - center;
+    if count == 0 || offset.length() < dead_zone {
+        return None;
+    }
+    let step = std::f32::consts::TAU / count as f32;
+    let turned = offset.angle() + std::f32::consts::FRAC_PI_2;
+    Some((turned / step).round().rem_euclid(count as f32) as usize % count)
+}
+
+/// Draw a radial menu of `options`, labels and colors, around `center`,
+/// highlighting the `hovered` one.
+pub fn radial_menu(
+    painter: &egui::Painter,
+    center: egui::Pos2,
+    radius: f32,
+    options: &[(&str, Color32)],
+    hovered: Option<usize>,
+) {
+    painter.circle_filled(center, radius * 1.4, Color32::from_black_alpha(140));
+    for (index, (label, color)) in options.iter().enumerate() {
+        let at = center + egui::Vec2::angled(radial_angle(index, options.len())) * radius;
+        let font = if hovered == Some(index) {
+            egui::FontId::proportional(18.0)
+        } else {
+            egui::FontId::proportional(14.0)
         };
+        painter.text(at, egui::Align2::CENTER_CENTER, *label, font, *color);
     }
-    Glyph {
-        character: ".",
-        fg_color: Color32::WHITE,
-        bg_color: Color32::BLACK,
-        size_mod: 2.0,
+}
+
+/// Color markers of `color` are drawn in.
+pub fn marker_color(color: MarkerColor) -> Color32 {
+    match color {
+        MarkerColor::Red => Color32::from_rgb(230, 70, 70),
+        MarkerColor::Yellow => Color32::from_rgb(240, 220, 80),
+        MarkerColor::Green => Color32::from_rgb(90, 210, 90),
+        MarkerColor::Cyan => Color32::from_rgb(80, 220, 230),
+        MarkerColor::Blue => Color32::from_rgb(90, 130, 255),
+        MarkerColor::Purple => Color32::from_rgb(190, 110, 240),
+    }
+}
+
+/// Draw `marker` on the tile at `min`: a diamond with its name above.
+pub fn marker_flag(painter: &egui::Painter, min: egui::Pos2, cell: f32, marker: &Marker) {
+    let center = min + egui::vec2(cell, cell) / 2.0;
+    let color = marker_color(marker.color);
+    let r = cell * 0.3;
+    let diamond = vec![
+        center - egui::vec2(0.0, r),
+        center + egui::vec2(r, 0.0),
+        center + egui::vec2(0.0, r),
+        center - egui::vec2(r, 0.0),
+    ];
+    painter.add(egui::Shape::convex_polygon(
+        diamond,
+        Color32::TRANSPARENT,
+        egui::Stroke::new(2.0, color),
+    ));
+    painter.text(
+        min + egui::vec2(cell / 2.0, 0.0),
+        egui::Align2::CENTER_BOTTOM,
+        &marker.name,
+        egui::FontId::proportional(cell * 0.4),
+        color,
+    );
+}
+
+/// Draw `name` above the tile at `min`, in the color of its player.
+pub fn name_plate(painter: &egui::Painter, min: egui::Pos2, cell: f32, name: &str, color: Color32) {
+    let font = egui::FontId::proportional(cell * 0.4);
+    let galley = painter.layout_no_wrap(name.to_owned(), font, color);
+    let pos = min + egui::vec2((cell - galley.size().x) / 2.0, -galley.size().y);
+    let back = egui::Rect::from_min_size(pos, galley.size()).expand(1.0);
+    painter.rect_filled(back, 2.0, Color32::from_black_alpha(160));
+    painter.galley(pos, galley, color);
+}
+
+/// Draw an arrow on the edge of `rect` pointing from its center towards
+/// `target`, which lies outside it, with `label` next to it.
+pub fn edge_arrow(
+    painter: &egui::Painter,
+    rect: egui::Rect,
+    target: egui::Pos2,
+    color: Color32,
+    label: &str,
+) {
+    const SIZE: f32 = 12.0;
+    let center = rect.center();
+    let dir = (target - center).normalized();
+    let half = rect.shrink(SIZE).size() / 2.0;
+    // Scale the direction until it reaches the nearer edge.
+    let reach = (half.x / dir.x.abs()).min(half.y / dir.y.abs());
+    if !reach.is_finite() {
+        return;
+    }
+    let tip = center + dir * reach;
+    let back = tip - dir * SIZE;
+    let side = dir.rot90() * (SIZE / 2.0);
+    painter.add(egui::Shape::convex_polygon(
+        vec![tip, back + side, back - side],
+        color,
+        egui::Stroke::NONE,
+    ));
+    painter.text(
+        back - dir * SIZE,
+        egui::Align2::CENTER_CENTER,
+        label,
+        egui::FontId::proportional(12.0),
+        color,
+    );
+}
+
+/// Draw the explored tiles within `radius` of `center` into the clip rect
+/// of `painter`.
+///
+/// Tiles are in the color of what was seen on them or of their `terrain`,
+/// with `markers` as dots, other `players` as dots in their colors and the
+/// player, at `center`, in white.
+pub fn minimap<'a>(
+    painter: &egui::Painter,
+    explored: &ExploredMap,
+    terrain: &TileMap,
+    center: Point,
+    radius: i32,
+    markers: impl Iterator<Item = &'a Marker>,
+    players: impl Iterator<Item = (Point, Color32)>,
+) {
+    let rect = painter.clip_rect();
+    let side = (2 * radius + 1) as f32;
+    let tile = rect.width().min(rect.height()) / side;
+    let corner = Point {
+        x: center.x - radius,
+        y: center.y - radius,
+    };
+    let tile_rect = |p: Point| {
+        let min = rect.min + egui::vec2((p.x - corner.x) as f32, (p.y - corner.y) as f32) * tile;
+        egui::Rect::from_min_size(min, egui::vec2(tile, tile))
+    };
+    painter.rect_filled(rect, 0.0, Color32::from_black_alpha(200));
+    for y in corner.y..=center.y + radius {
+        for x in corner.x..=center.x + radius {
+            let p = Point { x, y };
+            if let Some(seen) = explored.get(p) {
+                let color = match seen {
+                    Some(_) => map_color(seen),
+                    None => terrain_color(terrain.get(p)),
+                };
+                painter.rect_filled(tile_rect(p), 0.0, color);
+            }
+        }
+    }
+    for marker in markers.filter(|m| math::within_square(center, m.pos, radius)) {
+        let dot = tile_rect(marker.pos).center();
+        painter.circle_filled(dot, (tile * 1.5).max(3.0), marker_color(marker.color));
+    }
+    for (pos, color) in players.filter(|(pos, _)| math::within_square(center, *pos, radius)) {
+        let dot = tile_rect(pos).center();
+        painter.circle_filled(dot, (tile * 1.2).max(2.5), color);
+    }
+    painter.circle_filled(
+        tile_rect(center).center(),
+        (tile * 1.5).max(3.0),
+        Color32::WHITE,
+    );
+}
+
+/// How long ago something happened, `seconds` ago, e.g. "3 hours ago".
+pub fn time_ago(seconds: u64) -> String {
+    let (count, unit) = match seconds {
+        0..60 => return "just now".to_owned(),
+        60..3600 => (seconds / 60, "minute"),
+        3600..86_400 => (seconds / 3600, "hour"),
+        _ => (seconds / 86_400, "day"),
+    };
+    let plural = if count == 1 { "" } else { "s" };
+    format!("{count} {unit}{plural} ago")
+}
+
+/// Ticks in a day of world time: twenty minutes.
+pub const TICKS_PER_DAY: u64 = 24_000;
+
+/// World time at `tick`, e.g. "Day 2, 07:45"; the first day starts at
+/// midnight.
+pub fn world_time(tick: u64) -> String {
+    let day = tick / TICKS_PER_DAY + 1;
+    format!("Day {day}, {}", clock(tick))
+}
+
+/// Time of day at `tick`, e.g. "07:45".
+pub fn clock(tick: u64) -> String {
+    let minutes = tick % TICKS_PER_DAY * 24 * 60 / TICKS_PER_DAY;
+    format!("{:02}:{:02}", minutes / 60, minutes % 60)
+}
+
+/// Compass heading of `direction`, in degrees clockwise from north, which
+/// is up.
+pub const fn heading(direction: Direction) -> f32 {
+    match direction {
+        Direction::Up => 0.0,
+        Direction::Right => 90.0,
+        Direction::Down => 180.0,
+        Direction::Left => 270.0,
+    }
+}
+
+/// Heading from `from` to `to`, in degrees clockwise from north.
+pub fn bearing(from: Point, to: Point) -> f32 {
+    // Up is north, so the y axis is flipped; subtracting the other way
+    // round keeps the same tile at 0 rather than -0, which reads as south.
+    let (dx, up) = ((to.x - from.x) as f32, (from.y - to.y) as f32);
+    dx.atan2(up).to_degrees().rem_euclid(360.0)
+}
+
+/// Name of the compass point nearest `heading`, e.g. "NE".
+pub fn compass_point(heading: f32) -> &'static str {
+    const POINTS: [&str; 8] = ["N", "NE", "E", "SE", "S", "SW", "W", "NW"];
+    let index = (heading.rem_euclid(360.0) / 45.0).round() as usize % POINTS.len();
+    POINTS.get(index).copied().unwrap_or("N")
+}
+
+/// Draw a compass strip across `rect`, centered on `facing` and spanning
+/// half a turn, with `marks` as dots at their headings; marks behind the
+/// player sit at the edges.
+pub fn compass_strip(
+    painter: &egui::Painter,
+    rect: egui::Rect,
+    facing: f32,
+    marks: &[(f32, Color32)],
+) {
+    let relative = |heading: f32| (heading - facing + 180.0).rem_euclid(360.0) - 180.0;
+    let x_of = |heading: f32| {
+        rect.center().x + relative(heading).clamp(-90.0, 90.0) / 90.0 * rect.width() / 2.0
+    };
+    painter.rect_filled(rect, 2.0, Color32::from_black_alpha(160));
+    for step in 0..8 {
+        let heading = step as f32 * 45.0;
+        if relative(heading).abs() > 90.0 {
+            continue;
+        }
+        let color = if step == 0 {
+            Color32::LIGHT_RED
+        } else {
+            Color32::LIGHT_GRAY
+        };
+        painter.text(
+            egui::pos2(x_of(heading), rect.top() + 2.0),
+            egui::Align2::CENTER_TOP,
+            compass_point(heading),
+            egui::FontId::proportional(12.0),
+            color,
+        );
+    }
+    for (heading, color) in marks {
+        painter.circle_filled(egui::pos2(x_of(*heading), rect.bottom() - 5.0), 3.5, *color);
+    }
+    let x = rect.center().x;
+    painter.line_segment(
+        [egui::pos2(x, rect.top()), egui::pos2(x, rect.bottom())],
+        egui::Stroke::new(1.0, Color32::WHITE),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    fn tree() -> Glyph {
+        glyph(&EntityType::Tree)
+    }
+
+    #[test]
+    fn animation_cycles_through_its_frames_and_only_dims() {
+        let frames = ["木", "朩"];
+        let original = tree().fg_color;
+        let mut seen = BTreeSet::new();
+        for step in 0..40 {
+            let time = f64::from(step) * 0.45;
+            let glyph = animate(tree(), &EntityType::Tree, &Point { x: 3, y: 4 }, time);
+            assert!(frames.contains(&glyph.character), "{}", glyph.character);
+            assert!(glyph.fg_color.g() <= original.g());
+            assert!(glyph.fg_color.g() >= original.g() - original.g() / 5);
+            seen.insert(glyph.character);
+        }
+        assert_eq!(seen.len(), frames.len());
+
+        let at = |x| animate(tree(), &EntityType::Tree, &Point { x, y: 0 }, 1.0).character;
+        assert_eq!(at(5), at(5), "the same cell at the same time");
+    }
+
+    #[test]
+    fn unanimated_glyphs_are_left_alone() {
+        let player = glyph(&EntityType::Player);
+        let (character, color) = (player.character, player.fg_color);
+        let glyph = animate(player, &EntityType::Player, &Point { x: 1, y: 1 }, 12.3);
+        assert_eq!((glyph.character, glyph.fg_color), (character, color));
+
+        // Times before the clock started still land on a frame.
+        let glyph = animate(tree(), &EntityType::Tree, &Point { x: -7, y: -2 }, -5.0);
+        assert!(["木", "朩"].contains(&glyph.character));
+    }
+
+    #[test]
+    fn glyphs_come_from_appearances_over_the_ground() {
+        let rubble = glyph(&EntityType::Rubble);
+        assert_eq!((rubble.character, rubble.size_mod), (":", 2.0));
+        assert_eq!(rubble.fg_color, Color32::from_rgb(160, 160, 160));
+        assert!((rubble.remembered - 0.35).abs() < f32::EPSILON);
+
+        let at = Point { x: 2, y: 2 };
+        let mut terrain = TileMap::default();
+        terrain.set(at, Terrain::Sand);
+        let mut player = Entity::new(EntityType::Player, at, None);
+        let glyph = glyph_at(&SpatialIndex::from_iter([(at, &player)]), &terrain, &at);
+        assert_eq!((glyph.character, glyph.bg_color), ("@", SAND.bg_color));
+        assert_eq!(glyph.fg_color, Color32::WHITE);
+
+        player.color = Some(PlayerColor([200, 40, 40]));
+        let glyph = glyph_at(&SpatialIndex::from_iter([(at, &player)]), &terrain, &at);
+        assert_eq!(glyph.fg_color, Color32::from_rgb(200, 40, 40));
+
+        let empty = glyph_at(&SpatialIndex::default(), &terrain, &Point { x: 9, y: 9 });
+        assert_eq!(empty.character, FLOOR.character);
+        assert_eq!(silhouette(&EntityType::Player).bg_color, FLOOR.bg_color);
+    }
+
+    /// Paint a 4 × 3 frame of `tile` with `changes` set over it at
+    /// `font_size`, and return how many tiles were laid out.
+    fn paint_frame(
+        buffer: &mut FrameBuffer,
+        font_size: f32,
+        tile: TileDraw,
+        changes: &[(usize, usize, TileDraw)],
+    ) -> usize {
+        let _frame = egui::Context::default().run(egui::RawInput::default(), |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                buffer.begin(4, 3, font_size);
+                for (col, row) in (0..4).flat_map(|col| (0..3).map(move |row| (col, row))) {
+                    buffer.set(col, row, tile);
+                }
+                for (col, row, tile) in changes {
+                    buffer.set(*col, *row, *tile);
+                }
+                buffer.paint(ui, ui.max_rect(), font_size);
+            });
+        });
+        buffer.laid_out()
+    }
+
+    #[test]
+    fn frames_lay_out_only_tiles_whose_text_changed() {
+        let grass = TileDraw::from(FLOOR);
+        let mut buffer = FrameBuffer::default();
+        assert_eq!(paint_frame(&mut buffer, 16.0, grass, &[]), 12);
+        assert_eq!(buffer.len(), 12);
+        assert_eq!(paint_frame(&mut buffer, 16.0, grass, &[]), 0);
+
+        let shimmer = TileDraw {
+            fg_color: Color32::GRAY,
+            ..grass
+        };
+        assert_eq!(paint_frame(&mut buffer, 16.0, shimmer, &[]), 0, "colors");
+        let bold = TileDraw {
+            bold: true,
+            ..grass
+        };
+        let water = TileDraw::from(WATER);
+        let changes = [(1, 1, water), (2, 0, bold)];
+        assert_eq!(paint_frame(&mut buffer, 16.0, grass, &changes), 2);
+        // Back to grass where the water and bold tile were.
+        assert_eq!(paint_frame(&mut buffer, 16.0, grass, &[]), 2);
+
+        // Tiles off the viewport are dropped rather than wrapping around.
+        let off = [(4, 0, water), (0, 3, water), (9, 9, water)];
+        assert_eq!(paint_frame(&mut buffer, 16.0, grass, &off), 0);
+        assert_eq!(buffer.len(), 12);
+
+        assert_eq!(
+            paint_frame(&mut buffer, 20.0, grass, &[]),
+            12,
+            "new font size"
+        );
+    }
+
+    #[test]
+    fn tiles_are_found_under_the_pointer() {
+        let mut buffer = FrameBuffer::default();
+        buffer.begin(4, 3, 16.0);
+        let rect = egui::Rect::from_min_size(egui::pos2(100.0, 50.0), egui::vec2(64.0, 48.0));
+        let at = |buffer: &FrameBuffer, x, y, cell| buffer.tile_at(rect, cell, egui::pos2(x, y));
+        assert_eq!(at(&buffer, 100.0, 50.0, 16.0), Some((0, 0)));
+        assert_eq!(at(&buffer, 163.0, 97.0, 16.0), Some((3, 2)));
+        assert_eq!(at(&buffer, 99.0, 60.0, 16.0), None, "left of the map");
+        assert_eq!(at(&buffer, 120.0, 60.0, 0.0), None, "no cells");
+        // A rect larger than the buffer has no tiles past its edge.
+        assert_eq!(at(&buffer, 150.0, 60.0, 8.0), None);
+    }
+
+    #[test]
+    fn compass_headings_name_the_nearest_point() {
+        let origin = Point { x: 10, y: 10 };
+        let at = |x, y| bearing(origin, Point { x, y });
+        assert_eq!(compass_point(at(10, 0)), "N", "north is up");
+        assert_eq!(compass_point(at(20, 0)), "NE");
+        assert_eq!(compass_point(at(11, 10)), "E");
+        assert_eq!(compass_point(at(10, 30)), "S");
+        assert_eq!(compass_point(at(0, 10)), "W");
+        assert!((at(11, 10) - heading(Direction::Right)).abs() < 1e-3);
+        assert!((at(9, 10) - heading(Direction::Left)).abs() < 1e-3);
+
+        // Headings wrap and round to the nearest point, and a mark on the
+        // player's own tile reads as north rather than failing.
+        assert_eq!(compass_point(359.0), "N");
+        assert_eq!(compass_point(-90.0), "W");
+        assert_eq!(compass_point(202.0), "S");
+        assert_eq!(compass_point(720.0 + 45.0), "NE");
+        assert_eq!(compass_point(at(10, 10)), "N");
+        assert_eq!(compass_point(f32::NAN), "N");
+    }
+
+    #[test]
+    fn world_time_counts_days_from_midnight() {
+        assert_eq!(world_time(0), "Day 1, 00:00");
+        assert_eq!(clock(TICKS_PER_DAY / 4), "06:00");
+        assert_eq!(clock(TICKS_PER_DAY - 1), "23:59");
+        assert_eq!(
+            world_time(TICKS_PER_DAY * 2 + TICKS_PER_DAY / 2),
+            "Day 3, 12:00"
+        );
+        assert!(world_time(u64::MAX).starts_with("Day "));
     }
 }
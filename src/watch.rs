@@ -0,0 +1,115 @@
+//! Data files read again as they change, for reloading them into a
+//! running game while working on them, without restarting and rejoining.
+//!
+//! A [`FileWatcher`] polls: whoever owns it calls
+//! [`poll`](FileWatcher::poll) from its loop, such as each server tick,
+//! and gets the files back only when one of them changed. Checking what
+//! they hold, and keeping what was in use when they do not check out, is
+//! left to the caller.
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// How often a [`FileWatcher`] reads the files again.
+pub const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Reads a set of files from a directory again whenever they change.
+#[derive(Debug)]
+pub struct FileWatcher {
+    dir: PathBuf,
+    names: Vec<String>,
+    /// What each file held when last read, or why it could not be read.
+    last: Vec<Result<String, String>>,
+    next_check: Option<Instant>,
+}
+
+impl FileWatcher {
+    /// Watch the files under `dir`, by name, along with what they hold in
+    /// the game now. The first [`poll`](Self::poll) reads them, and reports
+    /// them only if they differ from that.
+    pub fn new<'a>(
+        dir: impl Into<PathBuf>,
+        files: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Self {
+        let (names, last) = files
+            .into_iter()
+            .map(|(name, held)| (name.to_owned(), Ok(held.to_owned())))
+            .unzip();
+        Self {
+            dir: dir.into(),
+            names,
+            last,
+            next_check: None,
+        }
+    }
+
+    /// What every file holds, in the order given, if any of them changed
+    /// since they were last read. Reads them at most once per
+    /// [`WATCH_INTERVAL`].
+    ///
+    /// # Errors
+    /// If a file cannot be read, naming it. It is reported once, like a
+    /// change, and again only once it changes.
+    pub fn poll(&mut self, now: Instant) -> Option<Result<Vec<&str>, String>> {
+        if self.next_check.is_some_and(|next| now < next) {
+            return None;
+        }
+        self.next_check = Some(now + WATCH_INTERVAL);
+        let read: Vec<Result<String, String>> = self
+            .names
+            .iter()
+            .map(|name| {
+                std::fs::read_to_string(self.dir.join(name)).map_err(|e| format!("{name}: {e}"))
+            })
+            .collect();
+        if read == self.last {
+            return None;
+        }
+        self.last = read;
+        Some(
+            self.last
+                .iter()
+                .map(|file| file.as_deref().map_err(Clone::clone))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::WorldId;
+
+    #[test]
+    fn watched_files_are_reported_once_each_time_they_change() {
+        let dir = std::env::temp_dir().join(format!("gamik-watch-{}", WorldId::generate()));
+        std::fs::create_dir_all(&dir).expect("temp dir is writable");
+        std::fs::write(dir.join("a.ron"), "1").expect("temp dir is writable");
+        std::fs::write(dir.join("b.ron"), "2").expect("temp dir is writable");
+        let mut watch = FileWatcher::new(&dir, [("a.ron", "1"), ("b.ron", "2")]);
+        let mut now = Instant::now();
+        assert_eq!(watch.poll(now), None, "the files hold what is in use");
+
+        std::fs::write(dir.join("b.ron"), "3").expect("temp dir is writable");
+        assert_eq!(watch.poll(now), None, "read at most once a second");
+        now += WATCH_INTERVAL;
+        assert_eq!(watch.poll(now), Some(Ok(vec!["1", "3"])));
+        now += WATCH_INTERVAL;
+        assert_eq!(watch.poll(now), None, "reported once");
+
+        std::fs::remove_file(dir.join("a.ron")).expect("was written");
+        now += WATCH_INTERVAL;
+        let err = watch
+            .poll(now)
+            .expect("changed")
+            .expect_err("a missing file");
+        assert!(err.starts_with("a.ron"), "{err}");
+        now += WATCH_INTERVAL;
+        assert_eq!(watch.poll(now), None, "the error is reported once");
+
+        std::fs::write(dir.join("a.ron"), "1").expect("temp dir is writable");
+        now += WATCH_INTERVAL;
+        assert_eq!(watch.poll(now), Some(Ok(vec!["1", "3"])), "back again");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}